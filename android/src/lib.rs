@@ -26,7 +26,6 @@ pub fn android_main() {
                 ..WgpuSettings::default()
             })
             .build()
-            .initialize()
             .await
             .run()
     })
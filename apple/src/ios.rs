@@ -0,0 +1,335 @@
+//! Renders the map into a `CAMetalLayer` of a UIKit app.
+//!
+//! The layer is passed from Swift and has to be the backing layer of a `UIView`, i.e. the view
+//! overrides `layerClass` to return `CAMetalLayer.self`. The map is updated by a `CADisplayLink`
+//! on the main run loop. While the app is in the background, the map is suspended, because iOS
+//! terminates apps which submit work to the GPU in the background.
+
+use instant::Instant;
+use maplibre::error::{Error, RenderError};
+use maplibre::io::scheduler::ScheduleMethod;
+use maplibre::io::source_client::HTTPClient;
+use maplibre::map_schedule::MapSchedule;
+use maplibre::window::{MapWindow, MapWindowConfig, Runnable, WindowSize};
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel};
+use objc::{class, msg_send, sel, sel_impl};
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle, UiKitHandle};
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::Once;
+use tokio::runtime::Handle;
+
+/// Name of the Objective-C class which receives the callbacks of the display link and the
+/// notifications of the app lifecycle.
+const DISPLAY_LINK_TARGET_CLASS: &str = "MapLibreRsDisplayLinkTarget";
+
+/// Instance variable of the display link target which points to its [`DisplayLinkCallback`].
+const CALLBACK_IVAR: &str = "callback";
+
+#[link(name = "Foundation", kind = "framework")]
+extern "C" {
+    static NSRunLoopCommonModes: *mut Object;
+}
+
+#[link(name = "UIKit", kind = "framework")]
+extern "C" {
+    static UIApplicationDidEnterBackgroundNotification: *mut Object;
+    static UIApplicationWillEnterForegroundNotification: *mut Object;
+}
+
+#[link(name = "QuartzCore", kind = "framework")]
+extern "C" {}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGPoint {
+    x: f64,
+    y: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGSize {
+    width: f64,
+    height: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGRect {
+    origin: CGPoint,
+    size: CGSize,
+}
+
+/// A `CAMetalLayer` which is the backing layer of a `UIView`. The layer is not retained, Swift
+/// keeps it alive while the map is shown.
+#[derive(Clone)]
+pub struct MetalLayer {
+    layer: *mut Object,
+}
+
+impl MetalLayer {
+    /// # Safety
+    ///
+    /// `layer` has to point to a `CAMetalLayer` which is the backing layer of a `UIView` and which
+    /// outlives the map.
+    pub unsafe fn from_raw(layer: *mut c_void) -> Self {
+        Self {
+            layer: layer as *mut Object,
+        }
+    }
+
+    /// The size of the layer in physical pixels. `None` until the layer is laid out.
+    fn size(&self) -> Option<WindowSize> {
+        let (bounds, scale): (CGRect, f64) = unsafe {
+            (
+                msg_send![self.layer, bounds],
+                msg_send![self.layer, contentsScale],
+            )
+        };
+        WindowSize::new(
+            (bounds.size.width * scale).round() as u32,
+            (bounds.size.height * scale).round() as u32,
+        )
+        .map(|size| size.with_device_pixel_ratio(scale))
+    }
+}
+
+unsafe impl HasRawWindowHandle for MetalLayer {
+    /// The backing layer of a view is used by wgpu to render into the view.
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        let mut handle = UiKitHandle::empty();
+        // The view is the delegate of its backing layer
+        handle.ui_view = unsafe { msg_send![self.layer, delegate] };
+        RawWindowHandle::UiKit(handle)
+    }
+}
+
+pub struct IosMapWindowConfig {
+    layer: MetalLayer,
+}
+
+impl IosMapWindowConfig {
+    /// # Safety
+    ///
+    /// See [`MetalLayer::from_raw`].
+    pub unsafe fn new(layer: *mut c_void) -> Self {
+        Self {
+            layer: MetalLayer::from_raw(layer),
+        }
+    }
+}
+
+impl MapWindowConfig for IosMapWindowConfig {
+    type MapWindow = IosMapWindow;
+}
+
+pub struct IosMapWindow {
+    layer: MetalLayer,
+}
+
+impl MapWindow for IosMapWindow {
+    type EventLoop = ();
+    type Window = MetalLayer;
+    type MapWindowConfig = IosMapWindowConfig;
+
+    fn create(map_window_config: &Self::MapWindowConfig) -> Self {
+        Self {
+            layer: map_window_config.layer.clone(),
+        }
+    }
+
+    fn size(&self) -> WindowSize {
+        // The layer might not be laid out yet. Its size is picked up by the next frame.
+        self.layer
+            .size()
+            .unwrap_or_else(|| WindowSize::new(100, 100).unwrap())
+    }
+
+    fn inner(&self) -> &Self::Window {
+        &self.layer
+    }
+}
+
+/// Events which the display link target forwards to the map.
+enum DisplayLinkEvent {
+    /// The display is about to be refreshed.
+    Frame,
+    /// The app moved to the background.
+    EnterBackground,
+    /// The app returns to the foreground.
+    EnterForeground,
+}
+
+/// Handles the events of the display link target. Returns false once the display link should be
+/// stopped.
+type DisplayLinkCallback = Box<dyn FnMut(DisplayLinkEvent) -> bool>;
+
+/// Registers the Objective-C class of the display link targets once.
+fn display_link_target_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut decl = ClassDecl::new(DISPLAY_LINK_TARGET_CLASS, class!(NSObject))
+            .expect("display link target class already registered");
+        decl.add_ivar::<*mut c_void>(CALLBACK_IVAR);
+        unsafe {
+            decl.add_method(
+                sel!(tick:),
+                tick as extern "C" fn(&Object, Sel, *mut Object),
+            );
+            decl.add_method(
+                sel!(didEnterBackground:),
+                did_enter_background as extern "C" fn(&Object, Sel, *mut Object),
+            );
+            decl.add_method(
+                sel!(willEnterForeground:),
+                will_enter_foreground as extern "C" fn(&Object, Sel, *mut Object),
+            );
+        }
+        decl.register();
+    });
+    Class::get(DISPLAY_LINK_TARGET_CLASS).unwrap()
+}
+
+/// Forwards `event` to the callback of the display link target `this`. Returns false if the
+/// callback was already dropped by [`tear_down`].
+fn dispatch(this: &Object, event: DisplayLinkEvent) -> bool {
+    unsafe {
+        let callback = *this.get_ivar::<*mut c_void>(CALLBACK_IVAR) as *mut DisplayLinkCallback;
+        if callback.is_null() {
+            return false;
+        }
+        (*callback)(event)
+    }
+}
+
+extern "C" fn tick(this: &Object, _: Sel, display_link: *mut Object) {
+    if !dispatch(this, DisplayLinkEvent::Frame) {
+        unsafe { tear_down(this, display_link) }
+    }
+}
+
+/// Stops the display link and frees everything which was set up by `run`: the display link and
+/// the notification center no longer refer to the target, the callback together with the map is
+/// dropped and the reference to the target which was created by `run` is released.
+///
+/// # Safety
+///
+/// `this` has to be the target of `display_link` and must not be used afterwards, because it is
+/// deallocated once the last reference is released.
+unsafe fn tear_down(this: &Object, display_link: *mut Object) {
+    let target = this as *const Object as *mut Object;
+
+    let _: () = msg_send![display_link, invalidate];
+    let notification_center: *mut Object = msg_send![class!(NSNotificationCenter), defaultCenter];
+    let _: () = msg_send![notification_center, removeObserver: target];
+
+    let callback = *(*target).get_ivar::<*mut c_void>(CALLBACK_IVAR) as *mut DisplayLinkCallback;
+    (*target).set_ivar::<*mut c_void>(CALLBACK_IVAR, ptr::null_mut());
+    if !callback.is_null() {
+        drop(Box::from_raw(callback));
+    }
+
+    let _: () = msg_send![target, release];
+}
+
+extern "C" fn did_enter_background(this: &Object, _: Sel, _notification: *mut Object) {
+    dispatch(this, DisplayLinkEvent::EnterBackground);
+}
+
+extern "C" fn will_enter_foreground(this: &Object, _: Sel, _notification: *mut Object) {
+    dispatch(this, DisplayLinkEvent::EnterForeground);
+}
+
+/// Drives the map from a `CADisplayLink` on the main run loop. Unlike the winit event loop,
+/// `run` returns right away, because the run loop is owned by UIKit. The map lives as long as the
+/// display link runs, the Tokio runtime of the calling thread needs to be kept alive as well.
+impl<MWC, SM, HC> Runnable<MWC, SM, HC> for IosMapWindow
+where
+    MWC: MapWindowConfig<MapWindow = IosMapWindow>,
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    fn run(self, mut map_state: MapSchedule<MWC, SM, HC>, max_frames: Option<u64>) {
+        let handle = Handle::try_current().ok();
+        let mut current_frame: u64 = 0;
+        let mut last_size = self.layer.size();
+
+        let callback: DisplayLinkCallback = Box::new(move |event| {
+            match event {
+                DisplayLinkEvent::EnterBackground => map_state.suspend(),
+                DisplayLinkEvent::EnterForeground => map_state.resume(&self),
+                DisplayLinkEvent::Frame => {
+                    // The bounds of the layer change with the layout of its view
+                    let size = self.layer.size();
+                    if size != last_size {
+                        last_size = size;
+                        if let Some(size) = size {
+                            map_state.resize(size);
+                        }
+                    }
+
+                    // In on-demand mode the map is only updated if it changed
+                    if !matches!(map_state.next_redraw(), Some(next_redraw) if next_redraw <= Instant::now())
+                    {
+                        return true;
+                    }
+
+                    let _guard = handle.as_ref().map(Handle::enter);
+                    match map_state.update_and_redraw() {
+                        Ok(_) => {}
+                        Err(Error::Render(RenderError::DeviceLost)) => {
+                            log::warn!("The surface was lost, initializing the renderer again");
+                            let result = match &handle {
+                                Some(handle) => handle
+                                    .block_on(map_state.recreate_surface(&self.layer, self.size())),
+                                None => Err(Error::Render(RenderError::DeviceLost)),
+                            };
+                            if let Err(e) = result {
+                                log::error!("Failed to initialize the renderer: {:?}", e);
+                                return false;
+                            }
+                        }
+                        Err(Error::Render(e)) => {
+                            log::error!("{}", e);
+                            if e.should_exit() {
+                                return false;
+                            }
+                        }
+                        e => log::error!("{:?}", e),
+                    };
+
+                    if let Some(max_frames) = max_frames {
+                        if current_frame >= max_frames {
+                            log::info!("Stopping because maximum frames reached.");
+                            return false;
+                        }
+
+                        current_frame += 1;
+                    }
+                }
+            }
+            true
+        });
+
+        unsafe {
+            // The target and its callback live until the callback returns false, see `tear_down`
+            let target: *mut Object = msg_send![display_link_target_class(), new];
+            (*target).set_ivar::<*mut c_void>(
+                CALLBACK_IVAR,
+                Box::into_raw(Box::new(callback)) as *mut c_void,
+            );
+
+            let display_link: *mut Object = msg_send![class!(CADisplayLink), displayLinkWithTarget: target selector: sel!(tick:)];
+            let run_loop: *mut Object = msg_send![class!(NSRunLoop), mainRunLoop];
+            let _: () =
+                msg_send![display_link, addToRunLoop: run_loop forMode: NSRunLoopCommonModes];
+
+            let notification_center: *mut Object =
+                msg_send![class!(NSNotificationCenter), defaultCenter];
+            let _: () = msg_send![notification_center, addObserver: target selector: sel!(didEnterBackground:) name: UIApplicationDidEnterBackgroundNotification object: ptr::null_mut::<Object>()];
+            let _: () = msg_send![notification_center, addObserver: target selector: sel!(willEnterForeground:) name: UIApplicationWillEnterForegroundNotification object: ptr::null_mut::<Object>()];
+        }
+    }
+}
@@ -1,9 +1,14 @@
 use maplibre::platform::http_client::ReqwestHttpClient;
+#[cfg(target_os = "ios")]
+use maplibre::platform::multithreaded_runtime;
 use maplibre::platform::run_multithreaded;
 use maplibre::platform::schedule_method::TokioScheduleMethod;
 use maplibre::MapBuilder;
 use maplibre_winit::winit::{WinitEventLoop, WinitMapWindow, WinitMapWindowConfig, WinitWindow};
 
+#[cfg(target_os = "ios")]
+pub mod ios;
+
 #[cfg(not(any(target_os = "macos", target_os = "ios")))]
 compile_error!("apple works only on macOS and iOS.");
 
@@ -17,7 +22,32 @@ pub fn maplibre_apple_main() {
             .with_http_client(ReqwestHttpClient::new(None))
             .with_schedule_method(TokioScheduleMethod::new())
             .build()
-            .initialize()
+            .await
+            .run()
+    })
+}
+
+/// Shows the map in `metal_layer`, the `CAMetalLayer` which backs a `UIView`, see
+/// [`ios::IosMapWindowConfig`]. Returns once the map is initialized, afterwards it is updated by a
+/// display link on the main run loop. Needs to be called on the main thread.
+///
+/// # Safety
+///
+/// `metal_layer` has to outlive the map, see [`ios::MetalLayer::from_raw`].
+#[cfg(target_os = "ios")]
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_apple_ios_main(metal_layer: *mut std::ffi::c_void) {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    // The tiles are loaded on the runtime after this function returned
+    let runtime = Box::leak(Box::new(multithreaded_runtime()));
+    let schedule_method = TokioScheduleMethod::from_handle(runtime.handle().clone());
+    runtime.block_on(async {
+        MapBuilder::new()
+            .with_map_window_config(ios::IosMapWindowConfig::new(metal_layer))
+            .with_http_client(ReqwestHttpClient::new(None))
+            .with_schedule_method(schedule_method)
+            .build()
             .await
             .run()
     })
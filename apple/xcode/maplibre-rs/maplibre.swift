@@ -1,5 +1,17 @@
+#if os(iOS)
+import QuartzCore
+#endif
+
 public class MapLibre {
     public static func start() {
         maplibre_apple_main();
     }
+
+    #if os(iOS)
+    /// Shows the map in the backing layer of a view whose `layerClass` is `CAMetalLayer`. The map
+    /// is updated by a display link on the main run loop. The layer needs to be kept alive.
+    public static func start(metalLayer: CAMetalLayer) {
+        maplibre_apple_ios_main(Unmanaged.passUnretained(metalLayer).toOpaque());
+    }
+    #endif
 }
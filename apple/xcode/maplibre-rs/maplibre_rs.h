@@ -9,3 +9,6 @@ FOUNDATION_EXPORT const unsigned char maplibre_rsVersionString[];
 // In this header, you should import all the public headers of your framework using statements like #import <maplibre_rs/PublicHeader.h>
 
 void maplibre_apple_main();
+
+// Shows the map in a CAMetalLayer which backs a UIView. The layer is not retained.
+void maplibre_apple_ios_main(void *metal_layer);
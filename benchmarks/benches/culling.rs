@@ -0,0 +1,91 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use maplibre::benchmarking::culling::{is_tile_visible, CullBatch, GpuCuller};
+use maplibre::context::{CameraTarget, ViewState};
+use maplibre::coords::{LatLon, Zoom};
+use maplibre::WindowSize;
+
+/// Number of layers which are drawn per tile, like in a detailed vector style.
+const LAYERS_PER_TILE: u64 = 60;
+
+/// A zoomed out, pitched and rotated view, whose bounding box contains many tiles outside of the
+/// view frustum.
+fn view_state() -> ViewState {
+    let mut view_state = ViewState::new(&WindowSize::new(3840, 2160).unwrap());
+    view_state.jump_to(CameraTarget {
+        center: Some(LatLon::new(48.137, 11.575)),
+        zoom: Some(Zoom::new(4.0)),
+        pitch: Some(cgmath::Deg(60.0).into()),
+        bearing: Some(cgmath::Deg(45.0).into()),
+    });
+    view_state
+}
+
+/// Adds the draws of all layers of the tiles in view to `batch`.
+fn fill_batch(batch: &mut CullBatch, view_state: &ViewState) {
+    let view_proj = view_state.view_projection();
+    let zoom = view_state.zoom();
+
+    batch.clear();
+    for (tile, coords) in view_state
+        .view_region()
+        .unwrap()
+        .iter_unwrapped()
+        .enumerate()
+    {
+        let transform = view_proj
+            .to_model_view_projection(coords.transform_for_zoom(zoom))
+            .downcast();
+        batch.set_tile(tile, transform.into());
+        for layer in 0..LAYERS_PER_TILE {
+            batch.push_draw((tile as u64, layer), tile as u32, 6);
+        }
+    }
+}
+
+/// Compares culling the tiles of a frame on the CPU with culling the draws of all of their layers
+/// on the GPU. `cpu` tests each tile against the frustum before its layers are queued.
+/// `gpu_upload` only builds the data from which the compute pass culls the draws, while `gpu`
+/// also uploads it, dispatches the compute pass on a headless device and waits until the indirect
+/// draws are read back. The `gpu` benchmark is skipped if there is no device which supports
+/// compute shaders.
+fn cull_tiles(c: &mut Criterion) {
+    let view_state = view_state();
+    let view_proj = view_state.view_projection();
+    let zoom = view_state.zoom();
+    let view_region = view_state.view_region().unwrap();
+
+    let mut group = c.benchmark_group("culling");
+    group.bench_function("cpu", |b| {
+        b.iter(|| {
+            view_region
+                .iter_unwrapped()
+                .filter(|coords| is_tile_visible(&view_proj, &coords.transform_for_zoom(zoom)))
+                .count()
+        })
+    });
+
+    let mut batch = CullBatch::default();
+    group.bench_function("gpu_upload", |b| {
+        b.iter(|| {
+            fill_batch(&mut batch, &view_state);
+            black_box(batch.tiles_as_bytes().len() + batch.items_as_bytes().len())
+        })
+    });
+
+    match pollster::block_on(GpuCuller::new()) {
+        Ok(Some(mut culler)) => {
+            group.bench_function("gpu", |b| {
+                b.iter(|| {
+                    fill_batch(culler.batch_mut(), &view_state);
+                    pollster::block_on(culler.cull()).unwrap()
+                })
+            });
+        }
+        Ok(None) => eprintln!("Skipping the gpu benchmark, the device lacks compute shaders"),
+        Err(e) => eprintln!("Skipping the gpu benchmark, no device: {:?}", e),
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cull_tiles);
+criterion_main!(benches);
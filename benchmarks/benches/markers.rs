@@ -0,0 +1,45 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use maplibre::benchmarking::render::MarkerBatch;
+use maplibre::context::ViewState;
+use maplibre::coords::LatLon;
+use maplibre::markers::{Marker, Markers};
+use maplibre::render::settings::ColorSpace;
+use maplibre::WindowSize;
+
+/// Number of markers of a dense layer of points of interest.
+const MARKER_COUNT: usize = 4000;
+
+fn markers() -> Markers {
+    let mut markers = Markers::default();
+    for i in 0..MARKER_COUNT {
+        let latitude = (i / 80) as f64 - 25.0;
+        let longitude = (i % 80) as f64 - 40.0;
+        markers.add(Marker::with_color(
+            LatLon::new(latitude, longitude),
+            [1.0, 0.0, 0.0, 1.0],
+        ));
+    }
+    markers
+}
+
+/// Compares the data which is built and uploaded every frame with and without instancing. Both
+/// draw all markers with a single draw call.
+fn build_markers(c: &mut Criterion) {
+    let view_state = ViewState::new(&WindowSize::new(1920, 1080).unwrap());
+    let markers = markers();
+
+    let mut group = c.benchmark_group("markers");
+    for (name, instanced) in [("vertices", false), ("instanced", true)] {
+        let mut batch = MarkerBatch::new(instanced);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                batch.build(&markers, &view_state, None, ColorSpace::Srgb);
+                black_box(batch.as_bytes().len())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, build_markers);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use maplibre::benchmarking::tile_load::{
+    decode_tiles, tessellate_first_layer, tessellate_tiles, TileLoader,
+};
+
+/// Synthetic tiles which are generated by `test-data/fixtures/generate.py`.
+const FIXTURES: [(&str, &[u8]); 2] = [
+    (
+        "fill-heavy",
+        include_bytes!("../../test-data/fixtures/fill-heavy.pbf"),
+    ),
+    (
+        "line-heavy",
+        include_bytes!("../../test-data/fixtures/line-heavy.pbf"),
+    ),
+];
+
+/// Measures parsing, tessellation and upload of whole tiles. The throughput is reported in
+/// tessellated vertices per second.
+fn load_tile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tile_load");
+    for (name, data) in FIXTURES {
+        let mut loader = TileLoader::default();
+        let vertices = loader.load((0, 0, 0).into(), data).unwrap().vertices;
+
+        group.throughput(Throughput::Elements(vertices as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), data, |b, data| {
+            b.iter(|| loader.load((0, 0, 0).into(), data).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Number of tiles of a view which are tessellated at once.
+const TILE_SET_SIZE: usize = 16;
+
+/// Compares tessellating the layers of a set of fill-heavy tiles one after another with
+/// tessellating them on the thread pool. The speedup should be close to the number of cores.
+fn tessellate_tile_set(c: &mut Criterion) {
+    let data = vec![FIXTURES[0].1; TILE_SET_SIZE];
+    let mut tiles = decode_tiles(&data).unwrap();
+    let vertices = tessellate_tiles(&mut tiles, false).unwrap();
+
+    let mut group = c.benchmark_group("tessellate_tile_set");
+    group.throughput(Throughput::Elements(vertices as u64));
+    for (name, parallel) in [("sequential", false), ("parallel", true)] {
+        group.bench_function(name, |b| {
+            b.iter(|| tessellate_tiles(&mut tiles, parallel).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Measures the time until the first layer of a tile is tessellated, once with the whole tile
+/// decoded beforehand and once with only the first layer decoded, like the map does.
+fn time_to_first_layer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("time_to_first_layer");
+    for (name, data) in FIXTURES {
+        for (variant, streaming) in [("decoded", false), ("streamed", true)] {
+            group.bench_with_input(BenchmarkId::new(variant, name), data, |b, data| {
+                b.iter(|| tessellate_first_layer(data, streaming).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, load_tile, tessellate_tile_set, time_to_first_layer);
+criterion_main!(benches);
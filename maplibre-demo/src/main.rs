@@ -21,7 +21,6 @@ fn run_in_window() {
             .with_http_client(ReqwestHttpClient::new(None))
             .with_schedule_method(TokioScheduleMethod::new())
             .build()
-            .initialize()
             .await
             .run()
     })
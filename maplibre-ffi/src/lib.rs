@@ -0,0 +1,9 @@
+//! Links the C API of [`maplibre::ffi`] into `libmaplibre_ffi.a` and `libmaplibre_ffi.so` (or
+//! `.dylib`, `.dll`), which apps link against together with the header of `just ffi-header`.
+//!
+//! Build the libraries with `just ffi`, which uses the `release-ffi` profile. Unlike `release`, it
+//! unwinds on panics, such that they are reported as `MAPLIBRE_STATUS_PANIC` instead of aborting
+//! the app.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use maplibre::ffi::*;
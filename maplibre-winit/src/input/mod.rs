@@ -4,30 +4,34 @@ use std::time::Duration;
 
 use cgmath::Vector2;
 
-use winit::event::{DeviceEvent, KeyboardInput, TouchPhase, WindowEvent};
+use winit::event::{
+    DeviceEvent, ElementState, KeyboardInput, MouseButton, MouseScrollDelta, TouchPhase,
+    VirtualKeyCode, WindowEvent,
+};
 
-use crate::input::pan_handler::PanHandler;
-use crate::input::pinch_handler::PinchHandler;
 use crate::input::query_handler::QueryHandler;
 use crate::input::shift_handler::ShiftHandler;
 use crate::input::tilt_handler::TiltHandler;
-use crate::input::zoom_handler::ZoomHandler;
 use maplibre::context::ViewState;
+use maplibre::map_schedule::CameraController;
+use maplibre::style::Style;
 
-mod pan_handler;
-mod pinch_handler;
 mod query_handler;
 mod shift_handler;
 mod tilt_handler;
-mod zoom_handler;
+
+/// Zoom levels which are added or removed by pressing a zoom key.
+const KEY_ZOOM_DELTA: f64 = 0.1;
 
 pub struct InputController {
-    pinch_handler: PinchHandler,
-    pan_handler: PanHandler,
-    zoom_handler: ZoomHandler,
+    camera_controller: CameraController,
     tilt_handler: TiltHandler,
     shift_handler: ShiftHandler,
     query_handler: QueryHandler,
+
+    cursor_position: Option<Vector2<f64>>,
+    is_dragging: bool,
+    zoom_sensitivity: f64,
 }
 
 impl InputController {
@@ -43,15 +47,65 @@ impl InputController {
     ///
     pub fn new(speed: f64, sensitivity: f64, zoom_sensitivity: f64) -> Self {
         Self {
-            pinch_handler: PinchHandler::new(),
-            pan_handler: PanHandler::new(),
-            zoom_handler: ZoomHandler::new(zoom_sensitivity),
+            camera_controller: CameraController::new(),
             tilt_handler: TiltHandler::new(speed, sensitivity),
             shift_handler: ShiftHandler::new(speed, sensitivity),
             query_handler: QueryHandler::new(),
+            cursor_position: None,
+            is_dragging: false,
+            zoom_sensitivity,
+        }
+    }
+
+    /// Limits the zoom to the zoom range of the sources of `style`.
+    pub fn set_zoom_range_of(&mut self, style: &Style) {
+        self.camera_controller.set_zoom_range_of(style);
+    }
+
+    /// Drags the map along with the cursor or finger.
+    fn process_window_position(&mut self, window_position: Vector2<f64>) {
+        if let (true, Some(cursor_position)) = (self.is_dragging, self.cursor_position) {
+            self.camera_controller
+                .drag(window_position - cursor_position);
+        }
+        self.cursor_position = Some(window_position);
+    }
+
+    fn process_drag(&mut self, pressed: bool) {
+        self.is_dragging = pressed;
+        if pressed {
+            self.camera_controller.drag_start();
+        } else {
+            self.camera_controller.drag_end();
         }
     }
 
+    fn process_scroll(&mut self, delta: &MouseScrollDelta) {
+        let delta = match delta {
+            MouseScrollDelta::LineDelta(_horizontal, vertical) => *vertical as f64,
+            MouseScrollDelta::PixelDelta(winit::dpi::PhysicalPosition { y: scroll, .. }) => {
+                *scroll / 100.0
+            }
+        };
+        let delta = delta * self.zoom_sensitivity;
+        match self.cursor_position {
+            Some(cursor_position) => self.camera_controller.zoom_around(delta, cursor_position),
+            None => self.camera_controller.zoom(delta),
+        }
+    }
+
+    fn process_zoom_key_press(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+        let delta = match key {
+            VirtualKeyCode::Plus | VirtualKeyCode::I => KEY_ZOOM_DELTA,
+            VirtualKeyCode::Minus | VirtualKeyCode::K => -KEY_ZOOM_DELTA,
+            _ => return false,
+        };
+        if state == ElementState::Pressed {
+            self.camera_controller.zoom(delta);
+        }
+        true
+    }
+
     pub fn device_input(&mut self, _event: &DeviceEvent) -> bool {
         false
     }
@@ -62,12 +116,9 @@ impl InputController {
         match event {
             WindowEvent::CursorMoved { position, .. } => {
                 let position: (f64, f64) = position.to_owned().into();
-                self.pan_handler
-                    .process_window_position(&Vector2::from(position), false);
+                self.process_window_position(Vector2::from(position));
                 self.query_handler
                     .process_window_position(&Vector2::from(position), false);
-                self.zoom_handler
-                    .process_window_position(&Vector2::from(position), false);
                 true
             }
             WindowEvent::KeyboardInput {
@@ -81,7 +132,7 @@ impl InputController {
             } => {
                 if !self.shift_handler.process_key_press(*key, *state) {
                     if !self.tilt_handler.process_key_press(*key, *state) {
-                        self.zoom_handler.process_key_press(*key, *state)
+                        self.process_zoom_key_press(*key, *state)
                     } else {
                         false
                     }
@@ -91,34 +142,35 @@ impl InputController {
             }
             WindowEvent::Touch(touch) => match touch.phase {
                 TouchPhase::Started => {
-                    self.pan_handler.process_touch_start();
+                    let position: (f64, f64) = touch.location.to_owned().into();
+                    self.cursor_position = Some(Vector2::from(position));
+                    self.process_drag(true);
                     self.query_handler.process_touch_start();
                     true
                 }
                 TouchPhase::Ended => {
-                    self.pan_handler.process_touch_end();
+                    self.process_drag(false);
                     self.query_handler.process_touch_end();
                     true
                 }
                 TouchPhase::Moved => {
                     let position: (f64, f64) = touch.location.to_owned().into();
-                    self.pan_handler
-                        .process_window_position(&Vector2::from(position), true);
+                    self.process_window_position(Vector2::from(position));
                     self.query_handler
                         .process_window_position(&Vector2::from(position), true);
-                    self.zoom_handler
-                        .process_window_position(&Vector2::from(position), true);
                     true
                 }
                 TouchPhase::Cancelled => false,
             },
             WindowEvent::MouseWheel { delta, .. } => {
                 self.shift_handler.process_scroll(delta);
-                self.zoom_handler.process_scroll(delta);
+                self.process_scroll(delta);
                 true
             }
             WindowEvent::MouseInput { button, state, .. } => {
-                self.pan_handler.process_mouse_key_press(button, state);
+                if *button == MouseButton::Left {
+                    self.process_drag(*state == ElementState::Pressed);
+                }
                 self.query_handler.process_mouse_key_press(button, state)
             }
             _ => false,
@@ -132,9 +184,7 @@ pub trait UpdateState {
 
 impl UpdateState for InputController {
     fn update_state(&mut self, state: &mut ViewState, dt: Duration) {
-        self.pan_handler.update_state(state, dt);
-        self.pinch_handler.update_state(state, dt);
-        self.zoom_handler.update_state(state, dt);
+        self.camera_controller.update_state(state, dt);
         self.tilt_handler.update_state(state, dt);
         self.shift_handler.update_state(state, dt);
         self.query_handler.update_state(state, dt);
@@ -1,18 +0,0 @@
-use super::UpdateState;
-
-use maplibre::context::ViewState;
-use std::time::Duration;
-
-pub struct PinchHandler {}
-
-impl UpdateState for PinchHandler {
-    fn update_state(&mut self, _state: &mut ViewState, _dt: Duration) {
-        // TODO
-    }
-}
-
-impl PinchHandler {
-    pub fn new() -> Self {
-        Self {}
-    }
-}
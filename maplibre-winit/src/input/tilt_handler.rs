@@ -8,6 +8,7 @@ use std::time::Duration;
 
 pub struct TiltHandler {
     delta_pitch: Deg<f64>,
+    delta_bearing: Deg<f64>,
 
     speed: f64,
     sensitivity: f64,
@@ -18,8 +19,14 @@ impl UpdateState for TiltHandler {
         let dt = dt.as_secs_f64() * (1.0 / self.speed);
 
         let delta = self.delta_pitch * dt;
-        state.camera.pitch += Rad::from(delta);
+        let pitch = state.camera.pitch + Rad::from(delta);
+        state.camera.set_pitch(pitch);
         self.delta_pitch -= delta;
+
+        let delta = self.delta_bearing * dt;
+        let bearing = state.camera.bearing + Rad::from(delta);
+        state.camera.set_bearing(bearing);
+        self.delta_bearing -= delta;
     }
 }
 
@@ -27,6 +34,7 @@ impl TiltHandler {
     pub fn new(speed: f64, sensitivity: f64) -> Self {
         Self {
             delta_pitch: Deg::zero(),
+            delta_bearing: Deg::zero(),
             speed,
             sensitivity,
         }
@@ -51,6 +59,14 @@ impl TiltHandler {
                 self.delta_pitch += amount;
                 true
             }
+            winit::event::VirtualKeyCode::Q => {
+                self.delta_bearing -= amount;
+                true
+            }
+            winit::event::VirtualKeyCode::E => {
+                self.delta_bearing += amount;
+                true
+            }
             _ => false,
         }
     }
@@ -1,16 +1,22 @@
 use instant::Instant;
-use maplibre::error::Error;
+use maplibre::error::{Error, RenderError};
 use maplibre::io::scheduler::ScheduleMethod;
 use maplibre::io::source_client::HTTPClient;
 use std::borrow::BorrowMut;
+use std::time::Duration;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::ControlFlow;
 
 use crate::input::{InputController, UpdateState};
 use maplibre::map_schedule::MapSchedule;
-use maplibre::window::{MapWindow, MapWindowConfig, Runnable};
+use maplibre::window::{MapWindow, MapWindowConfig, Runnable, WindowSize};
 use winit::event::Event;
 
+/// Longest time step with which the camera is moved. After the map was not redrawn for a while,
+/// e.g. in [`maplibre::redraw::RedrawMode::OnDemand`], the time since the last frame is not the
+/// duration of a frame.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(100);
+
 #[cfg(target_arch = "wasm32")]
 mod web;
 
@@ -84,17 +90,28 @@ where
         self.take_event_loop()
             .unwrap()
             .run(move |event, _, control_flow| {
+                // On android the native surface is created when the app is resumed and destroyed
+                // when it is suspended
                 #[cfg(target_os = "android")]
-                if !map_state.is_initialized() && event == Event::Resumed {
-                    use tokio::runtime::Handle;
-                    use tokio::task;
-
-                    let state = task::block_in_place(|| {
-                        Handle::current().block_on(async {
-                            map_state.late_init().await;
-                        })
-                    });
-                    return;
+                match event {
+                    Event::Resumed => {
+                        use tokio::runtime::Handle;
+                        use tokio::task;
+
+                        let result = task::block_in_place(|| {
+                            Handle::current()
+                                .block_on(map_state.recreate_surface(self.inner(), self.size()))
+                        });
+                        if let Err(e) = result {
+                            log::error!("Failed to initialize the renderer: {:?}", e);
+                        }
+                        return;
+                    }
+                    Event::Suspended => {
+                        map_state.destroy_surface();
+                        return;
+                    }
+                    _ => {}
                 }
 
                 match event {
@@ -102,14 +119,18 @@ where
                     ref event,
                     .. // We're not using device_id currently
                 } => {
-                    input_controller.device_input(event);
+                    if input_controller.device_input(event) {
+                        map_state.request_redraw();
+                    }
                 }
 
                 Event::WindowEvent {
                     ref event,
                     window_id,
                 } if window_id == self.inner().id() => {
-                    if !input_controller.window_input(event) {
+                    if input_controller.window_input(event) {
+                        map_state.request_redraw();
+                    } else {
                         match event {
                             WindowEvent::CloseRequested
                             | WindowEvent::KeyboardInput {
@@ -122,10 +143,22 @@ where
                                 ..
                             } => *control_flow = ControlFlow::Exit,
                             WindowEvent::Resized(physical_size) => {
-                                map_state.resize(physical_size.width, physical_size.height);
+                                // Minimized windows have a size of zero
+                                let size = WindowSize::new(physical_size.width, physical_size.height);
+                                if let Some(size) = size {
+                                    let scale_factor = self.inner().scale_factor();
+                                    map_state.resize(size.with_device_pixel_ratio(scale_factor));
+                                }
                             }
-                            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                                map_state.resize(new_inner_size.width, new_inner_size.height);
+                            WindowEvent::ScaleFactorChanged {
+                                scale_factor,
+                                new_inner_size,
+                            } => {
+                                let size =
+                                    WindowSize::new(new_inner_size.width, new_inner_size.height);
+                                if let Some(size) = size {
+                                    map_state.resize(size.with_device_pixel_ratio(*scale_factor));
+                                }
                             }
                             _ => {}
                         }
@@ -133,15 +166,40 @@ where
                 }
                 Event::RedrawRequested(_) => {
                     let now = Instant::now();
-                    let dt = now - last_render_time;
+                    let dt = (now - last_render_time).min(MAX_FRAME_TIME);
                     last_render_time = now;
 
                     {
+                        if let Some(style) = map_state.style() {
+                            input_controller.set_zoom_range_of(style);
+                        }
                         input_controller.update_state(map_state.view_state_mut(), dt);
                     }
 
                     match map_state.update_and_redraw() {
                         Ok(_) => {}
+                        Err(Error::Render(RenderError::DeviceLost)) => {
+                            log::warn!("The surface was lost, initializing the renderer again");
+                            #[cfg(not(target_arch = "wasm32"))]
+                            {
+                                use tokio::runtime::Handle;
+                                use tokio::task;
+
+                                let result = task::block_in_place(|| {
+                                    Handle::current()
+                                        .block_on(map_state.recreate_surface(self.inner(), self.size()))
+                                });
+                                if let Err(e) = result {
+                                    log::error!("Failed to initialize the renderer: {:?}", e);
+                                    *control_flow = ControlFlow::Exit;
+                                }
+                            }
+                            // The renderer can not be initialized synchronously in the browser
+                            #[cfg(target_arch = "wasm32")]
+                            {
+                                *control_flow = ControlFlow::Exit;
+                            }
+                        }
                         Err(Error::Render(e)) => {
                             eprintln!("{}", e);
                             if e.should_exit() {
@@ -168,8 +226,18 @@ where
                 }
                 Event::MainEventsCleared => {
                     // RedrawRequested will only trigger once, unless we manually
-                    // request it.
-                    self.inner().request_redraw();
+                    // request it. The map decides when it needs to be updated again. In
+                    // on-demand mode, an update only renders a frame if the map changed.
+                    if *control_flow != ControlFlow::Exit {
+                        match map_state.next_redraw() {
+                            Some(next_redraw) if next_redraw <= Instant::now() => {
+                                *control_flow = ControlFlow::Poll;
+                                self.inner().request_redraw();
+                            }
+                            Some(next_redraw) => *control_flow = ControlFlow::WaitUntil(next_redraw),
+                            None => *control_flow = ControlFlow::Wait,
+                        }
+                    }
                 }
                 _ => {}
             }
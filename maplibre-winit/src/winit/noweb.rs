@@ -41,10 +41,15 @@ impl MapWindow for WinitMapWindow {
         #[cfg(not(target_os = "android"))]
         let window_size =
             WindowSize::new(size.width, size.height).expect("failed to get window dimensions.");
-        window_size
+        window_size.with_device_pixel_ratio(self.window.scale_factor())
     }
 
     fn inner(&self) -> &Self::Window {
         &self.window
     }
+
+    /// On android the surface is only available once the application is resumed.
+    fn surface_available(&self) -> bool {
+        !cfg!(target_os = "android")
+    }
 }
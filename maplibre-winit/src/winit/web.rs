@@ -32,7 +32,9 @@ impl MapWindow for WinitMapWindow {
     fn size(&self) -> WindowSize {
         let size = self.window.inner_size();
 
-        WindowSize::new(size.width, size.height).expect("failed to get window dimensions.")
+        WindowSize::new(size.width, size.height)
+            .expect("failed to get window dimensions.")
+            .with_device_pixel_ratio(self.window.scale_factor())
     }
 
     fn inner(&self) -> &Self::Window {
@@ -5,6 +5,15 @@ pub mod io {
     pub use crate::io::*;
 }
 
+/// Re-export of the data which the marker overlay uploads every frame.
+pub mod render {
+    pub use crate::render::marker_overlay::MarkerBatch;
+}
+
+pub mod culling;
+
+pub mod tile_load;
+
 /// Re-export of the tessellation module.
 pub mod tessellation {
     pub use crate::tessellation::*;
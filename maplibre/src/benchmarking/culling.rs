@@ -0,0 +1,97 @@
+//! Culls tiles on the CPU like the queue stage does by default, and on the GPU with the compute
+//! pass of [`GpuCulling`], see [`crate::render::culling`]. Culling on the GPU is measured on a
+//! headless device, including the upload of the draws and the readback of the indirect draws.
+
+pub use crate::render::culling::{is_tile_visible, CullBatch};
+
+use crate::error::{Error, RenderError};
+use crate::render::culling::{GpuCulling, ShaderDrawIndexedIndirect, MAX_CULLED_DRAWS};
+use crate::render::settings::{RendererSettings, WgpuSettings};
+use crate::render::Renderer;
+use crate::window::WindowSize;
+use std::mem::size_of;
+
+/// Size of the arguments of an indirect draw within the buffer of the draws.
+const DRAW_SIZE: wgpu::BufferAddress =
+    size_of::<ShaderDrawIndexedIndirect>() as wgpu::BufferAddress;
+
+/// Runs the compute pass of [`GpuCulling`] on a headless device and reads back the indirect draws
+/// it writes.
+pub struct GpuCuller {
+    renderer: Renderer,
+    culling: GpuCulling,
+    readback: wgpu::Buffer,
+}
+
+impl GpuCuller {
+    /// Initializes a headless device. Returns `None` if the device lacks compute shaders or
+    /// indirect draws, like WebGL2.
+    pub async fn new() -> Result<Option<Self>, Error> {
+        let renderer = Renderer::initialize_headless(
+            WindowSize::new(1, 1).unwrap(),
+            WgpuSettings::default(),
+            RendererSettings::default(),
+        )
+        .await?;
+
+        let capabilities = renderer.capabilities();
+        if !capabilities.compute_shaders || !capabilities.indirect_execution {
+            return Ok(None);
+        }
+
+        let culling = GpuCulling::new(renderer.device());
+        let readback = renderer.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("culling readback buffer"),
+            size: MAX_CULLED_DRAWS as wgpu::BufferAddress * DRAW_SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Ok(Some(Self {
+            renderer,
+            culling,
+            readback,
+        }))
+    }
+
+    /// The tiles and draws which are culled by the next [`GpuCuller::cull`].
+    pub fn batch_mut(&mut self) -> &mut CullBatch {
+        self.culling.batch_mut()
+    }
+
+    /// Uploads the batch, dispatches the compute pass and waits until the indirect draws are read
+    /// back. Returns the number of draws which are not culled.
+    pub async fn cull(&mut self) -> Result<usize, Error> {
+        let device = self.renderer.device();
+        let queue = self.renderer.queue();
+        let size = self.culling.batch_mut().len() as wgpu::BufferAddress * DRAW_SIZE;
+        if size == 0 {
+            return Ok(0);
+        }
+
+        self.culling.write_batch(queue);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("culling benchmark encoder"),
+        });
+        self.culling.dispatch(&mut encoder);
+        encoder.copy_buffer_to_buffer(self.culling.draws(), 0, &self.readback, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = self.readback.slice(..size);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        mapping
+            .await
+            .map_err(|e| Error::Render(RenderError::Readback(e.to_string())))?;
+
+        let visible = {
+            let draws = slice.get_mapped_range();
+            bytemuck::cast_slice::<u8, ShaderDrawIndexedIndirect>(&draws)
+                .iter()
+                .filter(|draw| draw.instance_count > 0)
+                .count()
+        };
+        self.readback.unmap();
+        Ok(visible)
+    }
+}
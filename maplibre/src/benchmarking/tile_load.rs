@@ -0,0 +1,221 @@
+//! Loads vector tiles like the map does once they are fetched: the tile is decoded, each of its
+//! layers is tessellated and the geometry is uploaded into a [`BufferPool`]. The buffers of the
+//! pool live in memory, such that uploads measure the copies on the CPU but not the transfer to
+//! the GPU.
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::tessellation_pool::{map_ordered, tessellate_layer};
+use crate::io::tile_reader::read_layers;
+use crate::render::resource::{BackingBufferDescriptor, BufferPool, Queue};
+use crate::render::resource::{
+    FEATURE_METADATA_SIZE, INDICES_SIZE, LAYER_METADATA_SIZE, VERTEX_SIZE,
+};
+use crate::render::shaders::{ShaderFeatureStyle, ShaderLayerMetadata};
+use crate::render::ShaderVertex;
+use crate::style::layer::StyleLayer;
+use crate::tessellation::{IndexDataType, LineStyle};
+use geozero::mvt::{tile, Tile};
+use prost::Message;
+use std::cell::RefCell;
+use std::mem::size_of;
+use std::sync::Arc;
+
+/// Color of all features, because the style is not evaluated.
+const FEATURE_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+/// Buffer in memory which stands in for a GPU buffer.
+pub struct MemoryBuffer(RefCell<Vec<u8>>);
+
+impl MemoryBuffer {
+    fn new(size: usize) -> Self {
+        Self(RefCell::new(vec![0; size]))
+    }
+}
+
+/// Writes into [`MemoryBuffer`]s.
+pub struct MemoryQueue;
+
+impl Queue<MemoryBuffer> for MemoryQueue {
+    fn write_buffer(&self, buffer: &MemoryBuffer, offset: wgpu::BufferAddress, data: &[u8]) {
+        let offset = offset as usize;
+        buffer.0.borrow_mut()[offset..offset + data.len()].copy_from_slice(data);
+    }
+}
+
+/// Amount of geometry which was uploaded for a tile.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LoadedTile {
+    pub layers: usize,
+    pub features: usize,
+    pub vertices: usize,
+    pub indices: usize,
+}
+
+/// Loads tiles into a buffer pool of the same size as the one of the map. Once the pool is full,
+/// the oldest tiles are evicted like in the map.
+pub struct TileLoader {
+    queue: MemoryQueue,
+    buffer_pool: BufferPool<
+        MemoryQueue,
+        MemoryBuffer,
+        ShaderVertex,
+        IndexDataType,
+        ShaderLayerMetadata,
+        ShaderFeatureStyle,
+    >,
+}
+
+impl Default for TileLoader {
+    fn default() -> Self {
+        let descriptor = |stride: usize, count: wgpu::BufferAddress| {
+            let size = stride as wgpu::BufferAddress * count;
+            BackingBufferDescriptor::new(MemoryBuffer::new(size as usize), size)
+        };
+
+        Self {
+            queue: MemoryQueue,
+            buffer_pool: BufferPool::new(
+                descriptor(size_of::<ShaderVertex>(), VERTEX_SIZE),
+                descriptor(size_of::<IndexDataType>(), INDICES_SIZE),
+                descriptor(size_of::<ShaderLayerMetadata>(), LAYER_METADATA_SIZE),
+                descriptor(size_of::<ShaderFeatureStyle>(), FEATURE_METADATA_SIZE),
+            ),
+        }
+    }
+}
+
+impl TileLoader {
+    /// Decodes the vector tile `data`, tessellates all of its layers and uploads them as the tile
+    /// at `coords`. Each layer is drawn by a fill style layer of the same name.
+    pub fn load(&mut self, coords: WorldTileCoords, data: &[u8]) -> Result<LoadedTile, Error> {
+        let mut tile = Tile::decode(data).map_err(|e| Error::InvalidTile(e.to_string()))?;
+
+        let mut loaded = LoadedTile::default();
+        for (index, layer) in tile.layers.iter_mut().enumerate() {
+            let geometry = tessellate_layer(layer, &LineStyle::default(), || false)?;
+
+            let style_layer = StyleLayer {
+                index: index as u32,
+                id: layer.name.clone(),
+                source_layer: Some(layer.name.clone()),
+                ..StyleLayer::default()
+            };
+            // Like the `UploadStage`, one style is uploaded per index of a feature
+            let feature_metadata =
+                vec![ShaderFeatureStyle::new(FEATURE_COLOR, None); geometry.buffer.indices.len()];
+
+            loaded.layers += 1;
+            loaded.features += layer.features.len();
+            loaded.vertices += geometry.buffer.vertices.len();
+            loaded.indices += geometry.buffer.indices.len();
+
+            self.buffer_pool.allocate_layer_geometry(
+                &self.queue,
+                coords,
+                style_layer,
+                &geometry.buffer.into(),
+                ShaderLayerMetadata::new(index as u32, None, None, None),
+                &feature_metadata,
+            );
+        }
+        Ok(loaded)
+    }
+}
+
+/// Decodes the vector tiles `data`.
+pub fn decode_tiles(data: &[&[u8]]) -> Result<Vec<Tile>, Error> {
+    data.iter()
+        .map(|data| Tile::decode(*data).map_err(|e| Error::InvalidTile(e.to_string())))
+        .collect()
+}
+
+/// Tessellates all layers of `tiles`, on the thread pool of the map if `parallel` is set or one
+/// after another otherwise. Returns the number of vertices.
+pub fn tessellate_tiles(tiles: &mut [Tile], parallel: bool) -> Result<usize, Error> {
+    let layers: Vec<&mut tile::Layer> = tiles
+        .iter_mut()
+        .flat_map(|tile| tile.layers.iter_mut())
+        .collect();
+    let tessellate = |layer: &mut tile::Layer| {
+        tessellate_layer(layer, &LineStyle::default(), || false)
+            .map(|geometry| geometry.buffer.vertices.len())
+    };
+
+    let vertices = if parallel {
+        map_ordered(layers, tessellate)
+    } else {
+        layers.into_iter().map(tessellate).collect()
+    };
+    vertices.into_iter().sum()
+}
+
+/// Tessellates the first layer of the vector tile `data`, which is when the tile starts to appear.
+/// If `streaming` is set, only the first layer is decoded like in the map. Otherwise the whole tile
+/// is decoded beforehand. Returns the number of vertices.
+pub fn tessellate_first_layer(data: &[u8], streaming: bool) -> Result<usize, Error> {
+    let layer = if streaming {
+        read_layers(Arc::from(data))?
+            .first()
+            .map(|layer| layer.decode())
+            .transpose()?
+    } else {
+        Tile::decode(data)
+            .map_err(|e| Error::InvalidTile(e.to_string()))?
+            .layers
+            .into_iter()
+            .next()
+    };
+
+    match layer {
+        Some(mut layer) => tessellate_layer(&mut layer, &LineStyle::default(), || false)
+            .map(|geometry| geometry.buffer.vertices.len()),
+        None => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_tiles, tessellate_first_layer, tessellate_tiles, TileLoader};
+
+    #[test]
+    fn test_load_fixtures() {
+        let mut loader = TileLoader::default();
+
+        let fill = loader
+            .load(
+                (0, 0, 0).into(),
+                include_bytes!("../../../test-data/fixtures/fill-heavy.pbf"),
+            )
+            .unwrap();
+        assert_eq!((fill.layers, fill.features), (2, 1616));
+        assert!(fill.vertices > 0 && fill.indices > fill.vertices);
+
+        let line = loader
+            .load(
+                (1, 0, 1).into(),
+                include_bytes!("../../../test-data/fixtures/line-heavy.pbf"),
+            )
+            .unwrap();
+        assert_eq!((line.layers, line.features), (2, 1240));
+        assert!(line.vertices > 0);
+
+        assert!(loader.load((0, 1, 1).into(), &[0xff]).is_err());
+    }
+
+    #[test]
+    fn test_parallel_tessellation_is_identical() {
+        let fill = include_bytes!("../../../test-data/fixtures/fill-heavy.pbf");
+        let line = include_bytes!("../../../test-data/fixtures/line-heavy.pbf");
+        let mut tiles = decode_tiles(&[fill, line, fill]).unwrap();
+
+        assert_eq!(
+            tessellate_tiles(&mut tiles, true).unwrap(),
+            tessellate_tiles(&mut tiles, false).unwrap()
+        );
+
+        let streamed = tessellate_first_layer(line, true).unwrap();
+        assert!(streamed > 0);
+        assert_eq!(streamed, tessellate_first_layer(line, false).unwrap());
+    }
+}
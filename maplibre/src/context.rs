@@ -1,24 +1,73 @@
-use crate::coords::{Zoom, TILE_SIZE};
+use crate::coords::{
+    LatLon, ViewRegion, WorldCoords, WorldTileCoords, Zoom, MAX_LATITUDE, TILE_SIZE,
+};
+use crate::events::MapEvents;
+use crate::io::feature_query::{query_rendered_features, QueriedFeature, DEFAULT_QUERY_RADIUS};
 use crate::io::shared_thread_state::SharedThreadState;
 use crate::io::tile_cache::TileCache;
-use crate::io::TessellateMessage;
+use crate::io::{LayerTessellateMessage, TessellateMessage};
+use crate::markers::Markers;
 use crate::render::camera::{Camera, Perspective, ViewProjection};
+use crate::render::camera_animation::{AnimationHandle, CameraAnimation, CameraState};
+use crate::render::settings::Wireframe;
+use crate::style::source::Source;
 use crate::util::ChangeObserver;
 use crate::{Renderer, ScheduleMethod, Style, WindowSize};
+use cgmath::{Angle, Rad, Vector2, Vector4};
+use instant::Instant;
+use std::collections::{HashMap, HashSet};
 use std::sync::mpsc;
+use std::time::Duration;
+
+/// A position in the window in physical pixels. The origin is in the upper-left corner.
+pub type ScreenPoint = Vector2<f64>;
+
+/// The target of a camera animation. Properties which are `None` keep their current value.
+#[derive(Clone, Debug, Default)]
+pub struct CameraTarget {
+    pub center: Option<LatLon>,
+    pub zoom: Option<Zoom>,
+    pub pitch: Option<Rad<f64>>,
+    pub bearing: Option<Rad<f64>>,
+}
+
+impl CameraTarget {
+    pub fn new(center: LatLon, zoom: Zoom) -> Self {
+        Self {
+            center: Some(center),
+            zoom: Some(zoom),
+            pitch: None,
+            bearing: None,
+        }
+    }
+
+    /// Takes the properties which are `None` from `other`.
+    pub fn or(self, other: CameraTarget) -> Self {
+        Self {
+            center: self.center.or(other.center),
+            zoom: self.zoom.or(other.zoom),
+            pitch: self.pitch.or(other.pitch),
+            bearing: self.bearing.or(other.bearing),
+        }
+    }
+}
 
 /// Stores the camera configuration.
 pub struct ViewState {
     pub zoom: ChangeObserver<Zoom>,
     pub camera: ChangeObserver<Camera>,
     pub perspective: Perspective,
+    /// Number of physical pixels per logical pixel of the window.
+    device_pixel_ratio: f64,
+
+    animation: Option<CameraAnimation>,
 }
 
 impl ViewState {
     pub fn new(window_size: &WindowSize) -> Self {
         let camera = Camera::new(
             (TILE_SIZE / 2.0, TILE_SIZE / 2.0, 150.0),
-            cgmath::Deg(-90.0),
+            cgmath::Deg(0.0),
             cgmath::Deg(0.0),
             window_size.width(),
             window_size.height(),
@@ -36,6 +85,8 @@ impl ViewState {
             zoom: ChangeObserver::default(),
             camera: ChangeObserver::new(camera),
             perspective,
+            device_pixel_ratio: window_size.device_pixel_ratio(),
+            animation: None,
         }
     }
 
@@ -43,10 +94,34 @@ impl ViewState {
         self.camera.calc_view_proj(&self.perspective)
     }
 
+    /// Adapts the aspect ratio of the camera to a window of the given size.
+    pub fn resize(&mut self, size: WindowSize) {
+        self.perspective.resize(size.width(), size.height());
+        self.camera.resize(size.width(), size.height());
+        self.device_pixel_ratio = size.device_pixel_ratio();
+        // The tiles in view change with the size of the window
+        self.camera.reset_reference();
+    }
+
+    /// Number of physical pixels per logical pixel of the window. The camera works in physical
+    /// pixels. Line widths and text sizes are given in world units, so they keep their size
+    /// relative to the window regardless of its pixel density.
+    pub fn device_pixel_ratio(&self) -> f64 {
+        self.device_pixel_ratio
+    }
+
     pub fn visible_level(&self) -> u8 {
         self.zoom.level()
     }
 
+    /// Returns the tiles of the visible zoom level which are in view.
+    pub fn view_region(&self) -> Option<ViewRegion> {
+        let view_proj = self.view_projection();
+        self.camera
+            .view_region_bounding_box(&view_proj.invert())
+            .map(|bounding_box| ViewRegion::new(bounding_box, 0, *self.zoom, self.visible_level()))
+    }
+
     pub fn zoom(&self) -> Zoom {
         *self.zoom
     }
@@ -55,6 +130,269 @@ impl ViewState {
         *self.zoom = new_zoom;
         log::info!("zoom: {}", new_zoom);
     }
+
+    /// Returns the current center, zoom, pitch and bearing.
+    pub fn camera_state(&self) -> CameraState {
+        let world_size = TILE_SIZE * 2.0_f64.powf(self.zoom.value());
+        CameraState {
+            center: Vector2::new(
+                self.camera.position.x / world_size,
+                self.camera.position.y / world_size,
+            ),
+            zoom: self.zoom.value(),
+            pitch: self.camera.pitch,
+            bearing: self.camera.bearing,
+        }
+    }
+
+    /// Moves the camera to `state` immediately.
+    pub fn set_camera_state(&mut self, state: &CameraState) {
+        let world_size = TILE_SIZE * 2.0_f64.powf(state.zoom);
+        *self.zoom = Zoom::new(state.zoom);
+        self.camera.position.x = state.center.x * world_size;
+        self.camera.position.y = state.center.y * world_size;
+        self.camera.set_pitch(state.pitch);
+        self.camera.set_bearing(state.bearing);
+    }
+
+    fn target_state(&self, target: CameraTarget) -> CameraState {
+        let current = self.camera_state();
+        CameraState {
+            center: target
+                .center
+                .map(|center| {
+                    let world = center.into_world(Zoom::default());
+                    let mut center = Vector2::new(world.x / TILE_SIZE, world.y / TILE_SIZE);
+                    // Move towards the closest copy of the target, which might be across the
+                    // antimeridian
+                    center.x += (current.center.x - center.x).round();
+                    center
+                })
+                .unwrap_or(current.center),
+            zoom: target.zoom.map(|zoom| zoom.value()).unwrap_or(current.zoom),
+            pitch: target.pitch.unwrap_or(current.pitch),
+            bearing: target.bearing.unwrap_or(current.bearing),
+        }
+    }
+
+    /// Amount of world units which are covered by a pixel at the center of the window if the
+    /// camera looks straight down.
+    pub(crate) fn world_units_per_pixel(&self) -> f64 {
+        let visible_height = 2.0 * self.camera.position.z * (self.perspective.fovy() / 2.0).tan();
+        visible_height / self.camera.height
+    }
+
+    /// Calculates the center and the maximum zoom at which `bounds` fit into the window. `bounds`
+    /// are `[west, south, east, north]` in degrees and `padding` is the space in pixels which is
+    /// kept free at each side of the window. The bounds are fitted as seen from above, pitch and
+    /// bearing are not changed.
+    pub fn camera_for_bounds(&self, bounds: [f64; 4], padding: f64) -> CameraTarget {
+        let [west, south, mut east, north] = bounds;
+        if east < west {
+            // The bounds cross the antimeridian
+            east += 360.0;
+        }
+
+        // Web Mercator stretches the bounds towards the poles
+        let north_west = LatLon::new(north, west).into_world(Zoom::default());
+        let south_east = LatLon::new(south, east).into_world(Zoom::default());
+        let center = WorldCoords::at_ground(
+            (north_west.x + south_east.x) / 2.0,
+            (north_west.y + south_east.y) / 2.0,
+        )
+        .into_lat_lon(Zoom::default());
+
+        let world_units_per_pixel = self.world_units_per_pixel();
+        let available_width = (self.camera.width - 2.0 * padding).max(1.0) * world_units_per_pixel;
+        let available_height =
+            (self.camera.height - 2.0 * padding).max(1.0) * world_units_per_pixel;
+
+        let width = south_east.x - north_west.x;
+        let height = south_east.y - north_west.y;
+        let scale = (available_width / width).min(available_height / height);
+
+        CameraTarget {
+            center: Some(center),
+            zoom: if scale.is_finite() {
+                Some(Zoom::new(scale.log2()))
+            } else {
+                // The bounds are a single point
+                None
+            },
+            pitch: None,
+            bearing: None,
+        }
+    }
+
+    /// Moves the camera such that `bounds` fit into the window, see
+    /// [`ViewState::camera_for_bounds`]. Returns the new center and zoom.
+    pub fn fit_bounds(&mut self, bounds: [f64; 4], padding: f64) -> CameraTarget {
+        let target = self.camera_for_bounds(bounds, padding);
+        let state = self.target_state(target.clone());
+        self.stop_animation();
+        self.set_camera_state(&state);
+        target
+    }
+
+    /// Returns the geographic position at the center of the view. The longitude is within
+    /// `-180..180`, even if the camera moved across the antimeridian.
+    pub fn center(&self) -> LatLon {
+        WorldCoords::from(self.camera.position)
+            .into_lat_lon(self.zoom())
+            .wrap()
+    }
+
+    /// Moves the camera by whole world widths such that it is above the world, i.e. between the
+    /// longitudes -180° and 180°. The view does not change, because the world repeats
+    /// horizontally, but the camera stays close to the origin when panning around the world.
+    pub fn wrap_camera(&mut self) {
+        let world_size = TILE_SIZE * 2.0_f64.powf(self.zoom.value());
+        let x = self.camera.position.x;
+        if !(0.0..world_size).contains(&x) {
+            self.camera.position.x = x.rem_euclid(world_size);
+        }
+    }
+
+    /// Returns the position on the ground at the center of the window. Falls back to the position
+    /// below the camera if the center of the window is above the horizon.
+    pub fn center_at_ground(&self) -> WorldCoords {
+        let center = Vector2::new(self.camera.width, self.camera.height) / 2.0;
+        self.camera
+            .window_to_world_at_ground(&center, &self.view_projection().invert())
+            .map(|world| WorldCoords::at_ground(world.x, world.y))
+            .unwrap_or_else(|| WorldCoords::from(self.camera.position))
+    }
+
+    /// Returns the geographic position on the ground at `window_position`, or `None` if the
+    /// position is above the horizon. Longitudes beyond the antimeridian are not wrapped.
+    pub fn window_to_lat_lon(&self, window_position: &Vector2<f64>) -> Option<LatLon> {
+        let inverted_view_proj = self.view_projection().invert();
+        self.camera
+            .window_to_world_at_ground(window_position, &inverted_view_proj)
+            .map(|world| WorldCoords::at_ground(world.x, world.y).into_lat_lon(self.zoom()))
+    }
+
+    /// Returns where `lat_lon` is drawn in the window, or `None` if it is behind the camera. The
+    /// world repeats horizontally, so the copy of the position which is closest to the camera is
+    /// projected, just like the copies of the tiles which are drawn. Positions outside of the
+    /// window are projected as well.
+    pub fn project(&self, lat_lon: LatLon) -> Option<ScreenPoint> {
+        self.project_with(lat_lon, &self.view_projection())
+    }
+
+    /// Like [`ViewState::project`], but with the `view_proj` of [`ViewState::view_projection`]
+    /// such that it is computed only once when projecting many positions.
+    pub(crate) fn project_with(
+        &self,
+        lat_lon: LatLon,
+        view_proj: &ViewProjection,
+    ) -> Option<ScreenPoint> {
+        let zoom = self.zoom();
+        let world = lat_lon.into_world(zoom);
+        let world_size = TILE_SIZE * zoom.scale();
+        let x = world.x + ((self.camera.position.x - world.x) / world_size).round() * world_size;
+
+        let clip = view_proj.project(Vector4::new(x, world.y, 0.0, 1.0));
+        if clip.w <= 0.0 {
+            return None;
+        }
+
+        Some(ScreenPoint::new(
+            (clip.x / clip.w + 1.0) / 2.0 * self.camera.width,
+            (1.0 - clip.y / clip.w) / 2.0 * self.camera.height,
+        ))
+    }
+
+    /// Returns the geographic position on the ground at `screen_point`, i.e. the inverse of
+    /// [`ViewState::project`]. Returns `None` if the map is pitched and the point is above the
+    /// horizon. Longitudes beyond the antimeridian are not wrapped, see [`LatLon::wrap`].
+    pub fn unproject(&self, screen_point: &ScreenPoint) -> Option<LatLon> {
+        self.window_to_lat_lon(screen_point)
+    }
+
+    /// Changes the zoom such that the geographic position at `window_position` stays in place.
+    /// Positions above the horizon or beyond the poles of Web Mercator can not be kept in place,
+    /// in which case the center of the window stays in place instead.
+    pub fn zoom_around(&mut self, zoom: Zoom, window_position: &Vector2<f64>) {
+        let center = Vector2::new(self.camera.width, self.camera.height) / 2.0;
+        let anchor = self
+            .window_to_lat_lon(window_position)
+            .filter(|anchor| anchor.latitude.abs() <= MAX_LATITUDE)
+            .map(|anchor| (*window_position, anchor))
+            .or_else(|| {
+                self.window_to_lat_lon(&center)
+                    .map(|anchor| (center, anchor))
+            });
+
+        self.update_zoom(zoom);
+
+        if let Some((window_position, anchor)) = anchor {
+            let inverted_view_proj = self.view_projection().invert();
+            if let Some(position) = self
+                .camera
+                .window_to_world_at_ground(&window_position, &inverted_view_proj)
+            {
+                // The longitude is not wrapped, so an anchor beyond the antimeridian stays on
+                // the same side of the window
+                let target = anchor.into_world(zoom);
+                self.camera.position.x += target.x - position.x;
+                self.camera.position.y += target.y - position.y;
+            }
+        }
+    }
+
+    /// Moves the camera to `target` immediately. A running animation is interrupted.
+    pub fn jump_to(&mut self, target: CameraTarget) {
+        let state = self.target_state(target);
+        self.stop_animation();
+        self.set_camera_state(&state);
+    }
+
+    /// Animates the camera to `target` by interpolating center, zoom, pitch and bearing. A
+    /// running animation is interrupted.
+    pub fn ease_to(&mut self, target: CameraTarget, duration: Duration) -> AnimationHandle {
+        let animation =
+            CameraAnimation::ease(self.camera_state(), self.target_state(target), duration);
+        self.start_animation(animation)
+    }
+
+    /// Animates the camera to `target` along a path which zooms out and in again, so that the
+    /// transition stays comprehensible over long distances. A running animation is interrupted.
+    pub fn fly_to(&mut self, target: CameraTarget, duration: Duration) -> AnimationHandle {
+        let animation = CameraAnimation::fly(
+            self.camera_state(),
+            self.target_state(target),
+            (self.camera.width, self.camera.height),
+            duration,
+        );
+        self.start_animation(animation)
+    }
+
+    fn start_animation(&mut self, animation: CameraAnimation) -> AnimationHandle {
+        let handle = animation.handle();
+        self.animation = Some(animation);
+        handle
+    }
+
+    /// Stops the running animation, which resolves its [`AnimationHandle`] with false.
+    pub fn stop_animation(&mut self) {
+        self.animation = None;
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    /// Moves the camera to the state of the running animation at `now`.
+    pub fn advance_animation(&mut self, now: Instant) {
+        if let Some(animation) = &mut self.animation {
+            let (state, finished) = animation.tick(now);
+            self.set_camera_state(&state);
+            if finished {
+                self.animation = None;
+            }
+        }
+    }
 }
 
 pub struct MapContext {
@@ -62,9 +400,450 @@ pub struct MapContext {
     pub style: Style,
 
     pub tile_cache: TileCache,
+    /// Markers which are drawn on top of the map, see [`crate::markers`].
+    pub markers: Markers,
     pub renderer: Renderer,
     pub scheduler: Box<dyn ScheduleMethod>,
 
     pub message_receiver: mpsc::Receiver<TessellateMessage>,
     pub shared_thread_state: SharedThreadState,
+
+    /// Events of the current frame, see [`crate::events::MapEvent`].
+    pub events: MapEvents,
+}
+
+impl MapContext {
+    /// Returns the features which are rendered at `window_position`, topmost first, see
+    /// [`query_rendered_features`].
+    pub fn query_rendered_features(&self, window_position: &Vector2<f64>) -> Vec<QueriedFeature> {
+        query_rendered_features(
+            &self.view_state,
+            &self.style,
+            &self.tile_cache,
+            window_position,
+            DEFAULT_QUERY_RADIUS,
+        )
+    }
+}
+
+impl MapContext {
+    /// Replaces the style. Layers which are already tessellated in the tile cache are reused and
+    /// only source layers which the previous style did not use are requested. The geometry of all
+    /// layers is released and uploaded again with the new style layers. Raster tiles are kept for
+    /// sources whose tile URLs did not change. [`crate::events::MapEvent::StyleLoaded`] is emitted
+    /// again once the sources of the new style are resolved. Icons are drawn once the
+    /// [`Style::sprite_sheet`] is uploaded, which needs to be loaded before, see
+    /// [`Style::load_sprite`].
+    pub fn set_style(&mut self, style: Style) {
+        for source in changed_raster_sources(&self.style, &style) {
+            self.tile_cache.remove_raster_source(&source);
+            self.renderer.state.remove_raster_source(&source);
+        }
+
+        self.style = style;
+        self.renderer.state.clear_layers();
+        self.events.style_pending = true;
+
+        // Makes sure that the tiles in view are requested with the layers of the new style
+        self.view_state.camera.reset_reference();
+    }
+
+    /// Replaces the data of a GeoJSON source, e.g. with the current positions of live vehicles, see
+    /// [`Style::set_geojson_data`]. The data is parsed with the next frame and only the tiles
+    /// around the features which changed are sliced again on worker threads. Tiles which are
+    /// sliced from the previous data in the meantime are dropped. Returns false if there is no
+    /// such GeoJSON source.
+    pub fn update_geojson_source(&mut self, source_id: &str, data: serde_json::Value) -> bool {
+        if !self.style.set_geojson_data(source_id, data) {
+            return false;
+        }
+
+        // Makes sure that the request stage picks up the new data with the next frame
+        self.view_state.camera.reset_reference();
+        true
+    }
+
+    /// Shows or hides the debug overlay which outlines the tiles in view, see
+    /// [`crate::render::settings::RendererSettings::show_tile_boundaries`].
+    pub fn set_show_tile_boundaries(&mut self, show: bool) {
+        self.renderer.settings.show_tile_boundaries = show;
+    }
+
+    /// Changes the color below the background layers of the style, see
+    /// [`crate::render::settings::RendererSettings::clear_color`].
+    pub fn set_clear_color(&mut self, clear_color: wgpu::Color) {
+        self.renderer.settings.clear_color = clear_color;
+    }
+
+    /// Changes the layers which are drawn as wireframes, see
+    /// [`crate::render::settings::RendererSettings::wireframe`].
+    pub fn set_wireframe(&mut self, wireframe: Wireframe) {
+        self.renderer.settings.wireframe = wireframe;
+    }
+
+    /// Resizes the surface and the camera. Calling this with the current size does nothing.
+    pub fn resize(&mut self, size: WindowSize) {
+        if self.renderer.surface().size() == size {
+            return;
+        }
+
+        self.view_state.resize(size);
+        self.renderer.resize(size);
+    }
+
+    /// Whether all tiles in view are tessellated and their layers are uploaded to the GPU, so that
+    /// the next frame shows everything which can be shown at the current camera. Overzoomed tiles
+    /// are rendered once their ancestor is.
+    pub fn is_fully_rendered(&self) -> bool {
+        match self.view_state.view_region() {
+            Some(view_region) => self
+                .style
+                .overzoomed_tiles(&view_region)
+                .into_iter()
+                .filter(|coords| coords.build_quad_key().is_some())
+                .all(|coords| {
+                    is_tile_rendered(
+                        &self.style,
+                        &self.tile_cache,
+                        self.renderer.state().loaded_layers_at(&coords),
+                        &coords,
+                        self.view_state.zoom(),
+                    )
+                }),
+            None => true,
+        }
+    }
+}
+
+/// Returns the ids of the raster and raster DEM sources of `previous` which are removed or whose
+/// tiles are fetched from different URLs or decoded differently in `next`.
+pub(crate) fn changed_raster_sources(previous: &Style, next: &Style) -> Vec<String> {
+    previous
+        .sources
+        .iter()
+        .filter_map(|(id, source)| match source {
+            Source::Raster(source) | Source::RasterDem(source) => Some((id, source)),
+            _ => None,
+        })
+        .filter(|(id, source)| match next.sources.get(*id) {
+            Some(Source::Raster(next_source)) | Some(Source::RasterDem(next_source)) => {
+                next_source.tiles != source.tiles
+                    || next_source.url != source.url
+                    || next_source.scheme != source.scheme
+                    || next_source.encoding != source.encoding
+            }
+            _ => true,
+        })
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Whether the layers of `style` which are visible at `zoom` are tessellated at `coords` and,
+/// except for symbol and fill-extrusion layers, uploaded. Layers which are unavailable count as
+/// rendered. `loaded_layers` are the ids of the style layers which are uploaded at `coords`.
+pub(crate) fn is_tile_rendered(
+    style: &Style,
+    tile_cache: &TileCache,
+    loaded_layers: Option<HashSet<&str>>,
+    coords: &WorldTileCoords,
+    zoom: Zoom,
+) -> bool {
+    let loaded_layers = loaded_layers.unwrap_or_default();
+    let mut cached_layers: HashMap<(Option<&str>, &str), bool> = HashMap::new();
+    if let Some(layers) = tile_cache.iter_tessellated_layers_at(coords) {
+        for layer in layers {
+            let is_available = matches!(layer, LayerTessellateMessage::TessellatedLayer { .. });
+            *cached_layers
+                .entry((layer.source(), layer.layer_name()))
+                .or_default() |= is_available;
+        }
+    }
+
+    style
+        .layers
+        .iter()
+        .filter(|layer| layer.typ != "raster" && layer.is_visible_at(zoom.value()))
+        .filter_map(|layer| {
+            layer
+                .source_layer
+                .as_ref()
+                .map(|source_layer| (layer, source_layer))
+        })
+        .all(|(layer, source_layer)| {
+            match cached_layers.get(&(style.tile_source_id(layer), source_layer.as_str())) {
+                None => false,
+                Some(false) => true,
+                Some(true) => {
+                    layer.typ == "symbol"
+                        || layer.typ == "fill-extrusion"
+                        || loaded_layers.contains(layer.id.as_str())
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{changed_raster_sources, is_tile_rendered, CameraTarget, ViewState};
+    use crate::coords::{LatLon, WorldTileCoords, Zoom, TILE_SIZE};
+    use crate::io::tile_cache::TileCache;
+    use crate::io::LayerTessellateMessage;
+    use crate::render::camera::MAX_PITCH;
+    use crate::style::layer::StyleLayer;
+    use crate::{Style, WindowSize};
+    use cgmath::{InnerSpace, Vector2};
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_project_and_unproject() {
+        let mut view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+        view_state.update_zoom(Zoom::new(4.0));
+        view_state.camera.set_pitch(cgmath::Deg(45.0));
+
+        for screen_point in [
+            Vector2::new(400.0, 300.0),
+            Vector2::new(10.0, 590.0),
+            Vector2::new(700.0, 250.0),
+        ] {
+            let lat_lon = view_state.unproject(&screen_point).unwrap();
+            let projected = view_state.project(lat_lon).unwrap();
+            assert!((projected - screen_point).magnitude() < 1e-3);
+
+            // The copy of the world in view is used
+            let wrapped = LatLon::new(lat_lon.latitude, lat_lon.longitude + 360.0);
+            let projected = view_state.project(wrapped).unwrap();
+            assert!((projected - screen_point).magnitude() < 1e-3);
+        }
+
+        // Above the horizon there is no ground, while the bottom of the window still shows it
+        view_state.camera.set_pitch(MAX_PITCH);
+        assert!(view_state.unproject(&Vector2::new(400.0, 0.0)).is_none());
+        for screen_point in [Vector2::new(10.0, 590.0), Vector2::new(790.0, 590.0)] {
+            let lat_lon = view_state.unproject(&screen_point).unwrap();
+            let projected = view_state.project(lat_lon).unwrap();
+            assert!((projected - screen_point).magnitude() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_fit_bounds() {
+        let mut view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+
+        let target = view_state.fit_bounds([-180.0, -85.0, 180.0, 85.0], 0.0);
+        let zoom = target.zoom.unwrap().value();
+        // The bounds span the width of the world, which fits into the window
+        let world_size = TILE_SIZE * 2.0_f64.powf(zoom) / view_state.world_units_per_pixel();
+        assert!(world_size <= 800.0 + 1e-6);
+        // They are higher than wide in Web Mercator, so their height fills the window
+        let north = view_state.project(LatLon::new(85.0, 0.0)).unwrap();
+        let south = view_state.project(LatLon::new(-85.0, 0.0)).unwrap();
+        assert!((south.y - north.y - 600.0).abs() < 1e-6);
+
+        let center = view_state.center();
+        assert!(center.latitude.abs() < 1e-6);
+        assert!(center.longitude.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_camera_of_style() {
+        let style: Style = serde_json::from_value(json!({
+            "version": 8,
+            "center": [13.4, 52.5],
+            "zoom": 9.0,
+            "bearing": 90.0,
+            "sources": {},
+            "layers": []
+        }))
+        .unwrap();
+
+        let mut view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+        view_state.jump_to(CameraTarget::default().or(style.camera()));
+
+        let center = view_state.center();
+        assert!((center.latitude - 52.5).abs() < 1e-6);
+        assert!((center.longitude - 13.4).abs() < 1e-6);
+        assert!((view_state.zoom().value() - 9.0).abs() < 1e-6);
+
+        // The camera of the builder takes precedence
+        let target = CameraTarget::new(LatLon::new(0.0, 0.0), Zoom::new(2.0)).or(style.camera());
+        view_state.jump_to(target);
+        assert!(view_state.center().longitude.abs() < 1e-6);
+        assert!((view_state.zoom().value() - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_wrap_camera() {
+        let mut view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+        view_state.update_zoom(Zoom::new(2.0));
+        let world_size = TILE_SIZE * 4.0;
+
+        // Panning east across the antimeridian
+        view_state.camera.position.x = world_size * 1.25;
+        view_state.camera.position.y = world_size / 2.0;
+        assert!((view_state.center().longitude + 90.0).abs() < 1e-6);
+
+        view_state.wrap_camera();
+        assert!((view_state.camera.position.x - world_size * 0.25).abs() < 1e-6);
+        assert!((view_state.center().longitude + 90.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_zoomed_out_wide_window_repeats_the_world() {
+        let mut view_state = ViewState::new(&WindowSize::new(4000, 600).unwrap());
+
+        for zoom in 0..=2 {
+            view_state.update_zoom(Zoom::new(zoom as f64));
+            let world_size = TILE_SIZE * 2.0_f64.powi(zoom);
+            view_state.camera.position.x = world_size / 2.0;
+            view_state.camera.position.y = world_size / 2.0;
+
+            let view_region = view_state.view_region().unwrap();
+            let bounds = 1 << zoom;
+
+            // Copies of the world are drawn left and right of it
+            let columns = view_region
+                .iter_unwrapped()
+                .map(|coords| coords.x)
+                .collect::<Vec<_>>();
+            assert!(columns.iter().any(|x| *x < 0));
+            assert!(columns.iter().any(|x| *x >= bounds));
+
+            // The data of the copies is shared, so each tile is only requested once
+            let tiles = view_region.iter().collect::<Vec<_>>();
+            assert_eq!(
+                tiles.len(),
+                tiles.iter().collect::<HashSet<_>>().len(),
+                "zoom {}",
+                zoom
+            );
+            assert!(tiles.iter().all(|coords| (0..bounds).contains(&coords.x)));
+        }
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+        view_state.camera.update_reference();
+        assert!(!view_state.camera.did_change(0.05));
+
+        view_state.resize(WindowSize::new(400, 600).unwrap());
+        // Tiles which came into view are requested and drawn with the next frame
+        assert!(view_state.camera.did_change(0.05));
+        assert!(view_state.world_units_per_pixel() > 0.0);
+    }
+
+    #[test]
+    fn test_zoom_around() {
+        let mut view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+        view_state.update_zoom(Zoom::new(3.0));
+        // Close to the antimeridian
+        let world_size = TILE_SIZE * 8.0;
+        view_state.camera.position.x = world_size - 1.0;
+        view_state.camera.position.y = world_size / 2.0;
+
+        let east = Vector2::new(790.0, 300.0);
+        assert!(view_state.window_to_lat_lon(&east).unwrap().longitude > 180.0);
+
+        for pointer in [Vector2::new(200.0, 150.0), east] {
+            let before = view_state.window_to_lat_lon(&pointer).unwrap();
+            view_state.zoom_around(view_state.zoom() + Zoom::new(1.5), &pointer);
+            let after = view_state.window_to_lat_lon(&pointer).unwrap();
+
+            assert!((after.latitude - before.latitude).abs() < 1e-6);
+            assert!((after.longitude - before.longitude).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_fit_bounds_mercator() {
+        let view_state = ViewState::new(&WindowSize::new(800, 800).unwrap());
+
+        // Boxes with the same extent in degrees need a smaller zoom towards the poles
+        let equator = view_state.camera_for_bounds([0.0, -5.0, 10.0, 5.0], 10.0);
+        let north = view_state.camera_for_bounds([0.0, 65.0, 10.0, 75.0], 10.0);
+        assert!(north.zoom.unwrap().value() < equator.zoom.unwrap().value());
+
+        let padded = view_state.camera_for_bounds([0.0, -5.0, 10.0, 5.0], 100.0);
+        assert!(padded.zoom.unwrap().value() < equator.zoom.unwrap().value());
+
+        let antimeridian = view_state.camera_for_bounds([170.0, -5.0, -170.0, 5.0], 10.0);
+        let center = antimeridian.center.unwrap();
+        assert!((center.longitude.abs() - 180.0).abs() < 1e-6);
+        assert!(antimeridian.zoom.unwrap().value() < equator.zoom.unwrap().value());
+
+        let point = view_state.camera_for_bounds([10.0, 20.0, 10.0, 20.0], 0.0);
+        let center = point.center.unwrap();
+        assert!(point.zoom.is_none());
+        assert!((center.latitude - 20.0).abs() < 1e-6);
+        assert!((center.longitude - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_is_tile_rendered() {
+        let style = Style {
+            layers: vec![
+                StyleLayer {
+                    source_layer: Some("water".to_string()),
+                    ..StyleLayer::default()
+                },
+                StyleLayer {
+                    source_layer: Some("building".to_string()),
+                    minzoom: Some(10),
+                    maxzoom: Some(14),
+                    ..StyleLayer::default()
+                },
+            ],
+            ..Style::default()
+        };
+        let coords = WorldTileCoords { x: 0, y: 0, z: 1 };
+        let zoom = Zoom::new(1.0);
+        let mut tile_cache = TileCache::new();
+
+        assert!(!is_tile_rendered(&style, &tile_cache, None, &coords, zoom));
+
+        tile_cache.put_tessellated_layer(LayerTessellateMessage::UnavailableLayer {
+            coords,
+            source: None,
+            layer_name: "water".to_string(),
+        });
+        // The buildings are not drawn at this zoom level
+        assert!(is_tile_rendered(&style, &tile_cache, None, &coords, zoom));
+        assert!(!is_tile_rendered(
+            &style,
+            &tile_cache,
+            None,
+            &coords,
+            Zoom::new(10.0)
+        ));
+    }
+
+    #[test]
+    fn test_changed_raster_sources() {
+        let style = |sources: serde_json::Value| -> Style {
+            serde_json::from_value(json!({
+                "version": 8,
+                "name": "rasters",
+                "metadata": {},
+                "sources": sources,
+                "layers": []
+            }))
+            .unwrap()
+        };
+
+        let previous = style(json!({
+            "satellite": {"type": "raster", "tiles": ["https://a.example/{z}/{x}/{y}.png"]},
+            "hillshade": {"type": "raster", "tiles": ["https://b.example/{z}/{x}/{y}.png"]},
+            "terrain": {"type": "raster", "tiles": ["https://c.example/{z}/{x}/{y}.png"]}
+        }));
+        let next = style(json!({
+            "satellite": {"type": "raster", "tiles": ["https://a.example/{z}/{x}/{y}.png"]},
+            "hillshade": {"type": "raster", "tiles": ["https://d.example/{z}/{x}/{y}.png"]}
+        }));
+
+        let mut changed = changed_raster_sources(&previous, &next);
+        changed.sort();
+        assert_eq!(changed, vec!["hillshade", "terrain"]);
+        assert!(changed_raster_sources(&previous, &previous).is_empty());
+    }
 }
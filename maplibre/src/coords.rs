@@ -1,10 +1,12 @@
 //! Provides utilities related to coordinates.
 
+use crate::projection::{Projection, WebMercator};
 use crate::style::source::TileAddressingScheme;
 use crate::util::math::{div_floor, Aabb2};
 use crate::util::SignificantlyDifferent;
 use cgmath::num_traits::Pow;
 use cgmath::{AbsDiffEq, Matrix4, Point3, Vector3};
+use std::collections::HashSet;
 use std::fmt;
 
 pub const EXTENT_UINT: u32 = 4096;
@@ -58,6 +60,9 @@ impl fmt::Debug for Quadkey {
 
 /// `Zoom` is an exponential scale that defines the zoom of the camera on the map.
 /// We can derive the `ZoomLevel` from `Zoom` by using the `[crate::coords::ZOOM_BOUNDS]`.
+///
+/// A zoom is fractional, e.g. 5.5 is half way between the zoom levels 5 and 6. The tiles which are
+/// shown at a fractional zoom are those of its [`Zoom::level`], scaled by `2^fraction`.
 #[derive(Copy, Clone, Debug)]
 pub struct Zoom(f64);
 
@@ -65,6 +70,12 @@ impl Zoom {
     pub fn new(zoom: f64) -> Self {
         Zoom(zoom)
     }
+
+    /// Returns the zoom at which the world is `scale` times as large as at zoom 0, i.e. the
+    /// inverse of [`Zoom::scale`].
+    pub fn from_scale(scale: f64) -> Self {
+        Zoom(scale.log2())
+    }
 }
 
 impl Default for Zoom {
@@ -108,8 +119,33 @@ impl Zoom {
         2.0_f64.powf(zoom.0 - self.0)
     }
 
+    /// Returns the integer zoom level of the tiles which are shown at this zoom. The zoom is
+    /// rounded down, so tiles are only ever scaled up, e.g. the tiles of level 5 are shown from
+    /// zoom 5.0 up to but excluding 6.0. Negative zooms show the tiles of level 0 and zooms above
+    /// the last level of [`ZOOM_BOUNDS`] show the tiles of that level.
     pub fn level(&self) -> u8 {
-        self.0.floor() as u8
+        self.0.floor().clamp(0.0, (MAX_ZOOM - 1) as f64) as u8
+    }
+
+    /// Returns how far the zoom is beyond its [`Zoom::level`], within `0.0..1.0`.
+    pub fn fraction(&self) -> f64 {
+        self.0 - self.0.floor()
+    }
+
+    /// Returns the zoom clamped to `min..=max`, e.g. to the zoom range of a style or a camera
+    /// controller.
+    pub fn clamp(&self, min: Zoom, max: Zoom) -> Self {
+        Zoom(self.0.max(min.0).min(max.0))
+    }
+
+    /// Returns how many times larger the world is than at zoom 0, i.e. `2^zoom`.
+    pub fn scale(&self) -> f64 {
+        2.0_f64.powf(self.0)
+    }
+
+    /// Returns the zoom as a fractional zoom level.
+    pub fn value(&self) -> f64 {
+        self.0
     }
 }
 
@@ -254,6 +290,17 @@ impl WorldTileCoords {
         translate * normalize_and_scale
     }
 
+    /// Returns the coords of this tile within the world, i.e. with `x` in `0..2^z`. The world
+    /// repeats horizontally, so tiles beyond the antimeridian show the tiles on the other side.
+    pub fn wrap(&self) -> WorldTileCoords {
+        let bounds = ZOOM_BOUNDS[self.z as usize] as i64;
+        WorldTileCoords {
+            x: (self.x as i64).rem_euclid(bounds) as i32,
+            y: self.y,
+            z: self.z,
+        }
+    }
+
     pub fn into_aligned(self) -> AlignedWorldTileCoords {
         AlignedWorldTileCoords(WorldTileCoords {
             x: div_floor(self.x, 2) * 2,
@@ -408,13 +455,76 @@ impl WorldCoords {
         let y = self.y * tile_scale;
 
         WorldTileCoords {
-            x: x as i32,
-            y: y as i32,
+            x: x.floor() as i32,
+            y: y.floor() as i32,
             z,
         }
     }
 }
 
+/// Maximum latitude which can be projected with Web Mercator.
+pub const MAX_LATITUDE: f64 = 85.051129;
+
+/// A geographic position in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LatLon {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl LatLon {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Projects the position with Web Mercator onto the world at `zoom`. The world is
+    /// `TILE_SIZE * 2^zoom` wide and its origin is in the upper-left corner.
+    pub fn into_world(self, zoom: Zoom) -> WorldCoords {
+        self.into_world_with(&WebMercator, zoom)
+    }
+
+    /// Projects the position with `projection` onto the world at `zoom`, see
+    /// [`LatLon::into_world`].
+    pub fn into_world_with(self, projection: &dyn Projection, zoom: Zoom) -> WorldCoords {
+        let world_size = TILE_SIZE * 2.0_f64.powf(zoom.0);
+        let [x, y] = projection.project(self);
+
+        WorldCoords {
+            x: x * world_size,
+            y: y * world_size,
+        }
+    }
+}
+
+impl WorldCoords {
+    /// Inverse of [`LatLon::into_world`].
+    pub fn into_lat_lon(self, zoom: Zoom) -> LatLon {
+        self.into_lat_lon_with(&WebMercator, zoom)
+    }
+
+    /// Inverse of [`LatLon::into_world_with`].
+    pub fn into_lat_lon_with(self, projection: &dyn Projection, zoom: Zoom) -> LatLon {
+        let world_size = TILE_SIZE * 2.0_f64.powf(zoom.0);
+        let x = self.x / world_size;
+        let y = self.y / world_size;
+
+        projection.unproject([x, y])
+    }
+}
+
+impl LatLon {
+    /// Returns the same position with the longitude wrapped into `-180..180`.
+    pub fn wrap(self) -> Self {
+        Self {
+            latitude: self.latitude,
+            longitude: (self.longitude + 180.0).rem_euclid(360.0) - 180.0,
+        }
+    }
+}
+
 impl From<(f32, f32)> for WorldCoords {
     fn from(tuple: (f32, f32)) -> Self {
         WorldCoords {
@@ -443,7 +553,7 @@ impl From<Point3<f64>> for WorldCoords {
 }
 
 /// Defines a bounding box on a tiled map with a [`ZoomLevel`] and a padding.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ViewRegion {
     min_tile: WorldTileCoords,
     max_tile: WorldTileCoords,
@@ -470,17 +580,55 @@ impl ViewRegion {
         self.z
     }
 
+    /// Returns the region extended by `padding` tiles on each side.
+    pub fn padded(&self, padding: i32) -> Self {
+        Self {
+            padding: self.padding + padding,
+            ..self.clone()
+        }
+    }
+
+    /// Whether the tile at `world_coords` is in view. Tiles at lower zoom levels are in view if
+    /// they cover a tile in view, like the ancestors of overzoomed tiles. A tile is also in view
+    /// if one of its copies left or right of the world is.
     pub fn is_in_view(&self, &world_coords: &WorldTileCoords) -> bool {
-        world_coords.x <= self.max_tile.x + self.padding
-            && world_coords.y <= self.max_tile.y + self.padding
-            && world_coords.x >= self.min_tile.x - self.padding
-            && world_coords.y >= self.min_tile.y - self.padding
-            && world_coords.z == self.z
+        if world_coords.z > self.z {
+            return false;
+        }
+
+        let shift = self.z - world_coords.z;
+        let min_x = (self.min_tile.x - self.padding) >> shift;
+        let max_x = (self.max_tile.x + self.padding) >> shift;
+
+        // The copy of the tile closest to the right of the left edge of the region
+        let bounds = ZOOM_BOUNDS[world_coords.z as usize] as i64;
+        let x = min_x as i64 + (world_coords.x as i64 - min_x as i64).rem_euclid(bounds);
+
+        x <= max_x as i64
+            && world_coords.y <= (self.max_tile.y + self.padding) >> shift
+            && world_coords.y >= (self.min_tile.y - self.padding) >> shift
     }
 
+    /// Returns the tiles in view. Tiles which are in view multiple times, because the world
+    /// repeats horizontally, are returned once with their coords within the world, see
+    /// [`WorldTileCoords::wrap`].
     pub fn iter(&self) -> impl Iterator<Item = WorldTileCoords> + '_ {
+        let mut seen = HashSet::new();
+        self.iter_unwrapped()
+            .map(|coords| coords.wrap())
+            .filter(move |coords| seen.insert(*coords))
+    }
+
+    /// Returns the tiles in view at the position where they are drawn. Left and right of the
+    /// world the tiles of the copies of the world are returned, so their `x` can exceed the
+    /// bounds of the zoom level. There are no tiles above and below the world.
+    pub fn iter_unwrapped(&self) -> impl Iterator<Item = WorldTileCoords> + '_ {
+        let max_y = ZOOM_BOUNDS[self.z as usize] as i32 - 1;
+        let min_y = (self.min_tile.y - self.padding).max(0);
+        let max_y = (self.max_tile.y + self.padding).min(max_y);
+
         (self.min_tile.x - self.padding..self.max_tile.x + 1 + self.padding).flat_map(move |x| {
-            (self.min_tile.y - self.padding..self.max_tile.y + 1 + self.padding).map(move |y| {
+            (min_y..max_y + 1).map(move |y| {
                 let tile_coord: WorldTileCoords = (x, y, self.z as u8).into();
                 tile_coord
             })
@@ -524,7 +672,8 @@ mod tests {
     use crate::style::source::TileAddressingScheme;
 
     use crate::coords::{
-        Quadkey, TileCoords, ViewRegion, WorldCoords, WorldTileCoords, Zoom, EXTENT,
+        LatLon, Quadkey, TileCoords, ViewRegion, WorldCoords, WorldTileCoords, Zoom, EXTENT,
+        TILE_SIZE,
     };
     use crate::util::math::Aabb2;
 
@@ -595,4 +744,117 @@ mod tests {
             println!("{}", tile_coords);
         }
     }
+
+    #[test]
+    fn test_ancestors_are_in_view() {
+        let view_region = ViewRegion::new(
+            Aabb2::new(Point2::new(600.0, 600.0), Point2::new(1100.0, 1100.0)),
+            0,
+            Zoom::new(2.0),
+            2,
+        );
+
+        for coords in view_region.iter() {
+            assert!(view_region.is_in_view(&coords));
+            let parent = coords.get_parent().unwrap();
+            assert!(view_region.is_in_view(&parent));
+            assert!(view_region.is_in_view(&parent.get_parent().unwrap()));
+        }
+
+        // Children are not in view, even if their parent is
+        assert!(!view_region.is_in_view(&WorldTileCoords { x: 2, y: 2, z: 3 }));
+        assert!(!view_region.is_in_view(&WorldTileCoords { x: 3, y: 3, z: 2 }));
+    }
+
+    #[test]
+    fn test_view_region_across_antimeridian() {
+        // Centered at 179° longitude, the region reaches beyond the antimeridian
+        let zoom = Zoom::new(3.0);
+        let center = LatLon::new(0.0, 179.0).into_world(zoom);
+        let view_region = ViewRegion::new(
+            Aabb2::new(
+                Point2::new(center.x - 600.0, center.y - 300.0),
+                Point2::new(center.x + 600.0, center.y + 300.0),
+            ),
+            0,
+            zoom,
+            3,
+        );
+
+        let tiles = view_region.iter().collect::<Vec<_>>();
+        let columns = |tiles: &[WorldTileCoords]| {
+            let mut columns = tiles.iter().map(|coords| coords.x).collect::<Vec<_>>();
+            columns.dedup();
+            columns
+        };
+        // Both the tiles east of 135° and west of -135° are in view
+        assert_eq!(columns(&tiles), vec![6, 7, 0, 1]);
+        assert!(tiles.iter().all(|coords| coords.build_quad_key().is_some()));
+        assert!(view_region.is_in_view(&WorldTileCoords { x: 0, y: 3, z: 3 }));
+        assert!(view_region.is_in_view(&WorldTileCoords { x: 0, y: 1, z: 2 }));
+        assert!(!view_region.is_in_view(&WorldTileCoords { x: 4, y: 3, z: 3 }));
+
+        // The tiles beyond the antimeridian are drawn right of the world
+        let unwrapped = view_region.iter_unwrapped().collect::<Vec<_>>();
+        assert_eq!(columns(&unwrapped), vec![6, 7, 8, 9]);
+        assert_eq!(unwrapped[unwrapped.len() - 1].wrap().x, 1);
+    }
+
+    #[test]
+    fn test_zoomed_out_view_region_repeats_the_world() {
+        let view_region = ViewRegion::new(
+            Aabb2::new(Point2::new(-300.0, 100.0), Point2::new(1300.0, 400.0)),
+            0,
+            Zoom::default(),
+            0,
+        );
+
+        assert_eq!(view_region.iter_unwrapped().count(), 4);
+        assert_eq!(
+            view_region.iter().collect::<Vec<_>>(),
+            vec![WorldTileCoords { x: 0, y: 0, z: 0 }]
+        );
+    }
+
+    #[test]
+    fn test_wrap_longitude() {
+        assert_eq!(LatLon::new(10.0, 181.0).wrap(), LatLon::new(10.0, -179.0));
+        assert_eq!(LatLon::new(10.0, -540.0).wrap(), LatLon::new(10.0, -180.0));
+        assert_eq!(LatLon::new(10.0, 45.0).wrap(), LatLon::new(10.0, 45.0));
+    }
+
+    #[test]
+    fn test_zoom_level() {
+        assert_eq!(Zoom::new(5.0).level(), 5);
+        assert_eq!(Zoom::new(5.99).level(), 5);
+        assert!((Zoom::new(5.25).fraction() - 0.25).abs() < 1e-9);
+        assert_eq!(Zoom::new(-1.5).level(), 0);
+        assert_eq!(Zoom::new(40.0).level(), 31);
+
+        let clamped = Zoom::new(23.0).clamp(Zoom::new(0.0), Zoom::new(22.0));
+        assert_eq!(clamped.value(), 22.0);
+        assert_eq!(
+            Zoom::new(-2.0)
+                .clamp(Zoom::new(0.0), Zoom::new(22.0))
+                .value(),
+            0.0
+        );
+
+        assert!((Zoom::new(3.0).scale() - 8.0).abs() < 1e-9);
+        assert!((Zoom::from_scale(Zoom::new(7.3).scale()).value() - 7.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lat_lon_into_world() {
+        let world = LatLon::new(0.0, 0.0).into_world(Zoom::new(1.0));
+        assert!((world.x - TILE_SIZE).abs() < 1e-9);
+        assert!((world.y - TILE_SIZE).abs() < 1e-9);
+
+        let lat_lon = LatLon::new(48.137154, 11.576124);
+        let back = lat_lon
+            .into_world(Zoom::new(5.3))
+            .into_lat_lon(Zoom::new(5.3));
+        assert!((back.latitude - lat_lon.latitude).abs() < 1e-9);
+        assert!((back.longitude - lat_lon.longitude).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,206 @@
+//! Layers which are drawn by the application with its own WebGPU pipelines, e.g. a heatmap or a 3D
+//! model. Custom layers are interleaved with the layers of the style: a custom layer is drawn on
+//! top of the style layers below its position and below the style layers above it.
+//!
+//! The position is given by the id of the style layer which is drawn on top of the custom layer,
+//! see [`CustomLayers::add`]. Custom layers are drawn in the render pass of the flat layers. Raster
+//! and hillshade layers are drawn before all vector layers, therefore custom layers should write
+//! the depth which is passed to [`CustomLayer::render`] such that the depth test keeps them below
+//! the style layers above them.
+
+use crate::render::camera::ViewProjection;
+use crate::render::shaders::layer_depth;
+use crate::style::Style;
+
+/// The GPU and the formats of the render pass in which custom layers are drawn.
+pub struct CustomLayerContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    /// Format of the color attachment.
+    pub texture_format: wgpu::TextureFormat,
+    /// Format of the depth and stencil attachment.
+    pub depth_stencil_format: wgpu::TextureFormat,
+    /// Number of samples of the attachments, which is greater than one with multisampling.
+    pub sample_count: u32,
+}
+
+/// Draws custom WebGPU content at a position within the layers of the style.
+pub trait CustomLayer {
+    /// Creates the resources of the layer on the GPU, e.g. its pipelines. Called before the layer
+    /// is prepared the first time, and again once the renderer was recreated, e.g. because the
+    /// surface was destroyed on Android.
+    fn initialize(&mut self, context: &CustomLayerContext);
+
+    /// Updates the resources of the layer before each frame, e.g. uniforms of the camera.
+    fn prepare(&mut self, context: &CustomLayerContext, view_proj: &ViewProjection);
+
+    /// Issues the draws of the layer. Pipelines have to use the formats of the
+    /// [`CustomLayerContext`]. The depth buffer is cleared to 0.0 and flat layers are drawn with
+    /// the depth compare function [`wgpu::CompareFunction::Greater`]. `depth` lies between the
+    /// depths of the style layers below and above this layer. The stencil buffer holds the masks
+    /// of the tiles, which custom pipelines should leave untouched.
+    fn render<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        view_proj: &ViewProjection,
+        depth: f32,
+    );
+}
+
+struct CustomLayerEntry {
+    id: String,
+    /// Id of the style layer which is drawn on top of this layer. `None` for the top.
+    before: Option<String>,
+    layer: Box<dyn CustomLayer>,
+    /// Whether [`CustomLayer::initialize`] was called for the current renderer.
+    initialized: bool,
+    /// Index of the style layer on top of this layer, see [`CustomLayers::prepare`].
+    layer_index: u32,
+}
+
+/// The custom layers of a map. Custom layers at the same position are drawn in the order in which
+/// they have been added.
+#[derive(Default)]
+pub struct CustomLayers {
+    layers: Vec<CustomLayerEntry>,
+    /// The view projection of the frame, set by [`CustomLayers::prepare`].
+    view_proj: Option<ViewProjection>,
+}
+
+impl CustomLayers {
+    /// Adds the layer `id` below the style layer `before`, or on top of all style layers if
+    /// `before` is `None` or no such style layer exists. Returns false if there is a custom layer
+    /// with the same id already.
+    pub fn add(&mut self, id: &str, before: Option<&str>, layer: Box<dyn CustomLayer>) -> bool {
+        if self.contains(id) {
+            return false;
+        }
+        self.layers.push(CustomLayerEntry {
+            id: id.to_string(),
+            before: before.map(|before| before.to_string()),
+            layer,
+            initialized: false,
+            layer_index: u32::MAX,
+        });
+        true
+    }
+
+    /// Removes the layer `id` and returns it, e.g. to release its resources.
+    pub fn remove(&mut self, id: &str) -> Option<Box<dyn CustomLayer>> {
+        let position = self.layers.iter().position(|entry| entry.id == id)?;
+        Some(self.layers.remove(position).layer)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.layers.iter().any(|entry| entry.id == id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Requires all layers to be initialized again, because the renderer was recreated.
+    pub(crate) fn reset(&mut self) {
+        for entry in &mut self.layers {
+            entry.initialized = false;
+        }
+        self.view_proj = None;
+    }
+
+    /// Resolves the positions of the layers within the layers of `style` and prepares them for
+    /// the next frame.
+    pub(crate) fn prepare(
+        &mut self,
+        style: &Style,
+        context: &CustomLayerContext,
+        view_proj: ViewProjection,
+    ) {
+        for entry in &mut self.layers {
+            entry.layer_index = entry
+                .before
+                .as_ref()
+                .and_then(|before| style.layers.iter().find(|layer| &layer.id == before))
+                .map(|layer| layer.index)
+                .unwrap_or(style.layers.len() as u32);
+
+            if !entry.initialized {
+                entry.layer.initialize(context);
+                entry.initialized = true;
+            }
+            entry.layer.prepare(context, &view_proj);
+        }
+        // Stable, such that layers at the same position keep the order in which they were added
+        self.layers.sort_by_key(|entry| entry.layer_index);
+        self.view_proj = Some(view_proj);
+    }
+
+    /// Returns the prepared layers from bottom to top, together with the index of the style layer
+    /// on top of each.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u32, &dyn CustomLayer)> {
+        self.layers
+            .iter()
+            .filter(|entry| entry.initialized)
+            .map(|entry| (entry.layer_index, entry.layer.as_ref()))
+    }
+
+    /// Draws `layer` which lies below the style layer at `layer_index`.
+    pub(crate) fn render<'a>(
+        &self,
+        layer: &'a dyn CustomLayer,
+        layer_index: u32,
+        pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        if let Some(view_proj) = &self.view_proj {
+            layer.render(pass, view_proj, custom_layer_depth(layer_index));
+        }
+    }
+}
+
+/// Returns the depth between the style layer at `layer_index` and the one below it.
+fn custom_layer_depth(layer_index: u32) -> f32 {
+    let below = layer_index.checked_sub(1).map_or(0.0, layer_depth);
+    (below + layer_depth(layer_index)) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{custom_layer_depth, CustomLayer, CustomLayerContext, CustomLayers};
+    use crate::render::camera::ViewProjection;
+    use crate::render::shaders::layer_depth;
+
+    struct EmptyLayer;
+
+    impl CustomLayer for EmptyLayer {
+        fn initialize(&mut self, _context: &CustomLayerContext) {}
+
+        fn prepare(&mut self, _context: &CustomLayerContext, _view_proj: &ViewProjection) {}
+
+        fn render<'a>(
+            &'a self,
+            _pass: &mut wgpu::RenderPass<'a>,
+            _view_proj: &ViewProjection,
+            _depth: f32,
+        ) {
+        }
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let mut layers = CustomLayers::default();
+        assert!(layers.add("heatmap", Some("road"), Box::new(EmptyLayer)));
+        assert!(!layers.add("heatmap", None, Box::new(EmptyLayer)));
+        assert!(layers.contains("heatmap"));
+
+        assert!(layers.remove("heatmap").is_some());
+        assert!(layers.remove("heatmap").is_none());
+        assert!(layers.is_empty());
+    }
+
+    #[test]
+    fn test_custom_layer_depth() {
+        assert!(custom_layer_depth(0) > 0.0);
+        assert!(custom_layer_depth(0) < layer_depth(0));
+        assert!(custom_layer_depth(3) > layer_depth(2));
+        assert!(custom_layer_depth(3) < layer_depth(3));
+    }
+}
@@ -8,12 +8,40 @@ use std::sync::mpsc::SendError;
 #[derive(Debug)]
 pub enum RenderError {
     Surface(wgpu::SurfaceError),
+    /// None of the configured backends provides an adapter, e.g. because no GPU driver is
+    /// installed or the browser supports neither WebGPU nor WebGL2.
+    NoAdapter,
+    /// No device could be requested from the adapter.
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The rendered frame could not be read back from the GPU.
+    Readback(String),
+    /// The rendered frame could not be encoded as image.
+    Encode(String),
+    /// The surface or the device was lost. The renderer was dropped and needs to be initialized
+    /// again with [`crate::map_schedule::MapSchedule::recreate_surface`].
+    DeviceLost,
+    /// The renderer was initialized again the given number of times in a row, but the surface was
+    /// lost again every time.
+    RecoveryFailed(u32),
 }
 
 impl fmt::Display for RenderError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             RenderError::Surface(e) => write!(f, "{}", e),
+            RenderError::NoAdapter => write!(
+                f,
+                "unable to find a GPU, make sure that the required drivers are installed"
+            ),
+            RenderError::RequestDevice(e) => write!(f, "{}", e),
+            RenderError::Readback(e) => write!(f, "{}", e),
+            RenderError::Encode(e) => write!(f, "{}", e),
+            RenderError::DeviceLost => write!(f, "the surface or the GPU device was lost"),
+            RenderError::RecoveryFailed(attempts) => write!(
+                f,
+                "the surface was lost again after initializing the renderer {} times",
+                attempts
+            ),
         }
     }
 }
@@ -25,6 +53,10 @@ impl RenderError {
                 wgpu::SurfaceError::OutOfMemory => true,
                 _ => false,
             },
+            RenderError::NoAdapter
+            | RenderError::RequestDevice(_)
+            | RenderError::RecoveryFailed(_) => true,
+            RenderError::Readback(_) | RenderError::Encode(_) | RenderError::DeviceLost => false,
         }
     }
 }
@@ -36,6 +68,25 @@ pub enum Error {
     Network(String),
     Tesselation(TessellationError),
     Render(RenderError),
+    Font(String),
+    GeoJson(String),
+    PmTiles(String),
+    Mbtiles(String),
+    Cache(String),
+    /// A TileJSON document could not be parsed.
+    TileJson(String),
+    /// A style document could not be parsed.
+    Style(String),
+    /// A sprite sheet or its index could not be parsed.
+    Sprite(String),
+    /// Compressed data is truncated or corrupt, or uses an unsupported compression.
+    Decompression(String),
+    /// A vector tile could not be parsed.
+    InvalidTile(String),
+    /// A request was abandoned, e.g. because its tile is no longer in view.
+    Cancelled,
+    /// A map builder is missing a required option, e.g. the HTTP client.
+    Configuration(String),
 }
 
 impl From<wgpu::SurfaceError> for Error {
@@ -44,6 +95,12 @@ impl From<wgpu::SurfaceError> for Error {
     }
 }
 
+impl From<wgpu::RequestDeviceError> for Error {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        Error::Render(RenderError::RequestDevice(e))
+    }
+}
+
 impl From<TessellationError> for Error {
     fn from(e: TessellationError) -> Self {
         Error::Tesselation(e)
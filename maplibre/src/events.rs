@@ -0,0 +1,59 @@
+//! Events which inform embedding applications about the lifecycle of the map, e.g. to show a
+//! loading indicator or to take a screenshot once the map is idle. Listeners are registered with
+//! [`crate::map_schedule::MapSchedule::on_event`].
+
+use crate::coords::WorldTileCoords;
+
+/// An event which is emitted by the stages of a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapEvent {
+    /// The sources of the style are resolved, i.e. their TileJSON documents are fetched or failed
+    /// to load. Emitted once for the initial style and once after every style swap.
+    StyleLoaded,
+    /// Data of a source could not be loaded or decoded.
+    SourceError {
+        /// Id of the source in the style. `None` for the default tile source, which is used if the
+        /// style does not define a vector source.
+        source: Option<String>,
+        /// The tile which failed to load. `None` if the whole source is unusable.
+        coords: Option<WorldTileCoords>,
+        message: String,
+    },
+    /// A tile finished loading, i.e. all requested layers of a vector or GeoJSON tile are
+    /// tessellated or a raster tile is decoded.
+    TileLoaded { coords: WorldTileCoords },
+    /// The camera started moving, either because of user input or an animation.
+    MoveStart,
+    /// The camera stopped moving.
+    MoveEnd,
+    /// The camera does not move and all tiles in view are rendered. Emitted once until the map
+    /// changes again.
+    Idle,
+}
+
+/// Collects the events of a frame until they are dispatched to the listeners after the frame.
+pub struct MapEvents {
+    pending: Vec<MapEvent>,
+    /// Whether the active style has not been reported as loaded yet.
+    pub(crate) style_pending: bool,
+}
+
+impl Default for MapEvents {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            style_pending: true,
+        }
+    }
+}
+
+impl MapEvents {
+    pub fn emit(&mut self, event: MapEvent) {
+        self.pending.push(event);
+    }
+
+    /// Removes and returns the events in the order in which they were emitted.
+    pub fn drain(&mut self) -> impl Iterator<Item = MapEvent> + '_ {
+        self.pending.drain(..)
+    }
+}
@@ -0,0 +1,703 @@
+//! C API for embedding the map into apps which are not written in Rust, e.g. through Swift or
+//! Kotlin bindings. Enabled with the `ffi` feature.
+//!
+//! A map is created with [`maplibre_map_create`] and referenced through an opaque
+//! [`MapLibreMap`] pointer, which is released with [`maplibre_map_destroy`]. The app renders the
+//! map by calling [`maplibre_map_render`] for every frame, e.g. from its display link or
+//! choreographer, and forwards pointer input and changes of the native surface. All functions
+//! have to be called from the same thread. Tiles are loaded on a Tokio runtime which is owned by
+//! the map.
+//!
+//! Every function which can fail returns a [`MapLibreStatus`]. Panics are caught at the boundary
+//! of the API and reported as [`MapLibreStatus::Panic`], afterwards the map must only be
+//! destroyed. Catching panics requires unwinding: the `release` profile of the workspace sets
+//! `panic = "abort"`, with which any panic aborts the app instead. The libraries of the
+//! `maplibre-ffi` crate are therefore built with the `release-ffi` profile, which unwinds, see
+//! `just ffi`. The C header is generated with `just ffi-header`, see `cbindgen.toml`.
+
+use crate::error::{Error, RenderError};
+use crate::map_schedule::{CameraController, MapSchedule};
+use crate::platform::http_client::ReqwestHttpClient;
+use crate::platform::multithreaded_runtime;
+use crate::platform::schedule_method::TokioScheduleMethod;
+use crate::style::Style;
+use crate::window::{MapWindow, MapWindowConfig, WindowSize};
+use crate::{Map, MapBuilder};
+use cgmath::Vector2;
+use instant::Instant;
+use raw_window_handle::{
+    AndroidNdkHandle, AppKitHandle, HasRawWindowHandle, RawWindowHandle, UiKitHandle,
+    WaylandHandle, Win32Handle, XlibHandle,
+};
+use std::ffi::{c_void, CStr};
+use std::os::raw::{c_char, c_ulong};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// Longest time step with which the camera is moved, like in the winit event loop.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// Result of the functions of the C API.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapLibreStatus {
+    Ok = 0,
+    /// A pointer argument is null.
+    NullPointer = 1,
+    /// An argument is invalid, e.g. a string which is not UTF-8 or a surface without pixels.
+    InvalidArgument = 2,
+    /// A style could not be fetched or parsed.
+    Style = 3,
+    /// A request failed.
+    Network = 4,
+    /// The renderer could not be initialized or failed to render a frame.
+    Render = 5,
+    /// The surface was lost and could not be recreated. The map is drawn again once a surface is
+    /// passed to [`maplibre_map_set_surface`].
+    SurfaceLost = 6,
+    /// A panic was caught. The map must not be used anymore, except for destroying it. Only
+    /// returned if the library is built with `panic = "unwind"`, otherwise panics abort.
+    Panic = 7,
+    /// Any other error.
+    Other = 8,
+}
+
+impl From<&Error> for MapLibreStatus {
+    fn from(e: &Error) -> Self {
+        match e {
+            Error::Style(_) => MapLibreStatus::Style,
+            Error::Network(_) => MapLibreStatus::Network,
+            Error::Render(RenderError::DeviceLost) => MapLibreStatus::SurfaceLost,
+            Error::Render(_) => MapLibreStatus::Render,
+            _ => MapLibreStatus::Other,
+        }
+    }
+}
+
+/// Kind of the native surface which the map renders to, see [`MapLibreSurface`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapLibreSurfaceKind {
+    /// `handle` is an `NSView` on macOS.
+    AppKit = 0,
+    /// `handle` is a `UIView` whose backing layer is a `CAMetalLayer`.
+    UiKit = 1,
+    /// `handle` is an `ANativeWindow`.
+    AndroidNdk = 2,
+    /// `handle` is an `HWND` and `display` the `HINSTANCE`.
+    Win32 = 3,
+    /// `handle` is an X11 `Window` and `display` the `Display`.
+    Xlib = 4,
+    /// `handle` is a `wl_surface` and `display` the `wl_display`.
+    Wayland = 5,
+}
+
+/// A native surface of the app and its size in physical pixels. The surface is not retained, the
+/// app keeps it alive until it is replaced or the map is destroyed.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MapLibreSurface {
+    pub kind: MapLibreSurfaceKind,
+    pub handle: *mut c_void,
+    pub display: *mut c_void,
+    pub width: u32,
+    pub height: u32,
+    /// Number of physical pixels per logical pixel.
+    pub device_pixel_ratio: f64,
+}
+
+impl MapLibreSurface {
+    fn size(&self) -> Option<WindowSize> {
+        WindowSize::new(self.width, self.height)
+            .map(|size| size.with_device_pixel_ratio(self.device_pixel_ratio))
+    }
+}
+
+unsafe impl HasRawWindowHandle for MapLibreSurface {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        match self.kind {
+            MapLibreSurfaceKind::AppKit => {
+                let mut handle = AppKitHandle::empty();
+                handle.ns_view = self.handle;
+                RawWindowHandle::AppKit(handle)
+            }
+            MapLibreSurfaceKind::UiKit => {
+                let mut handle = UiKitHandle::empty();
+                handle.ui_view = self.handle;
+                RawWindowHandle::UiKit(handle)
+            }
+            MapLibreSurfaceKind::AndroidNdk => {
+                let mut handle = AndroidNdkHandle::empty();
+                handle.a_native_window = self.handle;
+                RawWindowHandle::AndroidNdk(handle)
+            }
+            MapLibreSurfaceKind::Win32 => {
+                let mut handle = Win32Handle::empty();
+                handle.hwnd = self.handle;
+                handle.hinstance = self.display;
+                RawWindowHandle::Win32(handle)
+            }
+            MapLibreSurfaceKind::Xlib => {
+                let mut handle = XlibHandle::empty();
+                handle.window = self.handle as c_ulong;
+                handle.display = self.display;
+                RawWindowHandle::Xlib(handle)
+            }
+            MapLibreSurfaceKind::Wayland => {
+                let mut handle = WaylandHandle::empty();
+                handle.surface = self.handle;
+                handle.display = self.display;
+                RawWindowHandle::Wayland(handle)
+            }
+        }
+    }
+}
+
+pub struct FfiMapWindowConfig {
+    surface: MapLibreSurface,
+}
+
+impl MapWindowConfig for FfiMapWindowConfig {
+    type MapWindow = FfiMapWindow;
+}
+
+/// The surface which the map is created with. Later surfaces are passed to
+/// [`MapSchedule::recreate_surface`] directly.
+pub struct FfiMapWindow {
+    surface: MapLibreSurface,
+}
+
+impl MapWindow for FfiMapWindow {
+    type EventLoop = ();
+    type Window = MapLibreSurface;
+    type MapWindowConfig = FfiMapWindowConfig;
+
+    fn create(map_window_config: &Self::MapWindowConfig) -> Self {
+        Self {
+            surface: map_window_config.surface,
+        }
+    }
+
+    fn size(&self) -> WindowSize {
+        self.surface
+            .size()
+            .expect("the size of the surface is checked on creation")
+    }
+
+    fn inner(&self) -> &Self::Window {
+        &self.surface
+    }
+}
+
+/// Opaque handle of a map, see [`maplibre_map_create`].
+pub struct MapLibreMap {
+    map_state: MapSchedule<FfiMapWindowConfig, TokioScheduleMethod, ReqwestHttpClient>,
+    /// The surface which is rendered to, or `None` after [`maplibre_map_destroy_surface`].
+    surface: Option<MapLibreSurface>,
+    http_client: ReqwestHttpClient,
+    camera_controller: CameraController,
+    /// Last position of the pointer, from which drags are measured.
+    pointer_position: Option<Vector2<f64>>,
+    is_dragging: bool,
+    last_render_time: Instant,
+    /// Dropped last, which shuts down the tasks of the map once it is gone.
+    runtime: Runtime,
+}
+
+impl MapLibreMap {
+    /// Fetches the style at `url` and its sprite sheet.
+    fn fetch_style(&self, url: &str) -> Result<Style, Error> {
+        self.runtime.block_on(async {
+            let style = load_style(url, &self.http_client).await?;
+            Ok(self.with_sprite(style).await)
+        })
+    }
+
+    /// Loads the sprite sheet of `style` for the current device pixel ratio. Styles are used
+    /// without icons if their sprite sheet can not be loaded.
+    async fn with_sprite(&self, mut style: Style) -> Style {
+        let device_pixel_ratio = self
+            .surface
+            .and_then(|surface| surface.size())
+            .map_or(1.0, |size| size.device_pixel_ratio());
+        if let Err(e) = style
+            .load_sprite(&self.http_client, device_pixel_ratio)
+            .await
+        {
+            log::error!("Failed to load the sprite of the style: {:?}", e);
+        }
+        style
+    }
+
+    /// Initializes the renderer for `surface`, or recreates its surface.
+    fn recreate_surface(&mut self, surface: MapLibreSurface) -> Result<(), MapLibreStatus> {
+        let size = surface.size().ok_or(MapLibreStatus::InvalidArgument)?;
+        self.surface = Some(surface);
+        let map_state = &mut self.map_state;
+        self.runtime
+            .block_on(map_state.recreate_surface(&surface, size))
+            .map_err(|e| MapLibreStatus::from(&e))?;
+        self.map_state.resize(size);
+        Ok(())
+    }
+
+    fn render(&mut self) -> Result<(), MapLibreStatus> {
+        let now = Instant::now();
+        let dt = (now - self.last_render_time).min(MAX_FRAME_TIME);
+        self.last_render_time = now;
+
+        if self.surface.is_none() {
+            return Ok(());
+        }
+
+        if let Some(style) = self.map_state.style() {
+            self.camera_controller.set_zoom_range_of(style);
+        }
+        self.camera_controller
+            .update_state(self.map_state.view_state_mut(), dt);
+
+        let result = {
+            let _guard = self.runtime.enter();
+            self.map_state.update_and_redraw()
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(Error::Render(RenderError::DeviceLost)) => {
+                log::warn!("The surface was lost, initializing the renderer again");
+                match self.surface {
+                    Some(surface) => self
+                        .recreate_surface(surface)
+                        .map_err(|_| MapLibreStatus::SurfaceLost),
+                    None => Err(MapLibreStatus::SurfaceLost),
+                }
+            }
+            Err(e) => Err(MapLibreStatus::from(&e)),
+        }
+    }
+}
+
+/// Fetches the style at `url`. Parts of the style which are not supported are logged.
+async fn load_style(url: &str, http_client: &ReqwestHttpClient) -> Result<Style, Error> {
+    let (style, issues) = Style::from_url(url, http_client).await?;
+    for issue in issues {
+        log::warn!("Style {}: {}", url, issue);
+    }
+    Ok(style)
+}
+
+/// Calls `f` with the map behind `map`, unless it is null. Panics are reported as
+/// [`MapLibreStatus::Panic`].
+unsafe fn with_map<F>(map: *mut MapLibreMap, f: F) -> MapLibreStatus
+where
+    F: FnOnce(&mut MapLibreMap) -> Result<(), MapLibreStatus>,
+{
+    let map = match map.as_mut() {
+        Some(map) => map,
+        None => return MapLibreStatus::NullPointer,
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| f(map))) {
+        Ok(Ok(())) => MapLibreStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => MapLibreStatus::Panic,
+    }
+}
+
+/// Borrows the nul-terminated UTF-8 string behind `string`.
+unsafe fn to_str<'a>(string: *const c_char) -> Result<&'a str, MapLibreStatus> {
+    if string.is_null() {
+        return Err(MapLibreStatus::NullPointer);
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| MapLibreStatus::InvalidArgument)
+}
+
+/// Creates a map which renders to `surface`. The style is fetched from `style_url`, or the default
+/// style is shown if it is null. On success, the map is written to `out_map`. If the style can not
+/// be fetched or parsed, [`MapLibreStatus::Network`] or [`MapLibreStatus::Style`] is returned and
+/// no map is created.
+///
+/// # Safety
+///
+/// `surface` and `out_map` have to be valid pointers, `style_url` has to be null or a
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_create(
+    surface: *const MapLibreSurface,
+    style_url: *const c_char,
+    out_map: *mut *mut MapLibreMap,
+) -> MapLibreStatus {
+    let (surface, out_map) = match (surface.as_ref(), out_map.as_mut()) {
+        (Some(surface), Some(out_map)) => (*surface, out_map),
+        _ => return MapLibreStatus::NullPointer,
+    };
+    if surface.size().is_none() {
+        return MapLibreStatus::InvalidArgument;
+    }
+    let style_url = if style_url.is_null() {
+        None
+    } else {
+        match to_str(style_url) {
+            Ok(style_url) => Some(style_url),
+            Err(status) => return status,
+        }
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let runtime = multithreaded_runtime();
+        let http_client = ReqwestHttpClient::new(None);
+        let mut builder = MapBuilder::new()
+            .with_map_window_config(FfiMapWindowConfig { surface })
+            .with_http_client(http_client.clone())
+            .with_schedule_method(TokioScheduleMethod::from_handle(runtime.handle().clone()));
+        // Unlike `MapBuilder::with_style_url`, which falls back to the default style, a style
+        // which can not be loaded is reported to the app
+        if let Some(style_url) = style_url {
+            let style = runtime
+                .block_on(load_style(style_url, &http_client))
+                .map_err(|e| MapLibreStatus::from(&e))?;
+            builder = builder.with_style(style);
+        }
+        let Map { map_state, .. }: Map<FfiMapWindow, _, _> = runtime.block_on(builder.build());
+
+        Ok(MapLibreMap {
+            map_state,
+            surface: Some(surface),
+            http_client,
+            camera_controller: CameraController::new(),
+            pointer_position: None,
+            is_dragging: false,
+            last_render_time: Instant::now(),
+            runtime,
+        })
+    }));
+
+    match result {
+        Ok(Ok(map)) => {
+            *out_map = Box::into_raw(Box::new(map));
+            MapLibreStatus::Ok
+        }
+        Ok(Err(status)) => status,
+        Err(_) => MapLibreStatus::Panic,
+    }
+}
+
+/// Destroys a map which was created by [`maplibre_map_create`]. Does nothing if `map` is null.
+///
+/// # Safety
+///
+/// `map` has to be null or a map which is not destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_destroy(map: *mut MapLibreMap) {
+    if !map.is_null() {
+        drop(Box::from_raw(map));
+    }
+}
+
+/// Fetches the style at `url` and shows it. Blocks until the style is fetched.
+///
+/// # Safety
+///
+/// `map` has to be a valid map and `url` a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_set_style_url(
+    map: *mut MapLibreMap,
+    url: *const c_char,
+) -> MapLibreStatus {
+    with_map(map, |map| {
+        let style = map
+            .fetch_style(to_str(url)?)
+            .map_err(|e| MapLibreStatus::from(&e))?;
+        map.map_state.set_style(style);
+        Ok(())
+    })
+}
+
+/// Parses the style document `json` and shows it. Blocks until its sprite sheet is fetched.
+///
+/// # Safety
+///
+/// `map` has to be a valid map and `json` a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_set_style_json(
+    map: *mut MapLibreMap,
+    json: *const c_char,
+) -> MapLibreStatus {
+    with_map(map, |map| {
+        let (style, issues) = Style::from_json(to_str(json)?.as_bytes(), None)
+            .map_err(|e| MapLibreStatus::from(&e))?;
+        for issue in issues {
+            log::warn!("Style: {}", issue);
+        }
+        let style = map.runtime.block_on(map.with_sprite(style));
+        map.map_state.set_style(style);
+        Ok(())
+    })
+}
+
+/// Changes the size of the surface in physical pixels, e.g. after the layout of the view changed.
+///
+/// # Safety
+///
+/// `map` has to be a valid map.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_resize(
+    map: *mut MapLibreMap,
+    width: u32,
+    height: u32,
+    device_pixel_ratio: f64,
+) -> MapLibreStatus {
+    with_map(map, |map| {
+        let size = WindowSize::new(width, height)
+            .ok_or(MapLibreStatus::InvalidArgument)?
+            .with_device_pixel_ratio(device_pixel_ratio);
+        if let Some(surface) = &mut map.surface {
+            surface.width = width;
+            surface.height = height;
+            surface.device_pixel_ratio = size.device_pixel_ratio();
+        }
+        map.map_state.resize(size);
+        Ok(())
+    })
+}
+
+/// Renders to a new native surface, e.g. when Android recreated the surface after the app was
+/// resumed. The tiles which are cached are uploaded again.
+///
+/// # Safety
+///
+/// `map` and `surface` have to be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_set_surface(
+    map: *mut MapLibreMap,
+    surface: *const MapLibreSurface,
+) -> MapLibreStatus {
+    with_map(map, |map| {
+        let surface = *surface.as_ref().ok_or(MapLibreStatus::NullPointer)?;
+        map.recreate_surface(surface)
+    })
+}
+
+/// Releases the native surface, e.g. when Android destroys it because the app moved to the
+/// background. Nothing is rendered until a surface is passed to [`maplibre_map_set_surface`].
+///
+/// # Safety
+///
+/// `map` has to be a valid map.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_destroy_surface(map: *mut MapLibreMap) -> MapLibreStatus {
+    with_map(map, |map| {
+        map.surface = None;
+        map.map_state.destroy_surface();
+        Ok(())
+    })
+}
+
+/// Updates the map and renders a frame if the map changed, see
+/// [`crate::redraw::RedrawMode`]. Meant to be called for every frame of the display.
+///
+/// # Safety
+///
+/// `map` has to be a valid map.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_render(map: *mut MapLibreMap) -> MapLibreStatus {
+    with_map(map, MapLibreMap::render)
+}
+
+/// Presses the pointer at `x`, `y` in physical pixels, which starts dragging the map.
+///
+/// # Safety
+///
+/// `map` has to be a valid map.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_pointer_down(
+    map: *mut MapLibreMap,
+    x: f64,
+    y: f64,
+) -> MapLibreStatus {
+    with_map(map, |map| {
+        map.pointer_position = Some(Vector2::new(x, y));
+        map.is_dragging = true;
+        map.camera_controller.drag_start();
+        Ok(())
+    })
+}
+
+/// Moves the pointer to `x`, `y` in physical pixels, which drags the map while it is pressed.
+///
+/// # Safety
+///
+/// `map` has to be a valid map.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_pointer_move(
+    map: *mut MapLibreMap,
+    x: f64,
+    y: f64,
+) -> MapLibreStatus {
+    with_map(map, |map| {
+        let position = Vector2::new(x, y);
+        if let (true, Some(pointer_position)) = (map.is_dragging, map.pointer_position) {
+            map.camera_controller.drag(position - pointer_position);
+            map.map_state.request_redraw();
+        }
+        map.pointer_position = Some(position);
+        Ok(())
+    })
+}
+
+/// Releases the pointer at `x`, `y` in physical pixels. The map keeps moving with the velocity of
+/// the drag.
+///
+/// # Safety
+///
+/// `map` has to be a valid map.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_pointer_up(
+    map: *mut MapLibreMap,
+    x: f64,
+    y: f64,
+) -> MapLibreStatus {
+    with_map(map, |map| {
+        map.pointer_position = Some(Vector2::new(x, y));
+        map.is_dragging = false;
+        map.camera_controller.drag_end();
+        map.map_state.request_redraw();
+        Ok(())
+    })
+}
+
+/// Zooms by `delta` levels such that the position at `x`, `y` in physical pixels stays in place,
+/// e.g. for the scroll wheel.
+///
+/// # Safety
+///
+/// `map` has to be a valid map.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_zoom(
+    map: *mut MapLibreMap,
+    delta: f64,
+    x: f64,
+    y: f64,
+) -> MapLibreStatus {
+    with_map(map, |map| {
+        map.camera_controller.zoom_around(delta, Vector2::new(x, y));
+        map.map_state.request_redraw();
+        Ok(())
+    })
+}
+
+/// Scales the map by `scale` around `x`, `y` in physical pixels, e.g. for a pinch gesture.
+///
+/// # Safety
+///
+/// `map` has to be a valid map.
+#[no_mangle]
+pub unsafe extern "C" fn maplibre_map_pinch(
+    map: *mut MapLibreMap,
+    scale: f64,
+    x: f64,
+    y: f64,
+) -> MapLibreStatus {
+    with_map(map, |map| {
+        if scale <= 0.0 {
+            return Err(MapLibreStatus::InvalidArgument);
+        }
+        map.camera_controller.pinch(scale, Vector2::new(x, y));
+        map.map_state.request_redraw();
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        maplibre_map_create, maplibre_map_destroy, maplibre_map_render,
+        maplibre_map_set_style_json, MapLibreStatus, MapLibreSurface, MapLibreSurfaceKind,
+    };
+    use crate::error::{Error, RenderError};
+    use std::ptr;
+
+    #[test]
+    fn test_null_pointers() {
+        unsafe {
+            assert_eq!(
+                maplibre_map_render(ptr::null_mut()),
+                MapLibreStatus::NullPointer
+            );
+            assert_eq!(
+                maplibre_map_set_style_json(ptr::null_mut(), b"{}\0".as_ptr() as *const _),
+                MapLibreStatus::NullPointer
+            );
+
+            let mut map = ptr::null_mut();
+            assert_eq!(
+                maplibre_map_create(ptr::null(), ptr::null(), &mut map),
+                MapLibreStatus::NullPointer
+            );
+            assert!(map.is_null());
+            // Destroying a null map does nothing
+            maplibre_map_destroy(map);
+        }
+    }
+
+    #[test]
+    fn test_surface_without_pixels() {
+        let surface = MapLibreSurface {
+            kind: MapLibreSurfaceKind::Wayland,
+            handle: ptr::null_mut(),
+            display: ptr::null_mut(),
+            width: 0,
+            height: 600,
+            device_pixel_ratio: 1.0,
+        };
+        let mut map = ptr::null_mut();
+        unsafe {
+            assert_eq!(
+                maplibre_map_create(&surface, ptr::null(), &mut map),
+                MapLibreStatus::InvalidArgument
+            );
+        }
+        assert!(map.is_null());
+    }
+
+    #[test]
+    fn test_style_which_can_not_be_fetched() {
+        let surface = MapLibreSurface {
+            kind: MapLibreSurfaceKind::Wayland,
+            handle: ptr::null_mut(),
+            display: ptr::null_mut(),
+            width: 800,
+            height: 600,
+            device_pixel_ratio: 1.0,
+        };
+        let mut map = ptr::null_mut();
+        unsafe {
+            assert_eq!(
+                maplibre_map_create(&surface, b"not a url\0".as_ptr() as *const _, &mut map),
+                MapLibreStatus::Network
+            );
+        }
+        assert!(map.is_null());
+    }
+
+    #[test]
+    fn test_status_of_errors() {
+        assert_eq!(
+            MapLibreStatus::from(&Error::Style("invalid".to_string())),
+            MapLibreStatus::Style
+        );
+        assert_eq!(
+            MapLibreStatus::from(&Error::Render(RenderError::DeviceLost)),
+            MapLibreStatus::SurfaceLost
+        );
+        assert_eq!(
+            MapLibreStatus::from(&Error::Render(RenderError::NoAdapter)),
+            MapLibreStatus::Render
+        );
+        assert_eq!(
+            MapLibreStatus::from(&Error::Cancelled),
+            MapLibreStatus::Other
+        );
+    }
+}
@@ -0,0 +1,486 @@
+//! Renders the map into an image in memory instead of a window, for example to generate rasters
+//! on a server or for snapshot tests.
+
+use crate::context::{MapContext, ViewState};
+use crate::coords::{WorldTileCoords, TILE_SIZE};
+use crate::error::{Error, RenderError};
+use crate::events::{MapEvent, MapEvents};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::disk_cache::DiskTileCache;
+#[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+use crate::io::mbtiles::MbtilesSource;
+use crate::io::pmtiles::PmTilesLocation;
+use crate::io::request_limiter::RequestLimits;
+use crate::io::scheduler::{ScheduleMethod, Scheduler};
+use crate::io::shared_thread_state::SharedThreadState;
+use crate::io::source_client::{HTTPClient, RetryPolicy, TileSource};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::tessellation_cache::TessellationCache;
+use crate::io::tile_cache::TileCache;
+use crate::io::LayerTessellateMessage;
+use crate::markers::Markers;
+use crate::metrics::MetricsSink;
+use crate::render::camera_animation::CameraState;
+use crate::render::register_render_stages;
+use crate::render::settings::{RendererSettings, SurfaceType, WgpuSettings};
+use crate::schedule::{Schedule, Stage};
+use crate::stages::register_stages;
+use crate::style::Style;
+use crate::{BuilderOptions, Renderer, WindowSize};
+use cgmath::{Rad, Vector2};
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+use instant::Instant;
+use std::cmp;
+use std::collections::{BTreeSet, HashSet};
+use std::marker::PhantomData;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+/// How long to wait for tiles between two frames of [`HeadlessMap::wait_until_idle`].
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A map without a window which renders frames into a texture of a fixed size.
+pub struct HeadlessMap<SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    map_context: MapContext,
+    schedule: Schedule,
+    /// Used to wait for tiles without blocking the executor.
+    http_client: HC,
+
+    phantom_sm: PhantomData<SM>,
+}
+
+impl<SM, HC> HeadlessMap<SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    /// Runs the schedule once for the current camera and returns the frame as tightly packed RGBA
+    /// rows of `width * height` pixels. The colors are premultiplied by alpha, which only matters
+    /// for a translucent [`RendererSettings::clear_color`].
+    pub async fn render_frame(&mut self) -> Result<Vec<u8>, Error> {
+        self.schedule.run(&mut self.map_context);
+        self.map_context.renderer.read_frame().await
+    }
+
+    /// Moves the camera to `camera` and renders a frame, see [`HeadlessMap::render_frame`].
+    pub async fn render(&mut self, camera: &CameraState) -> Result<Vec<u8>, Error> {
+        self.map_context.view_state.set_camera_state(camera);
+        self.render_frame().await
+    }
+
+    pub fn size(&self) -> WindowSize {
+        self.map_context.renderer.surface().size()
+    }
+
+    /// Changes the size of the rendered images.
+    pub fn resize(&mut self, size: WindowSize) {
+        self.map_context.resize(size);
+    }
+
+    /// Renders the tile at `coords` into a PNG of `size * size` pixels, like a raster tile server
+    /// would. Frames are rendered until the vector tiles in view finished loading or `timeout`
+    /// elapsed, whatever happens first.
+    pub async fn render_tile(
+        &mut self,
+        coords: WorldTileCoords,
+        size: u32,
+        timeout: Duration,
+    ) -> Result<RenderedTile, Error> {
+        let window_size = WindowSize::new(size, size)
+            .ok_or_else(|| Error::Render(RenderError::Encode("tile size is zero".to_string())))?;
+        self.resize(window_size);
+
+        let view_state = &mut self.map_context.view_state;
+        view_state.stop_animation();
+        let tiles = 2.0_f64.powi(coords.z as i32);
+        // At this zoom the tile is exactly `size` pixels wide
+        let zoom =
+            coords.z as f64 + (size as f64 * view_state.world_units_per_pixel() / TILE_SIZE).log2();
+        view_state.set_camera_state(&CameraState {
+            center: Vector2::new(
+                (coords.x as f64 + 0.5) / tiles,
+                (coords.y as f64 + 0.5) / tiles,
+            ),
+            zoom,
+            pitch: Rad(0.0),
+            bearing: Rad(0.0),
+        });
+
+        let loaded = self.wait_until_idle(timeout).await;
+
+        let rgba = self.render_frame().await?;
+        Ok(RenderedTile {
+            png: encode_png(&rgba, self.size())?,
+            loaded,
+            unavailable_layers: self.unavailable_layers(),
+        })
+    }
+
+    /// See [`MapContext::is_fully_rendered`].
+    pub fn is_fully_rendered(&self) -> bool {
+        self.map_context.is_fully_rendered()
+    }
+
+    /// Renders frames until all tiles in view are loaded and uploaded or `timeout` elapsed.
+    /// Returns whether the map is fully rendered.
+    pub async fn wait_until_idle(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.schedule.run(&mut self.map_context);
+            if self.is_fully_rendered() {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            self.http_client.sleep(IDLE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Returns the layers of the style which are unavailable in at least one tile in view.
+    fn unavailable_layers(&self) -> BTreeSet<String> {
+        let MapContext {
+            view_state,
+            style,
+            tile_cache,
+            ..
+        } = &self.map_context;
+
+        let layers: HashSet<String> = style
+            .layers
+            .iter()
+            .filter(|layer| layer.typ != "raster")
+            .filter_map(|layer| layer.source_layer.clone())
+            .collect();
+
+        match view_state.view_region() {
+            Some(view_region) => unavailable_layers(
+                tile_cache,
+                view_region
+                    .iter()
+                    .filter(|coords| coords.build_quad_key().is_some()),
+                &layers,
+            ),
+            None => BTreeSet::new(),
+        }
+    }
+
+    /// Returns the events which were emitted since the last call, see [`MapEvent`].
+    pub fn take_events(&mut self) -> Vec<MapEvent> {
+        self.map_context.events.drain().collect()
+    }
+
+    pub fn view_state(&self) -> &ViewState {
+        &self.map_context.view_state
+    }
+
+    pub fn view_state_mut(&mut self) -> &mut ViewState {
+        &mut self.map_context.view_state
+    }
+
+    pub fn map_context(&self) -> &MapContext {
+        &self.map_context
+    }
+
+    pub fn map_context_mut(&mut self) -> &mut MapContext {
+        &mut self.map_context
+    }
+}
+
+/// Image of a tile rendered by [`HeadlessMap::render_tile`].
+pub struct RenderedTile {
+    pub png: Vec<u8>,
+    /// Whether all tiles in view finished loading before the timeout.
+    pub loaded: bool,
+    /// Layers of the style which are unavailable in at least one tile in view. Either the tile
+    /// does not contain the layer or the tile could not be fetched.
+    pub unavailable_layers: BTreeSet<String>,
+}
+
+/// Returns the `layers` which are unavailable in at least one of `tiles`.
+fn unavailable_layers(
+    tile_cache: &TileCache,
+    tiles: impl Iterator<Item = WorldTileCoords>,
+    layers: &HashSet<String>,
+) -> BTreeSet<String> {
+    let mut unavailable = BTreeSet::new();
+
+    for coords in tiles {
+        if let Some(cached_layers) = tile_cache.iter_tessellated_layers_at(&coords) {
+            for layer in cached_layers {
+                if let LayerTessellateMessage::UnavailableLayer { layer_name, .. } = layer {
+                    if layers.contains(layer_name) {
+                        unavailable.insert(layer_name.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    unavailable
+}
+
+/// Encodes the premultiplied pixels of a frame as PNG, whose colors are not premultiplied.
+fn encode_png(rgba: &[u8], size: WindowSize) -> Result<Vec<u8>, Error> {
+    let mut pixels = rgba.to_vec();
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        if alpha != 0 && alpha != 255 {
+            for component in &mut pixel[..3] {
+                *component = cmp::min(255, *component as u32 * 255 / alpha as u32) as u8;
+            }
+        }
+    }
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&pixels, size.width(), size.height(), ColorType::Rgba8)
+        .map_err(|e| Error::Render(RenderError::Encode(e.to_string())))?;
+    Ok(png)
+}
+
+/// Configures a [`HeadlessMap`]. Unlike the [`crate::MapBuilder`] the size of the output is set
+/// explicitly, because there is no window.
+pub struct HeadlessMapBuilder<SM, HC>
+where
+    SM: ScheduleMethod,
+{
+    options: BuilderOptions<SM, HC>,
+    size: Option<WindowSize>,
+    transparent_background: bool,
+}
+
+impl<SM, HC> HeadlessMapBuilder<SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    pub fn new() -> Self {
+        Self {
+            options: BuilderOptions::new(),
+            size: None,
+            transparent_background: false,
+        }
+    }
+
+    /// Sets the size of the rendered images in pixels.
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.size = WindowSize::new(width, height);
+        self
+    }
+
+    pub fn with_renderer_settings(mut self, renderer_settings: RendererSettings) -> Self {
+        self.options.renderer_settings = Some(renderer_settings);
+        self
+    }
+
+    /// Clears the rendered images with a transparent color instead of the
+    /// [`RendererSettings::clear_color`], such that only the layers of the style are opaque and
+    /// the images can be composited over other content, e.g. as overlay tiles. Pixels which are
+    /// not covered by any layer have an alpha of 0.
+    pub fn with_transparent_background(mut self) -> Self {
+        self.transparent_background = true;
+        self
+    }
+
+    pub fn with_wgpu_settings(mut self, wgpu_settings: WgpuSettings) -> Self {
+        self.options.wgpu_settings = Some(wgpu_settings);
+        self
+    }
+
+    pub fn with_schedule_method(mut self, schedule_method: SM) -> Self {
+        self.options.schedule_method = Some(schedule_method);
+        self
+    }
+
+    pub fn with_http_client(mut self, http_client: HC) -> Self {
+        self.options.http_client = Some(http_client);
+        self
+    }
+
+    pub fn with_existing_scheduler(mut self, scheduler: Scheduler<SM>) -> Self {
+        self.options.scheduler = Some(scheduler);
+        self
+    }
+
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.options.style = Some(style);
+        self
+    }
+
+    /// Reads vector tiles from a PMTiles archive instead of fetching them from a tile server.
+    pub fn with_pmtiles(mut self, location: PmTilesLocation) -> Self {
+        self.options.tile_source = Some(TileSource::PmTiles(location));
+        self
+    }
+
+    /// Reads vector tiles from an MBTiles file instead of fetching them from a tile server.
+    #[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+    pub fn with_mbtiles(mut self, source: MbtilesSource) -> Self {
+        self.options.tile_source = Some(TileSource::Mbtiles(source));
+        self
+    }
+
+    /// Configures how failed requests to tile servers are retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.options.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Limits how many requests are fetched and tessellated at once. Further requests are queued.
+    pub fn with_request_limits(mut self, request_limits: RequestLimits) -> Self {
+        self.options.request_limits = Some(request_limits);
+        self
+    }
+
+    /// Keeps fetched tiles on disk so that they are not downloaded again after a restart.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_disk_cache(mut self, disk_cache: DiskTileCache) -> Self {
+        self.options.disk_cache = Some(disk_cache);
+        self
+    }
+
+    /// Keeps tessellated layers on disk so that they are not tessellated again, see
+    /// [`TessellationCache`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_tessellation_cache(mut self, tessellation_cache: TessellationCache) -> Self {
+        self.options.tessellation_cache = Some(tessellation_cache);
+        self
+    }
+
+    /// Reports timings of tile fetching and tessellation, see [`MetricsSink`].
+    pub fn with_metrics_sink(mut self, metrics: Arc<dyn MetricsSink>) -> Self {
+        self.options.metrics = Some(metrics);
+        self
+    }
+
+    /// Initializes the renderer with an offscreen texture of the configured size. Fails if the
+    /// size, the HTTP client, or neither a schedule method nor a scheduler are set.
+    pub async fn build(self) -> Result<HeadlessMap<SM, HC>, Error> {
+        let size = self
+            .size
+            .ok_or_else(|| Error::Configuration("the size must be set and not zero".to_string()))?;
+        let mut shared = self.options.resolve()?;
+
+        let mut renderer_settings = RendererSettings {
+            surface_type: SurfaceType::Headless,
+            ..shared.renderer_settings.clone()
+        };
+        if self.transparent_background {
+            renderer_settings.clear_color = wgpu::Color::TRANSPARENT;
+        }
+        let renderer =
+            Renderer::initialize_headless(size, shared.wgpu_settings.clone(), renderer_settings)
+                .await?;
+
+        let mut schedule = Schedule::default();
+        register_stages(&mut schedule, shared.take_source_client());
+        register_render_stages(&mut schedule);
+
+        let (message_sender, message_receiver) = mpsc::channel();
+        let shared_thread_state = SharedThreadState {
+            #[cfg(not(target_arch = "wasm32"))]
+            tessellation_cache: shared.tessellation_cache,
+            ..SharedThreadState::new(message_sender, shared.metrics, &shared.request_limits)
+        };
+
+        Ok(HeadlessMap {
+            map_context: MapContext {
+                view_state: ViewState::new(&size),
+                style: shared.style,
+                tile_cache: TileCache::new(),
+                markers: Markers::default(),
+                renderer,
+                scheduler: Box::new(shared.scheduler.take()),
+                message_receiver,
+                shared_thread_state,
+                events: MapEvents::default(),
+            },
+            schedule,
+            http_client: shared.http_client,
+            phantom_sm: Default::default(),
+        })
+    }
+}
+
+impl<SM, HC> Default for HeadlessMapBuilder<SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_png, unavailable_layers};
+    use crate::coords::WorldTileCoords;
+    use crate::error::Error;
+    use crate::io::tile_cache::TileCache;
+    use crate::io::LayerTessellateMessage;
+    #[cfg(all(not(target_arch = "wasm32"), feature = "tokio"))]
+    use crate::platform::{http_client::ReqwestHttpClient, schedule_method::TokioScheduleMethod};
+    use crate::{BuilderOptions, WindowSize};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_unavailable_layers() {
+        let mut tile_cache = TileCache::new();
+        let tiles = [
+            WorldTileCoords { x: 0, y: 0, z: 1 },
+            WorldTileCoords { x: 1, y: 0, z: 1 },
+        ];
+        let layers: HashSet<String> = ["water".to_string()].into_iter().collect();
+
+        assert!(unavailable_layers(&tile_cache, tiles.into_iter(), &layers).is_empty());
+
+        for coords in tiles {
+            tile_cache.put_tessellated_layer(LayerTessellateMessage::UnavailableLayer {
+                coords,
+                source: None,
+                layer_name: "water".to_string(),
+            });
+        }
+
+        assert!(unavailable_layers(&tile_cache, tiles.into_iter(), &layers).contains("water"));
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let size = WindowSize::new(2, 1).unwrap();
+        let png = encode_png(&[255, 0, 0, 255, 0, 0, 255, 255], size).unwrap();
+
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255, 255]);
+
+        // Translucent pixels are stored without premultiplied alpha
+        let png = encode_png(&[64, 0, 0, 128, 0, 0, 0, 0], size).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [127, 0, 0, 128]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 0, 0]);
+    }
+
+    #[cfg(all(not(target_arch = "wasm32"), feature = "tokio"))]
+    #[test]
+    fn test_incomplete_builder_options() {
+        let options = BuilderOptions::<TokioScheduleMethod, ReqwestHttpClient>::new();
+        assert!(matches!(options.resolve(), Err(Error::Configuration(_))));
+
+        let mut options = BuilderOptions::<TokioScheduleMethod, ReqwestHttpClient>::new();
+        options.schedule_method = Some(TokioScheduleMethod::new());
+        assert!(matches!(options.resolve(), Err(Error::Configuration(_))));
+
+        let mut options = BuilderOptions::new();
+        options.schedule_method = Some(TokioScheduleMethod::new());
+        options.http_client = Some(ReqwestHttpClient::new(None));
+        assert!(options.resolve().is_ok());
+    }
+}
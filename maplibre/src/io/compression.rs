@@ -0,0 +1,141 @@
+//! Decompression of fetched and stored tiles. Tile servers often serve vector tiles gzip or brotli
+//! encoded, and MBTiles and PMTiles archives usually store them gzip compressed.
+
+use crate::error::Error;
+use flate2::read::GzDecoder;
+use std::io::Read;
+
+/// Magic bytes at the start of gzip compressed data.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Buffer size of the brotli decoder.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Encoding of a HTTP response body, see
+/// [Content-Encoding](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Encoding).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl Default for ContentEncoding {
+    fn default() -> Self {
+        ContentEncoding::Identity
+    }
+}
+
+impl ContentEncoding {
+    /// Parses the value of a `Content-Encoding` header. Returns an error for encodings which can
+    /// not be decoded, e.g. multiple encodings or `deflate`.
+    pub fn from_header(header: &str) -> Result<Self, Error> {
+        match header.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => Ok(ContentEncoding::Identity),
+            "gzip" | "x-gzip" => Ok(ContentEncoding::Gzip),
+            "br" => Ok(ContentEncoding::Brotli),
+            encoding => Err(Error::Decompression(format!(
+                "unsupported content encoding {}",
+                encoding
+            ))),
+        }
+    }
+
+    /// The value of the `Accept-Encoding` header which announces the supported encodings.
+    pub fn accept_header() -> &'static str {
+        "gzip, br"
+    }
+}
+
+/// Decodes `data` according to the `Content-Encoding` of its response. Data which is still gzip
+/// compressed afterwards is decompressed as well, see [`decompress_if_gzip`].
+pub fn decode(data: Vec<u8>, encoding: ContentEncoding) -> Result<Vec<u8>, Error> {
+    let data = match encoding {
+        ContentEncoding::Identity => data,
+        ContentEncoding::Gzip => gunzip(&data)?,
+        ContentEncoding::Brotli => brotli_decompress(&data)?,
+    };
+    decompress_if_gzip(data)
+}
+
+/// Decompresses `data` if it starts with the gzip magic bytes. Tile servers which serve
+/// pre-compressed files without a `Content-Encoding` and archives store tiles like this.
+pub fn decompress_if_gzip(data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if data.starts_with(&GZIP_MAGIC) {
+        gunzip(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(data)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| Error::Decompression(format!("invalid gzip data: {}", e)))?;
+    Ok(decompressed)
+}
+
+pub fn brotli_decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decompressed = Vec::new();
+    brotli_decompressor::Decompressor::new(data, BROTLI_BUFFER_SIZE)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| Error::Decompression(format!("invalid brotli data: {}", e)))?;
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, decompress_if_gzip, ContentEncoding};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_content_encoding_from_header() {
+        assert_eq!(
+            ContentEncoding::from_header("gzip").unwrap(),
+            ContentEncoding::Gzip
+        );
+        assert_eq!(
+            ContentEncoding::from_header(" BR ").unwrap(),
+            ContentEncoding::Brotli
+        );
+        assert_eq!(
+            ContentEncoding::from_header("identity").unwrap(),
+            ContentEncoding::Identity
+        );
+        assert!(ContentEncoding::from_header("deflate").is_err());
+        assert!(ContentEncoding::from_header("gzip, br").is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        let data = b"tile data".to_vec();
+
+        assert_eq!(decode(gzip(&data), ContentEncoding::Gzip).unwrap(), data);
+        // Pre-compressed files without Content-Encoding
+        assert_eq!(
+            decode(gzip(&data), ContentEncoding::Identity).unwrap(),
+            data
+        );
+        assert_eq!(decompress_if_gzip(data.clone()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_malformed_data() {
+        let mut truncated = gzip(b"tile data");
+        truncated.truncate(truncated.len() / 2);
+
+        assert!(decompress_if_gzip(truncated).is_err());
+        assert!(decode(b"not gzip".to_vec(), ContentEncoding::Gzip).is_err());
+        // Starts with a reserved window size
+        assert!(decode(vec![0x11; 16], ContentEncoding::Brotli).is_err());
+    }
+}
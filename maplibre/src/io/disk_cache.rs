@@ -0,0 +1,354 @@
+//! Persistent cache of raw tile data on disk which survives restarts of the application.
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::source_client::CachePolicy;
+use crate::io::tessellation_cache::escape;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Extension of the files within the cache directory.
+const TILE_EXTENSION: &str = "tile";
+
+/// Length of the header which stores the expiry of a cached tile in seconds since the unix epoch.
+/// Zero means that the tile does not expire.
+const HEADER_LENGTH: usize = 8;
+
+/// Number of hits and misses since the cache was opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DiskCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct CacheEntry {
+    size: u64,
+    last_access: u64,
+}
+
+struct DiskCacheState {
+    directory: PathBuf,
+    max_size: u64,
+    entries: HashMap<String, CacheEntry>,
+    total_size: u64,
+    /// Increases with every access and orders the entries by recency.
+    clock: u64,
+    stats: DiskCacheStats,
+}
+
+/// Stores fetched tile data keyed by source and coordinates within a directory. If the size of
+/// all tiles exceeds the budget, the least recently used tiles are evicted. Clones share the same
+/// state.
+#[derive(Clone)]
+pub struct DiskTileCache {
+    state: Arc<Mutex<DiskCacheState>>,
+}
+
+impl DiskTileCache {
+    /// Opens the cache within `directory` and creates the directory if it is missing. Tiles which
+    /// are already in the directory are kept, ordered by the time they were written.
+    pub fn open<P: AsRef<Path>>(directory: P, max_size: u64) -> Result<Self, Error> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory).map_err(to_error)?;
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&directory).map_err(to_error)? {
+            let entry = entry.map_err(to_error)?;
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(TILE_EXTENSION) {
+                continue;
+            }
+            let metadata = entry.metadata().map_err(to_error)?;
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                files.push((
+                    name.to_string(),
+                    metadata.len(),
+                    metadata.modified().unwrap_or(UNIX_EPOCH),
+                ));
+            }
+        }
+        files.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut state = DiskCacheState {
+            directory,
+            max_size,
+            entries: HashMap::new(),
+            total_size: 0,
+            clock: 0,
+            stats: DiskCacheStats::default(),
+        };
+        for (name, size, _) in files {
+            state.clock += 1;
+            state.total_size += size;
+            state.entries.insert(
+                name,
+                CacheEntry {
+                    size,
+                    last_access: state.clock,
+                },
+            );
+        }
+        state.evict();
+
+        Ok(Self {
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Returns the cached data of the tile at `coords` from `source`. Expired tiles are removed
+    /// and count as a miss.
+    pub fn get(&self, source: &str, coords: &WorldTileCoords) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let name = file_name(source, coords);
+
+        let data = if state.entries.contains_key(&name) {
+            match fs::read(state.directory.join(&name)) {
+                Ok(data) if data.len() >= HEADER_LENGTH && !is_expired(&data) => Some(data),
+                _ => {
+                    state.remove(&name);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        match data {
+            Some(data) => {
+                state.stats.hits += 1;
+                state.clock += 1;
+                let clock = state.clock;
+                if let Some(entry) = state.entries.get_mut(&name) {
+                    entry.last_access = clock;
+                }
+                Some(data[HEADER_LENGTH..].to_vec())
+            }
+            None => {
+                state.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Stores the data of a tile, unless the response forbids it. Tiles which are larger than the
+    /// whole budget are not stored.
+    pub fn put(
+        &self,
+        source: &str,
+        coords: &WorldTileCoords,
+        data: &[u8],
+        policy: &CachePolicy,
+    ) -> Result<(), Error> {
+        if policy.no_store || policy.max_age == Some(Duration::ZERO) {
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let size = (HEADER_LENGTH + data.len()) as u64;
+        if size > state.max_size {
+            return Ok(());
+        }
+
+        let expires = policy
+            .max_age
+            .and_then(|max_age| SystemTime::now().checked_add(max_age))
+            .and_then(|expires| expires.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |expires| expires.as_secs().max(1));
+
+        let mut content = Vec::with_capacity(size as usize);
+        content.extend_from_slice(&expires.to_le_bytes());
+        content.extend_from_slice(data);
+
+        let name = file_name(source, coords);
+        fs::write(state.directory.join(&name), content).map_err(to_error)?;
+
+        state.clock += 1;
+        let last_access = state.clock;
+        if let Some(previous) = state.entries.insert(name, CacheEntry { size, last_access }) {
+            state.total_size -= previous.size;
+        }
+        state.total_size += size;
+        state.evict();
+
+        Ok(())
+    }
+
+    pub fn stats(&self) -> DiskCacheStats {
+        self.state.lock().unwrap().stats
+    }
+
+    /// Size of all cached tiles in bytes.
+    pub fn size(&self) -> u64 {
+        self.state.lock().unwrap().total_size
+    }
+}
+
+impl DiskCacheState {
+    fn remove(&mut self, name: &str) {
+        if let Some(entry) = self.entries.remove(name) {
+            self.total_size -= entry.size;
+            if let Err(e) = fs::remove_file(self.directory.join(name)) {
+                if e.kind() != ErrorKind::NotFound {
+                    log::warn!("failed to remove cached tile {}: {}", name, e);
+                }
+            }
+        }
+    }
+
+    /// Removes the least recently used tiles until the budget is met.
+    fn evict(&mut self) {
+        while self.total_size > self.max_size {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(name, _)| name.clone());
+
+            match oldest {
+                Some(name) => self.remove(&name),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Derives a file name from the source and coordinates. Characters which are not allowed in file
+/// names, like the slashes of URLs, are escaped such that distinct sources never share a file,
+/// see [`escape`].
+fn file_name(source: &str, coords: &WorldTileCoords) -> String {
+    format!(
+        "{}-{}-{}-{}.{}",
+        escape(source),
+        coords.z,
+        coords.x,
+        coords.y,
+        TILE_EXTENSION
+    )
+}
+
+fn is_expired(content: &[u8]) -> bool {
+    let mut header = [0; HEADER_LENGTH];
+    header.copy_from_slice(&content[..HEADER_LENGTH]);
+    let expires = u64::from_le_bytes(header);
+
+    expires != 0
+        && SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(false, |now| now.as_secs() >= expires)
+}
+
+fn to_error(error: std::io::Error) -> Error {
+    Error::Cache(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiskCacheStats, DiskTileCache};
+    use crate::coords::WorldTileCoords;
+    use crate::io::source_client::CachePolicy;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn directory(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("maplibre-disk-cache-{}", name));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    fn coords(x: i32) -> WorldTileCoords {
+        WorldTileCoords { x, y: 0, z: 4 }
+    }
+
+    #[test]
+    fn test_get_and_put() {
+        let cache = DiskTileCache::open(directory("get-and-put"), 1024).unwrap();
+
+        assert_eq!(cache.get("source", &coords(0)), None);
+        cache
+            .put("source", &coords(0), b"tile", &CachePolicy::default())
+            .unwrap();
+        assert_eq!(cache.get("source", &coords(0)), Some(b"tile".to_vec()));
+        assert_eq!(cache.get("other", &coords(0)), None);
+
+        assert_eq!(cache.stats(), DiskCacheStats { hits: 1, misses: 2 });
+    }
+
+    #[test]
+    fn test_sources_do_not_share_files() {
+        let cache = DiskTileCache::open(directory("distinct-sources"), 1024).unwrap();
+
+        cache
+            .put(
+                "https://a.org/{z}/{x}/{y}.pbf",
+                &coords(0),
+                b"tile",
+                &CachePolicy::default(),
+            )
+            .unwrap();
+        assert_eq!(cache.get("https://a_org/{z}/{x}/{y}.pbf", &coords(0)), None);
+    }
+
+    #[test]
+    fn test_survives_reopening() {
+        let path = directory("reopen");
+        DiskTileCache::open(&path, 1024)
+            .unwrap()
+            .put("source", &coords(0), b"tile", &CachePolicy::default())
+            .unwrap();
+
+        let cache = DiskTileCache::open(&path, 1024).unwrap();
+        assert_eq!(cache.get("source", &coords(0)), Some(b"tile".to_vec()));
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        // Room for two tiles of 16 bytes including the header
+        let cache = DiskTileCache::open(directory("evict"), 32).unwrap();
+        let policy = CachePolicy::default();
+
+        cache.put("source", &coords(0), &[0; 8], &policy).unwrap();
+        cache.put("source", &coords(1), &[0; 8], &policy).unwrap();
+        assert!(cache.get("source", &coords(0)).is_some());
+        cache.put("source", &coords(2), &[0; 8], &policy).unwrap();
+
+        assert!(cache.get("source", &coords(0)).is_some());
+        assert!(cache.get("source", &coords(1)).is_none());
+        assert!(cache.get("source", &coords(2)).is_some());
+        assert!(cache.size() <= 32);
+    }
+
+    #[test]
+    fn test_honors_cache_policy() {
+        let cache = DiskTileCache::open(directory("policy"), 1024).unwrap();
+
+        cache
+            .put(
+                "source",
+                &coords(0),
+                b"tile",
+                &CachePolicy {
+                    no_store: true,
+                    max_age: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(cache.get("source", &coords(0)), None);
+
+        cache
+            .put(
+                "source",
+                &coords(1),
+                b"tile",
+                &CachePolicy {
+                    no_store: false,
+                    max_age: Some(Duration::from_secs(3600)),
+                },
+            )
+            .unwrap();
+        assert_eq!(cache.get("source", &coords(1)), Some(b"tile".to_vec()));
+    }
+}
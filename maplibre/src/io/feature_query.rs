@@ -0,0 +1,323 @@
+//! Finds the rendered features at a position in the window.
+
+use crate::context::ViewState;
+use crate::coords::{WorldCoords, WorldTileCoords, Zoom, EXTENT, TILE_SIZE};
+use crate::io::tile_cache::TileCache;
+use crate::io::LayerTessellateMessage;
+use crate::style::layer::StyleLayer;
+use crate::style::Style;
+use crate::text::feature::{geometry_paths, property_keys, property_string, TileFeature};
+use cgmath::Vector2;
+use geozero::mvt::tile;
+use std::collections::HashMap;
+
+/// Default radius in pixels around the queried position within which lines and points are
+/// picked.
+pub const DEFAULT_QUERY_RADIUS: f64 = 3.0;
+
+/// A feature which is rendered at the queried position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueriedFeature {
+    /// Id of the style layer which rendered the feature.
+    pub layer_id: String,
+    pub source_layer: String,
+    /// The tile which contains the feature.
+    pub coords: WorldTileCoords,
+    pub properties: HashMap<String, String>,
+}
+
+/// Returns the features which are rendered at `window_position`, topmost first. Lines and
+/// points within `radius` pixels are included.
+pub fn query_rendered_features(
+    view_state: &ViewState,
+    style: &Style,
+    tile_cache: &TileCache,
+    window_position: &Vector2<f64>,
+    radius: f64,
+) -> Vec<QueriedFeature> {
+    let inverted_view_proj = view_state.view_projection().invert();
+    let world = match view_state
+        .camera
+        .window_to_world_at_ground(window_position, &inverted_view_proj)
+    {
+        Some(world) => WorldCoords::at_ground(world.x, world.y),
+        None => return Vec::new(),
+    };
+
+    let zoom = view_state.zoom();
+    let tile_units_per_pixel = |coords: &WorldTileCoords| {
+        view_state.world_units_per_pixel() * EXTENT * zoom.scale_to_tile(coords) / TILE_SIZE
+    };
+
+    let mut features = Vec::new();
+
+    // Layers which are drawn last are on top
+    for style_layer in style.layers.iter().rev() {
+        if !is_queryable(style_layer, zoom.value()) {
+            continue;
+        }
+
+        let source_layer = match &style_layer.source_layer {
+            Some(source_layer) => source_layer,
+            None => continue,
+        };
+
+        if let Some((coords, layer)) = find_layer(
+            tile_cache,
+            &world,
+            zoom,
+            style.tile_source_id(style_layer),
+            source_layer,
+            view_state.visible_level(),
+        ) {
+            let scale = zoom.scale_to_tile(&coords);
+            let point = [
+                (world.x / TILE_SIZE * scale - coords.x as f64) * EXTENT,
+                (world.y / TILE_SIZE * scale - coords.y as f64) * EXTENT,
+            ];
+
+            let tile_units_per_pixel = tile_units_per_pixel(&coords);
+            let line_width = style_layer
+                .paint
+                .as_ref()
+                .and_then(|paint| paint.get_line_width(zoom.value(), None))
+                .unwrap_or(0.0) as f64;
+            let tolerance = (radius + line_width / 2.0) * tile_units_per_pixel;
+
+            for feature in query_layer(style_layer, layer, point, tolerance) {
+                features.push(QueriedFeature {
+                    layer_id: style_layer.id.clone(),
+                    source_layer: source_layer.clone(),
+                    coords,
+                    properties: property_keys(layer, feature)
+                        .filter_map(|key| {
+                            property_string(layer, feature, key)
+                                .map(|value| (key.to_string(), value))
+                        })
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    features
+}
+
+/// Whether the features of the layer are drawn at `zoom`.
+fn is_queryable(style_layer: &StyleLayer, zoom: f64) -> bool {
+    style_layer.is_visible_at(zoom)
+        && matches!(style_layer.typ.as_str(), "fill" | "line" | "symbol")
+}
+
+/// Finds the tessellated layer of `source` at `world`. If the tile at `z` is not loaded yet, its
+/// parents are searched because they are drawn instead.
+fn find_layer<'a>(
+    tile_cache: &'a TileCache,
+    world: &WorldCoords,
+    zoom: Zoom,
+    source: Option<&str>,
+    source_layer: &str,
+    z: u8,
+) -> Option<(WorldTileCoords, &'a tile::Layer)> {
+    let mut current = Some(world.into_world_tile(z, zoom));
+
+    while let Some(coords) = current {
+        let layer = tile_cache
+            .iter_tessellated_layers_at(&coords)
+            .and_then(|mut layers| {
+                layers.find_map(|message| match message {
+                    LayerTessellateMessage::TessellatedLayer { layer_data, .. }
+                        if layer_data.name == source_layer && message.source() == source =>
+                    {
+                        Some(layer_data)
+                    }
+                    _ => None,
+                })
+            });
+
+        if let Some(layer) = layer {
+            return Some((coords, layer));
+        }
+        current = coords.get_parent();
+    }
+
+    None
+}
+
+/// Returns the features of `layer` which contain `point` or are within `tolerance` of it. Both
+/// are in tile units. Features which are drawn last come first.
+fn query_layer<'a>(
+    style_layer: &StyleLayer,
+    layer: &'a tile::Layer,
+    point: [f64; 2],
+    tolerance: f64,
+) -> Vec<&'a tile::Feature> {
+    layer
+        .features
+        .iter()
+        .rev()
+        .filter(|feature| {
+            style_layer.matches(&TileFeature {
+                layer,
+                feature: *feature,
+            })
+        })
+        .filter(|feature| {
+            let paths = geometry_paths(feature);
+            match style_layer.typ.as_str() {
+                "fill" if feature.r#type == Some(tile::GeomType::Polygon as i32) => {
+                    polygon_contains(&paths, point)
+                }
+                "line" => distance_to_paths(&paths, point) <= tolerance,
+                "symbol" if feature.r#type == Some(tile::GeomType::Point as i32) => {
+                    distance_to_paths(&paths, point) <= tolerance
+                }
+                _ => false,
+            }
+        })
+        .collect()
+}
+
+/// Tests whether `point` is inside the rings of a polygon with the even-odd rule. Holes are
+/// therefore excluded.
+fn polygon_contains(rings: &[Vec<[f32; 2]>], point: [f64; 2]) -> bool {
+    let mut inside = false;
+
+    for ring in rings {
+        for segment in ring.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+            let (ax, ay, bx, by) = (a[0] as f64, a[1] as f64, b[0] as f64, b[1] as f64);
+
+            if (ay > point[1]) != (by > point[1])
+                && point[0] < (bx - ax) * (point[1] - ay) / (by - ay) + ax
+            {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
+
+/// Returns the smallest distance between `point` and the segments or points of `paths`.
+fn distance_to_paths(paths: &[Vec<[f32; 2]>], point: [f64; 2]) -> f64 {
+    paths
+        .iter()
+        .flat_map(|path| {
+            let points = path.iter().map(|p| [p[0] as f64, p[1] as f64]);
+            if path.len() == 1 {
+                points.map(|p| distance(p, point)).collect::<Vec<_>>()
+            } else {
+                path.windows(2)
+                    .map(|segment| {
+                        distance_to_segment(
+                            [segment[0][0] as f64, segment[0][1] as f64],
+                            [segment[1][0] as f64, segment[1][1] as f64],
+                            point,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            }
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+fn distance_to_segment(a: [f64; 2], b: [f64; 2], point: [f64; 2]) -> f64 {
+    let length_squared = (b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2);
+    if length_squared == 0.0 {
+        return distance(a, point);
+    }
+
+    let t = (((point[0] - a[0]) * (b[0] - a[0]) + (point[1] - a[1]) * (b[1] - a[1]))
+        / length_squared)
+        .clamp(0.0, 1.0);
+    distance([a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1])], point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance_to_paths, polygon_contains, query_layer};
+    use crate::style::layer::StyleLayer;
+    use geozero::mvt::tile;
+
+    fn square(min: f32, max: f32) -> Vec<[f32; 2]> {
+        vec![[min, min], [max, min], [max, max], [min, max], [min, min]]
+    }
+
+    #[test]
+    fn test_polygon_contains() {
+        let rings = vec![square(0.0, 10.0), square(4.0, 6.0)];
+
+        assert!(polygon_contains(&rings, [2.0, 2.0]));
+        assert!(!polygon_contains(&rings, [5.0, 5.0]));
+        assert!(!polygon_contains(&rings, [12.0, 5.0]));
+    }
+
+    #[test]
+    fn test_distance_to_paths() {
+        let line = vec![vec![[0.0, 0.0], [10.0, 0.0]]];
+        assert_eq!(distance_to_paths(&line, [5.0, 3.0]), 3.0);
+        assert_eq!(distance_to_paths(&line, [13.0, 4.0]), 5.0);
+
+        let point = vec![vec![[1.0, 1.0]]];
+        assert_eq!(distance_to_paths(&point, [4.0, 5.0]), 5.0);
+    }
+
+    #[test]
+    fn test_query_layer() {
+        let layer = tile::Layer {
+            version: 2,
+            name: "water".to_string(),
+            features: vec![
+                tile::Feature {
+                    id: Some(1),
+                    tags: vec![],
+                    r#type: Some(tile::GeomType::Polygon as i32),
+                    // MoveTo (0, 0) LineTo(3) (10, 0) (10, 10) (0, 10) ClosePath
+                    geometry: vec![9, 0, 0, 26, 20, 0, 0, 20, 19, 0, 15],
+                },
+                tile::Feature {
+                    id: Some(2),
+                    tags: vec![],
+                    r#type: Some(tile::GeomType::Polygon as i32),
+                    // MoveTo (20, 20) LineTo(3) (30, 20) (30, 30) (20, 30) ClosePath
+                    geometry: vec![9, 40, 40, 26, 20, 0, 0, 20, 19, 0, 15],
+                },
+            ],
+            keys: vec![],
+            values: vec![],
+            extent: Some(4096),
+        };
+
+        let style_layer = StyleLayer {
+            typ: "fill".to_string(),
+            ..StyleLayer::default()
+        };
+        let ids = |point| {
+            query_layer(&style_layer, &layer, point, 1.0)
+                .iter()
+                .map(|feature| feature.id.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids([5.0, 5.0]), vec![1]);
+        assert_eq!(ids([25.0, 25.0]), vec![2]);
+        assert!(ids([15.0, 15.0]).is_empty());
+
+        let line_layer = StyleLayer {
+            typ: "line".to_string(),
+            ..StyleLayer::default()
+        };
+        assert_eq!(
+            query_layer(&line_layer, &layer, [10.5, 5.0], 1.0)
+                .iter()
+                .map(|feature| feature.id.unwrap())
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+}
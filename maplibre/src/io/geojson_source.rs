@@ -0,0 +1,774 @@
+//! Client-side tiling of GeoJSON data, similar to
+//! [geojson-vt](https://github.com/mapbox/geojson-vt).
+//!
+//! The features of a `FeatureCollection` are projected once into Web Mercator. Tiles are then
+//! sliced on demand and encoded as vector tile layers, such that they pass through the same
+//! tessellation path as tiles which are fetched from a server.
+
+use crate::coords::{WorldTileCoords, EXTENT, EXTENT_UINT, MAX_LATITUDE, TILE_SIZE};
+use crate::error::Error;
+use geo::algorithm::simplify::Simplify;
+use geo_types::{Coordinate, LineString};
+use geozero::mvt::tile;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+/// Simplification tolerance in pixels if the source does not define one.
+pub const DEFAULT_TOLERANCE: f64 = 0.375;
+/// Size of the buffer around each tile in pixels if the source does not define one.
+pub const DEFAULT_BUFFER: f64 = 128.0;
+
+const COMMAND_MOVE_TO: u32 = 1;
+const COMMAND_LINE_TO: u32 = 2;
+const COMMAND_CLOSE_PATH: u32 = 7;
+
+/// A position in Web Mercator where `[0, 0]` is the north-west and `[1, 1]` the south-east corner
+/// of the world.
+type Point = [f64; 2];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Geometry {
+    Points(Vec<Point>),
+    Lines(Vec<Vec<Point>>),
+    /// Each polygon consists of an exterior ring followed by its holes.
+    Polygons(Vec<Vec<Vec<Point>>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Feature {
+    id: Option<u64>,
+    geometry: Geometry,
+    properties: Vec<(String, tile::Value)>,
+    /// Bounding box `[min_x, min_y, max_x, max_y]` of the projected geometry.
+    bbox: [f64; 4],
+}
+
+/// Bounding boxes of the features which differ between two versions of a GeoJSON source, see
+/// [`GeoJsonSource::changes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoJsonChanges {
+    /// Bounding boxes `[min_x, min_y, max_x, max_y]` of the removed and the added features.
+    bounds: Vec<[f64; 4]>,
+    /// Buffer around each tile in pixels.
+    buffer: f64,
+}
+
+impl GeoJsonChanges {
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    /// Whether the tile at `coords`, including its buffer, intersects any of the changed features.
+    /// The layers of such tiles need to be sliced again.
+    pub fn affects(&self, coords: &WorldTileCoords) -> bool {
+        let (min, max) = tile_bounds(coords, self.buffer);
+        self.bounds.iter().any(|bbox| {
+            bbox[0] <= max[0] && bbox[2] >= min[0] && bbox[1] <= max[1] && bbox[3] >= min[1]
+        })
+    }
+}
+
+/// Returns the north-west and south-east corners of the tile at `coords` in Web Mercator, extended
+/// by `buffer` pixels.
+fn tile_bounds(coords: &WorldTileCoords, buffer: f64) -> (Point, Point) {
+    let scale = (1u64 << coords.z) as f64;
+    let buffer = buffer / TILE_SIZE;
+    (
+        [
+            (coords.x as f64 - buffer) / scale,
+            (coords.y as f64 - buffer) / scale,
+        ],
+        [
+            (coords.x as f64 + 1.0 + buffer) / scale,
+            (coords.y as f64 + 1.0 + buffer) / scale,
+        ],
+    )
+}
+
+/// Holds the features of a GeoJSON object and slices them into vector tile layers.
+#[derive(Debug, Clone)]
+pub struct GeoJsonSource {
+    features: Vec<Feature>,
+    /// Simplification tolerance in pixels.
+    tolerance: f64,
+    /// Buffer around each tile in pixels.
+    buffer: f64,
+}
+
+impl GeoJsonSource {
+    /// Parses a GeoJSON `FeatureCollection`, `Feature` or geometry.
+    pub fn parse(json: &serde_json::Value) -> Result<Self, Error> {
+        let mut features = Vec::new();
+
+        match json.get("type").and_then(|typ| typ.as_str()) {
+            Some("FeatureCollection") => {
+                let collection = json
+                    .get("features")
+                    .and_then(|features| features.as_array())
+                    .ok_or_else(|| {
+                        Error::GeoJson("FeatureCollection without features".to_string())
+                    })?;
+                for feature in collection {
+                    parse_feature(feature, &mut features)?;
+                }
+            }
+            Some("Feature") => parse_feature(json, &mut features)?,
+            Some(_) => parse_geometry(json, None, Vec::new(), &mut features)?,
+            None => return Err(Error::GeoJson("missing type".to_string())),
+        }
+
+        Ok(Self {
+            features,
+            tolerance: DEFAULT_TOLERANCE,
+            buffer: DEFAULT_BUFFER,
+        })
+    }
+
+    /// Sets the Douglas-Peucker simplification tolerance in pixels. Zero disables simplification.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Sets the size of the buffer around each tile in pixels. Geometries are clipped at the
+    /// buffer in order to avoid artifacts at the edges of tiles.
+    pub fn with_buffer(mut self, buffer: f64) -> Self {
+        self.buffer = buffer;
+        self
+    }
+
+    /// Compares the features with those of the `previous` version of the source. Features are
+    /// compared by their position within the collection, such that updates which move or restyle
+    /// a few features of a stable collection only affect the tiles around those features.
+    pub fn changes(&self, previous: &GeoJsonSource) -> GeoJsonChanges {
+        let mut bounds = Vec::new();
+        for i in 0..self.features.len().max(previous.features.len()) {
+            let (current, previous) = (self.features.get(i), previous.features.get(i));
+            if current != previous {
+                bounds.extend(
+                    current
+                        .into_iter()
+                        .chain(previous)
+                        .map(|feature| feature.bbox),
+                );
+            }
+        }
+
+        GeoJsonChanges {
+            bounds,
+            buffer: self.buffer.max(previous.buffer),
+        }
+    }
+
+    /// Slices the features which intersect the tile at `coords` and encodes them as a vector tile
+    /// layer with the given name.
+    pub fn tile_layer(&self, coords: &WorldTileCoords, name: &str) -> tile::Layer {
+        let scale = (1u64 << coords.z) as f64;
+        let (min, max) = tile_bounds(coords, self.buffer);
+
+        let to_tile = |point: &Point| -> Point {
+            [
+                (point[0] * scale - coords.x as f64) * EXTENT,
+                (point[1] * scale - coords.y as f64) * EXTENT,
+            ]
+        };
+        // The tolerance is defined in pixels, the simplification is done in tile units
+        let epsilon = self.tolerance * EXTENT / TILE_SIZE;
+
+        let mut encoder = LayerEncoder::new(name);
+
+        for feature in &self.features {
+            if feature.bbox[0] > max[0]
+                || feature.bbox[2] < min[0]
+                || feature.bbox[1] > max[1]
+                || feature.bbox[3] < min[1]
+            {
+                continue;
+            }
+
+            let (geom_type, geometry) = match &feature.geometry {
+                Geometry::Points(points) => {
+                    let points = points
+                        .iter()
+                        .filter(|point| {
+                            point[0] >= min[0]
+                                && point[0] <= max[0]
+                                && point[1] >= min[1]
+                                && point[1] <= max[1]
+                        })
+                        .map(to_tile)
+                        .collect::<Vec<_>>();
+                    (tile::GeomType::Point, encode_points(&points))
+                }
+                Geometry::Lines(lines) => {
+                    let lines = lines
+                        .iter()
+                        .flat_map(|line| clip_line(line, 0, min[0], max[0]))
+                        .flat_map(|line| clip_line(&line, 1, min[1], max[1]))
+                        .map(|line| simplify(line.iter().map(to_tile).collect(), epsilon))
+                        .filter(|line| line.len() >= 2)
+                        .collect::<Vec<_>>();
+                    (tile::GeomType::Linestring, encode_lines(&lines))
+                }
+                Geometry::Polygons(polygons) => {
+                    let polygons = polygons
+                        .iter()
+                        .filter_map(|polygon| {
+                            let rings = polygon
+                                .iter()
+                                .map(|ring| clip_ring(ring, 0, min[0], max[0]))
+                                .map(|ring| clip_ring(&ring, 1, min[1], max[1]))
+                                .map(|ring| simplify(ring.iter().map(to_tile).collect(), epsilon))
+                                .collect::<Vec<_>>();
+
+                            // Polygons without exterior ring are dropped together with their holes
+                            if rings.first().map_or(true, |exterior| exterior.len() < 4) {
+                                return None;
+                            }
+
+                            Some(
+                                rings
+                                    .into_iter()
+                                    .filter(|ring| ring.len() >= 4)
+                                    .collect::<Vec<_>>(),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    (tile::GeomType::Polygon, encode_polygons(&polygons))
+                }
+            };
+
+            if !geometry.is_empty() {
+                encoder.add_feature(feature, geom_type, geometry);
+            }
+        }
+
+        encoder.layer
+    }
+}
+
+/// Projects a longitude and latitude into Web Mercator.
+fn project(position: &serde_json::Value) -> Result<Point, Error> {
+    let coordinates = position
+        .as_array()
+        .filter(|coordinates| coordinates.len() >= 2)
+        .ok_or_else(|| Error::GeoJson("invalid position".to_string()))?;
+    let lon = coordinates[0]
+        .as_f64()
+        .ok_or_else(|| Error::GeoJson("invalid longitude".to_string()))?;
+    let lat = coordinates[1]
+        .as_f64()
+        .ok_or_else(|| Error::GeoJson("invalid latitude".to_string()))?;
+
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = lon / 360.0 + 0.5;
+    let y = 0.5 - (PI / 4.0 + lat / 2.0).tan().ln() / (2.0 * PI);
+    Ok([x, y])
+}
+
+fn project_array(positions: &serde_json::Value) -> Result<Vec<Point>, Error> {
+    positions
+        .as_array()
+        .ok_or_else(|| Error::GeoJson("invalid coordinates".to_string()))?
+        .iter()
+        .map(project)
+        .collect()
+}
+
+fn project_nested<T>(
+    value: &serde_json::Value,
+    project_inner: fn(&serde_json::Value) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+    value
+        .as_array()
+        .ok_or_else(|| Error::GeoJson("invalid coordinates".to_string()))?
+        .iter()
+        .map(project_inner)
+        .collect()
+}
+
+fn parse_feature(json: &serde_json::Value, features: &mut Vec<Feature>) -> Result<(), Error> {
+    let properties = json
+        .get("properties")
+        .and_then(|properties| properties.as_object())
+        .map(|properties| {
+            properties
+                .iter()
+                .filter_map(|(key, value)| {
+                    convert_property(value).map(|value| (key.clone(), value))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let id = json.get("id").and_then(|id| id.as_u64());
+
+    match json.get("geometry") {
+        Some(geometry) if !geometry.is_null() => parse_geometry(geometry, id, properties, features),
+        // Features without geometry can not be displayed
+        _ => Ok(()),
+    }
+}
+
+fn parse_geometry(
+    json: &serde_json::Value,
+    id: Option<u64>,
+    properties: Vec<(String, tile::Value)>,
+    features: &mut Vec<Feature>,
+) -> Result<(), Error> {
+    let typ = json
+        .get("type")
+        .and_then(|typ| typ.as_str())
+        .ok_or_else(|| Error::GeoJson("geometry without type".to_string()))?;
+
+    if typ == "GeometryCollection" {
+        let geometries = json
+            .get("geometries")
+            .and_then(|geometries| geometries.as_array())
+            .ok_or_else(|| Error::GeoJson("GeometryCollection without geometries".to_string()))?;
+        for geometry in geometries {
+            parse_geometry(geometry, id, properties.clone(), features)?;
+        }
+        return Ok(());
+    }
+
+    let coordinates = json
+        .get("coordinates")
+        .ok_or_else(|| Error::GeoJson(format!("{} without coordinates", typ)))?;
+
+    let geometry = match typ {
+        "Point" => Geometry::Points(vec![project(coordinates)?]),
+        "MultiPoint" => Geometry::Points(project_array(coordinates)?),
+        "LineString" => Geometry::Lines(vec![project_array(coordinates)?]),
+        "MultiLineString" => Geometry::Lines(project_nested(coordinates, project_array)?),
+        "Polygon" => Geometry::Polygons(vec![project_nested(coordinates, project_array)?]),
+        "MultiPolygon" => Geometry::Polygons(project_nested(coordinates, |polygon| {
+            project_nested(polygon, project_array)
+        })?),
+        _ => return Err(Error::GeoJson(format!("unknown geometry type {}", typ))),
+    };
+
+    let mut bbox = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+    let mut extend = |point: &Point| {
+        bbox[0] = bbox[0].min(point[0]);
+        bbox[1] = bbox[1].min(point[1]);
+        bbox[2] = bbox[2].max(point[0]);
+        bbox[3] = bbox[3].max(point[1]);
+    };
+    match &geometry {
+        Geometry::Points(points) => points.iter().for_each(&mut extend),
+        Geometry::Lines(lines) => lines.iter().flatten().for_each(&mut extend),
+        Geometry::Polygons(polygons) => polygons.iter().flatten().flatten().for_each(&mut extend),
+    }
+
+    features.push(Feature {
+        id,
+        geometry,
+        properties,
+        bbox,
+    });
+    Ok(())
+}
+
+/// Converts a GeoJSON property into a vector tile value. Arrays and objects are stored as JSON
+/// strings, `null` values are dropped.
+fn convert_property(value: &serde_json::Value) -> Option<tile::Value> {
+    let mut converted = tile::Value::default();
+    match value {
+        serde_json::Value::Null => return None,
+        serde_json::Value::Bool(bool) => converted.bool_value = Some(*bool),
+        serde_json::Value::Number(number) => {
+            if let Some(int) = number.as_i64() {
+                converted.int_value = Some(int)
+            } else if let Some(uint) = number.as_u64() {
+                converted.uint_value = Some(uint)
+            } else {
+                converted.double_value = number.as_f64()
+            }
+        }
+        serde_json::Value::String(string) => converted.string_value = Some(string.clone()),
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+            converted.string_value = Some(value.to_string())
+        }
+    }
+    Some(converted)
+}
+
+/// Interpolates the point on the segment from `a` to `b` at which `axis` equals `k`.
+fn intersect(a: &Point, b: &Point, axis: usize, k: f64) -> Point {
+    let t = (k - a[axis]) / (b[axis] - a[axis]);
+    let other = 1 - axis;
+    let mut point = [0.0; 2];
+    point[axis] = k;
+    point[other] = a[other] + (b[other] - a[other]) * t;
+    point
+}
+
+/// Clips a line string to the slab `k1 <= point[axis] <= k2`. The line can fall apart into
+/// multiple parts.
+fn clip_line(line: &[Point], axis: usize, k1: f64, k2: f64) -> Vec<Vec<Point>> {
+    let mut parts = Vec::new();
+    let mut current = Vec::new();
+
+    for segment in line.windows(2) {
+        let (a, b) = (&segment[0], &segment[1]);
+        let (a_value, b_value) = (a[axis], b[axis]);
+        let mut exited = false;
+
+        if a_value < k1 {
+            // Entering the slab from below
+            if b_value > k1 {
+                current.push(intersect(a, b, axis, k1));
+                if b_value > k2 {
+                    current.push(intersect(a, b, axis, k2));
+                    exited = true;
+                }
+            }
+        } else if a_value > k2 {
+            // Entering the slab from above
+            if b_value < k2 {
+                current.push(intersect(a, b, axis, k2));
+                if b_value < k1 {
+                    current.push(intersect(a, b, axis, k1));
+                    exited = true;
+                }
+            }
+        } else {
+            current.push(*a);
+            if b_value < k1 {
+                current.push(intersect(a, b, axis, k1));
+                exited = true;
+            } else if b_value > k2 {
+                current.push(intersect(a, b, axis, k2));
+                exited = true;
+            }
+        }
+
+        if exited {
+            parts.push(std::mem::take(&mut current));
+        }
+    }
+
+    if let Some(last) = line.last() {
+        if last[axis] >= k1 && last[axis] <= k2 {
+            current.push(*last);
+        }
+    }
+    parts.push(current);
+
+    parts.retain(|part| part.len() >= 2);
+    parts
+}
+
+/// Clips a closed ring to the slab `k1 <= point[axis] <= k2` with the Sutherland–Hodgman
+/// algorithm. The result is closed again.
+fn clip_ring(ring: &[Point], axis: usize, k1: f64, k2: f64) -> Vec<Point> {
+    let clip_edge = |ring: &[Point], inside: &dyn Fn(&Point) -> bool, k: f64| -> Vec<Point> {
+        let mut result = Vec::with_capacity(ring.len());
+        for segment in ring.windows(2) {
+            let (a, b) = (&segment[0], &segment[1]);
+            match (inside(a), inside(b)) {
+                (true, true) => result.push(*b),
+                (true, false) => result.push(intersect(a, b, axis, k)),
+                (false, true) => {
+                    result.push(intersect(a, b, axis, k));
+                    result.push(*b);
+                }
+                (false, false) => {}
+            }
+        }
+        if let Some(first) = result.first().cloned() {
+            result.push(first);
+        }
+        result
+    };
+
+    let ring = clip_edge(ring, &|point| point[axis] >= k1, k1);
+    clip_edge(&ring, &|point| point[axis] <= k2, k2)
+}
+
+/// Simplifies a line with the Douglas-Peucker algorithm.
+fn simplify(line: Vec<Point>, epsilon: f64) -> Vec<Point> {
+    if epsilon <= 0.0 || line.len() <= 2 {
+        return line;
+    }
+
+    LineString(line.into_iter().map(|[x, y]| Coordinate { x, y }).collect())
+        .simplify(&epsilon)
+        .0
+        .into_iter()
+        .map(|coordinate| [coordinate.x, coordinate.y])
+        .collect()
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn command(id: u32, count: usize) -> u32 {
+    (id & 0x7) | ((count as u32) << 3)
+}
+
+/// Encodes geometry commands with coordinates relative to the previous cursor position.
+#[derive(Default)]
+struct GeometryEncoder {
+    geometry: Vec<u32>,
+    cursor: (i32, i32),
+}
+
+impl GeometryEncoder {
+    fn push_point(&mut self, point: &Point) {
+        let (x, y) = (point[0].round() as i32, point[1].round() as i32);
+        self.geometry.push(zigzag(x - self.cursor.0));
+        self.geometry.push(zigzag(y - self.cursor.1));
+        self.cursor = (x, y);
+    }
+
+    fn push_path(&mut self, path: &[Point]) {
+        self.geometry.push(command(COMMAND_MOVE_TO, 1));
+        self.push_point(&path[0]);
+        self.geometry.push(command(COMMAND_LINE_TO, path.len() - 1));
+        for point in &path[1..] {
+            self.push_point(point);
+        }
+    }
+}
+
+fn encode_points(points: &[Point]) -> Vec<u32> {
+    let mut encoder = GeometryEncoder::default();
+    if !points.is_empty() {
+        encoder
+            .geometry
+            .push(command(COMMAND_MOVE_TO, points.len()));
+        for point in points {
+            encoder.push_point(point);
+        }
+    }
+    encoder.geometry
+}
+
+fn encode_lines(lines: &[Vec<Point>]) -> Vec<u32> {
+    let mut encoder = GeometryEncoder::default();
+    for line in lines {
+        encoder.push_path(line);
+    }
+    encoder.geometry
+}
+
+/// Signed area of a ring. In tile coordinates, where y points downwards, the area of clockwise
+/// rings is positive.
+fn signed_area(ring: &[Point]) -> f64 {
+    ring.windows(2)
+        .map(|segment| segment[0][0] * segment[1][1] - segment[1][0] * segment[0][1])
+        .sum::<f64>()
+        / 2.0
+}
+
+fn encode_polygons(polygons: &[Vec<Vec<Point>>]) -> Vec<u32> {
+    let mut encoder = GeometryEncoder::default();
+    for polygon in polygons {
+        for (i, ring) in polygon.iter().enumerate() {
+            // Vector tiles require clockwise exterior rings and counter-clockwise holes
+            let exterior = i == 0;
+            let mut ring = ring.clone();
+            if (signed_area(&ring) > 0.0) != exterior {
+                ring.reverse();
+            }
+
+            // The closing point is implied by the ClosePath command
+            encoder.push_path(&ring[..ring.len() - 1]);
+            encoder.geometry.push(command(COMMAND_CLOSE_PATH, 1));
+        }
+    }
+    encoder.geometry
+}
+
+/// Builds a vector tile layer and deduplicates the keys and values of properties.
+struct LayerEncoder {
+    layer: tile::Layer,
+    keys: HashMap<String, u32>,
+}
+
+impl LayerEncoder {
+    fn new(name: &str) -> Self {
+        Self {
+            layer: tile::Layer {
+                version: 2,
+                name: name.to_string(),
+                features: vec![],
+                keys: vec![],
+                values: vec![],
+                extent: Some(EXTENT_UINT),
+            },
+            keys: HashMap::new(),
+        }
+    }
+
+    fn add_feature(&mut self, feature: &Feature, geom_type: tile::GeomType, geometry: Vec<u32>) {
+        let mut tags = Vec::with_capacity(feature.properties.len() * 2);
+        for (key, value) in &feature.properties {
+            let layer = &mut self.layer;
+            let key_index = *self.keys.entry(key.clone()).or_insert_with(|| {
+                layer.keys.push(key.clone());
+                (layer.keys.len() - 1) as u32
+            });
+
+            let value_index = match layer.values.iter().position(|other| other == value) {
+                Some(index) => index,
+                None => {
+                    layer.values.push(value.clone());
+                    layer.values.len() - 1
+                }
+            };
+
+            tags.push(key_index);
+            tags.push(value_index as u32);
+        }
+
+        self.layer.features.push(tile::Feature {
+            id: feature.id,
+            tags,
+            r#type: Some(geom_type as i32),
+            geometry,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clip_line, clip_ring, GeoJsonSource};
+    use crate::coords::{WorldTileCoords, EXTENT};
+    use crate::text::feature::{geometry_paths, point_geometry, property_string};
+    use geozero::mvt::tile;
+    use serde_json::json;
+
+    #[test]
+    fn test_clip_line() {
+        let line = [[-1.0, 0.0], [0.5, 0.0], [2.0, 0.0], [0.5, 1.0]];
+        assert_eq!(
+            clip_line(&line, 0, 0.0, 1.0),
+            vec![
+                vec![[0.0, 0.0], [0.5, 0.0], [1.0, 0.0]],
+                vec![[1.0, 2.0 / 3.0], [0.5, 1.0]]
+            ]
+        );
+        assert!(clip_line(&line, 1, 2.0, 3.0).is_empty());
+    }
+
+    #[test]
+    fn test_clip_ring() {
+        let ring = [
+            [-1.0, 0.0],
+            [1.0, 0.0],
+            [1.0, 1.0],
+            [-1.0, 1.0],
+            [-1.0, 0.0],
+        ];
+        let clipped = clip_ring(&ring, 0, 0.0, 2.0);
+        assert_eq!(clipped.first(), clipped.last());
+        assert!(clipped.iter().all(|point| point[0] >= 0.0));
+        assert_eq!(clipped.len(), 5);
+    }
+
+    #[test]
+    fn test_tile_layer() {
+        let source = GeoJsonSource::parse(&json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": { "name": "Null Island" },
+                "geometry": { "type": "Point", "coordinates": [0.0, 0.0] }
+            }, {
+                "type": "Feature",
+                "properties": {},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[-10.0, -10.0], [10.0, -10.0], [10.0, 10.0], [-10.0, 10.0], [-10.0, -10.0]]]
+                }
+            }]
+        }))
+        .unwrap();
+
+        let layer = source.tile_layer(&WorldTileCoords { x: 0, y: 0, z: 0 }, "places");
+        assert_eq!(layer.name, "places");
+        assert_eq!(layer.features.len(), 2);
+        assert_eq!(point_geometry(&layer.features[0]), vec![[2048.0, 2048.0]]);
+        assert_eq!(
+            property_string(&layer, &layer.features[0], "name"),
+            Some("Null Island".to_string())
+        );
+        assert_eq!(
+            layer.features[1].r#type,
+            Some(tile::GeomType::Polygon as i32)
+        );
+
+        // The features are not part of the north-western tile at zoom level 2
+        let layer = source.tile_layer(&WorldTileCoords { x: 0, y: 0, z: 2 }, "places");
+        assert!(layer.features.is_empty());
+    }
+
+    #[test]
+    fn test_tile_buffer() {
+        let source = GeoJsonSource::parse(&json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": { "class": "primary" },
+                "geometry": { "type": "LineString", "coordinates": [[-20.0, 20.0], [20.0, 10.0]] }
+            }]
+        }))
+        .unwrap();
+
+        // The road crosses the edge between both tiles. Each of them keeps the part within its
+        // buffer, such that the road joins without a gap where the tiles are clipped.
+        let extent = EXTENT as f32;
+        let west = source.tile_layer(&WorldTileCoords { x: 0, y: 0, z: 1 }, "roads");
+        let west = geometry_paths(&west.features[0]);
+        assert!(west[0].iter().any(|point| point[0] > extent));
+
+        let east = source.tile_layer(&WorldTileCoords { x: 1, y: 0, z: 1 }, "roads");
+        let east = geometry_paths(&east.features[0]);
+        assert!(east[0].iter().any(|point| point[0] < 0.0));
+    }
+
+    fn vehicles(longitude: f64) -> GeoJsonSource {
+        GeoJsonSource::parse(&json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": { "name": "parked" },
+                "geometry": { "type": "Point", "coordinates": [0.0, 0.0] }
+            }, {
+                "type": "Feature",
+                "properties": { "name": "moving" },
+                "geometry": { "type": "Point", "coordinates": [longitude, 50.0] }
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_changes() {
+        let previous = vehicles(10.0);
+        assert!(vehicles(10.0).changes(&previous).is_empty());
+
+        let changes = vehicles(11.0).changes(&previous);
+        assert!(!changes.is_empty());
+        // Tiles around the previous and the current position of the moving vehicle are affected
+        assert!(changes.affects(&WorldTileCoords { x: 0, y: 0, z: 0 }));
+        assert!(changes.affects(&WorldTileCoords { x: 8, y: 5, z: 4 }));
+        // The tile of the parked vehicle is not
+        assert!(!changes.affects(&WorldTileCoords { x: 8, y: 8, z: 4 }));
+
+        // Removed features affect the tiles in which they were
+        let removed = GeoJsonSource::parse(&json!({ "type": "FeatureCollection", "features": [] }))
+            .unwrap()
+            .changes(&previous);
+        assert!(removed.affects(&WorldTileCoords { x: 8, y: 8, z: 4 }));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(GeoJsonSource::parse(&json!({ "features": [] })).is_err());
+        assert!(GeoJsonSource::parse(&json!({ "type": "Point" })).is_err());
+    }
+}
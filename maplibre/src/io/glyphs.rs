@@ -0,0 +1,240 @@
+//! Glyphs of font stacks as signed distance fields in the PBF format of the
+//! [style specification](https://maplibre.org/maplibre-gl-js-docs/style-spec/glyphs/). Font stacks
+//! are split into ranges of 256 Unicode code points, which are fetched from the `glyphs` URL
+//! template of the style once a label contains one of their characters. This way, large character
+//! sets like CJK are only fetched as far as they are used.
+
+use crate::error::Error;
+use prost::Message;
+use std::collections::HashMap;
+
+/// Number of code points within a range of glyphs.
+pub const GLYPH_RANGE_SIZE: u32 = 256;
+
+/// Font stack which is used if the `text-font` of a layer is not set.
+const DEFAULT_TEXT_FONT: [&str; 2] = ["Open Sans Regular", "Arial Unicode MS Regular"];
+
+/// Glyphs of a PBF, which may contain several font stacks.
+#[derive(Clone, PartialEq, Message)]
+struct Glyphs {
+    #[prost(message, repeated, tag = "1")]
+    stacks: Vec<Fontstack>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Fontstack {
+    #[prost(string, required, tag = "1")]
+    name: String,
+    #[prost(string, required, tag = "2")]
+    range: String,
+    #[prost(message, repeated, tag = "3")]
+    glyphs: Vec<SdfGlyph>,
+}
+
+/// A glyph which is rendered as signed distance field with a font size of 24 pixels and a buffer
+/// of 3 pixels around it.
+#[derive(Clone, PartialEq, Message)]
+pub struct SdfGlyph {
+    /// Unicode code point of the glyph.
+    #[prost(uint32, required, tag = "1")]
+    pub id: u32,
+    /// The distance field of `width + 6` times `height + 6` pixels. Glyphs without a bitmap, like
+    /// spaces, only advance the pen.
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub bitmap: Option<Vec<u8>>,
+    #[prost(uint32, required, tag = "3")]
+    pub width: u32,
+    #[prost(uint32, required, tag = "4")]
+    pub height: u32,
+    /// Offset from the pen position to the left edge of the glyph.
+    #[prost(sint32, required, tag = "5")]
+    pub left: i32,
+    /// Offset from the top of the em box, 24 pixels above the baseline, to the top edge of the
+    /// glyph, pointing upwards.
+    #[prost(sint32, required, tag = "6")]
+    pub top: i32,
+    /// Horizontal distance to the next pen position.
+    #[prost(uint32, required, tag = "7")]
+    pub advance: u32,
+}
+
+/// Returns the name of the font stack `text_font`, which is the value of `{fontstack}` in the
+/// `glyphs` URL template.
+pub fn fontstack_name(text_font: Option<&[String]>) -> String {
+    match text_font {
+        Some(text_font) if !text_font.is_empty() => text_font.join(","),
+        _ => DEFAULT_TEXT_FONT.join(","),
+    }
+}
+
+/// Returns the first code point of the range which contains `character`.
+pub fn glyph_range(character: char) -> u32 {
+    character as u32 / GLYPH_RANGE_SIZE * GLYPH_RANGE_SIZE
+}
+
+/// Replaces the place holders `{fontstack}` and `{range}` of the `glyphs` URL `template` for the
+/// range of glyphs which starts at `range`.
+pub fn glyphs_url(template: &str, fontstack: &str, range: u32) -> String {
+    template
+        .replace("{fontstack}", &fontstack.replace(' ', "%20"))
+        .replace(
+            "{range}",
+            &format!("{}-{}", range, range + GLYPH_RANGE_SIZE - 1),
+        )
+}
+
+/// Parses the glyphs of all font stacks within a glyph PBF.
+pub fn parse_glyphs(data: &[u8]) -> Result<Vec<SdfGlyph>, Error> {
+    let glyphs = Glyphs::decode(data).map_err(|e| Error::Font(e.to_string()))?;
+    Ok(glyphs
+        .stacks
+        .into_iter()
+        .flat_map(|stack| stack.glyphs)
+        .collect())
+}
+
+enum RangeState {
+    /// A label needs the range, but it has not been requested yet.
+    Wanted,
+    Requested,
+    /// The glyphs of the range arrived, but have not been added to the glyph atlas yet.
+    Loaded(Vec<SdfGlyph>),
+    /// The glyphs of the range are in the glyph atlas, or the range is unavailable.
+    Added,
+}
+
+/// Tracks the ranges of glyphs of each font stack from the moment a label needs them until they
+/// are added to the glyph atlas.
+#[derive(Default)]
+pub struct GlyphRanges {
+    ranges: HashMap<(String, u32), RangeState>,
+}
+
+impl GlyphRanges {
+    /// Whether the range which contains `character` has been loaded for `fontstack`. Ranges which
+    /// are unavailable count as loaded, such that their labels fall back to the configured font.
+    pub fn is_loaded(&self, fontstack: &str, character: char) -> bool {
+        matches!(
+            self.ranges
+                .get(&(fontstack.to_string(), glyph_range(character))),
+            Some(RangeState::Loaded(_) | RangeState::Added)
+        )
+    }
+
+    /// Marks the range starting at `range` as needed by a label, unless it is known already.
+    pub fn want(&mut self, fontstack: String, range: u32) {
+        self.ranges
+            .entry((fontstack, range))
+            .or_insert(RangeState::Wanted);
+    }
+
+    /// Returns the ranges which are needed but have not been requested yet and marks them as
+    /// requested.
+    pub fn take_wanted(&mut self) -> Vec<(String, u32)> {
+        let mut wanted = Vec::new();
+        for (key, state) in &mut self.ranges {
+            if let RangeState::Wanted = state {
+                *state = RangeState::Requested;
+                wanted.push(key.clone());
+            }
+        }
+        wanted
+    }
+
+    /// Stores the `glyphs` of a fetched range. Unavailable ranges have no glyphs.
+    pub fn put(&mut self, fontstack: String, range: u32, glyphs: Vec<SdfGlyph>) {
+        self.ranges
+            .insert((fontstack, range), RangeState::Loaded(glyphs));
+    }
+
+    /// Returns the glyphs of the ranges which have been loaded since the last call by their font
+    /// stack.
+    pub fn take_loaded(&mut self) -> Vec<(String, Vec<SdfGlyph>)> {
+        let mut loaded = Vec::new();
+        for ((fontstack, _), state) in &mut self.ranges {
+            if let RangeState::Loaded(glyphs) = state {
+                loaded.push((fontstack.clone(), std::mem::take(glyphs)));
+                *state = RangeState::Added;
+            }
+        }
+        loaded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fontstack_name, glyph_range, glyphs_url, parse_glyphs, Fontstack, GlyphRanges, Glyphs,
+        SdfGlyph,
+    };
+    use prost::Message;
+
+    #[test]
+    fn test_glyphs_url() {
+        assert_eq!(glyph_range('A'), 0);
+        assert_eq!(glyph_range('東'), 0x6700);
+        let fontstack = fontstack_name(Some(&["Noto Sans CJK JP Regular".to_string()]));
+        assert_eq!(
+            glyphs_url(
+                "https://example.com/fonts/{fontstack}/{range}.pbf",
+                &fontstack,
+                glyph_range('東')
+            ),
+            "https://example.com/fonts/Noto%20Sans%20CJK%20JP%20Regular/26368-26623.pbf"
+        );
+        assert_eq!(
+            fontstack_name(None),
+            "Open Sans Regular,Arial Unicode MS Regular"
+        );
+    }
+
+    #[test]
+    fn test_parse_glyphs() {
+        let glyph = SdfGlyph {
+            id: '東' as u32,
+            bitmap: Some(vec![128; 10 * 12]),
+            width: 4,
+            height: 6,
+            left: 1,
+            top: -5,
+            advance: 24,
+        };
+        let data = Glyphs {
+            stacks: vec![Fontstack {
+                name: "Noto Sans CJK JP Regular".to_string(),
+                range: "26368-26623".to_string(),
+                glyphs: vec![glyph.clone()],
+            }],
+        }
+        .encode_to_vec();
+
+        assert_eq!(parse_glyphs(&data).unwrap(), vec![glyph]);
+        assert!(parse_glyphs(&[0x0a, 0xff]).is_err());
+    }
+
+    #[test]
+    fn test_glyph_ranges() {
+        let mut ranges = GlyphRanges::default();
+        let fontstack = fontstack_name(None);
+
+        assert!(!ranges.is_loaded(&fontstack, '東'));
+        ranges.want(fontstack.clone(), glyph_range('東'));
+        ranges.want(fontstack.clone(), glyph_range('東'));
+        assert_eq!(
+            ranges.take_wanted(),
+            vec![(fontstack.clone(), glyph_range('東'))]
+        );
+        assert!(ranges.take_wanted().is_empty());
+
+        // Unavailable ranges are loaded without glyphs
+        ranges.put(fontstack.clone(), glyph_range('東'), Vec::new());
+        assert!(ranges.is_loaded(&fontstack, '東'));
+        assert_eq!(ranges.take_loaded(), vec![(fontstack.clone(), Vec::new())]);
+        assert!(ranges.take_loaded().is_empty());
+        assert!(ranges.is_loaded(&fontstack, '東'));
+
+        // Ranges are requested only once
+        ranges.want(fontstack, glyph_range('東'));
+        assert!(ranges.take_wanted().is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+//! Reads tiles from an [MBTiles](https://github.com/mapbox/mbtiles-spec) SQLite database.
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::compression::decompress_if_gzip;
+use crate::style::source::TileAddressingScheme;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        Error::Mbtiles(error.to_string())
+    }
+}
+
+/// Serves tiles from an MBTiles file. Clones share the same database connection.
+#[derive(Clone)]
+pub struct MbtilesSource {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl MbtilesSource {
+    /// Opens the MBTiles file at `path` read-only.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Returns the decompressed tile at `coords`. MBTiles stores tiles in the TMS scheme, so the
+    /// y-axis is flipped. Returns `None` if the tile is missing.
+    pub fn get_tile(&self, coords: &WorldTileCoords) -> Result<Option<Vec<u8>>, Error> {
+        let tile_coords = coords
+            .into_tile(TileAddressingScheme::TMS)
+            .ok_or_else(|| Error::Mbtiles(format!("invalid tile coordinates {}", coords)))?;
+
+        let connection = self.connection.lock().unwrap();
+        // language=SQL
+        let tile_data = connection
+            .query_row(
+                "SELECT tile_data FROM tiles
+                        WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3;",
+                params![tile_coords.z, tile_coords.x, tile_coords.y],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+
+        tile_data.map(decompress_if_gzip).transpose()
+    }
+
+    /// Reads the key/value pairs of the metadata table.
+    pub fn metadata(&self) -> Result<HashMap<String, String>, Error> {
+        let connection = self.connection.lock().unwrap();
+        // language=SQL
+        let mut statement = connection.prepare("SELECT name, value FROM metadata;")?;
+        let metadata = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(metadata)
+    }
+
+    /// Returns the longitude, latitude and zoom of the `center` metadata entry. This can be used
+    /// to initialize the camera.
+    pub fn center(&self) -> Result<Option<(f64, f64, f64)>, Error> {
+        Ok(self
+            .metadata()?
+            .get("center")
+            .and_then(|center| parse_center(center)))
+    }
+}
+
+/// Parses a center of the form `longitude,latitude,zoom`.
+fn parse_center(center: &str) -> Option<(f64, f64, f64)> {
+    let values = center
+        .split(',')
+        .map(|value| value.trim().parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    match values.as_slice() {
+        [lon, lat, zoom] => Some((*lon, *lat, *zoom)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_center, MbtilesSource};
+    use crate::coords::WorldTileCoords;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use rusqlite::{params, Connection};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    /// Creates a small MBTiles file with a single gzip compressed tile at `z=1, x=0, y=1` (XYZ).
+    fn fixture(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("maplibre-{}.mbtiles", name));
+        let _ = std::fs::remove_file(&path);
+
+        let connection = Connection::open(&path).unwrap();
+        // language=SQL
+        connection
+            .execute_batch(
+                "CREATE TABLE metadata (name TEXT, value TEXT);
+                CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+                INSERT INTO metadata VALUES ('name', 'fixture'), ('center', '11.58,48.14,12');",
+            )
+            .unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"tile").unwrap();
+        connection
+            .execute(
+                "INSERT INTO tiles VALUES (?1, ?2, ?3, ?4);",
+                params![1, 0, 0, encoder.finish().unwrap()],
+            )
+            .unwrap();
+
+        path
+    }
+
+    #[test]
+    fn test_get_tile() {
+        let source = MbtilesSource::open(fixture("get-tile")).unwrap();
+
+        assert_eq!(
+            source
+                .get_tile(&WorldTileCoords { x: 0, y: 1, z: 1 })
+                .unwrap(),
+            Some(b"tile".to_vec())
+        );
+        assert_eq!(
+            source
+                .get_tile(&WorldTileCoords { x: 0, y: 0, z: 1 })
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_metadata() {
+        let source = MbtilesSource::open(fixture("metadata")).unwrap();
+
+        assert_eq!(
+            source.metadata().unwrap().get("name"),
+            Some(&"fixture".to_string())
+        );
+        assert_eq!(source.center().unwrap(), Some((11.58, 48.14, 12.0)));
+    }
+
+    #[test]
+    fn test_parse_center() {
+        assert_eq!(parse_center("1, 2, 3"), Some((1.0, 2.0, 3.0)));
+        assert_eq!(parse_center("1,2"), None);
+        assert_eq!(parse_center("a,b,c"), None);
+    }
+}
@@ -2,21 +2,42 @@
 
 use crate::coords::WorldTileCoords;
 
-use crate::tessellation::{IndexDataType, OverAlignedVertexBuffer};
+use crate::tessellation::{IndexDataType, LineStyle, OverAlignedVertexBuffer};
 
+use crate::io::glyphs::SdfGlyph;
+use crate::io::tile_json::TileJSON;
 use crate::render::ShaderVertex;
 use geozero::mvt::tile;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 pub mod scheduler;
 pub mod source_client;
 pub mod static_tile_fetcher;
 
+pub mod compression;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod disk_cache;
+pub mod feature_query;
+pub mod geojson_source;
 pub mod geometry_index;
+pub mod glyphs;
+#[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+pub mod mbtiles;
+pub mod pmtiles;
+pub mod preload;
+pub mod request_limiter;
+pub mod request_transform;
 pub mod shared_thread_state;
+pub mod sprite;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tessellation_cache;
+pub mod tessellation_pool;
 pub mod tile_cache;
+pub mod tile_json;
+pub mod tile_reader;
 pub mod tile_request_state;
+pub mod tile_url;
 
 /// Contains a `Tile` if the fetch was successful otherwise `Unavailable`.
 pub enum TileFetchResult {
@@ -42,10 +63,39 @@ impl fmt::Debug for TileFetchResult {
     }
 }
 
-/// [crate::io::TileTessellateMessage] or [crate::io::LayerTessellateMessage] tessellation message.
+/// [crate::io::TileTessellateMessage], [crate::io::LayerTessellateMessage] tessellation message,
+/// a decoded [crate::io::RasterTileMessage], a fetched [crate::io::TileJsonMessage] or
+/// [crate::io::GlyphsMessage], or a [crate::io::SourceErrorMessage].
 pub enum TessellateMessage {
     Tile(TileTessellateMessage),
+    GeoJsonTile(GeoJsonTileMessage),
     Layer(LayerTessellateMessage),
+    Raster(RasterTileMessage),
+    TileJson(TileJsonMessage),
+    Glyphs(GlyphsMessage),
+    SourceError(SourceErrorMessage),
+}
+
+/// A tile of a source could not be fetched or decoded.
+pub struct SourceErrorMessage {
+    /// `None` for the default tile source.
+    pub source: Option<String>,
+    pub coords: WorldTileCoords,
+    pub message: String,
+}
+
+/// The TileJSON document behind `url`. `None` if it could not be fetched or parsed.
+pub struct TileJsonMessage {
+    pub url: String,
+    pub tile_json: Option<TileJSON>,
+}
+
+/// The glyphs of the range of `fontstack` which starts at `range`. `None` if the range could not
+/// be fetched or parsed.
+pub struct GlyphsMessage {
+    pub fontstack: String,
+    pub range: u32,
+    pub glyphs: Option<Vec<SdfGlyph>>,
 }
 
 ///  The result of the tessellation of a tile.
@@ -54,15 +104,24 @@ pub struct TileTessellateMessage {
     pub coords: WorldTileCoords,
 }
 
+/// Signals that all layers of a GeoJSON source at the given coordinates have been tessellated.
+pub struct GeoJsonTileMessage {
+    pub coords: WorldTileCoords,
+    pub source: String,
+}
+
 /// `TessellatedLayer` contains the result of the tessellation for a specific layer, otherwise
-/// `UnavailableLayer` if the layer doesn't exist.
+/// `UnavailableLayer` if the layer doesn't exist. Layers of different sources may share a name, so
+/// each message names the source it belongs to, `None` for the default tile source.
 pub enum LayerTessellateMessage {
     UnavailableLayer {
         coords: WorldTileCoords,
+        source: Option<String>,
         layer_name: String,
     },
     TessellatedLayer {
         coords: WorldTileCoords,
+        source: Option<String>,
         buffer: OverAlignedVertexBuffer<ShaderVertex, IndexDataType>,
         /// Holds for each feature the count of indices.
         feature_indices: Vec<u32>,
@@ -90,13 +149,72 @@ impl LayerTessellateMessage {
             LayerTessellateMessage::TessellatedLayer { layer_data, .. } => &layer_data.name,
         }
     }
+
+    pub fn source(&self) -> Option<&str> {
+        match self {
+            LayerTessellateMessage::UnavailableLayer { source, .. } => source.as_deref(),
+            LayerTessellateMessage::TessellatedLayer { source, .. } => source.as_deref(),
+        }
+    }
+}
+
+/// Format of the decoded pixels of a [`RasterTileMessage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterFormat {
+    /// Four bytes per pixel in RGBA order.
+    Rgba8,
+    /// One native endian `f32` per pixel, the elevation in meters of a raster DEM source.
+    Elevation,
+}
+
+/// `Raster` contains the decoded pixels of a raster tile of a specific source, otherwise
+/// `UnavailableRaster` if the tile could not be fetched or decoded.
+pub enum RasterTileMessage {
+    UnavailableRaster {
+        coords: WorldTileCoords,
+        source: String,
+    },
+    Raster {
+        coords: WorldTileCoords,
+        source: String,
+        width: u32,
+        height: u32,
+        format: RasterFormat,
+        data: Box<[u8]>,
+    },
+}
+
+impl fmt::Debug for RasterTileMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RasterTileMessage{}", self.get_coords())
+    }
+}
+
+impl RasterTileMessage {
+    pub fn get_coords(&self) -> WorldTileCoords {
+        match self {
+            RasterTileMessage::UnavailableRaster { coords, .. } => *coords,
+            RasterTileMessage::Raster { coords, .. } => *coords,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        match self {
+            RasterTileMessage::UnavailableRaster { source, .. } => source.as_str(),
+            RasterTileMessage::Raster { source, .. } => source.as_str(),
+        }
+    }
 }
 
 /// A request for a tile at the given coordinates and in the given layers.
 #[derive(Clone)]
 pub struct TileRequest {
     pub coords: WorldTileCoords,
+    /// Id of the vector source in the style. `None` for the default tile source.
+    pub source: Option<String>,
     pub layers: HashSet<String>,
+    /// How the lines of the layers are tessellated. Layers without an entry use the default.
+    pub line_styles: HashMap<String, LineStyle>,
 }
 
 impl fmt::Debug for TileRequest {
@@ -0,0 +1,449 @@
+//! Reads tiles from a single [PMTiles](https://github.com/protomaps/PMTiles) (version 3) archive.
+//!
+//! An archive starts with a fixed size header which points to a root directory. Directories map
+//! tile ids, which are derived from the tile coordinates with a Hilbert curve, to byte ranges of
+//! either tiles or leaf directories. Only the byte ranges which are needed are read, either from
+//! a local file or via HTTP range requests.
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::compression::{brotli_decompress, decompress_if_gzip, gunzip};
+use crate::io::source_client::HTTPClient;
+use crate::style::source::TileAddressingScheme;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+const HEADER_SIZE: u64 = 127;
+const MAGIC: &[u8] = b"PMTiles";
+const SUPPORTED_VERSION: u8 = 3;
+/// The spec limits the depth of leaf directories.
+const MAX_DIRECTORY_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Unknown,
+    None,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl From<u8> for Compression {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Compression::None,
+            2 => Compression::Gzip,
+            3 => Compression::Brotli,
+            4 => Compression::Zstd,
+            _ => Compression::Unknown,
+        }
+    }
+}
+
+/// The fixed size header at the start of an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub root_directory: Range<u64>,
+    pub metadata: Range<u64>,
+    pub leaf_directories_offset: u64,
+    pub tile_data_offset: u64,
+    pub internal_compression: Compression,
+    pub tile_compression: Compression,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    pub center_zoom: u8,
+    /// Longitude and latitude of the center in degrees.
+    pub center: (f64, f64),
+}
+
+impl Header {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE as usize || &bytes[0..7] != MAGIC {
+            return Err(Error::PmTiles("not a PMTiles archive".to_string()));
+        }
+
+        if bytes[7] != SUPPORTED_VERSION {
+            return Err(Error::PmTiles(format!(
+                "unsupported PMTiles version {}",
+                bytes[7]
+            )));
+        }
+
+        let u64_at = |offset: usize| {
+            let mut value = [0u8; 8];
+            value.copy_from_slice(&bytes[offset..offset + 8]);
+            u64::from_le_bytes(value)
+        };
+        let degrees_at = |offset: usize| {
+            let mut value = [0u8; 4];
+            value.copy_from_slice(&bytes[offset..offset + 4]);
+            i32::from_le_bytes(value) as f64 / 10_000_000.0
+        };
+        let range_at = |offset: usize| u64_at(offset)..u64_at(offset) + u64_at(offset + 8);
+
+        Ok(Self {
+            root_directory: range_at(8),
+            metadata: range_at(24),
+            leaf_directories_offset: u64_at(40),
+            tile_data_offset: u64_at(56),
+            internal_compression: bytes[97].into(),
+            tile_compression: bytes[98].into(),
+            min_zoom: bytes[100],
+            max_zoom: bytes[101],
+            center_zoom: bytes[118],
+            center: (degrees_at(119), degrees_at(123)),
+        })
+    }
+}
+
+/// An entry of a directory. A run length of zero marks a leaf directory, otherwise the entry
+/// points to the data of `run_length` consecutive tiles which share the same content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub tile_id: u64,
+    pub offset: u64,
+    pub length: u64,
+    pub run_length: u64,
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> Result<u64, Error> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes
+            .get(*position)
+            .ok_or_else(|| Error::PmTiles("unexpected end of directory".to_string()))?;
+        *position += 1;
+
+        if shift >= 64 {
+            return Err(Error::PmTiles("varint is too long".to_string()));
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+/// Parses a decompressed directory. Tile ids are delta encoded and offsets of consecutive entries
+/// are omitted.
+pub fn parse_directory(bytes: &[u8]) -> Result<Vec<Entry>, Error> {
+    let mut position = 0;
+    let count = read_varint(bytes, &mut position)? as usize;
+    let mut entries = vec![
+        Entry {
+            tile_id: 0,
+            offset: 0,
+            length: 0,
+            run_length: 0,
+        };
+        count
+    ];
+
+    let mut last_id = 0;
+    for entry in entries.iter_mut() {
+        last_id += read_varint(bytes, &mut position)?;
+        entry.tile_id = last_id;
+    }
+    for entry in entries.iter_mut() {
+        entry.run_length = read_varint(bytes, &mut position)?;
+    }
+    for entry in entries.iter_mut() {
+        entry.length = read_varint(bytes, &mut position)?;
+    }
+    for i in 0..count {
+        let value = read_varint(bytes, &mut position)?;
+        entries[i].offset = if value == 0 && i > 0 {
+            entries[i - 1].offset + entries[i - 1].length
+        } else {
+            value.saturating_sub(1)
+        };
+    }
+
+    Ok(entries)
+}
+
+/// Finds the entry which contains `tile_id` with a binary search. This is either the entry of the
+/// tile or of the leaf directory which might contain the tile.
+pub fn find_entry(entries: &[Entry], tile_id: u64) -> Option<&Entry> {
+    let index = match entries.binary_search_by_key(&tile_id, |entry| entry.tile_id) {
+        Ok(index) => index,
+        Err(0) => return None,
+        Err(index) => index - 1,
+    };
+
+    let entry = &entries[index];
+    if entry.run_length == 0 || tile_id - entry.tile_id < entry.run_length {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+/// Converts tile coordinates to the position of the tile on a Hilbert curve. The tiles of lower
+/// zoom levels are counted first.
+pub fn tile_id(z: u8, x: u32, y: u32) -> u64 {
+    let base = ((1u64 << (2 * z as u64)) - 1) / 3;
+
+    let n = 1u64 << z;
+    let (mut x, mut y) = (x as u64, y as u64);
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = ((x & s) > 0) as u64;
+        let ry = ((y & s) > 0) as u64;
+        d += s * s * ((3 * rx) ^ ry);
+
+        // Rotate the quadrant
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+
+    base + d
+}
+
+fn decompress(data: Vec<u8>, compression: Compression) -> Result<Vec<u8>, Error> {
+    match compression {
+        Compression::None | Compression::Unknown => Ok(data),
+        Compression::Gzip => gunzip(&data),
+        Compression::Brotli => brotli_decompress(&data),
+        compression => Err(Error::PmTiles(format!(
+            "unsupported compression {:?}",
+            compression
+        ))),
+    }
+}
+
+/// Location of an archive.
+#[derive(Debug, Clone)]
+pub enum PmTilesLocation {
+    /// The archive is read with HTTP range requests.
+    Url(String),
+    #[cfg(not(target_arch = "wasm32"))]
+    File(std::path::PathBuf),
+}
+
+/// Serves tiles from a PMTiles archive. The header and the directories are cached after they have
+/// been read once. Clones share the cache.
+#[derive(Clone)]
+pub struct PmTilesArchive<HC>
+where
+    HC: HTTPClient,
+{
+    location: PmTilesLocation,
+    http_client: HC,
+    header: Arc<Mutex<Option<Header>>>,
+    directories: Arc<Mutex<HashMap<u64, Arc<Vec<Entry>>>>>,
+}
+
+impl<HC> PmTilesArchive<HC>
+where
+    HC: HTTPClient,
+{
+    pub fn new(location: PmTilesLocation, http_client: HC) -> Self {
+        Self {
+            location,
+            http_client,
+            header: Default::default(),
+            directories: Default::default(),
+        }
+    }
+
+    pub fn http_client(&self) -> &HC {
+        &self.http_client
+    }
+
+    async fn read_range(&self, range: Range<u64>) -> Result<Vec<u8>, Error> {
+        match &self.location {
+            PmTilesLocation::Url(url) => self.http_client.fetch_range(url, range).await,
+            #[cfg(not(target_arch = "wasm32"))]
+            PmTilesLocation::File(path) => {
+                use std::io::{Read, Seek, SeekFrom};
+
+                let mut file =
+                    std::fs::File::open(path).map_err(|e| Error::PmTiles(e.to_string()))?;
+                file.seek(SeekFrom::Start(range.start))
+                    .map_err(|e| Error::PmTiles(e.to_string()))?;
+                let mut data = vec![0; (range.end - range.start) as usize];
+                file.read_exact(&mut data)
+                    .map_err(|e| Error::PmTiles(e.to_string()))?;
+                Ok(data)
+            }
+        }
+    }
+
+    pub async fn header(&self) -> Result<Header, Error> {
+        if let Some(header) = self.header.lock().unwrap().as_ref() {
+            return Ok(header.clone());
+        }
+
+        let header = Header::parse(&self.read_range(0..HEADER_SIZE).await?)?;
+        *self.header.lock().unwrap() = Some(header.clone());
+        Ok(header)
+    }
+
+    async fn directory(
+        &self,
+        header: &Header,
+        range: Range<u64>,
+    ) -> Result<Arc<Vec<Entry>>, Error> {
+        if let Some(directory) = self.directories.lock().unwrap().get(&range.start) {
+            return Ok(directory.clone());
+        }
+
+        let data = decompress(
+            self.read_range(range.clone()).await?,
+            header.internal_compression,
+        )?;
+        let directory = Arc::new(parse_directory(&data)?);
+        self.directories
+            .lock()
+            .unwrap()
+            .insert(range.start, directory.clone());
+        Ok(directory)
+    }
+
+    /// Reads and decompresses the tile at `coords`. Returns `None` if the archive does not
+    /// contain the tile.
+    pub async fn get_tile(&self, coords: &WorldTileCoords) -> Result<Option<Vec<u8>>, Error> {
+        let tile_coords = coords
+            .into_tile(TileAddressingScheme::XYZ)
+            .ok_or_else(|| Error::PmTiles(format!("invalid tile coordinates {}", coords)))?;
+        let header = self.header().await?;
+
+        if tile_coords.z < header.min_zoom || tile_coords.z > header.max_zoom {
+            return Ok(None);
+        }
+
+        let tile_id = tile_id(tile_coords.z, tile_coords.x, tile_coords.y);
+        let mut directory_range = header.root_directory.clone();
+
+        for _ in 0..MAX_DIRECTORY_DEPTH {
+            let directory = self.directory(&header, directory_range).await?;
+            let entry = match find_entry(&directory, tile_id) {
+                Some(entry) => *entry,
+                None => return Ok(None),
+            };
+
+            if entry.run_length > 0 {
+                let start = header.tile_data_offset + entry.offset;
+                let data = self.read_range(start..start + entry.length).await?;
+                // Some archives store gzip compressed tiles without declaring a compression
+                return decompress(data, header.tile_compression)
+                    .and_then(decompress_if_gzip)
+                    .map(Some);
+            }
+
+            let start = header.leaf_directories_offset + entry.offset;
+            directory_range = start..start + entry.length;
+        }
+
+        Err(Error::PmTiles(
+            "leaf directories are nested too deep".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_entry, parse_directory, tile_id, Entry, Header};
+
+    fn encode_varint(mut value: u64, bytes: &mut Vec<u8>) {
+        while value >= 0x80 {
+            bytes.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+    }
+
+    #[test]
+    fn test_tile_id() {
+        assert_eq!(tile_id(0, 0, 0), 0);
+        assert_eq!(tile_id(1, 0, 0), 1);
+        assert_eq!(tile_id(1, 0, 1), 2);
+        assert_eq!(tile_id(1, 1, 1), 3);
+        assert_eq!(tile_id(1, 1, 0), 4);
+        assert_eq!(tile_id(2, 0, 0), 5);
+    }
+
+    #[test]
+    fn test_parse_directory() {
+        let mut bytes = Vec::new();
+        // Three entries with the tile ids 0, 1 and 300
+        for value in [3, 0, 1, 299] {
+            encode_varint(value, &mut bytes);
+        }
+        // Run lengths, the last entry is a leaf directory
+        for value in [1, 2, 0] {
+            encode_varint(value, &mut bytes);
+        }
+        // Lengths
+        for value in [10, 20, 500] {
+            encode_varint(value, &mut bytes);
+        }
+        // Offsets, the second one is consecutive to the first one
+        for value in [1, 0, 1001] {
+            encode_varint(value, &mut bytes);
+        }
+
+        let entries = parse_directory(&bytes).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    tile_id: 0,
+                    offset: 0,
+                    length: 10,
+                    run_length: 1
+                },
+                Entry {
+                    tile_id: 1,
+                    offset: 10,
+                    length: 20,
+                    run_length: 2
+                },
+                Entry {
+                    tile_id: 300,
+                    offset: 1000,
+                    length: 500,
+                    run_length: 0
+                },
+            ]
+        );
+
+        assert_eq!(find_entry(&entries, 0).map(|entry| entry.tile_id), Some(0));
+        assert_eq!(find_entry(&entries, 2).map(|entry| entry.tile_id), Some(1));
+        assert_eq!(find_entry(&entries, 3), None);
+        assert_eq!(
+            find_entry(&entries, 1000).map(|entry| entry.tile_id),
+            Some(300)
+        );
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let mut bytes = vec![0u8; 127];
+        bytes[0..7].copy_from_slice(b"PMTiles");
+        bytes[7] = 3;
+        bytes[8..16].copy_from_slice(&127u64.to_le_bytes());
+        bytes[16..24].copy_from_slice(&50u64.to_le_bytes());
+        bytes[97] = 2;
+        bytes[101] = 14;
+
+        let header = Header::parse(&bytes).unwrap();
+        assert_eq!(header.root_directory, 127..177);
+        assert_eq!(header.internal_compression, super::Compression::Gzip);
+        assert_eq!(header.max_zoom, 14);
+
+        bytes[7] = 2;
+        assert!(Header::parse(&bytes).is_err());
+    }
+}
@@ -0,0 +1,356 @@
+//! Preloads the tiles of an area across a range of zoom levels without drawing them, which is the
+//! basis of offline map packs. Tiles which are fetched via HTTP are stored in the disk cache of the
+//! source client, see [`crate::io::source_client::SourceClient::with_disk_cache`], and the map
+//! reads them from there once it shows the area. Without a disk cache, e.g. on the web, only the
+//! HTTP cache of the browser keeps them.
+//!
+//! Preloads share the fetch limit of [`crate::io::request_limiter::RequestLimiter`] with the tiles
+//! in view, so they never open more connections than the map itself.
+
+use crate::coords::{LatLon, WorldTileCoords, Zoom, MAX_ZOOM, TILE_SIZE, ZOOM_BOUNDS};
+use crate::error::Error;
+use crate::io::scheduler::ScheduleMethod;
+use crate::io::shared_thread_state::SharedThreadState;
+use crate::io::source_client::{HTTPClient, SourceClient};
+use crate::io::tile_json::TileJSON;
+use crate::style::source::Source;
+use crate::style::Style;
+use std::collections::{BTreeSet, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Returns the tiles at zoom level `z` which intersect `bounds`, given as
+/// `[west, south, east, north]` in degrees. Bounds with `west > east` cross the antimeridian.
+pub fn tiles_in_bounds(bounds: [f64; 4], z: u8) -> impl Iterator<Item = WorldTileCoords> {
+    let [west, south, east, north] = bounds;
+    let east = if east < west { east + 360.0 } else { east };
+
+    let zoom = Zoom::new(z as f64);
+    let north_west = LatLon::new(north, west).into_world(zoom);
+    let south_east = LatLon::new(south, east).into_world(zoom);
+
+    let tiles = ZOOM_BOUNDS[z as usize] as i64;
+    let min_x = (north_west.x / TILE_SIZE).floor() as i64;
+    let max_x = ((south_east.x / TILE_SIZE).ceil() as i64 - 1).clamp(min_x, min_x + tiles - 1);
+    let min_y = ((north_west.y / TILE_SIZE).floor() as i64).clamp(0, tiles - 1);
+    let max_y = ((south_east.y / TILE_SIZE).ceil() as i64 - 1).clamp(min_y, tiles - 1);
+
+    (min_y..=max_y).flat_map(move |y| {
+        (min_x..=max_x).map(move |x| WorldTileCoords {
+            x: x.rem_euclid(tiles) as i32,
+            y: y as i32,
+            z,
+        })
+    })
+}
+
+/// The tiles of a source which are preloaded.
+pub(crate) struct PreloadSource {
+    /// `None` for the default tile server.
+    tile_json: Option<Arc<TileJSON>>,
+    /// Whether the tiles are fetched as raster tiles.
+    raster: bool,
+    tiles: Vec<WorldTileCoords>,
+}
+
+/// Returns the sources which the layers of `style` draw, together with their tiles in `bounds`
+/// from `min_zoom` to `max_zoom`. Above the maximum zoom level of a source, its tiles are replaced
+/// by their ancestors at that level. Sources whose TileJSON is not fetched yet are skipped.
+pub(crate) fn preload_sources(
+    style: &Style,
+    bounds: [f64; 4],
+    min_zoom: u8,
+    max_zoom: u8,
+    device_pixel_ratio: f64,
+) -> Vec<PreloadSource> {
+    let max_zoom = max_zoom.min(MAX_ZOOM as u8 - 1);
+
+    let source_ids = style
+        .layers
+        .iter()
+        .filter_map(|layer| match style.tile_source_id(layer) {
+            Some(id) => Some(Some(id)),
+            // Only layers with a source layer draw tiles of the default tile server
+            None => layer.source_layer.as_ref().map(|_| None),
+        })
+        .collect::<BTreeSet<_>>();
+
+    source_ids
+        .into_iter()
+        .filter_map(|id| {
+            let (source, tile_json, raster) = match id.and_then(|id| style.sources.get(id)) {
+                None => (None, None, false),
+                Some(Source::Vector(source)) => (Some(source), source.resolved_tile_json(), false),
+                Some(Source::Raster(source)) => (
+                    Some(source),
+                    source
+                        .resolved_tile_json()
+                        .map(|tile_json| tile_json.with_pixel_ratio(device_pixel_ratio)),
+                    true,
+                ),
+                Some(Source::RasterDem(source)) => {
+                    (Some(source), source.resolved_tile_json(), true)
+                }
+                Some(Source::GeoJson(_)) => return None,
+            };
+            if source.is_some() && tile_json.is_none() {
+                log::warn!("the TileJSON of source {:?} is not loaded yet", id);
+                return None;
+            }
+
+            let mut seen = HashSet::new();
+            let tiles = (min_zoom..=max_zoom)
+                .flat_map(|z| tiles_in_bounds(bounds, z))
+                .map(|coords| source.map_or(coords, |source| source.overzoomed_coords(&coords)))
+                .filter(|coords| {
+                    tile_json
+                        .as_ref()
+                        .map_or(true, |tile_json| tile_json.contains(coords))
+                })
+                .filter(|coords| seen.insert(*coords))
+                .collect();
+
+            Some(PreloadSource {
+                tile_json: tile_json.map(Arc::new),
+                raster,
+                tiles,
+            })
+        })
+        .collect()
+}
+
+/// Progress of a preload, see [`PreloadHandle::progress`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PreloadProgress {
+    /// Number of tiles which are preloaded.
+    pub total: usize,
+    pub loaded: usize,
+    pub failed: usize,
+    /// Tiles which were not fetched anymore, because the preload was cancelled.
+    pub cancelled: usize,
+}
+
+impl PreloadProgress {
+    /// Whether all tiles were either loaded, failed or cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.loaded + self.failed + self.cancelled >= self.total
+    }
+}
+
+#[derive(Default)]
+struct PreloadState {
+    total: usize,
+    loaded: AtomicUsize,
+    failed: AtomicUsize,
+    cancelled_tiles: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+/// Tracks and cancels a preload, see [`crate::map_schedule::MapSchedule::preload_area`]. Clones
+/// refer to the same preload.
+#[derive(Clone, Default)]
+pub struct PreloadHandle {
+    state: Arc<PreloadState>,
+}
+
+impl PreloadHandle {
+    fn new(total: usize) -> Self {
+        Self {
+            state: Arc::new(PreloadState {
+                total,
+                ..PreloadState::default()
+            }),
+        }
+    }
+
+    pub fn progress(&self) -> PreloadProgress {
+        PreloadProgress {
+            total: self.state.total,
+            loaded: self.state.loaded.load(Ordering::Relaxed),
+            failed: self.state.failed.load(Ordering::Relaxed),
+            cancelled: self.state.cancelled_tiles.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress().is_finished()
+    }
+
+    /// Stops fetching the tiles which are not fetched yet. Retries of tiles which are fetched
+    /// right now are stopped as well.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn tile_finished(&self, result: &Result<Vec<u8>, Error>) {
+        let counter = match result {
+            Ok(_) => &self.state.loaded,
+            Err(Error::Cancelled) => &self.state.cancelled_tiles,
+            Err(_) => &self.state.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Fetches the tiles of `sources` on the scheduler. Each tile waits for a fetch permit of the
+/// request limiter, like the tiles in view.
+pub(crate) fn schedule_preload<HC>(
+    sources: Vec<PreloadSource>,
+    source_client: &SourceClient<HC>,
+    scheduler: &dyn ScheduleMethod,
+    shared_thread_state: &SharedThreadState,
+) -> PreloadHandle
+where
+    HC: HTTPClient,
+{
+    let handle = PreloadHandle::new(sources.iter().map(|source| source.tiles.len()).sum());
+
+    for source in sources {
+        for coords in source.tiles {
+            let client = source_client.clone();
+            let tile_json = source.tile_json.clone();
+            let raster = source.raster;
+            let tile_handle = handle.clone();
+
+            let scheduled = scheduler.schedule(
+                shared_thread_state.clone(),
+                Box::new(move |state: SharedThreadState| {
+                    Box::pin(async move {
+                        let is_cancelled = {
+                            let handle = tile_handle.clone();
+                            move || handle.is_cancelled()
+                        };
+                        if is_cancelled() {
+                            tile_handle.tile_finished(&Err(Error::Cancelled));
+                            return;
+                        }
+
+                        let _permit = state.request_limiter.fetches.acquire().await;
+                        // Tiles which waited for their turn are skipped once cancelled
+                        let result = if is_cancelled() {
+                            Err(Error::Cancelled)
+                        } else {
+                            match (&tile_json, raster) {
+                                (Some(tile_json), true) => {
+                                    client.fetch_raster(&coords, tile_json, is_cancelled).await
+                                }
+                                (tile_json, _) => {
+                                    client
+                                        .fetch(&coords, tile_json.as_deref(), is_cancelled)
+                                        .await
+                                }
+                            }
+                        };
+                        if let Err(e) = &result {
+                            if !matches!(e, Error::Cancelled) {
+                                log::warn!("failed to preload tile {}: {:?}", coords, e);
+                            }
+                        }
+                        tile_handle.tile_finished(&result);
+                    })
+                }),
+            );
+            if let Err(e) = scheduled {
+                log::error!("failed to schedule the preload of tile {}: {:?}", coords, e);
+                handle.tile_finished(&Err(e));
+            }
+        }
+    }
+
+    handle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preload_sources, tiles_in_bounds, PreloadHandle};
+    use crate::coords::WorldTileCoords;
+    use crate::error::Error;
+    use crate::style::Style;
+
+    #[test]
+    fn test_tiles_in_bounds() {
+        // The whole world
+        assert_eq!(tiles_in_bounds([-180.0, -85.0, 180.0, 85.0], 0).count(), 1);
+        assert_eq!(tiles_in_bounds([-180.0, -85.0, 180.0, 85.0], 2).count(), 16);
+
+        // The north-east quarter of the world
+        let tiles = tiles_in_bounds([1.0, 1.0, 179.0, 80.0], 1).collect::<Vec<_>>();
+        assert_eq!(tiles, vec![WorldTileCoords { x: 1, y: 0, z: 1 }]);
+
+        // Across the antimeridian the tiles at both edges of the world are covered
+        let tiles = tiles_in_bounds([170.0, 1.0, -170.0, 10.0], 2).collect::<Vec<_>>();
+        assert_eq!(
+            tiles,
+            vec![
+                WorldTileCoords { x: 3, y: 1, z: 2 },
+                WorldTileCoords { x: 0, y: 1, z: 2 }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preload_sources() {
+        let style: Style = serde_json::from_value(serde_json::json!({
+            "version": 8,
+            "name": "Test Style",
+            "metadata": {},
+            "sources": {
+                "openmaptiles": {
+                    "type": "vector",
+                    "tiles": ["https://example.com/{z}/{x}/{y}.pbf"],
+                    "minzoom": 1,
+                    "maxzoom": 2
+                },
+                "pending": {
+                    "type": "raster",
+                    "url": "https://example.com/tiles.json"
+                }
+            },
+            "layers": [{
+                "id": "building",
+                "type": "fill",
+                "source": "openmaptiles",
+                "source-layer": "building"
+            }, {
+                "id": "satellite",
+                "type": "raster",
+                "source": "pending"
+            }]
+        }))
+        .unwrap();
+
+        let sources = preload_sources(&style, [-180.0, -85.0, 180.0, 85.0], 0, 4, 1.0);
+        // The raster source is skipped, because its TileJSON is not fetched yet
+        assert_eq!(sources.len(), 1);
+        assert!(!sources[0].raster);
+        // Zoom level 0 is below the source, levels 3 and 4 are overzoomed from level 2
+        assert_eq!(sources[0].tiles.len(), 4 + 16);
+        assert!(sources[0]
+            .tiles
+            .iter()
+            .all(|coords| (1..=2).contains(&coords.z)));
+    }
+
+    #[test]
+    fn test_preload_handle() {
+        let handle = PreloadHandle::new(3);
+        assert!(!handle.is_finished());
+
+        handle.tile_finished(&Ok(vec![]));
+        handle.tile_finished(&Err(Error::Network("timeout".to_string())));
+        handle.cancel();
+        assert!(handle.clone().is_cancelled());
+        handle.tile_finished(&Err(Error::Cancelled));
+
+        let progress = handle.progress();
+        assert_eq!(
+            (progress.loaded, progress.failed, progress.cancelled),
+            (1, 1, 1)
+        );
+        assert!(progress.is_finished());
+    }
+}
@@ -0,0 +1,264 @@
+//! Limits how many tile requests are processed at once. Without a limit, panning quickly schedules
+//! hundreds of requests which all fetch at the same time and overwhelm the tile server or the
+//! connection pool of the browser.
+//!
+//! Fetching and tessellating are limited separately, such that both the network and the CPU are
+//! bounded. Requests which exceed a limit wait in a queue and are checked for cancellation once
+//! it is their turn, so tiles which left the view in the meantime are not fetched anymore.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Maximum numbers of requests which are processed at once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// Tiles and TileJSON documents which are fetched at once. Like the image requests of
+    /// maplibre-gl-js, this defaults to 16.
+    pub max_fetches: usize,
+    /// Tiles which are decoded or tessellated at once.
+    pub max_tessellations: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_fetches: 16,
+            max_tessellations: 4,
+        }
+    }
+}
+
+/// Numbers of requests which are processed or wait for their turn, see
+/// [`RequestLimiter::in_flight`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InFlightRequests {
+    pub fetches: usize,
+    pub queued_fetches: usize,
+    pub tessellations: usize,
+    pub queued_tessellations: usize,
+}
+
+/// The semaphores which enforce the [`RequestLimits`]. Clones share the same semaphores.
+#[derive(Clone)]
+pub struct RequestLimiter {
+    pub fetches: Semaphore,
+    pub tessellations: Semaphore,
+}
+
+impl RequestLimiter {
+    pub fn new(limits: &RequestLimits) -> Self {
+        Self {
+            fetches: Semaphore::new(limits.max_fetches),
+            tessellations: Semaphore::new(limits.max_tessellations),
+        }
+    }
+
+    pub fn in_flight(&self) -> InFlightRequests {
+        InFlightRequests {
+            fetches: self.fetches.in_flight(),
+            queued_fetches: self.fetches.queued(),
+            tessellations: self.tessellations.in_flight(),
+            queued_tessellations: self.tessellations.queued(),
+        }
+    }
+}
+
+impl Default for RequestLimiter {
+    fn default() -> Self {
+        Self::new(&RequestLimits::default())
+    }
+}
+
+struct SemaphoreState {
+    limit: usize,
+    in_flight: usize,
+    queued: usize,
+    waiters: VecDeque<Waker>,
+}
+
+/// An async semaphore which does not depend on a specific runtime, such that it works with Tokio
+/// as well as with web workers. Waiting futures are woken in the order in which they started to
+/// wait.
+#[derive(Clone)]
+pub struct Semaphore {
+    state: Arc<Mutex<SemaphoreState>>,
+}
+
+impl Semaphore {
+    /// Creates a semaphore which hands out `limit` permits at once. A limit of zero behaves like
+    /// one, such that requests are never blocked forever.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SemaphoreState {
+                limit: limit.max(1),
+                in_flight: 0,
+                queued: 0,
+                waiters: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Waits until a permit is available. The permit is returned when it is dropped.
+    pub fn acquire(&self) -> Acquire {
+        Acquire {
+            semaphore: self.clone(),
+            queued: false,
+        }
+    }
+
+    /// Number of permits which are currently held.
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().map_or(0, |state| state.in_flight)
+    }
+
+    /// Number of futures which wait for a permit.
+    pub fn queued(&self) -> usize {
+        self.state.lock().map_or(0, |state| state.queued)
+    }
+}
+
+/// Future of [`Semaphore::acquire`].
+pub struct Acquire {
+    semaphore: Semaphore,
+    /// Whether this future is counted in [`Semaphore::queued`].
+    queued: bool,
+}
+
+impl Future for Acquire {
+    type Output = Permit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let semaphore = self.semaphore.clone();
+        let mut state = semaphore.state.lock().unwrap();
+
+        if state.in_flight < state.limit {
+            state.in_flight += 1;
+            if self.queued {
+                state.queued -= 1;
+                self.queued = false;
+            }
+            return Poll::Ready(Permit {
+                semaphore: semaphore.clone(),
+            });
+        }
+
+        if !self.queued {
+            state.queued += 1;
+            self.queued = true;
+        }
+        state.waiters.push_back(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        if self.queued {
+            if let Ok(mut state) = self.semaphore.state.lock() {
+                state.queued -= 1;
+            }
+        }
+    }
+}
+
+/// Allows to process a request while it is held.
+pub struct Permit {
+    semaphore: Semaphore,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let waiters: Vec<Waker> = match self.semaphore.state.lock() {
+            Ok(mut state) => {
+                state.in_flight -= 1;
+                state.waiters.drain(..).collect()
+            }
+            Err(_) => return,
+        };
+        // Waiters which were dropped meanwhile can not take the permit, so all of them are woken
+        // and the first one to be polled wins
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RequestLimiter, RequestLimits, Semaphore};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    /// Counts how often it is woken.
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn poll<F: Future + Unpin>(future: &mut F, waker: &Waker) -> Poll<F::Output> {
+        Pin::new(future).poll(&mut Context::from_waker(waker))
+    }
+
+    #[test]
+    fn test_semaphore_queues_requests_above_the_limit() {
+        let counter = Arc::new(CountingWaker::default());
+        let waker = Waker::from(counter.clone());
+        let semaphore = Semaphore::new(2);
+
+        let first = poll(&mut semaphore.acquire(), &waker);
+        let second = poll(&mut semaphore.acquire(), &waker);
+        assert!(first.is_ready() && second.is_ready());
+        assert_eq!(semaphore.in_flight(), 2);
+
+        let mut third = semaphore.acquire();
+        assert!(poll(&mut third, &waker).is_pending());
+        assert_eq!(semaphore.queued(), 1);
+
+        // Returning a permit wakes the waiting request, which takes the permit
+        drop(first);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        let third = poll(&mut third, &waker);
+        assert!(third.is_ready());
+        assert_eq!(semaphore.in_flight(), 2);
+        assert_eq!(semaphore.queued(), 0);
+
+        drop(second);
+        drop(third);
+        assert_eq!(semaphore.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_dropped_waiters_are_not_queued() {
+        let waker = Waker::from(Arc::new(CountingWaker::default()));
+        let limiter = RequestLimiter::new(&RequestLimits {
+            max_fetches: 1,
+            max_tessellations: 0,
+        });
+
+        let fetch = poll(&mut limiter.fetches.acquire(), &waker);
+        let tessellation = poll(&mut limiter.tessellations.acquire(), &waker);
+        assert!(fetch.is_ready());
+        // A limit of zero behaves like one
+        assert!(tessellation.is_ready());
+
+        let mut waiting = limiter.fetches.acquire();
+        assert!(poll(&mut waiting, &waker).is_pending());
+        assert_eq!(limiter.in_flight().queued_fetches, 1);
+        drop(waiting);
+
+        let in_flight = limiter.in_flight();
+        assert_eq!(in_flight.fetches, 1);
+        assert_eq!(in_flight.queued_fetches, 0);
+        assert_eq!(in_flight.tessellations, 1);
+    }
+}
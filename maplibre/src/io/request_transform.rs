@@ -0,0 +1,160 @@
+//! Headers and query parameters which are added to requests, e.g. the API keys or tokens which
+//! authenticated tile providers require. The [`crate::io::source_client::HTTPClient`]
+//! implementations apply them to every request of tiles, styles, sprites and glyphs.
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Additional headers and query parameters of a request.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RequestParameters {
+    pub headers: Vec<(String, String)>,
+    pub query: Vec<(String, String)>,
+}
+
+impl RequestParameters {
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_query_param(mut self, name: &str, value: &str) -> Self {
+        self.query.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds an `Authorization` header with a bearer token.
+    pub fn with_bearer_token(self, token: &str) -> Self {
+        self.with_header("Authorization", &format!("Bearer {}", token))
+    }
+
+    /// Appends the query parameters to `url`. Parameters are added in front of the fragment and
+    /// after the parameters which are part of `url` already.
+    pub fn apply_query(&self, url: &str) -> String {
+        if self.query.is_empty() {
+            return url.to_string();
+        }
+
+        let (url, fragment) = match url.find('#') {
+            Some(index) => url.split_at(index),
+            None => (url, ""),
+        };
+        let query = self
+            .query
+            .iter()
+            .map(|(name, value)| format!("{}={}", percent_encode(name), percent_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let separator = if !url.contains('?') {
+            "?"
+        } else if url.ends_with('?') || url.ends_with('&') {
+            ""
+        } else {
+            "&"
+        };
+        format!("{}{}{}{}", url, separator, query, fragment)
+    }
+}
+
+/// Computes the [`RequestParameters`] of a request from its URL. The closure is called for every
+/// request, including retries, so it can refresh tokens which expire.
+#[derive(Clone)]
+pub struct RequestTransform(Arc<dyn Fn(&str) -> RequestParameters + Send + Sync>);
+
+impl RequestTransform {
+    pub fn new<F>(transform: F) -> Self
+    where
+        F: Fn(&str) -> RequestParameters + Send + Sync + 'static,
+    {
+        Self(Arc::new(transform))
+    }
+
+    /// Adds the same `parameters` to every request.
+    pub fn fixed(parameters: RequestParameters) -> Self {
+        Self::new(move |_| parameters.clone())
+    }
+
+    /// Returns the parameters of a request of `url`.
+    pub fn parameters(&self, url: &str) -> RequestParameters {
+        (self.0)(url)
+    }
+}
+
+impl fmt::Debug for RequestTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RequestTransform")
+    }
+}
+
+/// Encodes all characters except the unreserved ones of RFC 3986.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RequestParameters, RequestTransform};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_apply_query() {
+        let parameters = RequestParameters::default().with_query_param("key", "a b&c");
+        assert_eq!(
+            parameters.apply_query("https://example.com/1/2/3.pbf"),
+            "https://example.com/1/2/3.pbf?key=a%20b%26c"
+        );
+        assert_eq!(
+            parameters.apply_query("https://example.com/tiles.json?v=2#map"),
+            "https://example.com/tiles.json?v=2&key=a%20b%26c#map"
+        );
+        assert_eq!(
+            RequestParameters::default().apply_query("https://example.com/a?"),
+            "https://example.com/a?"
+        );
+    }
+
+    #[test]
+    fn test_transform_is_evaluated_per_request() {
+        let refreshes = Arc::new(AtomicUsize::new(0));
+        let transform = {
+            let refreshes = refreshes.clone();
+            RequestTransform::new(move |url| {
+                let token = refreshes.fetch_add(1, Ordering::SeqCst);
+                if url.starts_with("https://tiles.example.com/") {
+                    RequestParameters::default().with_bearer_token(&format!("token-{}", token))
+                } else {
+                    RequestParameters::default()
+                }
+            })
+        };
+
+        assert_eq!(
+            transform
+                .parameters("https://tiles.example.com/0/0/0.pbf")
+                .headers,
+            vec![("Authorization".to_string(), "Bearer token-0".to_string())]
+        );
+        assert_eq!(
+            transform
+                .parameters("https://tiles.example.com/0/0/0.pbf")
+                .headers,
+            vec![("Authorization".to_string(), "Bearer token-1".to_string())]
+        );
+        assert!(transform
+            .parameters("https://fonts.example.com/0-255.pbf")
+            .headers
+            .is_empty());
+
+        let fixed = RequestTransform::fixed(RequestParameters::default().with_header("X-Key", "1"));
+        assert_eq!(fixed.parameters("a").headers, fixed.parameters("b").headers);
+    }
+}
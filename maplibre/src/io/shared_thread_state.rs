@@ -1,30 +1,87 @@
 //! Shared thread state.
 
-use crate::coords::{WorldCoords, WorldTileCoords, Zoom};
+use crate::coords::{ViewRegion, WorldCoords, WorldTileCoords, Zoom};
 use crate::error::Error;
+use crate::io::geojson_source::GeoJsonSource;
 use crate::io::geometry_index::{GeometryIndex, IndexProcessor, IndexedGeometry, TileIndex};
+use crate::io::glyphs::SdfGlyph;
+use crate::io::request_limiter::{RequestLimiter, RequestLimits};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::tessellation_cache::TessellationCache;
+use crate::io::tile_json::TileJSON;
 use crate::io::tile_request_state::TileRequestState;
 use crate::io::{
-    LayerTessellateMessage, TessellateMessage, TileRequest, TileRequestID, TileTessellateMessage,
+    GeoJsonTileMessage, GlyphsMessage, LayerTessellateMessage, RasterFormat, RasterTileMessage,
+    SourceErrorMessage, TessellateMessage, TileJsonMessage, TileRequest, TileRequestID,
+    TileTessellateMessage,
 };
+use crate::metrics::MetricsSink;
+use crate::style::source::DemEncoding;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use crate::tessellation::zero_tessellator::ZeroTessellator;
+use crate::io::tessellation_pool::{self, LayerGeometry};
+use crate::io::tile_reader::{self, RawLayer};
+use crate::tessellation::LineStyle;
 
-use geozero::GeozeroDatasource;
-use prost::Message;
+use geozero::mvt::tile;
+use instant::Instant;
 use std::sync::{mpsc, Arc, Mutex};
 
+/// Number of tiles around the view within which pending tile requests are not cancelled. Tiles
+/// right next to the view are likely to be needed again soon, e.g. while panning back and forth.
+pub const VIEW_REGION_MARGIN: i32 = 1;
+
 /// Stores and provides access to the thread safe data shared between the schedulers.
 #[derive(Clone)]
 pub struct SharedThreadState {
     pub tile_request_state: Arc<Mutex<TileRequestState>>,
     pub message_sender: mpsc::Sender<TessellateMessage>,
     pub geometry_index: Arc<Mutex<GeometryIndex>>,
+    /// The tiles which were in view when tiles were requested the last time, extended by
+    /// [`VIEW_REGION_MARGIN`] tiles.
+    pub view_region: Arc<Mutex<Option<ViewRegion>>>,
+    pub metrics: Arc<dyn MetricsSink>,
+    /// Limits how many requests are fetched and tessellated at once.
+    pub request_limiter: RequestLimiter,
+    /// Layers of vector tiles are read from and written to this cache instead of tessellating
+    /// them each time.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub tessellation_cache: Option<TessellationCache>,
 }
 
 impl SharedThreadState {
+    /// Creates the state which is shared with the schedulers of a map. Tessellated layers are not
+    /// cached, see [`SharedThreadState::tessellation_cache`].
+    pub fn new(
+        message_sender: mpsc::Sender<TessellateMessage>,
+        metrics: Arc<dyn MetricsSink>,
+        request_limits: &RequestLimits,
+    ) -> Self {
+        Self {
+            tile_request_state: Arc::new(Mutex::new(TileRequestState::new())),
+            message_sender,
+            geometry_index: Arc::new(Mutex::new(GeometryIndex::new())),
+            view_region: Arc::new(Mutex::new(None)),
+            metrics,
+            request_limiter: RequestLimiter::new(request_limits),
+            #[cfg(not(target_arch = "wasm32"))]
+            tessellation_cache: None,
+        }
+    }
+
+    pub fn set_view_region(&self, view_region: Option<ViewRegion>) {
+        if let Ok(mut current) = self.view_region.lock() {
+            *current = view_region.map(|view_region| view_region.padded(VIEW_REGION_MARGIN));
+        }
+    }
+
+    /// Whether the tile at `coords` is still in view or within the margin around it. Returns true
+    /// if the view is unknown.
+    pub fn is_tile_in_view(&self, coords: &WorldTileCoords) -> bool {
+        is_in_view_region(&self.view_region, coords)
+    }
+
     fn get_tile_request(&self, request_id: TileRequestID) -> Option<TileRequest> {
         self.tile_request_state
             .lock()
@@ -36,64 +93,140 @@ impl SharedThreadState {
     pub fn process_tile(&self, request_id: TileRequestID, data: Box<[u8]>) -> Result<(), Error> {
         if let Some(tile_request) = self.get_tile_request(request_id) {
             let coords = tile_request.coords;
+            let start = Instant::now();
+
+            // The tile may have left the view while it was fetched
+            if !self.is_tile_in_view(&coords) {
+                self.tile_request_cancelled(&coords, request_id);
+                return Ok(());
+            }
 
             tracing::info!("parsing tile {} with {}bytes", &coords, data.len());
 
             let _span_ = tracing::span!(tracing::Level::TRACE, "parse_tile_bytes").entered();
 
-            let mut tile = geozero::mvt::Tile::decode(data.as_ref()).expect("failed to load tile");
+            // Only the layers are split up front, their features are decoded right before they
+            // are tessellated
+            let layers = match tile_reader::read_layers(Arc::from(data)) {
+                Ok(layers) => layers,
+                Err(error) => {
+                    tracing::error!("tile {} decoding failed {:?}", &coords, error);
+                    self.source_error(tile_request.source.as_deref(), &coords, &error)?;
+                    return self.tile_unavailable(&coords, request_id);
+                }
+            };
 
             let index = IndexProcessor::new();
 
-            for layer in &mut tile.layers {
-                let cloned_layer = layer.clone();
-                let layer_name: &str = &cloned_layer.name;
-                if !tile_request.layers.contains(layer_name) {
-                    continue;
-                }
+            let available_layers: HashSet<_> = layers
+                .iter()
+                .map(|layer| layer.name.clone())
+                .collect::<HashSet<_>>();
+            let layers: Vec<RawLayer> = layers
+                .into_iter()
+                .filter(|layer| tile_request.layers.contains(&layer.name))
+                .collect();
 
-                tracing::info!("layer {} at {} ready", layer_name, &coords);
+            let view_region = self.view_region.clone();
+            let line_styles = Arc::new(tile_request.line_styles.clone());
+            #[cfg(not(target_arch = "wasm32"))]
+            let tessellation_cache = self.tessellation_cache.clone();
+            #[cfg(not(target_arch = "wasm32"))]
+            let source = tile_request.source.clone();
+            let tessellate = move |layer: RawLayer| {
+                tracing::info!("layer {} at {} ready", &layer.name, &coords);
 
-                let mut tessellator = ZeroTessellator::default();
-                if let Err(e) = layer.process(&mut tessellator) {
-                    self.message_sender.send(TessellateMessage::Layer(
-                        LayerTessellateMessage::UnavailableLayer {
-                            coords,
-                            layer_name: layer_name.to_owned(),
-                        },
-                    ))?;
+                let line_style = line_styles.get(&layer.name).copied().unwrap_or_default();
 
-                    tracing::error!(
-                        "layer {} at {} tesselation failed {:?}",
-                        layer_name,
+                // Cached layers are neither decoded nor tessellated
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(cached) = tessellation_cache.as_ref().and_then(|cache| {
+                    cache.get(source.as_deref(), &coords, &layer.name, &line_style)
+                }) {
+                    return (layer.name, Ok(cached));
+                }
+
+                let view_region = view_region.clone();
+                let result = layer.decode().and_then(|layer_data| {
+                    let mut features = layer_data.clone();
+                    let geometry = tessellation_pool::tessellate_layer(
+                        &mut features,
+                        &line_style,
+                        move || !is_in_view_region(&view_region, &coords),
+                    )?;
+                    Ok((layer_data, geometry))
+                });
+
+                #[cfg(not(target_arch = "wasm32"))]
+                if let (Some(cache), Ok((layer_data, geometry))) = (&tessellation_cache, &result) {
+                    if let Err(e) = cache.put(
+                        source.as_deref(),
                         &coords,
-                        e
-                    );
-                } else {
-                    self.message_sender.send(TessellateMessage::Layer(
-                        LayerTessellateMessage::TessellatedLayer {
-                            coords,
-                            buffer: tessellator.buffer.into(),
-                            feature_indices: tessellator.feature_indices,
-                            layer_data: cloned_layer,
-                        },
-                    ))?;
+                        layer_data,
+                        geometry,
+                        &line_style,
+                    ) {
+                        log::warn!(
+                            "failed to cache layer {} at {}: {:?}",
+                            &layer.name,
+                            &coords,
+                            e
+                        );
+                    }
+                }
+                (layer.name, result)
+            };
+
+            // Each layer is sent as soon as it and the layers before it are tessellated. Layers
+            // which have been sent already are kept in the tile cache, so only the cancelled ones
+            // are requested again.
+            let mut cancelled = false;
+            let mut first_layer = true;
+            let mut sent = Ok(());
+            tessellation_pool::for_each_ordered(layers, tessellate, |(layer_name, result)| {
+                if sent.is_err() {
+                    return;
+                }
+
+                match result {
+                    Err(Error::Cancelled) => {
+                        tracing::info!(
+                            "tessellation of layer {} at {} cancelled",
+                            &layer_name,
+                            &coords
+                        );
+                        cancelled = true;
+                    }
+                    result => {
+                        let tessellated = result.is_ok();
+                        sent = self.send_tessellated_layer(
+                            &coords,
+                            tile_request.source.as_deref(),
+                            &layer_name,
+                            result,
+                        );
+                        if tessellated && first_layer {
+                            first_layer = false;
+                            self.metrics
+                                .first_layer_tessellated(&coords, start.elapsed());
+                        }
+                    }
                 }
 
                 // TODO
                 // layer.process(&mut index).unwrap();
+            });
+            sent?;
+            if cancelled {
+                self.tile_request_cancelled(&coords, request_id);
+                return Ok(());
             }
 
-            let available_layers: HashSet<_> = tile
-                .layers
-                .iter()
-                .map(|layer| layer.name.clone())
-                .collect::<HashSet<_>>();
-
             for missing_layer in tile_request.layers.difference(&available_layers) {
                 self.message_sender.send(TessellateMessage::Layer(
                     LayerTessellateMessage::UnavailableLayer {
                         coords,
+                        source: tile_request.source.clone(),
                         layer_name: missing_layer.to_owned(),
                     },
                 ))?;
@@ -126,6 +259,124 @@ impl SharedThreadState {
         Ok(())
     }
 
+    /// Tessellates a layer and sends the result to the main thread. Returns [`Error::Cancelled`]
+    /// without sending anything if the tile leaves the view during the tessellation. `source` is
+    /// the id of the source the layer belongs to, `None` for the default tile source.
+    fn tessellate_layer(
+        &self,
+        coords: &WorldTileCoords,
+        source: Option<&str>,
+        layer: &mut tile::Layer,
+        line_style: &LineStyle,
+    ) -> Result<(), Error> {
+        let layer_data = layer.clone();
+        let view_region = self.view_region.clone();
+        let tile_coords = *coords;
+        let result = tessellation_pool::tessellate_layer(layer, line_style, move || {
+            !is_in_view_region(&view_region, &tile_coords)
+        });
+        if let Err(Error::Cancelled) = result {
+            tracing::info!(
+                "tessellation of layer {} at {} cancelled",
+                &layer_data.name,
+                &coords
+            );
+            return Err(Error::Cancelled);
+        }
+
+        let layer_name = layer_data.name.clone();
+        self.send_tessellated_layer(
+            coords,
+            source,
+            &layer_name,
+            result.map(|geometry| (layer_data, geometry)),
+        )
+    }
+
+    /// Sends the `result` of decoding and tessellating the layer `layer_name` to the main thread.
+    /// Layers which failed to decode or tessellate are reported as unavailable.
+    fn send_tessellated_layer(
+        &self,
+        coords: &WorldTileCoords,
+        source: Option<&str>,
+        layer_name: &str,
+        result: Result<(tile::Layer, LayerGeometry), Error>,
+    ) -> Result<(), Error> {
+        match result {
+            Err(e) => {
+                self.message_sender.send(TessellateMessage::Layer(
+                    LayerTessellateMessage::UnavailableLayer {
+                        coords: *coords,
+                        source: source.map(|source| source.to_string()),
+                        layer_name: layer_name.to_string(),
+                    },
+                ))?;
+
+                tracing::error!(
+                    "layer {} at {} tesselation failed {:?}",
+                    layer_name,
+                    &coords,
+                    e
+                );
+            }
+            Ok((layer_data, geometry)) => {
+                self.metrics.layer_tessellated(
+                    coords,
+                    layer_name,
+                    geometry.duration,
+                    geometry.buffer.vertices.len(),
+                    geometry.buffer.indices.len(),
+                );
+
+                self.message_sender.send(TessellateMessage::Layer(
+                    LayerTessellateMessage::TessellatedLayer {
+                        coords: *coords,
+                        source: source.map(|source| source.to_string()),
+                        buffer: geometry.buffer.into(),
+                        feature_indices: geometry.feature_indices,
+                        layer_data,
+                    },
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Slices the features of a GeoJSON source at the given coordinates into one vector tile
+    /// layer per requested layer name and tessellates them.
+    #[tracing::instrument(skip_all)]
+    pub fn process_geojson_tile(
+        &self,
+        coords: &WorldTileCoords,
+        source_id: &str,
+        source: &GeoJsonSource,
+        layers: &HashSet<String>,
+        line_styles: &HashMap<String, LineStyle>,
+    ) -> Result<(), Error> {
+        tracing::info!("slicing GeoJSON tile {} of {}", &coords, source_id);
+
+        for layer_name in layers {
+            let mut layer = source.tile_layer(coords, layer_name);
+            let line_style = line_styles.get(layer_name).copied().unwrap_or_default();
+            match self.tessellate_layer(coords, Some(source_id), &mut layer, &line_style) {
+                Err(Error::Cancelled) => {
+                    self.geojson_tile_request_cancelled(coords, source_id);
+                    return Ok(());
+                }
+                result => result?,
+            }
+        }
+
+        self.message_sender
+            .send(TessellateMessage::GeoJsonTile(GeoJsonTileMessage {
+                coords: *coords,
+                source: source_id.to_string(),
+            }))?;
+
+        Ok(())
+    }
+
     pub fn tile_unavailable(
         &self,
         coords: &WorldTileCoords,
@@ -137,6 +388,7 @@ impl SharedThreadState {
                 self.message_sender.send(TessellateMessage::Layer(
                     LayerTessellateMessage::UnavailableLayer {
                         coords: tile_request.coords,
+                        source: tile_request.source.clone(),
                         layer_name: to_load.to_string(),
                     },
                 ))?;
@@ -146,6 +398,178 @@ impl SharedThreadState {
         Ok(())
     }
 
+    /// Reports to the main thread that the tile at `coords` of `source` failed to load, such that
+    /// it is passed on to the listeners of [`crate::events::MapEvent::SourceError`].
+    pub fn source_error(
+        &self,
+        source: Option<&str>,
+        coords: &WorldTileCoords,
+        error: &Error,
+    ) -> Result<(), Error> {
+        self.message_sender
+            .send(TessellateMessage::SourceError(SourceErrorMessage {
+                source: source.map(|source| source.to_string()),
+                coords: *coords,
+                message: format!("{:?}", error),
+            }))?;
+
+        Ok(())
+    }
+
+    /// Forgets the pending request without marking its layers unavailable, so that the tile is
+    /// requested again once it comes back into view.
+    pub fn tile_request_cancelled(&self, coords: &WorldTileCoords, request_id: TileRequestID) {
+        tracing::info!("request of tile {} cancelled", coords);
+        if let Ok(mut tile_request_state) = self.tile_request_state.lock() {
+            tile_request_state.finish_tile_request(request_id);
+        }
+    }
+
+    /// Forgets the pending GeoJSON request, see [`SharedThreadState::tile_request_cancelled`].
+    pub fn geojson_tile_request_cancelled(&self, coords: &WorldTileCoords, source: &str) {
+        tracing::info!(
+            "request of GeoJSON tile of {} at {} cancelled",
+            source,
+            coords
+        );
+        if let Ok(mut tile_request_state) = self.tile_request_state.lock() {
+            tile_request_state.finish_geojson_request(coords, source);
+        }
+    }
+
+    /// Decodes a PNG or JPEG raster tile into RGBA pixels. Tiles of raster DEM sources, which
+    /// have an `encoding`, are decoded further into elevations.
+    #[tracing::instrument(skip_all)]
+    pub fn process_raster_tile(
+        &self,
+        coords: &WorldTileCoords,
+        source: &str,
+        data: Box<[u8]>,
+        encoding: Option<DemEncoding>,
+    ) -> Result<(), Error> {
+        tracing::info!("decoding raster tile {} with {}bytes", &coords, data.len());
+
+        let message = match image::load_from_memory(data.as_ref()) {
+            Ok(image) => {
+                let image = image.into_rgba8();
+                let (width, height) = (image.width(), image.height());
+                let (format, data) = match encoding {
+                    Some(encoding) => {
+                        let elevations = encoding.decode(image.as_raw());
+                        (
+                            RasterFormat::Elevation,
+                            bytemuck::cast_slice(&elevations).to_vec(),
+                        )
+                    }
+                    None => (RasterFormat::Rgba8, image.into_raw()),
+                };
+                RasterTileMessage::Raster {
+                    coords: *coords,
+                    source: source.to_string(),
+                    width,
+                    height,
+                    format,
+                    data: data.into_boxed_slice(),
+                }
+            }
+            Err(e) => {
+                tracing::error!("raster tile {} decoding failed {:?}", &coords, e);
+                self.message_sender
+                    .send(TessellateMessage::SourceError(SourceErrorMessage {
+                        source: Some(source.to_string()),
+                        coords: *coords,
+                        message: format!("decoding failed: {:?}", e),
+                    }))?;
+                RasterTileMessage::UnavailableRaster {
+                    coords: *coords,
+                    source: source.to_string(),
+                }
+            }
+        };
+
+        self.message_sender
+            .send(TessellateMessage::Raster(message))?;
+
+        Ok(())
+    }
+
+    pub fn raster_tile_unavailable(
+        &self,
+        coords: &WorldTileCoords,
+        source: &str,
+    ) -> Result<(), Error> {
+        tracing::warn!("raster tile of {} at {} unavailable", source, coords);
+        self.message_sender.send(TessellateMessage::Raster(
+            RasterTileMessage::UnavailableRaster {
+                coords: *coords,
+                source: source.to_string(),
+            },
+        ))?;
+
+        Ok(())
+    }
+
+    /// Forgets the pending raster request, see [`SharedThreadState::tile_request_cancelled`].
+    pub fn raster_tile_request_cancelled(&self, coords: &WorldTileCoords, source: &str) {
+        tracing::info!(
+            "request of raster tile of {} at {} cancelled",
+            source,
+            coords
+        );
+        if let Ok(mut tile_request_state) = self.tile_request_state.lock() {
+            tile_request_state.finish_raster_request(coords, source);
+        }
+    }
+
+    /// Passes the result of fetching the TileJSON document at `url` to the main thread.
+    pub fn process_tile_json(
+        &self,
+        url: &str,
+        tile_json: Result<TileJSON, Error>,
+    ) -> Result<(), Error> {
+        let tile_json = match tile_json {
+            Ok(tile_json) => Some(tile_json),
+            Err(e) => {
+                log::error!("TileJSON {} unavailable: {:?}", url, e);
+                None
+            }
+        };
+
+        self.message_sender
+            .send(TessellateMessage::TileJson(TileJsonMessage {
+                url: url.to_string(),
+                tile_json,
+            }))?;
+
+        Ok(())
+    }
+
+    /// Passes the result of fetching the range of glyphs of `fontstack` which starts at `range`
+    /// to the main thread.
+    pub fn process_glyphs(
+        &self,
+        fontstack: &str,
+        range: u32,
+        glyphs: Result<Vec<SdfGlyph>, Error>,
+    ) -> Result<(), Error> {
+        let glyphs = match glyphs {
+            Ok(glyphs) => Some(glyphs),
+            Err(e) => {
+                log::error!("glyphs {} of {} unavailable: {:?}", range, fontstack, e);
+                None
+            }
+        };
+
+        self.message_sender
+            .send(TessellateMessage::Glyphs(GlyphsMessage {
+                fontstack: fontstack.to_string(),
+                range,
+                glyphs,
+            }))?;
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip_all)]
     pub fn query_point(
         &self,
@@ -168,3 +592,16 @@ impl SharedThreadState {
         }
     }
 }
+
+/// Whether the tile at `coords` is within `view_region`. Returns true if the view is unknown.
+fn is_in_view_region(view_region: &Mutex<Option<ViewRegion>>, coords: &WorldTileCoords) -> bool {
+    view_region
+        .lock()
+        .ok()
+        .and_then(|view_region| {
+            view_region
+                .as_ref()
+                .map(|view_region| view_region.is_in_view(coords))
+        })
+        .unwrap_or(true)
+}
@@ -2,8 +2,26 @@
 
 use crate::coords::WorldTileCoords;
 use crate::error::Error;
+use crate::io::compression;
+use crate::io::compression::ContentEncoding;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::disk_cache::DiskTileCache;
+use crate::io::glyphs::{parse_glyphs, SdfGlyph};
+#[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+use crate::io::mbtiles::MbtilesSource;
+use crate::io::pmtiles::{PmTilesArchive, PmTilesLocation};
+use crate::io::tile_json::TileJSON;
+use crate::io::tile_url;
 use crate::style::source::TileAddressingScheme;
 use async_trait::async_trait;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::Range;
+use std::time::Duration;
+
+/// The tile server which serves vector tiles if no other [`TileSource`] is configured.
+const TILE_URL_TEMPLATE: &str = "https://maps.tuerantuer.org/europe_germany/{z}/{x}/{y}.pbf";
 
 /// A closure that returns a HTTP client.
 pub type HTTPClientFactory<HC> = dyn Fn() -> HC;
@@ -18,6 +36,167 @@ pub type HTTPClientFactory<HC> = dyn Fn() -> HC;
 #[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
 pub trait HTTPClient: Clone + Sync + Send + 'static {
     async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error>;
+
+    /// Fetches the bytes within `range` with a HTTP range request.
+    async fn fetch_range(&self, url: &str, range: Range<u64>) -> Result<Vec<u8>, Error>;
+
+    /// Waits for `duration` without blocking the executor. Used to back off between retries.
+    async fn sleep(&self, duration: Duration);
+
+    /// Fetches `url` and returns the headers of the response which are evaluated as well. Clients
+    /// which can not read headers may keep the default, which allows caching without expiry and
+    /// expects [`HTTPClient::fetch`] to return decoded data.
+    async fn fetch_response(&self, url: &str) -> Result<HttpResponse, Error> {
+        Ok(HttpResponse {
+            data: self.fetch(url).await?,
+            ..HttpResponse::default()
+        })
+    }
+}
+
+/// The body of a HTTP response together with the headers which the [`SourceClient`] evaluates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpResponse {
+    /// The body as it was received, i.e. still encoded according to `content_encoding`.
+    pub data: Vec<u8>,
+    pub cache_policy: CachePolicy,
+    /// The encoding of the body according to the `Content-Encoding` header. Clients which decode
+    /// responses themselves, like browsers do, report [`ContentEncoding::Identity`].
+    pub content_encoding: ContentEncoding,
+}
+
+/// Caching directives of a HTTP response, see
+/// [Cache-Control](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Cache-Control).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CachePolicy {
+    /// The response must not be stored.
+    pub no_store: bool,
+    /// How long the response stays fresh. `None` if the response does not say.
+    pub max_age: Option<Duration>,
+}
+
+impl CachePolicy {
+    /// Parses the value of a `Cache-Control` header. `no-cache` requires revalidation, which the
+    /// tile caches do not support, so it is treated like a `max-age` of zero.
+    pub fn from_cache_control(header: &str) -> Self {
+        let mut policy = Self::default();
+        for directive in header.split(',').map(|directive| directive.trim()) {
+            let mut parts = directive.splitn(2, '=');
+            let name = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let value = parts.next().map(|value| value.trim().trim_matches('"'));
+
+            match (name.as_str(), value) {
+                ("no-store", _) => policy.no_store = true,
+                ("no-cache", _) => policy.max_age = Some(Duration::ZERO),
+                ("max-age", Some(value)) if policy.max_age.is_none() => {
+                    policy.max_age = value.parse().ok().map(Duration::from_secs)
+                }
+                _ => {}
+            }
+        }
+        policy
+    }
+}
+
+/// Describes how often and how fast failed tile requests are retried. The delay before the n-th
+/// retry is `initial_delay * multiplier^n`, capped at `max_delay` and shortened randomly by up to
+/// `jitter` so that tiles which failed together are not retried together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of attempts including the first request. Zero behaves like one.
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Fraction of the delay within `0..=1` which is randomized.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which gives up after the first failed attempt.
+    pub fn no_retry() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the delay before the retry with the index `retry`, starting at zero. `random` is
+    /// expected to be within `0..1` and determines the jitter. Negative delays, e.g. of a negative
+    /// `multiplier`, are treated as zero.
+    pub fn delay(&self, retry: u32, random: f64) -> Duration {
+        let delay = (self.initial_delay.as_secs_f64() * self.multiplier.powi(retry as i32))
+            .min(self.max_delay.as_secs_f64())
+            .max(0.0);
+        let jitter = self.jitter.clamp(0.0, 1.0) * random.clamp(0.0, 1.0);
+        Duration::from_secs_f64(delay * (1.0 - jitter))
+    }
+
+    /// Runs `request` until it succeeds or `max_attempts` is reached. Before every attempt
+    /// `is_cancelled` is checked, in which case [`Error::Cancelled`] is returned.
+    async fn retry<HC, T, F, Fut, C>(
+        &self,
+        http_client: &HC,
+        is_cancelled: C,
+        mut request: F,
+    ) -> Result<T, Error>
+    where
+        HC: HTTPClient,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+        C: Fn() -> bool,
+    {
+        let max_attempts = self.max_attempts.max(1);
+        let mut attempt = 0;
+
+        loop {
+            if is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+
+            let error = match request().await {
+                Ok(result) => return Ok(result),
+                Err(e) => e,
+            };
+
+            attempt += 1;
+            if attempt >= max_attempts {
+                return Err(match error {
+                    Error::Network(message) => {
+                        Error::Network(format!("{} (gave up after {} attempts)", message, attempt))
+                    }
+                    error => error,
+                });
+            }
+
+            let delay = self.delay(attempt - 1, random());
+            tracing::warn!(
+                "request failed in attempt {}, retrying in {:?}: {:?}",
+                attempt,
+                delay,
+                error
+            );
+            http_client.sleep(delay).await;
+        }
+    }
+}
+
+/// Returns a number within `0..1` which is good enough to spread out retries.
+fn random() -> f64 {
+    let value = RandomState::new().build_hasher().finish();
+    (value >> 11) as f64 / (1u64 << 53) as f64
 }
 
 /// Gives access to the HTTP client which can be of multiple types,
@@ -28,6 +207,9 @@ where
     HC: HTTPClient,
 {
     inner_client: HC,
+    retry_policy: RetryPolicy,
+    #[cfg(not(target_arch = "wasm32"))]
+    disk_cache: Option<DiskTileCache>,
 }
 
 /// Defines the different types of HTTP clients such as basic HTTP and Mbtiles.
@@ -38,19 +220,134 @@ where
     HC: HTTPClient,
 {
     Http(HttpSourceClient<HC>),
+    /// Vector tiles are read from a single PMTiles archive.
+    PmTiles {
+        archive: PmTilesArchive<HC>,
+        raster_client: HttpSourceClient<HC>,
+    },
+    /// Vector tiles are read from a local MBTiles file. Raster tiles are still fetched via HTTP.
+    #[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
     Mbtiles {
-        // TODO
+        source: MbtilesSource,
+        raster_client: HttpSourceClient<HC>,
     },
 }
 
+/// Selects where vector tiles are read from if they are not fetched from a tile server.
+pub enum TileSource {
+    PmTiles(PmTilesLocation),
+    #[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+    Mbtiles(MbtilesSource),
+}
+
 impl<HC> SourceClient<HC>
 where
     HC: HTTPClient,
 {
-    pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+    /// Fetches the vector tile at `coords`. Only requests to a tile server are retried, which
+    /// stops once `is_cancelled` returns true. Tile servers are looked up in `tile_json`, or the
+    /// default tile server is used if there is none.
+    pub async fn fetch<C>(
+        &self,
+        coords: &WorldTileCoords,
+        tile_json: Option<&TileJSON>,
+        is_cancelled: C,
+    ) -> Result<Vec<u8>, Error>
+    where
+        C: Fn() -> bool,
+    {
+        match self {
+            SourceClient::Http(client) => match tile_json {
+                Some(tile_json) => client.fetch_raster(coords, tile_json, is_cancelled).await,
+                None => client.fetch(coords, is_cancelled).await,
+            },
+            SourceClient::PmTiles { archive, .. } => archive
+                .get_tile(coords)
+                .await?
+                .ok_or_else(|| Error::PmTiles(format!("tile {} is not in the archive", coords))),
+            #[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+            SourceClient::Mbtiles { source, .. } => source
+                .get_tile(coords)?
+                .ok_or_else(|| Error::Mbtiles(format!("tile {} is not in the file", coords))),
+        }
+    }
+
+    pub async fn fetch_raster<C>(
+        &self,
+        coords: &WorldTileCoords,
+        tile_json: &TileJSON,
+        is_cancelled: C,
+    ) -> Result<Vec<u8>, Error>
+    where
+        C: Fn() -> bool,
+    {
+        self.http_client()
+            .fetch_raster(coords, tile_json, is_cancelled)
+            .await
+    }
+
+    /// Fetches and parses the TileJSON document at `url`.
+    pub async fn fetch_tile_json(&self, url: &str) -> Result<TileJSON, Error> {
+        self.http_client().fetch_tile_json(url).await
+    }
+
+    /// Fetches and parses the glyph PBF at `url`.
+    pub async fn fetch_glyphs(&self, url: &str) -> Result<Vec<SdfGlyph>, Error> {
+        self.http_client().fetch_glyphs(url).await
+    }
+
+    /// The client which fetches resources via HTTP, like raster tiles.
+    fn http_client(&self) -> &HttpSourceClient<HC> {
+        match self {
+            SourceClient::Http(client) => client,
+            SourceClient::PmTiles { raster_client, .. } => raster_client,
+            #[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+            SourceClient::Mbtiles { raster_client, .. } => raster_client,
+        }
+    }
+
+    /// Caches the tiles which are fetched via HTTP on disk, see [`HttpSourceClient::with_disk_cache`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_disk_cache(self, disk_cache: DiskTileCache) -> Self {
         match self {
-            SourceClient::Http(client) => client.fetch(coords).await,
-            SourceClient::Mbtiles { .. } => unimplemented!(),
+            SourceClient::Http(client) => SourceClient::Http(client.with_disk_cache(disk_cache)),
+            SourceClient::PmTiles {
+                archive,
+                raster_client,
+            } => SourceClient::PmTiles {
+                archive,
+                raster_client: raster_client.with_disk_cache(disk_cache),
+            },
+            #[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+            SourceClient::Mbtiles {
+                source,
+                raster_client,
+            } => SourceClient::Mbtiles {
+                source,
+                raster_client: raster_client.with_disk_cache(disk_cache),
+            },
+        }
+    }
+
+    /// Creates the client which reads tiles from `tile_source`, or from the default tile server
+    /// if there is none. Requests to tile servers are retried according to `retry_policy`.
+    pub fn from_tile_source(
+        tile_source: Option<TileSource>,
+        http_client: HC,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        let http = HttpSourceClient::new(http_client.clone()).with_retry_policy(retry_policy);
+        match tile_source {
+            Some(TileSource::PmTiles(location)) => SourceClient::PmTiles {
+                archive: PmTilesArchive::new(location, http_client),
+                raster_client: http,
+            },
+            #[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+            Some(TileSource::Mbtiles(source)) => SourceClient::Mbtiles {
+                source,
+                raster_client: http,
+            },
+            None => SourceClient::Http(http),
         }
     }
 }
@@ -62,21 +359,173 @@ where
     pub fn new(http_client: HC) -> Self {
         Self {
             inner_client: http_client,
+            retry_policy: RetryPolicy::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            disk_cache: None,
         }
     }
 
-    pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+    /// Looks up tiles in `disk_cache` before fetching them and stores fetched tiles in it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_disk_cache(mut self, disk_cache: DiskTileCache) -> Self {
+        self.disk_cache = Some(disk_cache);
+        self
+    }
+
+    /// Fetches `url` with retries. `source` and `coords` identify the tile in the disk cache.
+    async fn fetch_tile<C>(
+        &self,
+        source: &str,
+        coords: &WorldTileCoords,
+        url: &str,
+        is_cancelled: C,
+    ) -> Result<Vec<u8>, Error>
+    where
+        C: Fn() -> bool,
+    {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(data) = self
+            .disk_cache
+            .as_ref()
+            .and_then(|disk_cache| disk_cache.get(source, coords))
+        {
+            return Ok(data);
+        }
+
+        let response = self
+            .retry_policy
+            .retry(&self.inner_client, is_cancelled, || {
+                self.inner_client.fetch_response(url)
+            })
+            .await?;
+        // Vector tiles are often served gzip compressed, even without a Content-Encoding
+        let data = compression::decode(response.data, response.content_encoding)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(disk_cache) = &self.disk_cache {
+            if let Err(e) = disk_cache.put(source, coords, &data, &response.cache_policy) {
+                log::warn!("failed to cache tile {}: {:?}", coords, e);
+            }
+        }
+
+        Ok(data)
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub async fn fetch<C>(
+        &self,
+        coords: &WorldTileCoords,
+        is_cancelled: C,
+    ) -> Result<Vec<u8>, Error>
+    where
+        C: Fn() -> bool,
+    {
         let tile_coords = coords.into_tile(TileAddressingScheme::TMS).unwrap();
-        self.inner_client
-            .fetch(
-                format!(
-                    "https://maps.tuerantuer.org/europe_germany/{z}/{x}/{y}.pbf",
-                    x = tile_coords.x,
-                    y = tile_coords.y,
-                    z = tile_coords.z
-                )
-                .as_str(),
-            )
+        let url = tile_url::expand(TILE_URL_TEMPLATE, &tile_coords, &[], "");
+        self.fetch_tile(TILE_URL_TEMPLATE, coords, &url, is_cancelled)
+            .await
+    }
+
+    /// Fetches a tile from one of the tile servers of `tile_json`.
+    pub async fn fetch_raster<C>(
+        &self,
+        coords: &WorldTileCoords,
+        tile_json: &TileJSON,
+        is_cancelled: C,
+    ) -> Result<Vec<u8>, Error>
+    where
+        C: Fn() -> bool,
+    {
+        let url = tile_json
+            .tile_url(coords)
+            .ok_or_else(|| Error::Network(format!("invalid tile coordinates {}", coords)))?;
+        self.fetch_tile(tile_json.cache_key(), coords, &url, is_cancelled)
             .await
     }
+
+    /// Fetches and parses the TileJSON document at `url`. The document is not cached on disk.
+    pub async fn fetch_tile_json(&self, url: &str) -> Result<TileJSON, Error> {
+        let data = self
+            .retry_policy
+            .retry(
+                &self.inner_client,
+                || false,
+                || self.inner_client.fetch(url),
+            )
+            .await?;
+        TileJSON::parse(&data)
+    }
+
+    /// Fetches and parses the glyph PBF at `url`. Glyphs are not cached on disk.
+    pub async fn fetch_glyphs(&self, url: &str) -> Result<Vec<SdfGlyph>, Error> {
+        let data = self
+            .retry_policy
+            .retry(
+                &self.inner_client,
+                || false,
+                || self.inner_client.fetch(url),
+            )
+            .await?;
+        parse_glyphs(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachePolicy, RetryPolicy};
+    use std::time::Duration;
+
+    #[test]
+    fn test_delay_grows_exponentially() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+
+        assert_eq!(policy.delay(0, 0.7), Duration::from_millis(250));
+        assert_eq!(policy.delay(1, 0.7), Duration::from_millis(500));
+        assert_eq!(policy.delay(2, 0.7), Duration::from_millis(1000));
+        assert_eq!(policy.delay(10, 0.7), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_delay_jitter() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.delay(1, 0.0), Duration::from_millis(500));
+        assert_eq!(policy.delay(1, 1.0), Duration::from_millis(250));
+        assert!(policy.delay(1, 0.5) > Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_negative_delay() {
+        let policy = RetryPolicy {
+            multiplier: -2.0,
+            ..RetryPolicy::default()
+        };
+
+        assert_eq!(policy.delay(1, 0.5), Duration::ZERO);
+        assert_eq!(policy.delay(2, 0.0), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_cache_policy_from_cache_control() {
+        assert_eq!(
+            CachePolicy::from_cache_control("public, max-age=3600"),
+            CachePolicy {
+                no_store: false,
+                max_age: Some(Duration::from_secs(3600)),
+            }
+        );
+        assert!(CachePolicy::from_cache_control("No-Store").no_store);
+        assert_eq!(
+            CachePolicy::from_cache_control("no-cache, max-age=60").max_age,
+            Some(Duration::ZERO)
+        );
+        assert_eq!(CachePolicy::from_cache_control(""), CachePolicy::default());
+    }
 }
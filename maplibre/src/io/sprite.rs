@@ -0,0 +1,373 @@
+//! Sprite sheets which contain the icons of symbol layers. A sprite consists of a PNG image and a
+//! JSON index which names the images within it, see the
+//! [style specification](https://maplibre.org/maplibre-gl-js-docs/style-spec/sprite/).
+//!
+//! Styles provide a sprite in normal and in high resolution (`@2x`). The index of each resolution
+//! locates the images within its own PNG, so the index and the PNG are always fetched together.
+
+use crate::error::Error;
+use crate::io::source_client::HTTPClient;
+use crate::io::tile_url;
+use crate::style::layer::IconAnchor;
+use crate::text::GlyphQuad;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Location of an image within a [`SpriteSheet`] in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteImage {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Physical pixels per logical pixel of the image. High resolution sprites use 2.
+    pub pixel_ratio: f32,
+}
+
+/// A repeating image within the texture of a [`SpriteSheet`], see `fill-pattern`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpritePattern {
+    /// Texture coordinates `[u_min, v_min, u_max, v_max]` of the image.
+    pub tex_coords: [f32; 4],
+    /// Size of a single repetition of the image in logical pixels.
+    pub size: [f32; 2],
+}
+
+/// An image of the JSON index of a sprite.
+#[derive(Deserialize)]
+struct IndexEntry {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    #[serde(rename = "pixelRatio")]
+    pixel_ratio: Option<f32>,
+}
+
+/// The RGBA pixels of a sprite and the images within it.
+pub struct SpriteSheet {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    /// Physical pixels per logical pixel of the sprite, i.e. 2 for the high resolution sprite.
+    pub pixel_ratio: f32,
+    images: HashMap<String, SpriteImage>,
+}
+
+impl fmt::Debug for SpriteSheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpriteSheet")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pixel_ratio", &self.pixel_ratio)
+            .field("images", &self.images.len())
+            .finish()
+    }
+}
+
+/// Returns the physical pixels per logical pixel of the sprite which is used on a display with
+/// `device_pixel_ratio`. Like tiles, displays with at least two physical pixels per logical pixel
+/// use the high resolution sprite.
+pub fn sprite_pixel_ratio(device_pixel_ratio: f64) -> f32 {
+    if tile_url::ratio_suffix(device_pixel_ratio).is_empty() {
+        1.0
+    } else {
+        2.0
+    }
+}
+
+/// Returns the URL of the index (`json`) or the image (`png`) of the sprite at `url` for a
+/// display with `device_pixel_ratio`, see [`sprite_pixel_ratio`].
+pub fn sprite_url(url: &str, device_pixel_ratio: f64, extension: &str) -> String {
+    let ratio = tile_url::ratio_suffix(device_pixel_ratio);
+    match url.find('?') {
+        Some(query) => format!("{}{}.{}{}", &url[..query], ratio, extension, &url[query..]),
+        None => format!("{}{}.{}", url, ratio, extension),
+    }
+}
+
+impl SpriteSheet {
+    /// Parses the JSON `index` and decodes the `png` of a sprite with `pixel_ratio` physical pixels
+    /// per logical pixel. Images which exceed the bounds of the PNG are skipped. Images without a
+    /// `pixelRatio` in the index use the `pixel_ratio` of the sprite.
+    pub fn from_data(index: &[u8], png: &[u8], pixel_ratio: f32) -> Result<Self, Error> {
+        let index: HashMap<String, IndexEntry> =
+            serde_json::from_slice(index).map_err(|e| Error::Sprite(e.to_string()))?;
+        let image = image::load_from_memory(png)
+            .map_err(|e| Error::Sprite(e.to_string()))?
+            .to_rgba8();
+        let (width, height) = image.dimensions();
+
+        let images = index
+            .into_iter()
+            .map(|(name, entry)| {
+                let image = SpriteImage {
+                    x: entry.x,
+                    y: entry.y,
+                    width: entry.width,
+                    height: entry.height,
+                    pixel_ratio: entry.pixel_ratio.unwrap_or(pixel_ratio),
+                };
+                (name, image)
+            })
+            .filter(|(name, image)| {
+                let contained = image.x.saturating_add(image.width) <= width
+                    && image.y.saturating_add(image.height) <= height;
+                if !contained {
+                    log::warn!("Sprite image {} exceeds the sprite sheet", name);
+                }
+                contained
+            })
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            data: image.into_raw(),
+            pixel_ratio,
+            images,
+        })
+    }
+
+    /// Fetches the index and the PNG of the sprite at `url`. The high resolution sprite is fetched
+    /// on HiDPI displays, falling back to the normal sprite if it is not available.
+    pub async fn fetch<HC>(
+        url: &str,
+        http_client: &HC,
+        device_pixel_ratio: f64,
+    ) -> Result<Self, Error>
+    where
+        HC: HTTPClient,
+    {
+        if sprite_pixel_ratio(device_pixel_ratio) > 1.0 {
+            match Self::fetch_with_ratio(url, http_client, device_pixel_ratio).await {
+                Ok(sprite) => return Ok(sprite),
+                Err(e) => log::warn!(
+                    "Failed to load high resolution sprite {}, falling back: {:?}",
+                    url,
+                    e
+                ),
+            }
+        }
+
+        Self::fetch_with_ratio(url, http_client, 1.0).await
+    }
+
+    async fn fetch_with_ratio<HC>(
+        url: &str,
+        http_client: &HC,
+        device_pixel_ratio: f64,
+    ) -> Result<Self, Error>
+    where
+        HC: HTTPClient,
+    {
+        let index = http_client
+            .fetch(&sprite_url(url, device_pixel_ratio, "json"))
+            .await?;
+        let png = http_client
+            .fetch(&sprite_url(url, device_pixel_ratio, "png"))
+            .await?;
+        Self::from_data(&index, &png, sprite_pixel_ratio(device_pixel_ratio))
+    }
+
+    pub fn image(&self, name: &str) -> Option<&SpriteImage> {
+        self.images.get(name)
+    }
+
+    /// Returns where the image `name` is located within the texture of the sprite, such that it
+    /// can be repeated across polygons. Returns `None` if there is no such image.
+    pub fn pattern(&self, name: &str) -> Option<SpritePattern> {
+        let image = self.image(name)?;
+
+        Some(SpritePattern {
+            tex_coords: [
+                image.x as f32 / self.width as f32,
+                image.y as f32 / self.height as f32,
+                (image.x + image.width) as f32 / self.width as f32,
+                (image.y + image.height) as f32 / self.height as f32,
+            ],
+            size: [
+                image.width as f32 / image.pixel_ratio,
+                image.height as f32 / image.pixel_ratio,
+            ],
+        })
+    }
+
+    /// Lays out the image `name` as an icon relative to its anchor in pixels. The icon is scaled
+    /// by `icon_size`, see `icon-size`. Returns `None` if there is no such image.
+    pub fn layout_icon(&self, name: &str, icon_size: f32, anchor: IconAnchor) -> Option<GlyphQuad> {
+        let image = self.image(name)?;
+
+        let width = image.width as f32 / image.pixel_ratio * icon_size;
+        let height = image.height as f32 / image.pixel_ratio * icon_size;
+
+        let (horizontal, vertical) = anchor.alignment();
+        let left = -width * horizontal;
+        let top = -height * vertical;
+
+        Some(GlyphQuad {
+            top_left: [left, top],
+            bottom_right: [left + width, top + height],
+            tex_top_left: [
+                image.x as f32 / self.width as f32,
+                image.y as f32 / self.height as f32,
+            ],
+            tex_bottom_right: [
+                (image.x + image.width) as f32 / self.width as f32,
+                (image.y + image.height) as f32 / self.height as f32,
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sprite_pixel_ratio, sprite_url, SpriteSheet};
+    use crate::error::Error;
+    use crate::io::source_client::HTTPClient;
+    use crate::style::layer::IconAnchor;
+    use async_trait::async_trait;
+    use image::codecs::png::PngEncoder;
+    use image::{ColorType, ImageEncoder};
+    use std::collections::HashMap;
+    use std::ops::Range;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Serves the files of a sprite from memory.
+    #[derive(Clone, Default)]
+    struct SpriteFiles(Arc<HashMap<String, Vec<u8>>>);
+
+    #[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
+    #[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
+    impl HTTPClient for SpriteFiles {
+        async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
+            self.0
+                .get(url)
+                .cloned()
+                .ok_or_else(|| Error::Network(format!("{} not found", url)))
+        }
+
+        async fn fetch_range(&self, url: &str, _range: Range<u64>) -> Result<Vec<u8>, Error> {
+            self.fetch(url).await
+        }
+
+        async fn sleep(&self, _duration: Duration) {}
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(
+                &vec![255; (width * height * 4) as usize],
+                width,
+                height,
+                ColorType::Rgba8,
+            )
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn test_sprite_url() {
+        assert_eq!(
+            sprite_url("https://example.com/sprite", 1.0, "json"),
+            "https://example.com/sprite.json"
+        );
+        assert_eq!(
+            sprite_url("https://example.com/sprite?key=abc", 2.0, "png"),
+            "https://example.com/sprite@2x.png?key=abc"
+        );
+        assert_eq!(sprite_pixel_ratio(1.0), 1.0);
+        assert_eq!(sprite_pixel_ratio(1.5), 1.0);
+        assert_eq!(sprite_pixel_ratio(3.0), 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_matching_sprite() {
+        // The high resolution index lacks the pixel ratio, which is taken from the sprite then
+        let files = SpriteFiles(Arc::new(HashMap::from([
+            (
+                "sprite.json".to_string(),
+                br#"{"cafe": {"x": 16, "y": 0, "width": 16, "height": 8}}"#.to_vec(),
+            ),
+            ("sprite.png".to_string(), png(32, 8)),
+            (
+                "sprite@2x.json".to_string(),
+                br#"{"cafe": {"x": 32, "y": 0, "width": 32, "height": 16}}"#.to_vec(),
+            ),
+            ("sprite@2x.png".to_string(), png(64, 16)),
+        ])));
+
+        let normal = SpriteSheet::fetch("sprite", &files, 1.0).await.unwrap();
+        assert_eq!((normal.width, normal.pixel_ratio), (32, 1.0));
+        let high = SpriteSheet::fetch("sprite", &files, 2.0).await.unwrap();
+        assert_eq!((high.width, high.pixel_ratio), (64, 2.0));
+        assert_eq!(high.image("cafe").unwrap().pixel_ratio, 2.0);
+
+        // Icons cover the same logical pixels and texture coordinates in both sprites
+        let normal_quad = normal.layout_icon("cafe", 1.0, IconAnchor::Center).unwrap();
+        let high_quad = high.layout_icon("cafe", 1.0, IconAnchor::Center).unwrap();
+        assert_eq!(normal_quad.bottom_right, [8.0, 4.0]);
+        assert_eq!(normal_quad.bottom_right, high_quad.bottom_right);
+        assert_eq!(normal_quad.tex_top_left, high_quad.tex_top_left);
+
+        // Without a high resolution sprite the normal one is used
+        let mut files = (*files.0).clone();
+        files.remove("sprite@2x.png");
+        let fallback = SpriteSheet::fetch("sprite", &SpriteFiles(Arc::new(files)), 2.0)
+            .await
+            .unwrap();
+        assert_eq!(fallback.pixel_ratio, 1.0);
+    }
+
+    #[test]
+    fn test_from_data() {
+        let index = br#"{
+            "cafe": {"x": 0, "y": 0, "width": 32, "height": 16, "pixelRatio": 2},
+            "shop": {"x": 32, "y": 0, "width": 16, "height": 16, "sdf": false},
+            "outside": {"x": 60, "y": 0, "width": 16, "height": 16}
+        }"#;
+        let sprite = SpriteSheet::from_data(index, &png(64, 16), 1.0).unwrap();
+
+        assert_eq!((sprite.width, sprite.height), (64, 16));
+        assert_eq!(sprite.data.len(), 64 * 16 * 4);
+        assert_eq!(sprite.image("shop").unwrap().pixel_ratio, 1.0);
+        assert!(sprite.image("outside").is_none());
+        assert!(SpriteSheet::from_data(b"[]", &png(64, 16), 1.0).is_err());
+    }
+
+    #[test]
+    fn test_layout_icon() {
+        let index = br#"{"cafe": {"x": 32, "y": 0, "width": 32, "height": 16, "pixelRatio": 2}}"#;
+        let sprite = SpriteSheet::from_data(index, &png(64, 16), 1.0).unwrap();
+
+        let quad = sprite.layout_icon("cafe", 1.0, IconAnchor::Center).unwrap();
+        assert_eq!(quad.top_left, [-8.0, -4.0]);
+        assert_eq!(quad.bottom_right, [8.0, 4.0]);
+        assert_eq!(quad.tex_top_left, [0.5, 0.0]);
+        assert_eq!(quad.tex_bottom_right, [1.0, 1.0]);
+
+        let quad = sprite
+            .layout_icon("cafe", 2.0, IconAnchor::BottomLeft)
+            .unwrap();
+        assert_eq!(quad.top_left, [0.0, -16.0]);
+        assert_eq!(quad.bottom_right, [32.0, 0.0]);
+
+        assert!(sprite
+            .layout_icon("shop", 1.0, IconAnchor::Center)
+            .is_none());
+    }
+
+    #[test]
+    fn test_pattern() {
+        let index = br#"{"hatch": {"x": 32, "y": 0, "width": 16, "height": 16, "pixelRatio": 2}}"#;
+        let sprite = SpriteSheet::from_data(index, &png(64, 16), 2.0).unwrap();
+
+        let pattern = sprite.pattern("hatch").unwrap();
+        assert_eq!(pattern.tex_coords, [0.5, 0.0, 0.75, 1.0]);
+        assert_eq!(pattern.size, [8.0, 8.0]);
+        assert!(sprite.pattern("dots").is_none());
+    }
+}
@@ -0,0 +1,492 @@
+//! Persistent cache of tessellated layers, which trades disk space for the CPU time of Lyon. Once a
+//! layer of a vector tile is tessellated, its vertices, indices and features are written to disk.
+//! The next time the layer is requested, e.g. after a restart, it is read back and uploaded to
+//! the buffer pool without decoding or tessellating it again. Caches can also be filled ahead of
+//! time and shipped with an application as precompiled tiles.
+//!
+//! Each file starts with a header which holds the [`FORMAT_VERSION`], the size of a
+//! [`ShaderVertex`] and the [`LineStyle`] the layer was tessellated with. Files whose header does
+//! not match are ignored and replaced once the layer is tessellated again, so changes of the
+//! vertex layout or the tessellation invalidate old caches safely. All numbers are little endian:
+//!
+//! | Bytes | Content                                        |
+//! |-------|------------------------------------------------|
+//! | 4     | Magic bytes `MLTS`                             |
+//! | 4     | [`FORMAT_VERSION`]                             |
+//! | 4     | Size of a [`ShaderVertex`]                     |
+//! | 8     | Line cap, line join, hollow, zero, miter limit |
+//! | 16    | Counts of vertices, indices, features and the length of the layer |
+//! | ...   | Vertices, indices and index counts of the features |
+//! | ...   | The layer with its features as MVT protobuf    |
+//!
+//! Layers of GeoJSON sources are not cached, because they are sliced from data which changes at
+//! runtime.
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::tessellation_pool::LayerGeometry;
+use crate::io::LayerTessellateMessage;
+use crate::render::ShaderVertex;
+use crate::style::layer::{LineCap, LineJoin};
+use crate::tessellation::{IndexDataType, LineStyle};
+use bytemuck::Pod;
+use geozero::mvt::tile;
+use instant::Instant;
+use lyon::tessellation::VertexBuffers;
+use prost::Message;
+use std::fs;
+use std::io::ErrorKind;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+
+/// Identifies files of this cache.
+const MAGIC: &[u8; 4] = b"MLTS";
+
+/// Version of the format of the cached layers. Increase it whenever the format, the layout of
+/// [`ShaderVertex`] or the output of the tessellation changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Length of the header which precedes the data of a layer.
+const HEADER_LENGTH: usize = 36;
+
+/// Extension of the files within the cache directory.
+const LAYER_EXTENSION: &str = "layer";
+
+/// Stores tessellated layers keyed by source, coordinates and layer name within a directory.
+/// Unlike [`crate::io::disk_cache::DiskTileCache`], the cache is not limited in size, such that
+/// precompiled tiles are never evicted.
+#[derive(Clone, Debug)]
+pub struct TessellationCache {
+    directory: PathBuf,
+}
+
+impl TessellationCache {
+    /// Opens the cache within `directory` and creates the directory if it is missing.
+    pub fn open<P: AsRef<Path>>(directory: P) -> Result<Self, Error> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory).map_err(to_error)?;
+        Ok(Self { directory })
+    }
+
+    /// Returns the layer `layer_name` at `coords` of `source`, `None` for the default tile source,
+    /// if it was cached with the same format and `line_style`.
+    pub fn get(
+        &self,
+        source: Option<&str>,
+        coords: &WorldTileCoords,
+        layer_name: &str,
+        line_style: &LineStyle,
+    ) -> Option<(tile::Layer, LayerGeometry)> {
+        let path = self
+            .directory
+            .join(file_name(source, coords, layer_name, line_style));
+        let data = fs::read(path).ok()?;
+        match decode_layer(&data, line_style) {
+            Ok(layer) => Some(layer),
+            Err(e) => {
+                log::debug!(
+                    "ignoring cached layer {} at {}: {:?}",
+                    layer_name,
+                    coords,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Stores a tessellated layer. A previously cached version of the layer is replaced.
+    pub fn put(
+        &self,
+        source: Option<&str>,
+        coords: &WorldTileCoords,
+        layer_data: &tile::Layer,
+        geometry: &LayerGeometry,
+        line_style: &LineStyle,
+    ) -> Result<(), Error> {
+        let data = encode_layer(
+            layer_data,
+            &geometry.buffer,
+            &geometry.feature_indices,
+            line_style,
+        );
+        let path = self
+            .directory
+            .join(file_name(source, coords, &layer_data.name, line_style));
+        fs::write(path, data).map_err(to_error)
+    }
+
+    /// Stores the layer of a [`LayerTessellateMessage`], e.g. to precompile the tiles of an area.
+    /// Unavailable layers are not stored.
+    pub fn put_message(
+        &self,
+        message: &LayerTessellateMessage,
+        line_style: &LineStyle,
+    ) -> Result<(), Error> {
+        if let Some(data) = encode_message(message, line_style) {
+            let path = self.directory.join(file_name(
+                message.source(),
+                &message.get_coords(),
+                message.layer_name(),
+                line_style,
+            ));
+            fs::write(path, data).map_err(to_error)?;
+        }
+        Ok(())
+    }
+
+    /// Removes all cached layers.
+    pub fn clear(&self) -> Result<(), Error> {
+        for entry in fs::read_dir(&self.directory).map_err(to_error)? {
+            let path = entry.map_err(to_error)?.path();
+            if path.extension().and_then(|extension| extension.to_str()) == Some(LAYER_EXTENSION) {
+                if let Err(e) = fs::remove_file(path) {
+                    if e.kind() != ErrorKind::NotFound {
+                        return Err(to_error(e));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serializes the layer of a [`LayerTessellateMessage`]. Returns `None` for unavailable layers.
+pub fn encode_message(message: &LayerTessellateMessage, line_style: &LineStyle) -> Option<Vec<u8>> {
+    match message {
+        LayerTessellateMessage::UnavailableLayer { .. } => None,
+        LayerTessellateMessage::TessellatedLayer {
+            buffer,
+            feature_indices,
+            layer_data,
+            ..
+        } => {
+            // The padding of the indices is added again once the layer is loaded
+            let usable_indices = buffer.usable_indices as usize;
+            let vertex_buffers = VertexBuffers {
+                vertices: buffer.buffer.vertices.clone(),
+                indices: buffer.buffer.indices[..usable_indices].to_vec(),
+            };
+            Some(encode_layer(
+                layer_data,
+                &vertex_buffers,
+                feature_indices,
+                line_style,
+            ))
+        }
+    }
+}
+
+/// Serializes a tessellated layer together with the header of the current format.
+pub fn encode_layer(
+    layer_data: &tile::Layer,
+    buffer: &VertexBuffers<ShaderVertex, IndexDataType>,
+    feature_indices: &[u32],
+    line_style: &LineStyle,
+) -> Vec<u8> {
+    let layer_bytes = layer_data.encode_to_vec();
+    let vertex_bytes: &[u8] = bytemuck::cast_slice(&buffer.vertices);
+    let index_bytes: &[u8] = bytemuck::cast_slice(&buffer.indices);
+    let feature_bytes: &[u8] = bytemuck::cast_slice(feature_indices);
+
+    let mut data = Vec::with_capacity(
+        HEADER_LENGTH
+            + vertex_bytes.len()
+            + index_bytes.len()
+            + feature_bytes.len()
+            + layer_bytes.len(),
+    );
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    data.extend_from_slice(&(size_of::<ShaderVertex>() as u32).to_le_bytes());
+    data.extend_from_slice(&encode_line_style(line_style));
+    for count in [
+        buffer.vertices.len(),
+        buffer.indices.len(),
+        feature_indices.len(),
+        layer_bytes.len(),
+    ] {
+        data.extend_from_slice(&(count as u32).to_le_bytes());
+    }
+    // The buffers are written in the byte order of the GPU, which is little endian on all
+    // supported platforms
+    data.extend_from_slice(vertex_bytes);
+    data.extend_from_slice(index_bytes);
+    data.extend_from_slice(feature_bytes);
+    data.extend_from_slice(&layer_bytes);
+    data
+}
+
+/// Deserializes a layer which was serialized by [`encode_layer`] with the same `line_style`. The
+/// duration of the returned geometry is the time it took to load it.
+pub fn decode_layer(
+    data: &[u8],
+    line_style: &LineStyle,
+) -> Result<(tile::Layer, LayerGeometry), Error> {
+    let start = Instant::now();
+    if data.len() < HEADER_LENGTH || &data[0..4] != MAGIC {
+        return Err(Error::Cache("not a tessellated layer".to_string()));
+    }
+    let version = read_u32(data, 4);
+    if version != FORMAT_VERSION {
+        return Err(Error::Cache(format!(
+            "format version {} is not {}",
+            version, FORMAT_VERSION
+        )));
+    }
+    if read_u32(data, 8) as usize != size_of::<ShaderVertex>()
+        || data[12..20] != encode_line_style(line_style)
+    {
+        return Err(Error::Cache(
+            "tessellated with a different vertex layout or line style".to_string(),
+        ));
+    }
+
+    let vertex_count = read_u32(data, 20) as usize;
+    let index_count = read_u32(data, 24) as usize;
+    let feature_count = read_u32(data, 28) as usize;
+    let layer_length = read_u32(data, 32) as usize;
+
+    let mut offset = HEADER_LENGTH;
+    let vertices = read_pod::<ShaderVertex>(data, &mut offset, vertex_count)?;
+    let indices = read_pod::<IndexDataType>(data, &mut offset, index_count)?;
+    let feature_indices = read_pod::<u32>(data, &mut offset, feature_count)?;
+    let layer_bytes = data
+        .get(offset..offset + layer_length)
+        .ok_or_else(|| Error::Cache("truncated layer".to_string()))?;
+    let layer_data =
+        tile::Layer::decode(layer_bytes).map_err(|e| Error::InvalidTile(e.to_string()))?;
+
+    Ok((
+        layer_data,
+        LayerGeometry {
+            buffer: VertexBuffers { vertices, indices },
+            feature_indices,
+            duration: start.elapsed(),
+        },
+    ))
+}
+
+fn encode_line_style(line_style: &LineStyle) -> [u8; 8] {
+    let cap = match line_style.cap {
+        LineCap::Butt => 0,
+        LineCap::Round => 1,
+        LineCap::Square => 2,
+    };
+    let join = match line_style.join {
+        LineJoin::Bevel => 0,
+        LineJoin::Round => 1,
+        LineJoin::Miter => 2,
+    };
+    let miter_limit = line_style.miter_limit.to_le_bytes();
+    [
+        cap,
+        join,
+        line_style.hollow as u8,
+        0,
+        miter_limit[0],
+        miter_limit[1],
+        miter_limit[2],
+        miter_limit[3],
+    ]
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Reads `count` values at `offset` and advances it. The data is copied, because it is not
+/// necessarily aligned for `T`.
+fn read_pod<T: Pod>(data: &[u8], offset: &mut usize, count: usize) -> Result<Vec<T>, Error> {
+    let length = count * size_of::<T>();
+    let bytes = data
+        .get(*offset..*offset + length)
+        .ok_or_else(|| Error::Cache("truncated buffer".to_string()))?;
+    let mut values = vec![T::zeroed(); count];
+    bytemuck::cast_slice_mut::<T, u8>(&mut values).copy_from_slice(bytes);
+    *offset += length;
+    Ok(values)
+}
+
+/// Derives a file name from the source, coordinates, layer name and line style, such that layers
+/// which are tessellated with different line styles do not replace each other. The line style is
+/// included with all bytes of its encoding in the header, so distinct styles never share a file.
+fn file_name(
+    source: Option<&str>,
+    coords: &WorldTileCoords,
+    layer_name: &str,
+    line_style: &LineStyle,
+) -> String {
+    let line_style: String = encode_line_style(line_style)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!(
+        "{}-{}-{}-{}-{}-{}.{}",
+        escape(source.unwrap_or("default")),
+        coords.z,
+        coords.x,
+        coords.y,
+        escape(layer_name),
+        line_style,
+        LAYER_EXTENSION
+    )
+}
+
+/// Escapes characters which are not allowed in file names, like the slashes of URLs. ASCII
+/// letters and digits are kept and all other bytes are written as `_` followed by their hex
+/// value. Unlike replacing them, this keeps names like `a/b` and `a_b` apart.
+pub(crate) fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("_{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+fn to_error(error: std::io::Error) -> Error {
+    Error::Cache(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_layer, encode_layer, file_name, TessellationCache, FORMAT_VERSION};
+    use crate::coords::WorldTileCoords;
+    use crate::io::tessellation_pool::LayerGeometry;
+    use crate::render::ShaderVertex;
+    use crate::style::layer::LineJoin;
+    use crate::tessellation::LineStyle;
+    use geozero::mvt::tile;
+    use lyon::tessellation::VertexBuffers;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    /// Returns an empty directory which is unique to the test `name` and this process, such that
+    /// tests which run concurrently do not share files.
+    fn directory(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "maplibre-tessellation-cache-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    fn layer() -> (tile::Layer, LayerGeometry) {
+        let layer_data = tile::Layer {
+            version: 2,
+            name: "water".to_string(),
+            extent: Some(4096),
+            ..tile::Layer::default()
+        };
+        let geometry = LayerGeometry {
+            buffer: VertexBuffers {
+                vertices: vec![
+                    ShaderVertex::new([0.0, 0.0], [0.0, 0.0], 0.0),
+                    ShaderVertex::new([4096.0, 0.0], [0.0, 1.0], 2.5),
+                    ShaderVertex::new([0.0, 4096.0], [1.0, 0.0], 0.0),
+                ],
+                indices: vec![0, 1, 2],
+            },
+            feature_indices: vec![3],
+            duration: Duration::ZERO,
+        };
+        (layer_data, geometry)
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        let (layer_data, geometry) = layer();
+        let line_style = LineStyle::default();
+        let data = encode_layer(
+            &layer_data,
+            &geometry.buffer,
+            &geometry.feature_indices,
+            &line_style,
+        );
+
+        let (decoded_layer, decoded_geometry) = decode_layer(&data, &line_style).unwrap();
+        assert_eq!(decoded_layer, layer_data);
+        assert_eq!(decoded_geometry.buffer.indices, vec![0, 1, 2]);
+        assert_eq!(decoded_geometry.feature_indices, vec![3]);
+        assert_eq!(decoded_geometry.buffer.vertices[1].position, [4096.0, 0.0]);
+        assert_eq!(decoded_geometry.buffer.vertices[1].line_distance, 2.5);
+
+        // Layers of an other version or line style are ignored, as well as truncated layers
+        let mut old_version = data.clone();
+        old_version[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(decode_layer(&old_version, &line_style).is_err());
+        let hollow = LineStyle {
+            hollow: true,
+            ..line_style
+        };
+        assert!(decode_layer(&data, &hollow).is_err());
+        assert!(decode_layer(&data[..data.len() - 1], &line_style).is_err());
+    }
+
+    #[test]
+    fn test_get_and_put() {
+        let directory = directory("get-and-put");
+        let cache = TessellationCache::open(&directory).unwrap();
+        let coords = WorldTileCoords { x: 1, y: 2, z: 3 };
+        let line_style = LineStyle::default();
+
+        assert!(cache.get(None, &coords, "water", &line_style).is_none());
+        let (layer_data, geometry) = layer();
+        cache
+            .put(None, &coords, &layer_data, &geometry, &line_style)
+            .unwrap();
+        assert!(cache.get(None, &coords, "water", &line_style).is_some());
+        assert!(cache
+            .get(Some("satellite"), &coords, "water", &line_style)
+            .is_none());
+
+        // Layers tessellated with another line style are kept side by side
+        let round = LineStyle {
+            join: LineJoin::Round,
+            ..line_style
+        };
+        assert!(cache.get(None, &coords, "water", &round).is_none());
+        cache
+            .put(None, &coords, &layer_data, &geometry, &round)
+            .unwrap();
+        assert!(cache.get(None, &coords, "water", &round).is_some());
+        assert!(cache.get(None, &coords, "water", &line_style).is_some());
+
+        cache.clear().unwrap();
+        assert!(cache.get(None, &coords, "water", &line_style).is_none());
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_file_name() {
+        let coords = WorldTileCoords { x: 1, y: 2, z: 3 };
+        let line_style = LineStyle::default();
+        let name = |source, layer_name| file_name(source, &coords, layer_name, &line_style);
+
+        assert_ne!(name(Some("a/b"), "water"), name(Some("a_b"), "water"));
+        assert_ne!(name(None, "road-label"), name(None, "road_label"));
+        assert!(name(Some("https://example.com/tiles"), "water")
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'));
+
+        let miter_limit = LineStyle {
+            miter_limit: line_style.miter_limit + 1.0,
+            ..line_style
+        };
+        assert_ne!(
+            name(None, "water"),
+            file_name(None, &coords, "water", &miter_limit)
+        );
+    }
+}
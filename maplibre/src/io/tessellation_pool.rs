@@ -0,0 +1,158 @@
+//! Tessellates the layers of vector tiles on a pool of threads. Lyon tessellates each layer
+//! independently, so the layers of a tile, as well as those of the tiles which are processed at the
+//! same time, are spread across all cores by [rayon](https://docs.rs/rayon).
+//!
+//! Web builds run on a single thread, so their layers are tessellated one after another.
+//!
+//! The results are returned in the order of the layers regardless of which layer finishes first,
+//! such that layers are always uploaded in the same order and do not flicker. With
+//! [`for_each_ordered`] each result is passed on as soon as it and all results before it are done,
+//! such that the first layers of a tile are drawn while the later ones are still tessellated.
+
+use crate::error::Error;
+use crate::render::ShaderVertex;
+use crate::tessellation::zero_tessellator::ZeroTessellator;
+use crate::tessellation::{IndexDataType, LineStyle};
+use geozero::mvt::tile;
+use geozero::GeozeroDatasource;
+use instant::Instant;
+use lyon::tessellation::VertexBuffers;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::BTreeMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+/// The geometry of a tessellated layer.
+pub struct LayerGeometry {
+    pub buffer: VertexBuffers<ShaderVertex, IndexDataType>,
+    /// Number of indices of each feature of the layer.
+    pub feature_indices: Vec<u32>,
+    /// How long the tessellation took.
+    pub duration: Duration,
+}
+
+/// Tessellates all features of `layer`. Returns [`Error::Cancelled`] once `is_cancelled` returns
+/// true before a feature is tessellated.
+pub fn tessellate_layer(
+    layer: &mut tile::Layer,
+    line_style: &LineStyle,
+    is_cancelled: impl Fn() -> bool + 'static,
+) -> Result<LayerGeometry, Error> {
+    let start = Instant::now();
+    let mut tessellator: ZeroTessellator<IndexDataType> = ZeroTessellator::default()
+        .with_line_style(line_style)
+        .with_cancellation(is_cancelled);
+    let result = layer.process(&mut tessellator);
+    if tessellator.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+    result.map_err(|e| Error::InvalidTile(format!("{:?}", e)))?;
+
+    Ok(LayerGeometry {
+        buffer: tessellator.buffer,
+        feature_indices: tessellator.feature_indices,
+        duration: start.elapsed(),
+    })
+}
+
+/// Applies `f` to all `items` on the thread pool and returns the results in the order of `items`.
+/// The calling thread blocks until all items are done.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn map_ordered<T, R, F>(items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Send + Sync,
+{
+    use rayon::prelude::*;
+
+    items.into_par_iter().map(f).collect()
+}
+
+/// Applies `f` to all `items` one after another, because there are no threads on the web.
+#[cfg(target_arch = "wasm32")]
+pub fn map_ordered<T, R, F>(items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Send + Sync,
+{
+    items.into_iter().map(f).collect()
+}
+
+/// Applies `f` to all `items` on the thread pool and passes the results to `consume` on the
+/// calling thread in the order of `items`. A result is consumed as soon as all results before it
+/// are consumed, without waiting for the remaining items.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn for_each_ordered<T, R, F, C>(items: Vec<T>, f: F, mut consume: C)
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Sync + 'static,
+    C: FnMut(R),
+{
+    let f = Arc::new(f);
+    let (sender, receiver) = mpsc::channel();
+    for (index, item) in items.into_iter().enumerate() {
+        let f = f.clone();
+        let sender = sender.clone();
+        rayon::spawn(move || {
+            // The receiver is only gone if consuming panicked
+            let _ = sender.send((index, f(item)));
+        });
+    }
+    drop(sender);
+
+    let mut done = BTreeMap::new();
+    let mut next = 0;
+    for (index, result) in receiver {
+        done.insert(index, result);
+        while let Some(result) = done.remove(&next) {
+            consume(result);
+            next += 1;
+        }
+    }
+}
+
+/// Applies `f` to all `items` one after another and passes each result to `consume` right away.
+#[cfg(target_arch = "wasm32")]
+pub fn for_each_ordered<T, R, F, C>(items: Vec<T>, f: F, mut consume: C)
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Sync + 'static,
+    C: FnMut(R),
+{
+    for item in items {
+        consume(f(item));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{for_each_ordered, map_ordered};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_results_keep_the_order_of_items() {
+        // Earlier items finish last
+        let results = map_ordered((0..8u64).collect(), |i| {
+            thread::sleep(Duration::from_millis(8 - i));
+            i * 2
+        });
+        assert_eq!(results, vec![0, 2, 4, 6, 8, 10, 12, 14]);
+
+        let mut consumed = Vec::new();
+        for_each_ordered(
+            (0..8u64).collect(),
+            |i| {
+                thread::sleep(Duration::from_millis(8 - i));
+                i * 2
+            },
+            |result| consumed.push(result),
+        );
+        assert_eq!(consumed, results);
+    }
+}
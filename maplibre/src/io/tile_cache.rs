@@ -2,54 +2,264 @@
 
 use crate::coords::{Quadkey, WorldTileCoords};
 
-use crate::io::LayerTessellateMessage;
+use crate::io::glyphs::GlyphRanges;
+use crate::io::tile_json::TileJSON;
+use crate::io::{LayerTessellateMessage, RasterTileMessage};
+use crate::render::ShaderVertex;
+use crate::tessellation::IndexDataType;
 
-use std::collections::{btree_map, BTreeMap, HashSet};
+use prost::Message;
+use std::collections::{btree_map, BTreeMap, HashMap, HashSet};
+use std::mem::size_of;
 
-/// Stores the multiple [crate::io::LayerTessellateMessage] of a cached tile.
+/// Stores the multiple [crate::io::LayerTessellateMessage] and [crate::io::RasterTileMessage] of a
+/// cached tile.
 pub struct CachedTile {
+    coords: WorldTileCoords,
     layers: Vec<LayerTessellateMessage>,
+    rasters: Vec<RasterTileMessage>,
+    /// The value of the clock of the [`TileCache`] when the tile was in view the last time.
+    last_used: u64,
 }
 
 impl CachedTile {
-    pub fn new(first_layer: LayerTessellateMessage) -> Self {
+    pub fn new(coords: WorldTileCoords) -> Self {
         Self {
-            layers: vec![first_layer],
+            coords,
+            layers: vec![],
+            rasters: vec![],
+            last_used: 0,
         }
     }
+
+    /// Estimates the number of bytes which the tessellated layers and the raster tiles occupy.
+    pub fn bytes(&self) -> usize {
+        let layers: usize = self
+            .layers
+            .iter()
+            .map(|layer| match layer {
+                LayerTessellateMessage::UnavailableLayer { .. } => 0,
+                LayerTessellateMessage::TessellatedLayer {
+                    buffer,
+                    feature_indices,
+                    layer_data,
+                    ..
+                } => {
+                    buffer.buffer.vertices.len() * size_of::<ShaderVertex>()
+                        + buffer.buffer.indices.len() * size_of::<IndexDataType>()
+                        + feature_indices.len() * size_of::<u32>()
+                        + layer_data.encoded_len()
+                }
+            })
+            .sum();
+        let rasters: usize = self
+            .rasters
+            .iter()
+            .map(|raster| match raster {
+                RasterTileMessage::UnavailableRaster { .. } => 0,
+                RasterTileMessage::Raster { data, .. } => data.len(),
+            })
+            .sum();
+        layers + rasters
+    }
 }
 
 /// Stores and provides access to a quad tree of cached tiles with world tile coords.
 #[derive(Default)]
 pub struct TileCache {
     cache: BTreeMap<Quadkey, CachedTile>,
+    /// Fetched TileJSON documents by their URL. `None` if a document is unavailable.
+    tile_jsons: HashMap<String, Option<TileJSON>>,
+    /// Ranges of glyphs which labels need or which have been fetched.
+    glyph_ranges: GlyphRanges,
+    /// Incremented with each call of [`TileCache::evict`].
+    clock: u64,
 }
 
 impl TileCache {
     pub fn new() -> Self {
         Self {
             cache: BTreeMap::new(),
+            tile_jsons: HashMap::new(),
+            glyph_ranges: GlyphRanges::default(),
+            clock: 0,
         }
     }
 
+    /// Number of cached tiles.
+    pub fn tile_count(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Estimates the number of bytes which the cached tiles occupy, see [`CachedTile::bytes`].
+    pub fn bytes(&self) -> usize {
+        self.cache
+            .values()
+            .map(|cached_tile| cached_tile.bytes())
+            .sum()
+    }
+
+    /// Marks the tiles for which `is_retained` returns true as used. Afterwards, the least recently
+    /// used tiles which are not retained are evicted until the cached tiles fit into `budget`
+    /// bytes. Evicted tiles are requested again once they are in view. Returns the number of
+    /// evicted tiles.
+    pub fn evict(
+        &mut self,
+        budget: Option<usize>,
+        is_retained: impl Fn(&WorldTileCoords) -> bool,
+    ) -> usize {
+        self.clock += 1;
+
+        let mut evictable = Vec::new();
+        for (key, cached_tile) in &mut self.cache {
+            if is_retained(&cached_tile.coords) {
+                cached_tile.last_used = self.clock;
+            } else {
+                evictable.push((*key, cached_tile.last_used));
+            }
+        }
+
+        let budget = match budget {
+            Some(budget) => budget,
+            None => return 0,
+        };
+
+        let mut used = self.bytes();
+        if used <= budget {
+            return 0;
+        }
+
+        // Least recently used tiles last, so that they are evicted first
+        evictable.sort_by_key(|(_, last_used)| std::cmp::Reverse(*last_used));
+
+        let mut evicted = 0;
+        while used > budget {
+            let key = match evictable.pop() {
+                Some((key, _)) => key,
+                None => break,
+            };
+            if let Some(cached_tile) = self.cache.remove(&key) {
+                used -= cached_tile.bytes();
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+
+    pub fn put_tile_json(&mut self, url: String, tile_json: Option<TileJSON>) {
+        self.tile_jsons.insert(url, tile_json);
+    }
+
+    /// Returns `None` if the TileJSON document at `url` has not been fetched yet and `Some(None)`
+    /// if it is unavailable.
+    pub fn get_tile_json(&self, url: &str) -> Option<Option<&TileJSON>> {
+        self.tile_jsons.get(url).map(|tile_json| tile_json.as_ref())
+    }
+
+    pub fn glyph_ranges(&self) -> &GlyphRanges {
+        &self.glyph_ranges
+    }
+
+    pub fn glyph_ranges_mut(&mut self) -> &mut GlyphRanges {
+        &mut self.glyph_ranges
+    }
+
     /// Inserts a tessellated layer into the quad tree at its world tile coords.
     /// If the space is vacant, the tessellated layer is inserted into a new
     /// [crate::io::tile_cache::CachedTile].
     /// If the space is occupied, the tessellated layer is added to the current
     /// [crate::io::tile_cache::CachedTile].
     pub fn put_tessellated_layer(&mut self, message: LayerTessellateMessage) {
-        if let Some(entry) = message
-            .get_coords()
+        if let Some(cached_tile) = self.cached_tile_mut(message.get_coords()) {
+            cached_tile.layers.push(message);
+        }
+    }
+
+    /// Inserts a raster tile into the quad tree at its world tile coords.
+    pub fn put_raster_tile(&mut self, message: RasterTileMessage) {
+        if let Some(cached_tile) = self.cached_tile_mut(message.get_coords()) {
+            cached_tile.rasters.push(message);
+        }
+    }
+
+    /// Returns the cached tile at `coords`, which is inserted if the space is vacant. Tiles are
+    /// inserted as recently used, such that they are not evicted before they were in view.
+    fn cached_tile_mut(&mut self, coords: WorldTileCoords) -> Option<&mut CachedTile> {
+        let clock = self.clock;
+        coords
             .build_quad_key()
-            .map(|key| self.cache.entry(key))
-        {
-            match entry {
-                btree_map::Entry::Vacant(entry) => {
-                    entry.insert(CachedTile::new(message));
-                }
-                btree_map::Entry::Occupied(mut entry) => {
-                    entry.get_mut().layers.push(message);
-                }
+            .map(|key| match self.cache.entry(key) {
+                btree_map::Entry::Vacant(entry) => entry.insert(CachedTile {
+                    last_used: clock,
+                    ..CachedTile::new(coords)
+                }),
+                btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            })
+    }
+
+    /// Removes the raster tiles of the given source from all cached tiles.
+    pub fn remove_raster_source(&mut self, source: &str) {
+        for cached_tile in self.cache.values_mut() {
+            cached_tile
+                .rasters
+                .retain(|raster| raster.source() != source);
+        }
+    }
+
+    /// Removes the layers of the given source from the cached tiles for which `is_affected` returns
+    /// true, such that they are requested again. Returns the number of affected tiles.
+    pub fn remove_source_layers(
+        &mut self,
+        source: &str,
+        is_affected: impl Fn(&WorldTileCoords) -> bool,
+    ) -> usize {
+        let mut affected = 0;
+        for cached_tile in self.cache.values_mut() {
+            if is_affected(&cached_tile.coords) {
+                cached_tile
+                    .layers
+                    .retain(|layer| layer.source() != Some(source));
+                affected += 1;
+            }
+        }
+        affected
+    }
+
+    /// Returns the raster tile of the given source at the given world tile coords. None if the
+    /// raster tile is missing from the cache.
+    pub fn get_raster_tile_at(
+        &self,
+        coords: &WorldTileCoords,
+        source: &str,
+    ) -> Option<&RasterTileMessage> {
+        coords
+            .build_quad_key()
+            .and_then(|key| self.cache.get(&key))
+            .and_then(|cached_tile| {
+                cached_tile
+                    .rasters
+                    .iter()
+                    .find(|raster| raster.source() == source)
+            })
+    }
+
+    /// Returns the available raster tile of the given source at the given world tile coords.
+    /// If it is not available, then the raster tile of the closest parent is returned.
+    pub fn get_raster_tile_fallback(
+        &self,
+        coords: &WorldTileCoords,
+        source: &str,
+    ) -> Option<&RasterTileMessage> {
+        let mut current = *coords;
+        loop {
+            if let Some(raster @ RasterTileMessage::Raster { .. }) =
+                self.get_raster_tile_at(&current, source)
+            {
+                return Some(raster);
+            } else if let Some(parent) = current.get_parent() {
+                current = parent
+            } else {
+                return None;
             }
         }
     }
@@ -66,30 +276,38 @@ impl TileCache {
             .map(|results| results.layers.iter())
     }
 
-    /// Removes all the cached tessellate layers that are not contained within the given
-    /// layers hashset.
+    /// Removes the layers of `source` which are cached already from the given layers hashset.
+    /// `source` is `None` for the default tile source.
     pub fn retain_missing_layer_names(
         &self,
         coords: &WorldTileCoords,
+        source: Option<&str>,
         layers: &mut HashSet<String>,
     ) {
         if let Some(cached_tile) = coords.build_quad_key().and_then(|key| self.cache.get(&key)) {
-            let tessellated_set: HashSet<String> = cached_tile
+            let tessellated_set: HashSet<&str> = cached_tile
                 .layers
                 .iter()
-                .map(|tessellated_layer| tessellated_layer.layer_name().to_string())
+                .filter(|tessellated_layer| tessellated_layer.source() == source)
+                .map(|tessellated_layer| tessellated_layer.layer_name())
                 .collect();
 
-            layers.retain(|layer| !tessellated_set.contains(layer));
+            layers.retain(|layer| !tessellated_set.contains(layer.as_str()));
         }
     }
 
-    /// Checks if a layer is missing from the given layers set at the given coords.
-    pub fn is_layers_missing(&self, coords: &WorldTileCoords, layers: &HashSet<String>) -> bool {
+    /// Checks if a layer of `source` from the given layers set is missing at the given coords.
+    pub fn is_layers_missing(
+        &self,
+        coords: &WorldTileCoords,
+        source: Option<&str>,
+        layers: &HashSet<String>,
+    ) -> bool {
         if let Some(cached_tile) = coords.build_quad_key().and_then(|key| self.cache.get(&key)) {
             let tessellated_set: HashSet<&str> = cached_tile
                 .layers
                 .iter()
+                .filter(|tessellated_layer| tessellated_layer.source() == source)
                 .map(|tessellated_layer| tessellated_layer.layer_name())
                 .collect();
 
@@ -104,3 +322,51 @@ impl TileCache {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TileCache;
+    use crate::coords::WorldTileCoords;
+    use crate::io::{RasterFormat, RasterTileMessage};
+
+    fn raster(x: i32) -> RasterTileMessage {
+        RasterTileMessage::Raster {
+            coords: WorldTileCoords { x, y: 0, z: 3 },
+            source: "satellite".to_string(),
+            width: 4,
+            height: 4,
+            format: RasterFormat::Rgba8,
+            data: vec![0; 64].into_boxed_slice(),
+        }
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let mut tile_cache = TileCache::new();
+        for x in 0..4 {
+            tile_cache.put_raster_tile(raster(x));
+        }
+        assert_eq!(tile_cache.tile_count(), 4);
+        assert_eq!(tile_cache.bytes(), 4 * 64);
+
+        // Without a budget nothing is evicted
+        assert_eq!(tile_cache.evict(None, |coords| coords.x == 0), 0);
+        // Tile 1 was in view more recently than tiles 2 and 3
+        assert_eq!(tile_cache.evict(None, |coords| coords.x == 1), 0);
+
+        // Tiles in view are never evicted, even if they exceed the budget
+        assert_eq!(tile_cache.evict(Some(2 * 64), |coords| coords.x == 0), 2);
+        assert_eq!(tile_cache.bytes(), 2 * 64);
+        let is_cached = |tile_cache: &TileCache, x| {
+            tile_cache
+                .get_raster_tile_at(&WorldTileCoords { x, y: 0, z: 3 }, "satellite")
+                .is_some()
+        };
+        assert!(is_cached(&tile_cache, 0));
+        assert!(is_cached(&tile_cache, 1));
+        assert!(!is_cached(&tile_cache, 2));
+
+        assert_eq!(tile_cache.evict(Some(0), |coords| coords.x == 0), 1);
+        assert_eq!(tile_cache.tile_count(), 1);
+    }
+}
@@ -0,0 +1,297 @@
+//! Parsing of [TileJSON](https://github.com/mapbox/tilejson-spec) documents which describe where
+//! and at which zoom levels the tiles of a source are available.
+
+use crate::coords::{LatLon, WorldTileCoords, Zoom, TILE_SIZE, ZOOM_BOUNDS};
+use crate::error::Error;
+use crate::io::tile_url;
+use crate::style::source::{TileAddressingScheme, TileUrl, VectorSource};
+use serde::{Deserialize, Serialize};
+
+/// Default of `maxzoom` according to the TileJSON specification.
+const DEFAULT_MAXZOOM: u8 = 30;
+/// Default of `bounds` according to the TileJSON specification, which covers the whole world.
+const DEFAULT_BOUNDS: [f64; 4] = [-180.0, -85.051129, 180.0, 85.051129];
+
+/// The properties of a TileJSON document which are needed to request tiles.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TileJSON {
+    /// URL templates which contain the place holders `{x}`, `{y}` and `{z}`, see
+    /// [`tile_url::expand`].
+    pub tiles: Vec<TileUrl>,
+    /// Subdomains which replace the place holder `{s}` in turns. This is not part of the TileJSON
+    /// specification, but can be set on the source in the style.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subdomains: Vec<String>,
+    #[serde(default)]
+    pub minzoom: u8,
+    #[serde(default = "default_maxzoom")]
+    pub maxzoom: u8,
+    /// West, south, east and north edge of the area in which tiles are available, in degrees.
+    #[serde(default = "default_bounds")]
+    pub bounds: [f64; 4],
+    #[serde(default)]
+    pub scheme: TileAddressingScheme,
+}
+
+fn default_maxzoom() -> u8 {
+    DEFAULT_MAXZOOM
+}
+
+fn default_bounds() -> [f64; 4] {
+    DEFAULT_BOUNDS
+}
+
+impl TileJSON {
+    /// Parses a fetched TileJSON document. Documents without any tile URL are rejected.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let tile_json: TileJSON =
+            serde_json::from_slice(data).map_err(|e| Error::TileJson(e.to_string()))?;
+        if tile_json.tiles.is_empty() {
+            return Err(Error::TileJson("no tile URLs".to_string()));
+        }
+        Ok(tile_json)
+    }
+
+    /// Builds the TileJSON of a source which lists its tile URLs inline. Returns `None` if the
+    /// source has no tile URL.
+    pub fn from_source(source: &VectorSource) -> Option<Self> {
+        let tiles = source.tiles.clone().filter(|tiles| !tiles.is_empty())?;
+        Some(
+            Self {
+                tiles,
+                subdomains: Vec::new(),
+                minzoom: 0,
+                maxzoom: DEFAULT_MAXZOOM,
+                bounds: DEFAULT_BOUNDS,
+                scheme: TileAddressingScheme::default(),
+            }
+            .with_overrides(source),
+        )
+    }
+
+    /// Properties which are set explicitly in the style take precedence over the TileJSON.
+    pub fn with_overrides(mut self, source: &VectorSource) -> Self {
+        if let Some(tiles) = source.tiles.as_ref().filter(|tiles| !tiles.is_empty()) {
+            self.tiles = tiles.clone();
+        }
+        if let Some(subdomains) = &source.subdomains {
+            self.subdomains = subdomains.clone();
+        }
+        if let Some(minzoom) = source.minzoom {
+            self.minzoom = minzoom;
+        }
+        if let Some(maxzoom) = source.maxzoom {
+            self.maxzoom = maxzoom;
+        }
+        if let Some((west, south, east, north)) = source.bounds {
+            self.bounds = [west, south, east, north];
+        }
+        if let Some(scheme) = &source.scheme {
+            self.scheme = scheme.clone();
+        }
+        self
+    }
+
+    /// Requests tiles in high resolution if the URL templates contain the place holder `{ratio}`
+    /// or `{r}` and the display has at least two physical pixels per logical pixel, like Mapbox GL
+    /// does.
+    pub fn with_pixel_ratio(mut self, device_pixel_ratio: f64) -> Self {
+        let ratio = tile_url::ratio_suffix(device_pixel_ratio);
+        for tile in &mut self.tiles {
+            for place_holder in tile_url::RATIO_PLACE_HOLDERS {
+                *tile = tile.replace(place_holder, ratio);
+            }
+        }
+        self
+    }
+
+    /// Returns true if the source provides the tile at `coords`, i.e. its zoom level is within
+    /// `minzoom..=maxzoom` and it intersects the `bounds`.
+    pub fn contains(&self, coords: &WorldTileCoords) -> bool {
+        if coords.z < self.minzoom || coords.z > self.maxzoom {
+            return false;
+        }
+
+        let bounds = match ZOOM_BOUNDS.get(coords.z as usize) {
+            Some(bounds) => *bounds as i32,
+            None => return false,
+        };
+        if coords.x < 0 || coords.y < 0 || coords.x >= bounds || coords.y >= bounds {
+            return false;
+        }
+
+        let [west, south, east, north] = self.bounds;
+        let zoom = Zoom::new(coords.z as f64);
+        let north_west = LatLon::new(north, west).into_world(zoom);
+        let south_east = LatLon::new(south, east).into_world(zoom);
+
+        let min_x = (north_west.x / TILE_SIZE).floor() as i32;
+        let min_y = (north_west.y / TILE_SIZE).floor() as i32;
+        let max_x = (south_east.x / TILE_SIZE).ceil() as i32 - 1;
+        let max_y = (south_east.y / TILE_SIZE).ceil() as i32 - 1;
+
+        (min_x..=max_x).contains(&coords.x) && (min_y..=max_y).contains(&coords.y)
+    }
+
+    /// Returns the URL of the tile at `coords`. The URL templates and subdomains are used in turns
+    /// such that requests are spread across all servers.
+    pub fn tile_url(&self, coords: &WorldTileCoords) -> Option<String> {
+        let tile_coords = coords.into_tile(self.scheme.clone())?;
+        let template = self
+            .tiles
+            .get(tile_url::rotation_index(&tile_coords, self.tiles.len()))?;
+
+        Some(tile_url::expand(
+            template,
+            &tile_coords,
+            &self.subdomains,
+            "",
+        ))
+    }
+
+    /// The first URL template, which identifies the source in caches.
+    pub fn cache_key(&self) -> &str {
+        self.tiles.first().map_or("", |tile| tile.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TileJSON;
+    use crate::coords::WorldTileCoords;
+    use crate::style::source::{TileAddressingScheme, VectorSource};
+
+    fn tile_json() -> TileJSON {
+        TileJSON::parse(
+            br#"{
+                "tilejson": "2.2.0",
+                "tiles": [
+                    "https://a.example.com/{z}/{x}/{y}.pbf",
+                    "https://b.example.com/{z}/{x}/{y}.pbf"
+                ],
+                "minzoom": 2,
+                "maxzoom": 14,
+                "bounds": [5.8, 47.2, 15.1, 55.1],
+                "scheme": "tms"
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let tile_json = tile_json();
+        assert_eq!(tile_json.tiles.len(), 2);
+        assert_eq!(tile_json.minzoom, 2);
+        assert_eq!(tile_json.maxzoom, 14);
+        assert_eq!(tile_json.scheme, TileAddressingScheme::TMS);
+
+        let minimal =
+            TileJSON::parse(br#"{"tiles": ["https://example.com/{z}/{x}/{y}"]}"#).unwrap();
+        assert_eq!(minimal.minzoom, 0);
+        assert_eq!(minimal.maxzoom, 30);
+        assert_eq!(minimal.scheme, TileAddressingScheme::XYZ);
+
+        assert!(TileJSON::parse(br#"{"tiles": []}"#).is_err());
+        assert!(TileJSON::parse(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let tile_json = tile_json();
+
+        // Germany at zoom level 6
+        assert!(tile_json.contains(&WorldTileCoords { x: 33, y: 21, z: 6 }));
+        // Outside of the zoom range
+        assert!(!tile_json.contains(&WorldTileCoords { x: 0, y: 0, z: 1 }));
+        assert!(!tile_json.contains(&WorldTileCoords {
+            x: 8500,
+            y: 5500,
+            z: 15
+        }));
+        // Outside of the bounds
+        assert!(!tile_json.contains(&WorldTileCoords { x: 10, y: 21, z: 6 }));
+        assert!(!tile_json.contains(&WorldTileCoords { x: 33, y: 40, z: 6 }));
+    }
+
+    #[test]
+    fn test_tile_url() {
+        let tile_json = tile_json();
+
+        // The y coordinate is flipped for TMS
+        assert_eq!(
+            tile_json
+                .tile_url(&WorldTileCoords { x: 33, y: 21, z: 6 })
+                .unwrap(),
+            "https://b.example.com/6/33/42.pbf"
+        );
+        assert_eq!(
+            tile_json
+                .tile_url(&WorldTileCoords { x: 34, y: 21, z: 6 })
+                .unwrap(),
+            "https://a.example.com/6/34/42.pbf"
+        );
+    }
+
+    #[test]
+    fn test_pixel_ratio() {
+        let tile_json =
+            TileJSON::parse(br#"{"tiles": ["https://example.com/{z}/{x}/{y}{ratio}.png"]}"#)
+                .unwrap();
+        let coords = WorldTileCoords { x: 1, y: 2, z: 3 };
+
+        assert_eq!(
+            tile_json.tile_url(&coords).unwrap(),
+            "https://example.com/3/1/2.png"
+        );
+        assert_eq!(
+            tile_json
+                .clone()
+                .with_pixel_ratio(1.5)
+                .tile_url(&coords)
+                .unwrap(),
+            "https://example.com/3/1/2.png"
+        );
+        assert_eq!(
+            tile_json.with_pixel_ratio(2.0).tile_url(&coords).unwrap(),
+            "https://example.com/3/1/2@2x.png"
+        );
+    }
+
+    #[test]
+    fn test_style_overrides() {
+        let source: VectorSource = serde_json::from_value(serde_json::json!({
+            "url": "https://example.com/tiles.json",
+            "maxzoom": 10
+        }))
+        .unwrap();
+
+        let tile_json = tile_json().with_overrides(&source);
+        assert_eq!(tile_json.minzoom, 2);
+        assert_eq!(tile_json.maxzoom, 10);
+        assert!(TileJSON::from_source(&source).is_none());
+    }
+
+    #[test]
+    fn test_subdomains() {
+        let source: VectorSource = serde_json::from_value(serde_json::json!({
+            "tiles": ["https://{s}.example.com/{z}/{x}/{y}.pbf"],
+            "subdomains": ["t1", "t2"]
+        }))
+        .unwrap();
+        let tile_json = TileJSON::from_source(&source).unwrap();
+
+        assert_eq!(
+            tile_json
+                .tile_url(&WorldTileCoords { x: 3, y: 4, z: 5 })
+                .unwrap(),
+            "https://t2.example.com/5/3/4.pbf"
+        );
+        assert_eq!(
+            tile_json
+                .tile_url(&WorldTileCoords { x: 4, y: 4, z: 5 })
+                .unwrap(),
+            "https://t1.example.com/5/4/4.pbf"
+        );
+    }
+}
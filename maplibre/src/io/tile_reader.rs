@@ -0,0 +1,177 @@
+//! Reads the layers of vector tiles one at a time. The layers of a tile are the only top-level
+//! field of the [MVT protobuf](https://github.com/mapbox/vector-tile-spec), so a tile is split
+//! into its layers by reading their lengths and names, without decoding any features. Each layer
+//! is then decoded right before it is tessellated, such that the first layers of large tiles are
+//! drawn while the remaining ones are still being decoded.
+
+use crate::error::Error;
+use geozero::mvt::tile;
+use prost::Message;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Field number of the layers in a tile.
+const TILE_LAYERS: u64 = 3;
+/// Field number of the name in a layer.
+const LAYER_NAME: u64 = 1;
+
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_64_BIT: u64 = 1;
+const WIRE_TYPE_LENGTH_DELIMITED: u64 = 2;
+const WIRE_TYPE_32_BIT: u64 = 5;
+
+/// A layer of a tile which has not been decoded yet.
+#[derive(Clone, Debug)]
+pub struct RawLayer {
+    pub name: String,
+    data: Arc<[u8]>,
+    range: Range<usize>,
+}
+
+impl RawLayer {
+    /// Decodes the features of the layer.
+    pub fn decode(&self) -> Result<tile::Layer, Error> {
+        tile::Layer::decode(&self.data[self.range.clone()])
+            .map_err(|e| Error::InvalidTile(e.to_string()))
+    }
+}
+
+/// Splits the tile `data` into its layers in the order in which they are stored.
+pub fn read_layers(data: Arc<[u8]>) -> Result<Vec<RawLayer>, Error> {
+    let mut layers = Vec::new();
+    let mut reader = FieldReader::new(&data, 0..data.len());
+    while let Some((field, value)) = reader.next_field()? {
+        if let (TILE_LAYERS, FieldValue::Bytes(range)) = (field, value) {
+            let name = read_layer_name(&data, range.clone())?;
+            layers.push(RawLayer {
+                name,
+                data: data.clone(),
+                range,
+            });
+        }
+    }
+    Ok(layers)
+}
+
+/// Reads the name of the layer which is stored at `range` of `data`.
+fn read_layer_name(data: &[u8], range: Range<usize>) -> Result<String, Error> {
+    let mut reader = FieldReader::new(data, range);
+    while let Some((field, value)) = reader.next_field()? {
+        if let (LAYER_NAME, FieldValue::Bytes(range)) = (field, value) {
+            return String::from_utf8(data[range].to_vec())
+                .map_err(|e| Error::InvalidTile(e.to_string()));
+        }
+    }
+    Err(Error::InvalidTile("layer without a name".to_string()))
+}
+
+/// Value of a protobuf field. Only the values of length-delimited fields are kept.
+enum FieldValue {
+    Bytes(Range<usize>),
+    Other,
+}
+
+/// Reads the fields of a protobuf message without decoding their values.
+struct FieldReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    end: usize,
+}
+
+impl<'a> FieldReader<'a> {
+    fn new(data: &'a [u8], range: Range<usize>) -> Self {
+        Self {
+            data,
+            position: range.start,
+            end: range.end,
+        }
+    }
+
+    /// Returns the number and value of the next field, or `None` at the end of the message.
+    fn next_field(&mut self) -> Result<Option<(u64, FieldValue)>, Error> {
+        if self.position >= self.end {
+            return Ok(None);
+        }
+
+        let key = self.read_varint()?;
+        let value = match key & 0x7 {
+            WIRE_TYPE_VARINT => {
+                self.read_varint()?;
+                FieldValue::Other
+            }
+            WIRE_TYPE_64_BIT => {
+                self.skip(8)?;
+                FieldValue::Other
+            }
+            WIRE_TYPE_LENGTH_DELIMITED => {
+                let length = self.read_varint()? as usize;
+                let start = self.position;
+                self.skip(length)?;
+                FieldValue::Bytes(start..self.position)
+            }
+            WIRE_TYPE_32_BIT => {
+                self.skip(4)?;
+                FieldValue::Other
+            }
+            wire_type => {
+                return Err(Error::InvalidTile(format!(
+                    "unsupported wire type {}",
+                    wire_type
+                )))
+            }
+        };
+        Ok(Some((key >> 3, value)))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.position)
+                .filter(|_| self.position < self.end)
+                .ok_or_else(|| Error::InvalidTile("truncated varint".to_string()))?;
+            self.position += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(Error::InvalidTile("varint is too long".to_string()))
+    }
+
+    fn skip(&mut self, length: usize) -> Result<(), Error> {
+        match self.position.checked_add(length) {
+            Some(position) if position <= self.end => {
+                self.position = position;
+                Ok(())
+            }
+            _ => Err(Error::InvalidTile("truncated field".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_layers;
+    use geozero::mvt::Tile;
+    use prost::Message;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_layers_are_decoded_like_the_whole_tile() {
+        let data = include_bytes!("../../../test-data/fixtures/fill-heavy.pbf");
+        let tile = Tile::decode(&data[..]).unwrap();
+
+        let layers = read_layers(Arc::from(&data[..])).unwrap();
+        let names: Vec<_> = layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(names, vec!["building", "landcover"]);
+        for (raw_layer, layer) in layers.iter().zip(&tile.layers) {
+            assert_eq!(&raw_layer.decode().unwrap(), layer);
+        }
+
+        // Truncated tiles are detected without decoding any features
+        assert!(read_layers(Arc::from(&data[..data.len() - 1])).is_err());
+        assert!(read_layers(Arc::from(&[][..])).unwrap().is_empty());
+    }
+}
@@ -4,12 +4,30 @@ use crate::coords::WorldTileCoords;
 use crate::io::{TileRequest, TileRequestID};
 use std::collections::{HashMap, HashSet};
 
+/// Outcome of [`TileRequestState::start_tile_request`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum TileRequestStart {
+    /// No request of the tile was pending, so the tile needs to be fetched.
+    New(TileRequestID),
+    /// The tile is fetched already. Layers which the pending request did not contain are added to
+    /// it, such that they are tessellated from the same data instead of fetching the tile twice.
+    /// Layers which are added once the fetched tile is tessellated are missed and requested again
+    /// with the next request of the tile.
+    Coalesced,
+}
+
 /// Stores a map of pending requests, coords and the current tile being requested.
 #[derive(Default)]
 pub struct TileRequestState {
     current_id: TileRequestID,
     pending_tile_requests: HashMap<TileRequestID, TileRequest>,
-    pending_coords: HashSet<WorldTileCoords>,
+    /// Pending requests by the coords and source of their tile.
+    pending_coords: HashMap<(WorldTileCoords, Option<String>), TileRequestID>,
+    pending_raster_tiles: HashSet<(WorldTileCoords, String)>,
+    pending_geojson_tiles: HashSet<(WorldTileCoords, String)>,
+    /// Pending GeoJSON tiles which are sliced from data which has been replaced in the meantime.
+    /// Their layers are dropped once they arrive and the tiles are requested again.
+    stale_geojson_tiles: HashSet<(WorldTileCoords, String)>,
 }
 
 impl TileRequestState {
@@ -18,28 +36,47 @@ impl TileRequestState {
             current_id: 1,
             pending_tile_requests: Default::default(),
             pending_coords: Default::default(),
+            pending_raster_tiles: Default::default(),
+            pending_geojson_tiles: Default::default(),
+            stale_geojson_tiles: Default::default(),
         }
     }
 
-    pub fn is_tile_request_pending(&self, coords: &WorldTileCoords) -> bool {
-        self.pending_coords.contains(coords)
+    pub fn is_tile_request_pending(&self, coords: &WorldTileCoords, source: Option<&str>) -> bool {
+        self.pending_coords
+            .contains_key(&(*coords, source.map(|source| source.to_string())))
     }
 
-    pub fn start_tile_request(&mut self, tile_request: TileRequest) -> Option<TileRequestID> {
-        if self.is_tile_request_pending(&tile_request.coords) {
-            return None;
+    /// Starts a request of a tile, unless the same tile of the same source is requested already.
+    /// Such duplicates are coalesced with the pending request, see [`TileRequestStart`].
+    pub fn start_tile_request(&mut self, tile_request: TileRequest) -> TileRequestStart {
+        let key = (tile_request.coords, tile_request.source.clone());
+
+        if let Some(pending) = self
+            .pending_coords
+            .get(&key)
+            .and_then(|id| self.pending_tile_requests.get_mut(id))
+        {
+            for layer in tile_request.layers {
+                if let Some(line_style) = tile_request.line_styles.get(&layer) {
+                    pending.line_styles.insert(layer.clone(), *line_style);
+                }
+                pending.layers.insert(layer);
+            }
+            return TileRequestStart::Coalesced;
         }
 
-        self.pending_coords.insert(tile_request.coords);
         let id = self.current_id;
+        self.pending_coords.insert(key, id);
         self.pending_tile_requests.insert(id, tile_request);
         self.current_id += 1;
-        Some(id)
+        TileRequestStart::New(id)
     }
 
     pub fn finish_tile_request(&mut self, id: TileRequestID) -> Option<TileRequest> {
         self.pending_tile_requests.remove(&id).map(|request| {
-            self.pending_coords.remove(&request.coords);
+            self.pending_coords
+                .remove(&(request.coords, request.source.clone()));
             request
         })
     }
@@ -47,4 +84,120 @@ impl TileRequestState {
     pub fn get_tile_request(&self, id: TileRequestID) -> Option<&TileRequest> {
         self.pending_tile_requests.get(&id)
     }
+
+    /// Marks the raster tile of a source as pending. Returns false if it is already pending.
+    pub fn start_raster_request(&mut self, coords: &WorldTileCoords, source: &str) -> bool {
+        self.pending_raster_tiles
+            .insert((*coords, source.to_string()))
+    }
+
+    pub fn finish_raster_request(&mut self, coords: &WorldTileCoords, source: &str) -> bool {
+        self.pending_raster_tiles
+            .remove(&(*coords, source.to_string()))
+    }
+
+    /// Marks the tile of a GeoJSON source as pending. Returns false if it is already pending.
+    pub fn start_geojson_request(&mut self, coords: &WorldTileCoords, source: &str) -> bool {
+        self.pending_geojson_tiles
+            .insert((*coords, source.to_string()))
+    }
+
+    pub fn finish_geojson_request(&mut self, coords: &WorldTileCoords, source: &str) -> bool {
+        let key = (*coords, source.to_string());
+        self.stale_geojson_tiles.remove(&key);
+        self.pending_geojson_tiles.remove(&key)
+    }
+
+    /// Marks the pending tiles of a GeoJSON source for which `is_affected` returns true as stale,
+    /// because the data of the source has been replaced since they were requested.
+    pub fn invalidate_geojson_requests(
+        &mut self,
+        source: &str,
+        is_affected: impl Fn(&WorldTileCoords) -> bool,
+    ) {
+        for (coords, pending_source) in &self.pending_geojson_tiles {
+            if pending_source == source && is_affected(coords) {
+                self.stale_geojson_tiles
+                    .insert((*coords, pending_source.clone()));
+            }
+        }
+    }
+
+    /// Whether the pending tile of a GeoJSON source is sliced from outdated data, see
+    /// [`TileRequestState::invalidate_geojson_requests`].
+    pub fn is_geojson_request_stale(&self, coords: &WorldTileCoords, source: &str) -> bool {
+        self.stale_geojson_tiles
+            .contains(&(*coords, source.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TileRequestStart, TileRequestState};
+    use crate::coords::WorldTileCoords;
+    use crate::io::TileRequest;
+    use std::collections::HashMap;
+
+    fn request(source: Option<&str>, layers: &[&str]) -> TileRequest {
+        TileRequest {
+            coords: WorldTileCoords { x: 1, y: 2, z: 3 },
+            source: source.map(|source| source.to_string()),
+            layers: layers.iter().map(|layer| layer.to_string()).collect(),
+            line_styles: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_coalesce_duplicate_requests() {
+        let mut state = TileRequestState::new();
+
+        assert_eq!(
+            state.start_tile_request(request(None, &["water"])),
+            TileRequestStart::New(1)
+        );
+        // The same tile is requested again before the first request finished
+        assert_eq!(
+            state.start_tile_request(request(None, &["water"])),
+            TileRequestStart::Coalesced
+        );
+        assert_eq!(
+            state.start_tile_request(request(None, &["water", "roads"])),
+            TileRequestStart::Coalesced
+        );
+        assert_eq!(state.get_tile_request(1).unwrap().layers.len(), 2);
+
+        // Tiles of other sources are fetched separately
+        assert_eq!(
+            state.start_tile_request(request(Some("other"), &["water"])),
+            TileRequestStart::New(2)
+        );
+
+        let finished = state.finish_tile_request(1).unwrap();
+        assert!(finished.layers.contains("roads"));
+        assert!(!state.is_tile_request_pending(&finished.coords, None));
+        assert_eq!(
+            state.start_tile_request(request(None, &["water"])),
+            TileRequestStart::New(3)
+        );
+    }
+
+    #[test]
+    fn test_invalidate_geojson_requests() {
+        let mut state = TileRequestState::new();
+        let affected = WorldTileCoords { x: 1, y: 2, z: 3 };
+        let unaffected = WorldTileCoords { x: 5, y: 2, z: 3 };
+        assert!(state.start_geojson_request(&affected, "vehicles"));
+        assert!(state.start_geojson_request(&unaffected, "vehicles"));
+        assert!(state.start_geojson_request(&affected, "stops"));
+
+        state.invalidate_geojson_requests("vehicles", |coords| coords.x == 1);
+        assert!(state.is_geojson_request_stale(&affected, "vehicles"));
+        assert!(!state.is_geojson_request_stale(&unaffected, "vehicles"));
+        assert!(!state.is_geojson_request_stale(&affected, "stops"));
+
+        // Tiles which are requested again after the stale request finished are not stale
+        assert!(state.finish_geojson_request(&affected, "vehicles"));
+        assert!(state.start_geojson_request(&affected, "vehicles"));
+        assert!(!state.is_geojson_request_stale(&affected, "vehicles"));
+    }
 }
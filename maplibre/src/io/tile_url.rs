@@ -0,0 +1,120 @@
+//! Expansion of tile URL templates like `https://{s}.tiles.example.com/{z}/{x}/{y}{r}.pbf`.
+//!
+//! Browsers limit the number of connections per host, so tile servers are often reachable through
+//! several subdomains. The place holder `{s}` is replaced by one of them, such that the requests of
+//! neighbouring tiles are spread across all subdomains. A tile always uses the same subdomain, so
+//! it is cached only once.
+
+use crate::coords::TileCoords;
+
+/// Subdomains which replace `{s}` if a source does not configure any, like in Leaflet.
+pub const DEFAULT_SUBDOMAINS: [&str; 3] = ["a", "b", "c"];
+
+/// Place holders which are replaced by `@2x` on HiDPI displays.
+pub const RATIO_PLACE_HOLDERS: [&str; 2] = ["{ratio}", "{r}"];
+
+/// Returns the index of the subdomain or server which serves the tile at `coords`, given
+/// `count` of them.
+pub fn rotation_index(coords: &TileCoords, count: usize) -> usize {
+    (coords.x as usize + coords.y as usize) % count.max(1)
+}
+
+/// Returns the suffix of the files of high resolution tiles if the display has at least two
+/// physical pixels per logical pixel.
+pub fn ratio_suffix(device_pixel_ratio: f64) -> &'static str {
+    if device_pixel_ratio >= 2.0 {
+        "@2x"
+    } else {
+        ""
+    }
+}
+
+/// Replaces the place holders of `template` for the tile at `coords`:
+///
+/// * `{x}`, `{y}` and `{z}` by the tile coordinates,
+/// * `{s}` by one of `subdomains`, or of [`DEFAULT_SUBDOMAINS`] if it is empty,
+/// * `{ratio}` and `{r}` by `ratio`, e.g. the [`ratio_suffix`].
+pub fn expand(template: &str, coords: &TileCoords, subdomains: &[String], ratio: &str) -> String {
+    let mut url = template
+        .replace("{x}", &coords.x.to_string())
+        .replace("{y}", &coords.y.to_string())
+        .replace("{z}", &coords.z.to_string());
+
+    if url.contains("{s}") {
+        let subdomain = if subdomains.is_empty() {
+            DEFAULT_SUBDOMAINS[rotation_index(coords, DEFAULT_SUBDOMAINS.len())]
+        } else {
+            subdomains[rotation_index(coords, subdomains.len())].as_str()
+        };
+        url = url.replace("{s}", subdomain);
+    }
+
+    for place_holder in RATIO_PLACE_HOLDERS {
+        url = url.replace(place_holder, ratio);
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand, ratio_suffix};
+    use crate::coords::TileCoords;
+
+    #[test]
+    fn test_subdomain_rotation() {
+        let template = "https://{s}.tiles.example.com/{z}/{x}/{y}.pbf";
+        let subdomains = vec!["t0".to_string(), "t1".to_string()];
+
+        let urls: Vec<_> = [(0, 0), (1, 0), (1, 1), (2, 1), (0, 0)]
+            .iter()
+            .map(|&(x, y)| expand(template, &TileCoords { x, y, z: 5 }, &subdomains, ""))
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://t0.tiles.example.com/5/0/0.pbf",
+                "https://t1.tiles.example.com/5/1/0.pbf",
+                "https://t0.tiles.example.com/5/1/1.pbf",
+                "https://t1.tiles.example.com/5/2/1.pbf",
+                // A tile always uses the same subdomain
+                "https://t0.tiles.example.com/5/0/0.pbf",
+            ]
+        );
+
+        let urls: Vec<_> = (0..4)
+            .map(|x| expand(template, &TileCoords { x, y: 7, z: 3 }, &[], ""))
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://b.tiles.example.com/3/0/7.pbf",
+                "https://c.tiles.example.com/3/1/7.pbf",
+                "https://a.tiles.example.com/3/2/7.pbf",
+                "https://b.tiles.example.com/3/3/7.pbf",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ratio() {
+        let coords = TileCoords { x: 1, y: 2, z: 3 };
+        assert_eq!(
+            expand(
+                "https://example.com/{z}/{x}/{y}{r}.png",
+                &coords,
+                &[],
+                ratio_suffix(2.0)
+            ),
+            "https://example.com/3/1/2@2x.png"
+        );
+        assert_eq!(
+            expand(
+                "https://example.com/{z}/{x}/{y}{ratio}.png",
+                &coords,
+                &[],
+                ratio_suffix(1.0)
+            ),
+            "https://example.com/3/1/2.png"
+        );
+    }
+}
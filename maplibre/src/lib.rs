@@ -4,7 +4,8 @@
 //!
 //! Maplibre-rs is a map renderer that can run natively on MacOS, Linux, Windows, Android, iOS and the web.
 //! It takes advantage of Lyon to tessellate vector tiles and WebGPU to display them efficiently.
-//! Maplibre-rs also has an headless mode (*work in progress*) that can generate rasters.
+//! Maplibre-rs also has an headless mode (*work in progress*) that can generate rasters, see
+//! [`headless::HeadlessMap`].
 //!
 //! The official guide book can be found [here](https://maxammann.org/maplibre-rs/docs/).
 //!
@@ -16,21 +17,45 @@
 //! maplibre = "0.0.2"
 //! ```
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::context::CameraTarget;
+use crate::error::Error;
+use crate::io::disk_cache::DiskTileCache;
+#[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+use crate::io::mbtiles::MbtilesSource;
+use crate::io::pmtiles::PmTilesLocation;
+use crate::io::request_limiter::RequestLimits;
 use crate::io::scheduler::{ScheduleMethod, Scheduler};
 use crate::io::source_client::HTTPClient;
+use crate::io::source_client::{RetryPolicy, SourceClient, TileSource};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::tessellation_cache::TessellationCache;
 use crate::map_schedule::MapSchedule;
+use crate::metrics::{MetricsSink, NoopMetricsSink};
+use crate::redraw::RedrawMode;
+use crate::render::capabilities::RendererCapabilities;
 use crate::render::settings::{RendererSettings, WgpuSettings};
 use crate::render::{RenderState, Renderer};
 use crate::style::Style;
 use crate::window::{MapWindow, MapWindowConfig, Runnable, WindowSize};
+use std::sync::Arc;
 
 pub mod context;
 pub mod coords;
+pub mod custom_layer;
 pub mod error;
+pub mod events;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+pub mod headless;
 pub mod io;
 // Exposed because of input handlers in maplibre-winit
 pub mod map_schedule;
+pub mod markers;
+pub mod metrics;
 pub mod platform;
+pub mod projection;
+pub mod redraw;
 // Exposed because of camera
 pub mod render;
 pub mod style;
@@ -44,6 +69,7 @@ pub mod benchmarking;
 // Internal modules
 pub(crate) mod stages;
 pub(crate) mod tessellation;
+pub(crate) mod text;
 pub(crate) mod util;
 
 /// Map's configuration and execution.
@@ -57,6 +83,95 @@ where
     window: W,
 }
 
+impl<W, SM, HC> Map<W, SM, HC>
+where
+    W: MapWindow,
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    /// Creates the window, initializes the whole rendering pipeline and loads the style of
+    /// `config`. Returns the map, ready to be run.
+    ///
+    /// If the surface of the window is not available yet, see [`MapWindow::surface_available`],
+    /// the renderer is initialized once the window is resumed.
+    pub async fn new(config: MapConfig<W::MapWindowConfig, SM, HC>) -> Self {
+        let MapConfig {
+            mut shared,
+            style_url,
+            camera,
+            redraw_mode,
+            map_window_config,
+        } = config;
+        let window = W::create(&map_window_config);
+        let window_size = window.size();
+
+        let renderer = if window.surface_available() {
+            Renderer::initialize(
+                &window,
+                shared.wgpu_settings.clone(),
+                shared.renderer_settings.clone(),
+            )
+            .await
+            .map_err(|e| log::error!("Failed to initialize the renderer: {:?}", e))
+            .ok()
+        } else {
+            None
+        };
+
+        let source_client = shared.take_source_client();
+
+        let mut style = match &style_url {
+            Some(url) => match Style::from_url(url, &shared.http_client).await {
+                Ok((style, issues)) => {
+                    for issue in issues {
+                        log::warn!("Style {}: {}", url, issue);
+                    }
+                    style
+                }
+                Err(e) => {
+                    log::error!("Failed to load the style {}: {:?}", url, e);
+                    shared.style
+                }
+            },
+            None => shared.style,
+        };
+        for error in style.validate() {
+            log::warn!("Style: {}", error);
+        }
+
+        if style.sprite_sheet.is_none() {
+            if let Err(e) = style
+                .load_sprite(&shared.http_client, window_size.device_pixel_ratio())
+                .await
+            {
+                log::error!("Failed to load the sprite of the style: {:?}", e);
+            }
+        }
+
+        // The camera of the builder takes precedence over the default camera of the style
+        let camera = camera.or(style.camera());
+
+        let mut map_state = MapSchedule::new(
+            map_window_config,
+            window_size,
+            renderer,
+            shared.scheduler,
+            source_client,
+            style,
+            shared.metrics,
+            shared.request_limits,
+            shared.wgpu_settings,
+            shared.renderer_settings,
+        );
+        map_state.view_state_mut().jump_to(camera);
+        map_state.set_redraw_mode(redraw_mode);
+        #[cfg(not(target_arch = "wasm32"))]
+        map_state.set_tessellation_cache(shared.tessellation_cache);
+
+        Map { map_state, window }
+    }
+}
+
 impl<W, SM, HC> Map<W, SM, HC>
 where
     W: MapWindow + Runnable<W::MapWindowConfig, SM, HC>,
@@ -72,7 +187,8 @@ where
     ///
     /// # Arguments
     ///
-    /// * `max_frames` - Maximum number of frames per second.
+    /// * `max_frames` - Number of frames after which the event loop exits. The frame rate is
+    /// limited with [`MapSchedule::set_redraw_mode`] instead.
     pub fn run_with_max_frames(self, max_frames: u64) {
         self.run_with_optionally_max_frames(Some(max_frames));
     }
@@ -81,81 +197,156 @@ where
     ///
     /// # Arguments
     ///
-    /// * `max_frames` - Optional maximum number of frames per second.
+    /// * `max_frames` - Optional number of frames after which the event loop exits.
     pub fn run_with_optionally_max_frames(self, max_frames: Option<u64>) {
         self.window.run(self.map_state, max_frames);
     }
 }
 
-/// Stores the map configuration before the map's state has been fully initialized.
-///
-/// FIXME: We could maybe remove this class, and store the render_state in an Optional in [`crate::map_state::MapState`].
-/// FIXME: I think we can find a workaround so that this class doesn't exist.
-pub struct UninitializedMap<MWC, SM, HC>
+/// Options which [`MapBuilder`] and [`headless::HeadlessMapBuilder`] have in common.
+pub(crate) struct BuilderOptions<SM, HC>
+where
+    SM: ScheduleMethod,
+{
+    pub(crate) schedule_method: Option<SM>,
+    pub(crate) scheduler: Option<Scheduler<SM>>,
+    pub(crate) http_client: Option<HC>,
+    pub(crate) style: Option<Style>,
+    pub(crate) tile_source: Option<TileSource>,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    pub(crate) request_limits: Option<RequestLimits>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) disk_cache: Option<DiskTileCache>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) tessellation_cache: Option<TessellationCache>,
+    pub(crate) metrics: Option<Arc<dyn MetricsSink>>,
+    pub(crate) wgpu_settings: Option<WgpuSettings>,
+    pub(crate) renderer_settings: Option<RendererSettings>,
+}
+
+impl<SM, HC> BuilderOptions<SM, HC>
 where
-    MWC: MapWindowConfig,
     SM: ScheduleMethod,
     HC: HTTPClient,
 {
-    scheduler: Scheduler<SM>,
-    http_client: HC,
-    style: Style,
+    pub(crate) fn new() -> Self {
+        Self {
+            schedule_method: None,
+            scheduler: None,
+            http_client: None,
+            style: None,
+            tile_source: None,
+            retry_policy: None,
+            request_limits: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            disk_cache: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            tessellation_cache: None,
+            metrics: None,
+            wgpu_settings: None,
+            renderer_settings: None,
+        }
+    }
 
-    wgpu_settings: WgpuSettings,
-    renderer_settings: RendererSettings,
-    map_window_config: MWC,
+    /// Fills in the defaults of the options which are not set. Fails if the HTTP client, or
+    /// neither a scheduler nor a schedule method are set.
+    pub(crate) fn resolve(self) -> Result<SharedConfig<SM, HC>, Error> {
+        let scheduler = match (self.scheduler, self.schedule_method) {
+            (Some(scheduler), _) => scheduler,
+            (None, Some(schedule_method)) => Scheduler::new(schedule_method),
+            (None, None) => {
+                return Err(Error::Configuration(
+                    "either a scheduler or a schedule method must be set".to_string(),
+                ))
+            }
+        };
+        let http_client = self
+            .http_client
+            .ok_or_else(|| Error::Configuration("the HTTP client must be set".to_string()))?;
+
+        Ok(SharedConfig {
+            scheduler,
+            http_client,
+            style: self.style.unwrap_or_default(),
+            tile_source: self.tile_source,
+            retry_policy: self.retry_policy.unwrap_or_default(),
+            request_limits: self.request_limits.unwrap_or_default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            disk_cache: self.disk_cache,
+            #[cfg(not(target_arch = "wasm32"))]
+            tessellation_cache: self.tessellation_cache,
+            metrics: self.metrics.unwrap_or_else(|| Arc::new(NoopMetricsSink)),
+            wgpu_settings: self.wgpu_settings.unwrap_or_default(),
+            renderer_settings: self.renderer_settings.unwrap_or_default(),
+        })
+    }
 }
 
-impl<MWC, SM, HC> UninitializedMap<MWC, SM, HC>
+/// The resolved [`BuilderOptions`].
+pub(crate) struct SharedConfig<SM, HC>
 where
-    MWC: MapWindowConfig,
     SM: ScheduleMethod,
-    HC: HTTPClient,
 {
-    /// Initializes the whole rendering pipeline for the given configuration.
-    /// Returns the initialized map, ready to be run.
-    pub async fn initialize(self) -> Map<MWC::MapWindow, SM, HC> {
-        let window = MWC::MapWindow::create(&self.map_window_config);
-        let window_size = window.size();
+    pub(crate) scheduler: Scheduler<SM>,
+    pub(crate) http_client: HC,
+    pub(crate) style: Style,
+    pub(crate) tile_source: Option<TileSource>,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) request_limits: RequestLimits,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) disk_cache: Option<DiskTileCache>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) tessellation_cache: Option<TessellationCache>,
+    pub(crate) metrics: Arc<dyn MetricsSink>,
+    pub(crate) wgpu_settings: WgpuSettings,
+    pub(crate) renderer_settings: RendererSettings,
+}
 
-        #[cfg(target_os = "android")]
-        let renderer = None;
-        #[cfg(not(target_os = "android"))]
-        let renderer = Renderer::initialize(
-            &window,
-            self.wgpu_settings.clone(),
-            self.renderer_settings.clone(),
-        )
-        .await
-        .ok();
-        Map {
-            map_state: MapSchedule::new(
-                self.map_window_config,
-                window_size,
-                renderer,
-                self.scheduler,
-                self.http_client,
-                self.style,
-                self.wgpu_settings,
-                self.renderer_settings,
-            ),
-            window,
-        }
+impl<SM, HC> SharedConfig<SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    /// Creates the client which fetches tiles from the configured tile source. The tile source
+    /// and the disk cache are moved into the client.
+    pub(crate) fn take_source_client(&mut self) -> SourceClient<HC> {
+        let source_client = SourceClient::from_tile_source(
+            self.tile_source.take(),
+            self.http_client.clone(),
+            self.retry_policy.clone(),
+        );
+        #[cfg(not(target_arch = "wasm32"))]
+        let source_client = match self.disk_cache.take() {
+            Some(disk_cache) => source_client.with_disk_cache(disk_cache),
+            None => source_client,
+        };
+        source_client
     }
 }
 
-pub struct MapBuilder<MWC, SM, HC>
+/// The configuration of a map, see [`MapBuilder`] and [`Map::new`].
+pub struct MapConfig<MWC, SM, HC>
 where
+    MWC: MapWindowConfig,
     SM: ScheduleMethod,
+    HC: HTTPClient,
 {
-    schedule_method: Option<SM>,
-    scheduler: Option<Scheduler<SM>>,
-    http_client: Option<HC>,
-    style: Option<Style>,
+    shared: SharedConfig<SM, HC>,
+    style_url: Option<String>,
+    camera: CameraTarget,
+    redraw_mode: RedrawMode,
+    map_window_config: MWC,
+}
 
+pub struct MapBuilder<MWC, SM, HC>
+where
+    SM: ScheduleMethod,
+{
+    options: BuilderOptions<SM, HC>,
+    style_url: Option<String>,
+    camera: Option<CameraTarget>,
+    redraw_mode: Option<RedrawMode>,
     map_window_config: Option<MWC>,
-    wgpu_settings: Option<WgpuSettings>,
-    renderer_settings: Option<RendererSettings>,
 }
 
 impl<MWC, SM, HC> MapBuilder<MWC, SM, HC>
@@ -166,13 +357,11 @@ where
 {
     pub fn new() -> Self {
         Self {
-            schedule_method: None,
-            scheduler: None,
-            http_client: None,
-            style: None,
+            options: BuilderOptions::new(),
+            style_url: None,
+            camera: None,
+            redraw_mode: None,
             map_window_config: None,
-            wgpu_settings: None,
-            renderer_settings: None,
         }
     }
 
@@ -182,49 +371,136 @@ where
     }
 
     pub fn with_renderer_settings(mut self, renderer_settings: RendererSettings) -> Self {
-        self.renderer_settings = Some(renderer_settings);
+        self.options.renderer_settings = Some(renderer_settings);
         self
     }
 
     pub fn with_wgpu_settings(mut self, wgpu_settings: WgpuSettings) -> Self {
-        self.wgpu_settings = Some(wgpu_settings);
+        self.options.wgpu_settings = Some(wgpu_settings);
         self
     }
 
     pub fn with_schedule_method(mut self, schedule_method: SM) -> Self {
-        self.schedule_method = Some(schedule_method);
+        self.options.schedule_method = Some(schedule_method);
         self
     }
 
     pub fn with_http_client(mut self, http_client: HC) -> Self {
-        self.http_client = Some(http_client);
+        self.options.http_client = Some(http_client);
         self
     }
 
     pub fn with_existing_scheduler(mut self, scheduler: Scheduler<SM>) -> Self {
-        self.scheduler = Some(scheduler);
+        self.options.scheduler = Some(scheduler);
         self
     }
 
     pub fn with_style(mut self, style: Style) -> Self {
-        self.style = Some(style);
+        self.options.style = Some(style);
         self
     }
 
-    /// Builds the UninitializedMap with the given configuration.
-    pub fn build(self) -> UninitializedMap<MWC, SM, HC> {
-        let scheduler = self
-            .scheduler
-            .unwrap_or_else(|| Scheduler::new(self.schedule_method.unwrap()));
-        let style = self.style.unwrap_or_default();
+    /// Loads the style from `url` while the map is initialized, see [`Style::from_url`]. Parts of
+    /// the style which are not supported are logged. If the style can not be loaded, the style of
+    /// [`MapBuilder::with_style`] or the default style is used.
+    pub fn with_style_url(mut self, url: &str) -> Self {
+        self.style_url = Some(url.to_string());
+        self
+    }
 
-        UninitializedMap {
-            scheduler,
-            http_client: self.http_client.unwrap(),
-            style,
-            wgpu_settings: self.wgpu_settings.unwrap_or_default(),
-            renderer_settings: self.renderer_settings.unwrap_or_default(),
-            map_window_config: self.map_window_config.unwrap(),
+    /// Positions the camera when the map is initialized. Properties which are `None` are taken
+    /// from the `center`, `zoom`, `pitch` and `bearing` of the style, see [`Style::camera`].
+    pub fn with_camera(mut self, camera: CameraTarget) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    /// Configures how often the map is redrawn, see [`MapSchedule::set_redraw_mode`]. By default
+    /// the map is redrawn continuously.
+    pub fn with_redraw_mode(mut self, redraw_mode: RedrawMode) -> Self {
+        self.redraw_mode = Some(redraw_mode);
+        self
+    }
+
+    /// Reads vector tiles from a PMTiles archive instead of fetching them from a tile server.
+    pub fn with_pmtiles(mut self, location: PmTilesLocation) -> Self {
+        self.options.tile_source = Some(TileSource::PmTiles(location));
+        self
+    }
+
+    /// Reads vector tiles from an MBTiles file instead of fetching them from a tile server.
+    #[cfg(all(feature = "mbtiles", not(target_arch = "wasm32")))]
+    pub fn with_mbtiles(mut self, source: MbtilesSource) -> Self {
+        self.options.tile_source = Some(TileSource::Mbtiles(source));
+        self
+    }
+
+    /// Configures how failed requests to tile servers are retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.options.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Limits how many requests are fetched and tessellated at once. Further requests are queued.
+    pub fn with_request_limits(mut self, request_limits: RequestLimits) -> Self {
+        self.options.request_limits = Some(request_limits);
+        self
+    }
+
+    /// Keeps fetched tiles on disk so that they are not downloaded again after a restart.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_disk_cache(mut self, disk_cache: DiskTileCache) -> Self {
+        self.options.disk_cache = Some(disk_cache);
+        self
+    }
+
+    /// Keeps tessellated layers on disk so that they are not tessellated again after a restart,
+    /// see [`TessellationCache`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_tessellation_cache(mut self, tessellation_cache: TessellationCache) -> Self {
+        self.options.tessellation_cache = Some(tessellation_cache);
+        self
+    }
+
+    /// Reports timings of tile fetching and tessellation as well as the occupancy of the buffer
+    /// pool to `metrics`.
+    pub fn with_metrics_sink(mut self, metrics: Arc<dyn MetricsSink>) -> Self {
+        self.options.metrics = Some(metrics);
+        self
+    }
+
+    /// Returns what the GPU of the device supports, without initializing the renderer. This also
+    /// works if the initialization of the renderer would fail because of unsupported settings.
+    /// Returns `None` if there is no usable GPU.
+    pub async fn capabilities(&self) -> Option<RendererCapabilities> {
+        Renderer::probe_capabilities(&self.options.wgpu_settings.clone().unwrap_or_default()).await
+    }
+
+    /// Builds the map with the given configuration, see [`Map::new`].
+    pub async fn build(self) -> Map<MWC::MapWindow, SM, HC> {
+        Map::new(self.build_config()).await
+    }
+
+    /// Returns the configuration of the map without building it.
+    ///
+    /// # Panics
+    ///
+    /// If the map window config, the HTTP client, or neither a schedule method nor a scheduler
+    /// are set.
+    pub fn build_config(self) -> MapConfig<MWC, SM, HC> {
+        let shared = self
+            .options
+            .resolve()
+            .unwrap_or_else(|e| panic!("the map is not configured completely: {:?}", e));
+
+        MapConfig {
+            shared,
+            style_url: self.style_url,
+            camera: self.camera.unwrap_or_default(),
+            redraw_mode: self.redraw_mode.unwrap_or_default(),
+            map_window_config: self
+                .map_window_config
+                .expect("the map window config must be set"),
         }
     }
 }
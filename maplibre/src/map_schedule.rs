@@ -1,31 +1,51 @@
 //! Stores the state of the map such as `[crate::coords::Zoom]`, `[crate::camera::Camera]`, `[crate::style::Style]`, `[crate::io::tile_cache::TileCache]` and more.
 
 use crate::context::{MapContext, ViewState};
-use crate::error::Error;
-use crate::io::geometry_index::GeometryIndex;
+use crate::coords::{Zoom, TILE_SIZE};
+use crate::custom_layer::{CustomLayer, CustomLayers};
+use crate::error::{Error, RenderError};
+use crate::events::{MapEvent, MapEvents};
+use crate::io::feature_query::{query_rendered_features, QueriedFeature, DEFAULT_QUERY_RADIUS};
+use crate::io::preload::{preload_sources, schedule_preload, PreloadHandle};
+use crate::io::request_limiter::{InFlightRequests, RequestLimits};
 use crate::io::scheduler::Scheduler;
 use crate::io::shared_thread_state::SharedThreadState;
-use crate::io::source_client::{HTTPClient, HttpSourceClient, SourceClient};
+use crate::io::source_client::{HTTPClient, SourceClient};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::tessellation_cache::TessellationCache;
 use crate::io::tile_cache::TileCache;
-use crate::io::tile_request_state::TileRequestState;
 use crate::io::TessellateMessage;
+use crate::markers::{Marker, MarkerId, Markers};
+use crate::metrics::{FrameStats, FrameTimer, MetricsSink};
+use crate::redraw::{RedrawMode, RedrawScheduler};
+use crate::render::capabilities::RendererCapabilities;
+use crate::render::frame_capture::FrameCapture;
 use crate::render::register_render_stages;
+use crate::render::surface_recovery::{SurfaceRecovery, SurfaceRecoveryAction};
 use crate::schedule::{Schedule, Stage};
-use crate::stages::register_stages;
+use crate::stages::{register_stages, PopulateTileStore};
+use crate::style::animation::ColorAnimation;
 use crate::style::Style;
 use crate::{
     MapWindow, MapWindowConfig, Renderer, RendererSettings, ScheduleMethod, WgpuSettings,
     WindowSize,
 };
+use cgmath::{InnerSpace, Vector2, Zero};
+use instant::Instant;
+use raw_window_handle::HasRawWindowHandle;
 use std::marker::PhantomData;
 use std::mem;
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
 
 pub struct PrematureMapContext {
     pub view_state: ViewState,
     pub style: Style,
 
     pub tile_cache: TileCache,
+    pub markers: Markers,
+    /// Kept while there is no renderer, see [`EventuallyMapContext::make_premature`].
+    pub custom_layers: CustomLayers,
     pub scheduler: Box<dyn ScheduleMethod>,
 
     pub message_receiver: mpsc::Receiver<TessellateMessage>,
@@ -42,7 +62,7 @@ pub enum EventuallyMapContext {
 }
 
 impl EventuallyMapContext {
-    pub fn make_full(&mut self, renderer: Renderer) {
+    pub fn make_full(&mut self, mut renderer: Renderer) {
         let context = mem::replace(self, EventuallyMapContext::Empty);
 
         match context {
@@ -51,30 +71,73 @@ impl EventuallyMapContext {
                 view_state,
                 style,
                 tile_cache,
+                markers,
+                mut custom_layers,
                 scheduler,
                 message_receiver,
                 shared_thread_state,
                 wgpu_settings,
                 renderer_settings,
             }) => {
+                // The resources of the custom layers belonged to the previous renderer
+                custom_layers.reset();
+                renderer.state.custom_layers = custom_layers;
                 mem::replace(
                     self,
                     EventuallyMapContext::Full(MapContext {
                         view_state,
                         style,
                         tile_cache,
+                        markers,
                         renderer,
                         scheduler,
                         message_receiver,
                         shared_thread_state,
+                        events: MapEvents::default(),
                     }),
                 );
             }
             EventuallyMapContext::Empty => {}
         }
     }
+
+    /// Drops the renderer and with it all resources on the GPU. The tile cache, the style, the
+    /// markers, the custom layers and the camera are kept, such that the map looks the same once a renderer is
+    /// created again with [`EventuallyMapContext::make_full`].
+    pub fn make_premature(&mut self) {
+        let context = mem::replace(self, EventuallyMapContext::Empty);
+
+        *self = match context {
+            EventuallyMapContext::Full(MapContext {
+                view_state,
+                style,
+                tile_cache,
+                markers,
+                mut renderer,
+                scheduler,
+                message_receiver,
+                shared_thread_state,
+                ..
+            }) => EventuallyMapContext::Premature(PrematureMapContext {
+                view_state,
+                style,
+                tile_cache,
+                markers,
+                custom_layers: mem::take(&mut renderer.state.custom_layers),
+                scheduler,
+                message_receiver,
+                shared_thread_state,
+                wgpu_settings: renderer.wgpu_settings,
+                renderer_settings: renderer.settings,
+            }),
+            context => context,
+        };
+    }
 }
 
+/// Callback which receives the events of every frame, see [`MapSchedule::on_event`].
+type EventListener = Box<dyn FnMut(&MapEvent)>;
+
 /// Stores the state of the map, dispatches tile fetching and caching, tessellation and drawing.
 pub struct MapSchedule<MWC, SM, HC>
 where
@@ -86,12 +149,25 @@ where
 
     map_context: EventuallyMapContext,
 
+    /// Stages which request data and update the state of the map.
     schedule: Schedule,
+    /// Stages which render the map. They are skipped if the map did not change in
+    /// [`RedrawMode::OnDemand`].
+    render_schedule: Schedule,
+    /// The client of the request stage, which also fetches the tiles of
+    /// [`MapSchedule::preload_area`].
+    source_client: SourceClient<HC>,
 
     phantom_sm: PhantomData<SM>,
     phantom_hc: PhantomData<HC>,
 
     suspended: bool,
+    surface_recovery: SurfaceRecovery,
+    frame_timer: FrameTimer,
+    redraw: RedrawScheduler,
+
+    /// Callbacks which receive the events of every frame, see [`MapSchedule::on_event`].
+    listeners: Vec<EventListener>,
 }
 
 impl<MWC, SM, HC> MapSchedule<MWC, SM, HC>
@@ -100,13 +176,16 @@ where
     SM: ScheduleMethod,
     HC: HTTPClient,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         map_window_config: MWC,
         window_size: WindowSize,
         renderer: Option<Renderer>,
         scheduler: Scheduler<SM>,
-        http_client: HC,
+        source_client: SourceClient<HC>,
         style: Style,
+        metrics: Arc<dyn MetricsSink>,
+        request_limits: RequestLimits,
         wgpu_settings: WgpuSettings,
         renderer_settings: RendererSettings,
     ) -> Self {
@@ -114,18 +193,14 @@ where
         let tile_cache = TileCache::new();
 
         let mut schedule = Schedule::default();
-        let client: SourceClient<HC> = SourceClient::Http(HttpSourceClient::new(http_client));
-        register_stages(&mut schedule, client);
-        register_render_stages(&mut schedule);
+        register_stages(&mut schedule, source_client.clone());
+        let mut render_schedule = Schedule::default();
+        register_render_stages(&mut render_schedule);
 
         let (message_sender, message_receiver) = mpsc::channel();
 
         let scheduler = Box::new(scheduler.take());
-        let shared_thread_state = SharedThreadState {
-            tile_request_state: Arc::new(Mutex::new(TileRequestState::new())),
-            message_sender,
-            geometry_index: Arc::new(Mutex::new(GeometryIndex::new())),
-        };
+        let shared_thread_state = SharedThreadState::new(message_sender, metrics, &request_limits);
         Self {
             map_window_config,
             map_context: match renderer {
@@ -133,6 +208,8 @@ where
                     view_state,
                     style,
                     tile_cache,
+                    markers: Markers::default(),
+                    custom_layers: CustomLayers::default(),
                     scheduler,
                     shared_thread_state,
                     wgpu_settings,
@@ -143,16 +220,24 @@ where
                     view_state,
                     style,
                     tile_cache,
+                    markers: Markers::default(),
                     renderer,
                     scheduler,
                     shared_thread_state,
                     message_receiver,
+                    events: MapEvents::default(),
                 }),
             },
             schedule,
+            render_schedule,
+            source_client,
             phantom_sm: Default::default(),
             phantom_hc: Default::default(),
             suspended: false,
+            surface_recovery: SurfaceRecovery::default(),
+            frame_timer: FrameTimer::default(),
+            redraw: RedrawScheduler::default(),
+            listeners: Vec::new(),
         }
     }
 
@@ -162,20 +247,125 @@ where
             return Ok(());
         }
 
-        if let EventuallyMapContext::Full(map_context) = &mut self.map_context {
-            self.schedule.run(map_context)
+        let surface_error = if let EventuallyMapContext::Full(map_context) = &mut self.map_context {
+            let start = Instant::now();
+            self.schedule.run(map_context);
+
+            let camera = map_context.view_state.camera_state();
+            let received_messages = self
+                .schedule
+                .get_stage::<PopulateTileStore>(&"populate_tile_store")
+                .map_or(0, PopulateTileStore::received_messages);
+            let render = self.redraw.needs_render(camera, received_messages);
+            if render {
+                self.render_schedule.run(map_context);
+                self.frame_timer.frame_finished(
+                    start,
+                    start.elapsed(),
+                    self.schedule
+                        .iter_durations()
+                        .chain(self.render_schedule.iter_durations())
+                        .map(|(label, duration)| (label.dyn_clone(), duration))
+                        .collect(),
+                );
+            }
+            self.redraw.updated(
+                Instant::now(),
+                camera,
+                received_messages,
+                render,
+                map_context.is_fully_rendered(),
+            );
+            // Animated layers change every frame
+            if !map_context.style.animations.is_empty() {
+                self.redraw.request();
+            }
+
+            for event in map_context.events.drain() {
+                for listener in &mut self.listeners {
+                    listener(&event);
+                }
+            }
+
+            map_context.renderer.state.take_surface_error()
+        } else {
+            return Ok(());
+        };
+
+        match surface_error {
+            None => self.surface_recovery.frame_presented(),
+            Some(e) => match self
+                .surface_recovery
+                .frame_failed(&e)
+                .map_err(Error::Render)?
+            {
+                SurfaceRecoveryAction::SkipFrame => {}
+                SurfaceRecoveryAction::Reinitialize => {
+                    // The tile cache is kept, such that the map is drawn again right away once
+                    // the renderer is initialized again
+                    self.map_context.make_premature();
+                    return Err(Error::Render(RenderError::DeviceLost));
+                }
+            },
         }
 
         Ok(())
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        if let EventuallyMapContext::Full(map_context) = &mut self.map_context {
-            let view_state = &mut map_context.view_state;
-            view_state.perspective.resize(width, height);
-            view_state.camera.resize(width, height);
+    /// Returns the frame rate and how long the last frame and each of its stages took, e.g. to
+    /// profile a style. With the `trace` feature, the stages are traced as spans as well.
+    pub fn stats(&self) -> &FrameStats {
+        self.frame_timer.stats()
+    }
+
+    /// Changes how often the map is redrawn, e.g. to lower the frame rate while the app is in the
+    /// background or to only redraw the map while it changes. The event loop asks
+    /// [`MapSchedule::next_redraw`] when to update the map next.
+    pub fn set_redraw_mode(&mut self, mode: RedrawMode) {
+        self.redraw.set_mode(mode);
+    }
+
+    pub fn redraw_mode(&self) -> RedrawMode {
+        self.redraw.mode()
+    }
+
+    /// Renders the next frame as soon as possible, also if nothing changed in
+    /// [`RedrawMode::OnDemand`]. Changes through the methods of the map request a redraw by
+    /// themselves, camera moves and data arrivals are detected automatically.
+    pub fn request_redraw(&mut self) {
+        self.redraw.request();
+    }
+
+    /// Returns when the event loop should update the map next, see
+    /// [`RedrawScheduler::next_update`]. `None` while the map is suspended or until a redraw is
+    /// requested.
+    pub fn next_redraw(&self) -> Option<Instant> {
+        if self.suspended {
+            return None;
+        }
+        self.redraw.next_update()
+    }
+
+    /// Registers a callback which is called with the events of the map, e.g. when the style is
+    /// loaded or the map becomes idle. The events are collected while a frame is processed and
+    /// dispatched after it was rendered, so callbacks should return quickly to keep the frame rate.
+    pub fn on_event<F>(&mut self, listener: F)
+    where
+        F: FnMut(&MapEvent) + 'static,
+    {
+        self.listeners.push(Box::new(listener));
+    }
 
-            map_context.renderer.resize(width, height)
+    /// Resizes the map, see [`MapContext::resize`]. The surface and the textures which depend on
+    /// its size are recreated with the next frame.
+    pub fn resize(&mut self, size: WindowSize) {
+        self.redraw.request();
+        match &mut self.map_context {
+            EventuallyMapContext::Full(map_context) => map_context.resize(size),
+            EventuallyMapContext::Premature(PrematureMapContext { view_state, .. }) => {
+                view_state.resize(size)
+            }
+            EventuallyMapContext::Empty => {}
         }
     }
 
@@ -188,12 +378,19 @@ where
         MW: MapWindow,
     {
         if let EventuallyMapContext::Full(map_context) = &mut self.map_context {
-            let mut renderer = &mut map_context.renderer;
-            renderer.surface.recreate(window, &renderer.instance);
+            map_context.renderer.recreate_surface(window);
+            // The window may have changed its size while the app was suspended
+            map_context.resize(window.size());
             self.suspended = false;
+            self.redraw.request();
         }
     }
 
+    /// The configuration with which the window of the map was created.
+    pub fn map_window_config(&self) -> &MWC {
+        &self.map_window_config
+    }
+
     pub fn is_initialized(&self) -> bool {
         match &self.map_context {
             EventuallyMapContext::Full(_) => true,
@@ -201,32 +398,316 @@ where
         }
     }
 
-    pub async fn late_init(&mut self) -> bool {
+    /// Renders to the native window behind `window_handle` once its surface is available, e.g.
+    /// when Android reports that the surface was created. If there is no renderer yet, or it was
+    /// torn down by [`MapSchedule::destroy_surface`], the renderer is initialized. Otherwise, only
+    /// the surface is recreated. Tiles which are cached are uploaded again with the next frame.
+    pub async fn recreate_surface<H>(
+        &mut self,
+        window_handle: &H,
+        size: WindowSize,
+    ) -> Result<(), Error>
+    where
+        H: HasRawWindowHandle,
+    {
+        match &mut self.map_context {
+            EventuallyMapContext::Full(map_context) => {
+                map_context
+                    .renderer
+                    .recreate_surface_from_handle(window_handle);
+            }
+            EventuallyMapContext::Premature(PrematureMapContext {
+                wgpu_settings,
+                renderer_settings,
+                ..
+            }) => {
+                let renderer = Renderer::initialize_with_window_handle(
+                    window_handle,
+                    size,
+                    wgpu_settings.clone(),
+                    renderer_settings.clone(),
+                )
+                .await?;
+                self.map_context.make_full(renderer);
+            }
+            EventuallyMapContext::Empty => return Ok(()),
+        }
+
+        self.resize(size);
+        self.suspended = false;
+        Ok(())
+    }
+
+    /// Tears down the renderer because the native surface is destroyed, e.g. when Android reports
+    /// that the surface was destroyed. The tile cache, the style, the markers and the camera are
+    /// kept until the surface is recreated by [`MapSchedule::recreate_surface`].
+    pub fn destroy_surface(&mut self) {
+        self.map_context.make_premature();
+        self.suspended = true;
+    }
+
+    /// Returns the features which are rendered at `window_position`, topmost first.
+    pub fn query_rendered_features(&self, window_position: &Vector2<f64>) -> Vec<QueriedFeature> {
         match &self.map_context {
-            EventuallyMapContext::Full(_) => false,
+            EventuallyMapContext::Full(map_context) => {
+                map_context.query_rendered_features(window_position)
+            }
             EventuallyMapContext::Premature(PrematureMapContext {
                 view_state,
                 style,
                 tile_cache,
-                scheduler,
-                message_receiver,
-                shared_thread_state,
-                wgpu_settings,
-                renderer_settings,
-            }) => {
-                let window = MWC::MapWindow::create(&self.map_window_config);
-                let renderer =
-                    Renderer::initialize(&window, wgpu_settings.clone(), renderer_settings.clone())
-                        .await
-                        .unwrap();
-                &self.map_context.make_full(renderer);
-                true
+                ..
+            }) => query_rendered_features(
+                view_state,
+                style,
+                tile_cache,
+                window_position,
+                DEFAULT_QUERY_RADIUS,
+            ),
+            EventuallyMapContext::Empty => Vec::new(),
+        }
+    }
+
+    /// Captures the next frame which is rendered, e.g. to share the map as image. The returned
+    /// future resolves once the frame has been read back from the GPU, which happens
+    /// asynchronously in browsers. Fails if there is no renderer.
+    pub fn capture_frame(&mut self) -> FrameCapture {
+        let capture = FrameCapture::default();
+        self.redraw.request();
+        match &mut self.map_context {
+            EventuallyMapContext::Full(map_context) => map_context
+                .renderer
+                .state
+                .capture_next_frame(capture.clone()),
+            _ => capture.fail(Error::Render(RenderError::Readback(
+                "the renderer is not initialized".to_string(),
+            ))),
+        }
+        capture
+    }
+
+    /// Whether all tiles in view are loaded and uploaded, see [`MapContext::is_fully_rendered`].
+    /// Returns false as long as there is no renderer.
+    pub fn is_fully_rendered(&self) -> bool {
+        match &self.map_context {
+            EventuallyMapContext::Full(map_context) => map_context.is_fully_rendered(),
+            _ => false,
+        }
+    }
+
+    /// Swaps the active style, see [`MapContext::set_style`]. The change takes effect with the next
+    /// frame.
+    pub fn set_style(&mut self, style: Style) {
+        self.redraw.request();
+        match &mut self.map_context {
+            EventuallyMapContext::Full(map_context) => map_context.set_style(style),
+            EventuallyMapContext::Premature(premature) => premature.style = style,
+            EventuallyMapContext::Empty => {}
+        }
+    }
+
+    /// Replaces the data of a GeoJSON source, see [`MapContext::update_geojson_source`]. Returns
+    /// false if the style has no such GeoJSON source.
+    pub fn update_geojson_source(&mut self, source_id: &str, data: serde_json::Value) -> bool {
+        self.redraw.request();
+        match &mut self.map_context {
+            EventuallyMapContext::Full(map_context) => {
+                map_context.update_geojson_source(source_id, data)
+            }
+            EventuallyMapContext::Premature(premature) => {
+                premature.style.set_geojson_data(source_id, data)
+            }
+            EventuallyMapContext::Empty => false,
+        }
+    }
+
+    /// Changes a paint property of a style layer, see [`Style::set_paint_property`]. The geometry
+    /// of the layer is kept and only its styles are uploaded again with the next frame. Returns
+    /// false if there is no such layer or the property can not be changed at runtime.
+    pub fn set_paint_property(
+        &mut self,
+        layer_id: &str,
+        property: &str,
+        value: serde_json::Value,
+    ) -> bool {
+        self.redraw.request();
+        match &mut self.map_context {
+            EventuallyMapContext::Full(map_context) => map_context
+                .style
+                .set_paint_property(layer_id, property, value),
+            EventuallyMapContext::Premature(premature) => premature
+                .style
+                .set_paint_property(layer_id, property, value),
+            EventuallyMapContext::Empty => false,
+        }
+    }
+
+    /// Animates the colors of a style layer or stops its animation, see
+    /// [`Style::set_color_animation`]. While layers are animated, the map is redrawn every frame
+    /// also in [`RedrawMode::OnDemand`]. Returns false if there is no such layer.
+    pub fn set_color_animation(
+        &mut self,
+        layer_id: &str,
+        animation: Option<ColorAnimation>,
+    ) -> bool {
+        self.redraw.request();
+        match &mut self.map_context {
+            EventuallyMapContext::Full(map_context) => {
+                map_context.style.set_color_animation(layer_id, animation)
+            }
+            EventuallyMapContext::Premature(premature) => {
+                premature.style.set_color_animation(layer_id, animation)
             }
             EventuallyMapContext::Empty => false,
         }
     }
 
+    /// Adds a marker which is drawn on top of the map from the next frame on, see
+    /// [`Markers::add`]. The returned handle updates or removes the marker.
+    pub fn add_marker(&mut self, marker: Marker) -> MarkerId {
+        self.markers_mut().add(marker)
+    }
+
+    /// Replaces the marker `id`, e.g. to move it. Returns false if there is no such marker.
+    pub fn update_marker(&mut self, id: MarkerId, marker: Marker) -> bool {
+        self.markers_mut().update(id, marker)
+    }
+
+    /// Removes the marker `id`. Returns false if there is no such marker.
+    pub fn remove_marker(&mut self, id: MarkerId) -> bool {
+        self.markers_mut().remove(id)
+    }
+
+    /// Returns the markers which are drawn on top of the map.
+    pub fn markers_mut(&mut self) -> &mut Markers {
+        self.redraw.request();
+        match &mut self.map_context {
+            EventuallyMapContext::Full(MapContext { markers, .. }) => markers,
+            EventuallyMapContext::Premature(PrematureMapContext { markers, .. }) => markers,
+            _ => panic!("should not happen"),
+        }
+    }
+
+    /// Adds the custom layer `id` below the style layer `before`, or on top of all style layers if
+    /// `before` is `None`, see [`CustomLayers::add`]. Returns false if there is a custom layer with
+    /// the same id already.
+    pub fn add_custom_layer(
+        &mut self,
+        id: &str,
+        before: Option<&str>,
+        layer: Box<dyn CustomLayer>,
+    ) -> bool {
+        self.custom_layers_mut().add(id, before, layer)
+    }
+
+    /// Removes the custom layer `id` and returns it.
+    pub fn remove_custom_layer(&mut self, id: &str) -> Option<Box<dyn CustomLayer>> {
+        self.custom_layers_mut().remove(id)
+    }
+
+    /// Returns the layers which are drawn by the application, see [`crate::custom_layer`].
+    pub fn custom_layers_mut(&mut self) -> &mut CustomLayers {
+        self.redraw.request();
+        match &mut self.map_context {
+            EventuallyMapContext::Full(MapContext { renderer, .. }) => {
+                &mut renderer.state.custom_layers
+            }
+            EventuallyMapContext::Premature(PrematureMapContext { custom_layers, .. }) => {
+                custom_layers
+            }
+            _ => panic!("should not happen"),
+        }
+    }
+
+    /// Returns what the GPU backend supports, unless the renderer is not yet initialized. Tells
+    /// e.g. whether the map fell back to the GL backend.
+    pub fn capabilities(&self) -> Option<&RendererCapabilities> {
+        match &self.map_context {
+            EventuallyMapContext::Full(map_context) => Some(map_context.renderer.capabilities()),
+            EventuallyMapContext::Premature(_) | EventuallyMapContext::Empty => None,
+        }
+    }
+
+    /// Returns how many requests are fetched and tessellated right now and how many are queued.
+    pub fn in_flight_requests(&self) -> InFlightRequests {
+        match &self.map_context {
+            EventuallyMapContext::Full(MapContext {
+                shared_thread_state,
+                ..
+            })
+            | EventuallyMapContext::Premature(PrematureMapContext {
+                shared_thread_state,
+                ..
+            }) => shared_thread_state.request_limiter.in_flight(),
+            EventuallyMapContext::Empty => InFlightRequests::default(),
+        }
+    }
+
+    /// Fetches the tiles which cover `bounds`, given as `[west, south, east, north]` in degrees,
+    /// from zoom level `min_zoom` to `max_zoom` for all sources of the style without drawing them,
+    /// e.g. to prepare an offline map pack. The tiles end up in the disk cache of the source client.
+    /// The returned handle reports the progress and cancels the preload, see
+    /// [`crate::io::preload`].
+    pub fn preload_area(&self, bounds: [f64; 4], min_zoom: u8, max_zoom: u8) -> PreloadHandle {
+        match &self.map_context {
+            EventuallyMapContext::Full(MapContext {
+                view_state,
+                style,
+                scheduler,
+                shared_thread_state,
+                ..
+            })
+            | EventuallyMapContext::Premature(PrematureMapContext {
+                view_state,
+                style,
+                scheduler,
+                shared_thread_state,
+                ..
+            }) => schedule_preload(
+                preload_sources(
+                    style,
+                    bounds,
+                    min_zoom,
+                    max_zoom,
+                    view_state.device_pixel_ratio(),
+                ),
+                &self.source_client,
+                scheduler.as_ref(),
+                shared_thread_state,
+            ),
+            EventuallyMapContext::Empty => PreloadHandle::default(),
+        }
+    }
+
+    /// Reads layers of vector tiles from `tessellation_cache` and writes tessellated layers to it,
+    /// see [`TessellationCache`]. Tiles which are requested already are not affected.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_tessellation_cache(&mut self, tessellation_cache: Option<TessellationCache>) {
+        match &mut self.map_context {
+            EventuallyMapContext::Full(MapContext {
+                shared_thread_state,
+                ..
+            })
+            | EventuallyMapContext::Premature(PrematureMapContext {
+                shared_thread_state,
+                ..
+            }) => shared_thread_state.tessellation_cache = tessellation_cache,
+            EventuallyMapContext::Empty => {}
+        }
+    }
+
+    /// Returns the active style.
+    pub fn style(&self) -> Option<&Style> {
+        match &self.map_context {
+            EventuallyMapContext::Full(MapContext { style, .. }) => Some(style),
+            EventuallyMapContext::Premature(PrematureMapContext { style, .. }) => Some(style),
+            EventuallyMapContext::Empty => None,
+        }
+    }
+
+    /// Returns the camera. Changes take effect with the next frame, which is requested.
     pub fn view_state_mut(&mut self) -> &mut ViewState {
+        self.redraw.request();
         match &mut self.map_context {
             EventuallyMapContext::Full(MapContext { view_state, .. }) => view_state,
             EventuallyMapContext::Premature(PrematureMapContext { view_state, .. }) => view_state,
@@ -234,3 +715,268 @@ where
         }
     }
 }
+
+/// Highest zoom level which the [`CameraController`] allows if the sources do not limit it.
+pub const DEFAULT_MAX_ZOOM: f64 = 22.0;
+/// Rate at which the velocity of the map decays after a drag, per second.
+const INERTIA_DECAY: f64 = 4.0;
+/// Velocity in pixels per second below which the map stops moving after a drag.
+const MIN_INERTIA_VELOCITY: f64 = 10.0;
+
+/// Moves the camera of a [`ViewState`] according to platform-agnostic input events. Platforms
+/// translate their raw events into drags, zooms and pinches and call
+/// [`CameraController::update_state`] once per frame.
+///
+/// Zooming keeps the position below the cursor in place and a drag continues with inertia after
+/// it ended. The zoom is clamped to the zoom range of the sources and the center stays within the
+/// Web Mercator bounds.
+pub struct CameraController {
+    /// Pixels the map was dragged by since the last update.
+    drag_delta: Vector2<f64>,
+    is_dragging: bool,
+    /// Velocity of the map in pixels per second, which is kept after a drag ended.
+    velocity: Vector2<f64>,
+
+    zoom_delta: f64,
+    zoom_position: Option<Vector2<f64>>,
+
+    min_zoom: f64,
+    max_zoom: f64,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraController {
+    pub fn new() -> Self {
+        Self {
+            drag_delta: Vector2::zero(),
+            is_dragging: false,
+            velocity: Vector2::zero(),
+            zoom_delta: 0.0,
+            zoom_position: None,
+            min_zoom: 0.0,
+            max_zoom: DEFAULT_MAX_ZOOM,
+        }
+    }
+
+    /// Limits the zoom to `min..=max`.
+    pub fn set_zoom_range(&mut self, min: f64, max: f64) {
+        self.min_zoom = min;
+        self.max_zoom = max.max(min);
+    }
+
+    /// Limits the zoom to the range in which the vector source of `style` provides tiles, see
+    /// [`Style::zoom_range`].
+    pub fn set_zoom_range_of(&mut self, style: &Style) {
+        let (min, max) = style.zoom_range();
+        self.set_zoom_range(
+            min.map_or(0.0, f64::from),
+            max.map_or(DEFAULT_MAX_ZOOM, f64::from),
+        );
+    }
+
+    /// Starts dragging the map, which stops the inertia of a previous drag.
+    pub fn drag_start(&mut self) {
+        self.is_dragging = true;
+        self.velocity = Vector2::zero();
+    }
+
+    /// Drags the map by `delta` pixels.
+    pub fn drag(&mut self, delta: Vector2<f64>) {
+        self.drag_delta += delta;
+    }
+
+    /// Releases the map, which keeps moving with the velocity of the drag.
+    pub fn drag_end(&mut self) {
+        self.is_dragging = false;
+    }
+
+    /// Zooms by `delta` levels around the center of the window.
+    pub fn zoom(&mut self, delta: f64) {
+        self.zoom_delta += delta;
+        self.zoom_position = None;
+    }
+
+    /// Zooms by `delta` levels such that the geographic position at `window_position` stays in
+    /// place, like zooming with the scroll wheel, see [`ViewState::zoom_around`].
+    pub fn zoom_around(&mut self, delta: f64, window_position: Vector2<f64>) {
+        self.zoom_delta += delta;
+        self.zoom_position = Some(window_position);
+    }
+
+    /// Scales the map by `scale` around `center`, e.g. 2.0 if the fingers moved twice as far apart.
+    pub fn pinch(&mut self, scale: f64, center: Vector2<f64>) {
+        if scale > 0.0 {
+            self.zoom_around(scale.log2(), center);
+        }
+    }
+
+    /// Whether the map keeps moving after a drag ended.
+    pub fn is_moving(&self) -> bool {
+        !self.is_dragging && self.velocity != Vector2::zero()
+    }
+
+    /// Applies the input since the last update to the camera of `state`. `dt` is the time since
+    /// the last update.
+    pub fn update_state(&mut self, state: &mut ViewState, dt: Duration) {
+        let dt = dt.as_secs_f64();
+        let mut delta = mem::replace(&mut self.drag_delta, Vector2::zero());
+
+        if self.is_dragging {
+            if dt > 0.0 {
+                // Smooths the velocity, because input events do not arrive with every frame
+                self.velocity = (self.velocity + delta / dt) / 2.0;
+            }
+        } else if self.velocity.magnitude() > MIN_INERTIA_VELOCITY {
+            delta += self.velocity * dt;
+            self.velocity *= (-INERTIA_DECAY * dt).exp();
+        } else {
+            self.velocity = Vector2::zero();
+        }
+
+        let zoom_delta = mem::replace(&mut self.zoom_delta, 0.0);
+        if delta != Vector2::zero() || zoom_delta != 0.0 {
+            state.stop_animation();
+        }
+
+        if delta != Vector2::zero() {
+            Self::pan(state, delta);
+        }
+
+        if state.is_animating() {
+            return;
+        }
+
+        let current_zoom = state.zoom().value();
+        let next_zoom = (current_zoom + zoom_delta).clamp(self.min_zoom, self.max_zoom);
+        if next_zoom != current_zoom {
+            let window_position = self
+                .zoom_position
+                .unwrap_or_else(|| Vector2::new(state.camera.width, state.camera.height) / 2.0);
+            state.zoom_around(Zoom::new(next_zoom), &window_position);
+        }
+
+        Self::clamp_center(state);
+    }
+
+    /// Moves the camera such that the ground moves by `delta` pixels at the center of the window.
+    fn pan(state: &mut ViewState, delta: Vector2<f64>) {
+        let inverted_view_proj = state.view_projection().invert();
+        let center = Vector2::new(state.camera.width, state.camera.height) / 2.0;
+
+        if let (Some(start), Some(end)) = (
+            state
+                .camera
+                .window_to_world_at_ground(&center, &inverted_view_proj),
+            state
+                .camera
+                .window_to_world_at_ground(&(center + delta), &inverted_view_proj),
+        ) {
+            state.camera.position.x += start.x - end.x;
+            state.camera.position.y += start.y - end.y;
+        }
+    }
+
+    /// Keeps the center of the view within the Web Mercator bounds.
+    fn clamp_center(state: &mut ViewState) {
+        let world_size = TILE_SIZE * 2.0_f64.powf(state.zoom().value());
+        let position = &mut state.camera.position;
+        position.x = position.x.clamp(0.0, world_size);
+        position.y = position.y.clamp(0.0, world_size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CameraController;
+    use crate::context::ViewState;
+    use crate::coords::{Zoom, TILE_SIZE};
+    use crate::WindowSize;
+    use cgmath::Vector2;
+    use std::time::Duration;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    fn view_state() -> ViewState {
+        let mut view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+        view_state.update_zoom(Zoom::new(2.0));
+        let world_size = TILE_SIZE * 4.0;
+        view_state.camera.position.x = world_size / 2.0;
+        view_state.camera.position.y = world_size / 2.0;
+        view_state
+    }
+
+    #[test]
+    fn test_zoom_is_clamped() {
+        let mut view_state = view_state();
+        let mut controller = CameraController::new();
+        controller.set_zoom_range(1.0, 4.0);
+
+        controller.zoom(5.0);
+        controller.update_state(&mut view_state, FRAME);
+        assert_eq!(view_state.zoom().value(), 4.0);
+
+        controller.zoom(-10.0);
+        controller.update_state(&mut view_state, FRAME);
+        assert_eq!(view_state.zoom().value(), 1.0);
+    }
+
+    #[test]
+    fn test_zoom_around_center_keeps_center() {
+        let mut view_state = view_state();
+        let mut controller = CameraController::new();
+        let center = view_state.camera_state().center;
+
+        controller.zoom(1.0);
+        controller.update_state(&mut view_state, FRAME);
+
+        let zoomed_center = view_state.camera_state().center;
+        assert_eq!(view_state.zoom().value(), 3.0);
+        assert!((zoomed_center.x - center.x).abs() < 1e-6);
+        assert!((zoomed_center.y - center.y).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_drag_continues_with_inertia() {
+        let mut view_state = view_state();
+        let mut controller = CameraController::new();
+        let start = view_state.camera.position;
+
+        controller.drag_start();
+        controller.drag(Vector2::new(50.0, 0.0));
+        controller.update_state(&mut view_state, FRAME);
+        // The map moves with the cursor, so the camera moves the opposite way
+        let dragged = view_state.camera.position;
+        assert!(dragged.x < start.x);
+        assert!((dragged.y - start.y).abs() < 1e-6);
+
+        controller.drag_end();
+        assert!(controller.is_moving());
+        controller.update_state(&mut view_state, FRAME);
+        assert!(view_state.camera.position.x < dragged.x);
+
+        for _ in 0..1000 {
+            controller.update_state(&mut view_state, FRAME);
+        }
+        assert!(!controller.is_moving());
+    }
+
+    #[test]
+    fn test_center_stays_within_bounds() {
+        let mut view_state = view_state();
+        let mut controller = CameraController::new();
+
+        controller.drag_start();
+        controller.drag(Vector2::new(1e6, -1e6));
+        controller.update_state(&mut view_state, FRAME);
+
+        let world_size = TILE_SIZE * 4.0;
+        let position = view_state.camera.position;
+        assert_eq!(position.x, 0.0);
+        assert!(position.y == 0.0 || position.y == world_size);
+    }
+}
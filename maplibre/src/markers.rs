@@ -0,0 +1,132 @@
+//! Markers which are drawn on top of the map at geographic positions, e.g. the position of the
+//! user or search results. Unlike layers of the style, markers are not part of any tile. They keep
+//! their size in pixels regardless of the zoom and are neither clipped nor hidden by the map.
+
+use crate::coords::LatLon;
+use crate::style::layer::IconAnchor;
+use std::collections::BTreeMap;
+
+/// Handle of a marker which is returned by [`Markers::add`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MarkerId(u64);
+
+/// A point on the map which is drawn with an image of the sprite sheet of the style or as a
+/// square of a single color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub position: LatLon,
+    /// Name of an image of [`crate::style::Style::sprite_sheet`]. Markers without an image, or
+    /// whose image is not in the sprite sheet, are drawn as squares.
+    pub image: Option<String>,
+    /// Color of the square in sRGB, or the color by which the image is multiplied.
+    pub color: [f32; 4],
+    /// Side length of the square in logical pixels, or the factor by which the image is scaled.
+    pub size: f32,
+    /// Part of the marker which is placed at its position.
+    pub anchor: IconAnchor,
+}
+
+impl Marker {
+    /// Creates a square marker of the given color.
+    pub fn with_color(position: LatLon, color: [f32; 4]) -> Self {
+        Self {
+            position,
+            image: None,
+            color,
+            size: 12.0,
+            anchor: IconAnchor::Center,
+        }
+    }
+
+    /// Creates a marker which is drawn with the image `image` of the sprite sheet. The bottom of
+    /// the image is placed at the position, like the tip of a pin.
+    pub fn with_image(position: LatLon, image: &str) -> Self {
+        Self {
+            position,
+            image: Some(image.to_string()),
+            color: [1.0, 1.0, 1.0, 1.0],
+            size: 1.0,
+            anchor: IconAnchor::Bottom,
+        }
+    }
+}
+
+/// The markers of a map. Markers which are added later are drawn on top of earlier ones.
+#[derive(Debug, Default)]
+pub struct Markers {
+    next_id: u64,
+    markers: BTreeMap<MarkerId, Marker>,
+}
+
+impl Markers {
+    /// Adds a marker and returns the handle with which it can be updated or removed.
+    pub fn add(&mut self, marker: Marker) -> MarkerId {
+        let id = MarkerId(self.next_id);
+        self.next_id += 1;
+        self.markers.insert(id, marker);
+        id
+    }
+
+    /// Replaces the marker `id`, e.g. to move it. Returns false if there is no such marker.
+    pub fn update(&mut self, id: MarkerId, marker: Marker) -> bool {
+        match self.markers.get_mut(&id) {
+            Some(existing) => {
+                *existing = marker;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the marker `id`. Returns false if there is no such marker.
+    pub fn remove(&mut self, id: MarkerId) -> bool {
+        self.markers.remove(&id).is_some()
+    }
+
+    pub fn get(&self, id: MarkerId) -> Option<&Marker> {
+        self.markers.get(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markers.is_empty()
+    }
+
+    /// Returns the markers in the order in which they are drawn.
+    pub fn iter(&self) -> impl Iterator<Item = (MarkerId, &Marker)> + '_ {
+        self.markers.iter().map(|(id, marker)| (*id, marker))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Marker, Markers};
+    use crate::coords::LatLon;
+
+    #[test]
+    fn test_markers() {
+        let mut markers = Markers::default();
+        let first = markers.add(Marker::with_color(
+            LatLon::new(48.0, 11.0),
+            [1.0, 0.0, 0.0, 1.0],
+        ));
+        let second = markers.add(Marker::with_image(LatLon::new(52.5, 13.4), "pin"));
+        assert_ne!(first, second);
+
+        let moved = Marker::with_color(LatLon::new(48.1, 11.6), [1.0, 0.0, 0.0, 1.0]);
+        assert!(markers.update(first, moved.clone()));
+        assert_eq!(markers.get(first), Some(&moved));
+
+        // Later markers are drawn on top
+        let order: Vec<_> = markers.iter().map(|(id, _)| id).collect();
+        assert_eq!(order, vec![first, second]);
+
+        assert!(markers.remove(first));
+        assert!(!markers.remove(first));
+        assert!(!markers.update(first, moved));
+
+        // Handles are not reused
+        let third = markers.add(Marker::with_image(LatLon::new(0.0, 0.0), "pin"));
+        assert_ne!(third, first);
+        assert_eq!(markers.iter().count(), 2);
+    }
+}
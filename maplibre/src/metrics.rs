@@ -0,0 +1,179 @@
+//! Hooks for collecting timings and sizes of the tile pipeline, e.g. to log them or to export them
+//! to a monitoring system. By default nothing is collected, see [`NoopMetricsSink`].
+//!
+//! The timings of the frames are always collected, see [`FrameStats`].
+
+use crate::coords::WorldTileCoords;
+use crate::schedule::StageLabel;
+use instant::Instant;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Number of frames over which the frame rate is averaged.
+const FRAME_WINDOW: usize = 60;
+
+/// Receives measurements of the tile pipeline. The methods are called from the threads of the
+/// scheduler as well as from the render thread, so implementations should return quickly.
+pub trait MetricsSink: Send + Sync {
+    /// A tile of `bytes` bytes was fetched within `duration`.
+    fn tile_fetched(&self, _coords: &WorldTileCoords, _duration: Duration, _bytes: usize) {}
+
+    /// A layer of a tile was tessellated within `duration` into `vertices` vertices and `indices`
+    /// indices.
+    fn layer_tessellated(
+        &self,
+        _coords: &WorldTileCoords,
+        _layer_name: &str,
+        _duration: Duration,
+        _vertices: usize,
+        _indices: usize,
+    ) {
+    }
+
+    /// The first layer of a tile was sent to the render thread `duration` after the tile started
+    /// to be parsed. Layers are sent as soon as they are tessellated, so this is the delay until a
+    /// tile starts to appear.
+    fn first_layer_tessellated(&self, _coords: &WorldTileCoords, _duration: Duration) {}
+
+    /// The occupancy of the buffer pool changed after layers were uploaded to the GPU.
+    fn buffer_pool_occupancy(&self, _occupancy: &BufferPoolOccupancy) {}
+}
+
+/// How much of the buffer pool is in use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferPoolOccupancy {
+    /// Number of tiles of which at least one layer is stored in the pool.
+    pub tiles: usize,
+    /// Number of layers which are stored in the pool.
+    pub layers: usize,
+    pub vertex_bytes: u64,
+    pub vertex_capacity: u64,
+    pub index_bytes: u64,
+    pub index_capacity: u64,
+}
+
+impl BufferPoolOccupancy {
+    /// Bytes of vertices and indices in use.
+    pub fn bytes(&self) -> u64 {
+        self.vertex_bytes + self.index_bytes
+    }
+}
+
+/// Discards all measurements.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopMetricsSink;
+
+impl MetricsSink for NoopMetricsSink {}
+
+/// Timings of the frames which were drawn recently, see
+/// [`crate::map_schedule::MapSchedule::stats`].
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    /// Time it took to update and draw the last frame.
+    pub frame_time: Duration,
+    /// Frames per second, averaged over the last 60 frames. Zero until two frames were drawn.
+    pub fps: f64,
+    /// Durations of the stages during the last frame in the order in which they run. Tiles are
+    /// uploaded in [`crate::render::RenderStageLabel::Prepare`].
+    pub stages: Vec<(Box<dyn StageLabel>, Duration)>,
+}
+
+impl FrameStats {
+    /// Returns how long the stage `label` took during the last frame.
+    pub fn stage(&self, label: impl StageLabel) -> Option<Duration> {
+        let label = &label as &dyn StageLabel;
+        self.stages
+            .iter()
+            .find(|(stage_label, _)| &**stage_label == label)
+            .map(|(_, duration)| *duration)
+    }
+}
+
+/// Collects the [`FrameStats`] while frames are drawn.
+#[derive(Default)]
+pub(crate) struct FrameTimer {
+    last_start: Option<Instant>,
+    /// Time between the starts of the last frames.
+    intervals: VecDeque<Duration>,
+    stats: FrameStats,
+}
+
+impl FrameTimer {
+    /// Records a frame which started at `start` and took `frame_time`.
+    pub fn frame_finished(
+        &mut self,
+        start: Instant,
+        frame_time: Duration,
+        stages: Vec<(Box<dyn StageLabel>, Duration)>,
+    ) {
+        if let Some(last_start) = self.last_start.replace(start) {
+            self.intervals.push_back(start.duration_since(last_start));
+            if self.intervals.len() > FRAME_WINDOW {
+                self.intervals.pop_front();
+            }
+        }
+
+        let elapsed: Duration = self.intervals.iter().sum();
+        self.stats = FrameStats {
+            frame_time,
+            fps: if elapsed.is_zero() {
+                0.0
+            } else {
+                self.intervals.len() as f64 / elapsed.as_secs_f64()
+            },
+            stages,
+        };
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameTimer, FRAME_WINDOW};
+    use crate::schedule::StageLabel;
+    use instant::Instant;
+    use std::time::Duration;
+
+    #[test]
+    fn test_frame_timer() {
+        let mut timer = FrameTimer::default();
+        let start = Instant::now();
+        let stages = || -> Vec<(Box<dyn StageLabel>, Duration)> {
+            vec![
+                (Box::new("request"), Duration::from_millis(1)),
+                (Box::new("render"), Duration::from_millis(4)),
+            ]
+        };
+
+        timer.frame_finished(start, Duration::from_millis(5), stages());
+        assert_eq!(timer.stats().fps, 0.0);
+        assert_eq!(
+            timer.stats().stage("render"),
+            Some(Duration::from_millis(4))
+        );
+        assert_eq!(timer.stats().stage("upload"), None);
+
+        // 10 frames every 20ms and afterwards a full window of frames every 10ms
+        for frame in 1..=10 {
+            timer.frame_finished(
+                start + Duration::from_millis(20 * frame),
+                Duration::from_millis(5),
+                stages(),
+            );
+        }
+        assert!((timer.stats().fps - 50.0).abs() < 1e-6);
+
+        for frame in 1..=FRAME_WINDOW as u64 {
+            timer.frame_finished(
+                start + Duration::from_millis(200 + 10 * frame),
+                Duration::from_millis(8),
+                stages(),
+            );
+        }
+        assert!((timer.stats().fps - 100.0).abs() < 1e-6);
+        assert_eq!(timer.stats().frame_time, Duration::from_millis(8));
+    }
+}
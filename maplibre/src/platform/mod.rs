@@ -31,18 +31,18 @@ mod noweb;
 
 /// Http client for non-web targets.
 pub mod http_client {
-    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg(all(not(target_arch = "wasm32"), feature = "tokio"))]
     pub use super::noweb::http_client::*;
 }
 
 /// Scheduler for non-web targets.
 pub mod schedule_method {
-    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg(all(not(target_arch = "wasm32"), feature = "tokio"))]
     pub use super::noweb::schedule_method::*;
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub use noweb::run_multithreaded;
+#[cfg(all(not(target_arch = "wasm32"), feature = "tokio"))]
+pub use noweb::{multithreaded_runtime, run_multithreaded};
 
 /// Minimum WebGPU buffer size
 ///
@@ -1,14 +1,22 @@
 use crate::error::Error;
+use crate::io::compression;
+use crate::io::compression::ContentEncoding;
+use crate::io::request_transform::RequestTransform;
+use crate::io::source_client::{CachePolicy, HttpResponse};
 use crate::HTTPClient;
 use async_trait::async_trait;
+use reqwest::header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, RANGE};
 use reqwest::{Client, StatusCode};
-use reqwest_middleware::ClientWithMiddleware;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
 use reqwest_middleware_cache::managers::CACacheManager;
 use reqwest_middleware_cache::{Cache, CacheMode};
+use std::ops::Range;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct ReqwestHttpClient {
     client: ClientWithMiddleware,
+    request_transform: Option<RequestTransform>,
 }
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
@@ -36,24 +44,92 @@ impl ReqwestHttpClient {
 
         Self {
             client: builder.build(),
+            request_transform: None,
         }
     }
+
+    /// Adds the headers and query parameters of `request_transform` to every request, e.g. to
+    /// authenticate at a tile provider.
+    pub fn with_request_transform(mut self, request_transform: RequestTransform) -> Self {
+        self.request_transform = Some(request_transform);
+        self
+    }
+
+    fn get(&self, url: &str) -> RequestBuilder {
+        let parameters = match &self.request_transform {
+            Some(request_transform) => request_transform.parameters(url),
+            None => return self.client.get(url),
+        };
+
+        parameters.headers.iter().fold(
+            self.client.get(&parameters.apply_query(url)),
+            |request, (name, value)| request.header(name.as_str(), value.as_str()),
+        )
+    }
 }
 
 #[async_trait]
 impl HTTPClient for ReqwestHttpClient {
     async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
-        let response = self.client.get(url).send().await?;
+        let response = self.fetch_response(url).await?;
+        compression::decode(response.data, response.content_encoding)
+    }
+
+    /// Requests gzip or brotli encoded responses. They are decoded by the caller, such that
+    /// malformed data results in an error of the tile instead of a failed request.
+    async fn fetch_response(&self, url: &str) -> Result<HttpResponse, Error> {
+        let response = self
+            .get(url)
+            .header(ACCEPT_ENCODING, ContentEncoding::accept_header())
+            .send()
+            .await?;
         match response.error_for_status() {
             Ok(response) => {
                 if response.status() == StatusCode::NOT_MODIFIED {
                     log::info!("Using data from cache");
                 }
 
+                let cache_policy = response
+                    .headers()
+                    .get(CACHE_CONTROL)
+                    .and_then(|value| value.to_str().ok())
+                    .map(CachePolicy::from_cache_control)
+                    .unwrap_or_default();
+                let content_encoding = match response.headers().get(CONTENT_ENCODING) {
+                    Some(value) => ContentEncoding::from_header(
+                        value
+                            .to_str()
+                            .map_err(|e| Error::Decompression(e.to_string()))?,
+                    )?,
+                    None => ContentEncoding::Identity,
+                };
+                let body = response.bytes().await?;
+                Ok(HttpResponse {
+                    data: Vec::from(body.as_ref()),
+                    cache_policy,
+                    content_encoding,
+                })
+            }
+            Err(e) => Err(Error::Network(e.to_string())),
+        }
+    }
+
+    async fn fetch_range(&self, url: &str, range: Range<u64>) -> Result<Vec<u8>, Error> {
+        let response = self
+            .get(url)
+            .header(RANGE, format!("bytes={}-{}", range.start, range.end - 1))
+            .send()
+            .await?;
+        match response.error_for_status() {
+            Ok(response) => {
                 let body = response.bytes().await?;
                 Ok(Vec::from(body.as_ref()))
             }
             Err(e) => Err(Error::Network(e.to_string())),
         }
     }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
 }
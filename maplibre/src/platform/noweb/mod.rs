@@ -1,11 +1,18 @@
 //! Module which is used target platform is not web related.
 
+#[cfg(feature = "tokio")]
 use std::future::Future;
 
+// reqwest requires a Tokio runtime
+#[cfg(feature = "tokio")]
 pub mod http_client;
+#[cfg(feature = "tokio")]
 pub mod schedule_method;
 
-pub fn run_multithreaded<F: Future>(future: F) -> F::Output {
+/// Creates the runtime on which the tiles are fetched and processed. Embedders whose event loop
+/// returns right away, e.g. on iOS, need to keep the runtime alive while the map is shown.
+#[cfg(feature = "tokio")]
+pub fn multithreaded_runtime() -> tokio::runtime::Runtime {
     tokio::runtime::Builder::new_multi_thread()
         .worker_threads(4)
         .enable_io()
@@ -16,5 +23,9 @@ pub fn run_multithreaded<F: Future>(future: F) -> F::Output {
         })
         .build()
         .unwrap()
-        .block_on(future)
+}
+
+#[cfg(feature = "tokio")]
+pub fn run_multithreaded<F: Future>(future: F) -> F::Output {
+    multithreaded_runtime().block_on(future)
 }
@@ -3,13 +3,35 @@ use crate::io::shared_thread_state::SharedThreadState;
 use crate::ScheduleMethod;
 use std::future::Future;
 use std::pin::Pin;
+use tokio::runtime::Handle;
 
 /// Multi-threading with Tokio.
-pub struct TokioScheduleMethod;
+///
+/// Futures are spawned onto the runtime of the given [`Handle`], such that applications which
+/// already run a Tokio runtime can share it with the map. Without a handle, futures are spawned
+/// onto the runtime of the thread which schedules them, see [`crate::platform::run_multithreaded`].
+/// Spawning never blocks the render thread, and requests of tiles which leave the view before
+/// they are fetched are cancelled by the futures themselves.
+pub struct TokioScheduleMethod {
+    handle: Option<Handle>,
+}
 
 impl TokioScheduleMethod {
     pub fn new() -> Self {
-        Self {}
+        Self { handle: None }
+    }
+
+    /// Spawns futures onto the runtime of `handle`.
+    pub fn from_handle(handle: Handle) -> Self {
+        Self {
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Default for TokioScheduleMethod {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -23,7 +45,53 @@ impl ScheduleMethod for TokioScheduleMethod {
                  + 'static),
         >,
     ) -> Result<(), Error> {
-        tokio::task::spawn((future_factory)(shared_thread_state));
+        let future = (future_factory)(shared_thread_state);
+        match &self.handle {
+            Some(handle) => handle.spawn(future),
+            None => tokio::task::spawn(future),
+        };
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TokioScheduleMethod;
+    use crate::io::request_limiter::RequestLimits;
+    use crate::io::shared_thread_state::SharedThreadState;
+    use crate::metrics::NoopMetricsSink;
+    use crate::ScheduleMethod;
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    #[test]
+    fn test_schedule_from_outside_of_runtime() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()
+            .unwrap();
+        let schedule_method = TokioScheduleMethod::from_handle(runtime.handle().clone());
+
+        let (message_sender, _message_receiver) = mpsc::channel();
+        let shared_thread_state = SharedThreadState::new(
+            message_sender,
+            Arc::new(NoopMetricsSink),
+            &RequestLimits::default(),
+        );
+
+        // The test thread is not part of the runtime
+        let (sender, receiver) = mpsc::channel();
+        schedule_method
+            .schedule(
+                shared_thread_state,
+                Box::new(move |_state| {
+                    Box::pin(async move {
+                        sender.send(()).unwrap();
+                    })
+                }),
+            )
+            .unwrap();
+
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+}
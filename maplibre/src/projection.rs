@@ -0,0 +1,84 @@
+//! Projections which map geographic positions onto the world in which tiles are placed. The world
+//! is a square of `TILE_SIZE * 2^zoom` units with its origin in the upper-left corner, see
+//! [`crate::coords::WorldCoords`]. The camera, the tiles and the tile grid only depend on the
+//! world, so another projection only needs to implement [`Projection`].
+//!
+//! Tiles are rendered with [`WebMercator`], which is what vector tiles are cut with.
+
+use crate::coords::{LatLon, MAX_LATITUDE};
+use std::f64::consts::PI;
+
+/// Maps geographic positions onto the world and back.
+pub trait Projection {
+    /// Projects `lat_lon` onto the world, normalized such that the world covers `0.0..=1.0` in
+    /// both directions. The origin is in the upper-left corner.
+    fn project(&self, lat_lon: LatLon) -> [f64; 2];
+
+    /// Inverse of [`Projection::project`].
+    fn unproject(&self, point: [f64; 2]) -> LatLon;
+
+    /// Largest absolute latitude which can be projected. Larger latitudes are clamped.
+    fn max_latitude(&self) -> f64;
+
+    /// Number of tiles in x and y direction at zoom level 0. Each zoom level doubles both of
+    /// them.
+    fn root_tiles(&self) -> [u32; 2] {
+        [1, 1]
+    }
+}
+
+/// The spherical Mercator projection of web maps, also known as EPSG:3857. The world is a single
+/// square tile at zoom level 0 which covers the latitudes up to [`MAX_LATITUDE`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WebMercator;
+
+impl Projection for WebMercator {
+    fn project(&self, lat_lon: LatLon) -> [f64; 2] {
+        let latitude = lat_lon
+            .latitude
+            .clamp(-MAX_LATITUDE, MAX_LATITUDE)
+            .to_radians();
+
+        let x = (lat_lon.longitude + 180.0) / 360.0;
+        let y = (1.0 - (latitude.tan() + 1.0 / latitude.cos()).ln() / PI) / 2.0;
+        [x, y]
+    }
+
+    fn unproject(&self, [x, y]: [f64; 2]) -> LatLon {
+        LatLon {
+            latitude: (PI * (1.0 - 2.0 * y)).sinh().atan().to_degrees(),
+            longitude: x * 360.0 - 180.0,
+        }
+    }
+
+    fn max_latitude(&self) -> f64 {
+        MAX_LATITUDE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Projection, WebMercator};
+    use crate::coords::{LatLon, MAX_LATITUDE};
+
+    #[test]
+    fn test_web_mercator() {
+        let projection = WebMercator;
+
+        assert_eq!(projection.project(LatLon::new(0.0, 0.0)), [0.5, 0.5]);
+        let [x, y] = projection.project(LatLon::new(MAX_LATITUDE, -180.0));
+        assert_eq!(x, 0.0);
+        assert!(y.abs() < 1e-6);
+        // Latitudes beyond the poles of Web Mercator are clamped
+        assert_eq!(
+            projection.project(LatLon::new(90.0, 10.0)),
+            projection.project(LatLon::new(MAX_LATITUDE, 10.0))
+        );
+
+        let lat_lon = LatLon::new(-33.8688, 151.2093);
+        let back = projection.unproject(projection.project(lat_lon));
+        assert!((back.latitude - lat_lon.latitude).abs() < 1e-9);
+        assert!((back.longitude - lat_lon.longitude).abs() < 1e-9);
+        assert_eq!(projection.root_tiles(), [1, 1]);
+    }
+}
@@ -0,0 +1,194 @@
+//! Decides when the event loop updates the map and whether an update renders a frame, see
+//! [`crate::map_schedule::MapSchedule::set_redraw_mode`]. Rendering fewer frames saves battery,
+//! e.g. while the app is in the background or the map does not change.
+
+use crate::render::camera_animation::CameraState;
+use instant::Instant;
+use std::time::Duration;
+
+/// How often the map is checked for new data while it waits for tiles in
+/// [`RedrawMode::OnDemand`].
+const DATA_POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+/// How often the map is redrawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedrawMode {
+    /// Redraws continuously. `max_fps` limits the frame rate if it is set.
+    Continuous { max_fps: Option<f64> },
+    /// Only renders a frame if the map changed since the last frame, i.e. if the camera moved, data
+    /// arrived or a redraw was requested. Input, changes through the API and
+    /// [`crate::map_schedule::MapSchedule::request_redraw`] request a redraw. While tiles are
+    /// loading, the map is updated without rendering until they arrive.
+    OnDemand,
+}
+
+impl Default for RedrawMode {
+    fn default() -> Self {
+        RedrawMode::Continuous { max_fps: None }
+    }
+}
+
+/// Tracks the updates of the map, the rendered frames and the redraw requests.
+#[derive(Default)]
+pub struct RedrawScheduler {
+    mode: RedrawMode,
+    /// When the map was updated last.
+    last_update: Option<Instant>,
+    /// Whether the last update rendered a frame.
+    last_rendered: bool,
+    /// Whether all tiles in view were rendered after the last update.
+    is_fully_rendered: bool,
+    /// The camera and the number of received data messages of the last rendered frame.
+    last_frame: Option<(CameraState, u64)>,
+    /// Whether the map is dirty regardless of the camera and the data, e.g. because the style
+    /// changed.
+    requested: bool,
+}
+
+impl RedrawScheduler {
+    pub fn mode(&self) -> RedrawMode {
+        self.mode
+    }
+
+    /// Changes the mode. The next frame is rendered right away, such that the new mode takes
+    /// effect.
+    pub fn set_mode(&mut self, mode: RedrawMode) {
+        self.mode = mode;
+        self.requested = true;
+    }
+
+    /// Renders the next frame as soon as possible, also in [`RedrawMode::OnDemand`].
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Whether the update of the map needs to render a frame. `received_messages` counts the data
+    /// which arrived from the tile pipeline so far.
+    pub fn needs_render(&self, camera: CameraState, received_messages: u64) -> bool {
+        match self.mode {
+            RedrawMode::Continuous { .. } => true,
+            RedrawMode::OnDemand => {
+                self.requested || self.last_frame != Some((camera, received_messages))
+            }
+        }
+    }
+
+    /// Records an update of the map at `now`. If `rendered` is set, a frame of `camera` and the
+    /// `received_messages` was rendered. `is_fully_rendered` tells whether all tiles in view are
+    /// rendered after the update.
+    pub fn updated(
+        &mut self,
+        now: Instant,
+        camera: CameraState,
+        received_messages: u64,
+        rendered: bool,
+        is_fully_rendered: bool,
+    ) {
+        self.last_update = Some(now);
+        self.last_rendered = rendered;
+        self.is_fully_rendered = is_fully_rendered;
+        if rendered {
+            self.last_frame = Some((camera, received_messages));
+            self.requested = false;
+        }
+    }
+
+    /// Returns when the map should be updated next. `None` if there is nothing to update until a
+    /// redraw is requested.
+    pub fn next_update(&self) -> Option<Instant> {
+        let last_update = match self.last_update {
+            Some(last_update) => last_update,
+            None => return Some(Instant::now()),
+        };
+
+        match self.mode {
+            RedrawMode::Continuous { max_fps: None } => Some(last_update),
+            RedrawMode::Continuous {
+                max_fps: Some(max_fps),
+            } => {
+                if max_fps > 0.0 {
+                    Some(last_update + Duration::from_secs_f64(1.0 / max_fps))
+                } else {
+                    None
+                }
+            }
+            RedrawMode::OnDemand => {
+                // After a rendered frame the camera might still move, e.g. because of an animation
+                if self.requested || self.last_rendered {
+                    Some(last_update)
+                } else if !self.is_fully_rendered {
+                    Some(last_update + DATA_POLL_INTERVAL)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RedrawMode, RedrawScheduler, DATA_POLL_INTERVAL};
+    use crate::render::camera_animation::CameraState;
+    use cgmath::{Rad, Vector2};
+    use instant::Instant;
+    use std::time::Duration;
+
+    fn camera(x: f64) -> CameraState {
+        CameraState {
+            center: Vector2::new(x, 0.5),
+            zoom: 2.0,
+            pitch: Rad(0.0),
+            bearing: Rad(0.0),
+        }
+    }
+
+    #[test]
+    fn test_max_fps() {
+        let mut scheduler = RedrawScheduler::default();
+        let now = Instant::now();
+
+        assert!(scheduler.needs_render(camera(0.5), 0));
+        scheduler.updated(now, camera(0.5), 0, true, true);
+        assert_eq!(scheduler.next_update(), Some(now));
+
+        scheduler.set_mode(RedrawMode::Continuous {
+            max_fps: Some(10.0),
+        });
+        scheduler.updated(now, camera(0.5), 0, true, true);
+        assert_eq!(
+            scheduler.next_update(),
+            Some(now + Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn test_on_demand() {
+        let mut scheduler = RedrawScheduler::default();
+        scheduler.set_mode(RedrawMode::OnDemand);
+        let now = Instant::now();
+
+        assert!(scheduler.needs_render(camera(0.5), 0));
+        scheduler.updated(now, camera(0.5), 0, true, false);
+        assert_eq!(scheduler.next_update(), Some(now));
+
+        // Nothing changed, so the map only waits for tiles
+        assert!(!scheduler.needs_render(camera(0.5), 0));
+        scheduler.updated(now, camera(0.5), 0, false, false);
+        assert_eq!(scheduler.next_update(), Some(now + DATA_POLL_INTERVAL));
+
+        // Data arrived
+        assert!(scheduler.needs_render(camera(0.5), 1));
+        scheduler.updated(now, camera(0.5), 1, true, true);
+        assert!(!scheduler.needs_render(camera(0.5), 1));
+        scheduler.updated(now, camera(0.5), 1, false, true);
+        assert_eq!(scheduler.next_update(), None);
+
+        // The camera moved
+        assert!(scheduler.needs_render(camera(0.6), 1));
+
+        scheduler.request();
+        assert!(scheduler.needs_render(camera(0.5), 1));
+        assert_eq!(scheduler.next_update(), Some(now));
+    }
+}
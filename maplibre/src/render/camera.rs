@@ -65,10 +65,27 @@ impl ModelViewProjection {
     }
 }
 
+/// Maximum angle between the viewing direction and the nadir. Larger angles would show the
+/// horizon.
+pub const MAX_PITCH: cgmath::Deg<f64> = cgmath::Deg(60.0);
+
+/// Share of the distance to the closest ground in view at which the near plane is placed, see
+/// [`Perspective::near_plane`]. The remaining space keeps extruded buildings in front of the
+/// ground from being cut off.
+const NEAR_PLANE_MARGIN: f64 = 0.5;
+
+fn clamp_pitch(pitch: cgmath::Rad<f64>) -> cgmath::Rad<f64> {
+    cgmath::Rad(pitch.0.clamp(0.0, cgmath::Rad::from(MAX_PITCH).0))
+}
+
 #[derive(Debug, Clone)]
 pub struct Camera {
+    /// Position of the eye in world coordinates.
     pub position: Point3<f64>,
-    pub yaw: cgmath::Rad<f64>,
+    /// Rotation of the map. The camera faces north at a bearing of zero and east at 90 degrees.
+    pub bearing: cgmath::Rad<f64>,
+    /// Tilt of the camera. The camera looks straight down at a pitch of zero. The pitch is
+    /// clamped to [`MAX_PITCH`] when building the view matrix.
     pub pitch: cgmath::Rad<f64>,
 
     pub width: f64,
@@ -80,23 +97,23 @@ impl SignificantlyDifferent for Camera {
 
     fn ne(&self, other: &Self, epsilon: Self::Epsilon) -> bool {
         self.position.abs_diff_ne(&other.position, epsilon)
-            || self.yaw.abs_diff_ne(&other.yaw, epsilon)
+            || self.bearing.abs_diff_ne(&other.bearing, epsilon)
             || self.pitch.abs_diff_ne(&other.pitch, epsilon)
     }
 }
 
 impl Camera {
-    pub fn new<V: Into<Point3<f64>>, Y: Into<cgmath::Rad<f64>>, P: Into<cgmath::Rad<f64>>>(
+    pub fn new<V: Into<Point3<f64>>, B: Into<cgmath::Rad<f64>>, P: Into<cgmath::Rad<f64>>>(
         position: V,
-        yaw: Y,
+        bearing: B,
         pitch: P,
         width: u32,
         height: u32,
     ) -> Self {
         Self {
             position: position.into(),
-            yaw: yaw.into(),
-            pitch: pitch.into(),
+            bearing: bearing.into(),
+            pitch: clamp_pitch(pitch.into()),
             width: width as f64,
             height: height as f64,
         }
@@ -107,17 +124,39 @@ impl Camera {
         self.height = height as f64;
     }
 
+    /// Sets the pitch, clamped to `0..=MAX_PITCH`.
+    pub fn set_pitch<P: Into<cgmath::Rad<f64>>>(&mut self, pitch: P) {
+        self.pitch = clamp_pitch(pitch.into());
+    }
+
+    /// Sets the bearing, normalized to `0..2π`.
+    pub fn set_bearing<B: Into<cgmath::Rad<f64>>>(&mut self, bearing: B) {
+        self.bearing = bearing.into().normalize();
+    }
+
+    /// The direction in which the camera looks.
+    fn direction(&self) -> Vector3<f64> {
+        let pitch = clamp_pitch(self.pitch);
+        // The y-axis of the world points south
+        Vector3::new(
+            self.bearing.sin() * pitch.sin(),
+            -self.bearing.cos() * pitch.sin(),
+            -pitch.cos(),
+        )
+    }
+
     fn calc_matrix(&self) -> Matrix4<f64> {
+        // The up vector points south because FLIP_Y flips the y-axis afterwards
         Matrix4::look_to_rh(
             self.position,
-            Vector3::new(self.yaw.cos(), self.pitch.sin(), self.yaw.sin()).normalize(),
-            Vector3::unit_y(),
+            self.direction(),
+            Vector3::new(-self.bearing.sin(), self.bearing.cos(), 0.0),
         )
     }
 
     #[tracing::instrument(skip_all)]
     pub fn calc_view_proj(&self, perspective: &Perspective) -> ViewProjection {
-        ViewProjection(FLIP_Y * perspective.current_projection * self.calc_matrix())
+        ViewProjection(FLIP_Y * perspective.projection(self) * self.calc_matrix())
     }
 
     /// A transform which can be used to transfrom between clip and window space.
@@ -235,6 +274,22 @@ impl Camera {
         )
     }
 
+    /// Gets the world coordinates for the specified `window` coordinates on the `z=0` plane. If
+    /// the ray through `window` does not hit the plane before the far plane, the point on the far
+    /// plane is projected onto the `z=0` plane instead.
+    fn window_to_world_at_ground_or_far(
+        &self,
+        window: &Vector2<f64>,
+        inverted_view_proj: &InvertedViewProjection,
+    ) -> Vector3<f64> {
+        self.window_to_world_at_ground(window, inverted_view_proj)
+            .unwrap_or_else(|| {
+                let far_world = self
+                    .window_to_world(&Vector3::new(window.x, window.y, 1.0), inverted_view_proj);
+                Vector3::new(far_world.x, far_world.y, 0.0)
+            })
+    }
+
     /// Gets the world coordinates for the specified `window` coordinates on the `z=0` plane.
     pub fn window_to_world_at_ground(
         &self,
@@ -271,23 +326,43 @@ impl Camera {
         &self,
         inverted_view_proj: &InvertedViewProjection,
     ) -> Option<Aabb2<f64>> {
-        let screen_bounding_box = [
-            Vector2::new(0.0, 0.0),
-            Vector2::new(self.width, 0.0),
-            Vector2::new(self.width, self.height),
-            Vector2::new(0.0, self.height),
-        ]
-        .map(|point| self.window_to_world_at_ground(&point, inverted_view_proj));
-
         let (min, max) = bounds_from_points(
-            screen_bounding_box
+            self.view_region(inverted_view_proj)?
                 .into_iter()
-                .flatten()
                 .map(|point| [point.x, point.y]),
         )?;
 
         Some(Aabb2::new(Point2::from(min), Point2::from(max)))
     }
+
+    /// Calculates the visible area on the `z=0` plane. The corners are in the order top-left,
+    /// top-right, bottom-right and bottom-left of the window. If the camera is pitched the area is
+    /// a trapezoid which is cut off at the far plane.
+    ///
+    /// *Note:* Returns `None` if the `z=0` plane is not in view.
+    pub fn view_region(
+        &self,
+        inverted_view_proj: &InvertedViewProjection,
+    ) -> Option<[Point2<f64>; 4]> {
+        let corners = [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(self.width, 0.0),
+            Vector2::new(self.width, self.height),
+            Vector2::new(0.0, self.height),
+        ];
+
+        if corners.iter().all(|corner| {
+            self.window_to_world_at_ground(corner, inverted_view_proj)
+                .is_none()
+        }) {
+            return None;
+        }
+
+        Some(corners.map(|corner| {
+            let world = self.window_to_world_at_ground_or_far(&corner, inverted_view_proj);
+            Point2::new(world.x, world.y)
+        }))
+    }
     /// An alternative implementation for `view_bounding_box`.
     ///
     /// This implementation works in the NDC space. We are creating a plane in the world 3D space.
@@ -349,10 +424,10 @@ impl Camera {
 
 pub struct Perspective {
     fovy: cgmath::Rad<f64>,
+    /// The farthest distance of the near plane, see [`Perspective::near_plane`].
     znear: f64,
     zfar: f64,
-
-    current_projection: Matrix4<f64>,
+    aspect: f64,
 }
 
 impl Perspective {
@@ -363,26 +438,39 @@ impl Perspective {
         znear: f64,
         zfar: f64,
     ) -> Self {
-        let rad = fovy.into();
         Self {
-            current_projection: Self::calc_matrix(width as f64 / height as f64, rad, znear, zfar),
-            fovy: rad,
+            fovy: fovy.into(),
             znear,
             zfar,
+            aspect: width as f64 / height as f64,
         }
     }
 
+    /// The vertical field of view.
+    pub fn fovy(&self) -> cgmath::Rad<f64> {
+        self.fovy
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
-        self.current_projection = Self::calc_matrix(
-            width as f64 / height as f64,
-            self.fovy,
-            self.znear,
-            self.zfar,
-        );
+        self.aspect = width as f64 / height as f64;
     }
 
-    fn calc_matrix(aspect: f64, fovy: cgmath::Rad<f64>, znear: f64, zfar: f64) -> Matrix4<f64> {
-        OPENGL_TO_WGPU_MATRIX * cgmath::perspective(fovy, aspect, znear, zfar)
+    /// The distance of the near plane from `camera`. While the camera is pitched, the ground at
+    /// the bottom edge of the window comes closer to the camera than the height of the camera.
+    /// The near plane moves along, such that it never cuts off the ground in view. Otherwise the
+    /// bottom of the map would be clipped and, at steep pitches, no ray through the corners of
+    /// the window would hit the ground in front of the near plane, see [`Camera::view_region`].
+    fn near_plane(&self, camera: &Camera) -> f64 {
+        let pitch = clamp_pitch(camera.pitch);
+        // The ray through the bottom edge is tilted by half the field of view towards the ground
+        let closest_ground =
+            camera.position.z / (pitch.cos() + (self.fovy / 2.0).tan() * pitch.sin());
+        self.znear.min(closest_ground * NEAR_PLANE_MARGIN)
+    }
+
+    fn projection(&self, camera: &Camera) -> Matrix4<f64> {
+        OPENGL_TO_WGPU_MATRIX
+            * cgmath::perspective(self.fovy, self.aspect, self.near_plane(camera), self.zfar)
     }
 }
 
@@ -400,7 +488,7 @@ mod tests {
         let height = 1080.0;
         let camera = Camera::new(
             (0.0, 5.0, 5000.0),
-            cgmath::Deg(-90.0),
+            cgmath::Deg(0.0),
             cgmath::Deg(45.0),
             width as u32,
             height as u32,
@@ -485,4 +573,83 @@ mod tests {
 
         //assert!(reverse_world.abs_diff_eq(&world_pos, 0.05))
     }
+
+    #[test]
+    fn test_view_region() {
+        let mut camera = Camera::new(
+            (0.0, 0.0, 150.0),
+            cgmath::Deg(0.0),
+            cgmath::Deg(0.0),
+            800,
+            600,
+        );
+        let perspective = Perspective::new(800, 600, cgmath::Deg(90.0), 100.0, 2000.0);
+
+        let [top_left, top_right, bottom_right, bottom_left] = camera
+            .view_region(&camera.calc_view_proj(&perspective).invert())
+            .unwrap();
+        assert!((top_left.y - (-bottom_left.y)).abs() < 1e-6);
+        assert!(((top_right.x - top_left.x) - (bottom_right.x - bottom_left.x)).abs() < 1e-6);
+
+        // Looking north, the far edge at the top of the window is wider
+        camera.set_pitch(cgmath::Deg(30.0));
+        let [top_left, top_right, bottom_right, bottom_left] = camera
+            .view_region(&camera.calc_view_proj(&perspective).invert())
+            .unwrap();
+        assert!(top_left.y < bottom_left.y);
+        assert!(top_right.x - top_left.x > bottom_right.x - bottom_left.x);
+    }
+
+    #[test]
+    fn test_view_region_at_max_pitch() {
+        let mut camera = Camera::new(
+            (0.0, 0.0, 150.0),
+            cgmath::Deg(0.0),
+            super::MAX_PITCH,
+            800,
+            600,
+        );
+        let perspective = Perspective::new(800, 600, cgmath::Deg(110.0), 100.0, 2000.0);
+
+        // The top of the window is above the horizon, the bottom corners are on the ground
+        let inverted_view_proj = camera.calc_view_proj(&perspective).invert();
+        for corner in [Vector2::new(0.0, 600.0), Vector2::new(800.0, 600.0)] {
+            assert!(camera
+                .window_to_world_at_ground(&corner, &inverted_view_proj)
+                .is_some());
+        }
+        assert!(camera
+            .view_region_bounding_box(&inverted_view_proj)
+            .is_some());
+
+        // The ground right below the bottom edge of the window is in front of the near plane
+        camera.set_pitch(cgmath::Deg(45.0));
+        let bottom = camera
+            .window_to_world_at_ground(
+                &Vector2::new(400.0, 600.0),
+                &camera.calc_view_proj(&perspective).invert(),
+            )
+            .unwrap();
+        // The bottom edge is tilted by half the field of view beyond the point below the camera
+        let behind_camera = 150.0 * (55.0_f64 - 45.0).to_radians().tan();
+        assert!((bottom.y - behind_camera).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_clamp_pitch() {
+        let mut camera = Camera::new(
+            (0.0, 0.0, 150.0),
+            cgmath::Deg(0.0),
+            cgmath::Deg(0.0),
+            800,
+            600,
+        );
+
+        camera.set_pitch(cgmath::Deg(80.0));
+        assert!(camera
+            .pitch
+            .abs_diff_eq(&cgmath::Rad::from(super::MAX_PITCH), 1e-9));
+        camera.set_pitch(cgmath::Deg(-10.0));
+        assert_eq!(camera.pitch, cgmath::Rad(0.0));
+    }
 }
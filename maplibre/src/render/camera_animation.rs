@@ -0,0 +1,326 @@
+//! Animated transitions of the camera, like `easeTo` and `flyTo` of MapLibre GL JS.
+
+use crate::coords::TILE_SIZE;
+use cgmath::{Angle, InnerSpace, Rad, Vector2};
+use instant::Instant;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+/// Curvature of the flight path. This is the value which is recommended by van Wijk and Nuij and
+/// used by MapLibre GL JS.
+const FLY_CURVE: f64 = 1.42;
+
+/// The parts of the view which can be animated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraState {
+    /// Center of the view in world coordinates at zoom 0, divided by the tile size. Both
+    /// components are within `0..1`.
+    pub center: Vector2<f64>,
+    pub zoom: f64,
+    pub pitch: Rad<f64>,
+    pub bearing: Rad<f64>,
+}
+
+/// Eases the progress of an animation in and out.
+fn ease_in_out(t: f64) -> f64 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+    }
+}
+
+fn lerp(from: f64, to: f64, k: f64) -> f64 {
+    from + (to - from) * k
+}
+
+/// Describes how center and zoom change during the animation.
+#[derive(Debug)]
+enum Path {
+    /// Center and zoom are interpolated linearly.
+    Ease,
+    /// Zooms out and in again while moving the center, see "Smooth and efficient zooming and
+    /// panning" by van Wijk and Nuij.
+    Fly {
+        r0: f64,
+        w0: f64,
+        u1: f64,
+        length: f64,
+    },
+    /// The center stays the same and only the zoom changes.
+    FlyInPlace { direction: f64, length: f64 },
+}
+
+#[derive(Default)]
+struct HandleState {
+    completed: Option<bool>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to true once the animation finished or to false if it was interrupted.
+#[derive(Clone, Default)]
+pub struct AnimationHandle(Arc<Mutex<HandleState>>);
+
+impl AnimationHandle {
+    /// Whether the animation either finished or was interrupted.
+    pub fn is_done(&self) -> bool {
+        self.0.lock().unwrap().completed.is_some()
+    }
+
+    fn resolve(&self, completed: bool) {
+        let mut state = self.0.lock().unwrap();
+        if state.completed.is_none() {
+            state.completed = Some(completed);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Future for AnimationHandle {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap();
+        match state.completed {
+            Some(completed) => Poll::Ready(completed),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Animates the camera from one [`CameraState`] to another. Dropping an unfinished animation
+/// resolves its [`AnimationHandle`] with false.
+pub struct CameraAnimation {
+    from: CameraState,
+    to: CameraState,
+    path: Path,
+    duration: Duration,
+    start: Option<Instant>,
+    handle: AnimationHandle,
+}
+
+impl CameraAnimation {
+    /// Interpolates center, zoom, pitch and bearing linearly.
+    pub fn ease(from: CameraState, to: CameraState, duration: Duration) -> Self {
+        Self {
+            from,
+            to,
+            path: Path::Ease,
+            duration,
+            start: None,
+            handle: AnimationHandle::default(),
+        }
+    }
+
+    /// Zooms out, moves to the target and zooms in again. The arc of the flight depends on the
+    /// size of the viewport.
+    pub fn fly(
+        from: CameraState,
+        to: CameraState,
+        viewport_size: (f64, f64),
+        duration: Duration,
+    ) -> Self {
+        let rho2 = FLY_CURVE * FLY_CURVE;
+        let w0 = viewport_size.0.max(viewport_size.1);
+        let w1 = w0 / 2.0_f64.powf(to.zoom - from.zoom);
+        // Distance between the centers in pixels at the start zoom
+        let u1 = (to.center - from.center).magnitude() * TILE_SIZE * 2.0_f64.powf(from.zoom);
+
+        let r = |i: usize| {
+            let (w, sign) = if i == 0 { (w0, 1.0) } else { (w1, -1.0) };
+            let b = (w1 * w1 - w0 * w0 + sign * rho2 * rho2 * u1 * u1) / (2.0 * w * rho2 * u1);
+            ((b * b + 1.0).sqrt() - b).ln()
+        };
+
+        let r0 = r(0);
+        let length = (r(1) - r0) / FLY_CURVE;
+
+        let path = if u1 > 1e-6 && length.is_finite() {
+            Path::Fly { r0, w0, u1, length }
+        } else if (w0 - w1).abs() > 1e-6 {
+            Path::FlyInPlace {
+                direction: if w1 < w0 { -1.0 } else { 1.0 },
+                length: (w1 / w0).ln().abs() / FLY_CURVE,
+            }
+        } else {
+            Path::Ease
+        };
+
+        Self {
+            from,
+            to,
+            path,
+            duration,
+            start: None,
+            handle: AnimationHandle::default(),
+        }
+    }
+
+    pub fn handle(&self) -> AnimationHandle {
+        self.handle.clone()
+    }
+
+    /// Returns the state at the progress `k` within `0..1` of the animation.
+    pub fn sample(&self, k: f64) -> CameraState {
+        if k >= 1.0 {
+            return self.to;
+        }
+
+        let (center_k, zoom) = match self.path {
+            Path::Ease => (k, lerp(self.from.zoom, self.to.zoom, k)),
+            Path::Fly { r0, w0, u1, length } => {
+                let s = k * length;
+                let rho2 = FLY_CURVE * FLY_CURVE;
+                let w = r0.cosh() / (r0 + FLY_CURVE * s).cosh();
+                let u = w0 * ((r0.cosh() * (r0 + FLY_CURVE * s).tanh() - r0.sinh()) / rho2) / u1;
+                (u, self.from.zoom - w.log2())
+            }
+            Path::FlyInPlace { direction, length } => {
+                let w = (direction * FLY_CURVE * k * length).exp();
+                (0.0, self.from.zoom - w.log2())
+            }
+        };
+
+        CameraState {
+            center: self.from.center + (self.to.center - self.from.center) * center_k,
+            zoom,
+            pitch: Rad(lerp(self.from.pitch.0, self.to.pitch.0, k)),
+            // Rotate in the direction which is shorter
+            bearing: self.from.bearing
+                + (self.to.bearing - self.from.bearing).normalize_signed() * k,
+        }
+    }
+
+    /// Advances the animation to `now`. Returns the current state and whether the animation
+    /// finished. The first call starts the animation.
+    pub fn tick(&mut self, now: Instant) -> (CameraState, bool) {
+        let start = *self.start.get_or_insert(now);
+        let t = if self.duration.is_zero() {
+            1.0
+        } else {
+            (now.duration_since(start).as_secs_f64() / self.duration.as_secs_f64()).min(1.0)
+        };
+
+        if t >= 1.0 {
+            self.handle.resolve(true);
+            (self.to, true)
+        } else {
+            (self.sample(ease_in_out(t)), false)
+        }
+    }
+}
+
+impl Drop for CameraAnimation {
+    fn drop(&mut self) {
+        self.handle.resolve(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ease_in_out, CameraAnimation, CameraState};
+    use cgmath::{Angle, Deg, Rad, Vector2};
+    use instant::Instant;
+    use std::time::Duration;
+
+    fn state(x: f64, zoom: f64) -> CameraState {
+        CameraState {
+            center: Vector2::new(x, 0.5),
+            zoom,
+            pitch: Rad(0.0),
+            bearing: Rad(0.0),
+        }
+    }
+
+    #[test]
+    fn test_ease_in_out() {
+        assert_eq!(ease_in_out(0.0), 0.0);
+        assert_eq!(ease_in_out(0.5), 0.5);
+        assert_eq!(ease_in_out(1.0), 1.0);
+    }
+
+    #[test]
+    fn test_ease() {
+        let animation = CameraAnimation::ease(state(0.2, 2.0), state(0.4, 4.0), Duration::ZERO);
+        let middle = animation.sample(0.5);
+
+        assert!((middle.center.x - 0.3).abs() < 1e-9);
+        assert!((middle.zoom - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_bearing_takes_shorter_direction() {
+        let animation = CameraAnimation::ease(
+            CameraState {
+                bearing: Rad::from(Deg(350.0)),
+                ..state(0.2, 2.0)
+            },
+            CameraState {
+                bearing: Rad::from(Deg(10.0)),
+                ..state(0.2, 2.0)
+            },
+            Duration::ZERO,
+        );
+
+        let middle = Deg::from(animation.sample(0.5).bearing.normalize());
+        assert!(middle.0 < 1e-6 || (middle.0 - 360.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fly_zooms_out() {
+        let from = state(0.2, 8.0);
+        let to = state(0.4, 8.0);
+        let animation = CameraAnimation::fly(from, to, (800.0, 600.0), Duration::ZERO);
+
+        let start = animation.sample(0.0);
+        assert!((start.center.x - 0.2).abs() < 1e-9);
+        assert!((start.zoom - 8.0).abs() < 1e-9);
+
+        let middle = animation.sample(0.5);
+        assert!(middle.zoom < 8.0);
+        assert!((middle.center.x - 0.3).abs() < 1e-9);
+
+        assert_eq!(animation.sample(1.0), to);
+    }
+
+    #[test]
+    fn test_fly_in_place() {
+        let animation = CameraAnimation::fly(
+            state(0.2, 2.0),
+            state(0.2, 4.0),
+            (800.0, 600.0),
+            Duration::ZERO,
+        );
+
+        let middle = animation.sample(0.5);
+        assert!((middle.center.x - 0.2).abs() < 1e-9);
+        assert!((middle.zoom - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut animation =
+            CameraAnimation::ease(state(0.2, 2.0), state(0.4, 4.0), Duration::from_secs(1));
+        let handle = animation.handle();
+
+        let now = Instant::now();
+        assert!(!animation.tick(now).1);
+        assert!(!handle.is_done());
+        assert!(animation.tick(now + Duration::from_secs(2)).1);
+        assert!(handle.is_done());
+
+        let interrupted =
+            CameraAnimation::ease(state(0.2, 2.0), state(0.4, 4.0), Duration::from_secs(1));
+        let handle = interrupted.handle();
+        drop(interrupted);
+        assert!(handle.is_done());
+    }
+}
@@ -0,0 +1,97 @@
+//! Capabilities of the GPU backend which was picked when the [`crate::render::Renderer`] was
+//! initialized. Applications can query them to adapt their settings and style to the device, e.g.
+//! to only enable MSAA if it is supported.
+
+use crate::render::settings::SUPPORTED_MSAA_SAMPLES;
+
+/// What the picked backend supports. Backends which do not implement all of WebGPU, most notably
+/// WebGL2 and the GL backend on older desktop drivers, are called downlevel. Compared to WebGPU
+/// they lack:
+///
+/// * compute shaders, see [`RendererCapabilities::compute_shaders`],
+/// * indirect draws, see [`RendererCapabilities::indirect_execution`],
+/// * large textures, which limits the size of raster tiles and of the glyph atlas, see
+///   [`RendererCapabilities::max_texture_dimension_2d`],
+/// * storage buffers in vertex and fragment shaders,
+/// * features which are specific to the adapter, which are skipped when the device is requested.
+///
+/// The renderer only relies on capabilities which WebGL2 provides, so maps render the same on
+/// downlevel backends. Render paths which need more than that must check these capabilities.
+#[derive(Debug, Clone)]
+pub struct RendererCapabilities {
+    pub backend: wgpu::Backend,
+    pub adapter_name: String,
+    /// Whether the backend implements all of WebGPU.
+    pub is_webgpu_compliant: bool,
+    /// Whether compute shaders are available. Not available with WebGL2.
+    pub compute_shaders: bool,
+    /// Whether draws can read their arguments from buffers. Not available with WebGL2.
+    pub indirect_execution: bool,
+    /// Whether vertex and fragment shaders can read storage buffers. Not available with WebGL2.
+    pub storage_buffers: bool,
+    /// Maximum width and height of textures. WebGL2 only guarantees 2048.
+    pub max_texture_dimension_2d: u32,
+    /// Whether shaders can read textures of 32-bit floats, in which hillshade layers store the
+    /// elevations of raster DEM tiles.
+    pub float_textures: bool,
+    /// Sample counts which can be used for [`crate::render::settings::Msaa`], in ascending order.
+    pub msaa_samples: Vec<u32>,
+    /// Formats with which the surface of the window can be configured, preferred format first.
+    /// Empty for headless renderers, which can render into any format.
+    pub surface_formats: Vec<wgpu::TextureFormat>,
+    /// Features with which the device is requested.
+    pub features: wgpu::Features,
+    /// Limits with which the device is requested.
+    pub limits: wgpu::Limits,
+    /// Features which were requested in [`crate::render::settings::WgpuSettings::features`] but
+    /// are not supported by the adapter.
+    pub missing_features: wgpu::Features,
+}
+
+impl RendererCapabilities {
+    pub(crate) fn new(
+        adapter: &wgpu::Adapter,
+        features: wgpu::Features,
+        limits: wgpu::Limits,
+        missing_features: wgpu::Features,
+    ) -> Self {
+        let info = adapter.get_info();
+        let downlevel = adapter.get_downlevel_properties();
+
+        Self {
+            backend: info.backend,
+            adapter_name: info.name,
+            is_webgpu_compliant: downlevel.is_webgpu_compliant(),
+            compute_shaders: downlevel
+                .flags
+                .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS),
+            indirect_execution: downlevel
+                .flags
+                .contains(wgpu::DownlevelFlags::INDIRECT_EXECUTION),
+            storage_buffers: downlevel.flags.contains(
+                wgpu::DownlevelFlags::VERTEX_STORAGE | wgpu::DownlevelFlags::FRAGMENT_STORAGE,
+            ),
+            max_texture_dimension_2d: limits.max_texture_dimension_2d,
+            float_textures: adapter
+                .get_texture_format_features(wgpu::TextureFormat::R32Float)
+                .allowed_usages
+                .contains(wgpu::TextureUsages::TEXTURE_BINDING),
+            // Both WebGPU and WebGL2 guarantee these sample counts for renderable formats
+            msaa_samples: SUPPORTED_MSAA_SAMPLES.to_vec(),
+            surface_formats: Vec::new(),
+            features,
+            limits,
+            missing_features,
+        }
+    }
+
+    /// Whether MSAA with `samples` samples per pixel is supported.
+    pub fn supports_msaa(&self, samples: u32) -> bool {
+        self.msaa_samples.contains(&samples)
+    }
+
+    /// Whether the renderer runs on WebGL2 or another GL backend.
+    pub fn is_gl(&self) -> bool {
+        self.backend == wgpu::Backend::Gl
+    }
+}
@@ -0,0 +1,370 @@
+//! Culls the tiles which lie outside of the view frustum. The tiles in view are the tiles of the
+//! bounding box of the visible part of the map, see [`crate::coords::ViewRegion`]. Once the map is
+//! pitched and zoomed out, many of them are outside of the frustum.
+//!
+//! By default, tiles are culled on the CPU before their layers are queued, see
+//! [`is_tile_visible`]. If [`crate::render::settings::RendererSettings::gpu_culling`] is enabled
+//! and the device supports compute shaders and indirect draws, the layers of all tiles are queued.
+//! A compute pass then writes the arguments of indirect draws, which draw the layers of culled
+//! tiles without instances. Backends without compute shaders, like WebGL2, fall back to the CPU.
+
+use crate::coords::EXTENT;
+use crate::render::camera::ViewProjection;
+use crate::render::resource::IndexEntry;
+use crate::render::shaders::Mat4x4f32;
+use crate::render::stages::TILE_VIEW_SIZE;
+use crate::render::tile_view_pattern::TileShape;
+use bytemuck_derive::{Pod, Zeroable};
+use cgmath::{Matrix4, Vector4};
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+
+/// Maximum number of draws of tile layers which are culled on the GPU per frame. Further draws
+/// are drawn regardless of whether their tile is visible.
+pub const MAX_CULLED_DRAWS: usize = 8192;
+/// Must match the `workgroup_size` of the compute shader.
+const WORKGROUP_SIZE: u32 = 64;
+/// Size of the count which precedes the items in the buffer of the items.
+const ITEMS_HEADER_SIZE: wgpu::BufferAddress = size_of::<u32>() as wgpu::BufferAddress;
+
+/// Returns false if the tile, which is placed in the world by `transform`, is outside of the view
+/// frustum. Tiles are treated as flat, so extrusions on tiles at the edge of the view might be
+/// culled although they reach into the view.
+pub fn is_tile_visible(view_proj: &ViewProjection, transform: &Matrix4<f64>) -> bool {
+    let corners = [[0.0, 0.0], [EXTENT, 0.0], [0.0, EXTENT], [EXTENT, EXTENT]]
+        .map(|[x, y]| view_proj.project(transform * Vector4::new(x, y, 0.0, 1.0)));
+
+    // The tile is culled if all of its corners lie beyond the same plane of the frustum
+    let beyond = |is_beyond: fn(&Vector4<f64>) -> bool| corners.iter().all(is_beyond);
+    !(beyond(|corner| corner.x < -corner.w)
+        || beyond(|corner| corner.x > corner.w)
+        || beyond(|corner| corner.y < -corner.w)
+        || beyond(|corner| corner.y > corner.w))
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct ShaderCullItem {
+    /// Index of the transform of the tile.
+    pub tile: u32,
+    pub index_count: u32,
+}
+
+/// Arguments of [`wgpu::RenderPass::draw_indexed_indirect`], which are written by the compute
+/// shader.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub(crate) struct ShaderDrawIndexedIndirect {
+    pub index_count: u32,
+    /// Zero if the draw is culled.
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+/// The tiles and draws which are uploaded for culling on the GPU every frame.
+#[derive(Default)]
+pub struct CullBatch {
+    /// Model-view-projection of each tile shape, indexed by [`TileShape::index`].
+    tiles: Vec<Mat4x4f32>,
+    items: Vec<ShaderCullItem>,
+    /// Slot of the indirect draw of each item, keyed by the offsets of its tile shape and of its
+    /// indices within their buffers.
+    slots: HashMap<(wgpu::BufferAddress, wgpu::BufferAddress), u32>,
+}
+
+impl CullBatch {
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+        self.items.clear();
+        self.slots.clear();
+    }
+
+    /// Sets the model-view-projection of the tile at `index`.
+    pub fn set_tile(&mut self, index: usize, transform: Mat4x4f32) {
+        if index >= self.tiles.len() {
+            self.tiles.resize(index + 1, Mat4x4f32::default());
+        }
+        self.tiles[index] = transform;
+    }
+
+    /// Adds a draw of `index_count` indices of the tile at `tile`. Returns false if there are
+    /// [`MAX_CULLED_DRAWS`] already.
+    pub fn push_draw(
+        &mut self,
+        key: (wgpu::BufferAddress, wgpu::BufferAddress),
+        tile: u32,
+        index_count: u32,
+    ) -> bool {
+        if self.items.len() >= MAX_CULLED_DRAWS {
+            return false;
+        }
+        self.slots.insert(key, self.items.len() as u32);
+        self.items.push(ShaderCullItem { tile, index_count });
+        true
+    }
+
+    /// Adds the draws of `items`, which are drawn with the tile shapes of the view.
+    pub fn build(&mut self, view_proj: &ViewProjection, items: &[(IndexEntry, TileShape)]) {
+        self.clear();
+
+        let mut tiles = HashSet::new();
+        for (entry, shape) in items {
+            let tile = shape.index();
+            if tiles.insert(tile) {
+                self.set_tile(
+                    tile,
+                    view_proj
+                        .to_model_view_projection(shape.transform)
+                        .downcast()
+                        .into(),
+                );
+            }
+
+            if !self.push_draw(
+                draw_key(entry, shape),
+                tile as u32,
+                entry.indices_range().end,
+            ) {
+                tracing::warn!("Too many draws to cull on the GPU, drawing the remaining ones");
+                break;
+            }
+        }
+    }
+
+    pub fn tiles_as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.tiles)
+    }
+
+    pub fn items_as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.items)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+fn draw_key(entry: &IndexEntry, shape: &TileShape) -> (wgpu::BufferAddress, wgpu::BufferAddress) {
+    (shape.buffer_range.start, entry.indices_buffer_range().start)
+}
+
+/// Culls the draws of tile layers on the GPU with a compute pass.
+pub struct GpuCulling {
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    tiles: wgpu::Buffer,
+    items: wgpu::Buffer,
+    /// Indirect draws, one per item of the batch.
+    draws: wgpu::Buffer,
+    batch: CullBatch,
+}
+
+impl GpuCulling {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("culling shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/culling.compute.wgsl").into()),
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("culling bind group layout"),
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, false),
+            ],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("culling pipeline"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("culling pipeline layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            module: &module,
+            entry_point: "main",
+        });
+
+        let tiles = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("culling tiles buffer"),
+            size: TILE_VIEW_SIZE * size_of::<Mat4x4f32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let items = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("culling items buffer"),
+            size: ITEMS_HEADER_SIZE
+                + (MAX_CULLED_DRAWS * size_of::<ShaderCullItem>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let draws = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("culling draws buffer"),
+            size: (MAX_CULLED_DRAWS * size_of::<ShaderDrawIndexedIndirect>())
+                as wgpu::BufferAddress,
+            // Copied for reading the draws back in benchmarks
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("culling bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: tiles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: items.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: draws.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+            tiles,
+            items,
+            draws,
+            batch: CullBatch::default(),
+        }
+    }
+
+    /// Uploads the tiles and draws of `items` which are culled by the next [`GpuCulling::dispatch`].
+    pub fn upload(
+        &mut self,
+        queue: &wgpu::Queue,
+        view_proj: &ViewProjection,
+        items: &[(IndexEntry, TileShape)],
+    ) {
+        self.batch.build(view_proj, items);
+        self.write_batch(queue);
+    }
+
+    /// The batch which is written by [`GpuCulling::write_batch`].
+    pub(crate) fn batch_mut(&mut self) -> &mut CullBatch {
+        &mut self.batch
+    }
+
+    /// Writes the tiles and draws of the batch into the buffers of the compute pass.
+    pub(crate) fn write_batch(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.tiles, 0, self.batch.tiles_as_bytes());
+        queue.write_buffer(
+            &self.items,
+            0,
+            bytemuck::bytes_of(&(self.batch.len() as u32)),
+        );
+        queue.write_buffer(&self.items, ITEMS_HEADER_SIZE, self.batch.items_as_bytes());
+    }
+
+    /// Records the compute pass which writes the indirect draws.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+        if self.batch.is_empty() {
+            return;
+        }
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("culling pass"),
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch(
+            (self.batch.len() as u32 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+            1,
+            1,
+        );
+    }
+
+    pub fn draws(&self) -> &wgpu::Buffer {
+        &self.draws
+    }
+
+    /// Returns the offset of the indirect draw of the layer `entry` on the tile `shape` within
+    /// [`GpuCulling::draws`]. `None` if the draw is not culled.
+    pub fn draw_offset(
+        &self,
+        entry: &IndexEntry,
+        shape: &TileShape,
+    ) -> Option<wgpu::BufferAddress> {
+        self.batch.slots.get(&draw_key(entry, shape)).map(|slot| {
+            *slot as wgpu::BufferAddress
+                * size_of::<ShaderDrawIndexedIndirect>() as wgpu::BufferAddress
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_tile_visible, CullBatch, MAX_CULLED_DRAWS};
+    use crate::context::{CameraTarget, ViewState};
+    use crate::coords::{LatLon, WorldTileCoords, Zoom};
+    use crate::WindowSize;
+    use cgmath::{Deg, Rad, Vector2};
+
+    #[test]
+    fn test_is_tile_visible() {
+        let mut view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+        view_state.jump_to(CameraTarget {
+            center: Some(LatLon::new(48.137, 11.575)),
+            zoom: Some(Zoom::new(6.0)),
+            pitch: Some(Rad::from(Deg(60.0))),
+            bearing: Some(Rad::from(Deg(45.0))),
+        });
+        let view_proj = view_state.view_projection();
+        let zoom = view_state.zoom();
+        let view_region = view_state.view_region().unwrap();
+
+        let (visible, culled): (Vec<WorldTileCoords>, Vec<WorldTileCoords>) = view_region
+            .iter_unwrapped()
+            .partition(|coords| is_tile_visible(&view_proj, &coords.transform_for_zoom(zoom)));
+
+        // The bounding box of the rotated and pitched view contains tiles beside the view
+        assert!(!visible.is_empty());
+        assert!(!culled.is_empty());
+
+        // The ground at the bottom of the window is not cut off by the near plane
+        let bottom = view_state
+            .window_to_lat_lon(&Vector2::new(400.0, 599.0))
+            .unwrap()
+            .into_world(zoom)
+            .into_world_tile(view_state.visible_level(), zoom);
+        assert!(visible.contains(&bottom));
+    }
+
+    #[test]
+    fn test_max_culled_draws() {
+        let mut batch = CullBatch::default();
+        for i in 0..MAX_CULLED_DRAWS {
+            assert!(batch.push_draw((0, i as u64), 0, 6));
+        }
+        assert!(!batch.push_draw((1, 0), 0, 6));
+        assert_eq!(batch.len(), MAX_CULLED_DRAWS);
+    }
+}
@@ -0,0 +1,34 @@
+//! Runs the compute pass which culls the draws of tile layers before the main pass, see
+//! [`crate::render::culling`].
+
+use crate::render::graph::{Node, NodeRunError, RenderContext, RenderGraphContext, SlotInfo};
+use crate::render::util::Eventually::Initialized;
+use crate::render::RenderState;
+
+pub struct CullingNode {}
+
+impl CullingNode {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Node for CullingNode {
+    fn input(&self) -> Vec<SlotInfo> {
+        vec![]
+    }
+
+    fn update(&mut self, _state: &mut RenderState) {}
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        state: &RenderState,
+    ) -> Result<(), NodeRunError> {
+        if let Initialized(Some(gpu_culling)) = &state.gpu_culling {
+            gpu_culling.dispatch(&mut render_context.command_encoder);
+        }
+        Ok(())
+    }
+}
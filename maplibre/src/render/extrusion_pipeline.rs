@@ -0,0 +1,89 @@
+//! Utility for declaring the pipeline which draws extruded polygons.
+
+use crate::platform::MIN_BUFFER_SIZE;
+use crate::render::resource::{FragmentState, VertexState};
+use crate::render::resource::{RenderPipeline, RenderPipelineDescriptor};
+use crate::render::settings::Msaa;
+use crate::render::shaders::ShaderGlobals;
+use std::cmp;
+
+pub struct ExtrusionPipeline {
+    msaa: Msaa,
+
+    vertex_state: VertexState,
+    fragment_state: FragmentState,
+}
+
+impl ExtrusionPipeline {
+    pub(crate) fn new(
+        msaa: Msaa,
+        vertex_state: VertexState,
+        fragment_state: FragmentState,
+    ) -> Self {
+        ExtrusionPipeline {
+            msaa,
+            vertex_state,
+            fragment_state,
+        }
+    }
+}
+
+impl RenderPipeline for ExtrusionPipeline {
+    fn describe_render_pipeline(self) -> RenderPipelineDescriptor {
+        // Extrusions may rise above the borders of their tile and are therefore not clipped by the
+        // tile masks
+        let stencil_state = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Keep,
+        };
+
+        let globals_buffer_byte_size =
+            cmp::max(MIN_BUFFER_SIZE, std::mem::size_of::<ShaderGlobals>() as u64);
+
+        RenderPipelineDescriptor {
+            label: Some("extrusion pipeline".into()),
+            layout: Some(vec![vec![wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(globals_buffer_byte_size),
+                },
+                count: None,
+            }]]),
+            vertex: self.vertex_state,
+            fragment: self.fragment_state,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                front_face: wgpu::FrontFace::Ccw,
+                strip_index_format: None,
+                cull_mode: None,
+                conservative: false,
+                unclipped_depth: false,
+            },
+            // The depth buffer is cleared before extrusions are drawn, because the flat layers
+            // use the depth for ordering layers instead of the distance to the camera
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth24PlusStencil8,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState {
+                    front: stencil_state,
+                    back: stencil_state,
+                    read_mask: 0xff,
+                    write_mask: 0x00,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.msaa.samples,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Captures of rendered frames, e.g. to share the map as image, see
+//! [`crate::map_schedule::MapSchedule::capture_frame`].
+
+use crate::error::{Error, RenderError};
+use crate::render::resource::BufferedTextureHead;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Pixels of a captured frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed RGBA rows of `width * height` pixels. The colors are premultiplied by alpha,
+    /// which only matters for a translucent
+    /// [`crate::render::settings::RendererSettings::clear_color`].
+    pub rgba: Vec<u8>,
+}
+
+type Mapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+/// A frame which has been copied into a buffer which is being mapped for reading.
+struct Readback {
+    head: BufferedTextureHead,
+    mapping: Mapping,
+}
+
+#[derive(Default)]
+struct CaptureState {
+    readback: Option<Readback>,
+    result: Option<Result<Image, Error>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the pixels of the next frame which is rendered after the capture was requested.
+#[derive(Clone, Default)]
+pub struct FrameCapture(Arc<Mutex<CaptureState>>);
+
+impl FrameCapture {
+    /// Resolves the capture with an error, unless it has been resolved already.
+    pub(crate) fn fail(&self, error: Error) {
+        let mut state = self.0.lock().unwrap();
+        if state.readback.is_none() && state.result.is_none() {
+            state.result = Some(Err(error));
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Reads the frame which has been copied into the output buffer of `head` once the buffer is
+    /// mapped.
+    fn read_back(&self, head: BufferedTextureHead, mapping: Mapping) {
+        let mut state = self.0.lock().unwrap();
+        state.readback = Some(Readback { head, mapping });
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Future for FrameCapture {
+    type Output = Result<Image, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap();
+
+        if let Some(readback) = &mut state.readback {
+            // The mapping wakes the task once the buffer is mapped
+            let mapped = match readback.mapping.as_mut().poll(cx) {
+                Poll::Ready(mapped) => mapped,
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(Readback { head, .. }) = state.readback.take() {
+                state.result = Some(
+                    mapped
+                        .map(|()| Image {
+                            width: head.width(),
+                            height: head.height(),
+                            rgba: head.read_mapped(),
+                        })
+                        .map_err(|e| Error::Render(RenderError::Readback(e.to_string()))),
+                );
+            }
+        }
+
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A requested capture which is fulfilled by the `GraphRunnerStage`. Dropping an unfulfilled
+/// request, e.g. because the renderer is dropped, resolves its [`FrameCapture`] with an error.
+pub(crate) struct CaptureRequest(FrameCapture);
+
+impl CaptureRequest {
+    pub fn new(capture: FrameCapture) -> Self {
+        Self(capture)
+    }
+
+    /// Hands the frame which has been copied into `head` over to the capture. The frame is read
+    /// once the GPU finished the copy.
+    pub fn fulfil(self, device: &wgpu::Device, head: BufferedTextureHead) {
+        let mapping = Box::pin(head.map_buffer());
+        // Calls the callback of the mapping on native platforms. In browsers the mapping resolves
+        // asynchronously instead.
+        device.poll(wgpu::Maintain::Wait);
+        self.0.read_back(head, mapping);
+    }
+
+    pub fn fail(self, error: Error) {
+        self.0.fail(error);
+    }
+}
+
+impl Drop for CaptureRequest {
+    fn drop(&mut self) {
+        self.0.fail(Error::Render(RenderError::Readback(
+            "the renderer was dropped before the frame was captured".to_string(),
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CaptureRequest, FrameCapture};
+    use crate::error::Error;
+
+    #[tokio::test]
+    async fn test_dropped_request_fails_capture() {
+        let capture = FrameCapture::default();
+        let request = CaptureRequest::new(capture.clone());
+
+        drop(request);
+        assert!(matches!(capture.await, Err(Error::Render(_))));
+    }
+}
@@ -0,0 +1,72 @@
+//! Utility for declaring the pipeline which shades raster DEM tiles.
+
+use crate::render::hillshade_tiles::HillshadeTiles;
+use crate::render::resource::{FragmentState, VertexState};
+use crate::render::resource::{RenderPipeline, RenderPipelineDescriptor};
+use crate::render::settings::Msaa;
+
+pub struct HillshadePipeline {
+    msaa: Msaa,
+
+    vertex_state: VertexState,
+    fragment_state: FragmentState,
+}
+
+impl HillshadePipeline {
+    pub(crate) fn new(
+        msaa: Msaa,
+        vertex_state: VertexState,
+        fragment_state: FragmentState,
+    ) -> Self {
+        HillshadePipeline {
+            msaa,
+            vertex_state,
+            fragment_state,
+        }
+    }
+}
+
+impl RenderPipeline for HillshadePipeline {
+    fn describe_render_pipeline(self) -> RenderPipelineDescriptor {
+        // The quads of hillshades cover exactly a tile. Therefore, they do not need to be clipped.
+        let stencil_state = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Keep,
+        };
+
+        RenderPipelineDescriptor {
+            label: Some("hillshade pipeline".into()),
+            layout: Some(vec![HillshadeTiles::bind_group_layout_entries()]),
+            vertex: self.vertex_state,
+            fragment: self.fragment_state,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                front_face: wgpu::FrontFace::Ccw,
+                strip_index_format: None,
+                cull_mode: None,
+                conservative: false,
+                unclipped_depth: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth24PlusStencil8,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: stencil_state,
+                    back: stencil_state,
+                    read_mask: 0xff,
+                    write_mask: 0x00,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.msaa.samples,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Stores the elevations of raster DEM tiles and decides which of them are shaded onto the tiles in
+//! view.
+
+use crate::coords::{WorldCoords, WorldTileCoords, Zoom, TILE_SIZE};
+use crate::render::raster_tiles::{tex_rect, RasterKey, TextureInView, TextureTiles};
+use crate::render::shaders::{ShaderHillshadeMetadata, Vec2f32, Vec4f32};
+use crate::render::tile_view_pattern::TileShape;
+
+/// Maximum amount of hillshades which can be drawn in a single frame.
+const HILLSHADE_VIEW_SIZE: wgpu::BufferAddress = 64;
+/// Amount of textures which are kept even though they are not in view.
+const HILLSHADE_TEXTURE_CAPACITY: usize = 64;
+
+/// A hillshade which is drawn onto a tile in view. The elevations might belong to a parent tile.
+pub type HillshadeInView = TextureInView<ShaderHillshadeMetadata>;
+
+/// How a hillshade layer shades the elevations, see [`ShaderHillshadeMetadata`].
+#[derive(Debug, Clone, Copy)]
+pub struct HillshadeStyle {
+    pub shadow: Vec4f32,
+    pub highlight: Vec4f32,
+    pub accent: Vec4f32,
+    pub exaggeration: f32,
+    pub illumination_direction: f32,
+}
+
+impl HillshadeStyle {
+    /// Describes how the elevations with the given `key` are shaded onto the tile of `shape`.
+    pub fn metadata(&self, shape: &TileShape, key: &RasterKey) -> ShaderHillshadeMetadata {
+        ShaderHillshadeMetadata {
+            tex_rect: tex_rect(&shape.coords, &key.0),
+            shadow: self.shadow,
+            highlight: self.highlight,
+            accent: self.accent,
+            latitude_range: latitude_range(&shape.coords),
+            exaggeration: self.exaggeration,
+            illumination_direction: self.illumination_direction,
+            dem_zoom: key.0.z as f32,
+        }
+    }
+}
+
+/// Stores the elevations of raster DEM tiles, which are native endian `f32`s.
+pub type HillshadeTiles = TextureTiles<ShaderHillshadeMetadata>;
+
+impl HillshadeTiles {
+    /// The layout of the bind groups of the textures. The elevations are stored as 32-bit floats,
+    /// which can not be filtered, so the shader loads the texels without a sampler.
+    pub fn bind_group_layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }]
+    }
+
+    pub fn from_device(device: &wgpu::Device) -> Self {
+        Self::new(
+            device,
+            "hillshade",
+            &Self::bind_group_layout_entries(),
+            None,
+            wgpu::TextureFormat::R32Float,
+            HILLSHADE_VIEW_SIZE,
+            HILLSHADE_TEXTURE_CAPACITY,
+        )
+    }
+}
+
+/// Returns the latitudes of the top and bottom edge of the tile at `coords` in degrees. The
+/// shader corrects the slope for the stretching of Web Mercator at these latitudes.
+fn latitude_range(coords: &WorldTileCoords) -> Vec2f32 {
+    let zoom = Zoom::new(coords.z as f64);
+    let latitude = |y: i32| {
+        WorldCoords::at_ground(0.0, y as f64 * TILE_SIZE)
+            .into_lat_lon(zoom)
+            .latitude as f32
+    };
+    [latitude(coords.y), latitude(coords.y + 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::latitude_range;
+    use crate::coords::WorldTileCoords;
+
+    #[test]
+    fn test_latitude_range() {
+        let [top, bottom] = latitude_range(&WorldTileCoords { x: 0, y: 0, z: 0 });
+        assert!((top - 85.05113).abs() < 1e-4);
+        assert!((bottom + 85.05113).abs() < 1e-4);
+
+        // The tile below the equator
+        let [top, bottom] = latitude_range(&WorldTileCoords { x: 3, y: 2, z: 2 });
+        assert!(top.abs() < 1e-4);
+        assert!((bottom + 66.51326).abs() < 1e-3);
+    }
+}
@@ -1,10 +1,14 @@
 //! The main render pass for this application.
 //!
-//! Right now there is only one render graph. A use case for multiple render passes would be
+//! Right now there is only one render graph. Extrusions are drawn in a second render pass within the
+//! same node. Another use case for multiple render passes would be
 //! [shadows](https://www.raywenderlich.com/books/metal-by-tutorials/v2.0/chapters/14-multipass-deferred-rendering).
 
 use crate::render::graph::{Node, NodeRunError, RenderContext, RenderGraphContext, SlotInfo};
-use crate::render::render_commands::{DrawMasks, DrawTiles};
+use crate::render::render_commands::{
+    DrawExtrusions, DrawHillshades, DrawMarkers, DrawMasks, DrawRasters, DrawSymbols,
+    DrawTileBoundaries, DrawTiles,
+};
 use crate::render::render_phase::{PhaseItem, RenderCommand};
 use crate::render::resource::TrackedRenderPass;
 use crate::render::stages::draw_graph;
@@ -48,23 +52,19 @@ impl Node for MainPassNode {
             return Ok(());
         };
 
-        let color_attachment = if let Some(texture) = multisampling_texture {
-            wgpu::RenderPassColorAttachment {
-                view: &texture.view,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
-                    store: true,
-                },
-                resolve_target: Some(render_target.deref()),
-            }
-        } else {
-            wgpu::RenderPassColorAttachment {
-                view: render_target.deref(),
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
-                    store: true,
-                },
-                resolve_target: None,
+        let color_attachment = |load: wgpu::LoadOp<wgpu::Color>| {
+            if let Some(texture) = multisampling_texture {
+                wgpu::RenderPassColorAttachment {
+                    view: &texture.view,
+                    ops: wgpu::Operations { load, store: true },
+                    resolve_target: Some(render_target.deref()),
+                }
+            } else {
+                wgpu::RenderPassColorAttachment {
+                    view: render_target.deref(),
+                    ops: wgpu::Operations { load, store: true },
+                    resolve_target: None,
+                }
             }
         };
 
@@ -73,9 +73,10 @@ impl Node for MainPassNode {
                 .command_encoder
                 .begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: None,
-                    color_attachments: &[color_attachment],
+                    color_attachments: &[color_attachment(wgpu::LoadOp::Clear(state.clear_color))],
                     depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                         view: &depth_texture.view,
+                        // Flat layers are drawn above the cleared depth, see `layer_depth`
                         depth_ops: Some(wgpu::Operations {
                             load: wgpu::LoadOp::Clear(0.0),
                             store: true,
@@ -93,9 +94,78 @@ impl Node for MainPassNode {
             DrawMasks::render(state, item, &mut tracked_pass);
         }
 
+        // Rasters and hillshades are interleaved in the order of their layers
+        let mut hillshades = state.hillshade_phase.items.iter().peekable();
+        for item in &state.raster_phase.items {
+            while let Some(hillshade) =
+                hillshades.next_if(|hillshade| hillshade.layer_index < item.layer_index)
+            {
+                DrawHillshades::render(state, hillshade, &mut tracked_pass);
+            }
+            DrawRasters::render(state, item, &mut tracked_pass);
+        }
+        for item in hillshades {
+            DrawHillshades::render(state, item, &mut tracked_pass);
+        }
+
+        // Custom layers are drawn below the first tile layer which is above them
+        let mut custom_layers = state.custom_layers.iter().peekable();
         for item in &state.tile_phase.items {
+            while let Some((layer_index, layer)) =
+                custom_layers.next_if(|(layer_index, _)| *layer_index <= item.0.style_layer.index)
+            {
+                state
+                    .custom_layers
+                    .render(layer, layer_index, tracked_pass.pass_mut());
+            }
             DrawTiles::render(state, item, &mut tracked_pass);
         }
+        for (layer_index, layer) in custom_layers {
+            state
+                .custom_layers
+                .render(layer, layer_index, tracked_pass.pass_mut());
+        }
+
+        drop(tracked_pass);
+
+        // Extrusions are depth tested by their distance to the camera. Therefore, they are drawn
+        // in a second pass which starts with a cleared depth buffer.
+        let render_pass =
+            render_context
+                .command_encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[color_attachment(wgpu::LoadOp::Load)],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &depth_texture.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        }),
+                    }),
+                });
+
+        let mut tracked_pass = TrackedRenderPass::new(render_pass);
+
+        for item in &state.extrusion_phase.items {
+            DrawExtrusions::render(state, item, &mut tracked_pass);
+        }
+
+        for item in &state.symbol_phase.items {
+            DrawSymbols::render(state, item, &mut tracked_pass);
+        }
+
+        for item in &state.debug_phase.items {
+            DrawTileBoundaries::render(state, item, &mut tracked_pass);
+        }
+
+        for item in &state.marker_phase.items {
+            DrawMarkers::render(state, item, &mut tracked_pass);
+        }
         Ok(())
     }
 }
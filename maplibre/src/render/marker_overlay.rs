@@ -0,0 +1,405 @@
+//! Overlay which draws the [`Markers`] on top of the map. The markers are projected on the CPU
+//! every frame, so they are drawn independently of the tiles in view.
+//!
+//! All markers are drawn with a single draw call. With instancing, only a [`MarkerInstance`] is
+//! uploaded per marker and the vertex shader expands it into a quad. Otherwise the six vertices of
+//! the quad are uploaded, which is more than three times the data for dense sets of markers.
+
+use crate::context::ViewState;
+use crate::io::sprite::SpriteSheet;
+use crate::markers::{Marker, Markers};
+use crate::render::settings::ColorSpace;
+use crate::render::shaders::{MarkerInstance, MarkerVertex, Vec2f32, Vec4f32};
+use crate::text::GlyphQuad;
+use std::mem::size_of;
+use std::ops::Range;
+
+/// Maximum amount of markers which can be drawn in a single frame.
+const MAX_MARKERS: usize = 4096;
+
+/// Number of vertices of the two triangles of a marker.
+const VERTICES_PER_MARKER: u32 = 6;
+
+/// All markers in view, which are drawn at once. Without instancing, the `vertices` of all
+/// markers are drawn once. With instancing, the vertices of a single quad are drawn for each of the
+/// `instances`.
+#[derive(Clone)]
+pub struct MarkersInView {
+    pub vertices: Range<u32>,
+    pub instances: Range<u32>,
+}
+
+/// The data of the markers in view, which is built on the CPU every frame and then uploaded.
+pub struct MarkerBatch {
+    instanced: bool,
+    vertices: Vec<MarkerVertex>,
+    instances: Vec<MarkerInstance>,
+}
+
+impl MarkerBatch {
+    /// Creates an empty batch which builds [`MarkerInstance`]s if `instanced` is set, or
+    /// [`MarkerVertex`]s otherwise.
+    pub fn new(instanced: bool) -> Self {
+        Self {
+            instanced,
+            vertices: Vec::new(),
+            instances: Vec::new(),
+        }
+    }
+
+    /// Number of bytes which are uploaded per marker.
+    pub fn marker_size(&self) -> usize {
+        if self.instanced {
+            size_of::<MarkerInstance>()
+        } else {
+            size_of::<MarkerVertex>() * VERTICES_PER_MARKER as usize
+        }
+    }
+
+    /// Number of markers in the batch.
+    pub fn len(&self) -> usize {
+        if self.instanced {
+            self.instances.len()
+        } else {
+            self.vertices.len() / VERTICES_PER_MARKER as usize
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The data which is uploaded to the vertex buffer.
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.instanced {
+            bytemuck::cast_slice(&self.instances)
+        } else {
+            bytemuck::cast_slice(&self.vertices)
+        }
+    }
+
+    /// Projects the `markers` into the window of the camera. At most 4096 markers are kept.
+    /// Images are looked up in `sprite_sheet`, which needs to be the sheet of the uploaded sprite
+    /// atlas.
+    pub fn build(
+        &mut self,
+        markers: &Markers,
+        view_state: &ViewState,
+        sprite_sheet: Option<&SpriteSheet>,
+        color_space: ColorSpace,
+    ) {
+        self.vertices.clear();
+        self.instances.clear();
+
+        let view_proj = view_state.view_projection();
+        let window_size = [view_state.camera.width, view_state.camera.height];
+        let pixel_ratio = view_state.device_pixel_ratio() as f32;
+
+        for (_, marker) in markers.iter() {
+            if self.len() >= MAX_MARKERS {
+                tracing::warn!("Too many markers in view");
+                break;
+            }
+
+            let window_position = match view_state.project_with(marker.position, &view_proj) {
+                Some(window_position) => [window_position.x, window_position.y],
+                None => continue,
+            };
+            let (quad, is_icon) = layout_marker(marker, sprite_sheet);
+            let color = color_space.color(marker.color);
+
+            if self.instanced {
+                self.instances.push(marker_instance(
+                    window_position,
+                    window_size,
+                    &quad,
+                    pixel_ratio,
+                    color,
+                    is_icon,
+                ));
+            } else {
+                self.vertices.extend(marker_vertices(
+                    window_position,
+                    window_size,
+                    &quad,
+                    pixel_ratio,
+                    color,
+                    is_icon,
+                ));
+            }
+        }
+    }
+
+    /// Returns what is drawn for the batch, or `None` if it is empty.
+    fn in_view(&self) -> Option<MarkersInView> {
+        let count = self.len() as u32;
+        if count == 0 {
+            None
+        } else if self.instanced {
+            Some(MarkersInView {
+                vertices: 0..VERTICES_PER_MARKER,
+                instances: 0..count,
+            })
+        } else {
+            Some(MarkersInView {
+                vertices: 0..count * VERTICES_PER_MARKER,
+                instances: 0..1,
+            })
+        }
+    }
+}
+
+pub struct MarkerOverlay {
+    in_view: Option<MarkersInView>,
+    batch: MarkerBatch,
+
+    buffer: wgpu::Buffer,
+}
+
+impl MarkerOverlay {
+    /// Creates the overlay and its buffer, see [`MarkerBatch::new`]. The pipeline needs to draw
+    /// instances if `instanced` is set.
+    pub fn from_device(device: &wgpu::Device, instanced: bool) -> Self {
+        let batch = MarkerBatch::new(instanced);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("marker overlay buffer"),
+            size: (batch.marker_size() * MAX_MARKERS) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            in_view: None,
+            batch,
+            buffer,
+        }
+    }
+
+    pub fn in_view(&self) -> Option<&MarkersInView> {
+        self.in_view.as_ref()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Projects the `markers` into the window of the camera. Images are looked up in
+    /// `sprite_sheet`, which needs to be the sheet of the uploaded sprite atlas.
+    pub fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        markers: &Markers,
+        view_state: &ViewState,
+        sprite_sheet: Option<&SpriteSheet>,
+        color_space: ColorSpace,
+    ) {
+        self.batch
+            .build(markers, view_state, sprite_sheet, color_space);
+
+        self.in_view = self.batch.in_view();
+        if self.in_view.is_some() {
+            queue.write_buffer(&self.buffer, 0, self.batch.as_bytes());
+        }
+    }
+}
+
+/// Lays out a marker relative to its position in logical pixels. Returns whether the marker is
+/// drawn with its image, or as a square because the image is not available.
+fn layout_marker(marker: &Marker, sprite_sheet: Option<&SpriteSheet>) -> (GlyphQuad, bool) {
+    let icon = sprite_sheet
+        .and_then(|sheet| sheet.layout_icon(marker.image.as_deref()?, marker.size, marker.anchor));
+    if let Some(icon) = icon {
+        return (icon, true);
+    }
+
+    let (horizontal, vertical) = marker.anchor.alignment();
+    let left = -marker.size * horizontal;
+    let top = -marker.size * vertical;
+    let quad = GlyphQuad {
+        top_left: [left, top],
+        bottom_right: [left + marker.size, top + marker.size],
+        tex_top_left: [0.0, 0.0],
+        tex_bottom_right: [0.0, 0.0],
+    };
+    (quad, false)
+}
+
+/// Returns the two triangles of a marker at `window_position` in normalized device coordinates.
+fn marker_vertices(
+    window_position: [f64; 2],
+    window_size: [f64; 2],
+    quad: &GlyphQuad,
+    pixel_ratio: f32,
+    color: Vec4f32,
+    is_icon: bool,
+) -> [MarkerVertex; 6] {
+    let to_ndc = |[x, y]: Vec2f32| -> Vec2f32 {
+        let x = window_position[0] + (x * pixel_ratio) as f64;
+        let y = window_position[1] + (y * pixel_ratio) as f64;
+        [
+            (x / window_size[0] * 2.0 - 1.0) as f32,
+            (1.0 - y / window_size[1] * 2.0) as f32,
+        ]
+    };
+
+    let [left, top] = to_ndc(quad.top_left);
+    let [right, bottom] = to_ndc(quad.bottom_right);
+    let [tex_left, tex_top] = quad.tex_top_left;
+    let [tex_right, tex_bottom] = quad.tex_bottom_right;
+
+    let vertex = |position, tex_coords| MarkerVertex::new(position, tex_coords, color, is_icon);
+    let top_left = vertex([left, top], [tex_left, tex_top]);
+    let top_right = vertex([right, top], [tex_right, tex_top]);
+    let bottom_left = vertex([left, bottom], [tex_left, tex_bottom]);
+    let bottom_right = vertex([right, bottom], [tex_right, tex_bottom]);
+
+    [
+        top_left,
+        top_right,
+        bottom_left,
+        top_right,
+        bottom_right,
+        bottom_left,
+    ]
+}
+
+/// Returns the instance of a marker at `window_position`, which the vertex shader expands into the
+/// same triangles as [`marker_vertices`].
+fn marker_instance(
+    window_position: [f64; 2],
+    window_size: [f64; 2],
+    quad: &GlyphQuad,
+    pixel_ratio: f32,
+    color: Vec4f32,
+    is_icon: bool,
+) -> MarkerInstance {
+    let to_ndc_offset = |[x, y]: Vec2f32| -> Vec2f32 {
+        [
+            ((x * pixel_ratio) as f64 / window_size[0] * 2.0) as f32,
+            (-(y * pixel_ratio) as f64 / window_size[1] * 2.0) as f32,
+        ]
+    };
+
+    MarkerInstance {
+        anchor: [
+            (window_position[0] / window_size[0] * 2.0 - 1.0) as f32,
+            (1.0 - window_position[1] / window_size[1] * 2.0) as f32,
+        ],
+        top_left: to_ndc_offset(quad.top_left),
+        bottom_right: to_ndc_offset(quad.bottom_right),
+        tex_top_left: quad.tex_top_left,
+        tex_bottom_right: quad.tex_bottom_right,
+        color,
+        is_icon: if is_icon { 1.0 } else { 0.0 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{layout_marker, marker_instance, marker_vertices, MarkerBatch};
+    use crate::context::ViewState;
+    use crate::coords::LatLon;
+    use crate::markers::{Marker, Markers};
+    use crate::render::settings::ColorSpace;
+    use crate::style::layer::IconAnchor;
+    use crate::WindowSize;
+
+    #[test]
+    fn test_marker_keeps_its_size_in_pixels() {
+        let mut marker = Marker::with_color(LatLon::new(0.0, 0.0), [1.0, 0.0, 0.0, 1.0]);
+        marker.size = 20.0;
+        marker.anchor = IconAnchor::Bottom;
+
+        let (quad, is_icon) = layout_marker(&marker, None);
+        assert!(!is_icon);
+        assert_eq!(quad.top_left, [-10.0, -20.0]);
+        assert_eq!(quad.bottom_right, [10.0, 0.0]);
+
+        // The marker covers 40 physical pixels in both directions on a HiDPI display
+        let vertices = marker_vertices(
+            [400.0, 300.0],
+            [800.0, 600.0],
+            &quad,
+            2.0,
+            marker.color,
+            false,
+        );
+        let [left, top] = vertices[0].position;
+        let [right, bottom] = vertices[4].position;
+        assert!((right - left - 40.0 / 400.0).abs() < 1e-6);
+        assert!((top - bottom - 40.0 / 300.0).abs() < 1e-6);
+        assert!(bottom.abs() < 1e-6);
+
+        // Markers whose image is missing are drawn as squares
+        marker.image = Some("missing".to_string());
+        assert!(!layout_marker(&marker, None).1);
+    }
+
+    #[test]
+    fn test_instance_covers_the_same_quad() {
+        let mut marker = Marker::with_color(LatLon::new(0.0, 0.0), [0.0, 1.0, 0.0, 1.0]);
+        marker.size = 16.0;
+        let (quad, _) = layout_marker(&marker, None);
+
+        let vertices = marker_vertices(
+            [100.0, 50.0],
+            [800.0, 600.0],
+            &quad,
+            1.5,
+            marker.color,
+            false,
+        );
+        let instance = marker_instance(
+            [100.0, 50.0],
+            [800.0, 600.0],
+            &quad,
+            1.5,
+            marker.color,
+            false,
+        );
+
+        // The corners which the vertex shader computes for the top-left and bottom-right vertex
+        let corner = |offset: [f32; 2]| {
+            [
+                instance.anchor[0] + offset[0],
+                instance.anchor[1] + offset[1],
+            ]
+        };
+        for (expected, actual) in [
+            (vertices[0].position, corner(instance.top_left)),
+            (vertices[4].position, corner(instance.bottom_right)),
+        ] {
+            assert!((expected[0] - actual[0]).abs() < 1e-6);
+            assert!((expected[1] - actual[1]).abs() < 1e-6);
+        }
+        assert_eq!(instance.color, vertices[0].color);
+        assert_eq!(instance.is_icon, 0.0);
+    }
+
+    #[test]
+    fn test_batch_draws_all_markers_at_once() {
+        let view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+        let mut markers = Markers::default();
+        for i in 0..10 {
+            markers.add(Marker::with_color(
+                LatLon::new(i as f64, i as f64),
+                [1.0, 0.0, 0.0, 1.0],
+            ));
+        }
+
+        let mut vertices = MarkerBatch::new(false);
+        vertices.build(&markers, &view_state, None, ColorSpace::Srgb);
+        let in_view = vertices.in_view().unwrap();
+        assert_eq!((in_view.vertices, in_view.instances), (0..60, 0..1));
+
+        let mut instances = MarkerBatch::new(true);
+        instances.build(&markers, &view_state, None, ColorSpace::Srgb);
+        let in_view = instances.in_view().unwrap();
+        assert_eq!((in_view.vertices, in_view.instances), (0..6, 0..10));
+
+        assert_eq!(instances.len(), vertices.len());
+        assert!(instances.as_bytes().len() * 3 < vertices.as_bytes().len());
+
+        assert!(MarkerBatch::new(true).in_view().is_none());
+    }
+}
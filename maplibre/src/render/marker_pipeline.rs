@@ -0,0 +1,72 @@
+//! Utility for declaring the pipeline which draws the [`crate::markers::Markers`].
+
+use crate::render::resource::SpriteAtlas;
+use crate::render::resource::{FragmentState, VertexState};
+use crate::render::resource::{RenderPipeline, RenderPipelineDescriptor};
+use crate::render::settings::Msaa;
+
+pub struct MarkerPipeline {
+    msaa: Msaa,
+
+    vertex_state: VertexState,
+    fragment_state: FragmentState,
+}
+
+impl MarkerPipeline {
+    pub(crate) fn new(
+        msaa: Msaa,
+        vertex_state: VertexState,
+        fragment_state: FragmentState,
+    ) -> Self {
+        MarkerPipeline {
+            msaa,
+            vertex_state,
+            fragment_state,
+        }
+    }
+}
+
+impl RenderPipeline for MarkerPipeline {
+    fn describe_render_pipeline(self) -> RenderPipelineDescriptor {
+        // Markers are neither clipped by the tile masks nor hidden by extrusions
+        let stencil_state = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Keep,
+        };
+
+        RenderPipelineDescriptor {
+            label: Some("marker pipeline".into()),
+            layout: Some(vec![SpriteAtlas::bind_group_layout_entries()]),
+            vertex: self.vertex_state,
+            fragment: self.fragment_state,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                front_face: wgpu::FrontFace::Ccw,
+                strip_index_format: None,
+                cull_mode: None,
+                conservative: false,
+                unclipped_depth: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth24PlusStencil8,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: stencil_state,
+                    back: stencil_state,
+                    read_mask: 0xff,
+                    write_mask: 0x00,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.msaa.samples,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        }
+    }
+}
@@ -18,43 +18,79 @@
 //! We appreciate the design and implementation work which as gone into it.
 //!
 
+use crate::coords::WorldTileCoords;
+use crate::custom_layer::CustomLayers;
+use crate::error::{Error, RenderError};
+use crate::metrics::BufferPoolOccupancy;
+use crate::render::capabilities::RendererCapabilities;
+use crate::render::culling::GpuCulling;
+use crate::render::frame_capture::{CaptureRequest, FrameCapture};
+use crate::render::hillshade_tiles::{HillshadeInView, HillshadeTiles};
+use crate::render::marker_overlay::{MarkerOverlay, MarkersInView};
+use crate::render::raster_tiles::{RasterInView, RasterTiles};
 use crate::render::render_phase::RenderPhase;
-use crate::render::resource::{BufferPool, Globals, IndexEntry};
+use crate::render::resource::{BufferPool, Globals, GlyphAtlas, IndexEntry, SpriteAtlas};
 use crate::render::resource::{Head, Surface};
 use crate::render::resource::{Texture, TextureView};
-use crate::render::settings::{RendererSettings, SurfaceType, WgpuSettings};
-use crate::render::shaders::{ShaderFeatureStyle, ShaderLayerMetadata};
+use crate::render::settings::{RendererSettings, SurfaceType, WgpuSettings, Wireframe};
+use crate::render::shaders::{
+    ShaderFeatureStyle, ShaderLayerMetadata, ShaderSymbolStyle, SymbolVertex,
+};
+use crate::render::tile_boundaries::{TileBoundaries, TileBoundary};
 use crate::render::tile_view_pattern::{TileInView, TileShape, TileViewPattern};
 use crate::render::util::Eventually;
 use crate::tessellation::IndexDataType;
-use crate::MapWindow;
-use log::info;
+use crate::text::placement::SymbolLayerLabels;
+use crate::{MapWindow, WindowSize};
+use log::{info, warn};
+use raw_window_handle::HasRawWindowHandle;
+use std::collections::{HashMap, HashSet};
 
 // Rendering internals
+pub(crate) mod culling;
+mod culling_node;
+mod debug_pipeline;
+mod extrusion_pipeline;
 mod graph;
 mod graph_runner;
+mod hillshade_pipeline;
+mod hillshade_tiles;
 mod main_pass;
+pub(crate) mod marker_overlay;
+mod marker_pipeline;
+mod raster_pipeline;
+mod raster_tiles;
 mod render_commands;
 mod render_phase;
-mod resource;
-mod shaders;
+pub(crate) mod resource;
+pub(crate) mod shaders;
 mod stages;
+pub(crate) mod surface_recovery;
+mod symbol_pipeline;
+mod tile_boundaries;
 mod tile_pipeline;
 mod tile_view_pattern;
 mod util;
 
 // Public API
 pub mod camera;
+pub mod camera_animation;
+pub mod capabilities;
+pub mod frame_capture;
 pub mod settings;
 
-pub use shaders::ShaderVertex;
-pub use stages::register_render_stages;
+pub use shaders::{ExtrusionVertex, ShaderVertex};
+pub use stages::{register_render_stages, RenderStageLabel};
 
 pub const INDEX_FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32; // Must match IndexDataType
 
 #[derive(Default)]
 pub struct RenderState {
     render_target: Eventually<TextureView>,
+    /// Why the frame of the surface could not be acquired in the current frame.
+    surface_error: Option<wgpu::SurfaceError>,
+    /// Captures which are fulfilled once the next frame is rendered.
+    capture_requests: Vec<CaptureRequest>,
 
     buffer_pool: Eventually<
         BufferPool<
@@ -66,18 +102,146 @@ pub struct RenderState {
             ShaderFeatureStyle,
         >,
     >,
+    symbol_buffer_pool: Eventually<
+        BufferPool<
+            wgpu::Queue,
+            wgpu::Buffer,
+            SymbolVertex,
+            IndexDataType,
+            ShaderLayerMetadata,
+            ShaderSymbolStyle,
+        >,
+    >,
+    extrusion_buffer_pool: Eventually<
+        BufferPool<
+            wgpu::Queue,
+            wgpu::Buffer,
+            ExtrusionVertex,
+            IndexDataType,
+            ShaderLayerMetadata,
+            ShaderFeatureStyle,
+        >,
+    >,
     tile_view_pattern: Eventually<TileViewPattern<wgpu::Queue, wgpu::Buffer>>,
+    raster_tiles: Eventually<RasterTiles>,
+    /// Only initialized if the device supports float textures.
+    hillshade_tiles: Eventually<HillshadeTiles>,
+    /// Only initialized once the debug overlay is shown.
+    tile_boundaries: Eventually<TileBoundaries>,
+    /// Only initialized once markers are added.
+    marker_overlay: Eventually<MarkerOverlay>,
+    /// Culls the draws of tile layers on the GPU. `None` if
+    /// [`RendererSettings::gpu_culling`] is disabled or not supported, see [`culling`].
+    gpu_culling: Eventually<Option<GpuCulling>>,
+    /// Layers which are drawn by the application, see [`crate::custom_layer`].
+    pub(crate) custom_layers: CustomLayers,
 
     tile_pipeline: Eventually<wgpu::RenderPipeline>,
     mask_pipeline: Eventually<wgpu::RenderPipeline>,
+    /// Draws the tiles like [`RenderState::tile_pipeline`] but only the edges of the triangles.
+    /// `None` if the device does not support [`wgpu::Features::POLYGON_MODE_LINE`].
+    wireframe_pipeline: Eventually<Option<wgpu::RenderPipeline>>,
+    symbol_pipeline: Eventually<wgpu::RenderPipeline>,
+    raster_pipeline: Eventually<wgpu::RenderPipeline>,
+    hillshade_pipeline: Eventually<wgpu::RenderPipeline>,
+    extrusion_pipeline: Eventually<wgpu::RenderPipeline>,
+    debug_pipeline: Eventually<wgpu::RenderPipeline>,
+    marker_pipeline: Eventually<wgpu::RenderPipeline>,
 
     globals_bind_group: Eventually<Globals>,
+    /// Glyphs of labels. The atlas is empty if no font is configured.
+    glyph_atlas: Eventually<GlyphAtlas>,
+    /// Icons of the sprite sheet of the style.
+    sprite_atlas: Eventually<SpriteAtlas>,
+    /// Labels of the symbols which have been uploaded, keyed by tile and style layer id.
+    symbol_labels: HashMap<(WorldTileCoords, String), SymbolLayerLabels>,
+
+    /// Color with which each frame is cleared before the layers are drawn.
+    clear_color: wgpu::Color,
+    /// Layers which are drawn with the [`RenderState::wireframe_pipeline`].
+    wireframe: Wireframe,
 
     depth_texture: Eventually<Texture>,
     multisampling_texture: Eventually<Option<Texture>>,
 
     mask_phase: RenderPhase<TileInView>,
+    raster_phase: RenderPhase<RasterInView>,
+    hillshade_phase: RenderPhase<HillshadeInView>,
     tile_phase: RenderPhase<(IndexEntry, TileShape)>,
+    symbol_phase: RenderPhase<(IndexEntry, TileShape)>,
+    extrusion_phase: RenderPhase<(IndexEntry, TileShape)>,
+    debug_phase: RenderPhase<TileBoundary>,
+    marker_phase: RenderPhase<MarkersInView>,
+}
+
+impl RenderState {
+    /// Returns why the frame of the surface could not be acquired, if it failed since this was
+    /// called the last time.
+    pub fn take_surface_error(&mut self) -> Option<wgpu::SurfaceError> {
+        self.surface_error.take()
+    }
+
+    /// Resolves `capture` with the next frame which is rendered.
+    pub fn capture_next_frame(&mut self, capture: FrameCapture) {
+        self.capture_requests.push(CaptureRequest::new(capture));
+    }
+
+    /// Releases the geometry and labels of all layers such that they are uploaded again with the
+    /// current style.
+    pub fn clear_layers(&mut self) {
+        if let Eventually::Initialized(buffer_pool) = &mut self.buffer_pool {
+            buffer_pool.clear();
+        }
+        if let Eventually::Initialized(symbol_buffer_pool) = &mut self.symbol_buffer_pool {
+            symbol_buffer_pool.clear();
+        }
+        if let Eventually::Initialized(extrusion_buffer_pool) = &mut self.extrusion_buffer_pool {
+            extrusion_buffer_pool.clear();
+        }
+        self.symbol_labels.clear();
+    }
+
+    /// Releases the geometry and labels of all layers of the tiles for which `is_removed` returns
+    /// true, such that they are uploaded again from the tile cache.
+    pub fn remove_tiles(&mut self, is_removed: impl Fn(&WorldTileCoords) -> bool) {
+        if let Eventually::Initialized(buffer_pool) = &mut self.buffer_pool {
+            buffer_pool.remove_tiles(&is_removed);
+        }
+        if let Eventually::Initialized(symbol_buffer_pool) = &mut self.symbol_buffer_pool {
+            symbol_buffer_pool.remove_tiles(&is_removed);
+        }
+        if let Eventually::Initialized(extrusion_buffer_pool) = &mut self.extrusion_buffer_pool {
+            extrusion_buffer_pool.remove_tiles(&is_removed);
+        }
+        self.symbol_labels
+            .retain(|(coords, _), _| !is_removed(coords));
+    }
+
+    /// Drops the textures of a raster or raster DEM source.
+    pub fn remove_raster_source(&mut self, source: &str) {
+        if let Eventually::Initialized(raster_tiles) = &mut self.raster_tiles {
+            raster_tiles.remove_source(source);
+        }
+        if let Eventually::Initialized(hillshade_tiles) = &mut self.hillshade_tiles {
+            hillshade_tiles.remove_source(source);
+        }
+    }
+
+    /// Returns the ids of the style layers which are uploaded to the GPU for the tile at `coords`.
+    pub fn loaded_layers_at(&self, coords: &WorldTileCoords) -> Option<HashSet<&str>> {
+        match &self.buffer_pool {
+            Eventually::Initialized(buffer_pool) => buffer_pool.get_loaded_layers_at(coords),
+            Eventually::Uninitialized => None,
+        }
+    }
+
+    /// Returns how many tiles and bytes of geometry are uploaded to the GPU.
+    pub fn buffer_pool_occupancy(&self) -> Option<BufferPoolOccupancy> {
+        match &self.buffer_pool {
+            Eventually::Initialized(buffer_pool) => Some(buffer_pool.occupancy()),
+            Eventually::Uninitialized => None,
+        }
+    }
 }
 
 pub struct Renderer {
@@ -85,6 +249,7 @@ pub struct Renderer {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub adapter_info: wgpu::AdapterInfo,
+    pub capabilities: RendererCapabilities,
 
     pub wgpu_settings: WgpuSettings,
     pub settings: RendererSettings,
@@ -94,80 +259,209 @@ pub struct Renderer {
 }
 
 impl Renderer {
-    /// Initializes the renderer by retrieving and preparing the GPU instance, device and queue
-    /// for the specified backend.
+    /// Initializes the renderer by retrieving and preparing the GPU instance, device and queue.
+    /// The backends of [`WgpuSettings::backend_candidates`] are tried in order until one of them
+    /// provides an adapter.
     pub async fn initialize<MW>(
         window: &MW,
         wgpu_settings: WgpuSettings,
         settings: RendererSettings,
-    ) -> Result<Self, wgpu::RequestDeviceError>
+    ) -> Result<Self, Error>
     where
         MW: MapWindow,
     {
-        let instance = wgpu::Instance::new(wgpu_settings.backends.unwrap_or(wgpu::Backends::all()));
+        Self::initialize_with_window_handle(window.inner(), window.size(), wgpu_settings, settings)
+            .await
+    }
 
-        let maybe_surface = match &settings.surface_type {
-            SurfaceType::Headless => None,
-            SurfaceType::Headed => Some(Surface::from_window(&instance, window, &settings)),
-        };
+    /// Initializes the renderer for the native window behind `window_handle` of the given size,
+    /// see [`Renderer::initialize`].
+    pub async fn initialize_with_window_handle<H>(
+        window_handle: &H,
+        size: WindowSize,
+        wgpu_settings: WgpuSettings,
+        settings: RendererSettings,
+    ) -> Result<Self, Error>
+    where
+        H: HasRawWindowHandle,
+    {
+        let settings = settings.supported();
+
+        for backends in wgpu_settings.backend_candidates() {
+            let instance = wgpu::Instance::new(backends);
+
+            let maybe_surface = match &settings.surface_type {
+                SurfaceType::Headless => None,
+                SurfaceType::Headed => Some(Surface::from_window_handle(
+                    &instance,
+                    window_handle,
+                    size,
+                    &settings,
+                )),
+            };
 
-        let compatible_surface = if let Some(surface) = &maybe_surface {
-            match &surface.head() {
-                Head::Headed(window_head) => Some(window_head.surface()),
-                Head::Headless(_) => None,
+            let compatible_surface = if let Some(surface) = &maybe_surface {
+                match &surface.head() {
+                    Head::Headed(window_head) => Some(window_head.surface()),
+                    Head::Headless(_) => None,
+                }
+            } else {
+                None
+            };
+
+            let adapter = match Self::request_adapter(
+                &instance,
+                backends,
+                &wgpu::RequestAdapterOptions {
+                    power_preference: wgpu_settings.power_preference,
+                    force_fallback_adapter: false,
+                    compatible_surface,
+                },
+            )
+            .await
+            {
+                Some(adapter) => adapter,
+                None => continue,
+            };
+
+            let (device, queue, adapter_info, mut capabilities) =
+                Self::request_device(&adapter, &wgpu_settings).await?;
+            if let Some(Head::Headed(window_head)) = maybe_surface.as_ref().map(Surface::head) {
+                capabilities.surface_formats = window_head
+                    .surface()
+                    .get_preferred_format(&adapter)
+                    .into_iter()
+                    .collect();
             }
-        } else {
-            None
-        };
 
-        let (device, queue, adapter_info) = Self::request_device(
-            &instance,
-            &wgpu_settings,
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu_settings.power_preference,
-                force_fallback_adapter: false,
-                compatible_surface,
-            },
-        )
-        .await?;
-
-        let surface = maybe_surface.unwrap_or_else(|| match &settings.surface_type {
-            SurfaceType::Headless => Surface::from_image(&device, window, &settings),
-            SurfaceType::Headed => Surface::from_window(&instance, window, &settings),
-        });
-
-        match surface.head() {
-            Head::Headed(window) => window.configure(&device),
-            Head::Headless(_) => {}
+            let surface = maybe_surface.unwrap_or_else(|| match &settings.surface_type {
+                SurfaceType::Headless => Surface::from_size(&device, size, &settings),
+                SurfaceType::Headed => {
+                    Surface::from_window_handle(&instance, window_handle, size, &settings)
+                }
+            });
+
+            surface.configure(&device);
+
+            return Ok(Self {
+                instance,
+                device,
+                queue,
+                adapter_info,
+                capabilities,
+                wgpu_settings,
+                settings,
+                state: Default::default(),
+                surface,
+            });
         }
 
-        Ok(Self {
-            instance,
-            device,
-            queue,
-            adapter_info,
-            wgpu_settings,
-            settings,
-            state: Default::default(),
-            surface,
-        })
+        Err(Error::Render(RenderError::NoAdapter))
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.surface.resize(width, height)
+    /// Initializes a renderer which draws into a texture of the given size instead of a window.
+    pub async fn initialize_headless(
+        size: WindowSize,
+        wgpu_settings: WgpuSettings,
+        settings: RendererSettings,
+    ) -> Result<Self, Error> {
+        let settings = settings.supported();
+
+        for backends in wgpu_settings.backend_candidates() {
+            let instance = wgpu::Instance::new(backends);
+
+            let adapter = match Self::request_adapter(
+                &instance,
+                backends,
+                &wgpu::RequestAdapterOptions {
+                    power_preference: wgpu_settings.power_preference,
+                    force_fallback_adapter: false,
+                    compatible_surface: None,
+                },
+            )
+            .await
+            {
+                Some(adapter) => adapter,
+                None => continue,
+            };
+
+            let (device, queue, adapter_info, capabilities) =
+                Self::request_device(&adapter, &wgpu_settings).await?;
+
+            let surface = Surface::from_size(&device, size, &settings);
+
+            return Ok(Self {
+                instance,
+                device,
+                queue,
+                adapter_info,
+                capabilities,
+                wgpu_settings,
+                settings,
+                state: Default::default(),
+                surface,
+            });
+        }
+
+        Err(Error::Render(RenderError::NoAdapter))
     }
 
-    /// Requests a device
-    async fn request_device(
+    /// Resizes the surface. Textures which depend on the size of the surface, like the depth
+    /// texture, are recreated by the `ResourceStage`.
+    pub fn resize(&mut self, size: WindowSize) {
+        self.surface.resize(size);
+
+        // The texture and buffer of a headless surface have a fixed size
+        if let Head::Headless(_) = self.surface.head() {
+            self.surface = Surface::from_size(&self.device, self.surface.size(), &self.settings);
+        }
+    }
+
+    /// Recreates the surface of a window, e.g. after an Android app was resumed. The surface is
+    /// configured immediately with its previous configuration.
+    pub fn recreate_surface<MW>(&mut self, window: &MW)
+    where
+        MW: MapWindow,
+    {
+        self.recreate_surface_from_handle(window.inner());
+    }
+
+    /// Recreates the surface for the native window behind `window_handle`, see
+    /// [`Renderer::recreate_surface`].
+    pub fn recreate_surface_from_handle<H>(&mut self, window_handle: &H)
+    where
+        H: HasRawWindowHandle,
+    {
+        self.surface.recreate(window_handle, &self.instance);
+        self.surface.configure(&self.device);
+    }
+
+    /// Requests an adapter of one of the given `backends`.
+    async fn request_adapter(
         instance: &wgpu::Instance,
-        settings: &WgpuSettings,
+        backends: wgpu::Backends,
         request_adapter_options: &wgpu::RequestAdapterOptions<'_>,
-    ) -> Result<(wgpu::Device, wgpu::Queue, wgpu::AdapterInfo), wgpu::RequestDeviceError> {
-        let adapter = instance
-            .request_adapter(request_adapter_options)
-            .await
-            .expect("Unable to find a GPU! Make sure you have installed required drivers!");
+    ) -> Option<wgpu::Adapter> {
+        let adapter = instance.request_adapter(request_adapter_options).await;
+        if adapter.is_none() {
+            warn!("No adapter found for the backends {:?}", backends);
+        }
+        adapter
+    }
 
+    /// Requests a device
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+        settings: &WgpuSettings,
+    ) -> Result<
+        (
+            wgpu::Device,
+            wgpu::Queue,
+            wgpu::AdapterInfo,
+            RendererCapabilities,
+        ),
+        wgpu::RequestDeviceError,
+    > {
         let adapter_info = adapter.get_info();
         info!("{:?}", adapter_info);
 
@@ -184,11 +478,42 @@ impl Renderer {
         #[cfg(target_arch = "wasm32")]
         let trace_path = None;
 
+        let (features, limits) = Self::device_features_and_limits(adapter, settings);
+        let missing_features = settings.features - adapter.features();
+        if !missing_features.is_empty() {
+            warn!(
+                "The adapter does not support the features {:?}",
+                missing_features
+            );
+        }
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: settings.device_label.as_ref().map(|a| a.as_ref()),
+                    features,
+                    limits: limits.clone(),
+                },
+                trace_path,
+            )
+            .await?;
+        let capabilities = RendererCapabilities::new(adapter, features, limits, missing_features);
+        info!("{:?}", capabilities);
+        Ok((device, queue, adapter_info, capabilities))
+    }
+
+    /// Returns the features and limits with which a device is requested from `adapter`.
+    fn device_features_and_limits(
+        adapter: &wgpu::Adapter,
+        settings: &WgpuSettings,
+    ) -> (wgpu::Features, wgpu::Limits) {
+        let adapter_info = adapter.get_info();
+
         // Maybe get features and limits based on what is supported by the adapter/backend
         let mut features = wgpu::Features::empty();
         let mut limits = settings.limits.clone();
 
-        features = adapter.features() | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+        features = adapter.features();
         if adapter_info.device_type == wgpu::DeviceType::DiscreteGpu {
             // `MAPPABLE_PRIMARY_BUFFERS` can have a significant, negative performance impact for
             // discrete GPUs due to having to transfer data across the PCI-E bus and so it
@@ -202,8 +527,10 @@ impl Renderer {
         if let Some(disabled_features) = settings.disabled_features {
             features -= disabled_features;
         }
+        // Features which the adapter does not support would make the request fail, e.g. on
+        // WebGL2, so they are skipped
         // NOTE: |= is used here to ensure that any explicitly-enabled features are respected.
-        features |= settings.features;
+        features |= settings.features & adapter.features();
 
         // Enforce the limit constraints
         if let Some(constrained_limits) = settings.constrained_limits.as_ref() {
@@ -297,17 +624,50 @@ impl Renderer {
             };
         }
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: settings.device_label.as_ref().map(|a| a.as_ref()),
-                    features,
-                    limits,
+        (features, limits)
+    }
+
+    /// Returns the capabilities of the adapter which [`Renderer::initialize_headless`] would
+    /// pick, without requesting a device. Returns `None` if no backend provides an adapter.
+    pub async fn probe_capabilities(wgpu_settings: &WgpuSettings) -> Option<RendererCapabilities> {
+        for backends in wgpu_settings.backend_candidates() {
+            let instance = wgpu::Instance::new(backends);
+            let adapter = Self::request_adapter(
+                &instance,
+                backends,
+                &wgpu::RequestAdapterOptions {
+                    power_preference: wgpu_settings.power_preference,
+                    force_fallback_adapter: false,
+                    compatible_surface: None,
                 },
-                trace_path,
             )
-            .await?;
-        Ok((device, queue, adapter_info))
+            .await;
+
+            if let Some(adapter) = adapter {
+                let (features, limits) = Self::device_features_and_limits(&adapter, wgpu_settings);
+                let missing_features = wgpu_settings.features - adapter.features();
+                return Some(RendererCapabilities::new(
+                    &adapter,
+                    features,
+                    limits,
+                    missing_features,
+                ));
+            }
+        }
+        None
+    }
+
+    /// Reads the last frame of a headless renderer as tightly packed RGBA rows.
+    pub async fn read_frame(&self) -> Result<Vec<u8>, Error> {
+        match self.surface.head() {
+            Head::Headless(head) => head
+                .read_rgba(&self.device)
+                .await
+                .map_err(|e| Error::Render(RenderError::Readback(e.to_string()))),
+            Head::Headed(_) => Err(Error::Render(RenderError::Readback(
+                "the renderer draws into a window".to_string(),
+            ))),
+        }
     }
 
     pub fn instance(&self) -> &wgpu::Instance {
@@ -319,6 +679,10 @@ impl Renderer {
     pub fn queue(&self) -> &wgpu::Queue {
         &self.queue
     }
+    /// Returns what the backend which was picked during initialization supports.
+    pub fn capabilities(&self) -> &RendererCapabilities {
+        &self.capabilities
+    }
     pub fn state(&self) -> &RenderState {
         &self.state
     }
@@ -0,0 +1,327 @@
+//! Stores the textures of raster tiles and decides which textures are drawn onto the tiles in view.
+//! The store is shared with the elevations of hillshades, see [`crate::render::hillshade_tiles`].
+
+use crate::coords::WorldTileCoords;
+use crate::render::settings::ColorSpace;
+use crate::render::shaders::{ShaderRasterMetadata, Vec4f32};
+use crate::render::tile_view_pattern::TileShape;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ops::Range;
+
+/// Maximum amount of rasters which can be drawn in a single frame.
+const RASTER_VIEW_SIZE: wgpu::BufferAddress = 64;
+/// Amount of textures which are kept even though they are not in view.
+const RASTER_TEXTURE_CAPACITY: usize = 128;
+
+/// Identifies the raster tile of a source.
+pub type RasterKey = (WorldTileCoords, String);
+
+pub struct RasterTexture {
+    pub texture: wgpu::Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// A texture which is drawn onto a tile in view. The texture might belong to a parent tile. `M`
+/// is the per-tile uniform of the texture, see [`TextureTiles`].
+pub struct TextureInView<M> {
+    pub shape: TileShape,
+    pub layer_index: u32,
+    pub key: RasterKey,
+    pub buffer_range: Range<wgpu::BufferAddress>,
+    phantom_m: PhantomData<M>,
+}
+
+impl<M> Clone for TextureInView<M> {
+    fn clone(&self) -> Self {
+        Self {
+            shape: self.shape.clone(),
+            layer_index: self.layer_index,
+            key: self.key.clone(),
+            buffer_range: self.buffer_range.clone(),
+            phantom_m: PhantomData,
+        }
+    }
+}
+
+/// A raster which is drawn onto a tile in view.
+pub type RasterInView = TextureInView<ShaderRasterMetadata>;
+
+/// Stores the textures of tiles of raster sources and the uniforms `M` of the tiles in view, which
+/// describe how the textures are drawn onto these tiles.
+pub struct TextureTiles<M> {
+    textures: HashMap<RasterKey, RasterTexture>,
+    in_view: Vec<TextureInView<M>>,
+    metadata: Vec<M>,
+
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Bound next to the texture if the shader samples it.
+    sampler: Option<wgpu::Sampler>,
+    texture_format: wgpu::TextureFormat,
+    label: &'static str,
+    view_size: wgpu::BufferAddress,
+    texture_capacity: usize,
+}
+
+/// Stores the textures of raster tiles and decides which textures are drawn onto the tiles in view.
+pub type RasterTiles = TextureTiles<ShaderRasterMetadata>;
+
+impl RasterTiles {
+    /// The layout of the bind groups of the textures. Pipelines which draw rasters need to use the
+    /// same layout.
+    pub fn bind_group_layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+    }
+
+    pub fn from_device(device: &wgpu::Device, color_space: ColorSpace) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("raster sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        // Textures decode the sRGB pixels if colors are blended in linear space
+        Self::new(
+            device,
+            "raster",
+            &Self::bind_group_layout_entries(),
+            Some(sampler),
+            color_space.texture_format(wgpu::TextureFormat::Rgba8UnormSrgb),
+            RASTER_VIEW_SIZE,
+            RASTER_TEXTURE_CAPACITY,
+        )
+    }
+}
+
+impl<M: bytemuck::Pod> TextureTiles<M> {
+    /// Creates a store whose textures have the given `texture_format`. At most `view_size` tiles
+    /// can be drawn in a single frame and `texture_capacity` textures are kept even though they
+    /// are not in view.
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        bind_group_layout_entries: &[wgpu::BindGroupLayoutEntry],
+        sampler: Option<wgpu::Sampler>,
+        texture_format: wgpu::TextureFormat,
+        view_size: wgpu::BufferAddress,
+        texture_capacity: usize,
+    ) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} metadata buffer", label)),
+            size: size_of::<M>() as wgpu::BufferAddress * view_size,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&format!("{} bind group layout", label)),
+            entries: bind_group_layout_entries,
+        });
+
+        Self {
+            textures: Default::default(),
+            in_view: Vec::with_capacity(view_size as usize),
+            metadata: Vec::with_capacity(view_size as usize),
+            buffer,
+            bind_group_layout,
+            sampler,
+            texture_format,
+            label,
+            view_size,
+            texture_capacity,
+        }
+    }
+
+    pub fn has_texture(&self, key: &RasterKey) -> bool {
+        self.textures.contains_key(key)
+    }
+
+    pub fn texture(&self, key: &RasterKey) -> Option<&RasterTexture> {
+        self.textures.get(key)
+    }
+
+    /// Creates a texture from tightly packed rows of texels in the format of the textures, e.g.
+    /// RGBA pixels for raster tiles.
+    pub fn upload_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        key: RasterKey,
+        width: u32,
+        height: u32,
+        data: &[u8],
+    ) {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&format!("{} texture", self.label)),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.texture_format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let texel_size = self.texture_format.describe().block_size as u32;
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(texel_size * width),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }];
+        if let Some(sampler) = &self.sampler {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} bind group", self.label)),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        });
+
+        self.textures.insert(
+            key,
+            RasterTexture {
+                texture,
+                bind_group,
+            },
+        );
+    }
+
+    /// Drops the textures of a source, e.g. because the tile URLs of the source changed.
+    pub fn remove_source(&mut self, source: &str) {
+        self.textures.retain(|(_, id), _| id != source);
+    }
+
+    pub fn clear_view(&mut self) {
+        self.in_view.clear();
+        self.metadata.clear();
+    }
+
+    /// Draws the texture with the given `key` onto the tile of `shape` as described by
+    /// `metadata`. Returns false if no more textures can be drawn within this frame.
+    pub fn push_in_view(
+        &mut self,
+        shape: &TileShape,
+        layer_index: u32,
+        key: RasterKey,
+        metadata: M,
+    ) -> bool {
+        let index = self.in_view.len() as wgpu::BufferAddress;
+        if index >= self.view_size {
+            return false;
+        }
+
+        let stride = size_of::<M>() as wgpu::BufferAddress;
+
+        self.metadata.push(metadata);
+        self.in_view.push(TextureInView {
+            shape: shape.clone(),
+            layer_index,
+            key,
+            buffer_range: index * stride..(index + 1) * stride,
+            phantom_m: PhantomData,
+        });
+        true
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub fn upload_view(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.metadata));
+    }
+
+    /// Drops textures which are not in view if there are too many.
+    pub fn evict(&mut self) {
+        if self.textures.len() <= self.texture_capacity {
+            return;
+        }
+
+        let used: HashSet<&RasterKey> = self.in_view.iter().map(|tile| &tile.key).collect();
+        self.textures.retain(|key, _| used.contains(key));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TextureInView<M>> + '_ {
+        self.in_view.iter()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
+
+/// Calculates the part of the texture of `raster_coords` which covers the tile at `coords`.
+/// `raster_coords` must either equal `coords` or be a parent of it.
+pub(crate) fn tex_rect(coords: &WorldTileCoords, raster_coords: &WorldTileCoords) -> Vec4f32 {
+    let scale = (1u32 << (coords.z - raster_coords.z)) as f32;
+    let u = (coords.x as f32 - raster_coords.x as f32 * scale) / scale;
+    let v = (coords.y as f32 - raster_coords.y as f32 * scale) / scale;
+    [u, v, u + 1.0 / scale, v + 1.0 / scale]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tex_rect;
+    use crate::coords::WorldTileCoords;
+
+    #[test]
+    fn test_tex_rect() {
+        let coords = WorldTileCoords { x: 5, y: 2, z: 3 };
+
+        assert_eq!(tex_rect(&coords, &coords), [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(
+            tex_rect(&coords, &WorldTileCoords { x: 2, y: 1, z: 2 }),
+            [0.5, 0.0, 1.0, 0.5]
+        );
+        assert_eq!(
+            tex_rect(&coords, &WorldTileCoords { x: 1, y: 0, z: 1 }),
+            [0.25, 0.5, 0.5, 0.75]
+        );
+    }
+}
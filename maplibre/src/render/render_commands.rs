@@ -1,8 +1,12 @@
 //! Specifies the instructions which are going to be sent to the GPU. Render commands can be concatenated
 //! into a new render command which executes multiple instruction sets.
 
+use crate::render::hillshade_tiles::HillshadeInView;
+use crate::render::marker_overlay::MarkersInView;
+use crate::render::raster_tiles::{RasterInView, RasterTexture, TextureInView};
 use crate::render::render_phase::{PhaseItem, RenderCommand, RenderCommandResult};
-use crate::render::resource::{Globals, IndexEntry, TrackedRenderPass};
+use crate::render::resource::{Globals, GlyphAtlas, IndexEntry, SpriteAtlas, TrackedRenderPass};
+use crate::render::tile_boundaries::TileBoundary;
 use crate::render::tile_view_pattern::{TileInView, TileShape};
 use crate::render::util::Eventually::Initialized;
 use crate::render::INDEX_FORMAT;
@@ -14,6 +18,29 @@ impl PhaseItem for TileInView {
     fn sort_key(&self) -> Self::SortKey {}
 }
 
+impl PhaseItem for TileBoundary {
+    type SortKey = ();
+
+    fn sort_key(&self) -> Self::SortKey {}
+}
+
+impl PhaseItem for MarkersInView {
+    type SortKey = ();
+
+    fn sort_key(&self) -> Self::SortKey {}
+}
+
+impl<M> PhaseItem for TextureInView<M> {
+    type SortKey = u32;
+
+    fn sort_key(&self) -> Self::SortKey {
+        self.layer_index
+    }
+}
+
+/// Layers are drawn from bottom to top such that translucent layers blend with the layers below.
+/// Overlapping features within the same layer share the same depth, so the depth test only lets the
+/// first fragment pass and translucent features are not blended twice.
 impl PhaseItem for (IndexEntry, TileShape) {
     type SortKey = u32;
 
@@ -54,13 +81,21 @@ impl<P: PhaseItem> RenderCommand<P> for SetMaskPipeline {
     }
 }
 
+/// Sets the [`RenderState::wireframe_pipeline`] instead for layers which are drawn as wireframes.
 pub struct SetTilePipeline;
-impl<P: PhaseItem> RenderCommand<P> for SetTilePipeline {
+impl RenderCommand<(IndexEntry, TileShape)> for SetTilePipeline {
     fn render<'w>(
         state: &'w RenderState,
-        _item: &P,
+        (entry, _shape): &(IndexEntry, TileShape),
         pass: &mut TrackedRenderPass<'w>,
     ) -> RenderCommandResult {
+        if let Initialized(Some(pipeline)) = &state.wireframe_pipeline {
+            if state.wireframe.contains(&entry.style_layer.id) {
+                pass.set_render_pipeline(pipeline);
+                return RenderCommandResult::Success;
+            }
+        }
+
         if let Initialized(pipeline) = &state.tile_pipeline {
             pass.set_render_pipeline(pipeline);
             RenderCommandResult::Success
@@ -70,6 +105,170 @@ impl<P: PhaseItem> RenderCommand<P> for SetTilePipeline {
     }
 }
 
+pub struct SetSymbolPipeline;
+impl<P: PhaseItem> RenderCommand<P> for SetSymbolPipeline {
+    fn render<'w>(
+        state: &'w RenderState,
+        _item: &P,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(pipeline) = &state.symbol_pipeline {
+            pass.set_render_pipeline(pipeline);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct SetExtrusionPipeline;
+impl<P: PhaseItem> RenderCommand<P> for SetExtrusionPipeline {
+    fn render<'w>(
+        state: &'w RenderState,
+        _item: &P,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(pipeline) = &state.extrusion_pipeline {
+            pass.set_render_pipeline(pipeline);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct SetDebugPipeline;
+impl<P: PhaseItem> RenderCommand<P> for SetDebugPipeline {
+    fn render<'w>(
+        state: &'w RenderState,
+        _item: &P,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(pipeline) = &state.debug_pipeline {
+            pass.set_render_pipeline(pipeline);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct SetMarkerPipeline;
+impl<P: PhaseItem> RenderCommand<P> for SetMarkerPipeline {
+    fn render<'w>(
+        state: &'w RenderState,
+        _item: &P,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(pipeline) = &state.marker_pipeline {
+            pass.set_render_pipeline(pipeline);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct SetGlyphAtlasBindGroup<const I: usize>;
+impl<const I: usize, P: PhaseItem> RenderCommand<P> for SetGlyphAtlasBindGroup<I> {
+    fn render<'w>(
+        state: &'w RenderState,
+        _item: &P,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(GlyphAtlas { bind_group, .. }) = &state.glyph_atlas {
+            pass.set_bind_group(I, bind_group, &[]);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct SetSpriteAtlasBindGroup<const I: usize>;
+impl<const I: usize, P: PhaseItem> RenderCommand<P> for SetSpriteAtlasBindGroup<I> {
+    fn render<'w>(
+        state: &'w RenderState,
+        _item: &P,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(SpriteAtlas { bind_group, .. }) = &state.sprite_atlas {
+            pass.set_bind_group(I, bind_group, &[]);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct SetRasterPipeline;
+impl<P: PhaseItem> RenderCommand<P> for SetRasterPipeline {
+    fn render<'w>(
+        state: &'w RenderState,
+        _item: &P,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(pipeline) = &state.raster_pipeline {
+            pass.set_render_pipeline(pipeline);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct SetRasterBindGroup<const I: usize>;
+impl<const I: usize> RenderCommand<RasterInView> for SetRasterBindGroup<I> {
+    fn render<'w>(
+        state: &'w RenderState,
+        item: &RasterInView,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(raster_tiles) = &state.raster_tiles {
+            if let Some(RasterTexture { bind_group, .. }) = raster_tiles.texture(&item.key) {
+                pass.set_bind_group(I, bind_group, &[]);
+                return RenderCommandResult::Success;
+            }
+        }
+
+        RenderCommandResult::Failure
+    }
+}
+
+pub struct SetHillshadePipeline;
+impl<P: PhaseItem> RenderCommand<P> for SetHillshadePipeline {
+    fn render<'w>(
+        state: &'w RenderState,
+        _item: &P,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(pipeline) = &state.hillshade_pipeline {
+            pass.set_render_pipeline(pipeline);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct SetHillshadeBindGroup<const I: usize>;
+impl<const I: usize> RenderCommand<HillshadeInView> for SetHillshadeBindGroup<I> {
+    fn render<'w>(
+        state: &'w RenderState,
+        item: &HillshadeInView,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(hillshade_tiles) = &state.hillshade_tiles {
+            if let Some(RasterTexture { bind_group, .. }) = hillshade_tiles.texture(&item.key) {
+                pass.set_bind_group(I, bind_group, &[]);
+                return RenderCommandResult::Success;
+            }
+        }
+
+        RenderCommandResult::Failure
+    }
+}
+
 pub struct DrawMask;
 impl RenderCommand<TileInView> for DrawMask {
     fn render<'w>(
@@ -83,7 +282,7 @@ impl RenderCommand<TileInView> for DrawMask {
             let shape_to_render = fallback.as_ref().unwrap_or(shape);
 
             let reference =
-                tile_view_pattern.stencil_reference_value(&shape_to_render.coords) as u32;
+                tile_view_pattern.stencil_reference_value(&shape_to_render.unwrapped_coords) as u32;
 
             pass.set_stencil_reference(reference);
             pass.set_vertex_buffer(
@@ -108,7 +307,8 @@ impl RenderCommand<(IndexEntry, TileShape)> for DrawTile {
         if let (Initialized(buffer_pool), Initialized(tile_view_pattern)) =
             (&state.buffer_pool, &state.tile_view_pattern)
         {
-            let reference = tile_view_pattern.stencil_reference_value(&shape.coords) as u32;
+            let reference =
+                tile_view_pattern.stencil_reference_value(&shape.unwrapped_coords) as u32;
 
             tracing::trace!(
                 "Drawing layer {:?} at {}",
@@ -141,6 +341,122 @@ impl RenderCommand<(IndexEntry, TileShape)> for DrawTile {
                     .feature_metadata()
                     .slice(entry.feature_metadata_buffer_range()),
             );
+            match &state.gpu_culling {
+                Initialized(Some(gpu_culling)) => match gpu_culling.draw_offset(entry, shape) {
+                    Some(offset) => pass.draw_indexed_indirect(gpu_culling.draws(), offset),
+                    None => pass.draw_indexed(entry.indices_range(), 0, 0..1),
+                },
+                _ => pass.draw_indexed(entry.indices_range(), 0, 0..1),
+            }
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct DrawRaster;
+impl RenderCommand<RasterInView> for DrawRaster {
+    fn render<'w>(
+        state: &'w RenderState,
+        RasterInView {
+            shape,
+            buffer_range,
+            ..
+        }: &RasterInView,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let (Initialized(raster_tiles), Initialized(tile_view_pattern)) =
+            (&state.raster_tiles, &state.tile_view_pattern)
+        {
+            tracing::trace!("Drawing raster at {}", &shape.coords);
+
+            pass.set_vertex_buffer(
+                0,
+                tile_view_pattern.buffer().slice(shape.buffer_range.clone()),
+            );
+            pass.set_vertex_buffer(1, raster_tiles.buffer().slice(buffer_range.clone()));
+            pass.draw(0..6, 0..1);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct DrawHillshade;
+impl RenderCommand<HillshadeInView> for DrawHillshade {
+    fn render<'w>(
+        state: &'w RenderState,
+        HillshadeInView {
+            shape,
+            buffer_range,
+            ..
+        }: &HillshadeInView,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let (Initialized(hillshade_tiles), Initialized(tile_view_pattern)) =
+            (&state.hillshade_tiles, &state.tile_view_pattern)
+        {
+            tracing::trace!("Drawing hillshade at {}", &shape.coords);
+
+            pass.set_vertex_buffer(
+                0,
+                tile_view_pattern.buffer().slice(shape.buffer_range.clone()),
+            );
+            pass.set_vertex_buffer(1, hillshade_tiles.buffer().slice(buffer_range.clone()));
+            pass.draw(0..6, 0..1);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct DrawSymbol;
+impl RenderCommand<(IndexEntry, TileShape)> for DrawSymbol {
+    fn render<'w>(
+        state: &'w RenderState,
+        (entry, shape): &(IndexEntry, TileShape),
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let (Initialized(symbol_buffer_pool), Initialized(tile_view_pattern)) =
+            (&state.symbol_buffer_pool, &state.tile_view_pattern)
+        {
+            tracing::trace!(
+                "Drawing symbols {:?} at {}",
+                entry.style_layer.source_layer,
+                &entry.coords
+            );
+
+            pass.set_index_buffer(
+                symbol_buffer_pool
+                    .indices()
+                    .slice(entry.indices_buffer_range()),
+                INDEX_FORMAT,
+            );
+            pass.set_vertex_buffer(
+                0,
+                symbol_buffer_pool
+                    .vertices()
+                    .slice(entry.vertices_buffer_range()),
+            );
+            pass.set_vertex_buffer(
+                1,
+                tile_view_pattern.buffer().slice(shape.buffer_range.clone()),
+            );
+            pass.set_vertex_buffer(
+                2,
+                symbol_buffer_pool
+                    .metadata()
+                    .slice(entry.layer_metadata_buffer_range()),
+            );
+            pass.set_vertex_buffer(
+                3,
+                symbol_buffer_pool
+                    .feature_metadata()
+                    .slice(entry.feature_metadata_buffer_range()),
+            );
             pass.draw_indexed(entry.indices_range(), 0, 0..1);
             RenderCommandResult::Success
         } else {
@@ -149,6 +465,130 @@ impl RenderCommand<(IndexEntry, TileShape)> for DrawTile {
     }
 }
 
-pub type DrawTiles = (SetTilePipeline, SetViewBindGroup<0>, DrawTile);
+pub struct DrawExtrusion;
+impl RenderCommand<(IndexEntry, TileShape)> for DrawExtrusion {
+    fn render<'w>(
+        state: &'w RenderState,
+        (entry, shape): &(IndexEntry, TileShape),
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let (Initialized(extrusion_buffer_pool), Initialized(tile_view_pattern)) =
+            (&state.extrusion_buffer_pool, &state.tile_view_pattern)
+        {
+            tracing::trace!(
+                "Drawing extrusions {:?} at {}",
+                entry.style_layer.source_layer,
+                &entry.coords
+            );
+
+            pass.set_index_buffer(
+                extrusion_buffer_pool
+                    .indices()
+                    .slice(entry.indices_buffer_range()),
+                INDEX_FORMAT,
+            );
+            pass.set_vertex_buffer(
+                0,
+                extrusion_buffer_pool
+                    .vertices()
+                    .slice(entry.vertices_buffer_range()),
+            );
+            pass.set_vertex_buffer(
+                1,
+                tile_view_pattern.buffer().slice(shape.buffer_range.clone()),
+            );
+            pass.set_vertex_buffer(
+                2,
+                extrusion_buffer_pool
+                    .feature_metadata()
+                    .slice(entry.feature_metadata_buffer_range()),
+            );
+            pass.draw_indexed(entry.indices_range(), 0, 0..1);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct DrawTileBoundary;
+impl RenderCommand<TileBoundary> for DrawTileBoundary {
+    fn render<'w>(
+        state: &'w RenderState,
+        TileBoundary { shape, vertices }: &TileBoundary,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let (Initialized(tile_boundaries), Initialized(tile_view_pattern)) =
+            (&state.tile_boundaries, &state.tile_view_pattern)
+        {
+            tracing::trace!("Drawing boundary of {}", &shape.coords);
+
+            pass.set_vertex_buffer(0, tile_boundaries.buffer().slice(..));
+            pass.set_vertex_buffer(
+                1,
+                tile_view_pattern.buffer().slice(shape.buffer_range.clone()),
+            );
+            pass.draw(vertices.clone(), 0..1);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub struct DrawMarker;
+impl RenderCommand<MarkersInView> for DrawMarker {
+    fn render<'w>(
+        state: &'w RenderState,
+        MarkersInView {
+            vertices,
+            instances,
+        }: &MarkersInView,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Initialized(marker_overlay) = &state.marker_overlay {
+            tracing::trace!(
+                "Drawing {} marker vertices of {} instances",
+                vertices.len(),
+                instances.len()
+            );
+
+            pass.set_vertex_buffer(0, marker_overlay.buffer().slice(..));
+            pass.draw(vertices.clone(), instances.clone());
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
+    }
+}
+
+pub type DrawTiles = (
+    SetTilePipeline,
+    SetViewBindGroup<0>,
+    SetSpriteAtlasBindGroup<1>,
+    DrawTile,
+);
 
 pub type DrawMasks = (SetMaskPipeline, DrawMask);
+
+pub type DrawRasters = (SetRasterPipeline, SetRasterBindGroup<0>, DrawRaster);
+
+pub type DrawHillshades = (
+    SetHillshadePipeline,
+    SetHillshadeBindGroup<0>,
+    DrawHillshade,
+);
+
+pub type DrawSymbols = (
+    SetSymbolPipeline,
+    SetViewBindGroup<0>,
+    SetGlyphAtlasBindGroup<1>,
+    SetSpriteAtlasBindGroup<2>,
+    DrawSymbol,
+);
+
+pub type DrawExtrusions = (SetExtrusionPipeline, SetViewBindGroup<0>, DrawExtrusion);
+
+pub type DrawTileBoundaries = (SetDebugPipeline, DrawTileBoundary);
+
+pub type DrawMarkers = (SetMarkerPipeline, SetSpriteAtlasBindGroup<0>, DrawMarker);
@@ -22,7 +22,8 @@ impl<I: PhaseItem> RenderPhase<I> {
         self.items.push(item);
     }
 
-    /// Sorts all of its [`PhaseItems`](PhaseItem).
+    /// Sorts all of its [`PhaseItems`](PhaseItem). The sort is stable, therefore items with
+    /// equal keys are drawn in the order in which they have been added.
     pub fn sort(&mut self) {
         self.items.sort_by_key(|d| d.sort_key());
     }
@@ -1,6 +1,7 @@
 //! A ring-buffer like pool of [buffers](wgpu::Buffer).
 
 use crate::coords::{Quadkey, WorldTileCoords};
+use crate::metrics::BufferPoolOccupancy;
 use crate::style::layer::StyleLayer;
 use crate::tessellation::OverAlignedVertexBuffer;
 use bytemuck::Pod;
@@ -36,6 +37,12 @@ pub struct BufferPool<Q, B, V, I, TM, FM> {
     feature_metadata: BackingBuffer<B>,
 
     index: RingIndex,
+    /// Maximum number of bytes of vertices and indices which are kept by [`BufferPool::evict`].
+    budget: Option<wgpu::BufferAddress>,
+    /// Incremented with each call of [`BufferPool::evict`].
+    clock: u64,
+    /// The value of `clock` when the tile was in view the last time.
+    last_used: BTreeMap<Quadkey, u64>,
     phantom_v: PhantomData<V>,
     phantom_i: PhantomData<I>,
     phantom_q: PhantomData<Q>,
@@ -130,6 +137,9 @@ impl<Q: Queue<B>, B, V: Pod, I: Pod, TM: Pod, FM: Pod> BufferPool<Q, B, V, I, TM
                 BackingBufferType::FeatureMetadata,
             ),
             index: RingIndex::new(),
+            budget: None,
+            clock: 0,
+            last_used: Default::default(),
             phantom_v: Default::default(),
             phantom_i: Default::default(),
             phantom_q: Default::default(),
@@ -138,6 +148,14 @@ impl<Q: Queue<B>, B, V: Pod, I: Pod, TM: Pod, FM: Pod> BufferPool<Q, B, V, I, TM
         }
     }
 
+    /// Limits the bytes of vertices and indices which are kept in the pool, see
+    /// [`BufferPool::evict`]. Without a budget, layers are only evicted once the backing buffers
+    /// are full.
+    pub fn with_budget(mut self, budget: Option<wgpu::BufferAddress>) -> Self {
+        self.budget = budget;
+        self
+    }
+
     #[cfg(test)]
     fn available_space(&self, typ: BackingBufferType) -> wgpu::BufferAddress {
         let gap = match typ {
@@ -186,11 +204,13 @@ impl<Q: Queue<B>, B, V: Pod, I: Pod, TM: Pod, FM: Pod> BufferPool<Q, B, V, I, TM
         (bytes, aligned_bytes)
     }
 
+    /// Returns the ids of the style layers which are allocated at `coords`. Style layers are
+    /// tracked instead of source layers because sources may share the names of their layers.
     pub fn get_loaded_layers_at(&self, coords: &WorldTileCoords) -> Option<HashSet<&str>> {
         self.index.get_layers(coords).map(|layers| {
             layers
                 .iter()
-                .map(|entry| entry.style_layer.source_layer.as_ref().unwrap().as_str())
+                .map(|entry| entry.style_layer.id.as_str())
                 .collect()
         })
     }
@@ -280,6 +300,9 @@ impl<Q: Queue<B>, B, V: Pod, I: Pod, TM: Pod, FM: Pod> BufferPool<Q, B, V, I, TM
             &bytemuck::cast_slice(feature_metadata)[0..aligned_feature_metadata_bytes as usize],
         );
 
+        if let Some(key) = coords.build_quad_key() {
+            self.last_used.insert(key, self.clock);
+        }
         self.index.push_back(maybe_entry);
     }
 
@@ -335,6 +358,110 @@ impl<Q: Queue<B>, B, V: Pod, I: Pod, TM: Pod, FM: Pod> BufferPool<Q, B, V, I, TM
     pub fn index(&self) -> &RingIndex {
         &self.index
     }
+
+    /// Replaces the style of the uploaded layers which have the id of `style_layer`, e.g. because
+    /// its paint properties changed. The metadata of the layers is not updated.
+    pub fn update_style_layer(&mut self, style_layer: &StyleLayer) {
+        for entries in self.index.tree_index.values_mut() {
+            for entry in entries
+                .iter_mut()
+                .filter(|entry| entry.style_layer.id == style_layer.id)
+            {
+                entry.style_layer = style_layer.clone();
+            }
+        }
+    }
+
+    /// Sums up the space which is used by the layers in the vertex and index buffers.
+    pub fn occupancy(&self) -> BufferPoolOccupancy {
+        let mut occupancy = BufferPoolOccupancy {
+            tiles: self.index.tree_index.len(),
+            vertex_capacity: self.vertices.inner_size,
+            index_capacity: self.indices.inner_size,
+            ..BufferPoolOccupancy::default()
+        };
+        for entry in self.index.iter().flatten() {
+            occupancy.layers += 1;
+            occupancy.vertex_bytes += entry.buffer_vertices.end - entry.buffer_vertices.start;
+            occupancy.index_bytes += entry.buffer_indices.end - entry.buffer_indices.start;
+        }
+        occupancy
+    }
+
+    /// Marks the tiles for which `is_retained` returns true as used. Afterwards, the least recently
+    /// used tiles which are not retained are evicted until the vertices and indices fit into the
+    /// budget. Returns the number of evicted tiles.
+    pub fn evict(&mut self, is_retained: impl Fn(&WorldTileCoords) -> bool) -> usize {
+        self.clock += 1;
+
+        let mut evictable = Vec::new();
+        for (key, entries) in &self.index.tree_index {
+            if let Some(entry) = entries.front() {
+                if is_retained(&entry.coords) {
+                    self.last_used.insert(*key, self.clock);
+                } else {
+                    evictable.push(*key);
+                }
+            }
+        }
+        let tree_index = &self.index.tree_index;
+        self.last_used.retain(|key, _| tree_index.contains_key(key));
+
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return 0,
+        };
+
+        let mut used = self.occupancy().bytes();
+        if used <= budget {
+            return 0;
+        }
+
+        // Least recently used tiles last, so that they are evicted first
+        evictable.sort_by_key(|key| std::cmp::Reverse(self.last_used.get(key).copied()));
+
+        let mut evicted = 0;
+        while used > budget {
+            let key = match evictable.pop() {
+                Some(key) => key,
+                None => break,
+            };
+            for entry in self.index.remove_tile(&key) {
+                used -= (entry.buffer_vertices.end - entry.buffer_vertices.start)
+                    + (entry.buffer_indices.end - entry.buffer_indices.start);
+            }
+            self.last_used.remove(&key);
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Releases the geometry of all layers of the tiles for which `is_removed` returns true, such
+    /// that they are uploaded again.
+    pub fn remove_tiles(&mut self, is_removed: impl Fn(&WorldTileCoords) -> bool) {
+        let removed = self
+            .index
+            .tree_index
+            .iter()
+            .filter(|(_, entries)| {
+                entries
+                    .front()
+                    .map_or(false, |entry| is_removed(&entry.coords))
+            })
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in removed {
+            self.index.remove_tile(&key);
+            self.last_used.remove(&key);
+        }
+    }
+
+    /// Releases the geometry of all layers. The space of the backing buffers is reused by the next
+    /// allocations.
+    pub fn clear(&mut self) {
+        self.index = RingIndex::new();
+        self.last_used.clear();
+    }
 }
 
 pub struct BackingBufferDescriptor<B> {
@@ -547,15 +674,20 @@ impl RingIndex {
     }
 
     fn pop_front(&mut self) -> Option<IndexEntry> {
-        if let Some(entries) = self
-            .linear_index
-            .pop_front()
-            .and_then(|key| self.tree_index.get_mut(&key))
-        {
-            entries.pop_front()
-        } else {
-            None
+        let key = self.linear_index.pop_front()?;
+        let entries = self.tree_index.get_mut(&key)?;
+        let entry = entries.pop_front();
+        if entries.is_empty() {
+            self.tree_index.remove(&key);
         }
+        entry
+    }
+
+    /// Removes all layers of a tile. The space which they occupy is reused once the ring buffer
+    /// wraps around.
+    fn remove_tile(&mut self, key: &Quadkey) -> VecDeque<IndexEntry> {
+        self.linear_index.retain(|linear_key| linear_key != key);
+        self.tree_index.remove(key).unwrap_or_default()
     }
 
     fn push_back(&mut self, entry: IndexEntry) {
@@ -581,7 +713,8 @@ mod tests {
     use crate::style::layer::StyleLayer;
     use lyon::tessellation::VertexBuffers;
 
-    use crate::render::buffer_pool::{
+    use crate::coords::WorldTileCoords;
+    use crate::render::resource::buffer_pool::{
         BackingBufferDescriptor, BackingBufferType, BufferPool, Queue,
     };
 
@@ -710,4 +843,87 @@ mod tests {
         println!("{:?}", &pool.index);
         assert_eq!(0, pool.available_space(BackingBufferType::Vertices));
     }
+
+    fn create_pool(budget: u64) -> BufferPool<TestQueue, TestBuffer, TestVertex, u32, u32, u32> {
+        BufferPool::new(
+            BackingBufferDescriptor::new(TestBuffer { size: 4096 }, 4096),
+            BackingBufferDescriptor::new(TestBuffer { size: 4096 }, 4096),
+            BackingBufferDescriptor::new(TestBuffer { size: 4096 }, 4096),
+            BackingBufferDescriptor::new(TestBuffer { size: 4096 }, 4096),
+        )
+        .with_budget(Some(budget))
+    }
+
+    /// Allocates a layer with 48 bytes of vertices and 16 bytes of indices.
+    fn allocate(
+        pool: &mut BufferPool<TestQueue, TestBuffer, TestVertex, u32, u32, u32>,
+        coords: WorldTileCoords,
+    ) {
+        let mut geometry = VertexBuffers::new();
+        geometry.vertices.append(&mut create_48byte());
+        geometry.indices.append(&mut vec![1, 2, 3, 4]);
+        pool.allocate_layer_geometry(
+            &TestQueue {},
+            coords,
+            StyleLayer::default(),
+            &geometry.into(),
+            2,
+            &[],
+        );
+    }
+
+    #[test]
+    fn test_evict_while_panning() {
+        let budget = 5 * 64;
+        let mut pool = create_pool(budget);
+
+        for x in 0..100 {
+            allocate(&mut pool, (x, 0, 7).into());
+            pool.evict(|coords| (coords.x - x).abs() <= 1);
+
+            let occupancy = pool.occupancy();
+            assert!(occupancy.bytes() <= budget);
+            assert!(occupancy.tiles <= 5);
+            assert!(pool.index().has_tile(&(x, 0, 7).into()));
+        }
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let mut pool = create_pool(3 * 64);
+
+        for x in 0..3 {
+            allocate(&mut pool, (x, 0, 7).into());
+        }
+        // Tiles 0 and 2 are viewed again after tile 1
+        pool.evict(|coords| coords.x == 1);
+        pool.evict(|coords| coords.x != 1);
+
+        allocate(&mut pool, (3, 0, 7).into());
+        assert_eq!(pool.evict(|coords| coords.x == 3), 1);
+        assert!(pool.index().has_tile(&(0, 0, 7).into()));
+        assert!(!pool.index().has_tile(&(1, 0, 7).into()));
+        assert!(pool.index().has_tile(&(2, 0, 7).into()));
+
+        // Tiles in view are never evicted, even if they exceed the budget
+        assert_eq!(pool.evict(|_| true), 0);
+        assert_eq!(pool.occupancy().tiles, 3);
+    }
+
+    #[test]
+    fn test_remove_tiles() {
+        let mut pool = create_pool(4 * 64);
+        for x in 0..3 {
+            allocate(&mut pool, (x, 0, 7).into());
+        }
+
+        pool.remove_tiles(|coords| coords.x == 1);
+        assert!(pool.index().has_tile(&(0, 0, 7).into()));
+        assert!(!pool.index().has_tile(&(1, 0, 7).into()));
+        assert_eq!(pool.occupancy().tiles, 2);
+
+        // Removed tiles are uploaded again like any other tile
+        allocate(&mut pool, (1, 0, 7).into());
+        assert!(pool.index().has_tile(&(1, 0, 7).into()));
+    }
 }
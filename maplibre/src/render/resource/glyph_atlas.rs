@@ -0,0 +1,122 @@
+//! A bind group which binds the texture of a [`GlyphAtlas`](crate::text::GlyphAtlas).
+
+use crate::text;
+
+pub struct GlyphAtlas {
+    pub atlas: text::GlyphAtlas,
+    pub texture: wgpu::Texture,
+    pub bind_group: wgpu::BindGroup,
+    /// Revision of the atlas which is uploaded to the texture.
+    revision: u64,
+}
+
+impl GlyphAtlas {
+    /// The layout of the bind group which is created by [`GlyphAtlas::from_device`]. Pipelines
+    /// which sample glyphs need to use the same layout.
+    pub fn bind_group_layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+    }
+
+    pub fn from_device(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        atlas: text::GlyphAtlas,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: atlas.width,
+            height: atlas.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glyph atlas"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &atlas.data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(atlas.width),
+                rows_per_image: std::num::NonZeroU32::new(atlas.height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("glyph atlas sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("glyph atlas bind group layout"),
+            entries: &Self::bind_group_layout_entries(),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("glyph atlas bind group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Self {
+            revision: atlas.revision,
+            atlas,
+            texture,
+            bind_group,
+        }
+    }
+
+    /// Uploads the atlas again if glyphs have been added since it was uploaded. The texture is
+    /// created again, because the atlas may have grown.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.revision != self.atlas.revision {
+            let atlas = std::mem::replace(&mut self.atlas, text::GlyphAtlas::empty());
+            *self = Self::from_device(device, queue, atlas);
+        }
+    }
+}
@@ -3,16 +3,20 @@
 
 mod buffer_pool;
 mod globals;
+mod glyph_atlas;
 mod pipeline;
 mod shader;
+mod sprite_atlas;
 mod surface;
 mod texture;
 mod tracked_render_pass;
 
 pub use buffer_pool::*;
 pub use globals::*;
+pub use glyph_atlas::*;
 pub use pipeline::*;
 pub use shader::*;
+pub use sprite_atlas::*;
 pub use surface::*;
 pub use texture::*;
 pub use tracked_render_pass::*;
@@ -0,0 +1,134 @@
+//! A bind group which binds the texture of a [`SpriteSheet`](crate::io::sprite::SpriteSheet).
+
+use crate::io::sprite::SpriteSheet;
+use crate::render::settings::ColorSpace;
+use std::sync::Arc;
+
+/// Content of the texture if there is no sprite sheet.
+static TRANSPARENT_PIXEL: [u8; 4] = [0; 4];
+
+pub struct SpriteAtlas {
+    /// The sprite sheet of the texture. Without a sprite sheet the texture is a single transparent
+    /// pixel, such that labels can be drawn anyway.
+    pub sheet: Option<Arc<SpriteSheet>>,
+    pub texture: wgpu::Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl SpriteAtlas {
+    /// The layout of the bind group which is created by [`SpriteAtlas::from_device`]. Pipelines
+    /// which sample icons need to use the same layout.
+    pub fn bind_group_layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+    }
+
+    /// Uploads the RGBA pixels of `sheet`. The texture decodes the sRGB pixels if colors are
+    /// blended in linear space.
+    pub fn from_device(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sheet: Option<Arc<SpriteSheet>>,
+        color_space: ColorSpace,
+    ) -> Self {
+        let (width, height, data) = match &sheet {
+            Some(sheet) => (sheet.width, sheet.height, sheet.data.as_slice()),
+            None => (1, 1, &TRANSPARENT_PIXEL[..]),
+        };
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("sprite atlas"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: color_space.texture_format(wgpu::TextureFormat::Rgba8UnormSrgb),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * width),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("sprite atlas sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sprite atlas bind group layout"),
+            entries: &Self::bind_group_layout_entries(),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sprite atlas bind group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Self {
+            sheet,
+            texture,
+            bind_group,
+        }
+    }
+
+    /// Whether the texture contains the pixels of `sheet`.
+    pub fn is_uploaded(&self, sheet: Option<&Arc<SpriteSheet>>) -> bool {
+        match (&self.sheet, sheet) {
+            (Some(uploaded), Some(sheet)) => Arc::ptr_eq(uploaded, sheet),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
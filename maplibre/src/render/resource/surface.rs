@@ -5,6 +5,8 @@ use crate::render::resource::texture::TextureView;
 use crate::render::settings::RendererSettings;
 use crate::render::util::HasChanged;
 use crate::{MapWindow, WindowSize};
+use raw_window_handle::HasRawWindowHandle;
+use std::future::Future;
 use std::mem::size_of;
 
 struct BufferDimensions {
@@ -40,11 +42,11 @@ impl WindowHead {
         self.surface.configure(device, &self.surface_config);
     }
 
-    pub fn recreate_surface<MW>(&mut self, window: &MW, instance: &wgpu::Instance)
+    pub fn recreate_surface<H>(&mut self, window_handle: &H, instance: &wgpu::Instance)
     where
-        MW: MapWindow,
+        H: HasRawWindowHandle,
     {
-        self.surface = unsafe { instance.create_surface(window.inner()) };
+        self.surface = unsafe { instance.create_surface(window_handle) };
     }
     pub fn surface(&self) -> &wgpu::Surface {
         &self.surface
@@ -55,6 +57,134 @@ pub struct BufferedTextureHead {
     texture: wgpu::Texture,
     output_buffer: wgpu::Buffer,
     buffer_dimensions: BufferDimensions,
+    format: wgpu::TextureFormat,
+}
+
+impl BufferedTextureHead {
+    /// Creates a texture of the given size which can be rendered into and copied into a buffer
+    /// from which the CPU can read.
+    pub fn new(
+        device: &wgpu::Device,
+        size: WindowSize,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        // It is a WebGPU requirement that ImageCopyBuffer.layout.bytes_per_row % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT == 0
+        // So we calculate padded_bytes_per_row by rounding unpadded_bytes_per_row
+        // up to the next multiple of wgpu::COPY_BYTES_PER_ROW_ALIGNMENT.
+        // https://en.wikipedia.org/wiki/Data_structure_alignment#Computing_padding
+        let buffer_dimensions =
+            BufferDimensions::new(size.width() as usize, size.height() as usize);
+        // The output buffer lets us retrieve the data as an array
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (buffer_dimensions.padded_bytes_per_row * buffer_dimensions.height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size.width(),
+                height: size.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+
+        Self {
+            texture,
+            output_buffer,
+            buffer_dimensions,
+            format,
+        }
+    }
+
+    pub fn create_view(&self) -> TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+            .into()
+    }
+
+    pub fn width(&self) -> u32 {
+        self.buffer_dimensions.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.buffer_dimensions.height as u32
+    }
+
+    /// Records the copy of the rendered texture into the output buffer.
+    pub fn copy_to_buffer(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(
+                        self.buffer_dimensions.padded_bytes_per_row as u32,
+                    ),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.buffer_dimensions.width as u32,
+                height: self.buffer_dimensions.height as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Reads the pixels of the last frame which was copied into the output buffer as tightly
+    /// packed RGBA rows. Blocks until the GPU finished all submitted work.
+    pub async fn read_rgba(
+        &self,
+        device: &wgpu::Device,
+    ) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+        let mapping = self.map_buffer();
+        device.poll(wgpu::Maintain::Wait);
+        mapping.await?;
+        Ok(self.read_mapped())
+    }
+
+    /// Maps the output buffer for reading. The returned future resolves once the GPU finished the
+    /// copy into the buffer. On native platforms this requires the device to be polled.
+    pub fn map_buffer(
+        &self,
+    ) -> impl Future<Output = Result<(), wgpu::BufferAsyncError>> + Send + 'static {
+        self.output_buffer.slice(..).map_async(wgpu::MapMode::Read)
+    }
+
+    /// Reads the pixels of the mapped output buffer as tightly packed RGBA rows and unmaps it, see
+    /// [`BufferedTextureHead::map_buffer`].
+    pub fn read_mapped(&self) -> Vec<u8> {
+        let dimensions = &self.buffer_dimensions;
+        let mut pixels = Vec::with_capacity(dimensions.unpadded_bytes_per_row * dimensions.height);
+        {
+            let padded = self.output_buffer.slice(..).get_mapped_range();
+            for row in padded.chunks(dimensions.padded_bytes_per_row) {
+                pixels.extend_from_slice(&row[..dimensions.unpadded_bytes_per_row]);
+            }
+        }
+        self.output_buffer.unmap();
+
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        pixels
+    }
 }
 
 pub enum Head {
@@ -76,7 +206,20 @@ impl Surface {
     where
         MW: MapWindow,
     {
-        let size = window.size();
+        Self::from_window_handle(instance, window.inner(), window.size(), settings)
+    }
+
+    /// Creates a surface for the native window behind `window_handle`, e.g. the surface of an
+    /// Android app which is handed over by the platform glue.
+    pub fn from_window_handle<H>(
+        instance: &wgpu::Instance,
+        window_handle: &H,
+        size: WindowSize,
+        settings: &RendererSettings,
+    ) -> Self
+    where
+        H: HasRawWindowHandle,
+    {
         let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: settings.texture_format,
@@ -86,7 +229,7 @@ impl Surface {
             present_mode: wgpu::PresentMode::Fifo, // VSync
         };
 
-        let surface = unsafe { instance.create_surface(window.inner()) };
+        let surface = unsafe { instance.create_surface(window_handle) };
 
         Self {
             size,
@@ -101,67 +244,41 @@ impl Surface {
     where
         MW: MapWindow,
     {
-        let size = window.size();
-
-        // It is a WebGPU requirement that ImageCopyBuffer.layout.bytes_per_row % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT == 0
-        // So we calculate padded_bytes_per_row by rounding unpadded_bytes_per_row
-        // up to the next multiple of wgpu::COPY_BYTES_PER_ROW_ALIGNMENT.
-        // https://en.wikipedia.org/wiki/Data_structure_alignment#Computing_padding
-        let buffer_dimensions =
-            BufferDimensions::new(size.width() as usize, size.height() as usize);
-        // The output buffer lets us retrieve the data as an array
-        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (buffer_dimensions.padded_bytes_per_row * buffer_dimensions.height) as u64,
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Surface texture"),
-            size: wgpu::Extent3d {
-                width: size.width(),
-                height: size.height(),
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: settings.texture_format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-        });
+        Self::from_size(device, window.size(), settings)
+    }
 
+    /// Creates a headless surface of the given size which does not depend on a window.
+    pub fn from_size(device: &wgpu::Device, size: WindowSize, settings: &RendererSettings) -> Self {
         Self {
             size,
-            head: Head::Headless(BufferedTextureHead {
-                texture,
-                output_buffer,
-                buffer_dimensions,
-            }),
+            head: Head::Headless(BufferedTextureHead::new(
+                device,
+                size,
+                settings.texture_format,
+                "Surface texture",
+            )),
         }
     }
 
+    /// Acquires the texture of the next frame. A headed surface which is outdated or lost is
+    /// configured again before the texture is acquired once more.
     #[tracing::instrument(name = "create_view", skip_all)]
-    pub fn create_view(&self, device: &wgpu::Device) -> TextureView {
+    pub fn create_view(&self, device: &wgpu::Device) -> Result<TextureView, wgpu::SurfaceError> {
         match &self.head {
             Head::Headed(window) => {
                 let WindowHead { surface, .. } = window;
                 let frame = match surface.get_current_texture() {
                     Ok(view) => view,
-                    Err(wgpu::SurfaceError::Outdated) => {
-                        tracing::trace!("surface outdated");
+                    Err(e @ (wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost)) => {
+                        tracing::trace!("surface {}, configuring it again", e);
                         window.configure(device);
-                        surface
-                            .get_current_texture()
-                            .expect("Error reconfiguring surface")
+                        surface.get_current_texture()?
                     }
-                    err => err.expect("Failed to acquire next swap chain texture!"),
+                    Err(e) => return Err(e),
                 };
-                frame.into()
+                Ok(frame.into())
             }
-            Head::Headless(BufferedTextureHead { texture, .. }) => texture
-                .create_view(&wgpu::TextureViewDescriptor::default())
-                .into(),
+            Head::Headless(head) => Ok(head.create_view()),
         }
     }
 
@@ -169,21 +286,19 @@ impl Surface {
         self.size
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.size = WindowSize::new(width, height).expect("Invalid size for resizing the surface.");
-        match &mut self.head {
-            Head::Headed(window) => {
-                window.surface_config.height = height;
-                window.surface_config.width = width;
-            }
-            Head::Headless(_) => {}
-        }
+    /// Changes the size of the surface. A headed surface is configured with the new size by
+    /// [`Surface::reconfigure`].
+    pub fn resize(&mut self, size: WindowSize) {
+        self.size = size;
     }
 
+    /// Configures a headed surface if its size changed since it was configured the last time.
     pub fn reconfigure(&mut self, device: &wgpu::Device) {
         match &mut self.head {
             Head::Headed(window) => {
                 if window.has_changed(&(self.size.width(), self.size.height())) {
+                    window.surface_config.width = self.size.width();
+                    window.surface_config.height = self.size.height();
                     window.configure(device);
                 }
             }
@@ -191,13 +306,21 @@ impl Surface {
         }
     }
 
-    pub fn recreate<MW>(&mut self, window: &MW, instance: &wgpu::Instance)
+    /// Configures a headed surface with its current configuration.
+    pub fn configure(&self, device: &wgpu::Device) {
+        match &self.head {
+            Head::Headed(window) => window.configure(device),
+            Head::Headless(_) => {}
+        }
+    }
+
+    pub fn recreate<H>(&mut self, window_handle: &H, instance: &wgpu::Instance)
     where
-        MW: MapWindow,
+        H: HasRawWindowHandle,
     {
         match &mut self.head {
             Head::Headed(head) => {
-                head.recreate_surface(window, instance);
+                head.recreate_surface(window_handle, instance);
             }
             Head::Headless(_) => {}
         }
@@ -216,6 +339,6 @@ impl HasChanged for WindowHead {
     type Criteria = (u32, u32);
 
     fn has_changed(&self, criteria: &Self::Criteria) -> bool {
-        self.surface_config.height != criteria.0 || self.surface_config.width != criteria.1
+        self.surface_config.width != criteria.0 || self.surface_config.height != criteria.1
     }
 }
@@ -16,6 +16,12 @@ impl<'a> TrackedRenderPass<'a> {
         Self { pass }
     }
 
+    /// Returns the tracked render pass, e.g. such that [custom layers](crate::custom_layer) can
+    /// issue their own draws.
+    pub fn pass_mut(&mut self) -> &mut wgpu::RenderPass<'a> {
+        &mut self.pass
+    }
+
     /// Sets the active [`RenderPipeline`].
     ///
     /// Subsequent draw calls will exhibit the behavior defined by the `pipeline`.
@@ -1,7 +1,11 @@
 //! Settings for the renderer
 
 use crate::platform::COLOR_TEXTURE_FORMAT;
+use crate::render::shaders::Vec4f32;
+use crate::style::expression::FeatureProperties;
+use crate::style::layer::{srgb_to_linear, LayerPaint};
 use std::borrow::Cow;
+use std::collections::HashSet;
 
 pub use wgpu::Backends;
 
@@ -11,10 +15,20 @@ pub use wgpu::Backends;
 #[derive(Clone)]
 pub struct WgpuSettings {
     pub device_label: Option<Cow<'static, str>>,
+    /// The backends which are tried first.
     pub backends: Option<wgpu::Backends>,
+    /// The backends which are tried if none of [`WgpuSettings::backends`] provides an adapter,
+    /// e.g. the GL backend on machines without Vulkan, Metal or DirectX 12 drivers. The
+    /// capabilities of the picked backend are available from
+    /// [`crate::render::Renderer::capabilities`].
+    ///
+    /// On the web, wgpu supports either WebGPU or WebGL2 in a single build. WebGL2 is used with
+    /// the `web-webgl` feature, so browsers without WebGPU need a separate build.
+    pub fallback_backends: Option<wgpu::Backends>,
     pub power_preference: wgpu::PowerPreference,
-    /// The features to ensure are enabled regardless of what the adapter/backend supports.
-    /// Setting these explicitly may cause renderer initialization to fail.
+    /// The features to enable if the adapter supports them. Features which are not supported are
+    /// skipped with a warning and listed in
+    /// [`crate::render::capabilities::RendererCapabilities::missing_features`].
     pub features: wgpu::Features,
     /// The features to ensure are disabled regardless of what the adapter/backend supports
     pub disabled_features: Option<wgpu::Features>,
@@ -29,7 +43,16 @@ pub struct WgpuSettings {
 
 impl Default for WgpuSettings {
     fn default() -> Self {
-        let backends = Some(wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all()));
+        let env_backends = wgpu::util::backend_bits_from_env();
+        let (backends, fallback_backends) = if cfg!(target_arch = "wasm32") {
+            (env_backends.unwrap_or(wgpu::Backends::all()), None)
+        } else {
+            match env_backends {
+                // Backends which are chosen explicitly are not replaced by a fallback
+                Some(backends) => (backends, None),
+                None => (wgpu::Backends::PRIMARY, Some(wgpu::Backends::GL)),
+            }
+        };
 
         let limits = if cfg!(feature = "web-webgl") {
             wgpu::Limits {
@@ -55,7 +78,8 @@ impl Default for WgpuSettings {
 
         Self {
             device_label: Default::default(),
-            backends,
+            backends: Some(backends),
+            fallback_backends,
             power_preference: wgpu::PowerPreference::HighPerformance,
             features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
             disabled_features: None,
@@ -66,6 +90,21 @@ impl Default for WgpuSettings {
     }
 }
 
+impl WgpuSettings {
+    /// The sets of backends in the order in which they are tried to find an adapter.
+    pub fn backend_candidates(&self) -> Vec<wgpu::Backends> {
+        let backends = self.backends.unwrap_or(wgpu::Backends::all());
+        let mut candidates = vec![backends];
+        if let Some(fallback_backends) = self.fallback_backends {
+            let fallback_backends = fallback_backends - backends;
+            if !fallback_backends.is_empty() {
+                candidates.push(fallback_backends);
+            }
+        }
+        candidates
+    }
+}
+
 #[derive(Clone)]
 pub enum SurfaceType {
     Headless,
@@ -80,7 +119,8 @@ pub struct Msaa {
     /// smoother edges.
     /// Defaults to 4.
     ///
-    /// Note that WGPU currently only supports 1 or 4 samples.
+    /// Note that WGPU currently only supports 1 or 4 samples, other values fall back to the
+    /// closest lower count, see [`Msaa::supported`].
     /// Ultimately we plan on supporting whatever is natively supported on a given device.
     /// Check out this issue for more info: <https://github.com/gfx-rs/wgpu/issues/1832>
     pub samples: u32,
@@ -90,6 +130,17 @@ impl Msaa {
     pub fn is_active(&self) -> bool {
         self.samples > 1
     }
+
+    /// Returns the highest supported sample count which does not exceed the requested one.
+    pub fn supported(&self) -> Self {
+        let samples = SUPPORTED_MSAA_SAMPLES
+            .iter()
+            .rev()
+            .find(|samples| **samples <= self.samples)
+            .copied()
+            .unwrap_or(1);
+        Self { samples }
+    }
 }
 
 impl Default for Msaa {
@@ -98,11 +149,205 @@ impl Default for Msaa {
     }
 }
 
+/// Sample counts which WGPU can render with.
+pub(crate) const SUPPORTED_MSAA_SAMPLES: [u32; 2] = [1, 4];
+
+/// Color space in which the shaders output colors and in which colors are blended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Colors of the style are converted from sRGB to linear space and the surface encodes them
+    /// to sRGB again. Translucent layers and anti-aliased edges are blended physically correct.
+    /// Requires an sRGB surface format.
+    Linear,
+    /// Colors of the style are blended as they are, like the JS renderer does. Requires a surface
+    /// format which is not sRGB.
+    Srgb,
+}
+
+impl ColorSpace {
+    /// The color space which fits `format` without converting colors twice.
+    pub fn from_format(format: wgpu::TextureFormat) -> Self {
+        if format.describe().srgb {
+            ColorSpace::Linear
+        } else {
+            ColorSpace::Srgb
+        }
+    }
+
+    /// Returns the variant of `format` which encodes colors to sRGB if the color space is linear,
+    /// or the variant which stores colors as they are otherwise. Formats without such a variant
+    /// are returned unchanged.
+    pub fn texture_format(self, format: wgpu::TextureFormat) -> wgpu::TextureFormat {
+        use wgpu::TextureFormat::*;
+        match (self, format) {
+            (ColorSpace::Linear, Rgba8Unorm) => Rgba8UnormSrgb,
+            (ColorSpace::Linear, Bgra8Unorm) => Bgra8UnormSrgb,
+            (ColorSpace::Srgb, Rgba8UnormSrgb) => Rgba8Unorm,
+            (ColorSpace::Srgb, Bgra8UnormSrgb) => Bgra8Unorm,
+            (_, format) => format,
+        }
+    }
+
+    /// Evaluates the color of `paint` and returns it in this color space, see
+    /// [`LayerPaint::get_color`] and [`LayerPaint::get_linear_color`].
+    pub fn paint_color(
+        self,
+        paint: &LayerPaint,
+        zoom: f64,
+        feature: Option<&dyn FeatureProperties>,
+    ) -> Option<Vec4f32> {
+        match self {
+            ColorSpace::Linear => paint.get_linear_color(zoom, feature),
+            ColorSpace::Srgb => paint.get_color(zoom, feature).map(|color| color.into()),
+        }
+    }
+
+    /// Converts an sRGB `color` into this color space. The alpha channel is not converted.
+    pub fn color(self, color: Vec4f32) -> Vec4f32 {
+        match self {
+            ColorSpace::Linear => {
+                let [r, g, b, alpha] = color;
+                [
+                    srgb_to_linear(r),
+                    srgb_to_linear(g),
+                    srgb_to_linear(b),
+                    alpha,
+                ]
+            }
+            ColorSpace::Srgb => color,
+        }
+    }
+}
+
+/// Layers of which the edges of the tessellated triangles are drawn instead of the filled
+/// triangles. Meant for debugging the tessellation of fill and line layers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Wireframe {
+    Disabled,
+    AllLayers,
+    /// Only the layers with these ids are drawn as wireframes.
+    Layers(HashSet<String>),
+}
+
+impl Wireframe {
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self, Wireframe::Disabled)
+    }
+
+    /// Whether the layer with `layer_id` is drawn as wireframe.
+    pub fn contains(&self, layer_id: &str) -> bool {
+        match self {
+            Wireframe::Disabled => false,
+            Wireframe::AllLayers => true,
+            Wireframe::Layers(layer_ids) => layer_ids.contains(layer_id),
+        }
+    }
+}
+
+impl Default for Wireframe {
+    fn default() -> Self {
+        Wireframe::Disabled
+    }
+}
+
 #[derive(Clone)]
 pub struct RendererSettings {
     pub msaa: Msaa,
+    /// Format of the surface. It is adapted to the [`RendererSettings::color_space`], see
+    /// [`RendererSettings::supported`].
     pub texture_format: wgpu::TextureFormat,
+    /// Defaults to the color space which fits the surface format of the platform. Platforms
+    /// without sRGB surfaces, like WebGPU in browsers, blend in sRGB.
+    pub color_space: ColorSpace,
     pub surface_type: SurfaceType,
+    /// Data of a TTF or OTF font which is used to render the labels of symbol layers. Labels are
+    /// not rendered if no font is set.
+    pub font: Option<Vec<u8>>,
+    /// Maximum number of bytes of vertices and indices which are kept on the GPU. Once exceeded,
+    /// the geometry of the least recently viewed tiles outside of the view is evicted. If `None`,
+    /// geometry is only evicted once the buffers are full.
+    pub buffer_pool_budget: Option<u64>,
+    /// Maximum number of bytes of tessellated layers and raster tiles which are kept in the
+    /// [`crate::io::tile_cache::TileCache`]. Once exceeded, the least recently viewed tiles outside
+    /// of the view are evicted and requested again once they return into view. If `None`, the
+    /// cache grows without bounds.
+    pub tile_cache_budget: Option<usize>,
+    /// Draws the outlines and the `z/x/y` coordinates of the tiles in view on top of the map.
+    /// Tiles which fall back to an ancestor, e.g. because they are incomplete or overzoomed, also
+    /// show the coordinates of the ancestor, which is outlined in blue. Meant for debugging and
+    /// can be toggled at runtime.
+    pub show_tile_boundaries: bool,
+    /// Draws fill and line layers as wireframes, see [`Wireframe`]. Requires
+    /// [`wgpu::Features::POLYGON_MODE_LINE`], which is not available with WebGL2. Without it the
+    /// layers are filled as usual. Can be changed at runtime.
+    pub wireframe: Wireframe,
+    /// Color with which frames are cleared below the background layers of the style, in sRGB and
+    /// not premultiplied by alpha. A transparent color such as [`wgpu::Color::TRANSPARENT`] lets
+    /// headless images be composited over other content. Can be changed at runtime.
+    pub clear_color: wgpu::Color,
+    /// Draws the markers with instancing, such that the GPU expands a single instance of a few
+    /// floats into the quad of each marker. Otherwise six vertices are uploaded per marker. Both
+    /// draw all markers with a single draw call.
+    pub instanced_markers: bool,
+    /// Culls the tiles outside of the view frustum with a compute pass instead of on the CPU,
+    /// which writes the arguments of indirect draws. Falls back to the CPU if the device lacks
+    /// compute shaders or indirect draws, like WebGL2.
+    pub gpu_culling: bool,
+}
+
+impl RendererSettings {
+    /// Replaces settings which can not be fulfilled with the closest supported ones.
+    pub fn supported(mut self) -> Self {
+        // Frames of headless surfaces are read back as 8-bit RGBA pixels including the alpha
+        // channel, see `BufferedTextureHead::read_rgba`
+        if matches!(self.surface_type, SurfaceType::Headless)
+            && !matches!(
+                self.texture_format,
+                wgpu::TextureFormat::Rgba8Unorm
+                    | wgpu::TextureFormat::Rgba8UnormSrgb
+                    | wgpu::TextureFormat::Bgra8Unorm
+                    | wgpu::TextureFormat::Bgra8UnormSrgb
+            )
+        {
+            log::warn!(
+                "{:?} can not be read back from headless surfaces, falling back to {:?}",
+                self.texture_format,
+                wgpu::TextureFormat::Rgba8Unorm
+            );
+            self.texture_format = wgpu::TextureFormat::Rgba8Unorm;
+        }
+
+        let msaa = self.msaa.supported();
+        if msaa.samples != self.msaa.samples {
+            log::warn!(
+                "{} MSAA samples are not supported, falling back to {}",
+                self.msaa.samples,
+                msaa.samples
+            );
+        }
+        self.msaa = msaa;
+
+        let texture_format = self.color_space.texture_format(self.texture_format);
+        if ColorSpace::from_format(texture_format) != self.color_space {
+            log::warn!(
+                "{:?} can not be blended in {:?} space, falling back to {:?}",
+                texture_format,
+                self.color_space,
+                ColorSpace::from_format(texture_format)
+            );
+        }
+        self.texture_format = texture_format;
+        self.color_space = ColorSpace::from_format(texture_format);
+
+        // wgpu 0.12 can not configure how windows composite the alpha channel of the surface
+        if self.clear_color.a < 1.0 && matches!(self.surface_type, SurfaceType::Headed) {
+            log::warn!(
+                "Translucent clear colors are only shown through windows on platforms which \
+                composite surfaces with alpha"
+            );
+        }
+        self
+    }
 }
 
 impl Default for RendererSettings {
@@ -110,7 +355,110 @@ impl Default for RendererSettings {
         Self {
             msaa: Msaa::default(),
             texture_format: COLOR_TEXTURE_FORMAT,
+            color_space: ColorSpace::from_format(COLOR_TEXTURE_FORMAT),
             surface_type: SurfaceType::Headed,
+            font: None,
+            buffer_pool_budget: None,
+            tile_cache_budget: None,
+            show_tile_boundaries: false,
+            wireframe: Wireframe::Disabled,
+            clear_color: wgpu::Color::WHITE,
+            instanced_markers: true,
+            gpu_culling: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorSpace, Msaa, RendererSettings, SurfaceType, WgpuSettings, Wireframe};
+
+    #[test]
+    fn test_supported_msaa() {
+        let supported = |samples| Msaa { samples }.supported().samples;
+
+        assert_eq!(supported(0), 1);
+        assert_eq!(supported(1), 1);
+        assert_eq!(supported(2), 1);
+        assert_eq!(supported(4), 4);
+        assert_eq!(supported(8), 4);
+    }
+
+    #[test]
+    fn test_wireframe_layers() {
+        assert!(!Wireframe::Disabled.contains("water"));
+        assert!(Wireframe::AllLayers.contains("water"));
+
+        let wireframe = Wireframe::Layers(["water".to_string()].into_iter().collect());
+        assert!(wireframe.is_enabled());
+        assert!(wireframe.contains("water"));
+        assert!(!wireframe.contains("park"));
+    }
+
+    #[test]
+    fn test_texture_format_follows_color_space() {
+        let settings = |texture_format, color_space| {
+            let settings = RendererSettings {
+                texture_format,
+                color_space,
+                ..RendererSettings::default()
+            }
+            .supported();
+            (settings.texture_format, settings.color_space)
+        };
+
+        assert_eq!(
+            settings(wgpu::TextureFormat::Bgra8Unorm, ColorSpace::Linear),
+            (wgpu::TextureFormat::Bgra8UnormSrgb, ColorSpace::Linear)
+        );
+        assert_eq!(
+            settings(wgpu::TextureFormat::Rgba8UnormSrgb, ColorSpace::Srgb),
+            (wgpu::TextureFormat::Rgba8Unorm, ColorSpace::Srgb)
+        );
+        // There is no sRGB variant of floating point formats
+        assert_eq!(
+            settings(wgpu::TextureFormat::Rgba16Float, ColorSpace::Linear),
+            (wgpu::TextureFormat::Rgba16Float, ColorSpace::Srgb)
+        );
+    }
+
+    #[test]
+    fn test_headless_surfaces_are_rgba() {
+        let settings = RendererSettings {
+            texture_format: wgpu::TextureFormat::Rgba16Float,
+            color_space: ColorSpace::Linear,
+            surface_type: SurfaceType::Headless,
+            clear_color: wgpu::Color::TRANSPARENT,
+            ..RendererSettings::default()
         }
+        .supported();
+        assert_eq!(settings.texture_format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(settings.clear_color, wgpu::Color::TRANSPARENT);
+    }
+
+    #[test]
+    fn test_backend_candidates() {
+        let candidates = |backends, fallback_backends| {
+            WgpuSettings {
+                backends,
+                fallback_backends,
+                ..WgpuSettings::default()
+            }
+            .backend_candidates()
+        };
+
+        assert_eq!(
+            candidates(Some(wgpu::Backends::PRIMARY), Some(wgpu::Backends::GL)),
+            vec![wgpu::Backends::PRIMARY, wgpu::Backends::GL]
+        );
+        assert_eq!(
+            candidates(Some(wgpu::Backends::VULKAN), None),
+            vec![wgpu::Backends::VULKAN]
+        );
+        // The GL backend is already tried together with the primary backends
+        assert_eq!(
+            candidates(None, Some(wgpu::Backends::GL)),
+            vec![wgpu::Backends::all()]
+        );
     }
 }
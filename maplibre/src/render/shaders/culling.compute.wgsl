@@ -0,0 +1,63 @@
+struct Tiles {
+    // Model-view-projection of each tile shape in view
+    transforms: array<mat4x4<f32>>;
+};
+
+struct CullItem {
+    tile: u32;
+    index_count: u32;
+};
+
+struct CullItems {
+    count: u32;
+    items: array<CullItem>;
+};
+
+struct DrawIndexedIndirect {
+    index_count: u32;
+    instance_count: u32;
+    first_index: u32;
+    base_vertex: i32;
+    first_instance: u32;
+};
+
+struct Draws {
+    draws: array<DrawIndexedIndirect>;
+};
+
+[[group(0), binding(0)]] var<storage, read> tiles: Tiles;
+[[group(0), binding(1)]] var<storage, read> cull_items: CullItems;
+[[group(0), binding(2)]] var<storage, read_write> draws: Draws;
+
+let EXTENT = 4096.0;
+
+// A tile is culled if all of its corners lie beyond the same plane of the view frustum
+fn is_visible(transform: mat4x4<f32>) -> bool {
+    let c0 = transform * vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    let c1 = transform * vec4<f32>(EXTENT, 0.0, 0.0, 1.0);
+    let c2 = transform * vec4<f32>(0.0, EXTENT, 0.0, 1.0);
+    let c3 = transform * vec4<f32>(EXTENT, EXTENT, 0.0, 1.0);
+
+    let left = c0.x < -c0.w && c1.x < -c1.w && c2.x < -c2.w && c3.x < -c3.w;
+    let right = c0.x > c0.w && c1.x > c1.w && c2.x > c2.w && c3.x > c3.w;
+    let bottom = c0.y < -c0.w && c1.y < -c1.w && c2.y < -c2.w && c3.y < -c3.w;
+    let top = c0.y > c0.w && c1.y > c1.w && c2.y > c2.w && c3.y > c3.w;
+
+    return !(left || right || bottom || top);
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= cull_items.count) {
+        return;
+    }
+
+    let item = cull_items.items[index];
+    var instance_count = 0u;
+    if (is_visible(tiles.transforms[item.tile])) {
+        instance_count = 1u;
+    }
+
+    draws.draws[index] = DrawIndexedIndirect(item.index_count, instance_count, 0u, 0, 0u);
+}
@@ -0,0 +1,25 @@
+struct VertexOutput {
+    [[location(0)]] v_color: vec4<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    [[location(0)]] anchor: vec2<f32>,
+    [[location(1)]] offset: vec2<f32>,
+    [[location(2)]] color: vec4<f32>,
+    [[location(4)]] translate1: vec4<f32>,
+    [[location(5)]] translate2: vec4<f32>,
+    [[location(6)]] translate3: vec4<f32>,
+    [[location(7)]] translate4: vec4<f32>,
+    [[location(9)]] zoom_factor: f32
+) -> VertexOutput {
+    let z = 0.0;
+
+    // Scaling the offset by the zoom factor keeps the size of the coordinates constant on the screen
+    var position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(anchor + offset * zoom_factor, z, 1.0);
+    // The overlay is drawn on top of everything else
+    position.z = 1.0;
+
+    return VertexOutput(color, position);
+}
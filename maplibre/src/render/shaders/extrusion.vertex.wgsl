@@ -0,0 +1,49 @@
+struct ShaderCamera {
+    view_proj: mat4x4<f32>;
+    view_position: vec4<f32>;
+};
+
+struct ShaderGlobals {
+    camera: ShaderCamera;
+    zoom: f32;
+    bearing: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> globals: ShaderGlobals;
+
+struct VertexOutput {
+    [[location(0)]] v_color: vec4<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+// Must match TILE_SIZE / EXTENT
+let TILE_SIZE_PER_EXTENT = 0.125;
+// Direction towards the light, which shines from the north-west. The y-axis points south.
+let LIGHT_DIRECTION = vec3<f32>(-0.5, -0.5, 0.70710678);
+// Fraction of the color which is visible on faces which are not lit
+let AMBIENT = 0.5;
+
+[[stage(vertex)]]
+fn main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+    [[location(4)]] translate1: vec4<f32>,
+    [[location(5)]] translate2: vec4<f32>,
+    [[location(6)]] translate3: vec4<f32>,
+    [[location(7)]] translate4: vec4<f32>,
+    [[location(8)]] color: vec4<f32>,
+    [[location(9)]] zoom_factor: f32,
+    [[builtin(instance_index)]] instance_idx: u32 // instance_index is used when we have multiple instances of the same "object"
+) -> VertexOutput {
+    // The transform of the tile only scales x and y. Therefore, the height is scaled in the same
+    // way here.
+    let z = position.z * TILE_SIZE_PER_EXTENT / zoom_factor;
+
+    // Unlike the flat layers, extrusions keep their depth such that walls and roofs occlude each
+    // other
+    let position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(position.xy, z, 1.0);
+
+    let light = AMBIENT + (1.0 - AMBIENT) * max(dot(normalize(normal), LIGHT_DIRECTION), 0.0);
+
+    return VertexOutput(vec4<f32>(color.rgb * light, color.a), position);
+}
@@ -0,0 +1,80 @@
+struct Output {
+    [[location(0)]] out_color: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var t_dem: texture_2d<f32>;
+
+let PI = 3.141592653589793;
+
+fn elevation(pixel: vec2<i32>, offset: vec2<i32>) -> f32 {
+    let size = textureDimensions(t_dem);
+    let clamped = clamp(pixel + offset, vec2<i32>(0, 0), size - vec2<i32>(1, 1));
+    return textureLoad(t_dem, clamped, 0).r;
+}
+
+// Shades the terrain like maplibre-gl-js. The slope is derived from the elevations around the
+// pixel with a Sobel operator.
+[[stage(fragment)]]
+fn main(
+    [[location(0)]] v_tex_coords: vec2<f32>,
+    [[location(1)]] v_shadow: vec4<f32>,
+    [[location(2)]] v_highlight: vec4<f32>,
+    [[location(3)]] v_accent: vec4<f32>,
+    [[location(4)]] v_light: vec4<f32>
+) -> Output {
+    let latitude = v_light.x;
+    let intensity = v_light.y;
+    let azimuth = v_light.z + PI;
+    let zoom = v_light.w;
+
+    let size = textureDimensions(t_dem);
+    let pixel = vec2<i32>(v_tex_coords * vec2<f32>(size));
+
+    let a = elevation(pixel, vec2<i32>(-1, -1));
+    let b = elevation(pixel, vec2<i32>(0, -1));
+    let c = elevation(pixel, vec2<i32>(1, -1));
+    let d = elevation(pixel, vec2<i32>(-1, 0));
+    let f = elevation(pixel, vec2<i32>(1, 0));
+    let g = elevation(pixel, vec2<i32>(-1, 1));
+    let h = elevation(pixel, vec2<i32>(0, 1));
+    let i = elevation(pixel, vec2<i32>(1, 1));
+
+    // Terrain is exaggerated at low zoom levels, where it would be too flat otherwise
+    let exaggeration_factor = select(select(0.3, 0.35, zoom < 4.5), 0.4, zoom < 2.0);
+    let exaggeration = min(zoom - 15.0, 0.0) * exaggeration_factor;
+
+    let deriv = clamp(
+        vec2<f32>((c + f + f + i) - (a + d + d + g), (g + h + h + i) - (a + b + b + c))
+            / pow(2.0, exaggeration + (19.2562 - zoom)),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0)
+    );
+
+    // Web Mercator stretches the terrain towards the poles
+    let scale_factor = cos(latitude * PI / 180.0);
+    let slope = atan(1.25 * length(deriv) / scale_factor);
+    let aspect = select(PI / 2.0 * sign(deriv.y), atan2(deriv.y, -deriv.x), deriv.x != 0.0);
+
+    let base = 1.875 - intensity * 1.75;
+    let max_value = 0.5 * PI;
+    let scaled_slope = select(
+        slope,
+        ((pow(base, slope) - 1.0) / (pow(base, max_value) - 1.0)) * max_value,
+        intensity != 0.5
+    );
+
+    // The colors are premultiplied to be composed, and the result is divided by its alpha again
+    let shadow = vec4<f32>(v_shadow.rgb * v_shadow.a, v_shadow.a);
+    let highlight = vec4<f32>(v_highlight.rgb * v_highlight.a, v_highlight.a);
+    let accent = vec4<f32>(v_accent.rgb * v_accent.a, v_accent.a);
+
+    let accent_color = (1.0 - cos(scaled_slope)) * accent * clamp(intensity * 2.0, 0.0, 1.0);
+    let shade = abs((((aspect + azimuth) / PI + 0.5) % 2.0 + 2.0) % 2.0 - 1.0);
+    let shade_color = mix(shadow, highlight, shade) * sin(scaled_slope) * clamp(intensity * 2.0, 0.0, 1.0);
+    let color = accent_color * (1.0 - shade_color.a) + shade_color;
+
+    if (color.a <= 0.0) {
+        return Output(vec4<f32>(0.0, 0.0, 0.0, 0.0));
+    }
+    return Output(vec4<f32>(color.rgb / color.a, color.a));
+}
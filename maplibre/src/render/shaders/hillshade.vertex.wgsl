@@ -0,0 +1,56 @@
+struct VertexOutput {
+    [[location(0)]] v_tex_coords: vec2<f32>;
+    [[location(1)]] v_shadow: vec4<f32>;
+    [[location(2)]] v_highlight: vec4<f32>;
+    [[location(3)]] v_accent: vec4<f32>;
+    // latitude, exaggeration, illumination direction and zoom level of the DEM tile
+    [[location(4)]] v_light: vec4<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+let EXTENT = 4096.0;
+
+[[stage(vertex)]]
+fn main(
+    [[location(4)]] translate1: vec4<f32>,
+    [[location(5)]] translate2: vec4<f32>,
+    [[location(6)]] translate3: vec4<f32>,
+    [[location(7)]] translate4: vec4<f32>,
+    [[location(8)]] tex_rect: vec4<f32>,
+    [[location(9)]] shadow: vec4<f32>,
+    [[location(10)]] highlight: vec4<f32>,
+    [[location(11)]] accent: vec4<f32>,
+    [[location(12)]] latitude_range: vec2<f32>,
+    [[location(13)]] exaggeration: f32,
+    [[location(14)]] illumination_direction: f32,
+    [[location(15)]] dem_zoom: f32,
+    [[builtin(vertex_index)]] vertex_idx: u32
+) -> VertexOutput {
+    let z = 0.0;
+
+    var VERTICES: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0)
+    );
+    let corner = VERTICES[vertex_idx];
+
+    let tex_coords = mix(tex_rect.xy, tex_rect.zw, corner);
+    let latitude = mix(latitude_range.x, latitude_range.y, corner.y);
+
+    var position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(corner * EXTENT, z, 1.0);
+    // Hillshades are drawn in the order of the layers without depth testing
+    position.z = 1.0;
+
+    return VertexOutput(
+        tex_coords,
+        shadow,
+        highlight,
+        accent,
+        vec4<f32>(latitude, exaggeration, illumination_direction, dem_zoom),
+        position
+    );
+}
@@ -0,0 +1,18 @@
+struct Output {
+    [[location(0)]] out_color: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var t_sprite: texture_2d<f32>;
+[[group(0), binding(1)]] var s_sprite: sampler;
+
+[[stage(fragment)]]
+fn main(
+    [[location(0)]] v_color: vec4<f32>,
+    [[location(1)]] v_tex_coords: vec2<f32>,
+    [[location(2)]] v_is_icon: f32
+) -> Output {
+    // The atlas is sampled for squares as well because sampling requires uniform control flow
+    let icon = textureSample(t_sprite, s_sprite, v_tex_coords);
+
+    return Output(mix(v_color, icon * v_color, v_is_icon));
+}
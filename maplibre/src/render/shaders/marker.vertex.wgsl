@@ -0,0 +1,17 @@
+struct VertexOutput {
+    [[location(0)]] v_color: vec4<f32>;
+    [[location(1)]] v_tex_coords: vec2<f32>;
+    [[location(2)]] v_is_icon: f32;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    [[location(0)]] position: vec2<f32>,
+    [[location(1)]] tex_coords: vec2<f32>,
+    [[location(2)]] color: vec4<f32>,
+    [[location(3)]] is_icon: f32
+) -> VertexOutput {
+    // Markers are projected on the CPU and drawn on top of everything else
+    return VertexOutput(color, tex_coords, is_icon, vec4<f32>(position, 1.0, 1.0));
+}
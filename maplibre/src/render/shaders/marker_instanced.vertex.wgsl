@@ -0,0 +1,35 @@
+struct VertexOutput {
+    [[location(0)]] v_color: vec4<f32>;
+    [[location(1)]] v_tex_coords: vec2<f32>;
+    [[location(2)]] v_is_icon: f32;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    [[location(0)]] anchor: vec2<f32>,
+    [[location(1)]] top_left: vec2<f32>,
+    [[location(2)]] bottom_right: vec2<f32>,
+    [[location(3)]] tex_top_left: vec2<f32>,
+    [[location(4)]] tex_bottom_right: vec2<f32>,
+    [[location(5)]] color: vec4<f32>,
+    [[location(6)]] is_icon: f32,
+    [[builtin(vertex_index)]] vertex_idx: u32
+) -> VertexOutput {
+    // Corners of the two triangles of the quad, in the same order as `marker_vertices`
+    var VERTICES: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0)
+    );
+    let corner = VERTICES[vertex_idx];
+
+    let position = anchor + mix(top_left, bottom_right, corner);
+    let tex_coords = mix(tex_top_left, tex_bottom_right, corner);
+
+    // Markers are projected on the CPU and drawn on top of everything else
+    return VertexOutput(color, tex_coords, is_icon, vec4<f32>(position, 1.0, 1.0));
+}
@@ -1,7 +1,9 @@
 #![allow(clippy::identity_op)]
 
-use crate::coords::WorldCoords;
+use crate::coords::{WorldCoords, EXTENT, TILE_SIZE};
+use crate::io::sprite::SpritePattern;
 use crate::render::resource::{FragmentState, VertexBufferLayout, VertexState};
+use crate::text::placement::LabelStyle;
 use bytemuck_derive::{Pod, Zeroable};
 use cgmath::SquareMatrix;
 
@@ -10,6 +12,26 @@ pub type Vec3f32 = [f32; 3];
 pub type Vec4f32 = [f32; 4];
 pub type Mat4x4f32 = [Vec4f32; 4];
 
+/// Half of the width of lines in tile units if the style does not define `line-width`.
+const DEFAULT_LINE_WIDTH: f32 = 3.0;
+
+/// Blends the colors of the shaders, which are not premultiplied by alpha, onto the target. The
+/// blended colors in the target are premultiplied, such that frames which are cleared with a
+/// transparent [`crate::render::settings::RendererSettings::clear_color`] keep an alpha of 0
+/// where nothing is drawn and can be composited over other content.
+pub const LAYER_BLENDING: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
 impl From<WorldCoords> for Vec3f32 {
     fn from(world_coords: WorldCoords) -> Self {
         [world_coords.x as f32, world_coords.y as f32, 0.0]
@@ -56,6 +78,13 @@ impl Shader for TileMaskShader {
                         format: wgpu::VertexFormat::Float32x4,
                         shader_location: 7,
                     },
+                    // mask_overscan
+                    wgpu::VertexAttribute {
+                        offset: 4 * wgpu::VertexFormat::Float32x4.size()
+                            + wgpu::VertexFormat::Float32.size(),
+                        format: wgpu::VertexFormat::Float32,
+                        shader_location: 8,
+                    },
                 ],
             }],
         }
@@ -105,6 +134,19 @@ impl Shader for TileShader {
                             format: wgpu::VertexFormat::Float32x2,
                             shader_location: 1,
                         },
+                        // line_distance
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x2.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 2,
+                        },
+                        // line_edge and line_side
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x2.size()
+                                + wgpu::VertexFormat::Float32.size(),
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 3,
+                        },
                     ],
                 },
                 // tile metadata
@@ -152,6 +194,33 @@ impl Shader for TileShader {
                             format: wgpu::VertexFormat::Float32,
                             shader_location: 10,
                         },
+                        // line_dasharray
+                        wgpu::VertexAttribute {
+                            offset: wgpu::VertexFormat::Float32.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 11,
+                        },
+                        // line_gap_width
+                        wgpu::VertexAttribute {
+                            offset: wgpu::VertexFormat::Float32.size()
+                                + wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 13,
+                        },
+                        // pattern
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32.size()
+                                + wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 14,
+                        },
+                        // pattern_size
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32.size()
+                                + 2 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 15,
+                        },
                     ],
                 },
                 // features
@@ -165,6 +234,12 @@ impl Shader for TileShader {
                             format: wgpu::VertexFormat::Float32x4,
                             shader_location: 8,
                         },
+                        // line_width
+                        wgpu::VertexAttribute {
+                            offset: wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 12,
+                        },
                     ],
                 },
             ],
@@ -177,98 +252,939 @@ impl Shader for TileShader {
             entry_point: "main",
             targets: vec![wgpu::ColorTargetState {
                 format: self.format,
-                /*blend: Some(wgpu::BlendState {
-                    color: wgpu::BlendComponent {
-                        src_factor: wgpu::BlendFactor::SrcAlpha,
-                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                        operation: wgpu::BlendOperation::Add,
-                    },
-                    alpha: wgpu::BlendComponent {
-                        src_factor: wgpu::BlendFactor::SrcAlpha,
-                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                        operation: wgpu::BlendOperation::Add,
-                    },
-                }),*/
-                blend: None,
+                blend: Some(LAYER_BLENDING),
                 write_mask: wgpu::ColorWrites::ALL,
             }],
         }
     }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Pod, Zeroable)]
-pub struct ShaderCamera {
-    view_proj: Mat4x4f32,   // 64 bytes
-    view_position: Vec4f32, // 16 bytes
+pub struct SymbolShader {
+    pub format: wgpu::TextureFormat,
 }
 
-impl ShaderCamera {
-    pub fn new(view_proj: Mat4x4f32, view_position: Vec4f32) -> Self {
-        Self {
-            view_position,
-            view_proj,
+impl Shader for SymbolShader {
+    fn describe_vertex(&self) -> VertexState {
+        VertexState {
+            source: include_str!("symbol.vertex.wgsl"),
+            entry_point: "main",
+            buffers: vec![
+                // vertex data
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<SymbolVertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: vec![
+                        // anchor
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 0,
+                        },
+                        // offset
+                        wgpu::VertexAttribute {
+                            offset: wgpu::VertexFormat::Float32x2.size(),
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 1,
+                        },
+                        // tex_coords
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x2.size(),
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 2,
+                        },
+                        // is_icon
+                        wgpu::VertexAttribute {
+                            offset: 3 * wgpu::VertexFormat::Float32x2.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 3,
+                        },
+                        // upright
+                        wgpu::VertexAttribute {
+                            offset: 3 * wgpu::VertexFormat::Float32x2.size()
+                                + wgpu::VertexFormat::Float32.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 13,
+                        },
+                    ],
+                },
+                // tile metadata
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderTileMetadata>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: vec![
+                        // translate
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 4,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 1 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 5,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 6,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 3 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 7,
+                        },
+                        // zoom_factor
+                        wgpu::VertexAttribute {
+                            offset: 4 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 9,
+                        },
+                    ],
+                },
+                // layer metadata
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderLayerMetadata>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: vec![
+                        // z_index
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 10,
+                        },
+                    ],
+                },
+                // features
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderSymbolStyle>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: vec![
+                        // color
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 8,
+                        },
+                        // halo_color
+                        wgpu::VertexAttribute {
+                            offset: wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 11,
+                        },
+                        // halo_width
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 12,
+                        },
+                    ],
+                },
+            ],
         }
     }
-}
 
-impl Default for ShaderCamera {
-    fn default() -> Self {
-        Self {
-            view_position: [0.0; 4],
-            view_proj: cgmath::Matrix4::identity().into(),
+    fn describe_fragment(&self) -> FragmentState {
+        FragmentState {
+            source: include_str!("symbol.fragment.wgsl"),
+            entry_point: "main",
+            targets: vec![wgpu::ColorTargetState {
+                format: self.format,
+                blend: Some(LAYER_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
         }
     }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Pod, Zeroable)]
-pub struct ShaderGlobals {
-    camera: ShaderCamera,
+pub struct ExtrusionShader {
+    pub format: wgpu::TextureFormat,
 }
 
-impl ShaderGlobals {
-    pub fn new(camera_uniform: ShaderCamera) -> Self {
-        Self {
-            camera: camera_uniform,
+impl Shader for ExtrusionShader {
+    fn describe_vertex(&self) -> VertexState {
+        VertexState {
+            source: include_str!("extrusion.vertex.wgsl"),
+            entry_point: "main",
+            buffers: vec![
+                // vertex data
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ExtrusionVertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: vec![
+                        // position
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x3,
+                            shader_location: 0,
+                        },
+                        // normal
+                        wgpu::VertexAttribute {
+                            offset: wgpu::VertexFormat::Float32x3.size(),
+                            format: wgpu::VertexFormat::Float32x3,
+                            shader_location: 1,
+                        },
+                    ],
+                },
+                // tile metadata
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderTileMetadata>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: vec![
+                        // translate
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 4,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 1 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 5,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 6,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 3 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 7,
+                        },
+                        // zoom_factor
+                        wgpu::VertexAttribute {
+                            offset: 4 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 9,
+                        },
+                    ],
+                },
+                // features
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderFeatureStyle>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: vec![
+                        // color
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 8,
+                        },
+                    ],
+                },
+            ],
         }
     }
-}
-
-#[repr(C)]
-#[derive(Copy, Clone, Pod, Zeroable)]
-pub struct ShaderVertex {
-    pub position: Vec2f32,
-    pub normal: Vec2f32,
-}
-
-impl ShaderVertex {
-    pub fn new(position: Vec2f32, normal: Vec2f32) -> Self {
-        Self { position, normal }
-    }
-}
 
-impl Default for ShaderVertex {
-    fn default() -> Self {
-        ShaderVertex::new([0.0, 0.0], [0.0, 0.0])
+    fn describe_fragment(&self) -> FragmentState {
+        FragmentState {
+            source: include_str!("extrusion.fragment.wgsl"),
+            entry_point: "main",
+            targets: vec![wgpu::ColorTargetState {
+                format: self.format,
+                blend: Some(LAYER_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }
     }
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
-pub struct ShaderFeatureStyle {
-    pub color: Vec4f32,
+pub struct RasterShader {
+    pub format: wgpu::TextureFormat,
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Pod, Zeroable)]
-pub struct ShaderLayerMetadata {
-    pub z_index: f32,
-}
+impl Shader for RasterShader {
+    fn describe_vertex(&self) -> VertexState {
+        VertexState {
+            source: include_str!("raster.vertex.wgsl"),
+            entry_point: "main",
+            buffers: vec![
+                // tile metadata
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderTileMetadata>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: vec![
+                        // translate
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 4,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 1 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 5,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 6,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 3 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 7,
+                        },
+                    ],
+                },
+                // raster metadata
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderRasterMetadata>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: vec![
+                        // tex_rect
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 10,
+                        },
+                        // opacity
+                        wgpu::VertexAttribute {
+                            offset: wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 11,
+                        },
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn describe_fragment(&self) -> FragmentState {
+        FragmentState {
+            source: include_str!("raster.fragment.wgsl"),
+            entry_point: "main",
+            targets: vec![wgpu::ColorTargetState {
+                format: self.format,
+                blend: Some(LAYER_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }
+    }
+}
+
+pub struct HillshadeShader {
+    pub format: wgpu::TextureFormat,
+}
+
+impl Shader for HillshadeShader {
+    fn describe_vertex(&self) -> VertexState {
+        VertexState {
+            source: include_str!("hillshade.vertex.wgsl"),
+            entry_point: "main",
+            buffers: vec![
+                // tile metadata
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderTileMetadata>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: vec![
+                        // translate
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 4,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 1 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 5,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 6,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 3 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 7,
+                        },
+                    ],
+                },
+                // hillshade metadata
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderHillshadeMetadata>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: vec![
+                        // tex_rect
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 8,
+                        },
+                        // shadow
+                        wgpu::VertexAttribute {
+                            offset: 1 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 9,
+                        },
+                        // highlight
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 10,
+                        },
+                        // accent
+                        wgpu::VertexAttribute {
+                            offset: 3 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 11,
+                        },
+                        // latitude_range
+                        wgpu::VertexAttribute {
+                            offset: 4 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 12,
+                        },
+                        // exaggeration
+                        wgpu::VertexAttribute {
+                            offset: 4 * wgpu::VertexFormat::Float32x4.size()
+                                + wgpu::VertexFormat::Float32x2.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 13,
+                        },
+                        // illumination_direction
+                        wgpu::VertexAttribute {
+                            offset: 4 * wgpu::VertexFormat::Float32x4.size()
+                                + wgpu::VertexFormat::Float32x2.size()
+                                + wgpu::VertexFormat::Float32.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 14,
+                        },
+                        // dem_zoom
+                        wgpu::VertexAttribute {
+                            offset: 4 * wgpu::VertexFormat::Float32x4.size()
+                                + wgpu::VertexFormat::Float32x2.size()
+                                + 2 * wgpu::VertexFormat::Float32.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 15,
+                        },
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn describe_fragment(&self) -> FragmentState {
+        FragmentState {
+            source: include_str!("hillshade.fragment.wgsl"),
+            entry_point: "main",
+            targets: vec![wgpu::ColorTargetState {
+                format: self.format,
+                blend: Some(LAYER_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }
+    }
+}
+
+pub struct DebugShader {
+    pub format: wgpu::TextureFormat,
+}
+
+impl Shader for DebugShader {
+    fn describe_vertex(&self) -> VertexState {
+        VertexState {
+            source: include_str!("debug.vertex.wgsl"),
+            entry_point: "main",
+            buffers: vec![
+                // vertex data
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<DebugVertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: vec![
+                        // anchor
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 0,
+                        },
+                        // offset
+                        wgpu::VertexAttribute {
+                            offset: wgpu::VertexFormat::Float32x2.size(),
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 1,
+                        },
+                        // color
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x2.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 2,
+                        },
+                    ],
+                },
+                // tile metadata
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShaderTileMetadata>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: vec![
+                        // translate
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 4,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 1 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 5,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 6,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: 3 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 7,
+                        },
+                        // zoom_factor
+                        wgpu::VertexAttribute {
+                            offset: 4 * wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 9,
+                        },
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn describe_fragment(&self) -> FragmentState {
+        FragmentState {
+            source: include_str!("debug.fragment.wgsl"),
+            entry_point: "main",
+            targets: vec![wgpu::ColorTargetState {
+                format: self.format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }
+    }
+}
+
+pub struct MarkerShader {
+    pub format: wgpu::TextureFormat,
+    /// Whether the markers are drawn from [`MarkerInstance`]s instead of [`MarkerVertex`]s.
+    pub instanced: bool,
+}
+
+impl Shader for MarkerShader {
+    fn describe_vertex(&self) -> VertexState {
+        if self.instanced {
+            return VertexState {
+                source: include_str!("marker_instanced.vertex.wgsl"),
+                entry_point: "main",
+                buffers: vec![
+                    // instance data
+                    VertexBufferLayout {
+                        array_stride: std::mem::size_of::<MarkerInstance>() as u64,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: vec![
+                            // anchor
+                            wgpu::VertexAttribute {
+                                offset: 0,
+                                format: wgpu::VertexFormat::Float32x2,
+                                shader_location: 0,
+                            },
+                            // top_left
+                            wgpu::VertexAttribute {
+                                offset: wgpu::VertexFormat::Float32x2.size(),
+                                format: wgpu::VertexFormat::Float32x2,
+                                shader_location: 1,
+                            },
+                            // bottom_right
+                            wgpu::VertexAttribute {
+                                offset: 2 * wgpu::VertexFormat::Float32x2.size(),
+                                format: wgpu::VertexFormat::Float32x2,
+                                shader_location: 2,
+                            },
+                            // tex_top_left
+                            wgpu::VertexAttribute {
+                                offset: 3 * wgpu::VertexFormat::Float32x2.size(),
+                                format: wgpu::VertexFormat::Float32x2,
+                                shader_location: 3,
+                            },
+                            // tex_bottom_right
+                            wgpu::VertexAttribute {
+                                offset: 4 * wgpu::VertexFormat::Float32x2.size(),
+                                format: wgpu::VertexFormat::Float32x2,
+                                shader_location: 4,
+                            },
+                            // color
+                            wgpu::VertexAttribute {
+                                offset: 5 * wgpu::VertexFormat::Float32x2.size(),
+                                format: wgpu::VertexFormat::Float32x4,
+                                shader_location: 5,
+                            },
+                            // is_icon
+                            wgpu::VertexAttribute {
+                                offset: 5 * wgpu::VertexFormat::Float32x2.size()
+                                    + wgpu::VertexFormat::Float32x4.size(),
+                                format: wgpu::VertexFormat::Float32,
+                                shader_location: 6,
+                            },
+                        ],
+                    },
+                ],
+            };
+        }
+
+        VertexState {
+            source: include_str!("marker.vertex.wgsl"),
+            entry_point: "main",
+            buffers: vec![
+                // vertex data
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<MarkerVertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: vec![
+                        // position
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 0,
+                        },
+                        // tex_coords
+                        wgpu::VertexAttribute {
+                            offset: wgpu::VertexFormat::Float32x2.size(),
+                            format: wgpu::VertexFormat::Float32x2,
+                            shader_location: 1,
+                        },
+                        // color
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x2.size(),
+                            format: wgpu::VertexFormat::Float32x4,
+                            shader_location: 2,
+                        },
+                        // is_icon
+                        wgpu::VertexAttribute {
+                            offset: 2 * wgpu::VertexFormat::Float32x2.size()
+                                + wgpu::VertexFormat::Float32x4.size(),
+                            format: wgpu::VertexFormat::Float32,
+                            shader_location: 3,
+                        },
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn describe_fragment(&self) -> FragmentState {
+        FragmentState {
+            source: include_str!("marker.fragment.wgsl"),
+            entry_point: "main",
+            targets: vec![wgpu::ColorTargetState {
+                format: self.format,
+                blend: Some(LAYER_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct ShaderCamera {
+    view_proj: Mat4x4f32,   // 64 bytes
+    view_position: Vec4f32, // 16 bytes
+}
+
+impl ShaderCamera {
+    pub fn new(view_proj: Mat4x4f32, view_position: Vec4f32) -> Self {
+        Self {
+            view_position,
+            view_proj,
+        }
+    }
+}
+
+impl Default for ShaderCamera {
+    fn default() -> Self {
+        Self {
+            view_position: [0.0; 4],
+            view_proj: cgmath::Matrix4::identity().into(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct ShaderGlobals {
+    camera: ShaderCamera,
+    /// Zoom level of the camera. Zoom-dependent line widths are interpolated between the integer
+    /// zoom levels around it, see [`ShaderFeatureStyle::line_width`].
+    zoom: f32,
+    /// Bearing of the camera in radians. Labels which stay upright are rotated by it, see
+    /// [`SymbolVertex::upright`].
+    bearing: f32,
+    _padding: [f32; 2],
+}
+
+impl ShaderGlobals {
+    pub fn new(camera_uniform: ShaderCamera, zoom: f32, bearing: f32) -> Self {
+        Self {
+            camera: camera_uniform,
+            zoom,
+            bearing,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct ShaderVertex {
+    pub position: Vec2f32,
+    pub normal: Vec2f32,
+    /// Distance along the line in tile units. Zero for vertices of fills.
+    pub line_distance: f32,
+    /// Edge of the line on which the vertex lies. `1` for the outer edge, `-1` for the inner edge
+    /// of the halves of hollow lines, see [`crate::tessellation::LineStyle::hollow`].
+    pub line_edge: f32,
+    /// Side of the path on which the vertex lies. `-1` for the left and `1` for the right side,
+    /// see [`lyon::tessellation::Side`]. Zero for vertices of fills.
+    pub line_side: f32,
+}
+
+impl ShaderVertex {
+    pub fn new(position: Vec2f32, normal: Vec2f32, line_distance: f32) -> Self {
+        Self {
+            position,
+            normal,
+            line_distance,
+            line_edge: 1.0,
+            line_side: 0.0,
+        }
+    }
+}
+
+impl Default for ShaderVertex {
+    fn default() -> Self {
+        ShaderVertex::new([0.0, 0.0], [0.0, 0.0], 0.0)
+    }
+}
+
+/// Vertex of a glyph or icon quad. All vertices of a label share the same `anchor` in tile coordinates.
+/// The `offset` is relative to the anchor in tile units at the zoom level of the tile.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct SymbolVertex {
+    pub anchor: Vec2f32,
+    pub offset: Vec2f32,
+    pub tex_coords: Vec2f32,
+    /// 1.0 if the texture coordinates refer to the sprite atlas instead of the glyph atlas.
+    pub is_icon: f32,
+    /// 1.0 if the quad stays upright in the viewport instead of rotating with the map. The offset
+    /// is rotated by the bearing of the camera then.
+    pub upright: f32,
+}
+
+impl SymbolVertex {
+    pub fn new(anchor: Vec2f32, offset: Vec2f32, tex_coords: Vec2f32) -> Self {
+        Self {
+            anchor,
+            offset,
+            tex_coords,
+            is_icon: 0.0,
+            upright: 0.0,
+        }
+    }
+
+    pub fn icon(anchor: Vec2f32, offset: Vec2f32, tex_coords: Vec2f32) -> Self {
+        Self {
+            anchor,
+            offset,
+            tex_coords,
+            is_icon: 1.0,
+            upright: 0.0,
+        }
+    }
+}
+
+/// Vertex of a line of the debug overlay. Like for [`SymbolVertex`], the `offset` is relative to the
+/// `anchor` in tile units at the zoom level of the tile, such that the overlay keeps its size on
+/// the screen.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+pub struct DebugVertex {
+    pub anchor: Vec2f32,
+    pub offset: Vec2f32,
+    pub color: Vec4f32,
+}
+
+impl DebugVertex {
+    pub fn new(anchor: Vec2f32, offset: Vec2f32, color: Vec4f32) -> Self {
+        Self {
+            anchor,
+            offset,
+            color,
+        }
+    }
+}
+
+/// Vertex of a marker quad. Markers are projected on the CPU, so the `position` is already in
+/// normalized device coordinates.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+pub struct MarkerVertex {
+    pub position: Vec2f32,
+    pub tex_coords: Vec2f32,
+    pub color: Vec4f32,
+    /// 1.0 if the marker is drawn with an image of the sprite atlas instead of its color.
+    pub is_icon: f32,
+}
+
+impl MarkerVertex {
+    pub fn new(position: Vec2f32, tex_coords: Vec2f32, color: Vec4f32, is_icon: bool) -> Self {
+        Self {
+            position,
+            tex_coords,
+            color,
+            is_icon: if is_icon { 1.0 } else { 0.0 },
+        }
+    }
+}
+
+/// Instance of a marker, which is expanded into the two triangles of its quad by the vertex
+/// shader. The `anchor` is the position of the marker in normalized device coordinates, the
+/// corners of the quad are relative to it.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+pub struct MarkerInstance {
+    pub anchor: Vec2f32,
+    pub top_left: Vec2f32,
+    pub bottom_right: Vec2f32,
+    pub tex_top_left: Vec2f32,
+    pub tex_bottom_right: Vec2f32,
+    pub color: Vec4f32,
+    /// 1.0 if the marker is drawn with an image of the sprite atlas instead of its color.
+    pub is_icon: f32,
+}
+
+/// Vertex of an extruded polygon. The `position` is in tile coordinates, except for the height
+/// which is in tile units at the zoom level of the tile. The `normal` is used for lighting.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct ExtrusionVertex {
+    pub position: Vec3f32,
+    pub normal: Vec3f32,
+}
+
+impl ExtrusionVertex {
+    pub fn new(position: Vec3f32, normal: Vec3f32) -> Self {
+        Self { position, normal }
+    }
+}
+
+/// Converts a width in pixels into half of the width in tile units at `zoom == z`, because lines
+/// are extruded by the width on both sides of the path.
+fn half_width_in_tile_units(width: f32) -> f32 {
+    width * (EXTENT / TILE_SIZE) as f32 / 2.0
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct ShaderFeatureStyle {
+    pub color: Vec4f32,
+    /// Half of the width of the feature's lines in tile units at `zoom == z`, evaluated at the
+    /// integer zoom levels below and above the camera's zoom. Lines are tessellated at a unit
+    /// width and extruded by the width which the vertex shader interpolates between both, such
+    /// that neither data-driven nor zoom-dependent widths require any work on the CPU while
+    /// zooming within a zoom level.
+    pub line_width: Vec2f32,
+}
+
+impl ShaderFeatureStyle {
+    /// Creates the style of a feature whose line width does not change with the zoom.
+    pub fn new(color: Vec4f32, line_width: Option<f32>) -> Self {
+        Self::interpolated(color, line_width, line_width)
+    }
+
+    /// Creates the style of a feature whose line width is `line_width` at the integer zoom level
+    /// below the camera's zoom and `line_width_above` at the one above.
+    pub fn interpolated(
+        color: Vec4f32,
+        line_width: Option<f32>,
+        line_width_above: Option<f32>,
+    ) -> Self {
+        let half_width = |width: Option<f32>| {
+            width
+                .map(half_width_in_tile_units)
+                .unwrap_or(DEFAULT_LINE_WIDTH)
+        };
+        Self {
+            color,
+            line_width: [half_width(line_width), half_width(line_width_above)],
+        }
+    }
+}
+
+/// Style of the vertices of symbol layers, see [`LabelStyle`]. Hidden labels are transparent.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+pub struct ShaderSymbolStyle {
+    pub color: Vec4f32,
+    pub halo_color: Vec4f32,
+    pub halo_width: f32,
+}
+
+impl From<LabelStyle> for ShaderSymbolStyle {
+    fn from(style: LabelStyle) -> Self {
+        Self {
+            color: style.color,
+            halo_color: style.halo_color,
+            halo_width: style.halo_width,
+        }
+    }
+}
+
+/// Number of style layers which are drawn at distinct depths. Layers beyond share the depth of
+/// the last one.
+pub const MAX_LAYER_DEPTHS: u32 = 1 << 16;
+
+/// Returns the depth at which the flat layer at `layer_index` is drawn, in normalized device
+/// coordinates. Layers further up in the style are closer to the camera, such that the depth
+/// test keeps them on top regardless of the order in which tiles and layers are drawn. All
+/// features of a layer share its depth.
+pub fn layer_depth(layer_index: u32) -> f32 {
+    (layer_index.min(MAX_LAYER_DEPTHS - 1) + 1) as f32 / (MAX_LAYER_DEPTHS + 1) as f32
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct ShaderLayerMetadata {
+    /// Depth of the layer, see [`layer_depth`].
+    pub z_index: f32,
+    /// Two dash/gap pairs in units of the line width. All zero for solid lines.
+    pub line_dasharray: Vec4f32,
+    /// Half of the gap between the two lines of a casing in tile units at `zoom == z`. Zero for
+    /// lines without a gap.
+    pub line_gap_width: f32,
+    /// Texture coordinates `[u_min, v_min, u_max, v_max]` of the image within the sprite atlas
+    /// which fills polygons or is repeated along lines, see [`SpritePattern`].
+    pub pattern: Vec4f32,
+    /// Size of a repetition of the pattern in pixels. Zero for layers without a pattern.
+    pub pattern_size: Vec2f32,
+}
 
 impl ShaderLayerMetadata {
-    pub fn new(z_index: f32) -> Self {
-        Self { z_index }
+    pub fn new(
+        layer_index: u32,
+        line_dasharray: Option<[f32; 4]>,
+        line_gap_width: Option<f32>,
+        pattern: Option<SpritePattern>,
+    ) -> Self {
+        Self {
+            z_index: layer_depth(layer_index),
+            line_dasharray: line_dasharray.unwrap_or([0.0; 4]),
+            line_gap_width: line_gap_width
+                .map(|gap_width| half_width_in_tile_units(gap_width.max(0.0)))
+                .unwrap_or(0.0),
+            pattern: pattern.map_or([0.0; 4], |pattern| pattern.tex_coords),
+            pattern_size: pattern.map_or([0.0; 2], |pattern| pattern.size),
+        }
     }
 }
 
@@ -277,13 +1193,137 @@ impl ShaderLayerMetadata {
 pub struct ShaderTileMetadata {
     pub transform: Mat4x4f32,
     pub zoom_factor: f32,
+    /// How far the tile mask extends beyond the extent of the tile in tile units.
+    pub mask_overscan: f32,
 }
 
 impl ShaderTileMetadata {
-    pub fn new(transform: Mat4x4f32, zoom_factor: f32) -> Self {
+    pub fn new(transform: Mat4x4f32, zoom_factor: f32, mask_overscan: f32) -> Self {
         Self {
             transform,
             zoom_factor,
+            mask_overscan,
         }
     }
 }
+
+/// Describes which part of a raster texture is drawn onto a tile. If the raster tile of a parent is
+/// used, then only a part of the texture is stretched over the tile.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct ShaderRasterMetadata {
+    /// Minimum and maximum texture coordinates: `[u_min, v_min, u_max, v_max]`
+    pub tex_rect: Vec4f32,
+    pub opacity: f32,
+}
+
+impl ShaderRasterMetadata {
+    pub fn new(tex_rect: Vec4f32, opacity: f32) -> Self {
+        Self { tex_rect, opacity }
+    }
+}
+
+/// Describes how the elevations of a raster DEM texture are shaded onto a tile. The colors are
+/// given in the color space of the renderer, the illumination direction in radians.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct ShaderHillshadeMetadata {
+    /// Minimum and maximum texture coordinates: `[u_min, v_min, u_max, v_max]`
+    pub tex_rect: Vec4f32,
+    pub shadow: Vec4f32,
+    pub highlight: Vec4f32,
+    pub accent: Vec4f32,
+    /// Latitudes of the top and bottom edge of the tile in degrees.
+    pub latitude_range: Vec2f32,
+    pub exaggeration: f32,
+    pub illumination_direction: f32,
+    /// Zoom level of the DEM tile, which determines the scale of the elevation differences.
+    pub dem_zoom: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        layer_depth, ShaderFeatureStyle, ShaderLayerMetadata, Vec4f32, DEFAULT_LINE_WIDTH,
+        LAYER_BLENDING, MAX_LAYER_DEPTHS,
+    };
+    use crate::coords::{EXTENT, TILE_SIZE};
+
+    /// Blends `src` onto `dst` like the GPU does with [`LAYER_BLENDING`].
+    fn blend(src: Vec4f32, dst: Vec4f32) -> Vec4f32 {
+        let factor = |factor: wgpu::BlendFactor| match factor {
+            wgpu::BlendFactor::One => 1.0,
+            wgpu::BlendFactor::SrcAlpha => src[3],
+            wgpu::BlendFactor::OneMinusSrcAlpha => 1.0 - src[3],
+            factor => unimplemented!("{:?}", factor),
+        };
+        let component = |blend: wgpu::BlendComponent, i: usize| {
+            assert_eq!(blend.operation, wgpu::BlendOperation::Add);
+            src[i] * factor(blend.src_factor) + dst[i] * factor(blend.dst_factor)
+        };
+        [
+            component(LAYER_BLENDING.color, 0),
+            component(LAYER_BLENDING.color, 1),
+            component(LAYER_BLENDING.color, 2),
+            component(LAYER_BLENDING.alpha, 3),
+        ]
+    }
+
+    #[test]
+    fn test_upper_layer_is_closer() {
+        // A road above water wins the depth test no matter which is drawn first
+        let water = ShaderLayerMetadata::new(0, None, None, None);
+        let road = ShaderLayerMetadata::new(1, None, None, None);
+        assert!(road.z_index > water.z_index);
+        assert!(water.z_index > 0.0);
+        assert!(road.z_index <= 1.0);
+
+        // Features of the same layer share the depth
+        assert_eq!(
+            ShaderLayerMetadata::new(1, Some([1.0, 2.0, 0.0, 0.0]), Some(2.0), None).z_index,
+            road.z_index
+        );
+    }
+
+    #[test]
+    fn test_layer_depth_is_clamped() {
+        assert!(layer_depth(MAX_LAYER_DEPTHS - 1) < 1.0);
+        assert_eq!(layer_depth(MAX_LAYER_DEPTHS), layer_depth(u32::MAX));
+        assert!(layer_depth(MAX_LAYER_DEPTHS - 1) > layer_depth(MAX_LAYER_DEPTHS - 2));
+    }
+
+    #[test]
+    fn test_feature_line_width() {
+        let tile_units_per_pixel = (EXTENT / TILE_SIZE) as f32;
+        let color = [1.0; 4];
+        assert_eq!(
+            ShaderFeatureStyle::new(color, Some(4.0)).line_width,
+            [2.0 * tile_units_per_pixel; 2]
+        );
+        assert_eq!(
+            ShaderFeatureStyle::new(color, None).line_width,
+            [DEFAULT_LINE_WIDTH; 2]
+        );
+        assert_eq!(
+            ShaderFeatureStyle::interpolated(color, Some(2.0), Some(4.0)).line_width,
+            [tile_units_per_pixel, 2.0 * tile_units_per_pixel]
+        );
+    }
+
+    #[test]
+    fn test_polygon_on_transparent_background() {
+        let transparent = [0.0; 4];
+        let red = [1.0, 0.0, 0.0, 0.5];
+
+        // Pixels outside of the polygon are not blended and keep the transparent clear color.
+        // Inside of it the color is premultiplied by its alpha.
+        assert_eq!(blend(red, transparent), [0.5, 0.0, 0.0, 0.5]);
+
+        // Translucent polygons accumulate coverage like premultiplied "over" compositing
+        let blue = [0.0, 0.0, 1.0, 0.5];
+        assert_eq!(blend(blue, blend(red, transparent)), [0.25, 0.0, 0.5, 0.75]);
+
+        // Opaque backgrounds stay opaque
+        assert_eq!(blend(red, [1.0; 4]), [1.0, 0.5, 0.5, 1.0]);
+    }
+}
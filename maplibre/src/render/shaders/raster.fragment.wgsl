@@ -0,0 +1,15 @@
+struct Output {
+    [[location(0)]] out_color: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var t_raster: texture_2d<f32>;
+[[group(0), binding(1)]] var s_raster: sampler;
+
+[[stage(fragment)]]
+fn main(
+    [[location(0)]] v_tex_coords: vec2<f32>,
+    [[location(1)]] v_opacity: f32
+) -> Output {
+    let color = textureSample(t_raster, s_raster, v_tex_coords);
+    return Output(vec4<f32>(color.rgb, color.a * v_opacity));
+}
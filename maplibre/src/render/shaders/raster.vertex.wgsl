@@ -0,0 +1,38 @@
+struct VertexOutput {
+    [[location(0)]] v_tex_coords: vec2<f32>;
+    [[location(1)]] v_opacity: f32;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+let EXTENT = 4096.0;
+
+[[stage(vertex)]]
+fn main(
+    [[location(4)]] translate1: vec4<f32>,
+    [[location(5)]] translate2: vec4<f32>,
+    [[location(6)]] translate3: vec4<f32>,
+    [[location(7)]] translate4: vec4<f32>,
+    [[location(10)]] tex_rect: vec4<f32>,
+    [[location(11)]] opacity: f32,
+    [[builtin(vertex_index)]] vertex_idx: u32
+) -> VertexOutput {
+    let z = 0.0;
+
+    var VERTICES: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0)
+    );
+    let corner = VERTICES[vertex_idx];
+
+    let tex_coords = mix(tex_rect.xy, tex_rect.zw, corner);
+
+    var position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(corner * EXTENT, z, 1.0);
+    // Rasters are drawn in the order of the layers without depth testing
+    position.z = 1.0;
+
+    return VertexOutput(tex_coords, opacity, position);
+}
@@ -0,0 +1,36 @@
+struct Output {
+    [[location(0)]] out_color: vec4<f32>;
+};
+
+[[group(1), binding(0)]] var t_glyphs: texture_2d<f32>;
+[[group(1), binding(1)]] var s_glyphs: sampler;
+
+[[group(2), binding(0)]] var t_sprite: texture_2d<f32>;
+[[group(2), binding(1)]] var s_sprite: sampler;
+
+// Matches the SDF_CUTOFF which was used to encode the glyphs
+let EDGE = 0.75;
+let GAMMA = 0.1;
+
+[[stage(fragment)]]
+fn main(
+    [[location(0)]] v_color: vec4<f32>,
+    [[location(1)]] v_tex_coords: vec2<f32>,
+    [[location(2)]] v_is_icon: f32,
+    [[location(3)]] v_halo_color: vec4<f32>,
+    [[location(4)]] v_halo_width: f32
+) -> Output {
+    // Both atlases are sampled because sampling requires uniform control flow
+    let distance = textureSample(t_glyphs, s_glyphs, v_tex_coords).r;
+    let icon = textureSample(t_sprite, s_sprite, v_tex_coords);
+
+    let alpha = v_color.a * smoothStep(EDGE - GAMMA, EDGE + GAMMA, distance);
+    // The halo extends the glyph up to a lower distance and is covered by the text
+    let halo_edge = EDGE - v_halo_width;
+    let halo_alpha = v_halo_color.a * smoothStep(halo_edge - GAMMA, halo_edge + GAMMA, distance) * (1.0 - alpha);
+    let glyph_alpha = alpha + halo_alpha;
+    let glyph_color = (v_color.rgb * alpha + v_halo_color.rgb * halo_alpha) / max(glyph_alpha, 0.0001);
+    let glyph = vec4<f32>(glyph_color, glyph_alpha);
+
+    return Output(mix(glyph, icon * v_color, v_is_icon));
+}
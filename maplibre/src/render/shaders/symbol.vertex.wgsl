@@ -0,0 +1,58 @@
+struct ShaderCamera {
+    view_proj: mat4x4<f32>;
+    view_position: vec4<f32>;
+};
+
+struct ShaderGlobals {
+    camera: ShaderCamera;
+    zoom: f32;
+    bearing: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> globals: ShaderGlobals;
+
+struct VertexOutput {
+    [[location(0)]] v_color: vec4<f32>;
+    [[location(1)]] v_tex_coords: vec2<f32>;
+    [[location(2)]] v_is_icon: f32;
+    [[location(3)]] v_halo_color: vec4<f32>;
+    [[location(4)]] v_halo_width: f32;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    [[location(0)]] anchor: vec2<f32>,
+    [[location(1)]] offset: vec2<f32>,
+    [[location(2)]] tex_coords: vec2<f32>,
+    [[location(3)]] is_icon: f32,
+    [[location(4)]] translate1: vec4<f32>,
+    [[location(5)]] translate2: vec4<f32>,
+    [[location(6)]] translate3: vec4<f32>,
+    [[location(7)]] translate4: vec4<f32>,
+    [[location(8)]] color: vec4<f32>,
+    [[location(9)]] zoom_factor: f32,
+    [[location(10)]] z_index: f32,
+    [[location(11)]] halo_color: vec4<f32>,
+    [[location(12)]] halo_width: f32,
+    [[location(13)]] upright: f32,
+    [[builtin(instance_index)]] instance_idx: u32 // instance_index is used when we have multiple instances of the same "object"
+) -> VertexOutput {
+    let z = 0.0;
+
+    // Rotating the offset by the bearing undoes the rotation of the map, such that the quad stays
+    // upright in the viewport
+    var rotated = offset;
+    if (upright > 0.5) {
+        let s = sin(globals.bearing);
+        let c = cos(globals.bearing);
+        rotated = vec2<f32>(offset.x * c - offset.y * s, offset.x * s + offset.y * c);
+    }
+
+    // Scaling the offset by the zoom factor keeps the size of labels constant on the screen
+    var position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(anchor + rotated * zoom_factor, z, 1.0);
+    // Labels are always drawn on top of the other layers
+    position.z = 1.0;
+
+    return VertexOutput(color, tex_coords, is_icon, halo_color, halo_width, position);
+}
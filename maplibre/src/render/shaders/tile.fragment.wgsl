@@ -2,7 +2,37 @@ struct Output {
     [[location(0)]] out_color: vec4<f32>;
 };
 
+[[group(1), binding(0)]] var t_sprite: texture_2d<f32>;
+[[group(1), binding(1)]] var s_sprite: sampler;
+
 [[stage(fragment)]]
-fn main([[location(0)]] v_color: vec4<f32>) -> Output {
-    return Output(v_color);
+fn main(
+    [[location(0)]] v_color: vec4<f32>,
+    [[location(1)]] v_line_distance: f32,
+    [[location(2)]] v_line_dasharray: vec4<f32>,
+    [[location(3)]] v_pattern_position: vec2<f32>,
+    [[location(4)]] v_pattern: vec4<f32>,
+    [[location(5)]] v_has_pattern: f32
+) -> Output {
+    // The pattern is sampled regardless of whether it is used, because sampling requires uniform
+    // control flow
+    let pattern_coords = mix(v_pattern.xy, v_pattern.zw, fract(v_pattern_position));
+    let pattern_color = textureSample(t_sprite, s_sprite, pattern_coords);
+
+    let pattern_length = v_line_dasharray.x + v_line_dasharray.y + v_line_dasharray.z + v_line_dasharray.w;
+
+    if (pattern_length > 0.0) {
+        let position = v_line_distance - pattern_length * floor(v_line_distance / pattern_length);
+
+        let first_gap_start = v_line_dasharray.x;
+        let second_dash_start = first_gap_start + v_line_dasharray.y;
+        let second_gap_start = second_dash_start + v_line_dasharray.z;
+
+        if ((position >= first_gap_start && position < second_dash_start) || position >= second_gap_start) {
+            discard;
+        }
+    }
+
+    // Patterns replace the color of the fill or line, only its opacity applies
+    return Output(mix(v_color, pattern_color * vec4<f32>(1.0, 1.0, 1.0, v_color.a), v_has_pattern));
 }
@@ -5,39 +5,98 @@ struct ShaderCamera {
 
 struct ShaderGlobals {
     camera: ShaderCamera;
+    zoom: f32;
+    bearing: f32;
 };
 
 [[group(0), binding(0)]] var<uniform> globals: ShaderGlobals;
 
 struct VertexOutput {
     [[location(0)]] v_color: vec4<f32>;
+    [[location(1)]] v_line_distance: f32;
+    [[location(2)]] v_line_dasharray: vec4<f32>;
+    [[location(3)]] v_pattern_position: vec2<f32>;
+    [[location(4)]] v_pattern: vec4<f32>;
+    [[location(5)]] v_has_pattern: f32;
     [[builtin(position)]] position: vec4<f32>;
 };
 
+// Tile units per pixel of a tile at `zoom == z`, i.e. EXTENT / TILE_SIZE
+let TILE_UNITS_PER_PIXEL = 8.0;
+
 [[stage(vertex)]]
 fn main(
     [[location(0)]] position: vec2<f32>,
     [[location(1)]] normal: vec2<f32>,
+    [[location(2)]] line_distance: f32,
+    [[location(3)]] line_edge_side: vec2<f32>,
     [[location(4)]] translate1: vec4<f32>,
     [[location(5)]] translate2: vec4<f32>,
     [[location(6)]] translate3: vec4<f32>,
     [[location(7)]] translate4: vec4<f32>,
     [[location(8)]] color: vec4<f32>,
+    [[location(12)]] line_width: vec2<f32>,
     [[location(9)]] zoom_factor: f32,
     [[location(10)]] z_index: f32,
+    [[location(11)]] line_dasharray: vec4<f32>,
+    [[location(13)]] line_gap_width: f32,
+    [[location(14)]] pattern: vec4<f32>,
+    [[location(15)]] pattern_size: vec2<f32>,
     [[builtin(instance_index)]] instance_idx: u32 // instance_index is used when we have multiple instances of the same "object"
 ) -> VertexOutput {
     let z = 0.0;
-    let width = 3.0 * zoom_factor;
+    let line_edge = line_edge_side.x;
+    let line_side = line_edge_side.y;
+    // Zoom-dependent widths are interpolated linearly between the integer zoom levels around the
+    // zoom of the camera.
+    let width = mix(line_width.x, line_width.y, fract(globals.zoom)) * zoom_factor;
+    let gap_width = line_gap_width * zoom_factor;
+
+    // Lines with a gap are drawn as two lines of the full width on both sides of the gap. The
+    // inner edges of hollow lines are moved towards the outer edge, see `LineStyle::hollow`.
+    var inset = 0.0;
+    var outset = width;
+    if (gap_width > 0.0) {
+        inset = gap_width;
+        outset = gap_width + 2.0 * width;
+    }
+    var extrusion = outset;
+    if (line_edge < 0.0) {
+        extrusion = -inset;
+    }
 
     // The following code moves all "invisible" vertices to (0, 0, 0)
     //if (color.w == 0.0) {
     //   return VertexOutput(color, vec4<f32>(0.0, 0.0, 0.0, 1.0));
     //}
 
-    var position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(position + normal * width, z, 1.0);
-    // FIXME: how to fix z-fighting?
-    position.z = z_index;
+    // Position in pixels of the tile at `zoom == z`
+    let tile_pixel = position / TILE_UNITS_PER_PIXEL;
+
+    var position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(position + normal * extrusion, z, 1.0);
+    // Layers are ordered by their depth, which is independent of the distance to the camera. The
+    // perspective division is undone such that all tiles of a layer end up at the same depth.
+    position.z = z_index * position.w;
+
+    // Fill patterns are repeated relative to the tile, such that they stay fixed to the map while
+    // panning. Tiles which are shown more than one zoom level above their own zoom repeat the
+    // pattern more often, such that it keeps roughly the same size on screen. Patterns whose size
+    // divides the size of tiles continue seamlessly across the edges of tiles.
+    let overzoom = exp2(max(floor(-log2(zoom_factor)), 0.0));
+    let size = max(pattern_size, vec2<f32>(0.0001));
+    var pattern_position = tile_pixel * overzoom / size;
+    if (line_side != 0.0) {
+        // Line patterns are repeated along the line at their size in pixels at the current zoom
+        // and stretched across the line from one side to the other
+        let pixel_distance = line_distance / (TILE_UNITS_PER_PIXEL * zoom_factor);
+        pattern_position = vec2<f32>(pixel_distance / size.x, line_side * 0.5 + 0.5);
+    }
+    var has_pattern = 0.0;
+    if (pattern_size.x > 0.0 && pattern_size.y > 0.0) {
+        has_pattern = 1.0;
+    }
 
-    return VertexOutput(color, position);
+    // The dash pattern is defined in units of the line width. Therefore, it scales with the zoom
+    // in the same way as the width does.
+    return VertexOutput(color, line_distance / width, line_dasharray, pattern_position, pattern, has_pattern, position);
 }
@@ -22,22 +22,27 @@ fn main(
     [[location(5)]] translate2: vec4<f32>,
     [[location(6)]] translate3: vec4<f32>,
     [[location(7)]] translate4: vec4<f32>,
+    [[location(8)]] mask_overscan: f32,
     [[builtin(vertex_index)]] vertex_idx: u32,
     [[builtin(instance_index)]] instance_idx: u32 // instance_index is used when we have multiple instances of the same "object"
 ) -> VertexOutput {
     let z = 0.0;
+    // Masks of adjacent tiles overlap slightly, such that no hairline gaps remain between them.
+    // The geometry of tiles reaches into their buffer, so it joins across the overlap.
+    let lower = -mask_overscan;
+    let upper = EXTENT + mask_overscan;
 
     let target_width = 1.0;
     let target_height = 1.0;
     let debug_color = vec4<f32>(1.0, 0.0, 0.0, 1.0);
 
     var VERTICES: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
-        vec3<f32>(0.0, 0.0, z),
-        vec3<f32>(0.0, EXTENT, z),
-        vec3<f32>(EXTENT, 0.0, z),
-        vec3<f32>(EXTENT, 0.0, z),
-        vec3<f32>(0.0, EXTENT, z),
-        vec3<f32>(EXTENT, EXTENT, z)
+        vec3<f32>(lower, lower, z),
+        vec3<f32>(lower, upper, z),
+        vec3<f32>(upper, lower, z),
+        vec3<f32>(upper, lower, z),
+        vec3<f32>(lower, upper, z),
+        vec3<f32>(upper, upper, z)
     );
     let a_position = VERTICES[vertex_idx];
 
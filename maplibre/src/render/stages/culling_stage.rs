@@ -0,0 +1,29 @@
+//! Uploads the draws of tile layers which are culled on the GPU, see [`crate::render::culling`].
+
+use crate::context::MapContext;
+use crate::render::util::Eventually::Initialized;
+use crate::schedule::Stage;
+use crate::Renderer;
+
+#[derive(Default)]
+pub struct CullingStage;
+
+impl Stage for CullingStage {
+    #[tracing::instrument(name = "CullingStage", skip_all)]
+    fn run(
+        &mut self,
+        MapContext {
+            view_state,
+            renderer: Renderer { queue, state, .. },
+            ..
+        }: &mut MapContext,
+    ) {
+        if let Initialized(Some(gpu_culling)) = &mut state.gpu_culling {
+            gpu_culling.upload(
+                queue,
+                &view_state.view_projection(),
+                &state.tile_phase.items,
+            );
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Prepares the [custom layers](crate::custom_layer) for the next frame.
+
+use crate::context::MapContext;
+use crate::custom_layer::CustomLayerContext;
+use crate::schedule::Stage;
+use crate::Renderer;
+
+#[derive(Default)]
+pub struct CustomLayerStage;
+
+impl Stage for CustomLayerStage {
+    #[tracing::instrument(name = "CustomLayerStage", skip_all)]
+    fn run(
+        &mut self,
+        MapContext {
+            view_state,
+            style,
+            renderer:
+                Renderer {
+                    settings,
+                    device,
+                    queue,
+                    state,
+                    ..
+                },
+            ..
+        }: &mut MapContext,
+    ) {
+        if state.custom_layers.is_empty() {
+            return;
+        }
+
+        let context = CustomLayerContext {
+            device,
+            queue,
+            texture_format: settings.texture_format,
+            depth_stencil_format: wgpu::TextureFormat::Depth24PlusStencil8,
+            sample_count: settings.msaa.samples,
+        };
+        state
+            .custom_layers
+            .prepare(style, &context, view_state.view_projection());
+    }
+}
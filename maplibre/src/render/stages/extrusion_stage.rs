@@ -0,0 +1,263 @@
+//! Builds the 3D geometry of fill-extrusion layers and uploads it to the GPU.
+
+use crate::context::MapContext;
+use crate::coords::{ViewRegion, WorldCoords, WorldTileCoords, Zoom, EXTENT, TILE_SIZE};
+use crate::io::tile_cache::TileCache;
+use crate::io::LayerTessellateMessage;
+use crate::render::settings::ColorSpace;
+use crate::render::shaders::{ExtrusionVertex, ShaderFeatureStyle, ShaderLayerMetadata, Vec4f32};
+use crate::render::util::Eventually::Initialized;
+use crate::schedule::Stage;
+use crate::style::expression::FeatureProperties;
+use crate::style::layer::StyleLayer;
+use crate::tessellation::extrusion::tessellate_extrusion;
+use crate::tessellation::IndexDataType;
+use crate::text::feature::{geometry_paths, TileFeature};
+use crate::{RenderState, Renderer, Style};
+use geozero::mvt::tile;
+use lyon::tessellation::VertexBuffers;
+use std::iter;
+
+/// Color of extrusions if the style of their layer does not define one.
+const DEFAULT_EXTRUSION_COLOR: Vec4f32 = [0.0, 0.0, 0.0, 1.0];
+/// Properties which are used for the height if the layer does not define
+/// `fill-extrusion-height`. The first one is used by OpenMapTiles.
+const HEIGHT_PROPERTIES: [&str; 2] = ["render_height", "height"];
+/// Properties which are used for the base if the layer does not define `fill-extrusion-base`.
+const BASE_PROPERTIES: [&str; 2] = ["render_min_height", "min_height"];
+/// Circumference of the earth at the equator in meters.
+const EARTH_CIRCUMFERENCE: f64 = 40_075_016.686;
+
+#[derive(Default)]
+pub struct ExtrusionStage;
+
+impl Stage for ExtrusionStage {
+    #[tracing::instrument(name = "ExtrusionStage", skip_all)]
+    fn run(
+        &mut self,
+        MapContext {
+            view_state,
+            style,
+            tile_cache,
+            renderer:
+                Renderer {
+                    settings,
+                    queue,
+                    state,
+                    ..
+                },
+            ..
+        }: &mut MapContext,
+    ) {
+        let visible_level = view_state.visible_level();
+
+        let view_proj = view_state.view_projection();
+
+        let view_region = view_state
+            .camera
+            .view_region_bounding_box(&view_proj.invert())
+            .map(|bounding_box| ViewRegion::new(bounding_box, 0, *view_state.zoom, visible_level));
+
+        if let Some(view_region) = &view_region {
+            self.upload_extrusions(
+                state,
+                queue,
+                tile_cache,
+                style,
+                view_region,
+                view_state.zoom(),
+                settings.color_space,
+            );
+        }
+    }
+}
+
+impl ExtrusionStage {
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all)]
+    pub fn upload_extrusions(
+        &self,
+        RenderState {
+            extrusion_buffer_pool,
+            ..
+        }: &mut RenderState,
+        queue: &wgpu::Queue,
+        tile_cache: &TileCache,
+        style: &Style,
+        view_region: &ViewRegion,
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) {
+        if let Initialized(extrusion_buffer_pool) = extrusion_buffer_pool {
+            for world_coords in style.overzoomed_tiles(view_region) {
+                let loaded_layers = extrusion_buffer_pool
+                    .get_loaded_layers_at(&world_coords)
+                    .unwrap_or_default();
+                let style_layers = style
+                    .layers
+                    .iter()
+                    .filter(|layer| {
+                        layer.typ == "fill-extrusion"
+                            && layer.is_visible_at(zoom.value())
+                            && !loaded_layers.contains(layer.id.as_str())
+                    })
+                    .collect::<Vec<_>>();
+
+                let available_layers =
+                    if let Some(layers) = tile_cache.iter_tessellated_layers_at(&world_coords) {
+                        layers.collect::<Vec<_>>()
+                    } else {
+                        continue;
+                    };
+
+                for style_layer in style_layers {
+                    if let Some(LayerTessellateMessage::TessellatedLayer {
+                        coords,
+                        layer_data,
+                        ..
+                    }) = available_layers
+                        .iter()
+                        .find(|layer| style.is_layer_data(style_layer, layer))
+                    {
+                        let (buffer, feature_metadata) =
+                            Self::extrude_layer(style_layer, layer_data, coords, zoom, color_space);
+
+                        tracing::trace!("Allocating extrusions at {}", &coords);
+                        extrusion_buffer_pool.allocate_layer_geometry(
+                            queue,
+                            *coords,
+                            style_layer.clone(),
+                            &buffer.into(),
+                            ShaderLayerMetadata::new(style_layer.index, None, None, None),
+                            &feature_metadata,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Extrudes the polygons within a layer. Returns the geometry and the color of each vertex.
+    fn extrude_layer(
+        style_layer: &StyleLayer,
+        layer_data: &tile::Layer,
+        coords: &WorldTileCoords,
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) -> (
+        VertexBuffers<ExtrusionVertex, IndexDataType>,
+        Vec<ShaderFeatureStyle>,
+    ) {
+        let mut buffer = VertexBuffers::new();
+        let mut feature_metadata = Vec::new();
+
+        let paint = style_layer.paint.as_ref();
+        let extent = layer_data.extent.unwrap_or(EXTENT as u32) as f32;
+        let meters_to_tile = meters_to_tile_units(coords, extent);
+
+        for feature in layer_data
+            .features
+            .iter()
+            .filter(|feature| feature.r#type == Some(tile::GeomType::Polygon as i32))
+        {
+            let properties = TileFeature {
+                layer: layer_data,
+                feature,
+            };
+            if !style_layer.matches(&properties) {
+                continue;
+            }
+
+            let (height, base) = paint
+                .map(|paint| paint.get_extrusion(zoom.value(), Some(&properties)))
+                .unwrap_or((None, None));
+            let height = height
+                .or_else(|| number_property(&properties, &HEIGHT_PROPERTIES))
+                .unwrap_or(0.0);
+            let base = base
+                .or_else(|| number_property(&properties, &BASE_PROPERTIES))
+                .unwrap_or(0.0);
+
+            let mut color: Vec4f32 = paint
+                .and_then(|paint| color_space.paint_color(paint, zoom.value(), Some(&properties)))
+                .unwrap_or(DEFAULT_EXTRUSION_COLOR);
+
+            // The opacity is folded into the alpha channel of the color
+            if let Some(opacity) =
+                paint.and_then(|paint| paint.get_opacity(zoom.value(), Some(&properties)))
+            {
+                color[3] *= opacity;
+            }
+
+            let first_vertex = buffer.vertices.len();
+            let first_index = buffer.indices.len();
+            if let Err(e) = tessellate_extrusion(
+                &geometry_paths(feature),
+                base * meters_to_tile,
+                height * meters_to_tile,
+                &mut buffer,
+            ) {
+                tracing::warn!("Failed to extrude feature in {}: {:?}", style_layer.id, e);
+                // Drop the walls which have been built before the roof failed
+                buffer.vertices.truncate(first_vertex);
+                buffer.indices.truncate(first_index);
+                continue;
+            }
+
+            feature_metadata.extend(
+                iter::repeat(ShaderFeatureStyle::new(color, None))
+                    .take(buffer.vertices.len() - first_vertex),
+            );
+        }
+
+        (buffer, feature_metadata)
+    }
+}
+
+/// Returns the first of the properties `keys` of a feature which is a number.
+fn number_property(feature: &dyn FeatureProperties, keys: &[&str]) -> Option<f32> {
+    keys.iter().find_map(|key| {
+        feature
+            .get_property(key)
+            .and_then(|value| value.as_number())
+            .map(|value| value as f32)
+    })
+}
+
+/// Returns how many units of a tile with the given `extent` make up one meter at the center of the
+/// tile. Distances in mercator projection are stretched towards the poles.
+fn meters_to_tile_units(coords: &WorldTileCoords, extent: f32) -> f32 {
+    let center = WorldCoords {
+        x: (coords.x as f64 + 0.5) * TILE_SIZE,
+        y: (coords.y as f64 + 0.5) * TILE_SIZE,
+    };
+    let latitude = center.into_lat_lon(Zoom::new(coords.z as f64)).latitude;
+
+    let tiles = 2.0_f64.powi(coords.z as i32);
+    let meters_per_tile = EARTH_CIRCUMFERENCE * latitude.to_radians().cos() / tiles;
+
+    (extent as f64 / meters_per_tile) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::meters_to_tile_units;
+    use crate::coords::{WorldTileCoords, EXTENT};
+
+    #[test]
+    fn test_meters_to_tile_units() {
+        let extent = EXTENT as f32;
+
+        // The center of the tile is at a latitude of about 41°
+        let north = meters_to_tile_units(&WorldTileCoords { x: 0, y: 1, z: 2 }, extent);
+        assert!((north - 0.000_541_544).abs() < 1e-9);
+
+        // Tiles which mirror each other at the equator are stretched in the same way
+        let south = meters_to_tile_units(&WorldTileCoords { x: 3, y: 2, z: 2 }, extent);
+        assert!((north - south).abs() < 1e-9);
+
+        // Tiles closer to the poles are stretched more
+        let polar = meters_to_tile_units(&WorldTileCoords { x: 0, y: 0, z: 2 }, extent);
+        assert!(polar > north * 2.0);
+    }
+}
@@ -6,12 +6,16 @@
 // 3. "sub graph" modules should be nested beneath their parent graph module
 
 use crate::context::MapContext;
+use crate::error::{Error, RenderError};
+use crate::render::culling_node::CullingNode;
 use crate::render::graph::{EmptyNode, RenderGraph};
 use crate::render::graph_runner::RenderGraphRunner;
+use crate::render::graph_runner::RenderGraphRunnerError;
 use crate::render::main_pass::{MainPassDriverNode, MainPassNode};
+use crate::render::resource::{BufferedTextureHead, Head};
 use crate::render::util::Eventually::Initialized;
 use crate::schedule::Stage;
-use crate::Renderer;
+use crate::{RenderState, Renderer, WindowSize};
 use log::error;
 
 pub mod node {
@@ -23,6 +27,7 @@ pub mod draw_graph {
     pub const NAME: &str = "draw";
     pub mod input {}
     pub mod node {
+        pub const CULLING: &str = "culling";
         pub const MAIN_PASS: &str = "main_pass";
     }
 }
@@ -38,10 +43,15 @@ impl Default for GraphRunnerStage {
         let mut graph = RenderGraph::default();
 
         let mut draw_graph = RenderGraph::default();
+        draw_graph.add_node(draw_graph::node::CULLING, CullingNode::new());
         draw_graph.add_node(draw_graph::node::MAIN_PASS, pass_node);
         let input_node_id = draw_graph.set_input(vec![]);
         draw_graph
-            .add_node_edge(input_node_id, draw_graph::node::MAIN_PASS)
+            .add_node_edge(input_node_id, draw_graph::node::CULLING)
+            .unwrap();
+        // The indirect draws of the main pass are written by the culling pass
+        draw_graph
+            .add_node_edge(draw_graph::node::CULLING, draw_graph::node::MAIN_PASS)
             .unwrap();
         graph.add_sub_graph(draw_graph::NAME, draw_graph);
 
@@ -54,6 +64,35 @@ impl Default for GraphRunnerStage {
     }
 }
 
+impl GraphRunnerStage {
+    /// Renders the frame once more into a texture from which it can be read back. The texture of
+    /// a window surface can not be copied on every platform.
+    fn capture_frame(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        state: &mut RenderState,
+        size: WindowSize,
+        format: wgpu::TextureFormat,
+    ) -> Result<BufferedTextureHead, RenderGraphRunnerError> {
+        let head = BufferedTextureHead::new(device, size, format, "Capture texture");
+
+        let surface_target =
+            std::mem::replace(&mut state.render_target, Initialized(head.create_view()));
+        let result = RenderGraphRunner::run(&self.graph, device, queue, state);
+        state.render_target = surface_target;
+        result?;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Capture copy encoder"),
+        });
+        head.copy_to_buffer(&mut encoder);
+        queue.submit(Some(encoder.finish()));
+
+        Ok(head)
+    }
+}
+
 impl Stage for GraphRunnerStage {
     fn run(
         &mut self,
@@ -63,6 +102,8 @@ impl Stage for GraphRunnerStage {
                     device,
                     queue,
                     state,
+                    surface,
+                    settings,
                     ..
                 },
             ..
@@ -86,6 +127,30 @@ impl Stage for GraphRunnerStage {
             panic!("Error running render graph: {:?}", e);
         }
 
+        if let Head::Headless(head) = surface.head() {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Headless copy encoder"),
+            });
+            head.copy_to_buffer(&mut encoder);
+            queue.submit(Some(encoder.finish()));
+        }
+
+        // Captures wait for a frame of which the surface texture could be acquired
+        if let Initialized(_) = state.render_target {
+            for request in std::mem::take(&mut state.capture_requests) {
+                match self.capture_frame(
+                    device,
+                    queue,
+                    state,
+                    surface.size(),
+                    settings.texture_format,
+                ) {
+                    Ok(head) => request.fulfil(device, head),
+                    Err(e) => request.fail(Error::Render(RenderError::Readback(e.to_string()))),
+                }
+            }
+        }
+
         {
             let _span = tracing::info_span!("present_frames").entered();
 
@@ -2,20 +2,31 @@
 
 use crate::context::MapContext;
 use crate::schedule::{MultiStage, Schedule, Stage, StageLabel};
+use custom_layer_stage::CustomLayerStage;
+use extrusion_stage::ExtrusionStage;
 use graph_runner_stage::GraphRunnerStage;
 use resource_stage::ResourceStage;
+use symbol_stage::SymbolStage;
 use upload_stage::UploadStage;
 
+mod culling_stage;
+mod custom_layer_stage;
+mod extrusion_stage;
 mod graph_runner_stage;
 mod phase_sort_stage;
+mod placement_stage;
 mod queue_stage;
 mod resource_stage;
+mod symbol_stage;
 mod upload_stage;
 
 use crate::multi_stage;
+use crate::render::stages::culling_stage::CullingStage;
 use crate::render::stages::phase_sort_stage::PhaseSortStage;
+use crate::render::stages::placement_stage::PlacementStage;
 use crate::render::stages::queue_stage::QueueStage;
 pub use graph_runner_stage::{draw_graph, node};
+pub(crate) use resource_stage::TILE_VIEW_SIZE;
 
 /// The labels of the default App rendering stages.
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
@@ -29,9 +40,15 @@ pub enum RenderStageLabel {
     /// [`Render`](RenderStageLabel::Render) stage.
     Queue,
 
+    /// Places labels of symbol layers which are queued and hides labels which collide with others.
+    Placement,
+
     /// Sort the [`RenderPhases`](crate::render_phase::RenderPhase) here.
     PhaseSort,
 
+    /// Uploads the sorted items which are culled on the GPU, see [`crate::render::culling`].
+    Cull,
+
     /// Actual rendering happens here.
     /// In most cases, only the render backend should insert resources here.
     Render,
@@ -46,11 +63,20 @@ impl StageLabel for RenderStageLabel {
     }
 }
 
-multi_stage!(PrepareStage, upload: UploadStage, resource: ResourceStage);
+multi_stage!(
+    PrepareStage,
+    upload: UploadStage,
+    symbol: SymbolStage,
+    extrusion: ExtrusionStage,
+    resource: ResourceStage,
+    custom_layer: CustomLayerStage
+);
 
 pub fn register_render_stages(schedule: &mut Schedule) {
     schedule.add_stage(RenderStageLabel::Prepare, PrepareStage::default());
     schedule.add_stage(RenderStageLabel::Queue, QueueStage::default());
+    schedule.add_stage(RenderStageLabel::Placement, PlacementStage::default());
     schedule.add_stage(RenderStageLabel::PhaseSort, PhaseSortStage::default());
+    schedule.add_stage(RenderStageLabel::Cull, CullingStage::default());
     schedule.add_stage(RenderStageLabel::Render, GraphRunnerStage::default());
 }
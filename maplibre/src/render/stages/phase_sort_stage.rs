@@ -31,5 +31,13 @@ impl Stage for PhaseSortStage {
         mask_phase.sort();
         let file_phase = &mut state.tile_phase;
         file_phase.sort();
+        let raster_phase = &mut state.raster_phase;
+        raster_phase.sort();
+        let hillshade_phase = &mut state.hillshade_phase;
+        hillshade_phase.sort();
+        let symbol_phase = &mut state.symbol_phase;
+        symbol_phase.sort();
+        let extrusion_phase = &mut state.extrusion_phase;
+        extrusion_phase.sort();
     }
 }
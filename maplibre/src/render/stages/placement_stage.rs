@@ -0,0 +1,218 @@
+//! Places the labels of symbol layers and hides the labels which collide with others.
+
+use crate::context::MapContext;
+use crate::coords::{WorldTileCoords, Zoom};
+use crate::render::camera::{Camera, ViewProjection};
+use crate::render::shaders::ShaderSymbolStyle;
+use crate::render::tile_view_pattern::TileShape;
+use crate::render::util::Eventually::Initialized;
+use crate::schedule::Stage;
+use crate::text::placement::{CollisionBox, CollisionGrid, SymbolLabel};
+use crate::util::SignificantlyDifferent;
+use crate::{RenderState, Renderer};
+use cgmath::Vector4;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// Distance the camera needs to move until labels are placed again.
+const CAMERA_THRESHOLD: f64 = 0.5;
+/// Relative change of the scale until labels are placed again.
+const ZOOM_THRESHOLD: f64 = 0.05;
+
+#[derive(Default)]
+pub struct PlacementStage {
+    grid: CollisionGrid,
+    last_camera: Option<Camera>,
+    last_zoom: Option<Zoom>,
+    /// Symbol layers which were considered during the last placement.
+    placed_entries: HashSet<(WorldTileCoords, String)>,
+}
+
+impl Stage for PlacementStage {
+    #[tracing::instrument(name = "PlacementStage", skip_all)]
+    fn run(
+        &mut self,
+        MapContext {
+            view_state,
+            renderer: Renderer { queue, state, .. },
+            ..
+        }: &mut MapContext,
+    ) {
+        let entries = state
+            .symbol_phase
+            .items
+            .iter()
+            .map(|(entry, _)| (entry.coords, entry.style_layer.id.clone()))
+            .collect::<HashSet<_>>();
+
+        let camera_moved = match (&self.last_camera, &self.last_zoom) {
+            (Some(last_camera), Some(last_zoom)) => {
+                SignificantlyDifferent::ne(last_camera, &*view_state.camera, CAMERA_THRESHOLD)
+                    || (last_zoom.scale_delta(&view_state.zoom()) - 1.0).abs() > ZOOM_THRESHOLD
+            }
+            _ => true,
+        };
+
+        if !camera_moved && entries == self.placed_entries {
+            return;
+        }
+
+        self.place_labels(
+            state,
+            queue,
+            &view_state.camera,
+            &view_state.view_projection(),
+        );
+
+        self.last_camera = Some((*view_state.camera).clone());
+        self.last_zoom = Some(view_state.zoom());
+        self.placed_entries = entries;
+    }
+}
+
+impl PlacementStage {
+    #[tracing::instrument(skip_all)]
+    fn place_labels(
+        &mut self,
+        RenderState {
+            symbol_buffer_pool,
+            symbol_labels,
+            symbol_phase,
+            ..
+        }: &mut RenderState,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        view_proj: &ViewProjection,
+    ) {
+        let symbol_buffer_pool = if let Initialized(symbol_buffer_pool) = symbol_buffer_pool {
+            symbol_buffer_pool
+        } else {
+            return;
+        };
+
+        // Forget about labels which have been evicted from the buffer pool
+        let index = symbol_buffer_pool.index();
+        symbol_labels.retain(|(coords, id), _| {
+            index.get_layers(coords).map_or(false, |entries| {
+                entries.iter().any(|entry| &entry.style_layer.id == id)
+            })
+        });
+
+        let items = symbol_phase
+            .items
+            .iter()
+            .filter_map(|(entry, shape)| {
+                symbol_labels
+                    .get(&(entry.coords, entry.style_layer.id.clone()))
+                    .map(|labels| (entry, shape, labels))
+            })
+            .collect::<Vec<_>>();
+
+        // Labels of upper layers are placed first. Within a layer the sort key of the features
+        // decides, also across tiles.
+        let mut labels = items
+            .iter()
+            .enumerate()
+            .flat_map(|(item, (entry, _, layer_labels))| {
+                layer_labels
+                    .labels
+                    .iter()
+                    .map(move |label| (item, entry.style_layer.index, label))
+            })
+            .collect::<Vec<_>>();
+        labels.sort_by(|(_, a_index, a_label), (_, b_index, b_label)| {
+            b_index.cmp(a_index).then(
+                a_label
+                    .sort_key
+                    .partial_cmp(&b_label.sort_key)
+                    .unwrap_or(Ordering::Equal),
+            )
+        });
+
+        self.grid.clear();
+
+        let mut feature_metadata = items
+            .iter()
+            .map(|(_, _, layer_labels)| {
+                vec![ShaderSymbolStyle::default(); layer_labels.vertex_count]
+            })
+            .collect::<Vec<_>>();
+
+        for (item, _, label) in labels {
+            let (_, shape, layer_labels) = items[item];
+            let visible = match Self::project_label(label, shape, camera, view_proj) {
+                Some(collision_box) => {
+                    if layer_labels.allow_overlap || !self.grid.collides(&collision_box) {
+                        self.grid.insert(collision_box);
+                        true
+                    } else {
+                        false
+                    }
+                }
+                // Labels behind the camera can not collide with anything
+                None => true,
+            };
+
+            if visible {
+                for (vertices, label_style) in label.styled_vertices() {
+                    feature_metadata[item][vertices].fill(label_style.into());
+                }
+            }
+        }
+
+        for ((entry, _, _), feature_metadata) in items.iter().zip(feature_metadata) {
+            symbol_buffer_pool.update_feature_metadata(queue, entry, &feature_metadata);
+        }
+    }
+
+    /// Projects the bounding box of a label into screen-space pixels. This matches the
+    /// transformation within the symbol vertex shader, which rotates upright labels by the bearing.
+    /// The collision box encloses the projected corners.
+    fn project_label(
+        label: &SymbolLabel,
+        shape: &TileShape,
+        camera: &Camera,
+        view_proj: &ViewProjection,
+    ) -> Option<CollisionBox> {
+        let (sin, cos) = if label.upright {
+            camera.bearing.0.sin_cos()
+        } else {
+            (0.0, 1.0)
+        };
+        let project = |[x, y]: [f32; 2]| {
+            let offset = [
+                x as f64 * cos - y as f64 * sin,
+                x as f64 * sin + y as f64 * cos,
+            ];
+            let x = label.anchor[0] as f64 + offset[0] * shape.zoom_factor;
+            let y = label.anchor[1] as f64 + offset[1] * shape.zoom_factor;
+            let clip = view_proj.project(shape.transform * Vector4::new(x, y, 0.0, 1.0));
+
+            if clip.w <= 0.0 {
+                return None;
+            }
+
+            Some([
+                ((clip.x / clip.w + 1.0) / 2.0 * camera.width) as f32,
+                ((1.0 - clip.y / clip.w) / 2.0 * camera.height) as f32,
+            ])
+        };
+
+        let corners = [
+            project(label.min)?,
+            project([label.max[0], label.min[1]])?,
+            project(label.max)?,
+            project([label.min[0], label.max[1]])?,
+        ];
+        let (min, max) = corners.iter().fold(
+            ([f32::MAX, f32::MAX], [f32::MIN, f32::MIN]),
+            |(min, max), corner| {
+                (
+                    [min[0].min(corner[0]), min[1].min(corner[1])],
+                    [max[0].max(corner[0]), max[1].max(corner[1])],
+                )
+            },
+        );
+        Some(CollisionBox::new(min, max))
+    }
+}
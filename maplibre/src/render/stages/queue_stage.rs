@@ -5,6 +5,7 @@ use crate::coords::{ViewRegion, Zoom};
 use crate::io::tile_cache::TileCache;
 use crate::io::LayerTessellateMessage;
 use crate::render::camera::ViewProjection;
+use crate::render::culling::is_tile_visible;
 use crate::render::resource::IndexEntry;
 use crate::render::shaders::{
     ShaderCamera, ShaderFeatureStyle, ShaderGlobals, ShaderLayerMetadata, Vec4f32,
@@ -13,6 +14,7 @@ use crate::render::tile_view_pattern::TileInView;
 use crate::render::util::Eventually::Initialized;
 use crate::schedule::Stage;
 use crate::{RenderState, Renderer, Style};
+use std::collections::HashSet;
 use std::iter;
 
 #[derive(Default)]
@@ -23,17 +25,62 @@ impl Stage for QueueStage {
     fn run(
         &mut self,
         MapContext {
-            renderer: Renderer { state, .. },
+            view_state,
+            renderer: Renderer {
+                settings, state, ..
+            },
+            style,
             ..
         }: &mut MapContext,
     ) {
+        // Layers which are drawn at other zoom levels can still be uploaded for fallback tiles
+        let hidden_layers = style.hidden_layers_at(view_state.zoom().value());
+
         state.mask_phase.items.clear();
         state.tile_phase.items.clear();
+        state.symbol_phase.items.clear();
+        state.raster_phase.items.clear();
+        state.hillshade_phase.items.clear();
+        state.extrusion_phase.items.clear();
+        state.debug_phase.items.clear();
+        state.marker_phase.items.clear();
+
+        if let Initialized(raster_tiles) = &state.raster_tiles {
+            for raster in raster_tiles.iter() {
+                state.raster_phase.add(raster.clone());
+            }
+        }
+
+        if let Initialized(hillshade_tiles) = &state.hillshade_tiles {
+            for hillshade in hillshade_tiles.iter() {
+                state.hillshade_phase.add(hillshade.clone());
+            }
+        }
+
+        if settings.show_tile_boundaries {
+            if let Initialized(tile_boundaries) = &state.tile_boundaries {
+                for boundary in tile_boundaries.iter() {
+                    state.debug_phase.add(boundary.clone());
+                }
+            }
+        }
+
+        if let Initialized(marker_overlay) = &state.marker_overlay {
+            if let Some(markers_in_view) = marker_overlay.in_view() {
+                state.marker_phase.add(markers_in_view.clone());
+            }
+        }
 
         if let (Initialized(tile_view_pattern), Initialized(buffer_pool)) =
             (&state.tile_view_pattern, &state.buffer_pool)
         {
             let index = buffer_pool.index();
+            // Without culling on the GPU, tiles outside of the frustum are skipped right away
+            let cull_on_cpu = !matches!(state.gpu_culling, Initialized(Some(_)));
+            let view_proj = view_state.view_projection();
+            // Overzoomed tiles share the ancestor they fall back to. The masks of all of them use
+            // the stencil reference of the ancestor, so its layers are drawn only once.
+            let mut queued_fallbacks = HashSet::new();
 
             for tile_in_view in tile_view_pattern.iter() {
                 let TileInView { shape, fallback } = &tile_in_view;
@@ -42,14 +89,21 @@ impl Stage for QueueStage {
 
                 let shape_to_render = fallback.as_ref().unwrap_or(shape);
 
+                if cull_on_cpu && !is_tile_visible(&view_proj, &shape.transform) {
+                    tracing::trace!("Culling tile at {coords}");
+                    continue;
+                }
+
                 // Draw mask
                 state.mask_phase.add(tile_in_view.clone());
 
-                if let Some(entries) = index.get_layers(&shape_to_render.coords) {
-                    let mut layers_to_render: Vec<&IndexEntry> = Vec::from_iter(entries);
-                    layers_to_render.sort_by_key(|entry| entry.style_layer.index);
+                if fallback.is_some() && !queued_fallbacks.insert(shape_to_render.unwrapped_coords)
+                {
+                    continue;
+                }
 
-                    for entry in layers_to_render {
+                if let Some(entries) = index.get_layers(&shape_to_render.coords) {
+                    for entry in layers_to_render(entries, &hidden_layers) {
                         // Draw tile
                         state
                             .tile_phase
@@ -58,7 +112,105 @@ impl Stage for QueueStage {
                 } else {
                     tracing::trace!("No layers found at {}", &shape_to_render.coords);
                 }
+
+                if let Initialized(symbol_buffer_pool) = &state.symbol_buffer_pool {
+                    if let Some(entries) = symbol_buffer_pool
+                        .index()
+                        .get_layers(&shape_to_render.coords)
+                    {
+                        for entry in layers_to_render(entries, &hidden_layers) {
+                            // Draw symbols
+                            state
+                                .symbol_phase
+                                .add((entry.clone(), shape_to_render.clone()))
+                        }
+                    }
+                }
+
+                if let Initialized(extrusion_buffer_pool) = &state.extrusion_buffer_pool {
+                    if let Some(entries) = extrusion_buffer_pool
+                        .index()
+                        .get_layers(&shape_to_render.coords)
+                    {
+                        for entry in layers_to_render(entries, &hidden_layers) {
+                            // Draw extrusions
+                            state
+                                .extrusion_phase
+                                .add((entry.clone(), shape_to_render.clone()))
+                        }
+                    }
+                }
             }
         }
     }
 }
+
+/// Returns the entries of the layers which are not hidden in the order in which they are drawn.
+fn layers_to_render<'a>(
+    entries: impl IntoIterator<Item = &'a IndexEntry>,
+    hidden_layers: &HashSet<&str>,
+) -> Vec<&'a IndexEntry> {
+    let mut layers_to_render: Vec<&IndexEntry> = entries
+        .into_iter()
+        .filter(|entry| !hidden_layers.contains(entry.style_layer.id.as_str()))
+        .collect();
+    layers_to_render.sort_by_key(|entry| entry.style_layer.index);
+    layers_to_render
+}
+
+#[cfg(test)]
+mod tests {
+    use super::layers_to_render;
+    use crate::render::resource::{BackingBufferDescriptor, BufferPool, Queue};
+    use crate::style::Style;
+    use lyon::tessellation::VertexBuffers;
+
+    struct TestBuffer;
+    struct TestQueue;
+
+    impl Queue<TestBuffer> for TestQueue {
+        fn write_buffer(&self, _buffer: &TestBuffer, _offset: wgpu::BufferAddress, _data: &[u8]) {}
+    }
+
+    #[test]
+    fn test_hidden_layer_is_not_drawn() {
+        let mut pool: BufferPool<TestQueue, TestBuffer, u32, u32, u32, u32> = BufferPool::new(
+            BackingBufferDescriptor::new(TestBuffer, 128),
+            BackingBufferDescriptor::new(TestBuffer, 128),
+            BackingBufferDescriptor::new(TestBuffer, 128),
+            BackingBufferDescriptor::new(TestBuffer, 128),
+        );
+
+        let mut style = Style::default();
+        let mut geometry = VertexBuffers::new();
+        geometry.vertices.push(0u32);
+        geometry.indices.push(0u32);
+        let geometry = geometry.into();
+
+        for style_layer in style.layers.iter().take(2) {
+            pool.allocate_layer_geometry(
+                &TestQueue,
+                (0, 0, 0).into(),
+                style_layer.clone(),
+                &geometry,
+                0,
+                &[],
+            );
+        }
+
+        let drawn = |style: &Style| {
+            layers_to_render(
+                pool.index().get_layers(&(0, 0, 0).into()).unwrap(),
+                &style.hidden_layers(),
+            )
+            .iter()
+            .map(|entry| entry.style_layer.id.clone())
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(drawn(&style), vec!["park", "landuse"]);
+
+        style.set_layer_visibility("park", false);
+        assert_eq!(drawn(&style), vec!["landuse"]);
+    }
+}
@@ -1,35 +1,63 @@
 //! Prepares GPU-owned resources by initializing them if they are uninitialized or out-of-date.
 
 use crate::context::MapContext;
+use crate::io::tile_cache::TileCache;
+use crate::io::{RasterFormat, RasterTileMessage};
 use crate::platform::MIN_BUFFER_SIZE;
+use crate::render::culling::GpuCulling;
+use crate::render::debug_pipeline::DebugPipeline;
+use crate::render::extrusion_pipeline::ExtrusionPipeline;
+use crate::render::hillshade_pipeline::HillshadePipeline;
+use crate::render::hillshade_tiles::{HillshadeStyle, HillshadeTiles};
+use crate::render::marker_overlay::MarkerOverlay;
+use crate::render::marker_pipeline::MarkerPipeline;
+use crate::render::raster_pipeline::RasterPipeline;
+use crate::render::raster_tiles::{tex_rect, RasterTiles};
 use crate::render::resource::Texture;
 use crate::render::resource::{BackingBufferDescriptor, BufferPool};
-use crate::render::resource::{Globals, RenderPipeline};
+use crate::render::resource::{Globals, GlyphAtlas, RenderPipeline, SpriteAtlas};
+use crate::render::settings::ColorSpace;
 use crate::render::shaders;
-use crate::render::shaders::{Shader, ShaderGlobals, ShaderTileMetadata};
+use crate::render::shaders::{Shader, ShaderRasterMetadata, ShaderTileMetadata};
+use crate::render::symbol_pipeline::SymbolPipeline;
+use crate::render::tile_boundaries::TileBoundaries;
 use crate::render::tile_pipeline::TilePipeline;
-use crate::render::tile_view_pattern::TileViewPattern;
+use crate::render::tile_view_pattern::{TileInView, TileViewPattern};
+use crate::render::util::Eventually::{Initialized, Uninitialized};
 use crate::schedule::Stage;
-use crate::Renderer;
+use crate::style::layer::{HillshadePaint, LayerPaint};
+use crate::text;
+use crate::{Renderer, Style};
 use std::cmp;
 use std::mem::size_of;
 
-pub const TILE_VIEW_SIZE: wgpu::BufferAddress = 32;
+/// Maximum amount of tile shapes in view, including fallbacks. When zoomed out on wide windows,
+/// the tiles of multiple copies of the world are in view.
+pub const TILE_VIEW_SIZE: wgpu::BufferAddress = 128;
 
 #[derive(Default)]
-pub struct ResourceStage;
+pub struct ResourceStage {
+    /// Whether it was logged that hillshade layers are not supported by the device.
+    hillshade_warned: bool,
+}
 
 impl Stage for ResourceStage {
     #[tracing::instrument(name = "ResourceStage", skip_all)]
     fn run(
         &mut self,
         MapContext {
+            view_state,
+            style,
+            tile_cache,
+            markers,
             renderer:
                 Renderer {
                     settings,
                     device,
+                    queue,
                     surface,
                     state,
+                    capabilities,
                     ..
                 },
             ..
@@ -37,11 +65,26 @@ impl Stage for ResourceStage {
     ) {
         let size = surface.size();
 
+        // Evaluated every frame such that changes of the style are picked up immediately
+        state.clear_color = Self::background_color(
+            style,
+            view_state.zoom().value(),
+            settings.color_space,
+            settings.clear_color,
+        );
+
         surface.reconfigure(device);
 
-        state
-            .render_target
-            .initialize(|| surface.create_view(device));
+        if let Uninitialized = state.render_target {
+            // Without a frame nothing is drawn, and MapSchedule decides how to recover
+            match surface.create_view(device) {
+                Ok(render_target) => state.render_target = Initialized(render_target),
+                Err(e) => {
+                    tracing::warn!("Failed to acquire the frame of the surface: {}", e);
+                    state.surface_error = Some(e);
+                }
+            }
+        }
 
         state.depth_texture.reinitialize(
             || {
@@ -75,10 +118,45 @@ impl Stage for ResourceStage {
             &(size.width(), size.height()),
         );
 
+        state.buffer_pool.initialize(|| {
+            BufferPool::from_device(device).with_budget(settings.buffer_pool_budget)
+        });
+
         state
-            .buffer_pool
+            .symbol_buffer_pool
             .initialize(|| BufferPool::from_device(device));
 
+        state
+            .extrusion_buffer_pool
+            .initialize(|| BufferPool::from_device(device));
+
+        state.glyph_atlas.initialize(|| {
+            let atlas = match settings
+                .font
+                .as_ref()
+                .map(|font| text::GlyphAtlas::from_font(font))
+            {
+                Some(Ok(atlas)) => atlas,
+                Some(Err(e)) => {
+                    log::error!("Failed to create glyph atlas: {:?}", e);
+                    text::GlyphAtlas::empty()
+                }
+                None => text::GlyphAtlas::empty(),
+            };
+            GlyphAtlas::from_device(device, queue, atlas)
+        });
+
+        // The sprite sheet changes with the style
+        let sprite_sheet = style.sprite_sheet.as_ref();
+        if !matches!(&state.sprite_atlas, Initialized(atlas) if atlas.is_uploaded(sprite_sheet)) {
+            state.sprite_atlas = Initialized(SpriteAtlas::from_device(
+                device,
+                queue,
+                sprite_sheet.cloned(),
+                settings.color_space,
+            ));
+        }
+
         state.tile_view_pattern.initialize(|| {
             let tile_view_buffer_desc = wgpu::BufferDescriptor {
                 label: Some("tile view buffer"),
@@ -117,6 +195,48 @@ impl Stage for ResourceStage {
             pipeline
         });
 
+        state.gpu_culling.initialize(|| {
+            if !settings.gpu_culling {
+                return None;
+            }
+            if !capabilities.compute_shaders || !capabilities.indirect_execution {
+                log::warn!("Culling on the GPU is not supported by the device, culling on the CPU");
+                return None;
+            }
+            Some(GpuCulling::new(device))
+        });
+
+        state.wireframe = settings.wireframe.clone();
+        if settings.wireframe.is_enabled() {
+            state.wireframe_pipeline.initialize(|| {
+                if !device
+                    .features()
+                    .contains(wgpu::Features::POLYGON_MODE_LINE)
+                {
+                    log::warn!("Wireframes are not supported by the device, layers are filled");
+                    return None;
+                }
+
+                let tile_shader = shaders::TileShader {
+                    format: settings.texture_format,
+                };
+
+                Some(
+                    TilePipeline::new(
+                        settings.msaa,
+                        tile_shader.describe_vertex(),
+                        tile_shader.describe_fragment(),
+                        true,
+                        false,
+                        false,
+                        true,
+                    )
+                    .describe_render_pipeline()
+                    .initialize(device),
+                )
+            });
+        }
+
         state.mask_pipeline.initialize(|| {
             let mask_shader = shaders::TileMaskShader {
                 format: settings.texture_format,
@@ -135,5 +255,501 @@ impl Stage for ResourceStage {
             .describe_render_pipeline()
             .initialize(device)
         });
+
+        state.symbol_pipeline.initialize(|| {
+            let symbol_shader = shaders::SymbolShader {
+                format: settings.texture_format,
+            };
+
+            SymbolPipeline::new(
+                settings.msaa,
+                symbol_shader.describe_vertex(),
+                symbol_shader.describe_fragment(),
+            )
+            .describe_render_pipeline()
+            .initialize(device)
+        });
+
+        state.extrusion_pipeline.initialize(|| {
+            let extrusion_shader = shaders::ExtrusionShader {
+                format: settings.texture_format,
+            };
+
+            ExtrusionPipeline::new(
+                settings.msaa,
+                extrusion_shader.describe_vertex(),
+                extrusion_shader.describe_fragment(),
+            )
+            .describe_render_pipeline()
+            .initialize(device)
+        });
+
+        state.raster_pipeline.initialize(|| {
+            let raster_shader = shaders::RasterShader {
+                format: settings.texture_format,
+            };
+
+            RasterPipeline::new(
+                settings.msaa,
+                raster_shader.describe_vertex(),
+                raster_shader.describe_fragment(),
+            )
+            .describe_render_pipeline()
+            .initialize(device)
+        });
+
+        state
+            .raster_tiles
+            .initialize(|| RasterTiles::from_device(device, settings.color_space));
+
+        // Hillshades are only prepared while the style has hillshade layers
+        if style.layers.iter().any(|layer| layer.typ == "hillshade") {
+            if capabilities.float_textures {
+                state.hillshade_pipeline.initialize(|| {
+                    let hillshade_shader = shaders::HillshadeShader {
+                        format: settings.texture_format,
+                    };
+
+                    HillshadePipeline::new(
+                        settings.msaa,
+                        hillshade_shader.describe_vertex(),
+                        hillshade_shader.describe_fragment(),
+                    )
+                    .describe_render_pipeline()
+                    .initialize(device)
+                });
+
+                state
+                    .hillshade_tiles
+                    .initialize(|| HillshadeTiles::from_device(device));
+            } else if !self.hillshade_warned {
+                tracing::warn!("Hillshade layers are not drawn without support for float textures");
+                self.hillshade_warned = true;
+            }
+        }
+
+        // The debug overlay is only prepared while it is shown
+        if settings.show_tile_boundaries {
+            state.debug_pipeline.initialize(|| {
+                let debug_shader = shaders::DebugShader {
+                    format: settings.texture_format,
+                };
+
+                DebugPipeline::new(
+                    settings.msaa,
+                    debug_shader.describe_vertex(),
+                    debug_shader.describe_fragment(),
+                )
+                .describe_render_pipeline()
+                .initialize(device)
+            });
+
+            state
+                .tile_boundaries
+                .initialize(|| TileBoundaries::from_device(device));
+
+            if let (Initialized(tile_boundaries), Initialized(tile_view_pattern)) =
+                (&mut state.tile_boundaries, &state.tile_view_pattern)
+            {
+                tile_boundaries.prepare(queue, tile_view_pattern.iter());
+            }
+        }
+
+        // The overlay stays initialized once markers were added, such that removing the last
+        // marker clears it
+        if !markers.is_empty() {
+            state.marker_pipeline.initialize(|| {
+                let marker_shader = shaders::MarkerShader {
+                    format: settings.texture_format,
+                    instanced: settings.instanced_markers,
+                };
+
+                MarkerPipeline::new(
+                    settings.msaa,
+                    marker_shader.describe_vertex(),
+                    marker_shader.describe_fragment(),
+                )
+                .describe_render_pipeline()
+                .initialize(device)
+            });
+
+            state
+                .marker_overlay
+                .initialize(|| MarkerOverlay::from_device(device, settings.instanced_markers));
+        }
+
+        if let (Initialized(marker_overlay), Initialized(sprite_atlas)) =
+            (&mut state.marker_overlay, &state.sprite_atlas)
+        {
+            marker_overlay.prepare(
+                queue,
+                markers,
+                view_state,
+                sprite_atlas.sheet.as_deref(),
+                settings.color_space,
+            );
+        }
+
+        if let (Initialized(raster_tiles), Initialized(tile_view_pattern)) =
+            (&mut state.raster_tiles, &state.tile_view_pattern)
+        {
+            Self::prepare_raster_tiles(
+                raster_tiles,
+                tile_view_pattern,
+                device,
+                queue,
+                tile_cache,
+                style,
+            );
+        }
+
+        if let (Initialized(hillshade_tiles), Initialized(tile_view_pattern)) =
+            (&mut state.hillshade_tiles, &state.tile_view_pattern)
+        {
+            Self::prepare_hillshade_tiles(
+                hillshade_tiles,
+                tile_view_pattern,
+                device,
+                queue,
+                tile_cache,
+                style,
+                view_state.zoom().value(),
+                view_state.camera.bearing.0,
+                settings.color_space,
+            );
+        }
+    }
+}
+
+impl ResourceStage {
+    /// Returns the color with which frames are cleared. The visible background layers are blended
+    /// on top of each other in the order of the style, starting with the sRGB `clear_color`.
+    /// Blending onto the clear color is equivalent to drawing a full-screen quad, because
+    /// backgrounds are below all other layers. The clear color is given in `color_space` and
+    /// premultiplied by alpha, like the layers which are blended onto it.
+    fn background_color(
+        style: &Style,
+        zoom: f64,
+        color_space: ColorSpace,
+        clear_color: wgpu::Color,
+    ) -> wgpu::Color {
+        let [r, g, b, a] = color_space.color([
+            clear_color.r as f32,
+            clear_color.g as f32,
+            clear_color.b as f32,
+            clear_color.a as f32,
+        ]);
+        let (r, g, b, a) = (r as f64, g as f64, b as f64, a as f64);
+        let clear_color = wgpu::Color {
+            r: r * a,
+            g: g * a,
+            b: b * a,
+            a,
+        };
+
+        style
+            .layers
+            .iter()
+            .filter(|layer| layer.typ == "background" && layer.is_visible())
+            .filter_map(|layer| layer.paint.as_ref())
+            .fold(clear_color, |below, paint| {
+                let [r, g, b, alpha] = match color_space.paint_color(paint, zoom, None) {
+                    Some(color) => color,
+                    None => return below,
+                };
+                let alpha = alpha as f64
+                    * paint
+                        .get_opacity(zoom, None)
+                        .map_or(1.0, |opacity| opacity as f64);
+
+                wgpu::Color {
+                    r: r as f64 * alpha + below.r * (1.0 - alpha),
+                    g: g as f64 * alpha + below.g * (1.0 - alpha),
+                    b: b as f64 * alpha + below.b * (1.0 - alpha),
+                    a: alpha + below.a * (1.0 - alpha),
+                }
+            })
+    }
+
+    /// Creates textures for the raster tiles in view. If a raster tile is not available, then the
+    /// raster tile of a parent is stretched over the tile.
+    #[tracing::instrument(skip_all)]
+    fn prepare_raster_tiles(
+        raster_tiles: &mut RasterTiles,
+        tile_view_pattern: &TileViewPattern<wgpu::Queue, wgpu::Buffer>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tile_cache: &TileCache,
+        style: &Style,
+    ) {
+        raster_tiles.clear_view();
+
+        for style_layer in style
+            .layers
+            .iter()
+            .filter(|layer| layer.typ == "raster" && layer.is_visible())
+        {
+            let source = if let Some(source) = &style_layer.source {
+                source
+            } else {
+                continue;
+            };
+
+            let opacity = match &style_layer.paint {
+                Some(LayerPaint::Raster(paint)) => paint.raster_opacity.unwrap_or(1.0),
+                _ => 1.0,
+            };
+
+            for TileInView { shape, .. } in tile_view_pattern.iter() {
+                if let Some(RasterTileMessage::Raster {
+                    coords,
+                    width,
+                    height,
+                    format: RasterFormat::Rgba8,
+                    data,
+                    ..
+                }) = tile_cache.get_raster_tile_fallback(&shape.coords, source)
+                {
+                    let key = (*coords, source.clone());
+
+                    if !raster_tiles.has_texture(&key) {
+                        raster_tiles.upload_texture(
+                            device,
+                            queue,
+                            key.clone(),
+                            *width,
+                            *height,
+                            data,
+                        );
+                    }
+
+                    let metadata =
+                        ShaderRasterMetadata::new(tex_rect(&shape.coords, &key.0), opacity);
+                    if !raster_tiles.push_in_view(shape, style_layer.index, key, metadata) {
+                        tracing::warn!("Too many raster tiles in view");
+                    }
+                }
+            }
+        }
+
+        raster_tiles.upload_view(queue);
+        raster_tiles.evict();
+    }
+
+    /// Creates textures for the raster DEM tiles in view, like
+    /// [`ResourceStage::prepare_raster_tiles`] does for raster tiles.
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all)]
+    fn prepare_hillshade_tiles(
+        hillshade_tiles: &mut HillshadeTiles,
+        tile_view_pattern: &TileViewPattern<wgpu::Queue, wgpu::Buffer>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tile_cache: &TileCache,
+        style: &Style,
+        zoom: f64,
+        bearing: f64,
+        color_space: ColorSpace,
+    ) {
+        hillshade_tiles.clear_view();
+
+        for style_layer in style
+            .layers
+            .iter()
+            .filter(|layer| layer.typ == "hillshade" && layer.is_visible())
+        {
+            let source = if let Some(source) = &style_layer.source {
+                source
+            } else {
+                continue;
+            };
+
+            let default_paint = HillshadePaint::default();
+            let paint = match &style_layer.paint {
+                Some(LayerPaint::Hillshade(paint)) => paint,
+                _ => &default_paint,
+            };
+            let [shadow, highlight, accent] =
+                paint.colors(zoom).map(|color| color_space.color(color));
+            let hillshade_style = HillshadeStyle {
+                shadow,
+                highlight,
+                accent,
+                exaggeration: paint.exaggeration(),
+                illumination_direction: paint.illumination_direction(bearing),
+            };
+
+            for TileInView { shape, .. } in tile_view_pattern.iter() {
+                if let Some(RasterTileMessage::Raster {
+                    coords,
+                    width,
+                    height,
+                    format: RasterFormat::Elevation,
+                    data,
+                    ..
+                }) = tile_cache.get_raster_tile_fallback(&shape.coords, source)
+                {
+                    let key = (*coords, source.clone());
+
+                    if !hillshade_tiles.has_texture(&key) {
+                        hillshade_tiles.upload_texture(
+                            device,
+                            queue,
+                            key.clone(),
+                            *width,
+                            *height,
+                            data,
+                        );
+                    }
+
+                    let metadata = hillshade_style.metadata(shape, &key);
+                    if !hillshade_tiles.push_in_view(shape, style_layer.index, key, metadata) {
+                        tracing::warn!("Too many hillshade tiles in view");
+                    }
+                }
+            }
+        }
+
+        hillshade_tiles.upload_view(queue);
+        hillshade_tiles.evict();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResourceStage;
+    use crate::render::settings::ColorSpace;
+    use crate::style::Style;
+    use serde_json::json;
+
+    fn style(layers: serde_json::Value) -> Style {
+        serde_json::from_value(json!({
+            "version": 8,
+            "name": "background",
+            "metadata": {},
+            "sources": {},
+            "layers": layers
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_background_color() {
+        assert_eq!(
+            ResourceStage::background_color(
+                &style(json!([])),
+                10.0,
+                ColorSpace::Linear,
+                wgpu::Color::WHITE
+            ),
+            wgpu::Color::WHITE
+        );
+
+        let opaque = style(json!([{
+            "id": "background",
+            "type": "background",
+            "paint": {"background-color": "black"}
+        }]));
+        assert_eq!(
+            ResourceStage::background_color(&opaque, 10.0, ColorSpace::Linear, wgpu::Color::WHITE),
+            wgpu::Color::BLACK
+        );
+
+        let translucent = style(json!([{
+            "id": "background",
+            "type": "background",
+            "paint": {"background-color": "black", "background-opacity": 0.25}
+        }]));
+        let color = ResourceStage::background_color(
+            &translucent,
+            10.0,
+            ColorSpace::Linear,
+            wgpu::Color::WHITE,
+        );
+        assert!((color.r - 0.75).abs() < 1e-6);
+        assert_eq!(color.a, 1.0);
+    }
+
+    #[test]
+    fn test_blending_color_space() {
+        let gray = style(json!([{
+            "id": "background",
+            "type": "background",
+            "paint": {"background-color": "black", "background-opacity": 0.5}
+        }]));
+        // Encodes a linear color component like an sRGB surface does
+        let encode = |linear: f64| {
+            if linear <= 0.0031308 {
+                linear * 12.92
+            } else {
+                1.055 * linear.powf(1.0 / 2.4) - 0.055
+            }
+        };
+
+        // Half of the light of white is shown, which is brighter than the mid gray of sRGB
+        let linear =
+            ResourceStage::background_color(&gray, 10.0, ColorSpace::Linear, wgpu::Color::WHITE);
+        assert!((linear.r - 0.5).abs() < 1e-6);
+        assert!((encode(linear.r) - 0.735).abs() < 1e-3);
+
+        // Without conversion the blended value is written to the surface as it is
+        let srgb =
+            ResourceStage::background_color(&gray, 10.0, ColorSpace::Srgb, wgpu::Color::WHITE);
+        assert!((srgb.r - 0.5).abs() < 1e-6);
+
+        let opaque_gray = style(json!([{
+            "id": "background",
+            "type": "background",
+            "paint": {"background-color": "#808080"}
+        }]));
+        let linear = ResourceStage::background_color(
+            &opaque_gray,
+            10.0,
+            ColorSpace::Linear,
+            wgpu::Color::WHITE,
+        );
+        assert!((encode(linear.r) - 128.0 / 255.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_transparent_clear_color() {
+        assert_eq!(
+            ResourceStage::background_color(
+                &style(json!([])),
+                10.0,
+                ColorSpace::Linear,
+                wgpu::Color::TRANSPARENT
+            ),
+            wgpu::Color::TRANSPARENT
+        );
+
+        // Translucent backgrounds stay translucent and are premultiplied by their alpha
+        let translucent = style(json!([{
+            "id": "background",
+            "type": "background",
+            "paint": {"background-color": "white", "background-opacity": 0.25}
+        }]));
+        let color = ResourceStage::background_color(
+            &translucent,
+            10.0,
+            ColorSpace::Srgb,
+            wgpu::Color::TRANSPARENT,
+        );
+        assert!((color.r - 0.25).abs() < 1e-6);
+        assert!((color.a - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_hidden_background_is_ignored() {
+        let hidden = style(json!([{
+            "id": "background",
+            "type": "background",
+            "layout": {"visibility": "none"},
+            "paint": {"background-color": "black"}
+        }]));
+        assert_eq!(
+            ResourceStage::background_color(&hidden, 10.0, ColorSpace::Linear, wgpu::Color::WHITE),
+            wgpu::Color::WHITE
+        );
     }
 }
@@ -0,0 +1,592 @@
+//! Lays out the labels and icons of symbol layers and uploads them as textured quads to the GPU.
+
+use crate::context::MapContext;
+use crate::coords::{ViewRegion, Zoom, EXTENT, TILE_SIZE};
+use crate::io::glyphs::{fontstack_name, glyph_range, GlyphRanges};
+use crate::io::sprite::SpriteSheet;
+use crate::io::tile_cache::TileCache;
+use crate::io::LayerTessellateMessage;
+use crate::render::resource::GlyphAtlas;
+use crate::render::settings::ColorSpace;
+use crate::render::shaders::{ShaderLayerMetadata, ShaderSymbolStyle, SymbolVertex, Vec4f32};
+use crate::render::util::Eventually::Initialized;
+use crate::schedule::Stage;
+use crate::style::layer::{IconTextFit, RotationAlignment, StyleLayer, SymbolPlacement};
+use crate::tessellation::IndexDataType;
+use crate::text::feature::{geometry_paths, point_geometry, resolve_text_field, TileFeature};
+use crate::text::line_placement::{rotate, LinePath};
+use crate::text::placement::{LabelStyle, SymbolLabel, SymbolLayerLabels};
+use crate::text::{fit_icon_to_text, sdf_halo_width, GlyphQuad};
+use crate::{RenderState, Renderer, Style};
+use geozero::mvt::tile;
+use lyon::tessellation::VertexBuffers;
+use std::collections::HashSet;
+
+/// Text size which is used if the `text-size` of a layer is not set.
+const DEFAULT_TEXT_SIZE: f32 = 16.0;
+/// Text color which is used if the `text-color` of a layer is not set.
+const DEFAULT_TEXT_COLOR: Vec4f32 = [0.0, 0.0, 0.0, 1.0];
+/// Halo color which is used if the `text-halo-color` of a layer is not set.
+const DEFAULT_TEXT_HALO_COLOR: Vec4f32 = [0.0; 4];
+/// Scale of icons which is used if the `icon-size` of a layer is not set.
+const DEFAULT_ICON_SIZE: f32 = 1.0;
+/// Distance in pixels between labels along lines which is used if the `symbol-spacing` of a layer
+/// is not set.
+const DEFAULT_SYMBOL_SPACING: f32 = 250.0;
+
+#[derive(Default)]
+pub struct SymbolStage;
+
+impl Stage for SymbolStage {
+    #[tracing::instrument(name = "SymbolStage", skip_all)]
+    fn run(
+        &mut self,
+        MapContext {
+            view_state,
+            style,
+            tile_cache,
+            renderer:
+                Renderer {
+                    settings,
+                    device,
+                    queue,
+                    state,
+                    ..
+                },
+            ..
+        }: &mut MapContext,
+    ) {
+        let visible_level = view_state.visible_level();
+
+        let view_proj = view_state.view_projection();
+
+        let view_region = view_state
+            .camera
+            .view_region_bounding_box(&view_proj.invert())
+            .map(|bounding_box| ViewRegion::new(bounding_box, 0, *view_state.zoom, visible_level));
+
+        if let Some(view_region) = &view_region {
+            self.upload_symbols(
+                state,
+                device,
+                queue,
+                tile_cache,
+                style,
+                view_region,
+                view_state.zoom(),
+                settings.color_space,
+            );
+        }
+    }
+}
+
+impl SymbolStage {
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all)]
+    pub fn upload_symbols(
+        &self,
+        RenderState {
+            symbol_buffer_pool,
+            glyph_atlas,
+            sprite_atlas,
+            symbol_labels,
+            ..
+        }: &mut RenderState,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tile_cache: &mut TileCache,
+        style: &Style,
+        view_region: &ViewRegion,
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) {
+        if let (
+            Initialized(symbol_buffer_pool),
+            Initialized(glyph_atlas),
+            Initialized(sprite_atlas),
+        ) = (symbol_buffer_pool, glyph_atlas, sprite_atlas)
+        {
+            // Waits until the sprite sheet of a swapped style is uploaded
+            if !sprite_atlas.is_uploaded(style.sprite_sheet.as_ref()) {
+                return;
+            }
+
+            let loaded_glyphs = tile_cache.glyph_ranges_mut().take_loaded();
+            if !loaded_glyphs.is_empty() {
+                let height = glyph_atlas.atlas.height;
+                for (fontstack, glyphs) in loaded_glyphs {
+                    glyph_atlas.atlas.add_glyphs(&fontstack, &glyphs);
+                }
+                // The texture coordinates of laid out labels are relative to the size of the atlas
+                if glyph_atlas.atlas.height != height {
+                    symbol_buffer_pool.clear();
+                    symbol_labels.clear();
+                }
+                glyph_atlas.update(device, queue);
+            }
+
+            // Without a glyphs URL, labels are drawn with the configured font right away
+            let glyph_ranges = style.glyphs.as_ref().map(|_| tile_cache.glyph_ranges());
+            let mut wanted_ranges = HashSet::new();
+
+            for world_coords in style.overzoomed_tiles(view_region) {
+                let loaded_layers = symbol_buffer_pool
+                    .get_loaded_layers_at(&world_coords)
+                    .unwrap_or_default();
+                let style_layers = style
+                    .layers
+                    .iter()
+                    .filter(|layer| {
+                        layer.typ == "symbol"
+                            && layer.is_visible_at(zoom.value())
+                            && !loaded_layers.contains(layer.id.as_str())
+                    })
+                    .collect::<Vec<_>>();
+
+                let available_layers =
+                    if let Some(layers) = tile_cache.iter_tessellated_layers_at(&world_coords) {
+                        layers.collect::<Vec<_>>()
+                    } else {
+                        continue;
+                    };
+
+                for style_layer in style_layers {
+                    if let Some(LayerTessellateMessage::TessellatedLayer {
+                        coords,
+                        layer_data,
+                        ..
+                    }) = available_layers
+                        .iter()
+                        .find(|layer| style.is_layer_data(style_layer, layer))
+                    {
+                        let (buffer, labels, missing_ranges) = Self::layout_layer(
+                            glyph_atlas,
+                            glyph_ranges,
+                            sprite_atlas.sheet.as_deref(),
+                            style_layer,
+                            layer_data,
+                            zoom,
+                            color_space,
+                        );
+                        // Labels are laid out again once all of their glyphs are loaded
+                        if !missing_ranges.is_empty() {
+                            wanted_ranges.extend(missing_ranges);
+                            continue;
+                        }
+
+                        let mut feature_metadata =
+                            vec![ShaderSymbolStyle::default(); buffer.vertices.len()];
+                        for label in &labels {
+                            for (vertices, label_style) in label.styled_vertices() {
+                                feature_metadata[vertices].fill(label_style.into());
+                            }
+                        }
+
+                        let layout = style_layer.layout.as_ref();
+                        symbol_labels.insert(
+                            (*coords, style_layer.id.clone()),
+                            SymbolLayerLabels {
+                                allow_overlap: layout
+                                    .and_then(|layout| layout.text_allow_overlap)
+                                    .unwrap_or(false),
+                                vertex_count: buffer.vertices.len(),
+                                labels,
+                            },
+                        );
+
+                        tracing::trace!("Allocating symbols at {}", &coords);
+                        symbol_buffer_pool.allocate_layer_geometry(
+                            queue,
+                            *coords,
+                            style_layer.clone(),
+                            &buffer.into(),
+                            ShaderLayerMetadata::new(style_layer.index, None, None, None),
+                            &feature_metadata,
+                        );
+                    }
+                }
+            }
+
+            for (fontstack, range) in wanted_ranges {
+                tile_cache.glyph_ranges_mut().want(fontstack, range);
+            }
+        }
+    }
+
+    /// Creates a quad for the icon and each glyph of the labels of the features within a layer.
+    /// Labels are placed at the points of features, or along their lines depending on
+    /// `symbol-placement`. Icons are only created for point placement and if the sprite sheet is
+    /// loaded. With `icon-text-fit`, the icon is stretched around the text of its label. Also returns the ranges of glyphs which labels need, but which are not in
+    /// `glyph_ranges` yet.
+    #[allow(clippy::type_complexity)]
+    fn layout_layer(
+        glyph_atlas: &GlyphAtlas,
+        glyph_ranges: Option<&GlyphRanges>,
+        sprite_sheet: Option<&SpriteSheet>,
+        style_layer: &StyleLayer,
+        layer_data: &tile::Layer,
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) -> (
+        VertexBuffers<SymbolVertex, IndexDataType>,
+        Vec<SymbolLabel>,
+        HashSet<(String, u32)>,
+    ) {
+        let mut buffer = VertexBuffers::new();
+        let mut labels = Vec::new();
+        let mut missing_ranges = HashSet::new();
+
+        let layout = if let Some(layout) = &style_layer.layout {
+            layout
+        } else {
+            return (buffer, labels, missing_ranges);
+        };
+
+        let placement = layout.symbol_placement.unwrap_or_default();
+        let icon_image = layout
+            .icon_image
+            .as_ref()
+            .filter(|_| sprite_sheet.is_some() && placement == SymbolPlacement::Point);
+        if layout.text_field.is_none() && icon_image.is_none() {
+            return (buffer, labels, missing_ranges);
+        }
+
+        let fontstack = fontstack_name(layout.text_font.as_deref());
+
+        let icon_anchor = layout.icon_anchor.unwrap_or_default();
+        let icon_text_fit = layout.icon_text_fit.unwrap_or_default();
+        let icon_text_fit_padding = layout.icon_text_fit_padding.unwrap_or([0.0; 4]);
+        let is_upright = |alignment: Option<RotationAlignment>| {
+            alignment.unwrap_or_default().resolve(placement) == RotationAlignment::Viewport
+        };
+        let text_upright = is_upright(layout.text_rotation_alignment);
+        let icon_upright = is_upright(layout.icon_rotation_alignment);
+
+        // Convert from pixels to tile units
+        let extent = layer_data.extent.unwrap_or(EXTENT as u32) as f32;
+        let pixel_to_tile = extent / TILE_SIZE as f32;
+
+        for feature in &layer_data.features {
+            let tile_feature = TileFeature {
+                layer: layer_data,
+                feature,
+            };
+            if !style_layer.matches(&tile_feature) {
+                continue;
+            }
+
+            let (anchors, paths): (_, Vec<Vec<[f32; 2]>>) = match placement {
+                SymbolPlacement::Point => (point_geometry(feature), Vec::new()),
+                SymbolPlacement::Line | SymbolPlacement::LineCenter => (
+                    Vec::new(),
+                    geometry_paths(feature)
+                        .into_iter()
+                        .filter(|path| path.len() > 1)
+                        .collect(),
+                ),
+            };
+            if anchors.is_empty() && paths.is_empty() {
+                continue;
+            }
+
+            // Layout properties can be data-driven, so they are evaluated for each feature
+            let text_size = layout
+                .get_text_size(zoom.value(), Some(&tile_feature))
+                .unwrap_or(DEFAULT_TEXT_SIZE);
+            let icon_size = layout
+                .get_icon_size(zoom.value(), Some(&tile_feature))
+                .unwrap_or(DEFAULT_ICON_SIZE);
+            let spacing = layout
+                .get_symbol_spacing(zoom.value(), Some(&tile_feature))
+                .unwrap_or(DEFAULT_SYMBOL_SPACING)
+                * pixel_to_tile;
+            let sort_key = layout
+                .get_symbol_sort_key(zoom.value(), Some(&tile_feature))
+                .unwrap_or(0.0);
+
+            let (quads, text_width) = match &layout.text_field {
+                Some(text_field) => {
+                    let text = resolve_text_field(text_field, layer_data, feature);
+                    if let Some(glyph_ranges) = glyph_ranges {
+                        missing_ranges.extend(
+                            text.chars()
+                                .filter(|character| !glyph_ranges.is_loaded(&fontstack, *character))
+                                .map(|character| (fontstack.clone(), glyph_range(character))),
+                        );
+                    }
+                    (
+                        glyph_atlas.atlas.layout_text(&fontstack, &text, text_size),
+                        glyph_atlas.atlas.text_width(&fontstack, &text, text_size),
+                    )
+                }
+                None => (Vec::new(), 0.0),
+            };
+            let icon = match (icon_image, sprite_sheet) {
+                (Some(icon_image), Some(sprite_sheet)) => sprite_sheet.layout_icon(
+                    &resolve_text_field(icon_image, layer_data, feature),
+                    icon_size,
+                    icon_anchor,
+                ),
+                _ => None,
+            };
+            // Fitted icons, like highway shields, are stretched around the line of text, whose
+            // height is approximated by the text size
+            let icon = match icon {
+                Some(icon) if icon_text_fit != IconTextFit::None && !quads.is_empty() => {
+                    Some(fit_icon_to_text(
+                        &icon,
+                        [-text_width / 2.0, -text_size / 2.0],
+                        [text_width / 2.0, text_size / 2.0],
+                        icon_text_fit,
+                        icon_text_fit_padding,
+                    ))
+                }
+                icon => icon,
+            };
+            if quads.is_empty() && icon.is_none() {
+                continue;
+            }
+
+            let color: Vec4f32 = style_layer
+                .paint
+                .as_ref()
+                .and_then(|paint| color_space.paint_color(paint, zoom.value(), Some(&tile_feature)))
+                .unwrap_or(DEFAULT_TEXT_COLOR);
+            let (halo_color, halo_width) = style_layer
+                .paint
+                .as_ref()
+                .map(|paint| paint.get_text_halo(zoom.value(), Some(&tile_feature)))
+                .unwrap_or((None, None));
+            let style = LabelStyle {
+                color,
+                halo_color: halo_color
+                    .map(|halo_color| color_space.color(halo_color.into()))
+                    .unwrap_or(DEFAULT_TEXT_HALO_COLOR),
+                halo_width: sdf_halo_width(halo_width.unwrap_or(0.0), text_size),
+            };
+
+            let (min, max) = icon.iter().chain(quads.iter()).fold(
+                ([f32::MAX, f32::MAX], [f32::MIN, f32::MIN]),
+                |(min, max), quad| {
+                    (
+                        [min[0].min(quad.top_left[0]), min[1].min(quad.top_left[1])],
+                        [
+                            max[0].max(quad.bottom_right[0]),
+                            max[1].max(quad.bottom_right[1]),
+                        ],
+                    )
+                },
+            );
+
+            for anchor in anchors {
+                // The icon is drawn below the text
+                let icon_vertices = icon.as_ref().map(|icon| {
+                    let first_vertex = buffer.vertices.len();
+                    Self::push_quad(
+                        &mut buffer,
+                        anchor,
+                        icon,
+                        pixel_to_tile,
+                        icon_upright,
+                        SymbolVertex::icon,
+                    );
+                    first_vertex..buffer.vertices.len()
+                });
+
+                let first_vertex = buffer.vertices.len();
+                for quad in &quads {
+                    Self::push_quad(
+                        &mut buffer,
+                        anchor,
+                        quad,
+                        pixel_to_tile,
+                        text_upright,
+                        SymbolVertex::new,
+                    );
+                }
+
+                labels.push(SymbolLabel {
+                    anchor,
+                    min: [min[0] * pixel_to_tile, min[1] * pixel_to_tile],
+                    max: [max[0] * pixel_to_tile, max[1] * pixel_to_tile],
+                    vertices: first_vertex..buffer.vertices.len(),
+                    style,
+                    icon_vertices,
+                    upright: if quads.is_empty() {
+                        icon_upright
+                    } else {
+                        text_upright
+                    },
+                    sort_key,
+                });
+            }
+
+            if !quads.is_empty() {
+                for path in &paths {
+                    Self::push_line_labels(
+                        &mut buffer,
+                        &mut labels,
+                        &LinePath::new(path),
+                        &quads,
+                        (max[0] - min[0]) * pixel_to_tile,
+                        spacing,
+                        placement,
+                        extent,
+                        pixel_to_tile,
+                        style,
+                        text_upright,
+                        sort_key,
+                    );
+                }
+            }
+        }
+
+        (buffer, labels, missing_ranges)
+    }
+
+    /// Places labels of the glyph `quads` along `line`. Each glyph is anchored on the line and
+    /// rotated to its direction.
+    #[allow(clippy::too_many_arguments)]
+    fn push_line_labels(
+        buffer: &mut VertexBuffers<SymbolVertex, IndexDataType>,
+        labels: &mut Vec<SymbolLabel>,
+        line: &LinePath,
+        quads: &[GlyphQuad],
+        label_length: f32,
+        spacing: f32,
+        placement: SymbolPlacement,
+        extent: f32,
+        pixel_to_tile: f32,
+        style: LabelStyle,
+        upright: bool,
+        sort_key: f32,
+    ) {
+        let glyph_centers = quads
+            .iter()
+            .map(|quad| (quad.top_left[0] + quad.bottom_right[0]) / 2.0 * pixel_to_tile)
+            .collect::<Vec<_>>();
+
+        for distance in line.label_anchors(label_length, spacing, placement) {
+            let label = match line.place_label(distance, &glyph_centers) {
+                Some(label) => label,
+                None => continue,
+            };
+            // Labels within the buffer of the tile are placed by the neighbouring tile
+            let [x, y] = label.anchor;
+            if x < 0.0 || y < 0.0 || x >= extent || y >= extent {
+                continue;
+            }
+
+            let mut min = [f32::MAX, f32::MAX];
+            let mut max = [f32::MIN, f32::MIN];
+            let first_vertex = buffer.vertices.len();
+            for ((quad, glyph), center) in quads.iter().zip(&label.glyphs).zip(&glyph_centers) {
+                let first_index = buffer.vertices.len() as IndexDataType;
+
+                let [left, top] = quad.top_left;
+                let [right, bottom] = quad.bottom_right;
+                let [tex_left, tex_top] = quad.tex_top_left;
+                let [tex_right, tex_bottom] = quad.tex_bottom_right;
+                let (left, right) = (
+                    left * pixel_to_tile - center,
+                    right * pixel_to_tile - center,
+                );
+                let (top, bottom) = (top * pixel_to_tile, bottom * pixel_to_tile);
+
+                for (corner, tex_coords) in [
+                    ([left, top], [tex_left, tex_top]),
+                    ([right, top], [tex_right, tex_top]),
+                    ([right, bottom], [tex_right, tex_bottom]),
+                    ([left, bottom], [tex_left, tex_bottom]),
+                ] {
+                    let offset = rotate(corner, glyph.angle);
+                    buffer.vertices.push(SymbolVertex {
+                        upright: if upright { 1.0 } else { 0.0 },
+                        ..SymbolVertex::new(glyph.anchor, offset, tex_coords)
+                    });
+
+                    // The bounding box is relative to the anchor of the label
+                    for axis in 0..2 {
+                        let position = glyph.anchor[axis] - label.anchor[axis] + offset[axis];
+                        min[axis] = min[axis].min(position);
+                        max[axis] = max[axis].max(position);
+                    }
+                }
+
+                buffer.indices.extend([
+                    first_index,
+                    first_index + 1,
+                    first_index + 2,
+                    first_index,
+                    first_index + 2,
+                    first_index + 3,
+                ]);
+            }
+
+            labels.push(SymbolLabel {
+                anchor: label.anchor,
+                min,
+                max,
+                vertices: first_vertex..buffer.vertices.len(),
+                style,
+                icon_vertices: None,
+                upright,
+                sort_key,
+            });
+        }
+    }
+
+    /// Appends the two triangles of a quad. Its offsets are converted from pixels to tile units.
+    /// `upright` quads stay upright in the viewport, see [`SymbolVertex::upright`].
+    fn push_quad(
+        buffer: &mut VertexBuffers<SymbolVertex, IndexDataType>,
+        anchor: [f32; 2],
+        quad: &GlyphQuad,
+        pixel_to_tile: f32,
+        upright: bool,
+        vertex: fn([f32; 2], [f32; 2], [f32; 2]) -> SymbolVertex,
+    ) {
+        let first_index = buffer.vertices.len() as IndexDataType;
+
+        let [left, top] = quad.top_left;
+        let [right, bottom] = quad.bottom_right;
+        let [tex_left, tex_top] = quad.tex_top_left;
+        let [tex_right, tex_bottom] = quad.tex_bottom_right;
+
+        let vertices = [
+            vertex(
+                anchor,
+                [left * pixel_to_tile, top * pixel_to_tile],
+                [tex_left, tex_top],
+            ),
+            vertex(
+                anchor,
+                [right * pixel_to_tile, top * pixel_to_tile],
+                [tex_right, tex_top],
+            ),
+            vertex(
+                anchor,
+                [right * pixel_to_tile, bottom * pixel_to_tile],
+                [tex_right, tex_bottom],
+            ),
+            vertex(
+                anchor,
+                [left * pixel_to_tile, bottom * pixel_to_tile],
+                [tex_left, tex_bottom],
+            ),
+        ];
+        buffer.vertices.extend(vertices.map(|vertex| SymbolVertex {
+            upright: if upright { 1.0 } else { 0.0 },
+            ..vertex
+        }));
+
+        buffer.indices.extend([
+            first_index,
+            first_index + 1,
+            first_index + 2,
+            first_index,
+            first_index + 2,
+            first_index + 3,
+        ]);
+    }
+}
@@ -1,23 +1,61 @@
 //! Uploads data to the GPU which is needed for rendering.
 
-use crate::context::MapContext;
-use crate::coords::{ViewRegion, Zoom};
+use crate::context::{is_tile_rendered, MapContext};
+use crate::coords::{ViewRegion, WorldTileCoords, Zoom};
+use crate::io::sprite::SpriteSheet;
 use crate::io::tile_cache::TileCache;
 use crate::io::LayerTessellateMessage;
+use crate::metrics::{BufferPoolOccupancy, MetricsSink};
 use crate::render::camera::ViewProjection;
-use crate::render::resource::IndexEntry;
+use crate::render::resource::{BufferPool, IndexEntry, Queue};
+use crate::render::settings::ColorSpace;
 use crate::render::shaders::{
     ShaderCamera, ShaderFeatureStyle, ShaderGlobals, ShaderLayerMetadata, Vec4f32,
 };
 use crate::render::tile_view_pattern::TileInView;
 use crate::render::util::Eventually::Initialized;
+use crate::render::ShaderVertex;
 use crate::schedule::Stage;
+use crate::style::animation::ColorAnimation;
+use crate::style::expression::FeatureProperties;
+use crate::style::layer::StyleLayer;
+use crate::tessellation::{IndexDataType, OverAlignedVertexBuffer};
+use crate::text::feature::TileFeature;
 use crate::{RenderState, Renderer, Style};
+use geozero::mvt::tile;
+use instant::Instant;
+use lyon::tessellation::VertexBuffers;
 
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::iter;
+use std::time::Duration;
+
+/// Color of features if the style of their layer does not define one.
+const DEFAULT_COLOR: Vec4f32 = [0.0, 0.0, 0.0, 1.0];
+
+/// Number of tiles around the view whose geometry is not evicted from the buffer pool.
+const RETAINED_TILE_PADDING: i32 = 1;
 
 #[derive(Default)]
-pub struct UploadStage;
+pub struct UploadStage {
+    /// Zoom at which the zoom-dependent styles have been evaluated the last time.
+    last_style_zoom: Option<f64>,
+    /// Occupancy of the buffer pool which has been reported to the metrics sink the last time.
+    last_occupancy: Option<BufferPoolOccupancy>,
+    /// Revisions of the paint properties which have been applied to the buffer pool, see
+    /// [`Style::paint_revisions`].
+    paint_revisions: HashMap<String, u64>,
+    /// When the first layer started to be animated, see [`Style::animations`].
+    animation_start: Option<Instant>,
+    /// Layers which are not uploaded because they have no source layer. Each of them is logged
+    /// once.
+    skipped_layers: HashSet<String>,
+}
+
+/// The buffer pool of the layers which are not symbols or extrusions.
+type LayerBufferPool<Q, B> =
+    BufferPool<Q, B, ShaderVertex, IndexDataType, ShaderLayerMetadata, ShaderFeatureStyle>;
 
 impl Stage for UploadStage {
     #[tracing::instrument(name = "UploadStage", skip_all)]
@@ -29,13 +67,14 @@ impl Stage for UploadStage {
             tile_cache,
             renderer:
                 Renderer {
-                    settings: _,
+                    settings,
                     device: _,
                     queue,
                     surface: _,
                     state,
                     ..
                 },
+            shared_thread_state,
             ..
         }: &mut MapContext,
     ) {
@@ -48,16 +87,20 @@ impl Stage for UploadStage {
             queue.write_buffer(
                 &globals_bind_group.uniform_buffer,
                 0,
-                bytemuck::cast_slice(&[ShaderGlobals::new(ShaderCamera::new(
-                    view_proj.downcast().into(),
-                    view_state
-                        .camera
-                        .position
-                        .to_homogeneous()
-                        .cast::<f32>()
-                        .unwrap()
-                        .into(),
-                ))]),
+                bytemuck::cast_slice(&[ShaderGlobals::new(
+                    ShaderCamera::new(
+                        view_proj.downcast().into(),
+                        view_state
+                            .camera
+                            .position
+                            .to_homogeneous()
+                            .cast::<f32>()
+                            .unwrap()
+                            .into(),
+                    ),
+                    view_state.zoom().value() as f32,
+                    view_state.camera.bearing.0 as f32,
+                )]),
             );
         }
 
@@ -69,14 +112,457 @@ impl Stage for UploadStage {
         if let Some(view_region) = &view_region {
             let zoom = view_state.zoom();
 
-            self.upload_tile_geometry(state, queue, tile_cache, style, view_region);
-            self.update_tile_view_pattern(state, queue, view_region, &view_proj, zoom);
+            self.upload_tile_geometry(
+                state,
+                queue,
+                tile_cache,
+                style,
+                view_region,
+                zoom,
+                settings.color_space,
+            );
             self.update_metadata();
+            Self::evict_tile_geometry(state, view_region);
+            self.update_changed_paint(state, queue, tile_cache, style, zoom, settings.color_space);
+            self.update_tile_view_pattern(
+                state,
+                queue,
+                tile_cache,
+                style,
+                view_region,
+                &view_proj,
+                zoom,
+            );
+            self.update_zoom_dependent_styles(
+                state,
+                queue,
+                tile_cache,
+                style,
+                zoom,
+                settings.color_space,
+            );
+            self.update_animated_styles(
+                state,
+                queue,
+                tile_cache,
+                style,
+                zoom,
+                settings.color_space,
+            );
         }
+
+        self.report_occupancy(state, shared_thread_state.metrics.as_ref());
     }
 }
 
 impl UploadStage {
+    /// Evicts the geometry of tiles which are neither in view nor next to it once the buffer pool
+    /// exceeds its budget.
+    fn evict_tile_geometry(
+        RenderState { buffer_pool, .. }: &mut RenderState,
+        view_region: &ViewRegion,
+    ) {
+        if let Initialized(buffer_pool) = buffer_pool {
+            let retained_region = view_region.padded(RETAINED_TILE_PADDING);
+            let evicted = buffer_pool.evict(|coords| retained_region.is_in_view(coords));
+            if evicted > 0 {
+                tracing::info!("evicted {} tiles from the buffer pool", evicted);
+            }
+        }
+    }
+
+    /// Reports the occupancy of the buffer pool whenever it changed since the last report.
+    fn report_occupancy(&mut self, state: &RenderState, metrics: &dyn MetricsSink) {
+        if let Initialized(buffer_pool) = &state.buffer_pool {
+            let occupancy = buffer_pool.occupancy();
+            if self.last_occupancy != Some(occupancy) {
+                metrics.buffer_pool_occupancy(&occupancy);
+                self.last_occupancy = Some(occupancy);
+            }
+        }
+    }
+
+    /// Evaluates the color and opacity of each feature within a layer at the given zoom level. The
+    /// line width is evaluated at the integer zoom levels around it, see
+    /// [`ShaderFeatureStyle::line_width`]. The colors are given in `color_space`.
+    fn feature_metadata(
+        style_layer: &StyleLayer,
+        layer_data: &tile::Layer,
+        feature_indices: &[u32],
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) -> Vec<ShaderFeatureStyle> {
+        let paint = style_layer.paint.as_ref();
+        let zoom_below = zoom.value().floor();
+        let evaluate = |feature: Option<&dyn FeatureProperties>| -> ShaderFeatureStyle {
+            let mut color: Vec4f32 = paint
+                .and_then(|paint| color_space.paint_color(paint, zoom.value(), feature))
+                .unwrap_or(DEFAULT_COLOR);
+
+            // The opacity is folded into the alpha channel of the color
+            if let Some(opacity) = paint.and_then(|paint| paint.get_opacity(zoom.value(), feature))
+            {
+                color[3] *= opacity;
+            }
+
+            let line_width =
+                |zoom: f64| paint.and_then(|paint| paint.get_line_width(zoom, feature));
+            ShaderFeatureStyle::interpolated(
+                color,
+                line_width(zoom_below),
+                line_width(zoom_below + 1.0),
+            )
+        };
+
+        let layer_style = if paint.map_or(false, |paint| paint.is_feature_dependent()) {
+            None
+        } else {
+            Some(evaluate(None))
+        };
+
+        if layer_data.features.len() != feature_indices.len() {
+            tracing::warn!(
+                "layer {} has {} features but indices for {}",
+                layer_data.name,
+                layer_data.features.len(),
+                feature_indices.len()
+            );
+        }
+
+        // Features without indices are not drawn, so they need no style
+        layer_data
+            .features
+            .iter()
+            .zip(feature_indices)
+            .flat_map(|(feature, count)| {
+                let style = layer_style.unwrap_or_else(|| {
+                    evaluate(Some(&TileFeature {
+                        layer: layer_data,
+                        feature,
+                    }))
+                });
+                iter::repeat(style).take(*count as usize)
+            })
+            .collect::<Vec<_>>()
+    }
+
+    /// Removes the indices of the features which do not match the filter of `style_layer`, such
+    /// that they are not drawn. The vertices are kept, so the feature metadata stays valid.
+    fn filter_features<'a>(
+        style_layer: &StyleLayer,
+        layer_data: &tile::Layer,
+        feature_indices: &[u32],
+        buffer: &'a OverAlignedVertexBuffer<ShaderVertex, IndexDataType>,
+    ) -> Cow<'a, OverAlignedVertexBuffer<ShaderVertex, IndexDataType>> {
+        if style_layer.filter.is_none() {
+            return Cow::Borrowed(buffer);
+        }
+
+        let mut indices = Vec::new();
+        let mut start = 0;
+        for (feature, count) in layer_data.features.iter().zip(feature_indices) {
+            let end = start + *count as usize;
+            if style_layer.matches(&TileFeature {
+                layer: layer_data,
+                feature,
+            }) {
+                indices.extend_from_slice(&buffer.buffer.indices[start..end]);
+            }
+            start = end;
+        }
+
+        Cow::Owned(
+            VertexBuffers {
+                vertices: buffer.buffer.vertices.clone(),
+                indices,
+            }
+            .into(),
+        )
+    }
+
+    /// Evaluates the properties which apply to the whole layer. The image of `fill-pattern` or
+    /// `line-pattern` is looked up in `sprite_sheet`, which needs to be the sheet of the uploaded
+    /// sprite atlas. Layers whose pattern is missing are drawn with their color.
+    fn layer_metadata(
+        style_layer: &StyleLayer,
+        zoom: Zoom,
+        sprite_sheet: Option<&SpriteSheet>,
+    ) -> ShaderLayerMetadata {
+        let paint = style_layer.paint.as_ref();
+        let pattern = paint
+            .and_then(|paint| paint.get_pattern())
+            .and_then(|name| sprite_sheet?.pattern(name));
+        ShaderLayerMetadata::new(
+            style_layer.index,
+            paint.and_then(|paint| paint.get_dash_pattern()),
+            paint.and_then(|paint| paint.get_line_gap_width(zoom.value())),
+            pattern,
+        )
+    }
+
+    /// Evaluates the styles of layers which depend on the zoom level again if the zoom changed.
+    #[tracing::instrument(skip_all)]
+    pub fn update_zoom_dependent_styles(
+        &mut self,
+        RenderState { buffer_pool, .. }: &mut RenderState,
+        queue: &wgpu::Queue,
+        tile_cache: &TileCache,
+        style: &Style,
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) {
+        if self.last_style_zoom == Some(zoom.value()) {
+            return;
+        }
+        // Line widths only need to be evaluated again once the zoom crosses an integer zoom level
+        let level_changed = self
+            .last_style_zoom
+            .map_or(true, |last_zoom| last_zoom.floor() != zoom.value().floor());
+        self.last_style_zoom = Some(zoom.value());
+
+        if let Initialized(buffer_pool) = buffer_pool {
+            for entries in buffer_pool.index().iter() {
+                for entry in entries {
+                    if entry.style_layer.paint.as_ref().map_or(false, |paint| {
+                        paint.is_zoom_dependent()
+                            || (level_changed && paint.is_line_width_zoom_dependent())
+                    }) {
+                        Self::update_entry_metadata(
+                            buffer_pool,
+                            queue,
+                            tile_cache,
+                            style,
+                            entry,
+                            zoom,
+                            color_space,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Evaluates the styles of layers whose paint properties changed at runtime again, see
+    /// [`Style::set_paint_property`]. The geometry of the layers is kept.
+    #[tracing::instrument(skip_all)]
+    pub fn update_changed_paint(
+        &mut self,
+        RenderState { buffer_pool, .. }: &mut RenderState,
+        queue: &wgpu::Queue,
+        tile_cache: &TileCache,
+        style: &Style,
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) {
+        if self.paint_revisions == style.paint_revisions {
+            return;
+        }
+        let changed_layers = style
+            .layers
+            .iter()
+            .filter(|layer| {
+                self.paint_revisions.get(&layer.id) != style.paint_revisions.get(&layer.id)
+            })
+            .collect::<Vec<_>>();
+        self.paint_revisions = style.paint_revisions.clone();
+
+        if let Initialized(buffer_pool) = buffer_pool {
+            Self::update_paint(
+                buffer_pool,
+                queue,
+                tile_cache,
+                style,
+                &changed_layers,
+                zoom,
+                color_space,
+            );
+        }
+    }
+
+    /// Replaces the style of the uploaded layers of `style_layers` and uploads their metadata
+    /// again.
+    fn update_paint<Q: Queue<B>, B>(
+        buffer_pool: &mut LayerBufferPool<Q, B>,
+        queue: &Q,
+        tile_cache: &TileCache,
+        style: &Style,
+        style_layers: &[&StyleLayer],
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) {
+        for style_layer in style_layers {
+            buffer_pool.update_style_layer(style_layer);
+        }
+
+        for entries in buffer_pool.index().iter() {
+            for entry in entries {
+                if style_layers
+                    .iter()
+                    .any(|style_layer| style_layer.id == entry.style_layer.id)
+                {
+                    Self::update_entry_metadata(
+                        buffer_pool,
+                        queue,
+                        tile_cache,
+                        style,
+                        entry,
+                        zoom,
+                        color_space,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Blends the colors of the animated layers for the current frame, see
+    /// [`Style::set_color_animation`]. Layers which are not animated are skipped.
+    #[tracing::instrument(skip_all)]
+    pub fn update_animated_styles(
+        &mut self,
+        RenderState { buffer_pool, .. }: &mut RenderState,
+        queue: &wgpu::Queue,
+        tile_cache: &TileCache,
+        style: &Style,
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) {
+        if style.animations.is_empty() {
+            self.animation_start = None;
+            return;
+        }
+        let elapsed = self
+            .animation_start
+            .get_or_insert_with(Instant::now)
+            .elapsed();
+
+        if let Initialized(buffer_pool) = buffer_pool {
+            Self::animate_paint(
+                buffer_pool,
+                queue,
+                tile_cache,
+                style,
+                elapsed,
+                zoom,
+                color_space,
+            );
+        }
+    }
+
+    /// Uploads the feature metadata of the animated layers at `elapsed` since the start of the
+    /// animations.
+    fn animate_paint<Q: Queue<B>, B>(
+        buffer_pool: &LayerBufferPool<Q, B>,
+        queue: &Q,
+        tile_cache: &TileCache,
+        style: &Style,
+        elapsed: Duration,
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) {
+        for entries in buffer_pool.index().iter() {
+            for entry in entries {
+                let animation = match style.animations.get(&entry.style_layer.id) {
+                    Some(animation) => animation,
+                    None => continue,
+                };
+                if let Some((layer_data, feature_indices)) =
+                    Self::tessellated_layer(tile_cache, style, entry)
+                {
+                    let mut feature_metadata = Self::feature_metadata(
+                        &entry.style_layer,
+                        layer_data,
+                        feature_indices,
+                        zoom,
+                        color_space,
+                    );
+                    for feature_style in &mut feature_metadata {
+                        feature_style.color = Self::animate_color(
+                            feature_style.color,
+                            animation,
+                            elapsed,
+                            color_space,
+                        );
+                    }
+                    buffer_pool.update_feature_metadata(queue, entry, &feature_metadata);
+                }
+            }
+        }
+    }
+
+    /// Blends `color` towards the color of `animation` at `elapsed`. The alpha channel is
+    /// blended as well.
+    fn animate_color(
+        color: Vec4f32,
+        animation: &ColorAnimation,
+        elapsed: Duration,
+        color_space: ColorSpace,
+    ) -> Vec4f32 {
+        let weight = animation.weight(elapsed);
+        let target = color_space.color([
+            animation.color.r as f32,
+            animation.color.g as f32,
+            animation.color.b as f32,
+            animation.color.a as f32,
+        ]);
+        let mut blended = color;
+        for (component, target) in blended.iter_mut().zip(target) {
+            *component += (target - *component) * weight;
+        }
+        blended
+    }
+
+    /// Returns the data and the indices per feature of the tessellated layer which `entry` draws.
+    fn tessellated_layer<'a>(
+        tile_cache: &'a TileCache,
+        style: &Style,
+        entry: &IndexEntry,
+    ) -> Option<(&'a tile::Layer, &'a [u32])> {
+        match tile_cache
+            .iter_tessellated_layers_at(&entry.coords)?
+            .find(|layer| style.is_layer_data(&entry.style_layer, layer))?
+        {
+            LayerTessellateMessage::TessellatedLayer {
+                layer_data,
+                feature_indices,
+                ..
+            } => Some((layer_data, feature_indices.as_slice())),
+            LayerTessellateMessage::UnavailableLayer { .. } => None,
+        }
+    }
+
+    /// Evaluates the feature and layer metadata of an uploaded layer again.
+    fn update_entry_metadata<Q: Queue<B>, B>(
+        buffer_pool: &LayerBufferPool<Q, B>,
+        queue: &Q,
+        tile_cache: &TileCache,
+        style: &Style,
+        entry: &IndexEntry,
+        zoom: Zoom,
+        color_space: ColorSpace,
+    ) {
+        let style_layer = &entry.style_layer;
+        if let Some((layer_data, feature_indices)) =
+            Self::tessellated_layer(tile_cache, style, entry)
+        {
+            buffer_pool.update_feature_metadata(
+                queue,
+                entry,
+                &Self::feature_metadata(
+                    style_layer,
+                    layer_data,
+                    feature_indices,
+                    zoom,
+                    color_space,
+                ),
+            );
+            buffer_pool.update_layer_metadata(
+                queue,
+                entry,
+                Self::layer_metadata(style_layer, zoom, style.sprite_sheet.as_deref()),
+            );
+        }
+    }
+
     #[tracing::instrument(skip_all)]
     pub(crate) fn update_metadata(&self) {
         /*let animated_one = 0.5
@@ -146,6 +632,7 @@ impl UploadStage {
         }*/
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[tracing::instrument(skip_all)]
     pub fn update_tile_view_pattern(
         &self,
@@ -155,6 +642,8 @@ impl UploadStage {
             ..
         }: &mut RenderState,
         queue: &wgpu::Queue,
+        tile_cache: &TileCache,
+        style: &Style,
         view_region: &ViewRegion,
         view_proj: &ViewProjection,
         zoom: Zoom,
@@ -162,47 +651,75 @@ impl UploadStage {
         if let (Initialized(tile_view_pattern), Initialized(buffer_pool)) =
             (tile_view_pattern, buffer_pool)
         {
-            tile_view_pattern.update_pattern(view_region, buffer_pool, zoom);
+            let is_complete = |coords: &WorldTileCoords| {
+                is_tile_rendered(
+                    style,
+                    tile_cache,
+                    buffer_pool.get_loaded_layers_at(coords),
+                    coords,
+                    zoom,
+                )
+            };
+            tile_view_pattern.update_pattern(view_region, buffer_pool, zoom, is_complete);
             tile_view_pattern.upload_pattern(queue, view_proj);
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[tracing::instrument(skip_all)]
     pub fn upload_tile_geometry(
-        &self,
+        &mut self,
         RenderState { buffer_pool, .. }: &mut RenderState,
         queue: &wgpu::Queue,
         tile_cache: &TileCache,
         style: &Style,
         view_region: &ViewRegion,
+        zoom: Zoom,
+        color_space: ColorSpace,
     ) {
+        let skipped_layers = &mut self.skipped_layers;
         if let Initialized(buffer_pool) = buffer_pool {
-            // Upload all tessellated layers which are in view
-            for world_coords in view_region.iter() {
+            // Upload all tessellated layers which are in view. Overzoomed tiles are drawn with the
+            // layers of their ancestor, see `TileViewPattern::update_pattern`
+            for world_coords in style.overzoomed_tiles(view_region) {
                 let loaded_layers = buffer_pool
                     .get_loaded_layers_at(&world_coords)
                     .unwrap_or_default();
+                // Symbol layers are laid out by the SymbolStage, extrusions are built by the
+                // ExtrusionStage, backgrounds are the clear color and rasters and hillshades are not
+                // tessellated. Layers without source layer have no data, see `Style::validate`
+                let style_layers = style
+                    .layers
+                    .iter()
+                    .filter(|layer| {
+                        layer.typ != "symbol"
+                            && layer.typ != "fill-extrusion"
+                            && layer.typ != "background"
+                            && layer.typ != "raster"
+                            && layer.typ != "hillshade"
+                            && layer.is_visible_at(zoom.value())
+                            && !loaded_layers.contains(layer.id.as_str())
+                    })
+                    .filter(|layer| {
+                        if layer.source_layer.is_some() {
+                            return true;
+                        }
+                        if skipped_layers.insert(layer.id.clone()) {
+                            tracing::warn!("layer {} has no source layer and is skipped", layer.id);
+                        }
+                        false
+                    })
+                    .collect::<Vec<_>>();
+
                 if let Some(available_layers) = tile_cache
                     .iter_tessellated_layers_at(&world_coords)
-                    .map(|layers| {
-                        layers
-                            .filter(|result| !loaded_layers.contains(&result.layer_name()))
-                            .collect::<Vec<_>>()
-                    })
+                    .map(|layers| layers.collect::<Vec<_>>())
                 {
-                    for style_layer in &style.layers {
-                        let source_layer = style_layer.source_layer.as_ref().unwrap();
-
+                    for style_layer in style_layers {
                         if let Some(message) = available_layers
                             .iter()
-                            .find(|layer| source_layer.as_str() == layer.layer_name())
+                            .find(|layer| style.is_layer_data(style_layer, layer))
                         {
-                            let color: Option<Vec4f32> = style_layer
-                                .paint
-                                .as_ref()
-                                .and_then(|paint| paint.get_color())
-                                .map(|color| color.into());
-
                             match message {
                                 LayerTessellateMessage::UnavailableLayer { coords: _, .. } => {
                                     /*self.buffer_pool.mark_layer_unavailable(*coords);*/
@@ -220,26 +737,33 @@ impl UploadStage {
                                     );
 
                                     let guard = allocate_feature_metadata.enter();
-                                    let feature_metadata = layer_data
-                                        .features
-                                        .iter()
-                                        .enumerate()
-                                        .flat_map(|(i, _feature)| {
-                                            iter::repeat(ShaderFeatureStyle {
-                                                color: color.unwrap(),
-                                            })
-                                            .take(feature_indices[i] as usize)
-                                        })
-                                        .collect::<Vec<_>>();
+                                    let feature_metadata = Self::feature_metadata(
+                                        style_layer,
+                                        layer_data,
+                                        feature_indices,
+                                        zoom,
+                                        color_space,
+                                    );
                                     drop(guard);
 
+                                    let geometry = Self::filter_features(
+                                        style_layer,
+                                        layer_data,
+                                        feature_indices,
+                                        buffer,
+                                    );
+
                                     tracing::trace!("Allocating geometry at {}", &coords);
                                     buffer_pool.allocate_layer_geometry(
                                         queue,
                                         *coords,
                                         style_layer.clone(),
-                                        buffer,
-                                        ShaderLayerMetadata::new(style_layer.index as f32),
+                                        &geometry,
+                                        Self::layer_metadata(
+                                            style_layer,
+                                            zoom,
+                                            style.sprite_sheet.as_deref(),
+                                        ),
                                         &feature_metadata,
                                     );
                                 }
@@ -251,3 +775,363 @@ impl UploadStage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::UploadStage;
+    use crate::coords::{WorldTileCoords, Zoom};
+    use crate::io::sprite::SpriteSheet;
+    use crate::io::tile_cache::TileCache;
+    use crate::io::LayerTessellateMessage;
+    use crate::render::resource::{BackingBufferDescriptor, BufferPool, Queue};
+    use crate::render::settings::ColorSpace;
+    use crate::render::shaders::{ShaderFeatureStyle, ShaderLayerMetadata};
+    use crate::render::ShaderVertex;
+    use crate::style::animation::ColorAnimation;
+    use crate::style::layer::StyleLayer;
+    use crate::tessellation::IndexDataType;
+    use crate::Style;
+    use csscolorparser::Color;
+    use geozero::mvt::tile;
+    use image::codecs::png::PngEncoder;
+    use image::{ColorType, ImageEncoder};
+    use lyon::tessellation::VertexBuffers;
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    /// Identifies the backing buffer which has been written to.
+    struct TestBuffer {
+        name: &'static str,
+    }
+
+    /// Records the writes to the backing buffers.
+    #[derive(Default)]
+    struct RecordingQueue {
+        writes: RefCell<Vec<(&'static str, Vec<u8>)>>,
+    }
+
+    impl Queue<TestBuffer> for RecordingQueue {
+        fn write_buffer(&self, buffer: &TestBuffer, _offset: wgpu::BufferAddress, data: &[u8]) {
+            self.writes.borrow_mut().push((buffer.name, data.to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_feature_metadata_depends_on_properties() {
+        let feature = |class: u32| tile::Feature {
+            id: None,
+            tags: vec![0, class],
+            r#type: Some(tile::GeomType::Polygon as i32),
+            geometry: vec![],
+        };
+        let layer = tile::Layer {
+            version: 2,
+            name: "landcover".to_string(),
+            features: vec![feature(0), feature(1)],
+            keys: vec!["class".to_string()],
+            values: ["water", "grass"]
+                .iter()
+                .map(|class| tile::Value {
+                    string_value: Some(class.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            extent: Some(4096),
+        };
+        let style_layer: StyleLayer = serde_json::from_value(json!({
+            "id": "landcover",
+            "type": "fill",
+            "source-layer": "landcover",
+            "paint": {
+                "fill-color": ["match", ["get", "class"], "water", "#0000ff", "#00ff00"]
+            }
+        }))
+        .unwrap();
+
+        let metadata = UploadStage::feature_metadata(
+            &style_layer,
+            &layer,
+            &[3, 6],
+            Zoom::new(0.0),
+            ColorSpace::Srgb,
+        );
+        let colors = metadata.iter().map(|style| style.color).collect::<Vec<_>>();
+
+        assert_eq!(colors.len(), 9);
+        assert_eq!(colors[0], [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(colors[2], [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(colors[3], [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(colors[8], [0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_layer_metadata_with_fill_pattern() {
+        let style_layer: StyleLayer = serde_json::from_value(json!({
+            "id": "landuse",
+            "type": "fill",
+            "source-layer": "landuse",
+            "paint": { "fill-pattern": "hatch", "fill-opacity": 0.5 }
+        }))
+        .unwrap();
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(&[255; 32 * 16 * 4], 32, 16, ColorType::Rgba8)
+            .unwrap();
+        let index = br#"{"hatch": {"x": 16, "y": 0, "width": 16, "height": 16, "pixelRatio": 2}}"#;
+        let sprite_sheet = SpriteSheet::from_data(index, &png, 2.0).unwrap();
+
+        // The hatch is repeated every 8 pixels
+        let metadata =
+            UploadStage::layer_metadata(&style_layer, Zoom::new(14.0), Some(&sprite_sheet));
+        assert_eq!(metadata.pattern, [0.5, 0.0, 1.0, 1.0]);
+        assert_eq!(metadata.pattern_size, [8.0, 8.0]);
+
+        // Without the image the layer is filled with its color
+        let metadata = UploadStage::layer_metadata(&style_layer, Zoom::new(14.0), None);
+        assert_eq!(metadata.pattern_size, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_layer_metadata_with_line_pattern() {
+        let style_layer: StyleLayer = serde_json::from_value(json!({
+            "id": "railway",
+            "type": "line",
+            "source-layer": "transportation",
+            "paint": { "line-pattern": "railway", "line-width": 4 }
+        }))
+        .unwrap();
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(&[255; 16 * 8 * 4], 16, 8, ColorType::Rgba8)
+            .unwrap();
+        let index = br#"{"railway": {"x": 0, "y": 0, "width": 16, "height": 8, "pixelRatio": 1}}"#;
+        let sprite_sheet = SpriteSheet::from_data(index, &png, 1.0).unwrap();
+
+        // The ties of the railway repeat every 16 pixels along the line
+        let metadata =
+            UploadStage::layer_metadata(&style_layer, Zoom::new(14.0), Some(&sprite_sheet));
+        assert_eq!(metadata.pattern, [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(metadata.pattern_size, [16.0, 8.0]);
+    }
+
+    type TestBufferPool = BufferPool<
+        RecordingQueue,
+        TestBuffer,
+        ShaderVertex,
+        IndexDataType,
+        ShaderLayerMetadata,
+        ShaderFeatureStyle,
+    >;
+
+    /// Uploads a tile with a blue "water" layer of a single triangle. The writes of the upload are
+    /// not recorded.
+    fn uploaded_water_layer() -> (
+        TestBufferPool,
+        RecordingQueue,
+        Style,
+        TileCache,
+        tile::Layer,
+    ) {
+        let descriptor = |name| BackingBufferDescriptor::new(TestBuffer { name }, 4096);
+        let mut buffer_pool = BufferPool::new(
+            descriptor("vertices"),
+            descriptor("indices"),
+            descriptor("layer_metadata"),
+            descriptor("feature_metadata"),
+        );
+        let queue = RecordingQueue::default();
+
+        let style = Style {
+            layers: vec![serde_json::from_value(json!({
+                "id": "water",
+                "type": "fill",
+                "source-layer": "water",
+                "paint": { "fill-color": "#0000ff" }
+            }))
+            .unwrap()],
+            ..Style::default()
+        };
+        let coords: WorldTileCoords = (0, 0, 0).into();
+        let layer_data = tile::Layer {
+            version: 2,
+            name: "water".to_string(),
+            features: vec![tile::Feature::default()],
+            keys: vec![],
+            values: vec![],
+            extent: Some(4096),
+        };
+        let mut geometry = VertexBuffers::new();
+        geometry.vertices.extend([ShaderVertex::default(); 3]);
+        geometry.indices.extend([0, 1, 2]);
+        let mut tile_cache = TileCache::new();
+        tile_cache.put_tessellated_layer(LayerTessellateMessage::TessellatedLayer {
+            coords,
+            source: None,
+            buffer: geometry.clone().into(),
+            feature_indices: vec![3],
+            layer_data: layer_data.clone(),
+        });
+
+        let zoom = Zoom::new(0.0);
+        buffer_pool.allocate_layer_geometry(
+            &queue,
+            coords,
+            style.layers[0].clone(),
+            &geometry.into(),
+            UploadStage::layer_metadata(&style.layers[0], zoom, None),
+            &UploadStage::feature_metadata(
+                &style.layers[0],
+                &layer_data,
+                &[3],
+                zoom,
+                ColorSpace::Srgb,
+            ),
+        );
+        queue.writes.borrow_mut().clear();
+
+        (buffer_pool, queue, style, tile_cache, layer_data)
+    }
+
+    /// Returns the data of the write to the feature metadata.
+    fn feature_metadata_write(queue: &RecordingQueue) -> Vec<u8> {
+        queue
+            .writes
+            .borrow()
+            .iter()
+            .find(|(name, _)| *name == "feature_metadata")
+            .map(|(_, data)| data.clone())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_update_paint_uploads_feature_metadata() {
+        let (mut buffer_pool, queue, mut style, tile_cache, layer_data) = uploaded_water_layer();
+        let zoom = Zoom::new(0.0);
+        let old_metadata = UploadStage::feature_metadata(
+            &style.layers[0],
+            &layer_data,
+            &[3],
+            zoom,
+            ColorSpace::Srgb,
+        );
+
+        assert!(style.set_paint_property("water", "fill-color", json!("#ff0000")));
+        UploadStage::update_paint(
+            &mut buffer_pool,
+            &queue,
+            &tile_cache,
+            &style,
+            &[&style.layers[0]],
+            zoom,
+            ColorSpace::Srgb,
+        );
+
+        let new_metadata = UploadStage::feature_metadata(
+            &style.layers[0],
+            &layer_data,
+            &[3],
+            zoom,
+            ColorSpace::Srgb,
+        );
+        assert_eq!(new_metadata[0].color, [1.0, 0.0, 0.0, 1.0]);
+        let uploaded = feature_metadata_write(&queue);
+        assert_eq!(
+            uploaded.as_slice(),
+            bytemuck::cast_slice::<_, u8>(&new_metadata)
+        );
+        assert_ne!(
+            uploaded.as_slice(),
+            bytemuck::cast_slice::<_, u8>(&old_metadata)
+        );
+        // The geometry is not uploaded again
+        let writes = queue.writes.borrow();
+        assert!(writes
+            .iter()
+            .all(|(name, _)| *name != "vertices" && *name != "indices"));
+        assert_eq!(
+            serde_json::to_value(&buffer_pool.index().front().unwrap().style_layer).unwrap(),
+            serde_json::to_value(&style.layers[0]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_animate_paint_blends_colors() {
+        let (buffer_pool, queue, mut style, tile_cache, layer_data) = uploaded_water_layer();
+        let animate = |style: &Style, millis| {
+            queue.writes.borrow_mut().clear();
+            UploadStage::animate_paint(
+                &buffer_pool,
+                &queue,
+                &tile_cache,
+                style,
+                Duration::from_millis(millis),
+                Zoom::new(0.0),
+                ColorSpace::Srgb,
+            );
+        };
+
+        // Static layers are not uploaded again
+        animate(&style, 500);
+        assert!(queue.writes.borrow().is_empty());
+
+        let animation = ColorAnimation::new(Color::from_rgb(1.0, 0.0, 0.0), Duration::from_secs(2));
+        assert!(style.set_color_animation("water", Some(animation)));
+        assert!(!style.set_color_animation("unknown", None));
+
+        // Halfway to the peak, blue is blended with red at equal parts
+        animate(&style, 500);
+        let mut expected = UploadStage::feature_metadata(
+            &style.layers[0],
+            &layer_data,
+            &[3],
+            Zoom::new(0.0),
+            ColorSpace::Srgb,
+        );
+        for feature_style in &mut expected {
+            feature_style.color = [0.5, 0.0, 0.5, 1.0];
+        }
+        assert_eq!(
+            feature_metadata_write(&queue).as_slice(),
+            bytemuck::cast_slice::<_, u8>(&expected)
+        );
+
+        // Stopping the animation uploads the colors of the style again
+        let revision = style.paint_revisions.get("water").copied();
+        assert!(style.set_color_animation("water", None));
+        assert!(style.animations.is_empty());
+        assert_ne!(style.paint_revisions.get("water").copied(), revision);
+    }
+
+    #[test]
+    fn test_feature_metadata_without_paint() {
+        let layer = tile::Layer {
+            version: 2,
+            name: "water".to_string(),
+            features: vec![tile::Feature::default(), tile::Feature::default()],
+            keys: vec![],
+            values: vec![],
+            extent: Some(4096),
+        };
+        let style_layer: StyleLayer = serde_json::from_value(json!({
+            "id": "water",
+            "type": "line",
+            "source-layer": "water",
+        }))
+        .unwrap();
+
+        // The indices of the second feature are missing
+        let metadata = UploadStage::feature_metadata(
+            &style_layer,
+            &layer,
+            &[3],
+            Zoom::new(0.0),
+            ColorSpace::Srgb,
+        );
+
+        assert_eq!(metadata.len(), 3);
+        assert!(metadata
+            .iter()
+            .all(|style| style.color == super::DEFAULT_COLOR));
+    }
+}
@@ -0,0 +1,117 @@
+//! Decides how to recover once the frame of a surface can not be acquired. Surfaces can be lost at
+//! any time, e.g. because the GPU was reset or the window moved to another display. Transient
+//! failures only skip a frame. A surface which stays lost is a sign that the device is lost as
+//! well, so the whole renderer is initialized again.
+
+use crate::error::RenderError;
+
+/// Number of frames in a row which may fail before the renderer is initialized again.
+const MAX_FAILED_FRAMES: u32 = 60;
+
+/// Number of times in a row the renderer is initialized again before giving up.
+const MAX_REINITIALIZATIONS: u32 = 3;
+
+/// What to do after a frame could not be acquired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceRecoveryAction {
+    /// The frame is skipped and the next frame is tried as usual.
+    SkipFrame,
+    /// The renderer is dropped and initialized again, while the tile cache is kept.
+    Reinitialize,
+}
+
+/// Counts the failures since the last frame which was presented.
+#[derive(Debug, Default)]
+pub struct SurfaceRecovery {
+    failed_frames: u32,
+    reinitializations: u32,
+}
+
+impl SurfaceRecovery {
+    /// Resets the failures once a frame was presented.
+    pub fn frame_presented(&mut self) {
+        self.failed_frames = 0;
+        self.reinitializations = 0;
+    }
+
+    /// Returns how to recover from `error`, which happened when acquiring the frame after the
+    /// surface was configured again. Returns an error if recovering failed too often.
+    pub fn frame_failed(
+        &mut self,
+        error: &wgpu::SurfaceError,
+    ) -> Result<SurfaceRecoveryAction, RenderError> {
+        let action = match error {
+            wgpu::SurfaceError::OutOfMemory => {
+                return Err(RenderError::Surface(error.clone()));
+            }
+            wgpu::SurfaceError::Lost => SurfaceRecoveryAction::Reinitialize,
+            wgpu::SurfaceError::Timeout | wgpu::SurfaceError::Outdated => {
+                self.failed_frames += 1;
+                if self.failed_frames > MAX_FAILED_FRAMES {
+                    SurfaceRecoveryAction::Reinitialize
+                } else {
+                    SurfaceRecoveryAction::SkipFrame
+                }
+            }
+        };
+
+        if action == SurfaceRecoveryAction::Reinitialize {
+            self.failed_frames = 0;
+            self.reinitializations += 1;
+            if self.reinitializations > MAX_REINITIALIZATIONS {
+                return Err(RenderError::RecoveryFailed(self.reinitializations - 1));
+            }
+        }
+
+        Ok(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SurfaceRecovery, SurfaceRecoveryAction, MAX_FAILED_FRAMES, MAX_REINITIALIZATIONS};
+    use crate::error::RenderError;
+
+    #[test]
+    fn test_lost_surface_is_recovered() {
+        let mut recovery = SurfaceRecovery::default();
+
+        // An outdated surface only skips frames until it is outdated for too long
+        for _ in 0..MAX_FAILED_FRAMES {
+            assert_eq!(
+                recovery
+                    .frame_failed(&wgpu::SurfaceError::Outdated)
+                    .unwrap(),
+                SurfaceRecoveryAction::SkipFrame
+            );
+        }
+        assert_eq!(
+            recovery
+                .frame_failed(&wgpu::SurfaceError::Outdated)
+                .unwrap(),
+            SurfaceRecoveryAction::Reinitialize
+        );
+
+        // A lost surface initializes the renderer again, until that failed too often in a row
+        for _ in 1..MAX_REINITIALIZATIONS {
+            assert_eq!(
+                recovery.frame_failed(&wgpu::SurfaceError::Lost).unwrap(),
+                SurfaceRecoveryAction::Reinitialize
+            );
+        }
+        assert!(matches!(
+            recovery.frame_failed(&wgpu::SurfaceError::Lost),
+            Err(RenderError::RecoveryFailed(MAX_REINITIALIZATIONS))
+        ));
+
+        // Presenting a frame means that the renderer recovered
+        recovery.frame_presented();
+        assert_eq!(
+            recovery.frame_failed(&wgpu::SurfaceError::Lost).unwrap(),
+            SurfaceRecoveryAction::Reinitialize
+        );
+        assert!(recovery
+            .frame_failed(&wgpu::SurfaceError::OutOfMemory)
+            .is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! Debug overlay which outlines the tiles in view and prints their coordinates, like the
+//! `showTileBoundaries` option of maplibre-gl-js.
+
+use crate::coords::{WorldTileCoords, EXTENT, TILE_SIZE};
+use crate::render::shaders::{DebugVertex, Vec2f32, Vec4f32};
+use crate::render::tile_view_pattern::{TileInView, TileShape};
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::ops::Range;
+
+/// Maximum amount of vertices which can be drawn in a single frame.
+const TILE_BOUNDARIES_SIZE: wgpu::BufferAddress = 16384;
+
+/// Color of the tiles in view which are drawn with their own layers.
+const TILE_COLOR: Vec4f32 = [1.0, 0.0, 0.0, 1.0];
+/// Color of the ancestors which are drawn instead of incomplete or overzoomed tiles.
+const FALLBACK_COLOR: Vec4f32 = [0.0, 0.0, 1.0, 1.0];
+
+/// Size of a digit in pixels.
+const GLYPH_WIDTH: f32 = 8.0;
+const GLYPH_HEIGHT: f32 = 14.0;
+/// Distance between the left edges of two digits in pixels.
+const GLYPH_ADVANCE: f32 = 12.0;
+/// Distance between the top edges of two lines in pixels.
+const LINE_HEIGHT: f32 = 20.0;
+
+/// Segments of a seven-segment display in units of the glyph size: top, top right, bottom right,
+/// bottom, bottom left, top left and middle.
+const SEGMENTS: [[Vec2f32; 2]; 7] = [
+    [[0.0, 0.0], [1.0, 0.0]],
+    [[1.0, 0.0], [1.0, 0.5]],
+    [[1.0, 0.5], [1.0, 1.0]],
+    [[0.0, 1.0], [1.0, 1.0]],
+    [[0.0, 0.5], [0.0, 1.0]],
+    [[0.0, 0.0], [0.0, 0.5]],
+    [[0.0, 0.5], [1.0, 0.5]],
+];
+
+/// Lines of the overlay which are drawn with the transform of `shape`.
+#[derive(Clone)]
+pub struct TileBoundary {
+    pub shape: TileShape,
+    pub vertices: Range<u32>,
+}
+
+pub struct TileBoundaries {
+    in_view: Vec<TileBoundary>,
+    vertices: Vec<DebugVertex>,
+
+    buffer: wgpu::Buffer,
+}
+
+impl TileBoundaries {
+    pub fn from_device(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("tile boundaries buffer"),
+            size: size_of::<DebugVertex>() as wgpu::BufferAddress * TILE_BOUNDARIES_SIZE,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            in_view: Vec::new(),
+            vertices: Vec::with_capacity(TILE_BOUNDARIES_SIZE as usize),
+            buffer,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TileBoundary> + '_ {
+        self.in_view.iter()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Outlines the `tiles` in view and prints their coordinates. Tiles which fall back to an
+    /// ancestor print the coordinates of the ancestor below their own, and the ancestor is
+    /// outlined in another color.
+    pub fn prepare<'a>(
+        &mut self,
+        queue: &wgpu::Queue,
+        tiles: impl Iterator<Item = &'a TileInView>,
+    ) {
+        self.in_view.clear();
+        self.vertices.clear();
+
+        let mut outlined_fallbacks = HashSet::new();
+        for TileInView { shape, fallback } in tiles {
+            let fallback_coords = fallback.as_ref().map(|fallback| fallback.coords);
+            if !self.push_in_view(
+                shape,
+                tile_vertices(&shape.coords, fallback_coords.as_ref()),
+            ) {
+                tracing::warn!("Too many tile boundaries in view");
+                break;
+            }
+
+            if let Some(fallback) = fallback {
+                if outlined_fallbacks.insert(fallback.unwrapped_coords)
+                    && !self.push_in_view(fallback, outline_vertices(FALLBACK_COLOR))
+                {
+                    tracing::warn!("Too many tile boundaries in view");
+                    break;
+                }
+            }
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+    }
+
+    /// Returns false if the vertices do not fit into the buffer anymore.
+    fn push_in_view(&mut self, shape: &TileShape, vertices: Vec<DebugVertex>) -> bool {
+        let start = self.vertices.len() as u32;
+        let end = start + vertices.len() as u32;
+        if end as wgpu::BufferAddress > TILE_BOUNDARIES_SIZE {
+            return false;
+        }
+
+        self.vertices.extend(vertices);
+        self.in_view.push(TileBoundary {
+            shape: shape.clone(),
+            vertices: start..end,
+        });
+        true
+    }
+}
+
+/// Returns the outline and the coordinates of the tile at `coords`. If the tile falls back to an
+/// ancestor, the coordinates of the ancestor are printed below.
+fn tile_vertices(coords: &WorldTileCoords, fallback: Option<&WorldTileCoords>) -> Vec<DebugVertex> {
+    let mut vertices = outline_vertices(TILE_COLOR);
+    match fallback {
+        Some(fallback) => {
+            vertices.extend(text_vertices(&coords_text(coords), 0, FALLBACK_COLOR));
+            vertices.extend(text_vertices(&coords_text(fallback), 1, FALLBACK_COLOR));
+        }
+        None => vertices.extend(text_vertices(&coords_text(coords), 0, TILE_COLOR)),
+    }
+    vertices
+}
+
+/// Formats the coordinates like tile URLs do: `z/x/y`.
+fn coords_text(coords: &WorldTileCoords) -> String {
+    format!("{}/{}/{}", coords.z, coords.x, coords.y)
+}
+
+/// Returns the lines along the edges of a tile.
+fn outline_vertices(color: Vec4f32) -> Vec<DebugVertex> {
+    let extent = EXTENT as f32;
+    let corners = [[0.0, 0.0], [extent, 0.0], [extent, extent], [0.0, extent]];
+    (0..corners.len())
+        .flat_map(|i| [corners[i], corners[(i + 1) % corners.len()]])
+        .map(|corner| DebugVertex::new(corner, [0.0, 0.0], color))
+        .collect()
+}
+
+/// Returns the lines of `text`, centered horizontally in the tile. The first `line` is centered
+/// vertically and further lines follow below. Characters which are not supported are skipped.
+fn text_vertices(text: &str, line: usize, color: Vec4f32) -> Vec<DebugVertex> {
+    let pixel_to_tile = (EXTENT / TILE_SIZE) as f32;
+    let center = [EXTENT as f32 / 2.0, EXTENT as f32 / 2.0];
+
+    let width = text.chars().count() as f32 * GLYPH_ADVANCE - (GLYPH_ADVANCE - GLYPH_WIDTH);
+    let left = -width / 2.0;
+    let top = line as f32 * LINE_HEIGHT - GLYPH_HEIGHT / 2.0;
+
+    text.chars()
+        .enumerate()
+        .flat_map(|(i, c)| {
+            let x = left + i as f32 * GLYPH_ADVANCE;
+            glyph_strokes(c).into_iter().flatten().map(move |[u, v]| {
+                DebugVertex::new(
+                    center,
+                    [
+                        (x + u * GLYPH_WIDTH) * pixel_to_tile,
+                        (top + v * GLYPH_HEIGHT) * pixel_to_tile,
+                    ],
+                    color,
+                )
+            })
+        })
+        .collect()
+}
+
+/// Returns the strokes of a character in units of the glyph size. Only digits and the slash which
+/// separates the coordinates are supported.
+fn glyph_strokes(c: char) -> Vec<[Vec2f32; 2]> {
+    let segments: &[usize] = match c {
+        '0' => &[0, 1, 2, 3, 4, 5],
+        '1' => &[1, 2],
+        '2' => &[0, 1, 6, 4, 3],
+        '3' => &[0, 1, 6, 2, 3],
+        '4' => &[5, 6, 1, 2],
+        '5' => &[0, 5, 6, 2, 3],
+        '6' => &[0, 5, 6, 4, 2, 3],
+        '7' => &[0, 1, 2],
+        '8' => &[0, 1, 2, 3, 4, 5, 6],
+        '9' => &[0, 1, 2, 3, 5, 6],
+        '/' => return vec![[[0.0, 1.0], [1.0, 0.0]]],
+        _ => &[],
+    };
+    segments.iter().map(|segment| SEGMENTS[*segment]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{coords_text, outline_vertices, text_vertices, tile_vertices, TILE_COLOR};
+    use crate::coords::{WorldTileCoords, EXTENT, TILE_SIZE};
+    use crate::render::shaders::DebugVertex;
+
+    fn max_y(vertices: &[DebugVertex]) -> f32 {
+        vertices
+            .iter()
+            .map(|vertex| vertex.offset[1])
+            .fold(f32::MIN, f32::max)
+    }
+
+    #[test]
+    fn test_outline_vertices() {
+        let vertices = outline_vertices(TILE_COLOR);
+        assert_eq!(vertices.len(), 8);
+        // Each line starts where the previous one ended
+        for i in (1..vertices.len() - 1).step_by(2) {
+            assert_eq!(vertices[i].anchor, vertices[i + 1].anchor);
+        }
+        assert_eq!(vertices[7].anchor, vertices[0].anchor);
+        assert!(vertices.iter().all(|vertex| vertex.offset == [0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_text_vertices() {
+        // "0" has six segments, the slash one line and "1" two segments
+        let vertices = text_vertices("0/1", 0, TILE_COLOR);
+        assert_eq!(vertices.len(), 2 * (6 + 1 + 2));
+
+        // The text is centered on the tile
+        let pixel_to_tile = (EXTENT / TILE_SIZE) as f32;
+        let min_x = vertices
+            .iter()
+            .map(|v| v.offset[0])
+            .fold(f32::MAX, f32::min);
+        let max_x = vertices
+            .iter()
+            .map(|v| v.offset[0])
+            .fold(f32::MIN, f32::max);
+        assert_eq!(min_x, -16.0 * pixel_to_tile);
+        assert_eq!(max_x, 16.0 * pixel_to_tile);
+        assert!(vertices
+            .iter()
+            .all(|v| v.anchor == [EXTENT as f32 / 2.0, EXTENT as f32 / 2.0]));
+
+        // Unsupported characters are skipped
+        assert!(text_vertices("()", 0, TILE_COLOR).is_empty());
+    }
+
+    #[test]
+    fn test_fallback_coordinates_are_printed_below() {
+        let coords = WorldTileCoords { x: 1, y: 1, z: 1 };
+        let fallback = WorldTileCoords { x: 0, y: 0, z: 0 };
+        assert_eq!(coords_text(&coords), "1/1/1");
+
+        let complete = tile_vertices(&coords, None);
+        let incomplete = tile_vertices(&coords, Some(&fallback));
+        assert!(incomplete.len() > complete.len());
+        assert!(max_y(&incomplete) > max_y(&complete));
+    }
+}
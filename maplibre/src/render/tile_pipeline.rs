@@ -1,13 +1,15 @@
 //! Utility for declaring pipelines.
 
 use crate::platform::MIN_BUFFER_SIZE;
-use crate::render::resource::{FragmentState, VertexState};
+use crate::render::resource::{FragmentState, SpriteAtlas, VertexState};
 use crate::render::resource::{RenderPipeline, RenderPipelineDescriptor};
 use crate::render::settings::Msaa;
 use crate::render::shaders::ShaderGlobals;
 use std::cmp;
 
 pub struct TilePipeline {
+    /// Binds the globals and the sprite atlas, which contains the images of `fill-pattern` and
+    /// `line-pattern`.
     bind_globals: bool,
     update_stencil: bool,
     debug_stencil: bool,
@@ -68,16 +70,19 @@ impl RenderPipeline for TilePipeline {
         RenderPipelineDescriptor {
             label: None,
             layout: if self.bind_globals {
-                Some(vec![vec![wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(globals_buffer_byte_size),
-                    },
-                    count: None,
-                }]])
+                Some(vec![
+                    vec![wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(globals_buffer_byte_size),
+                        },
+                        count: None,
+                    }],
+                    SpriteAtlas::bind_group_layout_entries(),
+                ])
             } else {
                 None
             },
@@ -101,7 +106,13 @@ impl RenderPipeline for TilePipeline {
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: wgpu::TextureFormat::Depth24PlusStencil8,
                 depth_write_enabled: !self.update_stencil,
-                depth_compare: wgpu::CompareFunction::Greater,
+                // Masks only update the stencil. Features of the same layer share a depth, so
+                // later features of a layer are drawn on top of earlier ones.
+                depth_compare: if self.update_stencil {
+                    wgpu::CompareFunction::Always
+                } else {
+                    wgpu::CompareFunction::GreaterEqual
+                },
                 stencil: wgpu::StencilState {
                     front: stencil_state,
                     back: stencil_state,
@@ -1,6 +1,6 @@
 //! Utility for generating a tile pattern which can be used for masking.
 
-use crate::coords::{ViewRegion, WorldTileCoords, Zoom};
+use crate::coords::{ViewRegion, WorldTileCoords, Zoom, EXTENT, TILE_SIZE};
 use crate::render::camera::ViewProjection;
 use crate::render::resource::{BackingBufferDescriptor, BufferPool, Queue};
 use crate::render::shaders::{ShaderFeatureStyle, ShaderLayerMetadata, ShaderTileMetadata};
@@ -12,6 +12,27 @@ use std::marker::PhantomData;
 use std::mem::size_of;
 use std::ops::Range;
 
+/// Size of the metadata of a [`TileShape`] within the buffer.
+const STRIDE: u64 = size_of::<ShaderTileMetadata>() as u64;
+
+/// How far the masks extend beyond the edges of the tiles in pixels. The transforms of adjacent
+/// tiles are rounded differently to 32 bit, so masks which end exactly at the shared edge leave
+/// hairline gaps. Where the masks overlap, the tile which is masked last shows the geometry of its
+/// buffer.
+///
+/// A pixel is masked if its center lies within the mask. The edges of adjacent masks are apart by
+/// the rounding error, which stays far below a pixel, see `test_mask_overscan`. Overscanning both
+/// masks by half a pixel lets them overlap by a whole pixel, so the center of every pixel at the
+/// seam lies within at least one of them. Each pixel keeps the stencil value of a single tile, so
+/// translucent layers are not blended twice within the overlap. A larger overscan would only reach
+/// further into the buffer of the tiles, which does not contain all features of the neighbour.
+const MASK_OVERSCAN: f64 = 0.5;
+
+/// Returns [`MASK_OVERSCAN`] in tile units for a tile which is scaled by `zoom_factor`.
+fn mask_overscan(zoom_factor: f64) -> f64 {
+    MASK_OVERSCAN * EXTENT / TILE_SIZE * zoom_factor
+}
+
 /// The tile mask pattern assigns each tile a value which can be used for stencil testing.
 pub struct TileViewPattern<Q, B> {
     in_view: Vec<TileInView>,
@@ -23,19 +44,28 @@ pub struct TileViewPattern<Q, B> {
 pub struct TileShape {
     pub zoom_factor: f64,
 
+    /// Coords of the tile within the world, which identify its data.
     pub coords: WorldTileCoords,
+    /// Coords at which the tile is drawn. Copies of the world left and right of it have the same
+    /// `coords` but differ in the `x` of these coords, see [`ViewRegion::iter_unwrapped`].
+    pub unwrapped_coords: WorldTileCoords,
 
     pub transform: Matrix4<f64>,
     pub buffer_range: Range<wgpu::BufferAddress>,
 }
 
 impl TileShape {
-    fn new(coords: WorldTileCoords, zoom: Zoom, index: u64) -> Self {
-        const STRIDE: u64 = size_of::<ShaderTileMetadata>() as u64;
+    /// Index of the shape among the shapes in view.
+    pub fn index(&self) -> usize {
+        (self.buffer_range.start / STRIDE) as usize
+    }
+
+    fn new(unwrapped_coords: WorldTileCoords, zoom: Zoom, index: u64) -> Self {
         Self {
-            coords,
-            zoom_factor: zoom.scale_to_tile(&coords),
-            transform: coords.transform_for_zoom(zoom),
+            coords: unwrapped_coords.wrap(),
+            unwrapped_coords,
+            zoom_factor: zoom.scale_to_tile(&unwrapped_coords),
+            transform: unwrapped_coords.transform_for_zoom(zoom),
             buffer_range: index as u64 * STRIDE..(index as u64 + 1) * STRIDE,
         }
     }
@@ -71,6 +101,11 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
         }
     }
 
+    /// Assigns each tile in view a shape. Tiles which are not completely rendered yet, as decided
+    /// by `is_complete`, fall back to the closest ancestor which has uploaded layers. The ancestor
+    /// is drawn until all layers of the tile are uploaded, such that the tile does not appear
+    /// partially. Tiles beyond the antimeridian are drawn with the data of the tiles on the other
+    /// side of the world. The copies of the world share the geometry in the `buffer_pool`.
     #[tracing::instrument(skip_all)]
     pub fn update_pattern(
         &mut self,
@@ -84,40 +119,46 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
             ShaderFeatureStyle,
         >,
         zoom: Zoom,
+        is_complete: impl Fn(&WorldTileCoords) -> bool,
     ) {
         self.in_view.clear();
 
         let mut index = 0;
+        let capacity = self.buffer.inner_size / STRIDE;
 
         let pool_index = buffer_pool.index();
 
-        for coords in view_region.iter() {
-            if coords.build_quad_key().is_none() {
+        for coords in view_region.iter_unwrapped() {
+            if coords.wrap().build_quad_key().is_none() {
                 continue;
             }
 
+            // Each tile might need a second shape for its fallback
+            if index + 2 > capacity {
+                tracing::warn!("Too many tiles in view, skipping the remaining ones");
+                break;
+            }
+
             let shape = TileShape::new(coords, zoom, index);
 
             index += 1;
 
-            let fallback = {
-                if !pool_index.has_tile(&coords) {
-                    if let Some(fallback_coords) = pool_index.get_tile_coords_fallback(&coords) {
-                        tracing::trace!(
-                            "Could not find data at {coords}. Falling back to {fallback_coords}"
-                        );
-
-                        let shape = TileShape::new(fallback_coords, zoom, index);
-
-                        index += 1;
-                        Some(shape)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            };
+            // The ancestors of a copy of a tile are the copies of the ancestors
+            let fallback = fallback_coords(
+                &coords,
+                |coords| is_complete(&coords.wrap()),
+                |coords| pool_index.has_tile(&coords.wrap()),
+            )
+            .map(|fallback_coords| {
+                tracing::trace!(
+                    "Tile at {coords} is incomplete. Falling back to {fallback_coords}"
+                );
+
+                let shape = TileShape::new(fallback_coords, zoom, index);
+
+                index += 1;
+                shape
+            });
 
             self.in_view.push(TileInView { shape, fallback });
         }
@@ -144,6 +185,7 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
                     .downcast()
                     .into(),
                 zoom_factor: tile.shape.zoom_factor as f32,
+                mask_overscan: mask_overscan(tile.shape.zoom_factor) as f32,
             });
 
             if let Some(fallback_shape) = &tile.fallback {
@@ -155,6 +197,7 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
                         .downcast()
                         .into(),
                     zoom_factor: fallback_shape.zoom_factor as f32,
+                    mask_overscan: mask_overscan(fallback_shape.zoom_factor) as f32,
                 });
             }
         }
@@ -177,3 +220,106 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
             }
     }
 }
+
+/// Returns the closest ancestor of an incomplete tile at `coords` for which `has_tile` returns
+/// true. Returns `None` if the tile is complete or no ancestor is available, in which case the
+/// tile is drawn with the layers which it has.
+fn fallback_coords(
+    coords: &WorldTileCoords,
+    is_complete: impl Fn(&WorldTileCoords) -> bool,
+    has_tile: impl Fn(&WorldTileCoords) -> bool,
+) -> Option<WorldTileCoords> {
+    if is_complete(coords) {
+        return None;
+    }
+
+    let mut current = coords.get_parent()?;
+    loop {
+        if has_tile(&current) {
+            return Some(current);
+        }
+        current = current.get_parent()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fallback_coords, mask_overscan, TileShape, MASK_OVERSCAN};
+    use crate::context::ViewState;
+    use crate::coords::{WorldTileCoords, Zoom, EXTENT, TILE_SIZE};
+    use crate::WindowSize;
+    use cgmath::Vector4;
+
+    #[test]
+    fn test_fallback_coords() {
+        let child = WorldTileCoords { x: 4, y: 6, z: 3 };
+        let parent = WorldTileCoords { x: 2, y: 3, z: 2 };
+        let grandparent = WorldTileCoords { x: 1, y: 1, z: 1 };
+
+        // Complete tiles are drawn themselves
+        assert_eq!(fallback_coords(&child, |_| true, |_| true), None);
+
+        // Incomplete tiles fall back to their closest available ancestor
+        assert_eq!(fallback_coords(&child, |_| false, |_| true), Some(parent));
+        assert_eq!(
+            fallback_coords(&child, |_| false, |coords| *coords == grandparent),
+            Some(grandparent)
+        );
+
+        // Without any ancestor the incomplete tile is drawn anyway
+        assert_eq!(
+            fallback_coords(&child, |_| false, |coords| *coords == child),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mask_overscan() {
+        let mut view_state = ViewState::new(&WindowSize::new(800, 600).unwrap());
+        let zoom = Zoom::new(16.5);
+        view_state.update_zoom(zoom);
+
+        // Two adjacent tiles far from the origin, such that their transforms are rounded
+        let left = WorldTileCoords {
+            x: 34_000,
+            y: 22_000,
+            z: 16,
+        };
+        let right = WorldTileCoords { x: 34_001, ..left };
+        let tile_size = TILE_SIZE * Zoom::new(16.0).scale_delta(&zoom);
+        view_state.camera.position.x = right.x as f64 * tile_size;
+        view_state.camera.position.y = (right.y as f64 + 0.5) * tile_size;
+
+        let view_proj = view_state.view_projection();
+        // Returns the horizontal position in pixels of the point at `x` in tile units
+        let project = |coords: &WorldTileCoords, x: fn(f64) -> f64| -> f32 {
+            let shape = TileShape::new(*coords, zoom, 0);
+            let x = x(mask_overscan(shape.zoom_factor)) as f32;
+            let transform = view_proj
+                .to_model_view_projection(shape.transform)
+                .downcast();
+            let position = transform * Vector4::new(x, EXTENT as f32 / 2.0, 0.0, 1.0);
+            (position.x / position.w + 1.0) / 2.0 * 800.0
+        };
+
+        // Without overscan the edges of both masks would be apart by the rounding error, which
+        // has to stay below the overlap of both masks
+        let rounding_error = (project(&left, |_| EXTENT) - project(&right, |_| 0.0)).abs();
+        assert!(
+            (rounding_error as f64) < MASK_OVERSCAN,
+            "rounding error of {} pixels",
+            rounding_error
+        );
+
+        // The masks of both tiles overlap around their shared edge
+        let overlap =
+            project(&left, |overscan| EXTENT + overscan) - project(&right, |overscan| -overscan);
+        let expected = 2.0 * MASK_OVERSCAN / view_state.world_units_per_pixel();
+        assert!(
+            (overlap as f64 - expected).abs() < 0.1,
+            "overlap of {} instead of {}",
+            overlap,
+            expected
+        );
+    }
+}
@@ -1,10 +1,12 @@
 use crate::context::MapContext;
 use crate::define_label;
 use downcast_rs::{impl_downcast, Downcast};
+use instant::Instant;
 use std::any::Any;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::rc::Rc;
+use std::time::Duration;
 
 pub struct NopStage;
 
@@ -83,6 +85,8 @@ impl_downcast!(Stage);
 pub struct Schedule {
     stages: HashMap<BoxedStageLabel, Box<dyn Stage>>,
     stage_order: Vec<BoxedStageLabel>,
+    /// How long the stages took during the last run, in execution order.
+    durations: Vec<Duration>,
 }
 
 impl Schedule {
@@ -249,14 +253,26 @@ impl Schedule {
 
     /// Executes each [`Stage`] contained in the schedule, one at a time.
     pub fn run_once(&mut self, context: &mut MapContext) {
+        self.durations.clear();
         for label in &self.stage_order {
             #[cfg(feature = "trace")]
             let _stage_span = tracing::info_span!("stage", name = ?label).entered();
             let stage = self.stages.get_mut(label).unwrap();
+            let start = Instant::now();
             stage.run(context);
+            self.durations.push(start.elapsed());
         }
     }
 
+    /// Iterates over the labels of the stages and how long they took during the last run, in
+    /// execution order.
+    pub fn iter_durations(&self) -> impl Iterator<Item = (&dyn StageLabel, Duration)> {
+        self.stage_order
+            .iter()
+            .zip(self.durations.iter())
+            .map(|(label, duration)| (&**label, *duration))
+    }
+
     /// Iterates over all of schedule's stages and their labels, in execution order.
     pub fn iter_stages(&self) -> impl Iterator<Item = (&dyn StageLabel, &dyn Stage)> {
         self.stage_order
@@ -0,0 +1,17 @@
+//! Advances camera animations before the tiles in view are requested. Afterwards the camera is
+//! moved back above the world if it crossed the antimeridian.
+
+use crate::context::MapContext;
+use crate::schedule::Stage;
+use instant::Instant;
+
+#[derive(Default)]
+pub struct CameraAnimationStage {}
+
+impl Stage for CameraAnimationStage {
+    fn run(&mut self, MapContext { view_state, .. }: &mut MapContext) {
+        view_state.advance_animation(Instant::now());
+        // Animations are interpolated without wrapping, so the camera is wrapped afterwards
+        view_state.wrap_camera();
+    }
+}
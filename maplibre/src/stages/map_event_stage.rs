@@ -0,0 +1,85 @@
+//! Emits the events which describe the movement of the camera and whether the map is idle.
+
+use crate::context::MapContext;
+use crate::events::{MapEvent, MapEvents};
+use crate::render::camera_animation::CameraState;
+use crate::schedule::Stage;
+
+#[derive(Default)]
+pub struct MapEventStage {
+    /// The camera of the previous frame.
+    last_camera: Option<CameraState>,
+    is_moving: bool,
+    is_idle: bool,
+}
+
+impl Stage for MapEventStage {
+    fn run(&mut self, map_context: &mut MapContext) {
+        // Runs before the render stages, so this tells whether the previous frame showed all
+        // tiles in view
+        let is_fully_rendered = map_context.is_fully_rendered();
+        let camera = map_context.view_state.camera_state();
+        self.update(camera, is_fully_rendered, &mut map_context.events);
+    }
+}
+
+impl MapEventStage {
+    fn update(&mut self, camera: CameraState, is_fully_rendered: bool, events: &mut MapEvents) {
+        let moved = self
+            .last_camera
+            .map_or(false, |last_camera| last_camera != camera);
+        self.last_camera = Some(camera);
+
+        if moved && !self.is_moving {
+            self.is_moving = true;
+            events.emit(MapEvent::MoveStart);
+        } else if !moved && self.is_moving {
+            self.is_moving = false;
+            events.emit(MapEvent::MoveEnd);
+        }
+
+        let is_idle = !self.is_moving && is_fully_rendered;
+        if is_idle && !self.is_idle {
+            events.emit(MapEvent::Idle);
+        }
+        self.is_idle = is_idle;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MapEventStage;
+    use crate::events::{MapEvent, MapEvents};
+    use crate::render::camera_animation::CameraState;
+    use cgmath::{Rad, Vector2};
+
+    fn camera(x: f64) -> CameraState {
+        CameraState {
+            center: Vector2::new(x, 0.5),
+            zoom: 2.0,
+            pitch: Rad(0.0),
+            bearing: Rad(0.0),
+        }
+    }
+
+    #[test]
+    fn test_move_and_idle() {
+        let mut stage = MapEventStage::default();
+        let mut events = MapEvents::default();
+        let mut update = |x: f64, is_fully_rendered: bool| {
+            stage.update(camera(x), is_fully_rendered, &mut events);
+            events.drain().collect::<Vec<_>>()
+        };
+
+        assert!(update(0.5, false).is_empty());
+        assert_eq!(update(0.5, true), vec![MapEvent::Idle]);
+        // Idle is emitted only once
+        assert!(update(0.5, true).is_empty());
+
+        assert_eq!(update(0.6, true), vec![MapEvent::MoveStart]);
+        assert!(update(0.7, true).is_empty());
+        // New tiles came into view which are not rendered yet
+        assert_eq!(update(0.7, false), vec![MapEvent::MoveEnd]);
+        assert_eq!(update(0.7, true), vec![MapEvent::Idle]);
+    }
+}
@@ -2,14 +2,21 @@
 
 use crate::io::source_client::SourceClient;
 use crate::schedule::Schedule;
-use crate::stages::populate_tile_store_stage::PopulateTileStore;
+use crate::stages::camera_animation_stage::CameraAnimationStage;
+use crate::stages::map_event_stage::MapEventStage;
 use crate::HTTPClient;
 use request_stage::RequestStage;
 
+pub(crate) use populate_tile_store_stage::PopulateTileStore;
+
+mod camera_animation_stage;
+mod map_event_stage;
 mod populate_tile_store_stage;
 mod request_stage;
 
 pub fn register_stages<HC: HTTPClient>(schedule: &mut Schedule, source_client: SourceClient<HC>) {
+    schedule.add_stage("camera_animation", CameraAnimationStage::default());
     schedule.add_stage("request", RequestStage::new(source_client));
     schedule.add_stage("populate_tile_store", PopulateTileStore::default());
+    schedule.add_stage("map_events", MapEventStage::default());
 }
@@ -1,23 +1,41 @@
 //! Receives data from async threads and populates the [`crate::io::tile_cache::TileCache`].
 
 use crate::context::MapContext;
-use crate::io::{TessellateMessage, TileTessellateMessage};
+use crate::events::MapEvent;
+use crate::io::{
+    GeoJsonTileMessage, GlyphsMessage, RasterTileMessage, SourceErrorMessage, TessellateMessage,
+    TileJsonMessage, TileTessellateMessage,
+};
 use crate::schedule::Stage;
+use crate::style::source::Source;
 
 #[derive(Default)]
-pub struct PopulateTileStore {}
+pub struct PopulateTileStore {
+    received_messages: u64,
+}
+
+impl PopulateTileStore {
+    /// The number of messages received so far. The map needs to be rendered again if it changed.
+    pub fn received_messages(&self) -> u64 {
+        self.received_messages
+    }
+}
 
 impl Stage for PopulateTileStore {
     fn run(
         &mut self,
         MapContext {
+            view_state,
+            style,
             tile_cache,
             shared_thread_state,
             message_receiver,
+            events,
             ..
         }: &mut MapContext,
     ) {
         if let Ok(result) = message_receiver.try_recv() {
+            self.received_messages += 1;
             match result {
                 TessellateMessage::Layer(layer_result) => {
                     tracing::trace!(
@@ -25,7 +43,96 @@ impl Stage for PopulateTileStore {
                         layer_result.layer_name(),
                         layer_result.get_coords()
                     );
-                    tile_cache.put_tessellated_layer(layer_result);
+                    // Layers which are sliced from replaced GeoJSON data are outdated already
+                    let is_stale = match layer_result.source() {
+                        Some(source) => loop {
+                            if let Ok(tile_request_state) =
+                                shared_thread_state.tile_request_state.try_lock()
+                            {
+                                break tile_request_state
+                                    .is_geojson_request_stale(&layer_result.get_coords(), source);
+                            }
+                        },
+                        None => false,
+                    };
+                    if !is_stale {
+                        tile_cache.put_tessellated_layer(layer_result);
+                    }
+                }
+                TessellateMessage::Raster(raster_result) => {
+                    let coords = raster_result.get_coords();
+                    tracing::trace!(
+                        "Raster tile of {} at {} reached main thread",
+                        raster_result.source(),
+                        coords
+                    );
+                    loop {
+                        if let Ok(mut tile_request_state) =
+                            shared_thread_state.tile_request_state.try_lock()
+                        {
+                            tile_request_state
+                                .finish_raster_request(&coords, raster_result.source());
+                            break;
+                        }
+                    }
+                    if let RasterTileMessage::Raster { .. } = raster_result {
+                        events.emit(MapEvent::TileLoaded { coords });
+                    }
+                    tile_cache.put_raster_tile(raster_result);
+                }
+                TessellateMessage::GeoJsonTile(GeoJsonTileMessage { coords, source }) => loop {
+                    if let Ok(mut tile_request_state) =
+                        shared_thread_state.tile_request_state.try_lock()
+                    {
+                        let is_stale =
+                            tile_request_state.is_geojson_request_stale(&coords, &source);
+                        tile_request_state.finish_geojson_request(&coords, &source);
+                        if is_stale {
+                            // Slices the tile again from the current data of the source
+                            tracing::trace!("GeoJSON tile of {} at {} is stale", source, coords);
+                            view_state.camera.reset_reference();
+                        } else {
+                            tracing::trace!(
+                                "GeoJSON tile of {} at {} finished loading",
+                                source,
+                                coords
+                            );
+                            events.emit(MapEvent::TileLoaded { coords });
+                        }
+                        break;
+                    }
+                },
+                TessellateMessage::TileJson(TileJsonMessage { url, tile_json }) => {
+                    tracing::trace!("TileJSON {} reached main thread", url);
+                    if tile_json.is_none() {
+                        // Sources which refer to a missing TileJSON document have no tiles
+                        for (id, source) in &style.sources {
+                            if let Source::Vector(source)
+                            | Source::Raster(source)
+                            | Source::RasterDem(source) = source
+                            {
+                                if source.url.as_ref() == Some(&url) {
+                                    events.emit(MapEvent::SourceError {
+                                        source: Some(id.clone()),
+                                        coords: None,
+                                        message: format!("TileJSON {} unavailable", url),
+                                    });
+                                }
+                            }
+                        }
+                    }
+                    tile_cache.put_tile_json(url, tile_json);
+                }
+                TessellateMessage::Glyphs(GlyphsMessage {
+                    fontstack,
+                    range,
+                    glyphs,
+                }) => {
+                    tracing::trace!("glyphs {} of {} reached main thread", range, fontstack);
+                    // Labels fall back to the configured font if the range is unavailable
+                    tile_cache
+                        .glyph_ranges_mut()
+                        .put(fontstack, range, glyphs.unwrap_or_default());
                 }
                 TessellateMessage::Tile(TileTessellateMessage { request_id, coords }) => loop {
                     if let Ok(mut tile_request_state) =
@@ -33,9 +140,21 @@ impl Stage for PopulateTileStore {
                     {
                         tile_request_state.finish_tile_request(request_id);
                         tracing::trace!("Tile at {} finished loading", coords);
+                        events.emit(MapEvent::TileLoaded { coords });
                         break;
                     }
                 },
+                TessellateMessage::SourceError(SourceErrorMessage {
+                    source,
+                    coords,
+                    message,
+                }) => {
+                    events.emit(MapEvent::SourceError {
+                        source,
+                        coords: Some(coords),
+                        message,
+                    });
+                }
             }
         }
     }
@@ -1,15 +1,29 @@
 //! Requests tiles which are currently in view
 
-use crate::context::MapContext;
-use crate::coords::{ViewRegion, WorldTileCoords};
+use crate::context::{MapContext, ViewState};
+use crate::coords::{ViewRegion, WorldCoords, WorldTileCoords, Zoom, TILE_SIZE};
 use crate::error::Error;
+use crate::events::{MapEvent, MapEvents};
+use crate::io::geojson_source::{GeoJsonChanges, GeoJsonSource, DEFAULT_BUFFER, DEFAULT_TOLERANCE};
+use crate::io::glyphs::glyphs_url;
 use crate::io::shared_thread_state::SharedThreadState;
 use crate::io::source_client::SourceClient;
 use crate::io::tile_cache::TileCache;
+use crate::io::tile_json::TileJSON;
+use crate::io::tile_request_state::TileRequestStart;
 use crate::io::TileRequest;
 use crate::schedule::Stage;
+use crate::style::source::{DemEncoding, Source, VectorSource};
+use crate::tessellation::LineStyle;
 use crate::{HTTPClient, ScheduleMethod, Style};
-use std::collections::HashSet;
+use cgmath::Vector2;
+use instant::Instant;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Number of tiles around the view which are not evicted from the tile cache.
+const RETAINED_TILE_PADDING: i32 = 1;
 
 pub struct RequestStage<HC>
 where
@@ -17,6 +31,11 @@ where
 {
     pub source_client: SourceClient<HC>,
     pub try_failed: bool,
+    /// Parsed GeoJSON sources of the style together with the revision of their data. `None` if the
+    /// data of the source is invalid.
+    geojson_sources: HashMap<String, (u64, Option<Arc<GeoJsonSource>>)>,
+    /// URLs of the TileJSON documents which have been requested.
+    tile_json_requests: HashSet<String>,
 }
 
 impl<HC> RequestStage<HC>
@@ -27,6 +46,8 @@ where
         Self {
             source_client,
             try_failed: false,
+            geojson_sources: HashMap::new(),
+            tile_json_requests: HashSet::new(),
         }
     }
 }
@@ -43,6 +64,8 @@ where
             tile_cache,
             scheduler,
             shared_thread_state,
+            events,
+            renderer,
             ..
         }: &mut MapContext,
     ) {
@@ -55,9 +78,54 @@ where
             .view_region_bounding_box(&view_proj.invert())
             .map(|bounding_box| ViewRegion::new(bounding_box, 0, *view_state.zoom, visible_level));
 
+        // Pending requests of tiles which left the view are not retried
+        shared_thread_state.set_view_region(view_region.clone());
+
+        // Labels wait for their glyphs regardless of whether the view changes
+        self.request_glyphs(style, tile_cache, shared_thread_state, scheduler);
+
         if view_state.camera.did_change(0.05) || view_state.zoom.did_change(0.05) || self.try_failed
         {
+            for (id, changes) in self.parse_geojson_sources(style, events) {
+                let is_affected = |coords: &WorldTileCoords| {
+                    changes
+                        .as_ref()
+                        .map_or(true, |changes| changes.affects(coords))
+                };
+                let affected = tile_cache.remove_source_layers(&id, is_affected);
+                renderer.state.remove_tiles(is_affected);
+                if let Ok(mut tile_request_state) = shared_thread_state.tile_request_state.lock() {
+                    tile_request_state.invalidate_geojson_requests(&id, is_affected);
+                }
+                tracing::info!(
+                    "data of GeoJSON source {} replaced, {} tiles affected",
+                    id,
+                    affected
+                );
+            }
+            let tile_json_pending =
+                self.resolve_tile_jsons(style, tile_cache, shared_thread_state, scheduler);
+            if !tile_json_pending && events.style_pending {
+                events.style_pending = false;
+                events.emit(MapEvent::StyleLoaded);
+            }
+
             if let Some(view_region) = &view_region {
+                // Evicted tiles are requested below as soon as they are in view again
+                let retained_region = view_region.padded(RETAINED_TILE_PADDING);
+                let evicted = tile_cache.evict(renderer.settings.tile_cache_budget, |coords| {
+                    retained_region.is_in_view(coords)
+                });
+                if evicted > 0 {
+                    tracing::info!(
+                        "evicted {} tiles from the tile cache, {} tiles with {} bytes remain",
+                        evicted,
+                        tile_cache.tile_count(),
+                        tile_cache.bytes()
+                    );
+                }
+
+                let priority = RequestPriority::new(view_state, visible_level);
                 // FIXME: We also need to request tiles from layers above if we are over the maximum zoom level
                 self.try_failed = self.request_tiles_in_view(
                     tile_cache,
@@ -65,8 +133,12 @@ where
                     shared_thread_state,
                     scheduler,
                     view_region,
+                    &priority,
+                    view_state.device_pixel_ratio(),
                 );
             }
+            // Tiles of sources with pending TileJSON documents are requested once they arrive
+            self.try_failed |= tile_json_pending;
         }
 
         view_state.camera.update_reference();
@@ -78,8 +150,157 @@ impl<HC> RequestStage<HC>
 where
     HC: HTTPClient,
 {
-    /// Request tiles which are currently in view.
+    /// Parses the data of GeoJSON sources which have not been seen before or whose data has been
+    /// replaced, see [`Style::set_geojson_data`]. Sources which are no longer part of the style
+    /// are forgotten. Returns the ids of the sources whose data has been replaced together with
+    /// the changes. The changes are `None` if either version of the data is invalid, in which case
+    /// all tiles of the source are affected.
+    fn parse_geojson_sources(
+        &mut self,
+        style: &Style,
+        events: &mut MapEvents,
+    ) -> Vec<(String, Option<GeoJsonChanges>)> {
+        self.geojson_sources
+            .retain(|id, _| matches!(style.sources.get(id), Some(Source::GeoJson(_))));
+
+        let mut replaced = Vec::new();
+        for (id, source) in &style.sources {
+            let spec = match source {
+                Source::GeoJson(spec) => spec,
+                _ => continue,
+            };
+            let previous = match self.geojson_sources.get(id) {
+                Some((revision, _)) if *revision == spec.revision => continue,
+                Some((_, previous)) => Some(previous.clone()),
+                None => None,
+            };
+
+            let source = match GeoJsonSource::parse(&spec.data) {
+                Ok(source) => Some(Arc::new(
+                    source
+                        .with_tolerance(spec.tolerance.unwrap_or(DEFAULT_TOLERANCE))
+                        .with_buffer(spec.buffer.unwrap_or(DEFAULT_BUFFER)),
+                )),
+                Err(e) => {
+                    log::error!("GeoJSON source {} is invalid: {:?}", id, e);
+                    events.emit(MapEvent::SourceError {
+                        source: Some(id.clone()),
+                        coords: None,
+                        message: format!("{:?}", e),
+                    });
+                    None
+                }
+            };
+
+            if let Some(previous) = previous {
+                let changes = match (&source, &previous) {
+                    (Some(source), Some(previous)) => Some(source.changes(previous)),
+                    _ => None,
+                };
+                replaced.push((id.clone(), changes));
+            }
+            self.geojson_sources
+                .insert(id.clone(), (spec.revision, source));
+        }
+        replaced
+    }
+
+    /// Assigns fetched TileJSON documents to the sources of the style and requests the documents
+    /// which have not been fetched yet. Returns true if documents are still pending.
+    fn resolve_tile_jsons(
+        &mut self,
+        style: &mut Style,
+        tile_cache: &TileCache,
+        shared_thread_state: &SharedThreadState,
+        scheduler: &Box<dyn ScheduleMethod>,
+    ) -> bool {
+        let mut pending = false;
+
+        for source in style.sources.values_mut() {
+            let source = match source {
+                Source::Vector(source) | Source::Raster(source) | Source::RasterDem(source) => {
+                    source
+                }
+                Source::GeoJson(_) => continue,
+            };
+            if !source.is_tile_json_missing() {
+                continue;
+            }
+            let url = if let Some(url) = &source.url {
+                url.clone()
+            } else {
+                continue;
+            };
+
+            match tile_cache.get_tile_json(&url) {
+                Some(tile_json) => source.tile_json = tile_json.cloned(),
+                None => {
+                    pending = true;
+                    if self.tile_json_requests.insert(url.clone()) {
+                        tracing::info!("new TileJSON request: {}", &url);
+
+                        let client = self.source_client.clone();
+                        scheduler
+                            .schedule(
+                                shared_thread_state.clone(),
+                                Box::new(move |state: SharedThreadState| {
+                                    Box::pin(async move {
+                                        let fetch_permit =
+                                            state.request_limiter.fetches.acquire().await;
+                                        let tile_json = client.fetch_tile_json(&url).await;
+                                        drop(fetch_permit);
+                                        state.process_tile_json(&url, tile_json).unwrap()
+                                    })
+                                }),
+                            )
+                            .unwrap();
+                    }
+                }
+            }
+        }
+
+        pending
+    }
+
+    /// Requests the ranges of glyphs which labels wait for, see
+    /// [`crate::io::glyphs::GlyphRanges`].
+    fn request_glyphs(
+        &self,
+        style: &Style,
+        tile_cache: &mut TileCache,
+        shared_thread_state: &SharedThreadState,
+        scheduler: &Box<dyn ScheduleMethod>,
+    ) {
+        let template = match &style.glyphs {
+            Some(template) => template,
+            None => return,
+        };
+
+        for (fontstack, range) in tile_cache.glyph_ranges_mut().take_wanted() {
+            let url = glyphs_url(template, &fontstack, range);
+            tracing::info!("new glyphs request: {}", &url);
+
+            let client = self.source_client.clone();
+            scheduler
+                .schedule(
+                    shared_thread_state.clone(),
+                    Box::new(move |state: SharedThreadState| {
+                        Box::pin(async move {
+                            let fetch_permit = state.request_limiter.fetches.acquire().await;
+                            let glyphs = client.fetch_glyphs(&url).await;
+                            drop(fetch_permit);
+                            state.process_glyphs(&fontstack, range, glyphs).unwrap()
+                        })
+                    }),
+                )
+                .unwrap();
+        }
+    }
+
+    /// Request tiles which are currently in view. Tiles close to the center of the view are
+    /// requested first, see [`RequestPriority`].
     #[tracing::instrument(skip_all)]
+    #[allow(clippy::too_many_arguments)]
     fn request_tiles_in_view(
         &self,
         tile_cache: &TileCache,
@@ -87,75 +308,262 @@ where
         shared_thread_state: &SharedThreadState,
         scheduler: &Box<dyn ScheduleMethod>,
         view_region: &ViewRegion,
+        priority: &RequestPriority,
+        device_pixel_ratio: f64,
     ) -> bool {
         let mut try_failed = false;
-        let source_layers: HashSet<String> = style
+
+        // Layers which are not drawn at the visible zoom level are not tessellated. Tiles are
+        // fetched from the default tile server for layers whose source the style does not define,
+        // and not at all for sources whose TileJSON is still pending
+        let zoom = view_region.zoom_level() as f64;
+        let vector_sources: Vec<VectorSourceRequest> = vector_source_layers(style, zoom)
+            .into_iter()
+            .filter_map(|(id, layers)| {
+                let tile_json = match &id {
+                    Some(id) => match style.sources.get(id) {
+                        Some(Source::Vector(source)) => Some(source.resolved_tile_json()?),
+                        _ => return None,
+                    },
+                    None => None,
+                };
+                let line_styles = line_styles(style, id.as_deref());
+                Some((id, tile_json, layers, line_styles))
+            })
+            .collect();
+
+        // Layers of GeoJSON sources are sliced on the client instead of being fetched
+        let geojson_sources: Vec<GeoJsonSourceRequest> = self
+            .geojson_sources
+            .iter()
+            .filter_map(|(id, (_, source))| source.as_ref().map(|source| (id, source)))
+            .map(|(id, source)| {
+                let layers = style
+                    .layers
+                    .iter()
+                    .filter(|layer| layer.source.as_ref() == Some(id))
+                    .filter(|layer| layer.is_in_zoom_range(zoom))
+                    .filter_map(|layer| layer.source_layer.clone())
+                    .collect::<HashSet<_>>();
+                (id, source, layers, line_styles(style, Some(id.as_str())))
+            })
+            .filter(|(_, _, layers, _)| !layers.is_empty())
+            .collect();
+
+        // Raster tiles are requested in high resolution on HiDPI displays if the source offers them.
+        // Tiles of raster DEM sources hold elevations instead of colors, so they are not.
+        let raster_sources: Vec<(&String, &VectorSource, TileJSON, Option<DemEncoding>)> = style
             .layers
             .iter()
-            .filter_map(|layer| layer.source_layer.clone())
+            .filter(|layer| layer.typ == "raster" || layer.typ == "hillshade")
+            .filter_map(|layer| layer.source.as_ref())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter_map(|id| match style.sources.get(id) {
+                Some(Source::Raster(source)) => source.resolved_tile_json().map(|tile_json| {
+                    (
+                        id,
+                        source,
+                        tile_json.with_pixel_ratio(device_pixel_ratio),
+                        None,
+                    )
+                }),
+                Some(Source::RasterDem(source)) => source.resolved_tile_json().map(|tile_json| {
+                    (
+                        id,
+                        source,
+                        tile_json,
+                        Some(source.encoding.unwrap_or_default()),
+                    )
+                }),
+                _ => None,
+            })
             .collect();
 
-        for coords in view_region.iter() {
+        // Above the maximum zoom level of the vector source the tiles of GeoJSON sources are sliced
+        // at the same zoom level, such that all layers are scaled up from the same tiles
+        let mut tiles = style.overzoomed_tiles(view_region);
+        priority.sort(&mut tiles);
+        for coords in tiles {
             if coords.build_quad_key().is_some() {
-                // TODO: Make tesselation depend on style?
-                try_failed = self
-                    .try_request_tile(
+                for (id, tile_json, layers, line_styles) in &vector_sources {
+                    let is_available = tile_json
+                        .as_ref()
+                        .map_or(true, |tile_json| tile_json.contains(&coords));
+
+                    if is_available {
+                        try_failed |= self
+                            .try_request_tile(
+                                tile_cache,
+                                shared_thread_state,
+                                scheduler,
+                                &coords,
+                                id.as_deref(),
+                                layers,
+                                line_styles,
+                                tile_json.as_ref(),
+                            )
+                            .unwrap();
+                    }
+                }
+
+                for (id, source, layers, line_styles) in &geojson_sources {
+                    try_failed |= self.try_request_geojson_tile(
                         tile_cache,
                         shared_thread_state,
                         scheduler,
                         &coords,
-                        &source_layers,
-                    )
-                    .unwrap();
+                        id,
+                        source,
+                        layers,
+                        line_styles,
+                    );
+                }
+            }
+        }
+
+        let mut tiles = view_region.iter().collect::<Vec<_>>();
+        priority.sort(&mut tiles);
+        for coords in tiles {
+            if coords.build_quad_key().is_some() {
+                for (id, source, tile_json, encoding) in &raster_sources {
+                    try_failed |= self.try_request_raster_tile(
+                        tile_cache,
+                        shared_thread_state,
+                        scheduler,
+                        &source.overzoomed_coords(&coords),
+                        id,
+                        tile_json,
+                        *encoding,
+                    );
+                }
             }
         }
         try_failed
     }
 
-    fn try_request_tile(
+    /// Slices the tile of a GeoJSON source on a worker thread. Returns true if the request needs
+    /// to be retried.
+    #[allow(clippy::too_many_arguments)]
+    fn try_request_geojson_tile(
         &self,
         tile_cache: &TileCache,
         shared_thread_state: &SharedThreadState,
         scheduler: &Box<dyn ScheduleMethod>,
         coords: &WorldTileCoords,
+        id: &str,
+        source: &Arc<GeoJsonSource>,
         layers: &HashSet<String>,
-    ) -> Result<bool, Error> {
-        if !tile_cache.is_layers_missing(coords, layers) {
-            return Ok(false);
+        line_styles: &HashMap<String, LineStyle>,
+    ) -> bool {
+        if !tile_cache.is_layers_missing(coords, Some(id), layers) {
+            return false;
         }
 
         if let Ok(mut tile_request_state) = shared_thread_state.tile_request_state.try_lock() {
-            if let Some(request_id) = tile_request_state.start_tile_request(TileRequest {
-                coords: *coords,
-                layers: layers.clone(),
-            }) {
-                tracing::info!("new tile request: {}", &coords);
-
-                // The following snippet can be added instead of the next code block to demonstrate
-                // an understanable approach of fetching
-                /*#[cfg(target_arch = "wasm32")]
-                if let Some(tile_coords) = coords.into_tile(TileAddressingScheme::TMS) {
-                    crate::platform::legacy_webworker_fetcher::request_tile(
-                        request_id,
-                        tile_coords,
-                    );
-                }*/
+            if tile_request_state.start_geojson_request(coords, id) {
+                tracing::info!("new GeoJSON tile request: {}", &coords);
+
+                let source = source.clone();
+                let layers = layers.clone();
+                let line_styles = line_styles.clone();
+                let coords = *coords;
+                let id = id.to_string();
+
+                scheduler
+                    .schedule(
+                        shared_thread_state.clone(),
+                        Box::new(move |state: SharedThreadState| {
+                            Box::pin(async move {
+                                let _permit = state.request_limiter.tessellations.acquire().await;
+                                state
+                                    .process_geojson_tile(
+                                        &coords,
+                                        &id,
+                                        &source,
+                                        &layers,
+                                        &line_styles,
+                                    )
+                                    .unwrap()
+                            })
+                        }),
+                    )
+                    .unwrap();
+            }
+
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Requests the raster tile of a source. Returns true if the request needs to be retried.
+    #[allow(clippy::too_many_arguments)]
+    fn try_request_raster_tile(
+        &self,
+        tile_cache: &TileCache,
+        shared_thread_state: &SharedThreadState,
+        scheduler: &Box<dyn ScheduleMethod>,
+        coords: &WorldTileCoords,
+        id: &str,
+        tile_json: &TileJSON,
+        encoding: Option<DemEncoding>,
+    ) -> bool {
+        if tile_cache.get_raster_tile_at(coords, id).is_some() {
+            return false;
+        }
+
+        if !tile_json.contains(coords) {
+            return false;
+        }
+
+        if let Ok(mut tile_request_state) = shared_thread_state.tile_request_state.try_lock() {
+            if tile_request_state.start_raster_request(coords, id) {
+                tracing::info!("new raster tile request: {}", &coords);
 
                 let client = self.source_client.clone();
+                let tile_json = tile_json.clone();
                 let coords = *coords;
+                let id = id.to_string();
 
                 scheduler
                     .schedule(
                         shared_thread_state.clone(),
                         Box::new(move |state: SharedThreadState| {
                             Box::pin(async move {
-                                match client.fetch(&coords).await {
-                                    Ok(data) => state
-                                        .process_tile(request_id, data.into_boxed_slice())
-                                        .unwrap(),
+                                let view_state = state.clone();
+                                let is_cancelled = move || !view_state.is_tile_in_view(&coords);
+                                // Requests wait for their turn before the start is measured
+                                let fetch_permit = state.request_limiter.fetches.acquire().await;
+                                let start = Instant::now();
+                                let result =
+                                    client.fetch_raster(&coords, &tile_json, is_cancelled).await;
+                                drop(fetch_permit);
+                                match result {
+                                    Ok(data) => {
+                                        state.metrics.tile_fetched(
+                                            &coords,
+                                            start.elapsed(),
+                                            data.len(),
+                                        );
+                                        let _permit =
+                                            state.request_limiter.tessellations.acquire().await;
+                                        state
+                                            .process_raster_tile(
+                                                &coords,
+                                                &id,
+                                                data.into_boxed_slice(),
+                                                encoding,
+                                            )
+                                            .unwrap()
+                                    }
+                                    Err(Error::Cancelled) => {
+                                        state.raster_tile_request_cancelled(&coords, &id)
+                                    }
                                     Err(e) => {
                                         log::error!("{:?}", &e);
-                                        state.tile_unavailable(&coords, request_id).unwrap()
+                                        state.source_error(Some(&id), &coords, &e).unwrap();
+                                        state.raster_tile_unavailable(&coords, &id).unwrap()
                                     }
                                 }
                             })
@@ -164,9 +572,438 @@ where
                     .unwrap();
             }
 
-            Ok(false)
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Requests the layers of a tile which are not yet in the tile cache. Layers which are cached
+    /// already, e.g. because a previous style used them too, are not tessellated again.
+    #[allow(clippy::too_many_arguments)]
+    fn try_request_tile(
+        &self,
+        tile_cache: &TileCache,
+        shared_thread_state: &SharedThreadState,
+        scheduler: &Box<dyn ScheduleMethod>,
+        coords: &WorldTileCoords,
+        source: Option<&str>,
+        layers: &HashSet<String>,
+        line_styles: &HashMap<String, LineStyle>,
+        tile_json: Option<&TileJSON>,
+    ) -> Result<bool, Error> {
+        let mut missing_layers = layers.clone();
+        tile_cache.retain_missing_layer_names(coords, source, &mut missing_layers);
+        if missing_layers.is_empty() {
+            return Ok(false);
+        }
+
+        if let Ok(mut tile_request_state) = shared_thread_state.tile_request_state.try_lock() {
+            match tile_request_state.start_tile_request(TileRequest {
+                coords: *coords,
+                source: source.map(|source| source.to_string()),
+                layers: missing_layers,
+                line_styles: line_styles.clone(),
+            }) {
+                TileRequestStart::Coalesced => Ok(false),
+                TileRequestStart::New(request_id) => {
+                    tracing::info!("new tile request: {}", &coords);
+
+                    // The following snippet can be added instead of the next code block to demonstrate
+                    // an understanable approach of fetching
+                    /*#[cfg(target_arch = "wasm32")]
+                    if let Some(tile_coords) = coords.into_tile(TileAddressingScheme::TMS) {
+                        crate::platform::legacy_webworker_fetcher::request_tile(
+                            request_id,
+                            tile_coords,
+                        );
+                    }*/
+
+                    let client = self.source_client.clone();
+                    let tile_json = tile_json.cloned();
+                    let coords = *coords;
+                    let source = source.map(|source| source.to_string());
+
+                    scheduler
+                        .schedule(
+                            shared_thread_state.clone(),
+                            Box::new(move |state: SharedThreadState| {
+                                Box::pin(async move {
+                                    let view_state = state.clone();
+                                    let is_cancelled = move || !view_state.is_tile_in_view(&coords);
+                                    // Requests wait for their turn before the start is measured
+                                    let fetch_permit =
+                                        state.request_limiter.fetches.acquire().await;
+                                    let start = Instant::now();
+                                    let result = client
+                                        .fetch(&coords, tile_json.as_ref(), is_cancelled)
+                                        .await;
+                                    drop(fetch_permit);
+                                    match result {
+                                        Ok(data) => {
+                                            state.metrics.tile_fetched(
+                                                &coords,
+                                                start.elapsed(),
+                                                data.len(),
+                                            );
+                                            let _permit =
+                                                state.request_limiter.tessellations.acquire().await;
+                                            state
+                                                .process_tile(request_id, data.into_boxed_slice())
+                                                .unwrap()
+                                        }
+                                        Err(Error::Cancelled) => {
+                                            state.tile_request_cancelled(&coords, request_id)
+                                        }
+                                        Err(e) => {
+                                            log::error!("{:?}", &e);
+                                            state
+                                                .source_error(source.as_deref(), &coords, &e)
+                                                .unwrap();
+                                            state.tile_unavailable(&coords, request_id).unwrap()
+                                        }
+                                    }
+                                })
+                            }),
+                        )
+                        .unwrap();
+
+                    Ok(false)
+                }
+            }
         } else {
             Ok(true)
         }
     }
 }
+
+/// The id of a vector source, its TileJSON, the source layers which are requested from it and
+/// their line styles.
+type VectorSourceRequest = (
+    Option<String>,
+    Option<TileJSON>,
+    HashSet<String>,
+    HashMap<String, LineStyle>,
+);
+
+/// A parsed GeoJSON source with its id, the source layers which are sliced from it and their line
+/// styles.
+type GeoJsonSourceRequest<'a> = (
+    &'a String,
+    &'a Arc<GeoJsonSource>,
+    HashSet<String>,
+    HashMap<String, LineStyle>,
+);
+
+/// Tiles of other zoom levels than the visible one, e.g. the ancestors of overzoomed tiles, are
+/// requested after the tiles of the visible level which are this many tiles away from the center.
+const OTHER_ZOOM_LEVEL_PENALTY: f64 = 4.0;
+
+/// Orders tile requests by their distance from the center of the view, such that the tiles in the
+/// middle of the screen appear first. The order is recomputed whenever the camera moved enough
+/// to request tiles again.
+struct RequestPriority {
+    /// The center of the view in tiles of the visible zoom level.
+    center: Vector2<f64>,
+    /// The visible zoom level.
+    z: u8,
+}
+
+impl RequestPriority {
+    fn new(view_state: &ViewState, z: u8) -> Self {
+        Self::from_center(&view_state.center_at_ground(), view_state.zoom(), z)
+    }
+
+    fn from_center(center: &WorldCoords, zoom: Zoom, z: u8) -> Self {
+        let tile_scale = zoom.scale_to_zoom_level(z) / TILE_SIZE;
+        Self {
+            center: Vector2::new(center.x * tile_scale, center.y * tile_scale),
+            z,
+        }
+    }
+
+    /// Lower scores are requested first. The score is the distance between the center of the tile
+    /// and the center of the view, in tiles of the visible zoom level.
+    fn score(&self, coords: &WorldTileCoords) -> f64 {
+        let dz = self.z as i32 - coords.z as i32;
+        let scale = 2.0_f64.powi(dz);
+        let tile_center = Vector2::new(
+            (coords.x as f64 + 0.5) * scale,
+            (coords.y as f64 + 0.5) * scale,
+        );
+        let delta = tile_center - self.center;
+        delta.x.hypot(delta.y) + dz.abs() as f64 * OTHER_ZOOM_LEVEL_PENALTY
+    }
+
+    fn sort(&self, tiles: &mut [WorldTileCoords]) {
+        tiles.sort_by(|a, b| {
+            self.score(a)
+                .partial_cmp(&self.score(b))
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+}
+
+/// Returns the line style of each source layer of `source`, see [`Style::tile_source_id`]. Lines
+/// are tessellated once per source layer, so the first line layer of a source layer decides how
+/// its ends and corners are shaped. Lines are hollow if any line layer of the source layer has a
+/// gap, see [`LineStyle::hollow`].
+fn line_styles(style: &Style, source: Option<&str>) -> HashMap<String, LineStyle> {
+    let mut line_styles = HashMap::new();
+    for layer in style
+        .layers
+        .iter()
+        .filter(|layer| layer.typ == "line" && style.tile_source_id(layer) == source)
+    {
+        if let Some(source_layer) = &layer.source_layer {
+            let line_style = line_styles
+                .entry(source_layer.clone())
+                .or_insert_with(|| LineStyle::from_layout(layer.layout.as_ref()));
+            line_style.hollow |= layer
+                .paint
+                .as_ref()
+                .map_or(false, |paint| paint.has_line_gap());
+        }
+    }
+    line_styles
+}
+
+/// Returns the source layers of `style` which are drawn at `zoom`, grouped by the id of the vector
+/// source they are fetched from. Layers whose source the style does not define are fetched from
+/// the default tile source, whose id is `None`.
+fn vector_source_layers(style: &Style, zoom: f64) -> HashMap<Option<String>, HashSet<String>> {
+    let mut source_layers: HashMap<Option<String>, HashSet<String>> = HashMap::new();
+    for layer in style
+        .layers
+        .iter()
+        .filter(|layer| layer.is_in_zoom_range(zoom))
+    {
+        let source_layer = match &layer.source_layer {
+            Some(source_layer) => source_layer,
+            None => continue,
+        };
+        let id = style.tile_source_id(layer);
+        if id.map_or(true, |id| {
+            matches!(style.sources.get(id), Some(Source::Vector(_)))
+        }) {
+            source_layers
+                .entry(id.map(|id| id.to_string()))
+                .or_default()
+                .insert(source_layer.clone());
+        }
+    }
+    source_layers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{line_styles, vector_source_layers, RequestPriority};
+    use crate::coords::{WorldCoords, WorldTileCoords, Zoom, TILE_SIZE};
+    use crate::io::tile_cache::TileCache;
+    use crate::io::LayerTessellateMessage;
+    use crate::style::layer::LineJoin;
+    use crate::style::Style;
+    use crate::tessellation::LineStyle;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    fn style(source_layers: &[&str]) -> Style {
+        let layers = source_layers
+            .iter()
+            .map(|source_layer| {
+                json!({
+                    "id": source_layer,
+                    "type": "fill",
+                    "source": "openmaptiles",
+                    "source-layer": source_layer
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::from_value(json!({
+            "version": 8,
+            "name": "style",
+            "metadata": {},
+            "sources": {},
+            "layers": layers
+        }))
+        .unwrap()
+    }
+
+    fn set(layers: &[&str]) -> HashSet<String> {
+        layers.iter().map(|layer| layer.to_string()).collect()
+    }
+
+    #[test]
+    fn test_swapping_styles_requests_only_new_layers() {
+        let light = style(&["water", "building"]);
+        let dark = style(&["water", "transportation"]);
+        let coords = WorldTileCoords { x: 0, y: 0, z: 1 };
+
+        let mut tile_cache = TileCache::new();
+        let mut layers = vector_source_layers(&light, 1.0).remove(&None).unwrap();
+        assert_eq!(layers, set(&["water", "building"]));
+
+        tile_cache.retain_missing_layer_names(&coords, None, &mut layers);
+        for layer_name in layers {
+            tile_cache.put_tessellated_layer(LayerTessellateMessage::UnavailableLayer {
+                coords,
+                source: None,
+                layer_name,
+            });
+        }
+
+        let mut layers = vector_source_layers(&dark, 1.0).remove(&None).unwrap();
+        assert_eq!(layers, set(&["water", "transportation"]));
+
+        tile_cache.retain_missing_layer_names(&coords, None, &mut layers);
+        assert_eq!(layers, set(&["transportation"]));
+
+        let mut layers = vector_source_layers(&light, 1.0).remove(&None).unwrap();
+        tile_cache.retain_missing_layer_names(&coords, None, &mut layers);
+        assert!(layers.is_empty());
+    }
+
+    #[test]
+    fn test_layers_outside_of_zoom_range_are_not_requested() {
+        let mut style = style(&["water", "building"]);
+        style.layers[1].minzoom = Some(10);
+        style.layers[1].maxzoom = Some(14);
+
+        assert_eq!(vector_source_layers(&style, 9.0)[&None], set(&["water"]));
+        assert_eq!(
+            vector_source_layers(&style, 10.0)[&None],
+            set(&["water", "building"])
+        );
+        assert_eq!(
+            vector_source_layers(&style, 13.0)[&None],
+            set(&["water", "building"])
+        );
+        assert_eq!(vector_source_layers(&style, 14.0)[&None], set(&["water"]));
+    }
+
+    #[test]
+    fn test_first_line_layer_decides_line_style() {
+        let style: Style = serde_json::from_value(json!({
+            "version": 8,
+            "name": "style",
+            "metadata": {},
+            "sources": {},
+            "layers": [
+                {
+                    "id": "road-casing",
+                    "type": "line",
+                    "source": "openmaptiles",
+                    "source-layer": "transportation",
+                    "layout": { "line-join": "round" }
+                },
+                {
+                    "id": "road",
+                    "type": "line",
+                    "source": "openmaptiles",
+                    "source-layer": "transportation",
+                    "layout": { "line-join": "bevel" },
+                    "paint": { "line-gap-width": 4 }
+                },
+                {
+                    "id": "water",
+                    "type": "fill",
+                    "source": "openmaptiles",
+                    "source-layer": "water"
+                }
+            ]
+        }))
+        .unwrap();
+
+        let line_styles = line_styles(&style, None);
+        assert_eq!(line_styles.len(), 1);
+        assert_eq!(
+            line_styles["transportation"],
+            LineStyle {
+                join: LineJoin::Round,
+                hollow: true,
+                ..LineStyle::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_sources_sharing_a_layer_name() {
+        let style: Style = serde_json::from_value(json!({
+            "version": 8,
+            "name": "style",
+            "metadata": {},
+            "sources": {
+                "streets": {"type": "vector", "tiles": ["https://a.example/{z}/{x}/{y}.pbf"]},
+                "nautical": {"type": "vector", "tiles": ["https://b.example/{z}/{x}/{y}.pbf"]}
+            },
+            "layers": [
+                {"id": "water", "type": "fill", "source": "streets", "source-layer": "water"},
+                {"id": "roads", "type": "line", "source": "streets", "source-layer": "roads"},
+                {"id": "depth", "type": "fill", "source": "nautical", "source-layer": "water"},
+                {"id": "parks", "type": "fill", "source": "unknown", "source-layer": "parks"}
+            ]
+        }))
+        .unwrap();
+        let coords = WorldTileCoords { x: 0, y: 0, z: 1 };
+
+        let source_layers = vector_source_layers(&style, 1.0);
+        assert_eq!(source_layers.len(), 3);
+        assert_eq!(
+            source_layers[&Some("streets".to_string())],
+            set(&["water", "roads"])
+        );
+        assert_eq!(
+            source_layers[&Some("nautical".to_string())],
+            set(&["water"])
+        );
+        // Layers of sources which the style does not define are fetched from the default source
+        assert_eq!(source_layers[&None], set(&["parks"]));
+
+        let mut tile_cache = TileCache::new();
+        tile_cache.put_tessellated_layer(LayerTessellateMessage::UnavailableLayer {
+            coords,
+            source: Some("streets".to_string()),
+            layer_name: "water".to_string(),
+        });
+
+        let mut layers = set(&["water"]);
+        tile_cache.retain_missing_layer_names(&coords, Some("nautical"), &mut layers);
+        assert_eq!(layers, set(&["water"]));
+        tile_cache.retain_missing_layer_names(&coords, Some("streets"), &mut layers);
+        assert!(layers.is_empty());
+
+        let message = tile_cache
+            .iter_tessellated_layers_at(&coords)
+            .and_then(|mut layers| layers.next())
+            .unwrap();
+        assert!(style.is_layer_data(&style.layers[0], message));
+        assert!(!style.is_layer_data(&style.layers[2], message));
+    }
+
+    #[test]
+    fn test_request_priority() {
+        // The view is centered on the tile (4, 4) at zoom level 3
+        let center = WorldCoords::at_ground(4.5 * TILE_SIZE, 4.5 * TILE_SIZE);
+        let priority = RequestPriority::from_center(&center, Zoom::new(3.0), 3);
+
+        let mut tiles: Vec<WorldTileCoords> = vec![
+            (2, 2, 3).into(),
+            (2, 2, 2).into(),
+            (5, 4, 3).into(),
+            (4, 4, 3).into(),
+            (3, 5, 3).into(),
+        ];
+        priority.sort(&mut tiles);
+
+        let expected: Vec<WorldTileCoords> = vec![
+            (4, 4, 3).into(),
+            (5, 4, 3).into(),
+            (3, 5, 3).into(),
+            (2, 2, 3).into(),
+            // The parent of the center tile comes after the tiles of the visible level nearby
+            (2, 2, 2).into(),
+        ];
+        assert_eq!(tiles, expected);
+    }
+}
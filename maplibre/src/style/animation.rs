@@ -0,0 +1,55 @@
+//! Animations of the paint of layers, which the renderer evaluates every frame.
+
+use csscolorparser::Color;
+use std::f64::consts::PI;
+use std::time::Duration;
+
+/// Animates the colors of the features of a layer. The colors oscillate along a sine wave between
+/// the colors which the style evaluates and `color`, e.g. to highlight a layer. Only the feature
+/// metadata of animated layers is uploaded every frame, the geometry is kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorAnimation {
+    /// The color which the features reach in the middle of each period.
+    pub color: Color,
+    /// Duration of one oscillation.
+    pub period: Duration,
+}
+
+impl ColorAnimation {
+    pub fn new(color: Color, period: Duration) -> Self {
+        Self { color, period }
+    }
+
+    /// Returns how far the colors are blended towards `color` after `elapsed`, from 0 to 1. The
+    /// animation starts with the colors of the style.
+    pub fn weight(&self, elapsed: Duration) -> f32 {
+        if self.period.is_zero() {
+            return 0.0;
+        }
+        let phase = elapsed.as_secs_f64() / self.period.as_secs_f64();
+        (0.5 * (1.0 - (2.0 * PI * phase).cos())) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ColorAnimation;
+    use csscolorparser::Color;
+    use std::time::Duration;
+
+    #[test]
+    fn test_weight_oscillates() {
+        let animation = ColorAnimation::new(Color::from_rgb(0.0, 0.0, 1.0), Duration::from_secs(2));
+        let weight = |millis| animation.weight(Duration::from_millis(millis));
+
+        assert!(weight(0).abs() < 1e-6);
+        assert!((weight(500) - 0.5).abs() < 1e-6);
+        assert!((weight(1000) - 1.0).abs() < 1e-6);
+        assert!(weight(2000).abs() < 1e-6);
+        assert!((weight(3000) - 1.0).abs() < 1e-6);
+
+        // Without a period the colors of the style are kept
+        let animation = ColorAnimation::new(Color::from_rgb(0.0, 0.0, 1.0), Duration::ZERO);
+        assert_eq!(animation.weight(Duration::from_millis(500)), 0.0);
+    }
+}
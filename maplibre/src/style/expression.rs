@@ -0,0 +1,724 @@
+//! Expressions allow the value of a style property to depend on the zoom level or on the
+//! properties of a feature. Expressions are written as JSON arrays, for example
+//! `["interpolate", ["linear"], ["zoom"], 5, "#abc", 15, "#def"]`.
+
+use csscolorparser::Color;
+use serde::de::Error as DeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+use std::str::FromStr;
+
+/// Gives access to the properties of a feature while evaluating an [`Expression`].
+pub trait FeatureProperties {
+    fn get_property(&self, key: &str) -> Option<Value>;
+}
+
+/// Concrete value which results from the evaluation of an [`Expression`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Color(Color),
+}
+
+impl Value {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as color. Strings are parsed as CSS colors.
+    pub fn as_color(&self) -> Option<Color> {
+        match self {
+            Value::Color(color) => Some(color.clone()),
+            Value::String(string) => Color::from_str(string).ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(bool) => json!(bool),
+            Value::Number(number) => json!(number),
+            Value::String(string) => json!(string),
+            Value::Color(color) => json!(color.to_hex_string()),
+        }
+    }
+}
+
+/// Describes how values between two stops of an `interpolate` expression are calculated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    Exponential(f64),
+}
+
+impl Interpolation {
+    /// Returns the interpolation factor between 0 and 1 for `input` between `lower` and `upper`.
+    fn factor(&self, input: f64, lower: f64, upper: f64) -> f64 {
+        let difference = upper - lower;
+        if difference == 0.0 {
+            return 0.0;
+        }
+
+        let progress = input - lower;
+        match self {
+            Interpolation::Linear => progress / difference,
+            Interpolation::Exponential(base) if (*base - 1.0).abs() < f64::EPSILON => {
+                progress / difference
+            }
+            Interpolation::Exponential(base) => {
+                (base.powf(progress) - 1.0) / (base.powf(difference) - 1.0)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(Value),
+    /// `["zoom"]`
+    Zoom,
+    /// `["get", key]`
+    Get(String),
+    /// `["interpolate", interpolation, input, stop_input_1, stop_output_1, ...]`
+    Interpolate {
+        interpolation: Interpolation,
+        input: Box<Expression>,
+        stops: Vec<(f64, Expression)>,
+    },
+    /// `["step", input, default, stop_input_1, stop_output_1, ...]`
+    Step {
+        input: Box<Expression>,
+        default: Box<Expression>,
+        stops: Vec<(f64, Expression)>,
+    },
+    /// `["match", input, label_1, output_1, ..., fallback]`. A label is either a single literal
+    /// or an array of literals.
+    Match {
+        input: Box<Expression>,
+        cases: Vec<(Vec<Value>, Expression)>,
+        fallback: Box<Expression>,
+    },
+}
+
+impl From<Color> for Expression {
+    fn from(color: Color) -> Self {
+        Expression::Literal(Value::Color(color))
+    }
+}
+
+impl From<f64> for Expression {
+    fn from(number: f64) -> Self {
+        Expression::Literal(Value::Number(number))
+    }
+}
+
+impl Expression {
+    /// Parses an expression from its JSON representation. Legacy functions like
+    /// `{"base": 1.4, "stops": [[5, 1], [15, 4]]}` are converted to `interpolate` expressions,
+    /// `interval` functions to `step` and `categorical` functions to `match` expressions.
+    pub fn parse(json: &serde_json::Value) -> Result<Self, String> {
+        match json {
+            serde_json::Value::Null => Ok(Expression::Literal(Value::Null)),
+            serde_json::Value::Bool(bool) => Ok(Expression::Literal(Value::Bool(*bool))),
+            serde_json::Value::Number(number) => Ok(Expression::Literal(Value::Number(
+                number.as_f64().ok_or("invalid number")?,
+            ))),
+            serde_json::Value::String(string) => {
+                Ok(Expression::Literal(Value::String(string.clone())))
+            }
+            serde_json::Value::Array(array) => Self::parse_array(array),
+            serde_json::Value::Object(object) => Self::parse_function(object),
+        }
+    }
+
+    fn parse_array(array: &[serde_json::Value]) -> Result<Self, String> {
+        let operator = array
+            .first()
+            .and_then(|operator| operator.as_str())
+            .ok_or("expressions must start with an operator")?;
+        let arguments = &array[1..];
+
+        match operator {
+            "literal" => match arguments {
+                [value] => Self::parse(value),
+                _ => Err("literal expects a single argument".to_string()),
+            },
+            "zoom" => Ok(Expression::Zoom),
+            "get" => match arguments {
+                [serde_json::Value::String(key)] => Ok(Expression::Get(key.clone())),
+                _ => Err("get expects a property name".to_string()),
+            },
+            "interpolate" => {
+                if arguments.len() < 4 {
+                    return Err("interpolate expects at least one stop".to_string());
+                }
+
+                Ok(Expression::Interpolate {
+                    interpolation: Self::parse_interpolation(&arguments[0])?,
+                    input: Box::new(Self::parse(&arguments[1])?),
+                    stops: Self::parse_stops(&arguments[2..])?,
+                })
+            }
+            "step" => {
+                if arguments.len() < 2 {
+                    return Err("step expects an input and a default".to_string());
+                }
+
+                Ok(Expression::Step {
+                    input: Box::new(Self::parse(&arguments[0])?),
+                    default: Box::new(Self::parse(&arguments[1])?),
+                    stops: Self::parse_stops(&arguments[2..])?,
+                })
+            }
+            "match" => {
+                if arguments.len() < 4 || arguments.len() % 2 != 0 {
+                    return Err(
+                        "match expects an input, pairs of labels and outputs and a fallback"
+                            .to_string(),
+                    );
+                }
+
+                let cases = arguments[1..arguments.len() - 1]
+                    .chunks(2)
+                    .map(|case| -> Result<(Vec<Value>, Expression), String> {
+                        Ok((Self::parse_labels(&case[0])?, Self::parse(&case[1])?))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+
+                Ok(Expression::Match {
+                    input: Box::new(Self::parse(&arguments[0])?),
+                    cases,
+                    fallback: Box::new(Self::parse(&arguments[arguments.len() - 1])?),
+                })
+            }
+            operator => Err(format!("unsupported expression operator {}", operator)),
+        }
+    }
+
+    /// Parses the label of a `match` case, which is a number, a string or an array of them.
+    fn parse_labels(json: &serde_json::Value) -> Result<Vec<Value>, String> {
+        let parse_label = |label: &serde_json::Value| match label {
+            serde_json::Value::Number(number) => {
+                Ok(Value::Number(number.as_f64().ok_or("invalid number")?))
+            }
+            serde_json::Value::String(string) => Ok(Value::String(string.clone())),
+            _ => Err(format!(
+                "match labels must be numbers or strings, got {}",
+                label
+            )),
+        };
+
+        match json {
+            serde_json::Value::Array(labels) => labels.iter().map(parse_label).collect(),
+            label => Ok(vec![parse_label(label)?]),
+        }
+    }
+
+    fn parse_interpolation(json: &serde_json::Value) -> Result<Interpolation, String> {
+        let array = json.as_array().ok_or("invalid interpolation type")?;
+
+        match array.first().and_then(|typ| typ.as_str()) {
+            Some("linear") => Ok(Interpolation::Linear),
+            Some("exponential") => Ok(Interpolation::Exponential(
+                array
+                    .get(1)
+                    .and_then(|base| base.as_f64())
+                    .ok_or("exponential interpolation expects a base")?,
+            )),
+            _ => Err(format!("unsupported interpolation type {}", json)),
+        }
+    }
+
+    fn parse_stops(arguments: &[serde_json::Value]) -> Result<Vec<(f64, Expression)>, String> {
+        if arguments.len() % 2 != 0 {
+            return Err("stops must be pairs of input and output".to_string());
+        }
+
+        let stops = arguments
+            .chunks(2)
+            .map(|stop| -> Result<(f64, Expression), String> {
+                Ok((
+                    stop[0].as_f64().ok_or("stop inputs must be numbers")?,
+                    Self::parse(&stop[1])?,
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Self::check_ascending(&stops)?;
+        Ok(stops)
+    }
+
+    fn check_ascending(stops: &[(f64, Expression)]) -> Result<(), String> {
+        if stops.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err("stop inputs must be in strictly ascending order".to_string());
+        }
+        Ok(())
+    }
+
+    fn parse_function(object: &serde_json::Map<String, serde_json::Value>) -> Result<Self, String> {
+        let stops = object
+            .get("stops")
+            .and_then(|stops| stops.as_array())
+            .ok_or("functions must contain stops")?
+            .iter()
+            .map(|stop| -> Result<(&serde_json::Value, Expression), String> {
+                match stop.as_array().map(|stop| stop.as_slice()) {
+                    Some([input, output]) => Ok((input, Self::parse(output)?)),
+                    _ => Err("stops must be pairs of input and output".to_string()),
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if stops.is_empty() {
+            return Err("functions expect at least one stop".to_string());
+        }
+
+        let input = Box::new(
+            match object
+                .get("property")
+                .and_then(|property| property.as_str())
+            {
+                Some(property) => Expression::Get(property.to_string()),
+                None => Expression::Zoom,
+            },
+        );
+
+        let numeric_stops = |stops: Vec<(&serde_json::Value, Expression)>| {
+            let stops = stops
+                .into_iter()
+                .map(|(input, output)| -> Result<(f64, Expression), String> {
+                    Ok((input.as_f64().ok_or("stop inputs must be numbers")?, output))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Self::check_ascending(&stops)?;
+            Ok::<_, String>(stops)
+        };
+
+        match object.get("type").and_then(|typ| typ.as_str()) {
+            None | Some("exponential") => Ok(Expression::Interpolate {
+                interpolation: Interpolation::Exponential(
+                    object
+                        .get("base")
+                        .and_then(|base| base.as_f64())
+                        .unwrap_or(1.0),
+                ),
+                input,
+                stops: numeric_stops(stops)?,
+            }),
+            Some("interval") => {
+                // Inputs below the first stop take its output as well
+                let mut stops = numeric_stops(stops)?;
+                let (_, default) = stops.remove(0);
+                Ok(Expression::Step {
+                    input,
+                    default: Box::new(default),
+                    stops,
+                })
+            }
+            Some("categorical") => {
+                let cases = stops
+                    .into_iter()
+                    .map(
+                        |(label, output)| -> Result<(Vec<Value>, Expression), String> {
+                            Ok((Self::parse_labels(label)?, output))
+                        },
+                    )
+                    .collect::<Result<Vec<_>, String>>()?;
+                let fallback = match object.get("default") {
+                    Some(default) => Self::parse(default)?,
+                    None => Expression::Literal(Value::Null),
+                };
+                Ok(Expression::Match {
+                    input,
+                    cases,
+                    fallback: Box::new(fallback),
+                })
+            }
+            Some(typ) => Err(format!("unsupported function type {}", typ)),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let stops_to_json = |stops: &Vec<(f64, Expression)>| {
+            stops
+                .iter()
+                .flat_map(|(input, output)| [json!(input), output.to_json()])
+                .collect::<Vec<_>>()
+        };
+
+        match self {
+            Expression::Literal(value) => value.to_json(),
+            Expression::Zoom => json!(["zoom"]),
+            Expression::Get(key) => json!(["get", key]),
+            Expression::Interpolate {
+                interpolation,
+                input,
+                stops,
+            } => {
+                let interpolation = match interpolation {
+                    Interpolation::Linear => json!(["linear"]),
+                    Interpolation::Exponential(base) => json!(["exponential", base]),
+                };
+                let mut array = vec![json!("interpolate"), interpolation, input.to_json()];
+                array.extend(stops_to_json(stops));
+                serde_json::Value::Array(array)
+            }
+            Expression::Step {
+                input,
+                default,
+                stops,
+            } => {
+                let mut array = vec![json!("step"), input.to_json(), default.to_json()];
+                array.extend(stops_to_json(stops));
+                serde_json::Value::Array(array)
+            }
+            Expression::Match {
+                input,
+                cases,
+                fallback,
+            } => {
+                let mut array = vec![json!("match"), input.to_json()];
+                for (labels, output) in cases {
+                    array.push(match labels.as_slice() {
+                        [label] => label.to_json(),
+                        labels => labels.iter().map(|label| label.to_json()).collect(),
+                    });
+                    array.push(output.to_json());
+                }
+                array.push(fallback.to_json());
+                serde_json::Value::Array(array)
+            }
+        }
+    }
+
+    /// Evaluates the expression for the given zoom level. Properties are looked up in `feature`.
+    pub fn evaluate(&self, zoom: f64, feature: Option<&dyn FeatureProperties>) -> Value {
+        match self {
+            Expression::Literal(value) => value.clone(),
+            Expression::Zoom => Value::Number(zoom),
+            Expression::Get(key) => feature
+                .and_then(|feature| feature.get_property(key))
+                .unwrap_or(Value::Null),
+            Expression::Interpolate {
+                interpolation,
+                input,
+                stops,
+            } => {
+                let input = match input.evaluate(zoom, feature).as_number() {
+                    Some(input) => input,
+                    None => return Value::Null,
+                };
+
+                let upper_index = stops.iter().position(|(stop, _)| *stop > input);
+                match upper_index {
+                    Some(0) => stops[0].1.evaluate(zoom, feature),
+                    None => stops[stops.len() - 1].1.evaluate(zoom, feature),
+                    Some(upper_index) => {
+                        let (lower_input, lower) = &stops[upper_index - 1];
+                        let (upper_input, upper) = &stops[upper_index];
+                        let t = interpolation.factor(input, *lower_input, *upper_input);
+
+                        interpolate(
+                            &lower.evaluate(zoom, feature),
+                            &upper.evaluate(zoom, feature),
+                            t,
+                        )
+                    }
+                }
+            }
+            Expression::Step {
+                input,
+                default,
+                stops,
+            } => {
+                let input = match input.evaluate(zoom, feature).as_number() {
+                    Some(input) => input,
+                    None => return default.evaluate(zoom, feature),
+                };
+
+                stops
+                    .iter()
+                    .rev()
+                    .find(|(stop, _)| *stop <= input)
+                    .map(|(_, output)| output.evaluate(zoom, feature))
+                    .unwrap_or_else(|| default.evaluate(zoom, feature))
+            }
+            Expression::Match {
+                input,
+                cases,
+                fallback,
+            } => {
+                let input = input.evaluate(zoom, feature);
+
+                cases
+                    .iter()
+                    .find(|(labels, _)| labels.contains(&input))
+                    .map(|(_, output)| output.evaluate(zoom, feature))
+                    .unwrap_or_else(|| fallback.evaluate(zoom, feature))
+            }
+        }
+    }
+
+    /// Returns true if the result of the expression depends on the zoom level.
+    pub fn is_zoom_dependent(&self) -> bool {
+        match self {
+            Expression::Literal(_) | Expression::Get(_) => false,
+            Expression::Zoom => true,
+            Expression::Interpolate { input, stops, .. } => {
+                input.is_zoom_dependent()
+                    || stops.iter().any(|(_, output)| output.is_zoom_dependent())
+            }
+            Expression::Step {
+                input,
+                default,
+                stops,
+            } => {
+                input.is_zoom_dependent()
+                    || default.is_zoom_dependent()
+                    || stops.iter().any(|(_, output)| output.is_zoom_dependent())
+            }
+            Expression::Match {
+                input,
+                cases,
+                fallback,
+            } => {
+                input.is_zoom_dependent()
+                    || fallback.is_zoom_dependent()
+                    || cases.iter().any(|(_, output)| output.is_zoom_dependent())
+            }
+        }
+    }
+
+    /// Returns true if the result of the expression depends on the properties of a feature.
+    pub fn is_feature_dependent(&self) -> bool {
+        match self {
+            Expression::Literal(_) | Expression::Zoom => false,
+            Expression::Get(_) => true,
+            Expression::Interpolate { input, stops, .. } => {
+                input.is_feature_dependent()
+                    || stops
+                        .iter()
+                        .any(|(_, output)| output.is_feature_dependent())
+            }
+            Expression::Step {
+                input,
+                default,
+                stops,
+            } => {
+                input.is_feature_dependent()
+                    || default.is_feature_dependent()
+                    || stops
+                        .iter()
+                        .any(|(_, output)| output.is_feature_dependent())
+            }
+            Expression::Match {
+                input,
+                cases,
+                fallback,
+            } => {
+                input.is_feature_dependent()
+                    || fallback.is_feature_dependent()
+                    || cases
+                        .iter()
+                        .any(|(_, output)| output.is_feature_dependent())
+            }
+        }
+    }
+}
+
+/// Interpolates numbers and colors. Other values can not be interpolated and snap to `lower`.
+fn interpolate(lower: &Value, upper: &Value, t: f64) -> Value {
+    if let (Some(lower), Some(upper)) = (lower.as_number(), upper.as_number()) {
+        return Value::Number(lower + (upper - lower) * t);
+    }
+
+    if let (Some(lower), Some(upper)) = (lower.as_color(), upper.as_color()) {
+        return Value::Color(Color::from_rgba(
+            lower.r + (upper.r - lower.r) * t,
+            lower.g + (upper.g - lower.g) * t,
+            lower.b + (upper.b - lower.b) * t,
+            lower.a + (upper.a - lower.a) * t,
+        ));
+    }
+
+    lower.clone()
+}
+
+impl Serialize for Expression {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Expression {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = serde_json::Value::deserialize(deserializer)?;
+        Expression::parse(&json).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expression, FeatureProperties, Value};
+    use csscolorparser::Color;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    impl FeatureProperties for HashMap<String, Value> {
+        fn get_property(&self, key: &str) -> Option<Value> {
+            self.get(key).cloned()
+        }
+    }
+
+    fn parse(json: serde_json::Value) -> Expression {
+        Expression::parse(&json).unwrap()
+    }
+
+    #[test]
+    fn test_literal() {
+        assert_eq!(parse(json!(3)).evaluate(10.0, None), Value::Number(3.0));
+        assert_eq!(
+            parse(json!(["literal", "text"])).evaluate(10.0, None),
+            Value::String("text".to_string())
+        );
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let expression = parse(json!(["interpolate", ["linear"], ["zoom"], 5, 1, 15, 3]));
+
+        assert!(expression.is_zoom_dependent());
+        assert_eq!(expression.evaluate(0.0, None), Value::Number(1.0));
+        assert_eq!(expression.evaluate(10.0, None), Value::Number(2.0));
+        assert_eq!(expression.evaluate(20.0, None), Value::Number(3.0));
+
+        let expression = parse(json!([
+            "interpolate",
+            ["linear"],
+            ["zoom"],
+            0,
+            "#000000",
+            10,
+            "#ffffff"
+        ]));
+
+        let color = expression.evaluate(5.0, None).as_color().unwrap();
+        assert!((color.r - 0.5).abs() < 1e-6);
+        assert!((color.a - 1.0).abs() < 1e-6);
+
+        let expression = parse(json!({"base": 2, "stops": [[0, 0], [2, 3]]}));
+        assert_eq!(expression.evaluate(1.0, None), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_step_and_get() {
+        let expression = parse(json!(["step", ["get", "rank"], "red", 5, "blue"]));
+        assert!(!expression.is_zoom_dependent());
+        assert!(expression.is_feature_dependent());
+
+        let mut feature = HashMap::new();
+        feature.insert("rank".to_string(), Value::Number(7.0));
+        assert_eq!(
+            expression.evaluate(0.0, Some(&feature)).as_color(),
+            Some(Color::from_rgb(0.0, 0.0, 1.0))
+        );
+
+        feature.insert("rank".to_string(), Value::Number(2.0));
+        assert_eq!(
+            expression.evaluate(0.0, Some(&feature)),
+            Value::String("red".to_string())
+        );
+
+        // Missing properties evaluate to the default
+        assert_eq!(
+            expression.evaluate(0.0, None),
+            Value::String("red".to_string())
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        let json = json!([
+            "match",
+            ["get", "class"],
+            "water",
+            "#0000ff",
+            ["wood", "grass"],
+            "#00ff00",
+            "#cccccc"
+        ]);
+        let expression = parse(json.clone());
+        assert!(expression.is_feature_dependent());
+        assert!(!expression.is_zoom_dependent());
+        assert_eq!(serde_json::to_value(&expression).unwrap(), json);
+
+        let color = |class: &str| {
+            let mut feature = HashMap::new();
+            feature.insert("class".to_string(), Value::String(class.to_string()));
+            expression.evaluate(0.0, Some(&feature)).as_color()
+        };
+        assert_eq!(color("water"), Some(Color::from_rgb(0.0, 0.0, 1.0)));
+        assert_eq!(color("grass"), Some(Color::from_rgb(0.0, 1.0, 0.0)));
+        assert_eq!(color("rock"), Color::from_str("#cccccc").ok());
+        assert_eq!(
+            expression.evaluate(0.0, None).as_color(),
+            Color::from_str("#cccccc").ok()
+        );
+
+        let rank = parse(json!(["match", ["get", "rank"], [1, 2], 10, 0]));
+        let mut feature = HashMap::new();
+        feature.insert("rank".to_string(), Value::Number(2.0));
+        assert_eq!(rank.evaluate(0.0, Some(&feature)), Value::Number(10.0));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Expression::parse(&json!(["unknown", 1])).is_err());
+        assert!(Expression::parse(&json!(["interpolate", ["linear"], ["zoom"], 5])).is_err());
+        assert!(Expression::parse(&json!(["step", ["zoom"], 1, 10, 2, 5, 3])).is_err());
+        assert!(Expression::parse(&json!(["match", ["get", "class"], "water", 1])).is_err());
+    }
+
+    #[test]
+    fn test_functions() {
+        let interval = parse(json!({"type": "interval", "stops": [[5, 1], [10, 2]]}));
+        assert_eq!(interval.evaluate(0.0, None), Value::Number(1.0));
+        assert_eq!(interval.evaluate(7.5, None), Value::Number(1.0));
+        assert_eq!(interval.evaluate(10.0, None), Value::Number(2.0));
+
+        let categorical = parse(json!({
+            "type": "categorical",
+            "property": "class",
+            "stops": [["water", 1], ["wood", 2]],
+            "default": 0
+        }));
+        let class = |class: &str| {
+            let mut feature = HashMap::new();
+            feature.insert("class".to_string(), Value::String(class.to_string()));
+            categorical.evaluate(0.0, Some(&feature))
+        };
+        assert_eq!(class("wood"), Value::Number(2.0));
+        assert_eq!(class("rock"), Value::Number(0.0));
+        let without_default = parse(json!({
+            "type": "categorical",
+            "property": "class",
+            "stops": [["water", 1]]
+        }));
+        assert_eq!(without_default.evaluate(0.0, None), Value::Null);
+
+        // Functions without stops, with unsorted stops or of unknown types are rejected
+        assert!(Expression::parse(&json!({"stops": []})).is_err());
+        assert!(Expression::parse(&json!({"stops": [[10, 1], [5, 2]]})).is_err());
+        assert!(
+            Expression::parse(&json!({"type": "interval", "stops": [[5, 1], [5, 2]]})).is_err()
+        );
+        assert!(Expression::parse(&json!({"type": "identity", "property": "rank"})).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! Filters select the features of a source layer which are drawn by a style layer. Filters are
+//! written as JSON arrays, for example `["all", ["==", "class", "motorway"], ["has", "name"]]`.
+//!
+//! Properties are either referred to by their name, like in the legacy filter syntax, or by a
+//! `["get", key]` expression. The geometry type of a feature is available as the property `$type`.
+
+use crate::style::expression::{FeatureProperties, Value};
+use serde::de::Error as DeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+
+/// Property which holds the geometry type of a feature: `Point`, `LineString` or `Polygon`.
+pub const GEOMETRY_TYPE_PROPERTY: &str = "$type";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// `["all", filter_1, ...]`
+    All(Vec<Filter>),
+    /// `["any", filter_1, ...]`
+    Any(Vec<Filter>),
+    /// `["none", filter_1, ...]`
+    None(Vec<Filter>),
+    /// `["==", key, value]`
+    Equal(String, Value),
+    /// `["!=", key, value]`
+    NotEqual(String, Value),
+    /// `["in", key, value_1, ...]`
+    In(String, Vec<Value>),
+    /// `["!in", key, value_1, ...]`
+    NotIn(String, Vec<Value>),
+    /// `["has", key]`
+    Has(String),
+    /// `["!has", key]`
+    NotHas(String),
+}
+
+impl Filter {
+    /// Parses a filter from its JSON representation.
+    pub fn parse(json: &serde_json::Value) -> Result<Self, String> {
+        let array = json.as_array().ok_or("filters must be arrays")?;
+        let operator = array
+            .first()
+            .and_then(|operator| operator.as_str())
+            .ok_or("filters must start with an operator")?;
+        let arguments = &array[1..];
+
+        let parse_filters = |arguments: &[serde_json::Value]| {
+            arguments
+                .iter()
+                .map(Self::parse)
+                .collect::<Result<Vec<_>, String>>()
+        };
+
+        match operator {
+            "all" => Ok(Filter::All(parse_filters(arguments)?)),
+            "any" => Ok(Filter::Any(parse_filters(arguments)?)),
+            "none" => Ok(Filter::None(parse_filters(arguments)?)),
+            "==" | "!=" => match arguments {
+                [key, value] => {
+                    let key = Self::parse_key(key)?;
+                    let value = Self::parse_value(value)?;
+                    Ok(if operator == "==" {
+                        Filter::Equal(key, value)
+                    } else {
+                        Filter::NotEqual(key, value)
+                    })
+                }
+                _ => Err(format!("{} expects a property and a value", operator)),
+            },
+            "in" | "!in" => match arguments {
+                [key, values @ ..] => {
+                    let key = Self::parse_key(key)?;
+                    let values = Self::parse_values(values)?;
+                    Ok(if operator == "in" {
+                        Filter::In(key, values)
+                    } else {
+                        Filter::NotIn(key, values)
+                    })
+                }
+                _ => Err(format!("{} expects a property", operator)),
+            },
+            "has" | "!has" => match arguments {
+                [key] => {
+                    let key = Self::parse_key(key)?;
+                    Ok(if operator == "has" {
+                        Filter::Has(key)
+                    } else {
+                        Filter::NotHas(key)
+                    })
+                }
+                _ => Err(format!("{} expects a property", operator)),
+            },
+            operator => Err(format!("unsupported filter operator {}", operator)),
+        }
+    }
+
+    /// Properties are either given by name or as `["get", key]`.
+    fn parse_key(json: &serde_json::Value) -> Result<String, String> {
+        match json {
+            serde_json::Value::String(key) => Ok(key.clone()),
+            serde_json::Value::Array(array) => match array.as_slice() {
+                [get, serde_json::Value::String(key)] if get == "get" => Ok(key.clone()),
+                _ => Err(format!("invalid property {}", json)),
+            },
+            _ => Err(format!("invalid property {}", json)),
+        }
+    }
+
+    fn parse_value(json: &serde_json::Value) -> Result<Value, String> {
+        match json {
+            serde_json::Value::Null => Ok(Value::Null),
+            serde_json::Value::Bool(bool) => Ok(Value::Bool(*bool)),
+            serde_json::Value::Number(number) => {
+                Ok(Value::Number(number.as_f64().ok_or("invalid number")?))
+            }
+            serde_json::Value::String(string) => Ok(Value::String(string.clone())),
+            _ => Err(format!("invalid filter value {}", json)),
+        }
+    }
+
+    /// Values are either listed one by one or given as a single `["literal", [value_1, ...]]`.
+    fn parse_values(arguments: &[serde_json::Value]) -> Result<Vec<Value>, String> {
+        let values = match arguments {
+            [serde_json::Value::Array(literal)] => match literal.as_slice() {
+                [operator, serde_json::Value::Array(values)] if operator == "literal" => {
+                    values.as_slice()
+                }
+                _ => arguments,
+            },
+            _ => arguments,
+        };
+
+        values.iter().map(Self::parse_value).collect()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let filters_to_json = |operator: &str, filters: &Vec<Filter>| {
+            let mut array = vec![json!(operator)];
+            array.extend(filters.iter().map(|filter| filter.to_json()));
+            serde_json::Value::Array(array)
+        };
+        let values_to_json = |operator: &str, key: &String, values: &Vec<Value>| {
+            let mut array = vec![json!(operator), json!(key)];
+            array.extend(values.iter().map(|value| value.to_json()));
+            serde_json::Value::Array(array)
+        };
+
+        match self {
+            Filter::All(filters) => filters_to_json("all", filters),
+            Filter::Any(filters) => filters_to_json("any", filters),
+            Filter::None(filters) => filters_to_json("none", filters),
+            Filter::Equal(key, value) => json!(["==", key, value.to_json()]),
+            Filter::NotEqual(key, value) => json!(["!=", key, value.to_json()]),
+            Filter::In(key, values) => values_to_json("in", key, values),
+            Filter::NotIn(key, values) => values_to_json("!in", key, values),
+            Filter::Has(key) => json!(["has", key]),
+            Filter::NotHas(key) => json!(["!has", key]),
+        }
+    }
+
+    /// Whether the properties of `feature` match the filter. Features without the property match
+    /// `!=` and `!in`, but not `==` and `in`.
+    pub fn matches(&self, feature: &dyn FeatureProperties) -> bool {
+        match self {
+            Filter::All(filters) => filters.iter().all(|filter| filter.matches(feature)),
+            Filter::Any(filters) => filters.iter().any(|filter| filter.matches(feature)),
+            Filter::None(filters) => !filters.iter().any(|filter| filter.matches(feature)),
+            Filter::Equal(key, value) => feature.get_property(key).as_ref() == Some(value),
+            Filter::NotEqual(key, value) => feature.get_property(key).as_ref() != Some(value),
+            Filter::In(key, values) => feature
+                .get_property(key)
+                .map_or(false, |property| values.contains(&property)),
+            Filter::NotIn(key, values) => feature
+                .get_property(key)
+                .map_or(true, |property| !values.contains(&property)),
+            Filter::Has(key) => feature.get_property(key).is_some(),
+            Filter::NotHas(key) => feature.get_property(key).is_none(),
+        }
+    }
+}
+
+impl Serialize for Filter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Filter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = serde_json::Value::deserialize(deserializer)?;
+        Filter::parse(&json).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+    use crate::style::expression::{FeatureProperties, Value};
+    use serde_json::json;
+
+    struct Road {
+        class: &'static str,
+        name: Option<&'static str>,
+    }
+
+    impl FeatureProperties for Road {
+        fn get_property(&self, key: &str) -> Option<Value> {
+            match key {
+                "class" => Some(Value::String(self.class.to_string())),
+                "name" => self.name.map(|name| Value::String(name.to_string())),
+                "$type" => Some(Value::String("LineString".to_string())),
+                _ => None,
+            }
+        }
+    }
+
+    const MOTORWAY: Road = Road {
+        class: "motorway",
+        name: Some("A1"),
+    };
+    const PATH: Road = Road {
+        class: "path",
+        name: None,
+    };
+
+    fn parse(json: serde_json::Value) -> Filter {
+        Filter::parse(&json).unwrap()
+    }
+
+    #[test]
+    fn test_comparison() {
+        let equal = parse(json!(["==", "class", "motorway"]));
+        assert!(equal.matches(&MOTORWAY));
+        assert!(!equal.matches(&PATH));
+
+        let not_equal = parse(json!(["!=", ["get", "class"], "motorway"]));
+        assert!(!not_equal.matches(&MOTORWAY));
+        assert!(not_equal.matches(&PATH));
+
+        assert!(parse(json!(["==", "$type", "LineString"])).matches(&PATH));
+        // Missing properties are not equal to any value
+        assert!(!parse(json!(["==", "ref", "A1"])).matches(&MOTORWAY));
+        assert!(parse(json!(["!=", "ref", "A1"])).matches(&MOTORWAY));
+    }
+
+    #[test]
+    fn test_membership() {
+        let major = parse(json!(["in", "class", "motorway", "trunk", "primary"]));
+        assert!(major.matches(&MOTORWAY));
+        assert!(!major.matches(&PATH));
+
+        let minor = parse(json!(["!in", "class", "motorway", "trunk", "primary"]));
+        assert!(!minor.matches(&MOTORWAY));
+        assert!(minor.matches(&PATH));
+
+        let literal = parse(json!([
+            "in",
+            ["get", "class"],
+            ["literal", ["path", "track"]]
+        ]));
+        assert!(literal.matches(&PATH));
+
+        assert!(parse(json!(["has", "name"])).matches(&MOTORWAY));
+        assert!(!parse(json!(["has", "name"])).matches(&PATH));
+        assert!(parse(json!(["!has", "name"])).matches(&PATH));
+    }
+
+    #[test]
+    fn test_combinators() {
+        let named_motorway = parse(json!(["all", ["==", "class", "motorway"], ["has", "name"]]));
+        assert!(named_motorway.matches(&MOTORWAY));
+        assert!(!named_motorway.matches(&PATH));
+
+        let any = parse(json!(["any", ["==", "class", "path"], ["has", "name"]]));
+        assert!(any.matches(&MOTORWAY));
+        assert!(any.matches(&PATH));
+
+        let none = parse(json!(["none", ["==", "class", "path"], ["has", "ref"]]));
+        assert!(none.matches(&MOTORWAY));
+        assert!(!none.matches(&PATH));
+
+        // An empty `all` matches every feature
+        assert!(parse(json!(["all"])).matches(&PATH));
+    }
+
+    #[test]
+    fn test_serialization() {
+        let json = json!([
+            "all",
+            ["in", "class", "motorway", "trunk"],
+            ["!has", "tunnel"]
+        ]);
+        let filter: Filter = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&filter).unwrap(), json);
+
+        assert!(Filter::parse(&json!(["unknown", "class"])).is_err());
+        assert!(Filter::parse(&json!(["==", "class"])).is_err());
+        assert!(Filter::parse(&json!("class")).is_err());
+    }
+}
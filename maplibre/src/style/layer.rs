@@ -1,34 +1,217 @@
 //! Vector tile layer drawing utilities.
 
+use crate::style::expression::{Expression, FeatureProperties};
+use crate::style::filter::Filter;
 use cint::{Alpha, EncodedSrgb};
-use csscolorparser::Color;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct BackgroundPaint {
     #[serde(rename = "background-color")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub background_color: Option<Color>,
+    pub background_color: Option<Expression>,
+    #[serde(rename = "background-opacity")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_opacity: Option<Expression>,
     // TODO a lot
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct FillPaint {
     #[serde(rename = "fill-color")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub fill_color: Option<Color>,
+    pub fill_color: Option<Expression>,
+    #[serde(rename = "fill-opacity")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_opacity: Option<Expression>,
+    /// Name of an image of the sprite which is repeated across the polygons instead of filling
+    /// them with `fill-color`.
+    #[serde(rename = "fill-pattern")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_pattern: Option<String>,
     // TODO a lot
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FillExtrusionPaint {
+    #[serde(rename = "fill-extrusion-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_extrusion_color: Option<Expression>,
+    #[serde(rename = "fill-extrusion-opacity")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_extrusion_opacity: Option<Expression>,
+    /// Height of the roof in meters.
+    #[serde(rename = "fill-extrusion-height")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_extrusion_height: Option<Expression>,
+    /// Height of the bottom of the walls in meters.
+    #[serde(rename = "fill-extrusion-base")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_extrusion_base: Option<Expression>,
+    // TODO a lot
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct LinePaint {
     #[serde(rename = "line-color")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub line_color: Option<Color>,
+    pub line_color: Option<Expression>,
+    #[serde(rename = "line-opacity")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_opacity: Option<Expression>,
+    /// Width of the line in pixels.
+    #[serde(rename = "line-width")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_width: Option<Expression>,
+    /// Width of the gap in pixels between two lines, which are each `line-width` wide and drawn
+    /// on both sides of the path. Used for casings, e.g. of bridges.
+    #[serde(rename = "line-gap-width")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_gap_width: Option<Expression>,
+    /// Lengths of alternating dashes and gaps in units of the line width.
+    #[serde(rename = "line-dasharray")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_dasharray: Option<Vec<f32>>,
+    /// Name of an image of the sprite which is repeated along the lines instead of drawing them
+    /// with `line-color`, e.g. the ties of railways.
+    #[serde(rename = "line-pattern")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_pattern: Option<String>,
+    // TODO a lot
+}
+
+impl LinePaint {
+    /// Normalizes the `line-dasharray` into exactly two dash/gap pairs which can be passed to the
+    /// shaders. Odd length arrays are repeated like in the reference renderer, longer arrays are
+    /// truncated. Returns `None` if the line is solid.
+    pub fn dash_pattern(&self) -> Option<[f32; 4]> {
+        let dasharray = self.line_dasharray.as_ref()?;
+
+        if dasharray.is_empty() || dasharray.iter().any(|length| *length < 0.0) {
+            return None;
+        }
+
+        let mut pattern = dasharray.clone();
+        while pattern.len() < 4 {
+            pattern.extend_from_within(..);
+        }
+
+        let pattern = [pattern[0], pattern[1], pattern[2], pattern[3]];
+
+        if pattern.iter().sum::<f32>() <= 0.0 {
+            None
+        } else {
+            Some(pattern)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SymbolPaint {
+    #[serde(rename = "text-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_color: Option<Expression>,
+    /// Color of the halo around the glyphs, which keeps labels legible on busy backgrounds.
+    #[serde(rename = "text-halo-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_halo_color: Option<Expression>,
+    /// Distance in pixels from the edge of the glyphs to the outer edge of the halo.
+    #[serde(rename = "text-halo-width")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_halo_width: Option<Expression>,
+    // TODO a lot
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RasterPaint {
+    #[serde(rename = "raster-opacity")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raster_opacity: Option<f32>,
     // TODO a lot
 }
 
+/// Whether the direction of the light of hillshade layers is relative to north or to the top of
+/// the viewport.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IlluminationAnchor {
+    #[serde(rename = "map")]
+    Map,
+    #[serde(rename = "viewport")]
+    Viewport,
+}
+
+impl Default for IlluminationAnchor {
+    fn default() -> Self {
+        IlluminationAnchor::Viewport
+    }
+}
+
+/// Paint of layers which shade the terrain of a raster DEM source.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HillshadePaint {
+    /// Intensity of the shading from 0 to 1.
+    #[serde(rename = "hillshade-exaggeration")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_exaggeration: Option<f32>,
+    /// Direction of the light source in degrees, clockwise from the top.
+    #[serde(rename = "hillshade-illumination-direction")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_illumination_direction: Option<f32>,
+    #[serde(rename = "hillshade-illumination-anchor")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_illumination_anchor: Option<IlluminationAnchor>,
+    /// Color of slopes which face away from the light source.
+    #[serde(rename = "hillshade-shadow-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_shadow_color: Option<Expression>,
+    /// Color of slopes which face the light source.
+    #[serde(rename = "hillshade-highlight-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_highlight_color: Option<Expression>,
+    /// Color which accentuates rugged terrain, e.g. sharp cliffs.
+    #[serde(rename = "hillshade-accent-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_accent_color: Option<Expression>,
+}
+
+impl HillshadePaint {
+    pub fn exaggeration(&self) -> f32 {
+        self.hillshade_exaggeration.unwrap_or(0.5).clamp(0.0, 1.0)
+    }
+
+    /// Returns the direction of the light source in radians, clockwise from north. If the light
+    /// is anchored to the viewport, the direction is rotated with the map by `bearing`.
+    pub fn illumination_direction(&self, bearing: f64) -> f32 {
+        let direction = self
+            .hillshade_illumination_direction
+            .unwrap_or(335.0)
+            .to_radians();
+        match self.hillshade_illumination_anchor.unwrap_or_default() {
+            IlluminationAnchor::Map => direction,
+            IlluminationAnchor::Viewport => direction + bearing as f32,
+        }
+    }
+
+    /// Evaluates the shadow, highlight and accent colors in sRGB at the given zoom level.
+    pub fn colors(&self, zoom: f64) -> [[f32; 4]; 3] {
+        let color = |expression: &Option<Expression>, default: [f32; 4]| {
+            expression
+                .as_ref()
+                .and_then(|color| color.evaluate(zoom, None).as_color())
+                .map_or(default, |color| {
+                    let color: Alpha<EncodedSrgb<f32>> = color.into();
+                    color.into()
+                })
+        };
+        [
+            color(&self.hillshade_shadow_color, [0.0, 0.0, 0.0, 1.0]),
+            color(&self.hillshade_highlight_color, [1.0, 1.0, 1.0, 1.0]),
+            color(&self.hillshade_accent_color, [0.0, 0.0, 0.0, 1.0]),
+        ]
+    }
+}
+
 /// The different types of paints.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", content = "paint")]
@@ -39,44 +222,713 @@ pub enum LayerPaint {
     Line(LinePaint),
     #[serde(rename = "fill")]
     Fill(FillPaint),
+    #[serde(rename = "fill-extrusion")]
+    FillExtrusion(FillExtrusionPaint),
+    #[serde(rename = "symbol")]
+    Symbol(SymbolPaint),
+    #[serde(rename = "raster")]
+    Raster(RasterPaint),
+    #[serde(rename = "hillshade")]
+    Hillshade(HillshadePaint),
 }
 
 impl LayerPaint {
-    pub fn get_color(&self) -> Option<Alpha<EncodedSrgb<f32>>> {
+    fn color_expression(&self) -> Option<&Expression> {
+        match self {
+            LayerPaint::Background(paint) => paint.background_color.as_ref(),
+            LayerPaint::Line(paint) => paint.line_color.as_ref(),
+            LayerPaint::Fill(paint) => paint.fill_color.as_ref(),
+            LayerPaint::FillExtrusion(paint) => paint.fill_extrusion_color.as_ref(),
+            LayerPaint::Symbol(paint) => paint.text_color.as_ref(),
+            LayerPaint::Raster(_) | LayerPaint::Hillshade(_) => None,
+        }
+    }
+
+    fn opacity_expression(&self) -> Option<&Expression> {
         match self {
-            LayerPaint::Background(paint) => paint
-                .background_color
+            LayerPaint::Background(paint) => paint.background_opacity.as_ref(),
+            LayerPaint::Line(paint) => paint.line_opacity.as_ref(),
+            LayerPaint::Fill(paint) => paint.fill_opacity.as_ref(),
+            LayerPaint::FillExtrusion(paint) => paint.fill_extrusion_opacity.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Evaluates the color at the given zoom level for a feature.
+    pub fn get_color(
+        &self,
+        zoom: f64,
+        feature: Option<&dyn FeatureProperties>,
+    ) -> Option<Alpha<EncodedSrgb<f32>>> {
+        self.color_expression()
+            .and_then(|color| color.evaluate(zoom, feature).as_color())
+            .map(|color| color.into())
+    }
+
+    /// Evaluates the color like [`LayerPaint::get_color`] and converts it from sRGB to linear
+    /// space, in which GPUs blend colors correctly. The alpha channel is not converted.
+    pub fn get_linear_color(
+        &self,
+        zoom: f64,
+        feature: Option<&dyn FeatureProperties>,
+    ) -> Option<[f32; 4]> {
+        self.get_color(zoom, feature).map(|color| {
+            let EncodedSrgb { r, g, b } = color.color;
+            [
+                srgb_to_linear(r),
+                srgb_to_linear(g),
+                srgb_to_linear(b),
+                color.alpha,
+            ]
+        })
+    }
+
+    /// Evaluates the opacity at the given zoom level for a feature. The opacity is clamped to
+    /// `[0, 1]`.
+    pub fn get_opacity(&self, zoom: f64, feature: Option<&dyn FeatureProperties>) -> Option<f32> {
+        self.opacity_expression()
+            .and_then(|opacity| opacity.evaluate(zoom, feature).as_number())
+            .map(|opacity| opacity.clamp(0.0, 1.0) as f32)
+    }
+
+    /// Evaluates the color and the width in pixels of the halo of labels at the given zoom level
+    /// for a feature, see [`SymbolPaint::text_halo_color`].
+    pub fn get_text_halo(
+        &self,
+        zoom: f64,
+        feature: Option<&dyn FeatureProperties>,
+    ) -> (Option<Alpha<EncodedSrgb<f32>>>, Option<f32>) {
+        match self {
+            LayerPaint::Symbol(paint) => (
+                paint
+                    .text_halo_color
+                    .as_ref()
+                    .and_then(|color| color.evaluate(zoom, feature).as_color())
+                    .map(|color| color.into()),
+                paint
+                    .text_halo_width
+                    .as_ref()
+                    .and_then(|width| width.evaluate(zoom, feature).as_number())
+                    .map(|width| width.max(0.0) as f32),
+            ),
+            _ => (None, None),
+        }
+    }
+
+    fn line_width_expression(&self) -> Option<&Expression> {
+        match self {
+            LayerPaint::Line(paint) => paint.line_width.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Evaluates the width of lines at the given zoom level for a feature.
+    pub fn get_line_width(
+        &self,
+        zoom: f64,
+        feature: Option<&dyn FeatureProperties>,
+    ) -> Option<f32> {
+        self.line_width_expression()
+            .and_then(|width| width.evaluate(zoom, feature).as_number())
+            .map(|width| width as f32)
+    }
+
+    /// Evaluates the width of the gap between the two lines of a casing at the given zoom level,
+    /// see [`LinePaint::line_gap_width`].
+    pub fn get_line_gap_width(&self, zoom: f64) -> Option<f32> {
+        match self {
+            LayerPaint::Line(paint) => paint
+                .line_gap_width
                 .as_ref()
-                .map(|color| color.clone().into()),
-            LayerPaint::Line(paint) => paint.line_color.as_ref().map(|color| color.clone().into()),
-            LayerPaint::Fill(paint) => paint.fill_color.as_ref().map(|color| color.clone().into()),
+                .and_then(|gap_width| gap_width.evaluate(zoom, None).as_number())
+                .map(|gap_width| gap_width as f32),
+            _ => None,
+        }
+    }
+
+    /// Whether lines of this paint can have a gap, see [`LinePaint::line_gap_width`].
+    pub fn has_line_gap(&self) -> bool {
+        matches!(
+            self,
+            LayerPaint::Line(LinePaint {
+                line_gap_width: Some(_),
+                ..
+            })
+        )
+    }
+
+    /// Evaluates the height and the base of extruded features in meters. Either is `None` if the
+    /// paint does not define it or it does not evaluate to a number for the feature.
+    pub fn get_extrusion(
+        &self,
+        zoom: f64,
+        feature: Option<&dyn FeatureProperties>,
+    ) -> (Option<f32>, Option<f32>) {
+        match self {
+            LayerPaint::FillExtrusion(paint) => {
+                let evaluate = |expression: &Option<Expression>| {
+                    expression
+                        .as_ref()
+                        .and_then(|expression| expression.evaluate(zoom, feature).as_number())
+                        .map(|value| value as f32)
+                };
+                (
+                    evaluate(&paint.fill_extrusion_height),
+                    evaluate(&paint.fill_extrusion_base),
+                )
+            }
+            _ => (None, None),
+        }
+    }
+
+    /// Returns true if the color, opacity or gap width of this paint changes with the zoom level.
+    /// Line widths are interpolated between zoom levels on the GPU instead, see
+    /// [`LayerPaint::is_line_width_zoom_dependent`].
+    pub fn is_zoom_dependent(&self) -> bool {
+        let line_gap_width = match self {
+            LayerPaint::Line(paint) => paint.line_gap_width.as_ref(),
+            _ => None,
+        };
+
+        self.color_expression()
+            .into_iter()
+            .chain(self.opacity_expression())
+            .chain(line_gap_width)
+            .any(|expression| expression.is_zoom_dependent())
+    }
+
+    /// Returns true if the width of lines changes with the zoom level.
+    pub fn is_line_width_zoom_dependent(&self) -> bool {
+        self.line_width_expression()
+            .map_or(false, |expression| expression.is_zoom_dependent())
+    }
+
+    /// Returns true if the color, opacity or line width of features can differ within a layer.
+    pub fn is_feature_dependent(&self) -> bool {
+        self.color_expression()
+            .into_iter()
+            .chain(self.opacity_expression())
+            .chain(self.line_width_expression())
+            .any(|expression| expression.is_feature_dependent())
+    }
+
+    /// Returns the normalized dash pattern of line layers, see [`LinePaint::dash_pattern`].
+    pub fn get_dash_pattern(&self) -> Option<[f32; 4]> {
+        match self {
+            LayerPaint::Line(paint) => paint.dash_pattern(),
+            _ => None,
         }
     }
+
+    /// Returns the name of the sprite image which fills polygons or is repeated along lines, see
+    /// [`FillPaint::fill_pattern`] and [`LinePaint::line_pattern`].
+    pub fn get_pattern(&self) -> Option<&str> {
+        match self {
+            LayerPaint::Fill(paint) => paint.fill_pattern.as_deref(),
+            LayerPaint::Line(paint) => paint.line_pattern.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a layer is displayed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    #[serde(rename = "visible")]
+    Visible,
+    #[serde(rename = "none")]
+    None,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility::Visible
+    }
+}
+
+/// Shape of the ends of lines.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    #[serde(rename = "butt")]
+    Butt,
+    #[serde(rename = "round")]
+    Round,
+    #[serde(rename = "square")]
+    Square,
+}
+
+/// Shape of the corners of lines.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+    #[serde(rename = "bevel")]
+    Bevel,
+    #[serde(rename = "round")]
+    Round,
+    #[serde(rename = "miter")]
+    Miter,
+}
+
+/// Placement of labels relative to the geometry of their feature.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolPlacement {
+    /// Labels are placed at the points of the feature.
+    #[serde(rename = "point")]
+    Point,
+    /// Labels follow the lines of the feature and repeat every `symbol-spacing` pixels.
+    #[serde(rename = "line")]
+    Line,
+    /// A single label follows the center of each line of the feature.
+    #[serde(rename = "line-center")]
+    LineCenter,
+}
+
+impl Default for SymbolPlacement {
+    fn default() -> Self {
+        SymbolPlacement::Point
+    }
+}
+
+/// Whether the text or the icons of labels rotate with the map when the bearing changes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationAlignment {
+    /// Labels are aligned with the map and rotate with it.
+    #[serde(rename = "map")]
+    Map,
+    /// Labels stay upright in the viewport.
+    #[serde(rename = "viewport")]
+    Viewport,
+    /// `map` for labels which are placed along lines, `viewport` otherwise.
+    #[serde(rename = "auto")]
+    Auto,
+}
+
+impl Default for RotationAlignment {
+    fn default() -> Self {
+        RotationAlignment::Auto
+    }
+}
+
+impl RotationAlignment {
+    /// Resolves [`RotationAlignment::Auto`] for labels with the given `placement`.
+    pub fn resolve(self, placement: SymbolPlacement) -> RotationAlignment {
+        match (self, placement) {
+            (RotationAlignment::Auto, SymbolPlacement::Point) => RotationAlignment::Viewport,
+            (RotationAlignment::Auto, _) => RotationAlignment::Map,
+            (alignment, _) => alignment,
+        }
+    }
+}
+
+/// Part of an icon which is placed at the position of its feature.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconAnchor {
+    #[serde(rename = "center")]
+    Center,
+    #[serde(rename = "left")]
+    Left,
+    #[serde(rename = "right")]
+    Right,
+    #[serde(rename = "top")]
+    Top,
+    #[serde(rename = "bottom")]
+    Bottom,
+    #[serde(rename = "top-left")]
+    TopLeft,
+    #[serde(rename = "top-right")]
+    TopRight,
+    #[serde(rename = "bottom-left")]
+    BottomLeft,
+    #[serde(rename = "bottom-right")]
+    BottomRight,
+}
+
+impl Default for IconAnchor {
+    fn default() -> Self {
+        IconAnchor::Center
+    }
+}
+
+impl IconAnchor {
+    /// Returns the position of the anchor within the icon as fractions of its width and height,
+    /// measured from the top left corner.
+    pub fn alignment(self) -> (f32, f32) {
+        match self {
+            IconAnchor::Center => (0.5, 0.5),
+            IconAnchor::Left => (0.0, 0.5),
+            IconAnchor::Right => (1.0, 0.5),
+            IconAnchor::Top => (0.5, 0.0),
+            IconAnchor::Bottom => (0.5, 1.0),
+            IconAnchor::TopLeft => (0.0, 0.0),
+            IconAnchor::TopRight => (1.0, 0.0),
+            IconAnchor::BottomLeft => (0.0, 1.0),
+            IconAnchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+/// How the icon of a label is stretched to fit around its text, e.g. for highway shields.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconTextFit {
+    /// The icon keeps its size.
+    #[serde(rename = "none")]
+    None,
+    /// The icon is stretched horizontally to the width of the text.
+    #[serde(rename = "width")]
+    Width,
+    /// The icon is stretched vertically to the height of the text.
+    #[serde(rename = "height")]
+    Height,
+    /// The icon is stretched in both directions to the size of the text.
+    #[serde(rename = "both")]
+    Both,
+}
+
+impl Default for IconTextFit {
+    fn default() -> Self {
+        IconTextFit::None
+    }
+}
+
+/// Layout properties of a layer. Layout properties are applied when the geometry of a layer is
+/// prepared for rendering.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LayerLayout {
+    /// Hidden layers are neither uploaded nor drawn.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<Visibility>,
+    /// Value to use for a text label. Tokens like `{name}` are replaced with feature properties.
+    #[serde(rename = "text-field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_field: Option<String>,
+    /// Font size in pixels.
+    #[serde(rename = "text-size")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_size: Option<Expression>,
+    /// Font stack to use for displaying text.
+    #[serde(rename = "text-font")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_font: Option<Vec<String>>,
+    /// If true, the text is shown even if it collides with other labels.
+    #[serde(rename = "text-allow-overlap")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_allow_overlap: Option<bool>,
+    #[serde(rename = "symbol-placement")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol_placement: Option<SymbolPlacement>,
+    /// Distance in pixels between labels which are placed along lines.
+    #[serde(rename = "symbol-spacing")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol_spacing: Option<Expression>,
+    #[serde(rename = "text-rotation-alignment")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_rotation_alignment: Option<RotationAlignment>,
+    /// Sorts labels in ascending order. Labels with a lower key are placed first.
+    #[serde(rename = "symbol-sort-key")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol_sort_key: Option<Expression>,
+    /// Name of the image in the sprite which is drawn as icon. Tokens like `{class}` are replaced
+    /// with feature properties.
+    #[serde(rename = "icon-image")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_image: Option<String>,
+    /// Factor by which the image of the icon is scaled.
+    #[serde(rename = "icon-size")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_size: Option<Expression>,
+    #[serde(rename = "icon-anchor")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_anchor: Option<IconAnchor>,
+    #[serde(rename = "icon-rotation-alignment")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_rotation_alignment: Option<RotationAlignment>,
+    /// Stretches the icon to fit around the text of the label. The icon is centered on the text
+    /// and `icon-size` as well as `icon-anchor` are ignored.
+    #[serde(rename = "icon-text-fit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_text_fit: Option<IconTextFit>,
+    /// Padding in pixels between the text and the edges of a fitted icon, in the order top, right,
+    /// bottom and left.
+    #[serde(rename = "icon-text-fit-padding")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_text_fit_padding: Option<[f32; 4]>,
+    #[serde(rename = "line-cap")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_cap: Option<LineCap>,
+    #[serde(rename = "line-join")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_join: Option<LineJoin>,
+    /// Miter joins of sharp corners are turned into bevel joins above this ratio of the miter
+    /// length to the line width.
+    #[serde(rename = "line-miter-limit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_miter_limit: Option<f32>,
+    // TODO a lot
+}
+
+/// Evaluates the numeric layout property `expression` at the given zoom level for a feature.
+fn evaluate_number(
+    expression: &Option<Expression>,
+    zoom: f64,
+    feature: Option<&dyn FeatureProperties>,
+) -> Option<f32> {
+    expression
+        .as_ref()
+        .and_then(|expression| expression.evaluate(zoom, feature).as_number())
+        .map(|number| number as f32)
+}
+
+impl LayerLayout {
+    /// Evaluates the font size in pixels at the given zoom level for a feature.
+    pub fn get_text_size(&self, zoom: f64, feature: Option<&dyn FeatureProperties>) -> Option<f32> {
+        evaluate_number(&self.text_size, zoom, feature)
+    }
+
+    /// Evaluates the distance in pixels between labels along lines at the given zoom level for a
+    /// feature.
+    pub fn get_symbol_spacing(
+        &self,
+        zoom: f64,
+        feature: Option<&dyn FeatureProperties>,
+    ) -> Option<f32> {
+        evaluate_number(&self.symbol_spacing, zoom, feature)
+    }
+
+    /// Evaluates the sort key of the labels of a feature at the given zoom level.
+    pub fn get_symbol_sort_key(
+        &self,
+        zoom: f64,
+        feature: Option<&dyn FeatureProperties>,
+    ) -> Option<f32> {
+        evaluate_number(&self.symbol_sort_key, zoom, feature)
+    }
+
+    /// Evaluates the scale of icons at the given zoom level for a feature.
+    pub fn get_icon_size(&self, zoom: f64, feature: Option<&dyn FeatureProperties>) -> Option<f32> {
+        evaluate_number(&self.icon_size, zoom, feature)
+    }
 }
 
 /// Stores all the styles for a specific layer.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawStyleLayer")]
 pub struct StyleLayer {
     #[serde(skip)]
     pub index: u32,
     pub id: String,
     #[serde(rename = "type")]
     pub typ: String,
-    // TODO filter
-    // TODO layout
+    /// Selects the features of the source layer which are drawn. All features are drawn if there
+    /// is no filter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<LayerLayout>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub maxzoom: Option<u8>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub minzoom: Option<u8>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub metadata: Option<HashMap<String, String>>,
+    pub metadata: Option<HashMap<String, serde_json::Value>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(flatten)]
     pub paint: Option<LayerPaint>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
+    #[serde(rename = "source-layer")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_layer: Option<String>,
+    /// Paint and layout properties of the style document which are not supported and therefore
+    /// ignored, e.g. `paint.fill-antialias`, see [`crate::style::Style::validate`].
+    #[serde(skip)]
+    pub unrecognized_properties: Vec<String>,
+}
+
+/// Whether changing the paint property `property` only changes the colors and widths with which
+/// the tessellated geometry is drawn. The gap width decides whether lines are tessellated as two
+/// halves, see [`crate::tessellation::LineStyle::hollow`].
+fn is_runtime_paint_property(property: &str) -> bool {
+    (property.ends_with("-color") || property.ends_with("-opacity") || property.ends_with("-width"))
+        && property != "line-gap-width"
+}
+
+impl StyleLayer {
+    /// Changes the paint property `property` to `value`, or resets it to its default if `value`
+    /// is null. Only colors, opacities and line widths of fill, line and background layers can be
+    /// changed, because the other properties are baked into the geometry or the layer metadata.
+    /// Returns false if the property can not be changed or `value` is invalid.
+    pub fn set_paint_property(&mut self, property: &str, value: serde_json::Value) -> bool {
+        if !is_runtime_paint_property(property) {
+            return false;
+        }
+
+        let paint = match &self.paint {
+            Some(paint) => serde_json::to_value(paint).ok(),
+            None => Some(serde_json::json!({ "type": self.typ, "paint": {} })),
+        };
+        let mut paint = match paint {
+            Some(serde_json::Value::Object(paint)) => paint,
+            _ => return false,
+        };
+        match paint.get("type").and_then(|typ| typ.as_str()) {
+            Some("fill" | "line" | "background") => {}
+            _ => return false,
+        }
+
+        let properties = match paint.get_mut("paint") {
+            Some(serde_json::Value::Object(properties)) => properties,
+            _ => return false,
+        };
+        let reset = value.is_null();
+        if reset {
+            properties.remove(property);
+        } else {
+            properties.insert(property.to_string(), value);
+        }
+
+        // Values of properties which the type of the layer does not have are ignored by serde, so
+        // the property needs to survive a round trip
+        match serde_json::from_value::<LayerPaint>(serde_json::Value::Object(paint)) {
+            Ok(parsed)
+                if reset
+                    || serde_json::to_value(&parsed)
+                        .ok()
+                        .and_then(|parsed| parsed.get("paint")?.get(property).cloned())
+                        .is_some() =>
+            {
+                self.paint = Some(parsed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.layout
+            .as_ref()
+            .and_then(|layout| layout.visibility)
+            .unwrap_or_default()
+            == Visibility::Visible
+    }
+
+    /// Shows or hides the layer.
+    pub fn set_visibility(&mut self, visibility: Visibility) {
+        self.layout.get_or_insert_with(Default::default).visibility = Some(visibility);
+    }
+
+    /// Whether `zoom` is within `minzoom..maxzoom`. Layers are only drawn within their zoom range.
+    pub fn is_in_zoom_range(&self, zoom: f64) -> bool {
+        self.minzoom.map_or(true, |minzoom| zoom >= minzoom as f64)
+            && self.maxzoom.map_or(true, |maxzoom| zoom < maxzoom as f64)
+    }
+
+    /// Whether the layer draws `feature`, see [`Filter::matches`].
+    pub fn matches(&self, feature: &dyn FeatureProperties) -> bool {
+        self.filter
+            .as_ref()
+            .map_or(true, |filter| filter.matches(feature))
+    }
+
+    /// Whether the layer is visible and `zoom` is within its zoom range.
+    pub fn is_visible_at(&self, zoom: f64) -> bool {
+        self.is_visible() && self.is_in_zoom_range(zoom)
+    }
+}
+
+/// A layer as it is stored in a style document. The paint properties are parsed according to the
+/// type of the layer. Deriving this for [`StyleLayer`] does not work, because the type would be
+/// consumed by [`StyleLayer::typ`] before the flattened [`LayerPaint`] could see it.
+#[derive(Deserialize)]
+struct RawStyleLayer {
+    id: String,
+    #[serde(rename = "type")]
+    typ: String,
+    filter: Option<Filter>,
+    layout: Option<serde_json::Map<String, serde_json::Value>>,
+    maxzoom: Option<u8>,
+    minzoom: Option<u8>,
+    metadata: Option<HashMap<String, serde_json::Value>>,
+    paint: Option<serde_json::Map<String, serde_json::Value>>,
+    source: Option<String>,
+    #[serde(rename = "source-layer")]
+    source_layer: Option<String>,
+}
+
+/// Returns the keys of `raw` which are missing in `parsed`, i.e. which serde ignored while parsing
+/// `raw`. Each key is prefixed with `prefix`.
+fn ignored_keys(
+    prefix: &str,
+    raw: &serde_json::Map<String, serde_json::Value>,
+    parsed: Option<serde_json::Value>,
+) -> Vec<String> {
+    let parsed = parsed.unwrap_or_default();
+    raw.keys()
+        .filter(|key| parsed.get(key.as_str()).is_none())
+        .map(|key| format!("{}.{}", prefix, key))
+        .collect()
+}
+
+impl TryFrom<RawStyleLayer> for StyleLayer {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: RawStyleLayer) -> Result<Self, Self::Error> {
+        let raw_layout = raw.layout.unwrap_or_default();
+        let raw_paint = raw.paint.unwrap_or_default();
+
+        let layout = if raw_layout.is_empty() {
+            None
+        } else {
+            Some(serde_json::from_value::<LayerLayout>(
+                serde_json::Value::Object(raw_layout.clone()),
+            )?)
+        };
+
+        let paint = serde_json::json!({
+            "type": raw.typ,
+            "paint": raw_paint,
+        });
+        // Layers of types without paint properties, or with paint properties which are not
+        // supported, are drawn with the default paint
+        let paint = match serde_json::from_value::<LayerPaint>(paint) {
+            Ok(paint) => Some(paint),
+            Err(e) => {
+                log::warn!("paint of layer {} is not supported: {}", raw.id, e);
+                None
+            }
+        };
+
+        // Properties which survive a round trip through the parsed structs are recognized
+        let mut unrecognized_properties = ignored_keys(
+            "layout",
+            &raw_layout,
+            layout
+                .as_ref()
+                .and_then(|layout| serde_json::to_value(layout).ok()),
+        );
+        if let Some(paint) = &paint {
+            unrecognized_properties.extend(ignored_keys(
+                "paint",
+                &raw_paint,
+                serde_json::to_value(paint)
+                    .ok()
+                    .and_then(|paint| paint.get("paint").cloned()),
+            ));
+        }
+
+        Ok(Self {
+            index: 0,
+            id: raw.id,
+            typ: raw.typ,
+            filter: raw.filter,
+            layout,
+            maxzoom: raw.maxzoom,
+            minzoom: raw.minzoom,
+            metadata: raw.metadata,
+            paint,
+            source: raw.source,
+            source_layer: raw.source_layer,
+            unrecognized_properties,
+        })
+    }
 }
 
 impl Default for StyleLayer {
@@ -85,12 +937,367 @@ impl Default for StyleLayer {
             index: 0,
             id: "id".to_string(),
             typ: "fill".to_string(),
+            filter: None,
+            layout: None,
             maxzoom: None,
             minzoom: None,
             metadata: None,
             paint: None,
             source: None,
             source_layer: Some("does not exist".to_string()),
+            unrecognized_properties: Vec::new(),
+        }
+    }
+}
+
+/// Converts a color component from sRGB to linear space, see
+/// <https://en.wikipedia.org/wiki/SRGB#From_sRGB_to_CIE_XYZ>.
+pub(crate) fn srgb_to_linear(component: f32) -> f32 {
+    if component <= 0.04045 {
+        component / 12.92
+    } else {
+        ((component + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        srgb_to_linear, FillPaint, HillshadePaint, IconAnchor, IconTextFit, LayerPaint, LinePaint,
+        RotationAlignment, StyleLayer, SymbolPaint, SymbolPlacement, Visibility,
+    };
+    use crate::style::expression::{FeatureProperties, Value};
+    use serde_json::json;
+
+    fn dash_pattern(dasharray: Option<Vec<f32>>) -> Option<[f32; 4]> {
+        LinePaint {
+            line_dasharray: dasharray,
+            ..LinePaint::default()
+        }
+        .dash_pattern()
+    }
+
+    #[test]
+    fn test_dash_pattern() {
+        assert_eq!(dash_pattern(None), None);
+        assert_eq!(dash_pattern(Some(vec![])), None);
+        assert_eq!(dash_pattern(Some(vec![0.0, 0.0])), None);
+        assert_eq!(dash_pattern(Some(vec![2.0])), Some([2.0, 2.0, 2.0, 2.0]));
+        assert_eq!(
+            dash_pattern(Some(vec![2.0, 1.0])),
+            Some([2.0, 1.0, 2.0, 1.0])
+        );
+        assert_eq!(
+            dash_pattern(Some(vec![3.0, 1.0, 2.0])),
+            Some([3.0, 1.0, 2.0, 3.0])
+        );
+        assert_eq!(
+            dash_pattern(Some(vec![4.0, 1.0, 2.0, 1.0, 5.0, 5.0])),
+            Some([4.0, 1.0, 2.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn test_opacity() {
+        let paint: FillPaint = serde_json::from_value(json!({
+            "fill-color": "red",
+            "fill-opacity": 0.5
+        }))
+        .unwrap();
+        let paint = LayerPaint::Fill(paint);
+
+        assert_eq!(paint.get_opacity(10.0, None), Some(0.5));
+        assert_eq!(
+            paint.get_color(10.0, None).map(|color| color.alpha),
+            Some(1.0)
+        );
+        assert_eq!(
+            LayerPaint::Fill(FillPaint::default()).get_opacity(10.0, None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_linear_color() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        // Mid gray of sRGB is much darker in linear space
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+
+        let paint: FillPaint = serde_json::from_value(json!({
+            "fill-color": "rgba(128, 128, 128, 0.5)"
+        }))
+        .unwrap();
+        let [r, g, b, a] = LayerPaint::Fill(paint)
+            .get_linear_color(10.0, None)
+            .unwrap();
+        assert!((r - 0.216).abs() < 1e-3);
+        assert_eq!(r, g);
+        assert_eq!(g, b);
+        // Alpha is linear already
+        assert_eq!(a, 0.5);
+    }
+
+    #[test]
+    fn test_text_halo() {
+        let paint: SymbolPaint = serde_json::from_value(json!({
+            "text-color": "black",
+            "text-halo-color": "rgba(255, 255, 255, 0.8)",
+            "text-halo-width": {"stops": [[10, 1], [14, 2]]}
+        }))
+        .unwrap();
+        let (color, width) = LayerPaint::Symbol(paint).get_text_halo(14.0, None);
+
+        assert_eq!(color.map(|color| color.alpha), Some(0.8));
+        assert_eq!(width, Some(2.0));
+        assert_eq!(
+            LayerPaint::Symbol(SymbolPaint::default()).get_text_halo(14.0, None),
+            (None, None)
+        );
+    }
+
+    struct Building;
+
+    impl FeatureProperties for Building {
+        fn get_property(&self, key: &str) -> Option<Value> {
+            match key {
+                "render_height" => Some(Value::Number(30.0)),
+                _ => None,
+            }
         }
     }
+
+    #[test]
+    fn test_extrusion() {
+        let layer: StyleLayer = serde_json::from_value(json!({
+            "id": "building-3d",
+            "type": "fill-extrusion",
+            "paint": {
+                "fill-extrusion-color": "gray",
+                "fill-extrusion-height": ["get", "render_height"],
+                "fill-extrusion-base": 5
+            }
+        }))
+        .unwrap();
+        let paint = layer.paint.unwrap();
+
+        assert_eq!(
+            paint.get_extrusion(15.0, Some(&Building)),
+            (Some(30.0), Some(5.0))
+        );
+        assert_eq!(paint.get_extrusion(15.0, None), (None, Some(5.0)));
+        assert!(paint.get_color(15.0, None).is_some());
+    }
+
+    struct Road;
+
+    impl FeatureProperties for Road {
+        fn get_property(&self, key: &str) -> Option<Value> {
+            match key {
+                "width" => Some(Value::Number(6.0)),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_data_driven_line_width() {
+        let layer: StyleLayer = serde_json::from_value(json!({
+            "id": "road",
+            "type": "line",
+            "paint": { "line-width": ["get", "width"] }
+        }))
+        .unwrap();
+        let paint = layer.paint.unwrap();
+
+        assert!(paint.is_feature_dependent());
+        assert_eq!(paint.get_line_width(15.0, Some(&Road)), Some(6.0));
+        assert_eq!(paint.get_line_width(15.0, None), None);
+    }
+
+    #[test]
+    fn test_zoom_dependent_line_width() {
+        let paint: LinePaint = serde_json::from_value(json!({
+            "line-color": "white",
+            "line-width": {"base": 1.4, "stops": [[5, 1], [15, 4]]}
+        }))
+        .unwrap();
+        let paint = LayerPaint::Line(paint);
+
+        // Widths are interpolated on the GPU, so only the line width depends on the zoom
+        assert!(paint.is_line_width_zoom_dependent());
+        assert!(!paint.is_zoom_dependent());
+    }
+
+    #[test]
+    fn test_visibility() {
+        let mut layer: StyleLayer = serde_json::from_value(json!({
+            "id": "water",
+            "type": "fill",
+            "layout": { "visibility": "none" }
+        }))
+        .unwrap();
+        assert!(!layer.is_visible());
+
+        layer.set_visibility(Visibility::Visible);
+        assert!(layer.is_visible());
+        assert!(StyleLayer::default().is_visible());
+    }
+
+    #[test]
+    fn test_icon_layout() {
+        let layer: StyleLayer = serde_json::from_value(json!({
+            "id": "poi",
+            "type": "symbol",
+            "layout": {
+                "icon-image": "{class}_11",
+                "icon-size": 1.5,
+                "icon-anchor": "bottom-left"
+            }
+        }))
+        .unwrap();
+        let layout = layer.layout.unwrap();
+
+        assert_eq!(layout.icon_image.as_deref(), Some("{class}_11"));
+        assert_eq!(layout.get_icon_size(14.0, None), Some(1.5));
+        assert_eq!(layout.icon_anchor, Some(IconAnchor::BottomLeft));
+        assert_eq!(IconAnchor::BottomLeft.alignment(), (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_icon_text_fit() {
+        let layer: StyleLayer = serde_json::from_value(json!({
+            "id": "highway-shield",
+            "type": "symbol",
+            "layout": {
+                "text-field": "{ref}",
+                "icon-image": "motorway_{ref_length}",
+                "icon-text-fit": "both",
+                "icon-text-fit-padding": [2, 4, 2, 4]
+            }
+        }))
+        .unwrap();
+        let layout = layer.layout.unwrap();
+
+        assert_eq!(layout.icon_text_fit, Some(IconTextFit::Both));
+        assert_eq!(layout.icon_text_fit_padding, Some([2.0, 4.0, 2.0, 4.0]));
+        assert!(layer.unrecognized_properties.is_empty());
+        assert_eq!(IconTextFit::default(), IconTextFit::None);
+    }
+
+    #[test]
+    fn test_symbol_placement() {
+        let layer: StyleLayer = serde_json::from_value(json!({
+            "id": "road-label",
+            "type": "symbol",
+            "layout": {
+                "text-field": "{name}",
+                "symbol-placement": "line",
+                "symbol-spacing": 300
+            }
+        }))
+        .unwrap();
+        let layout = layer.layout.unwrap();
+
+        assert_eq!(layout.symbol_placement, Some(SymbolPlacement::Line));
+        assert_eq!(layout.get_symbol_spacing(14.0, None), Some(300.0));
+        assert_eq!(SymbolPlacement::default(), SymbolPlacement::Point);
+    }
+
+    struct Place;
+
+    impl FeatureProperties for Place {
+        fn get_property(&self, key: &str) -> Option<Value> {
+            match key {
+                "rank" => Some(Value::Number(3.0)),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_data_driven_symbol_layout() {
+        let layer: StyleLayer = serde_json::from_value(json!({
+            "id": "place-label",
+            "type": "symbol",
+            "layout": {
+                "text-field": "{name}",
+                "text-size": {"stops": [[10, 12], [14, 20]]},
+                "icon-size": ["get", "rank"],
+                "symbol-sort-key": ["get", "rank"]
+            }
+        }))
+        .unwrap();
+        let layout = layer.layout.unwrap();
+
+        assert_eq!(layout.get_text_size(10.0, None), Some(12.0));
+        assert_eq!(layout.get_text_size(12.0, None), Some(16.0));
+        assert_eq!(layout.get_icon_size(14.0, Some(&Place)), Some(3.0));
+        assert_eq!(layout.get_symbol_sort_key(14.0, Some(&Place)), Some(3.0));
+        assert_eq!(layout.get_symbol_spacing(14.0, Some(&Place)), None);
+    }
+
+    #[test]
+    fn test_rotation_alignment() {
+        let layer: StyleLayer = serde_json::from_value(json!({
+            "id": "poi",
+            "type": "symbol",
+            "layout": {
+                "text-field": "{name}",
+                "icon-image": "{class}_11",
+                "icon-rotation-alignment": "map"
+            }
+        }))
+        .unwrap();
+        let layout = layer.layout.unwrap();
+
+        assert_eq!(layout.icon_rotation_alignment, Some(RotationAlignment::Map));
+        assert_eq!(layout.text_rotation_alignment, None);
+        assert_eq!(
+            RotationAlignment::default().resolve(SymbolPlacement::Point),
+            RotationAlignment::Viewport
+        );
+        assert_eq!(
+            RotationAlignment::default().resolve(SymbolPlacement::Line),
+            RotationAlignment::Map
+        );
+        assert_eq!(
+            RotationAlignment::Viewport.resolve(SymbolPlacement::Line),
+            RotationAlignment::Viewport
+        );
+    }
+
+    #[test]
+    fn test_hillshade() {
+        let layer: StyleLayer = serde_json::from_value(json!({
+            "id": "hills",
+            "type": "hillshade",
+            "source": "terrain",
+            "paint": {
+                "hillshade-exaggeration": 2,
+                "hillshade-illumination-direction": 90,
+                "hillshade-illumination-anchor": "map",
+                "hillshade-shadow-color": "#ff0000"
+            }
+        }))
+        .unwrap();
+        let paint = match layer.paint.unwrap() {
+            LayerPaint::Hillshade(paint) => paint,
+            paint => panic!("unexpected paint {:?}", paint),
+        };
+
+        assert_eq!(paint.exaggeration(), 1.0);
+        // Light anchored to the map does not rotate with the bearing
+        assert_eq!(paint.illumination_direction(1.0), 90_f32.to_radians());
+        let [shadow, highlight, _] = paint.colors(10.0);
+        assert_eq!(shadow, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(highlight, [1.0, 1.0, 1.0, 1.0]);
+
+        let paint = HillshadePaint::default();
+        assert_eq!(paint.exaggeration(), 0.5);
+        assert_eq!(
+            paint.illumination_direction(1.0),
+            335_f32.to_radians() + 1.0
+        );
+    }
 }
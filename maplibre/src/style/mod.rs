@@ -1,5 +1,8 @@
 //! Vector tile format styling.
 
+pub mod animation;
+pub mod expression;
+pub mod filter;
 pub mod layer;
 pub mod source;
 mod style;
@@ -1,5 +1,7 @@
 //! Vector tile data utilities.
 
+use crate::coords::WorldTileCoords;
+use crate::io::tile_json::TileJSON;
 use serde::{Deserialize, Serialize};
 
 /// String url to a tile.
@@ -9,7 +11,7 @@ pub type TileUrl = String;
 pub type TileJSONUrl = String;
 
 /// Tiles can be positioned using either the xyz coordinates or the TMS (Tile Map Service) protocol.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum TileAddressingScheme {
     #[serde(rename = "xyz")]
     XYZ,
@@ -23,6 +25,38 @@ impl Default for TileAddressingScheme {
     }
 }
 
+/// How the elevation of a pixel of a `raster-dem` tile is encoded in its RGB channels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemEncoding {
+    /// Terrain-RGB: `-10000 + (R * 256 * 256 + G * 256 + B) * 0.1` meters.
+    #[serde(rename = "mapbox")]
+    Mapbox,
+    /// Terrarium: `R * 256 + G + B / 256 - 32768` meters.
+    #[serde(rename = "terrarium")]
+    Terrarium,
+}
+
+impl Default for DemEncoding {
+    fn default() -> Self {
+        DemEncoding::Mapbox
+    }
+}
+
+impl DemEncoding {
+    /// Decodes the elevations in meters of RGBA pixels.
+    pub fn decode(self, rgba: &[u8]) -> Vec<f32> {
+        rgba.chunks_exact(4)
+            .map(|pixel| {
+                let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+                match self {
+                    DemEncoding::Mapbox => -10000.0 + (r * 256.0 * 256.0 + g * 256.0 + b) * 0.1,
+                    DemEncoding::Terrarium => r * 256.0 + g + b / 256.0 - 32768.0,
+                }
+            })
+            .collect()
+    }
+}
+
 /// Source properties for tiles or rasters.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VectorSource {
@@ -44,11 +78,91 @@ pub struct VectorSource {
     pub scheme: Option<TileAddressingScheme>,
     /// Array of URLs which can contain place holders like {x}, {y}, {z}.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tiles: Option<TileUrl>,
-    // url: Option<TileJSONUrl>,
+    pub tiles: Option<Vec<TileUrl>>,
+    /// Subdomains which replace the place holder `{s}` of `tiles` in turns.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subdomains: Option<Vec<String>>,
+    /// URL to a TileJSON document which describes the source.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<TileJSONUrl>,
+    /// The TileJSON document behind `url` once it has been fetched.
+    #[serde(skip)]
+    pub tile_json: Option<TileJSON>,
+    /// Encoding of the elevations of `raster-dem` sources. Defaults to
+    /// [`DemEncoding::Mapbox`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<DemEncoding>,
     // TODO volatile
 }
 
+impl VectorSource {
+    /// Returns where the tiles of this source are available. Properties which are set in the style
+    /// take precedence over the fetched TileJSON. Returns `None` if the TileJSON is still loading
+    /// or the source has no tile URLs.
+    pub fn resolved_tile_json(&self) -> Option<TileJSON> {
+        if self.is_tile_json_missing() {
+            return None;
+        }
+
+        match &self.tile_json {
+            Some(tile_json) => Some(tile_json.clone().with_overrides(self)),
+            None => TileJSON::from_source(self),
+        }
+    }
+
+    /// The maximum zoom level at which tiles are available, if it is known.
+    pub fn resolved_minzoom(&self) -> Option<u8> {
+        self.minzoom
+            .or_else(|| self.tile_json.as_ref().map(|tile_json| tile_json.minzoom))
+    }
+
+    pub fn resolved_maxzoom(&self) -> Option<u8> {
+        self.maxzoom
+            .or_else(|| self.tile_json.as_ref().map(|tile_json| tile_json.maxzoom))
+    }
+
+    /// Returns the coords of the tile which provides the data for the tile at `coords`. Above the
+    /// maximum zoom level this is the ancestor at the maximum zoom level, which is scaled up.
+    pub fn overzoomed_coords(&self, coords: &WorldTileCoords) -> WorldTileCoords {
+        match self.resolved_maxzoom() {
+            Some(maxzoom) if coords.z > maxzoom => {
+                let shift = coords.z - maxzoom;
+                WorldTileCoords {
+                    x: coords.x >> shift,
+                    y: coords.y >> shift,
+                    z: maxzoom,
+                }
+            }
+            _ => *coords,
+        }
+    }
+
+    /// Returns true if the TileJSON of the source needs to be fetched before tiles can be
+    /// requested.
+    pub fn is_tile_json_missing(&self) -> bool {
+        self.url.is_some() && self.tile_json.is_none()
+    }
+}
+
+/// Source properties for GeoJSON data which is tiled on the client.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GeoJsonSourceSpec {
+    /// String which contains attribution information for the data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+    /// Inline GeoJSON object, usually a `FeatureCollection`.
+    pub data: serde_json::Value,
+    /// Size of the buffer around each tile in pixels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<f64>,
+    /// Douglas-Peucker simplification tolerance in pixels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
+    /// Incremented whenever `data` is replaced at runtime, see [`crate::Style::set_geojson_data`].
+    #[serde(skip)]
+    pub revision: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum Source {
@@ -56,4 +170,39 @@ pub enum Source {
     Vector(VectorSource),
     #[serde(rename = "raster")]
     Raster(VectorSource), // FIXME: Does it make sense that a raster have a VectorSource?
+    /// Raster tiles whose pixels encode elevations, which `hillshade` layers shade.
+    #[serde(rename = "raster-dem")]
+    RasterDem(VectorSource),
+    #[serde(rename = "geojson")]
+    GeoJson(GeoJsonSourceSpec),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DemEncoding, Source};
+    use serde_json::json;
+
+    #[test]
+    fn test_decode_elevation() {
+        // The base of both encodings and one meter above it
+        let rgba = [1, 134, 160, 255, 1, 134, 170, 255];
+        assert_eq!(DemEncoding::Mapbox.decode(&rgba), vec![0.0, 1.0]);
+
+        let rgba = [128, 0, 0, 255, 128, 1, 128, 255];
+        assert_eq!(DemEncoding::Terrarium.decode(&rgba), vec![0.0, 1.5]);
+    }
+
+    #[test]
+    fn test_raster_dem_source() {
+        let source: Source = serde_json::from_value(json!({
+            "type": "raster-dem",
+            "tiles": ["https://example.com/{z}/{x}/{y}.png"],
+            "encoding": "terrarium"
+        }))
+        .unwrap();
+        match source {
+            Source::RasterDem(source) => assert_eq!(source.encoding, Some(DemEncoding::Terrarium)),
+            _ => panic!("expected a raster-dem source"),
+        }
+    }
 }
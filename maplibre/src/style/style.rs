@@ -1,20 +1,546 @@
 //! Default vector tile styles configuration.
 
-use crate::style::layer::{LayerPaint, LinePaint, StyleLayer};
-use crate::style::source::Source;
+use crate::context::CameraTarget;
+use crate::coords::{LatLon, ViewRegion, WorldTileCoords, Zoom};
+use crate::error::Error;
+use crate::io::source_client::HTTPClient;
+use crate::io::sprite::SpriteSheet;
+use crate::io::LayerTessellateMessage;
+use crate::style::animation::ColorAnimation;
+use crate::style::layer::{LayerPaint, LinePaint, StyleLayer, Visibility};
+use crate::style::source::{Source, VectorSource};
+use cgmath::Deg;
 use csscolorparser::Color;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
+
+/// Layer types which are rendered.
+const SUPPORTED_LAYER_TYPES: [&str; 7] = [
+    "background",
+    "fill",
+    "fill-extrusion",
+    "hillshade",
+    "line",
+    "raster",
+    "symbol",
+];
 
 /// Stores the style for a multi-layered map.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Style {
     pub version: u16,
+    #[serde(default)]
     pub name: String,
-    pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, Value>,
+    /// URL of the sprite sheet of icons and patterns, see [`Style::load_sprite`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<String>,
+    /// The loaded sprite sheet. Symbol layers only draw icons once it is loaded.
+    #[serde(skip)]
+    pub sprite_sheet: Option<Arc<SpriteSheet>>,
+    /// URL template of the glyphs of labels, which contains the place holders `{fontstack}` and
+    /// `{range}`. Labels are rendered with [`crate::render::settings::RendererSettings::font`]
+    /// for now.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glyphs: Option<String>,
+    /// Default center of the map as `[longitude, latitude]`, see [`Style::camera`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub center: Option<[f64; 2]>,
+    /// Default zoom level of the map.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoom: Option<f64>,
+    /// Default bearing of the map in degrees. At a bearing of 90 degrees east is up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearing: Option<f64>,
+    /// Default pitch of the map in degrees.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<f64>,
+    #[serde(default)]
     pub sources: HashMap<String, Source>,
+    #[serde(default)]
     pub layers: Vec<StyleLayer>,
+    /// Incremented for a layer whenever one of its paint properties is changed at runtime, see
+    /// [`Style::set_paint_property`].
+    #[serde(skip)]
+    pub paint_revisions: HashMap<String, u64>,
+    /// Animations of the colors of layers by the id of the layer, see
+    /// [`Style::set_color_animation`].
+    #[serde(skip)]
+    pub animations: HashMap<String, ColorAnimation>,
+}
+
+/// A part of a style document which can not be used. The rest of the style is used anyway.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StyleIssue {
+    /// A layer which is invalid or has a type which is not rendered is skipped.
+    Layer { id: Option<String>, message: String },
+    /// A source which is invalid or has a type which is not supported is skipped. Layers which
+    /// refer to it draw nothing.
+    Source { id: String, message: String },
+}
+
+impl fmt::Display for StyleIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StyleIssue::Layer { id, message } => write!(
+                f,
+                "layer {} skipped: {}",
+                id.as_deref().unwrap_or("without id"),
+                message
+            ),
+            StyleIssue::Source { id, message } => write!(f, "source {} skipped: {}", id, message),
+        }
+    }
+}
+
+/// A mistake in a style which keeps a layer from being drawn as intended, see [`Style::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StyleError {
+    /// The layer refers to a source which the style does not define. Its features are taken from
+    /// the default tile source instead.
+    UnknownSource { layer: String, source: String },
+    /// The layer refers to a source of a type which it can not draw, e.g. a fill layer to a raster
+    /// source.
+    IncompatibleSource { layer: String, source: String },
+    /// The layer lacks a property which it needs to draw anything, e.g. the `source` of a raster
+    /// layer.
+    MissingProperty { layer: String, property: String },
+    /// A paint or layout property of the layer is not supported and therefore ignored.
+    UnrecognizedProperty { layer: String, property: String },
+}
+
+impl StyleError {
+    /// Returns the id of the layer which the error is about.
+    pub fn layer(&self) -> &str {
+        match self {
+            StyleError::UnknownSource { layer, .. }
+            | StyleError::IncompatibleSource { layer, .. }
+            | StyleError::MissingProperty { layer, .. }
+            | StyleError::UnrecognizedProperty { layer, .. } => layer,
+        }
+    }
+}
+
+impl fmt::Display for StyleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StyleError::UnknownSource { layer, source } => {
+                write!(f, "layer {} refers to the unknown source {}", layer, source)
+            }
+            StyleError::IncompatibleSource { layer, source } => {
+                write!(f, "layer {} can not draw the source {}", layer, source)
+            }
+            StyleError::MissingProperty { layer, property } => {
+                write!(f, "layer {} lacks the property {}", layer, property)
+            }
+            StyleError::UnrecognizedProperty { layer, property } => {
+                write!(
+                    f,
+                    "layer {} has the unsupported property {}",
+                    layer, property
+                )
+            }
+        }
+    }
+}
+
+impl Style {
+    /// Parses a [MapLibre style](https://maplibre.org/maplibre-gl-js-docs/style-spec/) document.
+    /// Layers and sources which are not supported are skipped and returned as [`StyleIssue`]s
+    /// instead of failing the whole style. Relative URLs of the sprite, the glyphs and the sources
+    /// are resolved against `base_url`, which is usually the URL of the document.
+    pub fn from_json(
+        data: &[u8],
+        base_url: Option<&str>,
+    ) -> Result<(Self, Vec<StyleIssue>), Error> {
+        let mut document: Value =
+            serde_json::from_slice(data).map_err(|e| Error::Style(e.to_string()))?;
+        let object = document
+            .as_object_mut()
+            .ok_or_else(|| Error::Style("the style is not an object".to_string()))?;
+        let layers = match object.remove("layers") {
+            Some(Value::Array(layers)) => layers,
+            Some(_) => return Err(Error::Style("the layers are not an array".to_string())),
+            None => Vec::new(),
+        };
+        let sources = match object.remove("sources") {
+            Some(Value::Object(sources)) => sources,
+            Some(_) => return Err(Error::Style("the sources are not an object".to_string())),
+            None => Default::default(),
+        };
+
+        let mut style: Style =
+            serde_json::from_value(document).map_err(|e| Error::Style(e.to_string()))?;
+        let mut issues = Vec::new();
+
+        for (id, source) in sources {
+            match serde_json::from_value::<Source>(source) {
+                Ok(source) => {
+                    style.sources.insert(id, source);
+                }
+                Err(e) => issues.push(StyleIssue::Source {
+                    id,
+                    message: e.to_string(),
+                }),
+            }
+        }
+
+        for layer in layers {
+            let id = layer.get("id").and_then(Value::as_str).map(str::to_string);
+            match serde_json::from_value::<StyleLayer>(layer) {
+                Ok(layer) if !SUPPORTED_LAYER_TYPES.contains(&layer.typ.as_str()) => {
+                    issues.push(StyleIssue::Layer {
+                        id,
+                        message: format!("unsupported layer type {}", layer.typ),
+                    })
+                }
+                Ok(mut layer) => {
+                    // Layers are drawn in the order of the document
+                    layer.index = style.layers.len() as u32;
+                    style.layers.push(layer);
+                }
+                Err(e) => issues.push(StyleIssue::Layer {
+                    id,
+                    message: e.to_string(),
+                }),
+            }
+        }
+
+        if let Some(base_url) = base_url {
+            style.resolve_urls(base_url);
+        }
+
+        Ok((style, issues))
+    }
+
+    /// Fetches and parses the style document at `url`, see [`Style::from_json`]. The data of
+    /// GeoJSON sources which refer to it by URL is fetched as well.
+    pub async fn from_url<HC>(url: &str, http_client: &HC) -> Result<(Self, Vec<StyleIssue>), Error>
+    where
+        HC: HTTPClient,
+    {
+        let data = http_client.fetch(url).await?;
+        let (mut style, mut issues) = Self::from_json(&data, Some(url))?;
+
+        let mut failed_sources = Vec::new();
+        for (id, source) in style.sources.iter_mut() {
+            let spec = match source {
+                Source::GeoJson(spec) => spec,
+                _ => continue,
+            };
+            let data_url = match &spec.data {
+                Value::String(data_url) => data_url.clone(),
+                _ => continue,
+            };
+
+            let data = http_client.fetch(&data_url).await.and_then(|data| {
+                serde_json::from_slice(&data).map_err(|e| Error::GeoJson(e.to_string()))
+            });
+            match data {
+                Ok(data) => spec.data = data,
+                Err(e) => {
+                    failed_sources.push(id.clone());
+                    issues.push(StyleIssue::Source {
+                        id: id.clone(),
+                        message: format!("{:?}", e),
+                    });
+                }
+            }
+        }
+        for id in failed_sources {
+            style.sources.remove(&id);
+        }
+
+        Ok((style, issues))
+    }
+
+    /// Fetches the sprite sheet of [`Style::sprite`] for a display with `device_pixel_ratio`
+    /// physical pixels per logical pixel. Does nothing if the style has no sprite.
+    pub async fn load_sprite<HC>(
+        &mut self,
+        http_client: &HC,
+        device_pixel_ratio: f64,
+    ) -> Result<(), Error>
+    where
+        HC: HTTPClient,
+    {
+        if let Some(url) = &self.sprite {
+            let sprite_sheet = SpriteSheet::fetch(url, http_client, device_pixel_ratio).await?;
+            self.sprite_sheet = Some(Arc::new(sprite_sheet));
+        }
+        Ok(())
+    }
+
+    /// Resolves relative URLs of the sprite, the glyphs and the sources against `base_url`.
+    fn resolve_urls(&mut self, base_url: &str) {
+        for url in self.sprite.iter_mut().chain(self.glyphs.iter_mut()) {
+            *url = resolve_url(base_url, url);
+        }
+
+        for source in self.sources.values_mut() {
+            match source {
+                Source::Vector(source) | Source::Raster(source) | Source::RasterDem(source) => {
+                    for url in source
+                        .url
+                        .iter_mut()
+                        .chain(source.tiles.iter_mut().flatten())
+                    {
+                        *url = resolve_url(base_url, url);
+                    }
+                }
+                Source::GeoJson(spec) => {
+                    if let Value::String(url) = &mut spec.data {
+                        *url = resolve_url(base_url, url);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks that the layers refer to sources which exist and which they can draw, that they
+    /// have the properties they need and that their paint and layout properties are supported.
+    /// Returns the mistakes in the order of the layers. Layers with mistakes are drawn as far as
+    /// possible anyway.
+    pub fn validate(&self) -> Vec<StyleError> {
+        let mut errors = Vec::new();
+
+        for layer in &self.layers {
+            let id = &layer.id;
+            let draws_raster = layer.typ == "raster" || layer.typ == "hillshade";
+
+            match &layer.source {
+                // Layers without source draw the default tile source, except for raster layers
+                None => {
+                    if draws_raster {
+                        errors.push(StyleError::MissingProperty {
+                            layer: id.clone(),
+                            property: "source".to_string(),
+                        });
+                    }
+                }
+                Some(source) => match self.sources.get(source) {
+                    None => errors.push(StyleError::UnknownSource {
+                        layer: id.clone(),
+                        source: source.clone(),
+                    }),
+                    Some(spec) => {
+                        let compatible = match layer.typ.as_str() {
+                            "background" => true,
+                            "raster" => matches!(spec, Source::Raster(_)),
+                            "hillshade" => matches!(spec, Source::RasterDem(_)),
+                            _ => matches!(spec, Source::Vector(_) | Source::GeoJson(_)),
+                        };
+                        if !compatible {
+                            errors.push(StyleError::IncompatibleSource {
+                                layer: id.clone(),
+                                source: source.clone(),
+                            });
+                        }
+                    }
+                },
+            }
+
+            // The features of vector tiles, also of those sliced from GeoJSON, are grouped in
+            // source layers
+            let draws_vector_tiles = match layer.source.as_ref().and_then(|id| self.sources.get(id))
+            {
+                Some(spec) => matches!(spec, Source::Vector(_) | Source::GeoJson(_)),
+                None => true,
+            };
+            if layer.typ != "background"
+                && !draws_raster
+                && draws_vector_tiles
+                && layer.source_layer.is_none()
+            {
+                errors.push(StyleError::MissingProperty {
+                    layer: id.clone(),
+                    property: "source-layer".to_string(),
+                });
+            }
+
+            errors.extend(layer.unrecognized_properties.iter().map(|property| {
+                StyleError::UnrecognizedProperty {
+                    layer: id.clone(),
+                    property: property.clone(),
+                }
+            }));
+        }
+
+        errors
+    }
+
+    /// Shows or hides the layer with the id `layer_id`. The change takes effect with the next
+    /// frame. Returns false if there is no such layer.
+    pub fn set_layer_visibility(&mut self, layer_id: &str, visible: bool) -> bool {
+        if let Some(layer) = self.layers.iter_mut().find(|layer| layer.id == layer_id) {
+            layer.set_visibility(if visible {
+                Visibility::Visible
+            } else {
+                Visibility::None
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Changes the paint property `property` of the layer with the id `layer_id` to `value`, or
+    /// resets it to its default if `value` is null. The uploaded layers are styled again with the
+    /// next frame without tessellating them again, therefore only colors, opacities and line widths
+    /// of fill, line and background layers can be changed, see
+    /// [`StyleLayer::set_paint_property`]. Returns false if there is no such layer or the property
+    /// can not be changed.
+    pub fn set_paint_property(&mut self, layer_id: &str, property: &str, value: Value) -> bool {
+        if let Some(layer) = self.layers.iter_mut().find(|layer| layer.id == layer_id) {
+            if layer.set_paint_property(property, value) {
+                *self
+                    .paint_revisions
+                    .entry(layer_id.to_string())
+                    .or_default() += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Animates the colors of the layer with the id `layer_id`, or stops its animation if
+    /// `animation` is `None`. Layers are not animated unless they opt in, such that static layers
+    /// are not uploaded every frame. Returns false if there is no such layer.
+    pub fn set_color_animation(
+        &mut self,
+        layer_id: &str,
+        animation: Option<ColorAnimation>,
+    ) -> bool {
+        if !self.layers.iter().any(|layer| layer.id == layer_id) {
+            return false;
+        }
+        match animation {
+            Some(animation) => {
+                self.animations.insert(layer_id.to_string(), animation);
+            }
+            None => {
+                // The colors of the style are uploaded again, like after a change of the paint
+                if self.animations.remove(layer_id).is_some() {
+                    *self
+                        .paint_revisions
+                        .entry(layer_id.to_string())
+                        .or_default() += 1;
+                }
+            }
+        }
+        true
+    }
+
+    /// Replaces the data of the GeoJSON source with the id `source_id`. Only the tiles around the
+    /// features which changed are sliced and tessellated again, starting with the next frame.
+    /// Returns false if there is no such GeoJSON source.
+    pub fn set_geojson_data(&mut self, source_id: &str, data: Value) -> bool {
+        if let Some(Source::GeoJson(spec)) = self.sources.get_mut(source_id) {
+            spec.data = data;
+            spec.revision += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the ids of the layers which are hidden.
+    pub fn hidden_layers(&self) -> HashSet<&str> {
+        self.layers
+            .iter()
+            .filter(|layer| !layer.is_visible())
+            .map(|layer| layer.id.as_str())
+            .collect()
+    }
+
+    /// Returns the default camera of the style, at which a map opens which loads the style.
+    /// Properties which the style does not define are `None`.
+    pub fn camera(&self) -> CameraTarget {
+        CameraTarget {
+            center: self
+                .center
+                .map(|[longitude, latitude]| LatLon::new(latitude, longitude)),
+            zoom: self.zoom.map(Zoom::new),
+            pitch: self.pitch.map(|pitch| Deg(pitch).into()),
+            bearing: self.bearing.map(|bearing| Deg(bearing).into()),
+        }
+    }
+
+    /// Returns the ids of the layers which are hidden or not drawn at `zoom`, see
+    /// [`StyleLayer::is_visible_at`].
+    pub fn hidden_layers_at(&self, zoom: f64) -> HashSet<&str> {
+        self.layers
+            .iter()
+            .filter(|layer| !layer.is_visible_at(zoom))
+            .map(|layer| layer.id.as_str())
+            .collect()
+    }
+
+    /// Returns the vector source which the first layer refers to. Its zoom range decides at which
+    /// zoom levels the tiles of all sources are overzoomed.
+    pub fn vector_source(&self) -> Option<&VectorSource> {
+        self.vector_source_id()
+            .and_then(|id| match self.sources.get(id) {
+                Some(Source::Vector(source)) => Some(source),
+                _ => None,
+            })
+    }
+
+    /// Returns the id of the vector source, see [`Style::vector_source`].
+    pub fn vector_source_id(&self) -> Option<&str> {
+        self.layers
+            .iter()
+            .filter_map(|layer| layer.source.as_ref())
+            .find(|id| matches!(self.sources.get(*id), Some(Source::Vector(_))))
+            .map(|id| id.as_str())
+    }
+
+    /// Returns the id of the source whose tiles hold the features of `layer`. `None` if the style
+    /// does not define the source of the layer, in which case the default tile source is used.
+    pub fn tile_source_id<'a>(&self, layer: &'a StyleLayer) -> Option<&'a str> {
+        layer
+            .source
+            .as_deref()
+            .filter(|id| self.sources.contains_key(*id))
+    }
+
+    /// Whether `message` holds the source layer which `layer` draws, i.e. both the name of the
+    /// source layer and the source match.
+    pub fn is_layer_data(&self, layer: &StyleLayer, message: &LayerTessellateMessage) -> bool {
+        layer.source_layer.as_deref() == Some(message.layer_name())
+            && self.tile_source_id(layer) == message.source()
+    }
+
+    /// Returns the range of zoom levels in which the vector source provides tiles. Bounds which
+    /// are unknown, e.g. because the TileJSON of the source is not fetched yet, are `None`.
+    pub fn zoom_range(&self) -> (Option<u8>, Option<u8>) {
+        self.vector_source().map_or((None, None), |source| {
+            (source.resolved_minzoom(), source.resolved_maxzoom())
+        })
+    }
+
+    /// Returns the coords of the tile which holds the vector data of the tile at `coords`, see
+    /// [`VectorSource::overzoomed_coords`].
+    pub fn overzoomed_coords(&self, coords: &WorldTileCoords) -> WorldTileCoords {
+        self.vector_source()
+            .map_or(*coords, |source| source.overzoomed_coords(coords))
+    }
+
+    /// Returns the tiles which hold the vector data of the tiles in view. Above the maximum zoom
+    /// level of the vector source, multiple tiles in view share the same ancestor.
+    pub fn overzoomed_tiles(&self, view_region: &ViewRegion) -> Vec<WorldTileCoords> {
+        let mut seen = HashSet::new();
+        view_region
+            .iter()
+            .map(|coords| self.overzoomed_coords(&coords))
+            .filter(|coords| seen.insert(*coords))
+            .collect()
+    }
 }
 
 impl Default for Style {
@@ -23,117 +549,200 @@ impl Default for Style {
             version: 8,
             name: "Default Style".to_string(),
             metadata: Default::default(),
+            sprite: None,
+            sprite_sheet: None,
+            glyphs: None,
+            center: None,
+            zoom: None,
+            bearing: None,
+            pitch: None,
             sources: Default::default(),
             layers: vec![
                 StyleLayer {
                     index: 0,
                     id: "park".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("lightgreen").unwrap()),
+                        line_color: Some(Color::from_str("lightgreen").unwrap().into()),
+                        ..LinePaint::default()
                     })),
                     source: None,
                     source_layer: Some("park".to_string()),
+                    unrecognized_properties: Vec::new(),
                 },
                 StyleLayer {
                     index: 1,
                     id: "landuse".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("lightgreen").unwrap()),
+                        line_color: Some(Color::from_str("lightgreen").unwrap().into()),
+                        ..LinePaint::default()
                     })),
                     source: None,
                     source_layer: Some("landuse".to_string()),
+                    unrecognized_properties: Vec::new(),
                 },
                 StyleLayer {
                     index: 2,
                     id: "landcover".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("lightgreen").unwrap()),
+                        line_color: Some(Color::from_str("lightgreen").unwrap().into()),
+                        ..LinePaint::default()
                     })),
                     source: None,
                     source_layer: Some("landcover".to_string()),
+                    unrecognized_properties: Vec::new(),
                 },
                 StyleLayer {
                     index: 3,
                     id: "1transportation".to_string(),
                     typ: "line".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("violet").unwrap()),
+                        line_color: Some(Color::from_str("violet").unwrap().into()),
+                        ..LinePaint::default()
                     })),
                     source: None,
                     source_layer: Some("transportation".to_string()),
+                    unrecognized_properties: Vec::new(),
                 },
                 StyleLayer {
                     index: 4,
                     id: "building".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("grey").unwrap()),
+                        line_color: Some(Color::from_str("grey").unwrap().into()),
+                        ..LinePaint::default()
                     })),
                     source: None,
                     source_layer: Some("building".to_string()),
+                    unrecognized_properties: Vec::new(),
                 },
                 StyleLayer {
                     index: 4,
                     id: "water".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("blue").unwrap()),
+                        line_color: Some(Color::from_str("blue").unwrap().into()),
+                        ..LinePaint::default()
                     })),
                     source: None,
                     source_layer: Some("water".to_string()),
+                    unrecognized_properties: Vec::new(),
                 },
                 StyleLayer {
                     index: 6,
                     id: "waterway".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("blue").unwrap()),
+                        line_color: Some(Color::from_str("blue").unwrap().into()),
+                        ..LinePaint::default()
                     })),
                     source: None,
                     source_layer: Some("waterway".to_string()),
+                    unrecognized_properties: Vec::new(),
                 },
                 StyleLayer {
                     index: 7,
                     id: "boundary".to_string(),
                     typ: "line".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("black").unwrap()),
+                        line_color: Some(Color::from_str("black").unwrap().into()),
+                        ..LinePaint::default()
                     })),
                     source: None,
                     source_layer: Some("boundary".to_string()),
+                    unrecognized_properties: Vec::new(),
                 },
             ],
+            paint_revisions: HashMap::new(),
+            animations: HashMap::new(),
         }
     }
 }
 
+/// Resolves `url` relative to `base_url` like a browser does. URLs with a scheme, e.g. `https://`
+/// or `mapbox://`, are returned unchanged.
+fn resolve_url(base_url: &str, url: &str) -> String {
+    if url.contains("://") {
+        return url.to_string();
+    }
+
+    let (scheme, rest) = match base_url.split_once("://") {
+        Some(split) => split,
+        None => return url.to_string(),
+    };
+    if let Some(url) = url.strip_prefix("//") {
+        return format!("{}://{}", scheme, url);
+    }
+
+    // Query and fragment of the base URL are not part of its path
+    let rest = rest
+        .split(|c| c == '?' || c == '#')
+        .next()
+        .unwrap_or_default();
+    let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    if url.starts_with('/') {
+        return format!("{}://{}{}", scheme, host, url);
+    }
+
+    // The last segment of the base path is a file name
+    let mut segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if !path.ends_with('/') {
+        segments.pop();
+    }
+    for segment in url.split('/') {
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("{}://{}/{}", scheme, host, segments.join("/"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -173,7 +782,8 @@ mod tests {
               "source": "openmaptiles",
               "source-layer": "boundary",
               "paint": {
-                "line-color": "#3D3D3D"
+                "line-color": "#3D3D3D",
+                "line-dasharray": [3, 1, 1, 1]
               }
             },
             {
@@ -186,6 +796,21 @@ mod tests {
               "paint": {
                 "line-color": "#3D3D3D"
               }
+            },
+            {
+              "id": "place",
+              "type": "symbol",
+              "source": "openmaptiles",
+              "source-layer": "place",
+              "layout": {
+                "text-field": "{name}",
+                "text-size": 14,
+                "text-font": ["Open Sans Regular"],
+                "symbol-sort-key": 1
+              },
+              "paint": {
+                "text-color": "#333333"
+              }
             }
           ]
         }
@@ -193,4 +818,284 @@ mod tests {
 
         let _style: Style = serde_json::from_str(style_json_str).unwrap();
     }
+
+    #[test]
+    fn test_overzoomed_coords() {
+        let style: Style = serde_json::from_value(serde_json::json!({
+            "version": 8,
+            "name": "Test Style",
+            "metadata": {},
+            "sources": {
+                "openmaptiles": {
+                    "type": "vector",
+                    "tiles": ["https://example.com/{z}/{x}/{y}.pbf"],
+                    "maxzoom": 14
+                }
+            },
+            "layers": [{
+                "id": "building",
+                "type": "fill",
+                "source": "openmaptiles",
+                "source-layer": "building"
+            }]
+        }))
+        .unwrap();
+
+        assert_eq!(style.zoom_range(), (None, Some(14)));
+
+        let coords = WorldTileCoords {
+            x: 8803,
+            y: 5374,
+            z: 14,
+        };
+        assert_eq!(style.overzoomed_coords(&coords), coords);
+        assert_eq!(
+            style.overzoomed_coords(&WorldTileCoords {
+                x: 35214,
+                y: 21499,
+                z: 16
+            }),
+            coords
+        );
+
+        // Without a vector source tiles are never overzoomed
+        let child = WorldTileCoords { x: 3, y: 5, z: 20 };
+        assert_eq!(Style::default().overzoomed_coords(&child), child);
+    }
+
+    #[test]
+    fn test_hidden_layers_at() {
+        let mut style = Style::default();
+        style.layers[0].minzoom = Some(10);
+        style.layers[0].maxzoom = Some(14);
+        let id = style.layers[0].id.clone();
+
+        assert!(style.hidden_layers_at(9.9).contains(id.as_str()));
+        assert!(!style.hidden_layers_at(10.0).contains(id.as_str()));
+        assert!(!style.hidden_layers_at(13.9).contains(id.as_str()));
+        assert!(style.hidden_layers_at(14.0).contains(id.as_str()));
+
+        style.set_layer_visibility(&id, false);
+        assert!(style.hidden_layers_at(12.0).contains(id.as_str()));
+    }
+
+    #[test]
+    fn test_set_geojson_data() {
+        let mut style: Style = serde_json::from_value(serde_json::json!({
+            "version": 8,
+            "name": "Test Style",
+            "metadata": {},
+            "sources": {
+                "vehicles": {
+                    "type": "geojson",
+                    "data": { "type": "FeatureCollection", "features": [] }
+                }
+            },
+            "layers": []
+        }))
+        .unwrap();
+
+        let data = serde_json::json!({
+            "type": "Feature",
+            "properties": {},
+            "geometry": { "type": "Point", "coordinates": [13.4, 52.5] }
+        });
+        assert!(style.set_geojson_data("vehicles", data.clone()));
+        match &style.sources["vehicles"] {
+            Source::GeoJson(spec) => {
+                assert_eq!(spec.data, data);
+                assert_eq!(spec.revision, 1);
+            }
+            _ => panic!("expected a GeoJSON source"),
+        }
+        assert!(!style.set_geojson_data("does not exist", data));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Style::default().validate().is_empty());
+
+        let (style, _) = Style::from_json(
+            br#"{
+                "version": 8,
+                "sources": {
+                    "openmaptiles": {"type": "vector", "url": "tiles.json"},
+                    "satellite": {"type": "raster", "url": "satellite.json"}
+                },
+                "layers": [
+                    {"id": "background", "type": "background"},
+                    {"id": "imagery", "type": "raster", "source": "satellite"},
+                    {"id": "water", "type": "fill", "source": "openmaptiles", "source-layer": "water",
+                     "paint": {"fill-color": "blue", "fill-antialias": false}},
+                    {"id": "roads", "type": "line", "source": "osm", "source-layer": "roads"},
+                    {"id": "parks", "type": "fill", "source": "satellite", "source-layer": "parks"},
+                    {"id": "labels", "type": "symbol", "source": "openmaptiles",
+                     "layout": {"text-field": "{name}", "text-fnt": ["Noto Sans"]}},
+                    {"id": "relief", "type": "hillshade"}
+                ]
+            }"#,
+            None,
+        )
+        .unwrap();
+
+        let errors = style.validate();
+        assert_eq!(
+            errors,
+            vec![
+                StyleError::UnrecognizedProperty {
+                    layer: "water".to_string(),
+                    property: "paint.fill-antialias".to_string()
+                },
+                StyleError::UnknownSource {
+                    layer: "roads".to_string(),
+                    source: "osm".to_string()
+                },
+                StyleError::IncompatibleSource {
+                    layer: "parks".to_string(),
+                    source: "satellite".to_string()
+                },
+                StyleError::MissingProperty {
+                    layer: "labels".to_string(),
+                    property: "source-layer".to_string()
+                },
+                StyleError::UnrecognizedProperty {
+                    layer: "labels".to_string(),
+                    property: "layout.text-fnt".to_string()
+                },
+                StyleError::MissingProperty {
+                    layer: "relief".to_string(),
+                    property: "source".to_string()
+                },
+            ]
+        );
+        assert_eq!(errors[1].layer(), "roads");
+    }
+
+    #[test]
+    fn test_set_layer_visibility() {
+        let mut style = Style::default();
+
+        assert!(style.set_layer_visibility("park", false));
+        assert!(style.hidden_layers().contains("park"));
+        assert!(style.set_layer_visibility("park", true));
+        assert!(style.hidden_layers().is_empty());
+        assert!(!style.set_layer_visibility("does not exist", false));
+    }
+
+    #[test]
+    fn test_set_paint_property() {
+        let mut style = Style::default();
+
+        assert!(style.set_paint_property("park", "line-color", serde_json::json!("#ff0000")));
+        assert!(style.set_paint_property("park", "line-width", serde_json::json!(3.0)));
+        assert_eq!(style.paint_revisions.get("park"), Some(&2));
+        let paint = style.layers[0].paint.as_ref().unwrap();
+        assert_eq!(
+            paint.get_color(10.0, None).map(|color| color.color.r),
+            Some(1.0)
+        );
+        assert_eq!(paint.get_line_width(10.0, None), Some(3.0));
+
+        // Properties which change the geometry or are unknown are not supported
+        assert!(!style.set_paint_property("park", "line-gap-width", serde_json::json!(2.0)));
+        assert!(!style.set_paint_property("park", "line-dasharray", serde_json::json!([2, 1])));
+        assert!(!style.set_paint_property("park", "fill-color", serde_json::json!("red")));
+        assert!(!style.set_paint_property(
+            "does not exist",
+            "line-color",
+            serde_json::json!("red")
+        ));
+        assert_eq!(style.paint_revisions.get("park"), Some(&2));
+    }
+
+    #[test]
+    fn test_from_json_skips_unsupported_layers() {
+        let (style, issues) = Style::from_json(
+            br#"{
+                "version": 8,
+                "metadata": {"maputnik:renderer": "mbgljs", "openmaptiles:version": 3},
+                "sprite": "sprites/basic",
+                "glyphs": "/fonts/{fontstack}/{range}.pbf",
+                "sources": {
+                    "openmaptiles": {"type": "vector", "url": "tiles.json"},
+                    "traffic": {"type": "video", "urls": ["traffic.mp4"]}
+                },
+                "layers": [
+                    {"id": "background", "type": "background"},
+                    {"id": "poi", "type": "circle", "source": "openmaptiles", "source-layer": "poi"},
+                    {"type": "fill"},
+                    {"id": "water", "type": "fill", "source": "openmaptiles", "source-layer": "water"}
+                ]
+            }"#,
+            Some("https://example.com/styles/basic/style.json?key=1"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            style
+                .layers
+                .iter()
+                .map(|layer| (layer.id.as_str(), layer.index))
+                .collect::<Vec<_>>(),
+            vec![("background", 0), ("water", 1)]
+        );
+        assert_eq!(style.layers[1].source_layer.as_deref(), Some("water"));
+        assert_eq!(issues.len(), 3);
+        assert!(issues.contains(&StyleIssue::Layer {
+            id: Some("poi".to_string()),
+            message: "unsupported layer type circle".to_string()
+        }));
+        assert!(issues
+            .iter()
+            .any(|issue| matches!(issue, StyleIssue::Source { id, .. } if id == "traffic")));
+
+        assert_eq!(
+            style.sprite.as_deref(),
+            Some("https://example.com/styles/basic/sprites/basic")
+        );
+        assert_eq!(
+            style.glyphs.as_deref(),
+            Some("https://example.com/fonts/{fontstack}/{range}.pbf")
+        );
+        assert_eq!(
+            style.vector_source().unwrap().url.as_deref(),
+            Some("https://example.com/styles/basic/tiles.json")
+        );
+
+        assert!(Style::from_json(b"[]", None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_url() {
+        let base = "https://example.com/styles/basic/style.json";
+
+        assert_eq!(
+            resolve_url(base, "https://tiles.example.com/tiles.json"),
+            "https://tiles.example.com/tiles.json"
+        );
+        assert_eq!(
+            resolve_url(base, "mapbox://mapbox.satellite"),
+            "mapbox://mapbox.satellite"
+        );
+        assert_eq!(
+            resolve_url(base, "//cdn.example.com/sprite"),
+            "https://cdn.example.com/sprite"
+        );
+        assert_eq!(
+            resolve_url(base, "/tiles.json"),
+            "https://example.com/tiles.json"
+        );
+        assert_eq!(
+            resolve_url(base, "./tiles.json"),
+            "https://example.com/styles/basic/tiles.json"
+        );
+        assert_eq!(
+            resolve_url(base, "../../tiles.json"),
+            "https://example.com/tiles.json"
+        );
+        assert_eq!(
+            resolve_url("https://example.com", "tiles.json"),
+            "https://example.com/tiles.json"
+        );
+    }
 }
@@ -0,0 +1,148 @@
+//! Tessellation of extruded polygons like 3D buildings.
+
+use crate::error::Error;
+use crate::render::ExtrusionVertex;
+use crate::tessellation::{IndexDataType, DEFAULT_TOLERANCE};
+use lyon::geom;
+use lyon::lyon_tessellation::VertexBuffers;
+use lyon::path::Path;
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex, FillVertexConstructor,
+};
+
+/// Normal of the roof, which points upwards.
+const ROOF_NORMAL: [f32; 3] = [0.0, 0.0, 1.0];
+
+/// Creates the vertices of the roof at a fixed height.
+struct RoofVertexConstructor {
+    height: f32,
+}
+
+impl FillVertexConstructor<ExtrusionVertex> for RoofVertexConstructor {
+    fn new_vertex(&mut self, vertex: FillVertex) -> ExtrusionVertex {
+        let [x, y] = vertex.position().to_array();
+        ExtrusionVertex::new([x, y, self.height], ROOF_NORMAL)
+    }
+}
+
+/// Tessellates a polygon extruded from `base` up to `height` and appends the geometry to `buffer`.
+/// The `rings` are the closed rings of the polygon in tile coordinates, like they are returned by
+/// [`geometry_paths`](crate::text::feature::geometry_paths).
+///
+/// Every edge of a ring becomes a wall with its own vertices such that the normals of adjacent
+/// walls are not smoothed. The normals of walls point outwards if exterior rings are wound like
+/// the vector tile specification demands and holes are wound the other way round. The bottom is
+/// not tessellated, because it can not be seen from above.
+pub fn tessellate_extrusion(
+    rings: &[Vec<[f32; 2]>],
+    base: f32,
+    height: f32,
+    buffer: &mut VertexBuffers<ExtrusionVertex, IndexDataType>,
+) -> Result<(), Error> {
+    if height <= base {
+        return Ok(());
+    }
+
+    for ring in rings {
+        for edge in ring.windows(2) {
+            let ([ax, ay], [bx, by]) = (edge[0], edge[1]);
+            let (dx, dy) = (bx - ax, by - ay);
+            let length = (dx * dx + dy * dy).sqrt();
+            if length == 0.0 {
+                continue;
+            }
+
+            let normal = [dy / length, -dx / length, 0.0];
+            let first_index = buffer.vertices.len() as IndexDataType;
+
+            buffer.vertices.extend([
+                ExtrusionVertex::new([ax, ay, base], normal),
+                ExtrusionVertex::new([bx, by, base], normal),
+                ExtrusionVertex::new([ax, ay, height], normal),
+                ExtrusionVertex::new([bx, by, height], normal),
+            ]);
+            buffer.indices.extend([
+                first_index,
+                first_index + 1,
+                first_index + 2,
+                first_index + 1,
+                first_index + 3,
+                first_index + 2,
+            ]);
+        }
+    }
+
+    let mut path_builder = Path::builder();
+    for ring in rings.iter().filter(|ring| ring.len() >= 3) {
+        path_builder.begin(geom::point(ring[0][0], ring[0][1]));
+        for [x, y] in &ring[1..] {
+            path_builder.line_to(geom::point(*x, *y));
+        }
+        path_builder.end(true);
+    }
+
+    FillTessellator::new().tessellate_path(
+        &path_builder.build(),
+        &FillOptions::tolerance(DEFAULT_TOLERANCE).with_fill_rule(FillRule::NonZero),
+        &mut BuffersBuilder::new(buffer, RoofVertexConstructor { height }),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tessellate_extrusion, ROOF_NORMAL};
+    use lyon::lyon_tessellation::VertexBuffers;
+
+    fn square() -> Vec<Vec<[f32; 2]>> {
+        vec![vec![
+            [0.0, 0.0],
+            [10.0, 0.0],
+            [10.0, 10.0],
+            [0.0, 10.0],
+            [0.0, 0.0],
+        ]]
+    }
+
+    #[test]
+    fn test_walls_and_roof() {
+        let mut buffer = VertexBuffers::new();
+        tessellate_extrusion(&square(), 2.0, 8.0, &mut buffer).unwrap();
+
+        let (roof, walls): (Vec<&_>, Vec<&_>) = buffer
+            .vertices
+            .iter()
+            .partition(|vertex| vertex.normal == ROOF_NORMAL);
+
+        // Four walls with four vertices each
+        assert_eq!(walls.len(), 16);
+        assert!(walls
+            .iter()
+            .all(|vertex| vertex.position[2] == 2.0 || vertex.position[2] == 8.0));
+        assert!(!roof.is_empty());
+        assert!(roof.iter().all(|vertex| vertex.position[2] == 8.0));
+        // Four walls and at least two triangles for the roof
+        assert!(buffer.indices.len() >= 4 * 6 + 2 * 3);
+    }
+
+    #[test]
+    fn test_wall_normals_point_outwards() {
+        let mut buffer = VertexBuffers::new();
+        tessellate_extrusion(&square(), 0.0, 8.0, &mut buffer).unwrap();
+
+        // The first wall lies along the top edge of the square at y = 0
+        assert_eq!(buffer.vertices[0].normal, [0.0, -1.0, 0.0]);
+        // The second wall lies along the right edge of the square at x = 10
+        assert_eq!(buffer.vertices[4].normal, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_flat_polygon_is_skipped() {
+        let mut buffer = VertexBuffers::new();
+        tessellate_extrusion(&square(), 8.0, 8.0, &mut buffer).unwrap();
+
+        assert!(buffer.vertices.is_empty());
+        assert!(buffer.indices.is_empty());
+    }
+}
@@ -3,17 +3,26 @@
 use bytemuck::Pod;
 use std::ops::Add;
 
+use lyon::path::Path;
+use lyon::tessellation;
+use lyon::tessellation::geometry_builder::MaxIndex;
 use lyon::tessellation::{
-    FillVertex, FillVertexConstructor, StrokeVertex, StrokeVertexConstructor, VertexBuffers,
+    BuffersBuilder, FillVertex, FillVertexConstructor, Side, StrokeOptions, StrokeTessellator,
+    StrokeVertex, StrokeVertexConstructor, TessellationError, VertexBuffers, VertexId,
 };
 
 use crate::error::Error;
 use crate::render::ShaderVertex;
+use crate::style::layer::{LayerLayout, LineCap, LineJoin};
 
+pub mod extrusion;
 pub mod zero_tessellator;
 
 const DEFAULT_TOLERANCE: f32 = 0.02;
 
+/// Default of `line-miter-limit` according to the style specification.
+const DEFAULT_MITER_LIMIT: f32 = 2.0;
+
 /// Vertex buffers index data type.
 pub type IndexDataType = u32; // Must match INDEX_FORMAT
 
@@ -30,16 +39,130 @@ pub struct VertexConstructor {}
 
 impl FillVertexConstructor<ShaderVertex> for VertexConstructor {
     fn new_vertex(&mut self, vertex: FillVertex) -> ShaderVertex {
-        ShaderVertex::new(vertex.position().to_array(), [0.0, 0.0])
+        ShaderVertex::new(vertex.position().to_array(), [0.0, 0.0], 0.0)
     }
 }
 
 impl StrokeVertexConstructor<ShaderVertex> for VertexConstructor {
     fn new_vertex(&mut self, vertex: StrokeVertex) -> ShaderVertex {
-        ShaderVertex::new(
-            vertex.position_on_path().to_array(),
-            vertex.normal().to_array(),
-        )
+        ShaderVertex {
+            line_side: match vertex.side() {
+                Side::Left => -1.0,
+                Side::Right => 1.0,
+            },
+            ..ShaderVertex::new(
+                vertex.position_on_path().to_array(),
+                vertex.normal().to_array(),
+                vertex.advancement(),
+            )
+        }
+    }
+}
+
+/// Constructs the vertices of one half of a hollow line, see [`LineStyle::hollow`]. The vertices
+/// on the other side of the line are on the inner edge of the half.
+struct HalfLineVertexConstructor {
+    side: Side,
+}
+
+impl StrokeVertexConstructor<ShaderVertex> for HalfLineVertexConstructor {
+    fn new_vertex(&mut self, vertex: StrokeVertex) -> ShaderVertex {
+        let line_edge = if vertex.side() == self.side {
+            1.0
+        } else {
+            -1.0
+        };
+        ShaderVertex {
+            line_edge,
+            ..StrokeVertexConstructor::new_vertex(&mut VertexConstructor {}, vertex)
+        }
+    }
+}
+
+/// Shape of the ends and corners of the lines of a layer, see `line-cap`, `line-join` and
+/// `line-miter-limit`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineStyle {
+    pub cap: LineCap,
+    pub join: LineJoin,
+    pub miter_limit: f32,
+    /// Whether lines are tessellated as two halves, which are moved apart by the shader such that
+    /// a gap is left along the path, see `line-gap-width`. Lines without a gap are drawn from the
+    /// same geometry, so source layers which are shared by casings and other layers are only
+    /// tessellated once.
+    pub hollow: bool,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self {
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            miter_limit: DEFAULT_MITER_LIMIT,
+            hollow: false,
+        }
+    }
+}
+
+impl LineStyle {
+    pub fn from_layout(layout: Option<&LayerLayout>) -> Self {
+        let default = Self::default();
+        match layout {
+            Some(layout) => Self {
+                cap: layout.line_cap.unwrap_or(default.cap),
+                join: layout.line_join.unwrap_or(default.join),
+                miter_limit: layout.line_miter_limit.unwrap_or(default.miter_limit),
+                ..default
+            },
+            None => default,
+        }
+    }
+
+    /// Tessellates the stroke of `path` into `buffer`. Hollow lines are tessellated twice, once
+    /// for each half.
+    pub fn tessellate_stroke<I>(
+        &self,
+        path: &Path,
+        buffer: &mut VertexBuffers<ShaderVertex, I>,
+    ) -> Result<(), TessellationError>
+    where
+        I: Add + From<VertexId> + MaxIndex,
+    {
+        let mut tessellator = StrokeTessellator::new();
+        let options = self.stroke_options();
+        if self.hollow {
+            for side in [Side::Left, Side::Right] {
+                tessellator.tessellate_path(
+                    path,
+                    &options,
+                    &mut BuffersBuilder::new(buffer, HalfLineVertexConstructor { side }),
+                )?;
+            }
+        } else {
+            tessellator.tessellate_path(
+                path,
+                &options,
+                &mut BuffersBuilder::new(buffer, VertexConstructor {}),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Lines are tessellated with a width of one. The width of the style is applied in the shader
+    /// by moving the vertices along their normals.
+    pub fn stroke_options(&self) -> StrokeOptions {
+        StrokeOptions::tolerance(DEFAULT_TOLERANCE)
+            .with_line_cap(match self.cap {
+                LineCap::Butt => tessellation::LineCap::Butt,
+                LineCap::Round => tessellation::LineCap::Round,
+                LineCap::Square => tessellation::LineCap::Square,
+            })
+            .with_line_join(match self.join {
+                LineJoin::Bevel => tessellation::LineJoin::Bevel,
+                LineJoin::Round => tessellation::LineJoin::Round,
+                LineJoin::Miter => tessellation::LineJoin::Miter,
+            })
+            .with_miter_limit(self.miter_limit.max(StrokeOptions::MINIMUM_MITER_LIMIT))
     }
 }
 
@@ -103,3 +226,101 @@ impl<V: Pod, I: Pod> Align<V, I> for VertexBuffers<V, I> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::LineStyle;
+    use crate::render::ShaderVertex;
+    use crate::style::layer::{LineCap, LineJoin, StyleLayer};
+    use lyon::geom;
+    use lyon::path::Path;
+    use lyon::tessellation::VertexBuffers;
+
+    /// Tessellates a line with a right angle.
+    fn tessellate_corner(line_style: &LineStyle) -> Vec<ShaderVertex> {
+        let mut path_builder = Path::builder();
+        path_builder.begin(geom::point(0.0, 0.0));
+        path_builder.line_to(geom::point(10.0, 0.0));
+        path_builder.line_to(geom::point(10.0, 10.0));
+        path_builder.end(false);
+
+        let mut buffer: VertexBuffers<ShaderVertex, u32> = VertexBuffers::new();
+        line_style
+            .tessellate_stroke(&path_builder.build(), &mut buffer)
+            .unwrap();
+        buffer.vertices
+    }
+
+    /// Tessellates a line with a right angle and returns the number of vertices.
+    fn corner_vertices(line_style: &LineStyle) -> usize {
+        tessellate_corner(line_style).len()
+    }
+
+    #[test]
+    fn test_line_style_from_layout() {
+        let layer: StyleLayer = serde_json::from_value(serde_json::json!({
+            "id": "road",
+            "type": "line",
+            "layout": {
+                "line-cap": "round",
+                "line-join": "bevel",
+                "line-miter-limit": 4
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            LineStyle::from_layout(layer.layout.as_ref()),
+            LineStyle {
+                cap: LineCap::Round,
+                join: LineJoin::Bevel,
+                miter_limit: 4.0,
+                hollow: false,
+            }
+        );
+        assert_eq!(LineStyle::from_layout(None), LineStyle::default());
+    }
+
+    #[test]
+    fn test_round_joins_add_vertices() {
+        let miter = corner_vertices(&LineStyle::default());
+        let round = corner_vertices(&LineStyle {
+            join: LineJoin::Round,
+            ..LineStyle::default()
+        });
+        let round_caps = corner_vertices(&LineStyle {
+            cap: LineCap::Round,
+            join: LineJoin::Round,
+            ..LineStyle::default()
+        });
+
+        assert!(round > miter);
+        assert!(round_caps > round);
+    }
+
+    #[test]
+    fn test_hollow_lines_have_inner_edges() {
+        let solid = tessellate_corner(&LineStyle::default());
+        assert!(solid.iter().all(|vertex| vertex.line_edge == 1.0));
+
+        let hollow = tessellate_corner(&LineStyle {
+            hollow: true,
+            ..LineStyle::default()
+        });
+        assert_eq!(hollow.len(), 2 * solid.len());
+        let inner = hollow
+            .iter()
+            .filter(|vertex| vertex.line_edge == -1.0)
+            .count();
+        assert_eq!(inner, solid.len());
+    }
+
+    #[test]
+    fn test_line_sides() {
+        // Patterns are stretched across the line from one side to the other
+        let vertices = tessellate_corner(&LineStyle::default());
+        assert!(vertices.iter().any(|vertex| vertex.line_side == -1.0));
+        assert!(vertices.iter().any(|vertex| vertex.line_side == 1.0));
+        assert!(vertices.iter().all(|vertex| vertex.line_side.abs() == 1.0));
+    }
+}
@@ -1,5 +1,6 @@
 //! Tessellator implementation.
 
+use geozero::error::GeozeroError;
 use geozero::{FeatureProcessor, GeomProcessor, PropertyProcessor};
 use lyon::geom;
 
@@ -8,12 +9,10 @@ use lyon::lyon_tessellation::VertexBuffers;
 use lyon::path::path::Builder;
 use lyon::path::Path;
 use lyon::tessellation::geometry_builder::MaxIndex;
-use lyon::tessellation::{
-    BuffersBuilder, FillOptions, FillRule, FillTessellator, StrokeOptions, StrokeTessellator,
-};
+use lyon::tessellation::{BuffersBuilder, FillOptions, FillRule, FillTessellator};
 use std::cell::RefCell;
 
-use crate::tessellation::{VertexConstructor, DEFAULT_TOLERANCE};
+use crate::tessellation::{LineStyle, VertexConstructor, DEFAULT_TOLERANCE};
 
 type GeoResult<T> = geozero::error::Result<T>;
 
@@ -22,11 +21,20 @@ pub struct ZeroTessellator<I: std::ops::Add + From<lyon::tessellation::VertexId>
     path_builder: RefCell<Builder>,
     path_open: bool,
     is_point: bool,
+    /// First and last point of the currently open path. Used to detect closed line strings.
+    path_start: Option<(f32, f32)>,
+    path_current: Option<(f32, f32)>,
 
     pub buffer: VertexBuffers<ShaderVertex, I>,
 
     pub feature_indices: Vec<u32>,
     current_index: usize,
+
+    line_style: LineStyle,
+
+    /// Checked before each feature. Tessellation is aborted once it returns true.
+    is_cancelled: Option<Box<dyn Fn() -> bool>>,
+    cancelled: bool,
 }
 
 impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> Default
@@ -40,11 +48,33 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> Default
             current_index: 0,
             path_open: false,
             is_point: false,
+            path_start: None,
+            path_current: None,
+            line_style: LineStyle::default(),
+            is_cancelled: None,
+            cancelled: false,
         }
     }
 }
 
 impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> ZeroTessellator<I> {
+    /// Tessellates the ends and corners of lines according to `line_style`.
+    pub fn with_line_style(mut self, line_style: &LineStyle) -> Self {
+        self.line_style = *line_style;
+        self
+    }
+
+    /// Aborts the tessellation before the next feature once `is_cancelled` returns true.
+    pub fn with_cancellation(mut self, is_cancelled: impl Fn() -> bool + 'static) -> Self {
+        self.is_cancelled = Some(Box::new(is_cancelled));
+        self
+    }
+
+    /// Whether the tessellation has been aborted, see [`ZeroTessellator::with_cancellation`].
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
     fn update_feature_indices(&mut self) {
         let next_index = self.buffer.indices.len();
         let indices = (next_index - self.current_index) as u32;
@@ -55,12 +85,8 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> ZeroTesse
     fn tessellate_strokes(&mut self) {
         let path_builder = self.path_builder.replace(Path::builder());
 
-        StrokeTessellator::new()
-            .tessellate_path(
-                &path_builder.build(),
-                &StrokeOptions::tolerance(DEFAULT_TOLERANCE),
-                &mut BuffersBuilder::new(&mut self.buffer, VertexConstructor {}),
-            )
+        self.line_style
+            .tessellate_stroke(&path_builder.build(), &mut self.buffer)
             .unwrap();
     }
 
@@ -68,16 +94,32 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> ZeroTesse
         if self.path_open {
             self.path_builder.borrow_mut().end(close);
             self.path_open = false;
+            self.path_start = None;
+            self.path_current = None;
+        }
+    }
+
+    /// Returns whether the currently open path ends at its starting point. Closing such a path
+    /// makes sure that the distance along the line is accumulated continuously around the ring.
+    fn is_ring(&self) -> bool {
+        match (self.path_start, self.path_current) {
+            (Some(start), Some(current)) => start == current,
+            _ => false,
         }
     }
 
+    /// Fills the rings of a polygon or multipolygon. The even-odd rule cuts out holes regardless
+    /// of the winding of their rings. Vector tiles require holes to be wound opposite to their
+    /// exterior ring, but tiles in the wild do not always follow this. Holes which are wound like
+    /// an exterior ring are parsed as separate polygons, which overlap the polygon they belong to.
+    /// Self-intersecting rings are filled like by the reference renderer as well.
     fn tessellate_fill(&mut self) {
         let path_builder = self.path_builder.replace(Path::builder());
 
         FillTessellator::new()
             .tessellate_path(
                 &path_builder.build(),
-                &FillOptions::tolerance(DEFAULT_TOLERANCE).with_fill_rule(FillRule::NonZero),
+                &FillOptions::tolerance(DEFAULT_TOLERANCE).with_fill_rule(FillRule::EvenOdd),
                 &mut BuffersBuilder::new(&mut self.buffer, VertexConstructor {}),
             )
             .unwrap();
@@ -97,10 +139,13 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> GeomProce
                 .borrow_mut()
                 .begin(geom::point(x as f32, y as f32));
             self.path_open = true;
+            self.path_start = Some((x as f32, y as f32));
+            self.path_current = self.path_start;
         } else {
             self.path_builder
                 .borrow_mut()
                 .line_to(geom::point(x as f32, y as f32));
+            self.path_current = Some((x as f32, y as f32));
         }
         Ok(())
     }
@@ -135,7 +180,8 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> GeomProce
     fn linestring_end(&mut self, tagged: bool, _idx: usize) -> GeoResult<()> {
         // log::info!("linestring_end");
 
-        self.end(false);
+        let close = self.is_ring();
+        self.end(close);
 
         if tagged {
             self.tessellate_strokes();
@@ -190,8 +236,145 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> PropertyP
 impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> FeatureProcessor
     for ZeroTessellator<I>
 {
+    fn feature_begin(&mut self, _idx: u64) -> geozero::error::Result<()> {
+        if self
+            .is_cancelled
+            .as_ref()
+            .map_or(false, |is_cancelled| is_cancelled())
+        {
+            self.cancelled = true;
+            return Err(GeozeroError::Feature("tessellation cancelled".to_string()));
+        }
+        Ok(())
+    }
+
     fn feature_end(&mut self, _idx: u64) -> geozero::error::Result<()> {
         self.update_feature_indices();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ZeroTessellator;
+    use crate::coords::WorldTileCoords;
+    use crate::io::geojson_source::GeoJsonSource;
+    use geozero::mvt::tile;
+    use geozero::GeozeroDatasource;
+    use serde_json::json;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn square(offset: f64) -> serde_json::Value {
+        json!({
+            "type": "Feature",
+            "properties": {},
+            "geometry": {
+                "type": "Polygon",
+                "coordinates": [[
+                    [offset, 0.0],
+                    [offset + 10.0, 0.0],
+                    [offset + 10.0, 10.0],
+                    [offset, 10.0],
+                    [offset, 0.0]
+                ]]
+            }
+        })
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let source = GeoJsonSource::parse(&json!({
+            "type": "FeatureCollection",
+            "features": [square(0.0), square(20.0)]
+        }))
+        .unwrap();
+        let mut layer = source.tile_layer(&WorldTileCoords { x: 0, y: 0, z: 0 }, "squares");
+
+        let mut tessellator: ZeroTessellator<u32> = ZeroTessellator::default();
+        assert!(layer.process(&mut tessellator).is_ok());
+        assert_eq!(tessellator.feature_indices.len(), 2);
+        assert!(!tessellator.is_cancelled());
+
+        // Cancelled after the first feature
+        let checks = Rc::new(Cell::new(0));
+        let counter = checks.clone();
+        let mut tessellator: ZeroTessellator<u32> =
+            ZeroTessellator::default().with_cancellation(move || {
+                counter.set(counter.get() + 1);
+                counter.get() > 1
+            });
+        assert!(layer.process(&mut tessellator).is_err());
+        assert_eq!(tessellator.feature_indices.len(), 1);
+        assert!(tessellator.is_cancelled());
+        assert_eq!(checks.get(), 2);
+    }
+
+    fn zigzag(value: i32) -> u32 {
+        ((value << 1) ^ (value >> 31)) as u32
+    }
+
+    /// Encodes the rings of a polygon feature with the geometry commands of vector tiles.
+    fn polygon_layer(rings: &[&[[i32; 2]]]) -> tile::Layer {
+        let mut geometry = Vec::new();
+        let mut cursor = [0, 0];
+        for ring in rings {
+            for (i, point) in ring.iter().enumerate() {
+                if i == 0 {
+                    geometry.push((1 << 3) | 1);
+                } else if i == 1 {
+                    geometry.push(((ring.len() as u32 - 1) << 3) | 2);
+                }
+                geometry.push(zigzag(point[0] - cursor[0]));
+                geometry.push(zigzag(point[1] - cursor[1]));
+                cursor = *point;
+            }
+            geometry.push((1 << 3) | 7);
+        }
+
+        tile::Layer {
+            version: 2,
+            name: "water".to_string(),
+            features: vec![tile::Feature {
+                id: None,
+                tags: vec![],
+                r#type: Some(tile::GeomType::Polygon as i32),
+                geometry,
+            }],
+            keys: vec![],
+            values: vec![],
+            extent: Some(4096),
+        }
+    }
+
+    /// Returns the area which is covered by the triangles of the tessellation.
+    fn filled_area(layer: &mut tile::Layer) -> f32 {
+        let mut tessellator: ZeroTessellator<u32> = ZeroTessellator::default();
+        layer.process(&mut tessellator).unwrap();
+
+        let vertices = &tessellator.buffer.vertices;
+        tessellator
+            .buffer
+            .indices
+            .chunks(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+                ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])).abs() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_polygon_with_hole() {
+        let exterior: &[[i32; 2]] = &[[0, 0], [100, 0], [100, 100], [0, 100]];
+        let hole: &[[i32; 2]] = &[[30, 30], [30, 70], [70, 70], [70, 30]];
+        let misoriented_hole: &[[i32; 2]] = &[[30, 30], [70, 30], [70, 70], [30, 70]];
+
+        let area = |rings: &[&[[i32; 2]]]| filled_area(&mut polygon_layer(rings));
+
+        assert!((area(&[exterior]) - 10000.0).abs() < 1e-3);
+        // The hole is transparent
+        assert!((area(&[exterior, hole]) - 8400.0).abs() < 1e-3);
+        assert!((area(&[exterior, misoriented_hole]) - 8400.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,298 @@
+//! Helpers to access the raw geometry and properties of vector tile features.
+
+use crate::style::expression::{FeatureProperties, Value};
+use crate::style::filter::GEOMETRY_TYPE_PROPERTY;
+use geozero::mvt::tile;
+
+const COMMAND_MOVE_TO: u32 = 1;
+const COMMAND_LINE_TO: u32 = 2;
+const COMMAND_CLOSE_PATH: u32 = 7;
+
+fn decode_zigzag(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ (-((value & 1) as i32))
+}
+
+/// Decodes the coordinates of a point feature. Returns an empty list for other geometry types.
+pub fn point_geometry(feature: &tile::Feature) -> Vec<[f32; 2]> {
+    if feature.r#type != Some(tile::GeomType::Point as i32) {
+        return Vec::new();
+    }
+
+    let geometry = &feature.geometry;
+    let mut points = Vec::new();
+    let (mut x, mut y) = (0i32, 0i32);
+    let mut i = 0;
+
+    while i < geometry.len() {
+        let command = geometry[i] & 0x7;
+        let count = geometry[i] >> 3;
+        i += 1;
+
+        match command {
+            COMMAND_MOVE_TO | COMMAND_LINE_TO => {
+                for _ in 0..count {
+                    if i + 1 >= geometry.len() {
+                        return points;
+                    }
+                    x += decode_zigzag(geometry[i]);
+                    y += decode_zigzag(geometry[i + 1]);
+                    i += 2;
+
+                    if command == COMMAND_MOVE_TO {
+                        points.push([x as f32, y as f32]);
+                    }
+                }
+            }
+            COMMAND_CLOSE_PATH => {}
+            _ => break,
+        }
+    }
+
+    points
+}
+
+/// Decodes the geometry of a feature into paths. Each line string and each ring of a polygon is a
+/// separate path. Rings are closed by repeating their first point. Points are returned as paths
+/// with a single point.
+pub fn geometry_paths(feature: &tile::Feature) -> Vec<Vec<[f32; 2]>> {
+    let geometry = &feature.geometry;
+    let mut paths: Vec<Vec<[f32; 2]>> = Vec::new();
+    let (mut x, mut y) = (0i32, 0i32);
+    let mut i = 0;
+
+    while i < geometry.len() {
+        let command = geometry[i] & 0x7;
+        let count = geometry[i] >> 3;
+        i += 1;
+
+        match command {
+            COMMAND_MOVE_TO | COMMAND_LINE_TO => {
+                for _ in 0..count {
+                    if i + 1 >= geometry.len() {
+                        return paths;
+                    }
+                    x += decode_zigzag(geometry[i]);
+                    y += decode_zigzag(geometry[i + 1]);
+                    i += 2;
+
+                    match paths.last_mut() {
+                        Some(path) if command == COMMAND_LINE_TO => path.push([x as f32, y as f32]),
+                        _ => paths.push(vec![[x as f32, y as f32]]),
+                    }
+                }
+            }
+            COMMAND_CLOSE_PATH => {
+                if let Some(path) = paths.last_mut() {
+                    if let Some(first) = path.first().cloned() {
+                        path.push(first);
+                    }
+                }
+            }
+            _ => break,
+        }
+    }
+
+    paths
+}
+
+/// Returns the property keys of a feature.
+pub fn property_keys<'a>(
+    layer: &'a tile::Layer,
+    feature: &'a tile::Feature,
+) -> impl Iterator<Item = &'a str> + 'a {
+    feature
+        .tags
+        .chunks(2)
+        .filter_map(move |tag| layer.keys.get(tag[0] as usize).map(|key| key.as_str()))
+}
+
+/// Returns the raw value of the property `key` of a feature.
+fn property_value<'a>(
+    layer: &'a tile::Layer,
+    feature: &tile::Feature,
+    key: &str,
+) -> Option<&'a tile::Value> {
+    feature.tags.chunks(2).find_map(|tag| {
+        if tag.len() != 2 || layer.keys.get(tag[0] as usize)?.as_str() != key {
+            return None;
+        }
+
+        layer.values.get(tag[1] as usize)
+    })
+}
+
+/// Returns the property `key` of a feature formatted as string.
+pub fn property_string(layer: &tile::Layer, feature: &tile::Feature, key: &str) -> Option<String> {
+    let value = property_value(layer, feature, key)?;
+
+    value
+        .string_value
+        .clone()
+        .or_else(|| value.float_value.map(|v| v.to_string()))
+        .or_else(|| value.double_value.map(|v| v.to_string()))
+        .or_else(|| value.int_value.map(|v| v.to_string()))
+        .or_else(|| value.uint_value.map(|v| v.to_string()))
+        .or_else(|| value.sint_value.map(|v| v.to_string()))
+        .or_else(|| value.bool_value.map(|v| v.to_string()))
+}
+
+/// Exposes the properties of a vector tile feature to style expressions.
+pub struct TileFeature<'a> {
+    pub layer: &'a tile::Layer,
+    pub feature: &'a tile::Feature,
+}
+
+impl<'a> FeatureProperties for TileFeature<'a> {
+    fn get_property(&self, key: &str) -> Option<Value> {
+        if key == GEOMETRY_TYPE_PROPERTY {
+            return geometry_type(self.feature).map(|typ| Value::String(typ.to_string()));
+        }
+
+        let value = property_value(self.layer, self.feature, key)?;
+
+        value
+            .string_value
+            .clone()
+            .map(Value::String)
+            .or_else(|| value.float_value.map(|v| Value::Number(v as f64)))
+            .or_else(|| value.double_value.map(Value::Number))
+            .or_else(|| value.int_value.map(|v| Value::Number(v as f64)))
+            .or_else(|| value.uint_value.map(|v| Value::Number(v as f64)))
+            .or_else(|| value.sint_value.map(|v| Value::Number(v as f64)))
+            .or_else(|| value.bool_value.map(Value::Bool))
+    }
+}
+
+/// Returns the geometry type of a feature as it is named in filters.
+fn geometry_type(feature: &tile::Feature) -> Option<&'static str> {
+    match feature.r#type {
+        Some(typ) if typ == tile::GeomType::Point as i32 => Some("Point"),
+        Some(typ) if typ == tile::GeomType::Linestring as i32 => Some("LineString"),
+        Some(typ) if typ == tile::GeomType::Polygon as i32 => Some("Polygon"),
+        _ => None,
+    }
+}
+
+/// Replaces tokens like `{name}` in a `text-field` with the properties of a feature. Unknown
+/// properties are replaced with an empty string.
+pub fn resolve_text_field(template: &str, layer: &tile::Layer, feature: &tile::Feature) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        if let Some(end) = rest[start..].find('}') {
+            let key = &rest[start + 1..start + end];
+            if let Some(value) = property_string(layer, feature, key) {
+                result.push_str(&value);
+            }
+            rest = &rest[start + end + 1..];
+        } else {
+            result.push_str(&rest[start..]);
+            rest = "";
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_zigzag, geometry_paths, point_geometry, property_keys, resolve_text_field,
+        TileFeature,
+    };
+    use crate::style::expression::{FeatureProperties, Value};
+    use geozero::mvt::tile;
+
+    fn layer_with_name() -> (tile::Layer, tile::Feature) {
+        let layer = tile::Layer {
+            version: 2,
+            name: "place".to_string(),
+            features: vec![],
+            keys: vec!["name".to_string()],
+            values: vec![tile::Value {
+                string_value: Some("München".to_string()),
+                ..Default::default()
+            }],
+            extent: Some(4096),
+        };
+        let feature = tile::Feature {
+            id: None,
+            tags: vec![0, 0],
+            r#type: Some(tile::GeomType::Point as i32),
+            // MoveTo(2) (25, 17) (3, -1)
+            geometry: vec![(2 << 3) | 1, 50, 34, 6, 1],
+        };
+        (layer, feature)
+    }
+
+    #[test]
+    fn test_zigzag() {
+        assert_eq!(decode_zigzag(0), 0);
+        assert_eq!(decode_zigzag(1), -1);
+        assert_eq!(decode_zigzag(2), 1);
+        assert_eq!(decode_zigzag(3), -2);
+    }
+
+    #[test]
+    fn test_point_geometry() {
+        let (_layer, feature) = layer_with_name();
+        assert_eq!(point_geometry(&feature), vec![[25.0, 17.0], [28.0, 16.0]]);
+    }
+
+    #[test]
+    fn test_geometry_paths() {
+        let (layer, feature) = layer_with_name();
+        assert_eq!(
+            geometry_paths(&feature),
+            vec![vec![[25.0, 17.0]], vec![[28.0, 16.0]]]
+        );
+        assert_eq!(
+            property_keys(&layer, &feature).collect::<Vec<_>>(),
+            vec!["name"]
+        );
+
+        let polygon = tile::Feature {
+            id: None,
+            tags: vec![],
+            r#type: Some(tile::GeomType::Polygon as i32),
+            // MoveTo (3, 6) LineTo(2) (8, 12) (20, 34) ClosePath
+            geometry: vec![9, 6, 12, 18, 10, 12, 24, 44, 15],
+        };
+        assert_eq!(
+            geometry_paths(&polygon),
+            vec![vec![[3.0, 6.0], [8.0, 12.0], [20.0, 34.0], [3.0, 6.0]]]
+        );
+    }
+
+    #[test]
+    fn test_resolve_text_field() {
+        let (layer, feature) = layer_with_name();
+        assert_eq!(resolve_text_field("{name}", &layer, &feature), "München");
+        assert_eq!(
+            resolve_text_field("City: {name} {missing}", &layer, &feature),
+            "City: München "
+        );
+        assert_eq!(resolve_text_field("{name", &layer, &feature), "{name");
+    }
+
+    #[test]
+    fn test_tile_feature_properties() {
+        let (layer, feature) = layer_with_name();
+        let properties = TileFeature {
+            layer: &layer,
+            feature: &feature,
+        };
+        assert_eq!(
+            properties.get_property("name"),
+            Some(Value::String("München".to_string()))
+        );
+        assert_eq!(properties.get_property("missing"), None);
+        assert_eq!(
+            properties.get_property("$type"),
+            Some(Value::String("Point".to_string()))
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Placement of labels along lines for `symbol-placement` `line` and `line-center`. Each glyph is
+//! anchored at its own position on the line and rotated to the direction of the line there.
+//!
+//! The anchors of the glyphs are fixed in tile coordinates while their size is constant on the
+//! screen. Therefore the distance between glyphs only matches their advance at the zoom level of
+//! the tile.
+
+use crate::style::layer::SymbolPlacement;
+use std::f32::consts::PI;
+
+/// Largest angle between adjacent glyphs of a label, like the default `text-max-angle`. Labels are
+/// not placed across sharper bends.
+const MAX_ANGLE: f32 = PI / 4.0;
+
+/// Rotates `point` by `angle` in radians around the origin.
+pub fn rotate([x, y]: [f32; 2], angle: f32) -> [f32; 2] {
+    let (sin, cos) = angle.sin_cos();
+    [x * cos - y * sin, x * sin + y * cos]
+}
+
+/// Maps `angle` into the range from -π to π.
+fn normalize_angle(angle: f32) -> f32 {
+    let angle = angle % (2.0 * PI);
+    if angle > PI {
+        angle - 2.0 * PI
+    } else if angle < -PI {
+        angle + 2.0 * PI
+    } else {
+        angle
+    }
+}
+
+/// A glyph which has been placed on a line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineGlyph {
+    /// Position of the center of the glyph in tile coordinates.
+    pub anchor: [f32; 2],
+    /// Rotation of the glyph in radians.
+    pub angle: f32,
+}
+
+/// A label which has been placed on a line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineLabel {
+    /// Position of the center of the label in tile coordinates.
+    pub anchor: [f32; 2],
+    /// The placed glyphs in the order of the glyph centers they were placed for.
+    pub glyphs: Vec<LineGlyph>,
+}
+
+/// A line string together with the distance of each of its points from its start.
+pub struct LinePath<'a> {
+    points: &'a [[f32; 2]],
+    distances: Vec<f32>,
+}
+
+impl<'a> LinePath<'a> {
+    pub fn new(points: &'a [[f32; 2]]) -> Self {
+        let mut distances = Vec::with_capacity(points.len());
+        let mut distance = 0.0;
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                let [x, y] = points[i - 1];
+                distance += (point[0] - x).hypot(point[1] - y);
+            }
+            distances.push(distance);
+        }
+        Self { points, distances }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.distances.last().cloned().unwrap_or(0.0)
+    }
+
+    /// Returns the point at `distance` from the start of the line and the direction of the line
+    /// there. `None` if the distance is beyond the ends of the line.
+    fn point_at(&self, distance: f32) -> Option<([f32; 2], f32)> {
+        if distance < 0.0 || distance > self.length() {
+            return None;
+        }
+
+        for (i, segment) in self.points.windows(2).enumerate() {
+            let (start, end) = (self.distances[i], self.distances[i + 1]);
+            // Segments without a length have no direction
+            if end <= start || distance > end {
+                continue;
+            }
+
+            let [x0, y0] = segment[0];
+            let [x1, y1] = segment[1];
+            let t = (distance - start) / (end - start);
+            return Some((
+                [x0 + (x1 - x0) * t, y0 + (y1 - y0) * t],
+                (y1 - y0).atan2(x1 - x0),
+            ));
+        }
+        None
+    }
+
+    /// Returns the distances from the start of the line at which labels of `label_length` are
+    /// centered. Labels are repeated at least `spacing` apart and spread evenly across the line.
+    /// Lines which are shorter than the label are skipped.
+    pub fn label_anchors(
+        &self,
+        label_length: f32,
+        spacing: f32,
+        placement: SymbolPlacement,
+    ) -> Vec<f32> {
+        let length = self.length();
+        if label_length > length {
+            return Vec::new();
+        }
+
+        match placement {
+            SymbolPlacement::Point => Vec::new(),
+            SymbolPlacement::LineCenter => vec![length / 2.0],
+            SymbolPlacement::Line => {
+                let count = (length / spacing.max(label_length).max(1.0))
+                    .floor()
+                    .max(1.0) as usize;
+                (0..count)
+                    .map(|i| (i as f32 + 0.5) * length / count as f32)
+                    .collect()
+            }
+        }
+    }
+
+    /// Places the glyphs of a label which is centered at `distance` from the start of the line.
+    /// `glyph_centers` are the horizontal offsets of the centers of the glyphs from the center of
+    /// the label in tile units.
+    ///
+    /// If the line runs from right to left, the glyphs are placed in reverse direction and turned
+    /// around, such that the text is not upside down. `None` if the label runs over an end of the
+    /// line or across a sharp bend.
+    pub fn place_label(&self, distance: f32, glyph_centers: &[f32]) -> Option<LineLabel> {
+        let half_length = glyph_centers
+            .iter()
+            .fold(0.0f32, |half_length, center| half_length.max(center.abs()));
+
+        let (anchor, _) = self.point_at(distance)?;
+        let (start, _) = self.point_at(distance - half_length)?;
+        let (end, _) = self.point_at(distance + half_length)?;
+        let flip = end[0] < start[0];
+
+        let mut glyphs: Vec<LineGlyph> = Vec::with_capacity(glyph_centers.len());
+        for center in glyph_centers {
+            let (anchor, angle) = if flip {
+                let (anchor, angle) = self.point_at(distance - center)?;
+                (anchor, normalize_angle(angle + PI))
+            } else {
+                self.point_at(distance + center)?
+            };
+
+            if let Some(previous) = glyphs.last() {
+                if normalize_angle(angle - previous.angle).abs() > MAX_ANGLE {
+                    return None;
+                }
+            }
+            glyphs.push(LineGlyph { anchor, angle });
+        }
+
+        Some(LineLabel { anchor, glyphs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rotate, LinePath};
+    use crate::style::layer::SymbolPlacement;
+    use std::f32::consts::PI;
+
+    fn assert_close(a: [f32; 2], b: [f32; 2]) {
+        assert!(
+            (a[0] - b[0]).abs() < 1e-3 && (a[1] - b[1]).abs() < 1e-3,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn test_label_anchors() {
+        let points = [[0.0, 0.0], [1000.0, 0.0]];
+        let line = LinePath::new(&points);
+
+        assert_eq!(
+            line.label_anchors(100.0, 400.0, SymbolPlacement::Line),
+            vec![250.0, 750.0]
+        );
+        assert_eq!(
+            line.label_anchors(100.0, 400.0, SymbolPlacement::LineCenter),
+            vec![500.0]
+        );
+        // Lines which are too short are not labelled
+        assert!(line
+            .label_anchors(2000.0, 400.0, SymbolPlacement::Line)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_place_label() {
+        let points = [[0.0, 0.0], [1000.0, 0.0]];
+        let label = LinePath::new(&points)
+            .place_label(500.0, &[-20.0, 0.0, 20.0])
+            .unwrap();
+
+        assert_close(label.anchor, [500.0, 0.0]);
+        assert_close(label.glyphs[0].anchor, [480.0, 0.0]);
+        assert_close(label.glyphs[2].anchor, [520.0, 0.0]);
+        assert!(label.glyphs.iter().all(|glyph| glyph.angle == 0.0));
+
+        // Labels run over the ends of the line
+        assert!(LinePath::new(&points)
+            .place_label(10.0, &[-20.0, 0.0, 20.0])
+            .is_none());
+
+        // Glyphs follow the direction of the line
+        let points = [[0.0, 0.0], [0.0, 1000.0]];
+        let label = LinePath::new(&points)
+            .place_label(500.0, &[-20.0, 20.0])
+            .unwrap();
+        assert_close(label.glyphs[0].anchor, [0.0, 480.0]);
+        assert!((label.glyphs[0].angle - PI / 2.0).abs() < 1e-5);
+        assert_close(rotate([1.0, 0.0], label.glyphs[0].angle), [0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_flip_upside_down_label() {
+        let points = [[1000.0, 0.0], [0.0, 0.0]];
+        let label = LinePath::new(&points)
+            .place_label(500.0, &[-20.0, 0.0, 20.0])
+            .unwrap();
+
+        // The text still reads from left to right
+        assert_close(label.glyphs[0].anchor, [480.0, 0.0]);
+        assert_close(label.glyphs[2].anchor, [520.0, 0.0]);
+        assert!(label.glyphs.iter().all(|glyph| glyph.angle.abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_sharp_bend() {
+        let points = [[0.0, 0.0], [100.0, 0.0], [100.0, 100.0]];
+        let line = LinePath::new(&points);
+
+        assert!(line.place_label(100.0, &[-20.0, 0.0, 20.0]).is_none());
+        assert!(line.place_label(50.0, &[-20.0, 0.0, 20.0]).is_some());
+    }
+}
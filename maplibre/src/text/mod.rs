@@ -0,0 +1,456 @@
+//! Text rendering utilities. Glyphs are rasterized from a font and stored as signed distance
+//! fields (SDF) within an atlas. Labels are laid out as a list of textured quads.
+//!
+//! Glyphs of the font stacks of the style are added to the atlas once their ranges are fetched,
+//! see [`crate::io::glyphs`]. They take precedence over the glyphs of the configured font.
+
+use crate::error::Error;
+use crate::io::glyphs::SdfGlyph;
+use crate::style::layer::IconTextFit;
+use std::collections::HashMap;
+
+pub mod feature;
+pub mod line_placement;
+pub mod placement;
+pub mod sdf;
+
+/// Font size in pixels at which glyphs are rasterized into the atlas.
+pub const GLYPH_SIZE: f32 = 24.0;
+/// Padding around each glyph in the atlas. This allows the distance field to fall off.
+pub const GLYPH_BUFFER: usize = 3;
+/// Maximum distance in pixels which is encoded in the distance field.
+const SDF_RADIUS: f32 = 8.0;
+const ATLAS_WIDTH: u32 = 512;
+/// Height up to which the atlas grows while glyphs are added. This is the largest texture which
+/// WebGL 2 guarantees.
+const MAX_ATLAS_HEIGHT: u32 = 2048;
+/// Distance of the top of the em box of glyph PBFs from the baseline.
+const PBF_ASCENT: i32 = 24;
+
+/// Converts the width of a halo in pixels around text of `text_size` into the distance it spans
+/// within the signed distance field. Halos are limited to the distance which the field encodes
+/// outside of glyphs.
+pub fn sdf_halo_width(halo_width: f32, text_size: f32) -> f32 {
+    (halo_width * GLYPH_SIZE / text_size / SDF_RADIUS).clamp(0.0, 1.0 - sdf::SDF_CUTOFF)
+}
+
+/// Stretches `icon` around the box of a text from `text_min` to `text_max`, see `icon-text-fit`.
+/// `padding` is given in pixels in the order top, right, bottom and left. Along the directions in
+/// which the icon is not fitted, it keeps its size and is centered on the text.
+pub fn fit_icon_to_text(
+    icon: &GlyphQuad,
+    text_min: [f32; 2],
+    text_max: [f32; 2],
+    fit: IconTextFit,
+    padding: [f32; 4],
+) -> GlyphQuad {
+    let [top, right, bottom, left] = padding;
+    let fit_width = matches!(fit, IconTextFit::Width | IconTextFit::Both);
+    let fit_height = matches!(fit, IconTextFit::Height | IconTextFit::Both);
+
+    let centered = |axis: usize| {
+        let size = icon.bottom_right[axis] - icon.top_left[axis];
+        let center = (text_min[axis] + text_max[axis]) / 2.0;
+        (center - size / 2.0, center + size / 2.0)
+    };
+    let (x_min, x_max) = if fit_width {
+        (text_min[0] - left, text_max[0] + right)
+    } else {
+        centered(0)
+    };
+    let (y_min, y_max) = if fit_height {
+        (text_min[1] - top, text_max[1] + bottom)
+    } else {
+        centered(1)
+    };
+
+    GlyphQuad {
+        top_left: [x_min, y_min],
+        bottom_right: [x_max, y_max],
+        ..*icon
+    }
+}
+
+/// The range of characters which are rasterized into the atlas.
+fn latin_characters() -> impl Iterator<Item = char> {
+    (0x20u32..=0x7e)
+        .chain(0xa0u32..=0xff)
+        .filter_map(char::from_u32)
+}
+
+/// Location and metrics of a single glyph within the [`GlyphAtlas`].
+#[derive(Debug, Clone, Copy)]
+pub struct GlyphInfo {
+    /// Position and size of the glyph (including the buffer) within the atlas in pixels.
+    pub atlas_x: u32,
+    pub atlas_y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Offset from the pen position to the left edge of the glyph bitmap.
+    pub left: f32,
+    /// Offset from the baseline to the bottom edge of the glyph bitmap, pointing upwards.
+    pub bottom: f32,
+    /// Horizontal distance to the next pen position.
+    pub advance: f32,
+}
+
+/// A textured quad of a laid out glyph. Positions are relative to the anchor of the label and
+/// in pixels. Texture coordinates are normalized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphQuad {
+    pub top_left: [f32; 2],
+    pub bottom_right: [f32; 2],
+    pub tex_top_left: [f32; 2],
+    pub tex_bottom_right: [f32; 2],
+}
+
+/// Single channel texture which contains the signed distance fields of the Latin glyphs of a font
+/// and of the glyphs of font stacks which have been added since. Glyphs are packed into rows and
+/// the atlas grows in height as needed.
+pub struct GlyphAtlas {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    /// Glyphs of the configured font, which are used for characters no font stack provides.
+    glyphs: HashMap<char, GlyphInfo>,
+    /// Glyphs of the font stacks of the style by the name of the font stack.
+    fontstacks: HashMap<String, HashMap<char, GlyphInfo>>,
+    /// Position of the next glyph within the current row.
+    pen: [u32; 2],
+    row_height: u32,
+    /// Incremented whenever glyphs are added, such that the texture is uploaded again.
+    pub revision: u64,
+}
+
+impl GlyphAtlas {
+    /// Rasterizes the glyphs of the given TTF/OTF font and packs them into rows.
+    pub fn from_font(font_data: &[u8]) -> Result<Self, Error> {
+        let font = fontdue::Font::from_bytes(font_data, fontdue::FontSettings::default())
+            .map_err(|e| Error::Font(e.to_string()))?;
+
+        let mut atlas = Self::empty();
+        for character in latin_characters() {
+            let (metrics, coverage) = font.rasterize(character, GLYPH_SIZE);
+
+            let sdf = sdf::signed_distance_field(
+                &coverage,
+                metrics.width,
+                metrics.height,
+                GLYPH_BUFFER,
+                SDF_RADIUS,
+            );
+
+            let glyph = atlas.pack(
+                &sdf,
+                (metrics.width + 2 * GLYPH_BUFFER) as u32,
+                (metrics.height + 2 * GLYPH_BUFFER) as u32,
+                metrics.xmin as f32,
+                metrics.ymin as f32,
+                metrics.advance_width,
+            );
+            if let Some(glyph) = glyph {
+                atlas.glyphs.insert(character, glyph);
+            }
+        }
+
+        Ok(atlas)
+    }
+
+    /// An atlas without glyphs, which is used if no font is available. Only glyphs of font
+    /// stacks can be laid out with it.
+    pub fn empty() -> Self {
+        Self {
+            width: ATLAS_WIDTH,
+            height: 1,
+            data: vec![0; ATLAS_WIDTH as usize],
+            glyphs: HashMap::new(),
+            fontstacks: HashMap::new(),
+            pen: [0, 0],
+            row_height: 0,
+            revision: 0,
+        }
+    }
+
+    /// Adds the `glyphs` of a range of `fontstack`. Glyphs which do not fit into the atlas
+    /// anymore are skipped.
+    pub fn add_glyphs(&mut self, fontstack: &str, glyphs: &[SdfGlyph]) {
+        let mut added = HashMap::new();
+        for glyph in glyphs {
+            let character = match char::from_u32(glyph.id) {
+                Some(character) => character,
+                None => continue,
+            };
+
+            let width = glyph.width + 2 * GLYPH_BUFFER as u32;
+            let height = glyph.height + 2 * GLYPH_BUFFER as u32;
+            let bitmap = match &glyph.bitmap {
+                Some(bitmap) if glyph.width > 0 && glyph.height > 0 => bitmap.as_slice(),
+                // Only advances the pen, see `layout_text`
+                _ => &[],
+            };
+            if !bitmap.is_empty() && bitmap.len() != (width * height) as usize {
+                log::warn!(
+                    "Bitmap of glyph {:?} of {} is invalid",
+                    character,
+                    fontstack
+                );
+                continue;
+            }
+
+            let left = glyph.left as f32;
+            let bottom = (glyph.top + PBF_ASCENT - glyph.height as i32) as f32;
+            let advance = glyph.advance as f32;
+            let info = if bitmap.is_empty() {
+                Some(GlyphInfo {
+                    atlas_x: 0,
+                    atlas_y: 0,
+                    width: 0,
+                    height: 0,
+                    left,
+                    bottom,
+                    advance,
+                })
+            } else {
+                self.pack(bitmap, width, height, left, bottom, advance)
+            };
+
+            match info {
+                Some(info) => {
+                    added.insert(character, info);
+                }
+                None => log::warn!(
+                    "Glyph atlas is full, skipping glyph {:?} of {}",
+                    character,
+                    fontstack
+                ),
+            }
+        }
+
+        self.fontstacks
+            .entry(fontstack.to_string())
+            .or_default()
+            .extend(added);
+        self.revision += 1;
+    }
+
+    /// Copies the distance field `sdf` of `width` times `height` pixels into the next free space
+    /// of the atlas. Returns `None` if the atlas is full.
+    fn pack(
+        &mut self,
+        sdf: &[u8],
+        width: u32,
+        height: u32,
+        left: f32,
+        bottom: f32,
+        advance: f32,
+    ) -> Option<GlyphInfo> {
+        if self.pen[0] + width > self.width {
+            self.pen = [0, self.pen[1] + self.row_height];
+            self.row_height = 0;
+        }
+
+        let [x, y] = self.pen;
+        if y + height > self.height {
+            let grown = (y + height).next_power_of_two();
+            if grown > MAX_ATLAS_HEIGHT {
+                return None;
+            }
+            self.height = grown;
+            self.data.resize((self.width * self.height) as usize, 0);
+        }
+
+        for (row, pixels) in sdf.chunks(width as usize).enumerate() {
+            let start = ((y + row as u32) * self.width + x) as usize;
+            self.data[start..start + pixels.len()].copy_from_slice(pixels);
+        }
+
+        self.pen[0] += width;
+        self.row_height = self.row_height.max(height);
+
+        Some(GlyphInfo {
+            atlas_x: x,
+            atlas_y: y,
+            width,
+            height,
+            left,
+            bottom,
+            advance,
+        })
+    }
+
+    /// Returns the glyph of `character` of `fontstack`, or of the configured font if the font
+    /// stack does not provide it.
+    pub fn glyph(&self, fontstack: &str, character: char) -> Option<&GlyphInfo> {
+        self.fontstacks
+            .get(fontstack)
+            .and_then(|glyphs| glyphs.get(&character))
+            .or_else(|| self.glyphs.get(&character))
+    }
+
+    /// Returns the width in pixels of `text` laid out by [`GlyphAtlas::layout_text`], i.e. the
+    /// sum of the advances of its glyphs.
+    pub fn text_width(&self, fontstack: &str, text: &str, text_size: f32) -> f32 {
+        self.advance_width(fontstack, text) * text_size / GLYPH_SIZE
+    }
+
+    fn advance_width(&self, fontstack: &str, text: &str) -> f32 {
+        text.chars()
+            .filter_map(|character| self.glyph(fontstack, character))
+            .map(|glyph| glyph.advance)
+            .sum()
+    }
+
+    /// Lays out `text` with the glyphs of `fontstack` on a single line which is centered around
+    /// the anchor. Characters which are not available in the atlas are skipped.
+    pub fn layout_text(&self, fontstack: &str, text: &str, text_size: f32) -> Vec<GlyphQuad> {
+        let scale = text_size / GLYPH_SIZE;
+
+        let width = self.advance_width(fontstack, text);
+
+        // Center vertically around the anchor by approximating the x-height
+        let baseline = GLYPH_SIZE * 0.35;
+        let mut pen_x = -width / 2.0;
+
+        let mut quads = Vec::with_capacity(text.len());
+
+        for glyph in text
+            .chars()
+            .filter_map(|character| self.glyph(fontstack, character))
+        {
+            if glyph.width > 2 * GLYPH_BUFFER as u32 && glyph.height > 2 * GLYPH_BUFFER as u32 {
+                let left = pen_x + glyph.left - GLYPH_BUFFER as f32;
+                let top = baseline - glyph.bottom - glyph.height as f32 + GLYPH_BUFFER as f32;
+
+                quads.push(GlyphQuad {
+                    top_left: [left * scale, top * scale],
+                    bottom_right: [
+                        (left + glyph.width as f32) * scale,
+                        (top + glyph.height as f32) * scale,
+                    ],
+                    tex_top_left: [
+                        glyph.atlas_x as f32 / self.width as f32,
+                        glyph.atlas_y as f32 / self.height as f32,
+                    ],
+                    tex_bottom_right: [
+                        (glyph.atlas_x + glyph.width) as f32 / self.width as f32,
+                        (glyph.atlas_y + glyph.height) as f32 / self.height as f32,
+                    ],
+                });
+            }
+
+            pen_x += glyph.advance;
+        }
+
+        quads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fit_icon_to_text, sdf_halo_width, GlyphAtlas, GlyphQuad, GLYPH_BUFFER};
+    use crate::io::glyphs::SdfGlyph;
+    use crate::style::layer::IconTextFit;
+
+    fn glyph(character: char, width: u32, height: u32) -> SdfGlyph {
+        let buffer = 2 * GLYPH_BUFFER as u32;
+        SdfGlyph {
+            id: character as u32,
+            bitmap: Some(vec![255; ((width + buffer) * (height + buffer)) as usize]),
+            width,
+            height,
+            left: 0,
+            top: -4,
+            advance: width + 2,
+        }
+    }
+
+    #[test]
+    fn test_sdf_halo_width() {
+        assert_eq!(sdf_halo_width(0.0, 16.0), 0.0);
+        // Glyphs are rasterized at 24 pixels with 8 pixels of distance encoded per unit
+        assert_eq!(sdf_halo_width(2.0, 24.0), 0.25);
+        assert_eq!(sdf_halo_width(1.0, 12.0), 0.25);
+        assert_eq!(sdf_halo_width(100.0, 12.0), 0.75);
+    }
+
+    #[test]
+    fn test_add_glyphs() {
+        let mut atlas = GlyphAtlas::empty();
+        let fontstack = "Noto Sans CJK JP Regular";
+        assert!(atlas.layout_text(fontstack, "東京", 24.0).is_empty());
+
+        let space = SdfGlyph {
+            bitmap: None,
+            ..glyph(' ', 0, 0)
+        };
+        atlas.add_glyphs(
+            fontstack,
+            &[glyph('東', 20, 20), glyph('京', 20, 20), space],
+        );
+        assert_eq!(atlas.revision, 1);
+        assert_eq!((atlas.width, atlas.height), (512, 32));
+        assert_eq!(atlas.data.len(), 512 * 32);
+
+        // Glyphs are packed next to each other and spaces only advance the pen
+        assert_eq!(atlas.glyph(fontstack, '京').unwrap().atlas_x, 26);
+        let quads = atlas.layout_text(fontstack, "東 京", 24.0);
+        assert_eq!(quads.len(), 2);
+        assert_eq!(quads[0].top_left[0], -(22.0 + 2.0 + 22.0) / 2.0 - 3.0);
+        // The glyphs are only available for their font stack
+        assert!(atlas.glyph("Open Sans Regular", '東').is_none());
+
+        // The atlas grows once a row is full
+        let glyphs: Vec<_> = ('a'..='z')
+            .map(|character| glyph(character, 20, 20))
+            .collect();
+        atlas.add_glyphs(fontstack, &glyphs);
+        assert_eq!(atlas.height, 64);
+        assert_eq!(atlas.glyph(fontstack, 'z').unwrap().atlas_y, 26);
+    }
+
+    #[test]
+    fn test_fit_icon_to_text() {
+        let mut atlas = GlyphAtlas::empty();
+        atlas.add_glyphs("Shield", &[glyph('A', 10, 16), glyph('9', 10, 16)]);
+        // The advances are 12 pixels at the size of the atlas
+        let width = atlas.text_width("Shield", "A9", 12.0);
+        assert_eq!(width, 12.0);
+
+        let icon = GlyphQuad {
+            top_left: [-9.0, -9.0],
+            bottom_right: [9.0, 9.0],
+            tex_top_left: [0.25, 0.0],
+            tex_bottom_right: [0.5, 0.5],
+        };
+        let text_min = [-width / 2.0, -6.0];
+        let text_max = [width / 2.0, 6.0];
+
+        let both = fit_icon_to_text(
+            &icon,
+            text_min,
+            text_max,
+            IconTextFit::Both,
+            [2.0, 4.0, 2.0, 4.0],
+        );
+        assert_eq!(both.top_left, [-10.0, -8.0]);
+        assert_eq!(both.bottom_right, [10.0, 8.0]);
+        assert_eq!(both.tex_top_left, icon.tex_top_left);
+
+        // The height of the icon is kept and centered on the text
+        let shifted_min = [text_min[0], text_min[1] + 4.0];
+        let shifted_max = [text_max[0], text_max[1] + 4.0];
+        let width_only = fit_icon_to_text(
+            &icon,
+            shifted_min,
+            shifted_max,
+            IconTextFit::Width,
+            [0.0; 4],
+        );
+        assert_eq!(width_only.top_left, [-6.0, -5.0]);
+        assert_eq!(width_only.bottom_right, [6.0, 13.0]);
+
+        let none = fit_icon_to_text(&icon, text_min, text_max, IconTextFit::None, [0.0; 4]);
+        assert_eq!(none.top_left, icon.top_left);
+        assert_eq!(none.bottom_right, icon.bottom_right);
+    }
+}
@@ -0,0 +1,151 @@
+//! Collision detection for labels. Labels are placed greedily in screen-space: A label is only
+//! shown if its bounding box does not intersect the box of an already placed label.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Size of a cell of the [`CollisionGrid`] in pixels.
+const CELL_SIZE: f32 = 64.0;
+/// Style of visible icons. Their pixels are multiplied by the color and they have no halo.
+const ICON_STYLE: LabelStyle = LabelStyle {
+    color: [1.0; 4],
+    halo_color: [0.0; 4],
+    halo_width: 0.0,
+};
+
+/// Axis-aligned box in screen-space pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionBox {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl CollisionBox {
+    pub fn new(a: [f32; 2], b: [f32; 2]) -> Self {
+        Self {
+            min: [a[0].min(b[0]), a[1].min(b[1])],
+            max: [a[0].max(b[0]), a[1].max(b[1])],
+        }
+    }
+
+    pub fn intersects(&self, other: &CollisionBox) -> bool {
+        self.min[0] < other.max[0]
+            && other.min[0] < self.max[0]
+            && self.min[1] < other.max[1]
+            && other.min[1] < self.max[1]
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (i32, i32)> {
+        let min_x = (self.min[0] / CELL_SIZE).floor() as i32;
+        let min_y = (self.min[1] / CELL_SIZE).floor() as i32;
+        let max_x = (self.max[0] / CELL_SIZE).floor() as i32;
+        let max_y = (self.max[1] / CELL_SIZE).floor() as i32;
+
+        (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+    }
+}
+
+/// Uniform grid which speeds up the lookup of placed boxes which are close to a new box.
+#[derive(Default)]
+pub struct CollisionGrid {
+    boxes: Vec<CollisionBox>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl CollisionGrid {
+    pub fn clear(&mut self) {
+        self.boxes.clear();
+        self.cells.clear();
+    }
+
+    /// Checks whether `collision_box` intersects any box which has been inserted before.
+    pub fn collides(&self, collision_box: &CollisionBox) -> bool {
+        collision_box.cells().any(|cell| {
+            self.cells.get(&cell).map_or(false, |indices| {
+                indices
+                    .iter()
+                    .any(|index| self.boxes[*index].intersects(collision_box))
+            })
+        })
+    }
+
+    pub fn insert(&mut self, collision_box: CollisionBox) {
+        let index = self.boxes.len();
+        for cell in collision_box.cells() {
+            self.cells.entry(cell).or_default().push(index);
+        }
+        self.boxes.push(collision_box);
+    }
+}
+
+/// How the glyphs of a label are drawn while it is visible.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LabelStyle {
+    pub color: [f32; 4],
+    pub halo_color: [f32; 4],
+    /// Distance from the edge of the glyphs to the outer edge of the halo within the signed
+    /// distance field, see [`crate::text::sdf_halo_width`].
+    pub halo_width: f32,
+}
+
+/// A label which has been laid out within a tile.
+#[derive(Debug, Clone)]
+pub struct SymbolLabel {
+    /// Position of the label in tile coordinates.
+    pub anchor: [f32; 2],
+    /// Bounding box of the glyphs and the icon relative to the anchor in tile coordinates.
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    /// Range of the vertices of the glyphs within the geometry of its layer.
+    pub vertices: Range<usize>,
+    /// Style of the text if the label is visible.
+    pub style: LabelStyle,
+    /// Range of the vertices of the icon within the geometry of its layer.
+    pub icon_vertices: Option<Range<usize>>,
+    /// Whether the label stays upright in the viewport instead of rotating with the map, see
+    /// [`crate::style::layer::RotationAlignment`].
+    pub upright: bool,
+    /// Value of `symbol-sort-key` for the feature of the label. Labels with a lower key are placed
+    /// first.
+    pub sort_key: f32,
+}
+
+impl SymbolLabel {
+    /// Returns the ranges of vertices of the label together with the style they have if the label
+    /// is visible.
+    pub fn styled_vertices(&self) -> impl Iterator<Item = (Range<usize>, LabelStyle)> + '_ {
+        self.icon_vertices
+            .iter()
+            .map(|vertices| (vertices.clone(), ICON_STYLE))
+            .chain(std::iter::once((self.vertices.clone(), self.style)))
+    }
+}
+
+/// Labels which have been uploaded for a single layer of a tile.
+#[derive(Debug, Clone)]
+pub struct SymbolLayerLabels {
+    /// Value of `text-allow-overlap`.
+    pub allow_overlap: bool,
+    pub vertex_count: usize,
+    pub labels: Vec<SymbolLabel>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CollisionBox, CollisionGrid};
+
+    #[test]
+    fn test_collision_grid() {
+        let mut grid = CollisionGrid::default();
+
+        grid.insert(CollisionBox::new([0.0, 0.0], [100.0, 20.0]));
+
+        assert!(grid.collides(&CollisionBox::new([90.0, 10.0], [150.0, 30.0])));
+        assert!(grid.collides(&CollisionBox::new([-10.0, -10.0], [5.0, 5.0])));
+        assert!(!grid.collides(&CollisionBox::new([100.0, 0.0], [150.0, 20.0])));
+        assert!(!grid.collides(&CollisionBox::new([200.0, 200.0], [300.0, 220.0])));
+
+        grid.clear();
+        assert!(!grid.collides(&CollisionBox::new([90.0, 10.0], [150.0, 30.0])));
+    }
+}
@@ -0,0 +1,89 @@
+//! Generation of signed distance fields from glyph coverage bitmaps.
+
+/// Value which is stored at the edge of a glyph. Values above this threshold are inside the glyph.
+pub const SDF_CUTOFF: f32 = 0.25;
+
+/// Calculates a signed distance field for a coverage bitmap as it is returned by a rasterizer.
+///
+/// The resulting field is `buffer` pixels larger on each side than the input bitmap such that the
+/// falloff of the glyph edges is not cut off. Distances are normalized by `radius` and encoded
+/// like in the reference renderer: the edge is located at `1.0 - SDF_CUTOFF`.
+pub fn signed_distance_field(
+    coverage: &[u8],
+    width: usize,
+    height: usize,
+    buffer: usize,
+    radius: f32,
+) -> Vec<u8> {
+    let sdf_width = width + 2 * buffer;
+    let sdf_height = height + 2 * buffer;
+    let search = radius.ceil() as i64;
+
+    let is_inside = |x: i64, y: i64| -> bool {
+        if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+            return false;
+        }
+        coverage[y as usize * width + x as usize] >= 128
+    };
+
+    let mut sdf = vec![0u8; sdf_width * sdf_height];
+
+    for sdf_y in 0..sdf_height {
+        for sdf_x in 0..sdf_width {
+            let x = sdf_x as i64 - buffer as i64;
+            let y = sdf_y as i64 - buffer as i64;
+            let inside = is_inside(x, y);
+
+            // Brute force search for the closest pixel which is on the other side of the edge
+            let mut min_distance_squared = f32::MAX;
+            for dy in -search..=search {
+                for dx in -search..=search {
+                    if is_inside(x + dx, y + dy) != inside {
+                        let distance_squared = (dx * dx + dy * dy) as f32;
+                        if distance_squared < min_distance_squared {
+                            min_distance_squared = distance_squared;
+                        }
+                    }
+                }
+            }
+
+            // The edge is located between the two pixel centers
+            let distance = (min_distance_squared.sqrt() - 0.5).min(radius);
+            let signed_distance = if inside { -distance } else { distance };
+
+            let value = 1.0 - (signed_distance / radius + SDF_CUTOFF);
+            sdf[sdf_y * sdf_width + sdf_x] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+
+    sdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{signed_distance_field, SDF_CUTOFF};
+
+    #[test]
+    fn test_signed_distance_field() {
+        let mut coverage = vec![0u8; 8 * 8];
+        for y in 2..6 {
+            for x in 2..6 {
+                coverage[y * 8 + x] = 255;
+            }
+        }
+
+        let sdf = signed_distance_field(&coverage, 8, 8, 2, 4.0);
+        let at = |x: usize, y: usize| sdf[y * 12 + x] as f32 / 255.0;
+
+        let edge = 1.0 - SDF_CUTOFF;
+        // Center of the square is inside
+        assert!(at(6, 6) > edge);
+        // Corners of the padded field are outside
+        assert!(at(0, 0) < edge);
+        assert!(at(11, 11) < edge);
+        // Values decrease monotonically when moving out of the square
+        assert!(at(6, 6) >= at(4, 6));
+        assert!(at(4, 6) > at(3, 6));
+        assert!(at(3, 6) > at(2, 6));
+    }
+}
@@ -83,6 +83,12 @@ where
         self.reference_value = Some(self.inner.clone());
     }
 
+    /// Forgets the reference value such that the next [`did_change`](Self::did_change) returns
+    /// true.
+    pub fn reset_reference(&mut self) {
+        self.reference_value = None;
+    }
+
     pub fn did_change(&self, epsilon: T::Epsilon) -> bool {
         if let Some(reference_value) = &self.reference_value {
             reference_value.ne(&self.inner, epsilon)
@@ -13,6 +13,12 @@ pub trait MapWindow {
     fn size(&self) -> WindowSize;
 
     fn inner(&self) -> &Self::Window;
+
+    /// Whether a surface can be created for the window right after it is created. Otherwise, the
+    /// renderer is initialized later by [`MapSchedule::recreate_surface`].
+    fn surface_available(&self) -> bool {
+        true
+    }
 }
 
 pub trait MapWindowConfig: 'static {
@@ -28,11 +34,13 @@ where
     fn run(self, map_state: MapSchedule<MWC, SM, HC>, max_frames: Option<u64>);
 }
 
-/// Window size with a width and an height in pixels.
-#[derive(Clone, Copy, Eq, PartialEq)]
+/// Window size with a width and an height in physical pixels.
+#[derive(Clone, Copy, PartialEq)]
 pub struct WindowSize {
     width: u32,
     height: u32,
+    /// Number of physical pixels per logical pixel, e.g. 2 on HiDPI displays.
+    device_pixel_ratio: f64,
 }
 
 impl WindowSize {
@@ -41,7 +49,20 @@ impl WindowSize {
             return None;
         }
 
-        Some(Self { width, height })
+        Some(Self {
+            width,
+            height,
+            device_pixel_ratio: 1.0,
+        })
+    }
+
+    /// Sets the number of physical pixels per logical pixel. Ratios which are not positive are
+    /// ignored.
+    pub fn with_device_pixel_ratio(mut self, device_pixel_ratio: f64) -> Self {
+        if device_pixel_ratio > 0.0 {
+            self.device_pixel_ratio = device_pixel_ratio;
+        }
+        self
     }
 
     pub fn width(&self) -> u32 {
@@ -50,4 +71,8 @@ impl WindowSize {
     pub fn height(&self) -> u32 {
         self.height
     }
+
+    pub fn device_pixel_ratio(&self) -> f64 {
+        self.device_pixel_ratio
+    }
 }
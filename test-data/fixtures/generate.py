@@ -0,0 +1,159 @@
+#!/usr/bin/env python3
+"""Generates the synthetic vector tiles which are used by the tile load benchmarks.
+
+The tiles are deterministic, such that benchmark results stay comparable between runs and
+machines. Run this script from its directory to regenerate them.
+"""
+
+import math
+
+EXTENT = 4096
+
+
+class Random:
+    """Linear congruential generator, such that the output does not depend on the Python version."""
+
+    def __init__(self, seed):
+        self.state = seed
+
+    def next(self):
+        self.state = (self.state * 6364136223846793005 + 1442695040888963407) % 2**64
+        return (self.state >> 33) / 2**31
+
+    def range(self, low, high):
+        return low + (high - low) * self.next()
+
+
+def varint(value):
+    out = bytearray()
+    while True:
+        byte = value & 0x7F
+        value >>= 7
+        if value:
+            out.append(byte | 0x80)
+        else:
+            out.append(byte)
+            return bytes(out)
+
+
+def field(number, wire_type, payload):
+    key = varint((number << 3) | wire_type)
+    if wire_type == 0:
+        return key + varint(payload)
+    return key + varint(len(payload)) + payload
+
+
+def zigzag(value):
+    return (value << 1) ^ (value >> 31)
+
+
+def command(id, count):
+    return (id & 0x7) | (count << 3)
+
+
+def geometry(parts, close):
+    """Encodes line strings or rings as MVT geometry commands."""
+    commands = []
+    cursor = (0, 0)
+    for points in parts:
+        points = [(int(round(x)), int(round(y))) for x, y in points]
+        for i, (x, y) in enumerate(points):
+            if i == 0:
+                commands.append(command(1, 1))
+            elif i == 1:
+                commands.append(command(2, len(points) - 1))
+            commands += [zigzag(x - cursor[0]), zigzag(y - cursor[1])]
+            cursor = (x, y)
+        if close:
+            commands.append(command(7, 1))
+    return b"".join(varint(value) for value in commands)
+
+
+def feature(id, geometry_type, tags, encoded_geometry):
+    return (
+        field(1, 0, id)
+        + field(2, 2, b"".join(varint(tag) for tag in tags))
+        + field(3, 0, geometry_type)
+        + field(4, 2, encoded_geometry)
+    )
+
+
+def layer(name, features, classes):
+    return (
+        field(15, 0, 2)
+        + field(1, 2, name.encode())
+        + b"".join(field(2, 2, f) for f in features)
+        + field(3, 2, b"class")
+        + b"".join(field(4, 2, field(1, 2, c.encode())) for c in classes)
+        + field(5, 0, EXTENT)
+    )
+
+
+def star_polygon(random, center, radius, count):
+    """A simple polygon around `center`, clockwise in tile coordinates like MVT exterior rings."""
+    points = []
+    for i in range(count):
+        angle = 2 * math.pi * i / count
+        r = radius * random.range(0.6, 1.0)
+        points.append((center[0] + r * math.cos(angle), center[1] + r * math.sin(angle)))
+    return points
+
+
+def random_walk(random, count, step):
+    x, y = random.range(0, EXTENT), random.range(0, EXTENT)
+    heading = random.range(0, 2 * math.pi)
+    points = []
+    for _ in range(count):
+        points.append((min(max(x, 0), EXTENT), min(max(y, 0), EXTENT)))
+        heading += random.range(-0.5, 0.5)
+        x += step * math.cos(heading)
+        y += step * math.sin(heading)
+    return points
+
+
+def fill_heavy():
+    random = Random(1)
+    classes = ["residential", "commercial", "industrial"]
+
+    buildings = []
+    cells = 40
+    cell = EXTENT / cells
+    for i in range(cells * cells):
+        center = ((i % cells + 0.5) * cell, (i // cells + 0.5) * cell)
+        ring = star_polygon(random, center, cell * 0.4, int(random.range(5, 13)))
+        buildings.append(feature(i + 1, 3, [0, i % len(classes)], geometry([ring], True)))
+
+    landcover = []
+    for i in range(16):
+        center = ((i % 4 + 0.5) * EXTENT / 4, (i // 4 + 0.5) * EXTENT / 4)
+        ring = star_polygon(random, center, EXTENT / 8, 200)
+        landcover.append(feature(i + 1, 3, [0, 0], geometry([ring], True)))
+
+    return field(3, 2, layer("building", buildings, classes)) + field(
+        3, 2, layer("landcover", landcover, ["grass"])
+    )
+
+
+def line_heavy():
+    random = Random(2)
+    classes = ["primary", "secondary", "minor"]
+
+    roads = []
+    for i in range(1200):
+        line = random_walk(random, 30, 40)
+        roads.append(feature(i + 1, 2, [0, i % len(classes)], geometry([line], False)))
+
+    rivers = []
+    for i in range(40):
+        line = random_walk(random, 150, 25)
+        rivers.append(feature(i + 1, 2, [0, 0], geometry([line], False)))
+
+    return field(3, 2, layer("transportation", roads, classes)) + field(
+        3, 2, layer("waterway", rivers, ["river"])
+    )
+
+
+if __name__ == "__main__":
+    for name, tile in [("fill-heavy.pbf", fill_heavy()), ("line-heavy.pbf", line_heavy())]:
+        with open(name, "wb") as file:
+            file.write(tile)
@@ -59,7 +59,6 @@ pub async fn run(scheduler_ptr: *mut Scheduler<WebWorkerPoolScheduleMethod>) {
         .with_http_client(WHATWGFetchHttpClient::new())
         .with_existing_scheduler(*scheduler)
         .build()
-        .initialize()
         .await
         .run();
 
@@ -1,28 +1,63 @@
-use js_sys::{ArrayBuffer, Uint8Array};
+use js_sys::{ArrayBuffer, Promise, Uint8Array};
+use maplibre::io::request_transform::RequestTransform;
 use maplibre::io::source_client::HTTPClient;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
 
-use web_sys::{Request, RequestInit, Response, WorkerGlobalScope};
+use std::ops::Range;
+use std::time::Duration;
+use web_sys::{Headers, Request, RequestInit, Response, WorkerGlobalScope};
 
 use crate::error::WebError;
 use async_trait::async_trait;
 
 use maplibre::error::Error;
 
-pub struct WHATWGFetchHttpClient {}
+pub struct WHATWGFetchHttpClient {
+    request_transform: Option<RequestTransform>,
+}
 
 impl WHATWGFetchHttpClient {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            request_transform: None,
+        }
+    }
+
+    /// Adds the headers and query parameters of `request_transform` to every request, e.g. to
+    /// authenticate at a tile provider.
+    pub fn with_request_transform(mut self, request_transform: RequestTransform) -> Self {
+        self.request_transform = Some(request_transform);
+        self
     }
 
-    async fn fetch_array_buffer(url: &str) -> Result<JsValue, JsValue> {
+    async fn fetch_array_buffer(
+        &self,
+        url: &str,
+        range: Option<Range<u64>>,
+    ) -> Result<JsValue, JsValue> {
         let mut opts = RequestInit::new();
         opts.method("GET");
 
-        let request = Request::new_with_str_and_init(url, &opts)?;
+        let headers = Headers::new()?;
+        if let Some(range) = range {
+            headers.set("Range", &format!("bytes={}-{}", range.start, range.end - 1))?;
+        }
+
+        let url = match &self.request_transform {
+            Some(request_transform) => {
+                let parameters = request_transform.parameters(url);
+                for (name, value) in &parameters.headers {
+                    headers.set(name, value)?;
+                }
+                parameters.apply_query(url)
+            }
+            None => url.to_string(),
+        };
+        opts.headers(&headers);
+
+        let request = Request::new_with_str_and_init(&url, &opts)?;
 
         // Get the global scope
         let global = js_sys::global();
@@ -39,8 +74,8 @@ impl WHATWGFetchHttpClient {
         Ok(maybe_array_buffer)
     }
 
-    async fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>, WebError> {
-        let maybe_array_buffer = Self::fetch_array_buffer(url).await?;
+    async fn fetch_bytes(&self, url: &str, range: Option<Range<u64>>) -> Result<Vec<u8>, WebError> {
+        let maybe_array_buffer = self.fetch_array_buffer(url, range).await?;
 
         assert!(maybe_array_buffer.is_instance_of::<ArrayBuffer>());
         let array_buffer: ArrayBuffer = maybe_array_buffer.dyn_into().unwrap();
@@ -52,19 +87,49 @@ impl WHATWGFetchHttpClient {
 
         Ok(output)
     }
+
+    /// Resolves after `duration` by using `setTimeout` of the worker.
+    async fn timeout(duration: Duration) -> Result<JsValue, JsValue> {
+        let promise = Promise::new(&mut |resolve, _reject| {
+            let scope = js_sys::global().unchecked_into::<WorkerGlobalScope>();
+            // If the timeout can not be scheduled the retry simply happens immediately
+            if scope
+                .set_timeout_with_callback_and_timeout_and_arguments_0(
+                    &resolve,
+                    duration.as_millis() as i32,
+                )
+                .is_err()
+            {
+                let _ = resolve.call0(&JsValue::NULL);
+            }
+        });
+        JsFuture::from(promise).await
+    }
 }
 
 impl Clone for WHATWGFetchHttpClient {
     fn clone(&self) -> Self {
-        WHATWGFetchHttpClient {}
+        WHATWGFetchHttpClient {
+            request_transform: self.request_transform.clone(),
+        }
     }
 }
 
 #[async_trait(?Send)]
 impl HTTPClient for WHATWGFetchHttpClient {
     async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
-        self.fetch_bytes(url)
+        self.fetch_bytes(url, None)
             .await
             .map_err(|WebError(msg)| Error::Network(msg))
     }
+
+    async fn fetch_range(&self, url: &str, range: Range<u64>) -> Result<Vec<u8>, Error> {
+        self.fetch_bytes(url, Some(range))
+            .await
+            .map_err(|WebError(msg)| Error::Network(msg))
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let _ = Self::timeout(duration).await;
+    }
 }